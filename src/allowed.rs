@@ -0,0 +1,302 @@
+//! Answers whether a package may use an API according to the current config, without building
+//! anything. The answer is reported via the exit code so that it can be used by automation, e.g. to
+//! decide whether adding a dependency would pass policy.
+
+use crate::capabilities::CapabilityManifest;
+use crate::config::permissions::PermSel;
+use crate::config::permissions::PermissionScope;
+use crate::config::ApiName;
+use crate::config::Config;
+use crate::config::PackageConfig;
+use crate::crate_index::CrateIndex;
+use anyhow::bail;
+use anyhow::Result;
+use clap::Parser;
+use clap::ValueEnum;
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct AllowedOptions {
+    /// The name of the package. It doesn't need to be a current dependency.
+    package: String,
+
+    /// The name of the API. e.g. `net`.
+    api: String,
+
+    /// Which of the package's code to ask about.
+    #[clap(long, value_enum, default_value_t = Scope::All)]
+    scope: Scope,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum Scope {
+    /// The package's regular code.
+    All,
+    /// The package's build script.
+    Build,
+    /// The package's tests.
+    Test,
+    /// The package's proc macro.
+    ProcMacro,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Verdict {
+    allowed: bool,
+    explanation: String,
+}
+
+/// Prints whether the package and API in `options` are allowed. Returns whether they are.
+pub(crate) fn check(
+    config: &Config,
+    crate_index: &CrateIndex,
+    options: &AllowedOptions,
+) -> Result<bool> {
+    let scope = match options.scope {
+        Scope::All => PermissionScope::All,
+        Scope::Build => PermissionScope::Build,
+        Scope::Test => PermissionScope::Test,
+        Scope::ProcMacro => PermissionScope::ProcMacro,
+    };
+    let perm_sel = PermSel::for_primary(options.package.as_str()).clone_with_scope(scope);
+    let verdict = verdict(config, crate_index, &perm_sel, &ApiName::new(&options.api))?;
+    println!("{}", verdict.explanation);
+    Ok(verdict.allowed)
+}
+
+fn verdict(
+    config: &Config,
+    crate_index: &CrateIndex,
+    perm_sel: &PermSel,
+    api: &ApiName,
+) -> Result<Verdict> {
+    if !config.raw.apis.contains_key(api) {
+        bail!("Unknown API `{api}`");
+    }
+    let grants_api = |pkg_config: &PackageConfig| pkg_config.allow_apis.contains(api);
+    if let Some(grant) = find_grant(config, crate_index, perm_sel, &grants_api) {
+        return Ok(Verdict {
+            allowed: true,
+            explanation: format!("`{perm_sel}` is allowed to use `{api}`, granted by {grant}"),
+        });
+    }
+    let mut explanation = format!(
+        "`{perm_sel}` isn't allowed to use `{api}`. To allow it, add `{api}` to `{}.allow_apis`",
+        table_name(perm_sel)
+    );
+    if !crate_index
+        .package_ids()
+        .any(|pkg_id| pkg_id.name_str() == perm_sel.package_name.as_ref())
+    {
+        explanation.push_str(&format!(
+            "\nNote, `{}` isn't currently a dependency",
+            perm_sel.package_name
+        ));
+    }
+    Ok(Verdict {
+        allowed: false,
+        explanation,
+    })
+}
+
+/// Returns whether `perm_sel` would be granted a permission, as determined by `grants`, whether or
+/// not the package is currently a dependency.
+pub(crate) fn is_granted(
+    config: &Config,
+    crate_index: &CrateIndex,
+    perm_sel: &PermSel,
+    grants: &dyn Fn(&PackageConfig) -> bool,
+) -> bool {
+    find_grant(config, crate_index, perm_sel, grants).is_some()
+}
+
+/// Returns a description of the config that grants a permission, as determined by `grants`, to
+/// `perm_sel` or one of the selectors that it inherits from, if any does.
+fn find_grant(
+    config: &Config,
+    crate_index: &CrateIndex,
+    perm_sel: &PermSel,
+    grants: &dyn Fn(&PackageConfig) -> bool,
+) -> Option<String> {
+    let mut sel = Some(perm_sel.clone());
+    while let Some(current) = sel {
+        if let Some(grant) = find_direct_grant(config, crate_index, &current, grants) {
+            return Some(grant);
+        }
+        sel = current.parent();
+    }
+    None
+}
+
+/// Like `find_grant`, but ignores inheritance from parent selectors.
+fn find_direct_grant(
+    config: &Config,
+    crate_index: &CrateIndex,
+    perm_sel: &PermSel,
+    grants: &dyn Fn(&PackageConfig) -> bool,
+) -> Option<String> {
+    let grants = |pkg_config: Option<&PackageConfig>| pkg_config.is_some_and(grants);
+    if grants(config.permissions_no_inheritance.get(perm_sel)) {
+        return Some(format!("`{}.allow_apis`", table_name(perm_sel)));
+    }
+    let pkg_name = perm_sel.package_name.as_ref();
+    for (pattern_name, pattern) in &config.package_patterns {
+        let pattern_sel = perm_sel.clone_with_package_name(pattern_name.clone());
+        if pattern.matches(pkg_name) && grants(config.permissions_no_inheritance.get(&pattern_sel))
+        {
+            return Some(format!("`{}.allow_apis`", table_name(&pattern_sel)));
+        }
+    }
+    if config.raw.common.trust_capability_manifests {
+        for (pkg_id, manifest) in &config.capability_manifests {
+            if pkg_id.name_str() == pkg_name && grants(manifest_config(manifest, perm_sel).as_ref())
+            {
+                return Some(format!("the capability manifest of `{pkg_id}`"));
+            }
+        }
+    }
+    if perm_sel.scope == PermissionScope::All
+        && crate_index.is_dev_only(pkg_name)
+        && grants(Some(&PackageConfig {
+            allow_apis: config.raw.common.dev_dependencies_default_allow.clone(),
+            ..PackageConfig::default()
+        }))
+    {
+        return Some(
+            "`common.dev_dependencies_default_allow`, since it's only used via dev-dependencies"
+                .to_owned(),
+        );
+    }
+    let defaults = &config.raw.defaults;
+    let classes = if !config.raw.is_first_party(pkg_name, crate_index) {
+        vec![("third_party", &defaults.third_party)]
+    } else if crate_index.is_bin_only(pkg_name) {
+        vec![
+            ("first_party", &defaults.first_party),
+            ("first_party_bin", &defaults.first_party_bin),
+        ]
+    } else {
+        vec![
+            ("first_party", &defaults.first_party),
+            ("first_party_lib", &defaults.first_party_lib),
+        ]
+    };
+    for (class, defaults) in classes {
+        if grants(scoped_config(defaults, perm_sel.scope)) {
+            let mut table = format!("defaults.{class}");
+            if let Some(selector) = perm_sel.scope.config_selector() {
+                table = format!("{table}.{selector}");
+            }
+            return Some(format!("`{table}.allow_apis`"));
+        }
+    }
+    None
+}
+
+/// Returns the permissions that `manifest` declares for `perm_sel`. Manifests only declare
+/// permissions for a package's regular code and its build script.
+fn manifest_config(manifest: &CapabilityManifest, perm_sel: &PermSel) -> Option<PackageConfig> {
+    match perm_sel.scope {
+        PermissionScope::All => Some(manifest.package_config()),
+        PermissionScope::Build => manifest.build_package_config(),
+        _ => None,
+    }
+}
+
+/// Returns the part of `pkg_config` that applies to `scope`.
+fn scoped_config(pkg_config: &PackageConfig, scope: PermissionScope) -> Option<&PackageConfig> {
+    match scope {
+        PermissionScope::All => Some(pkg_config),
+        PermissionScope::Build => pkg_config.build.as_deref(),
+        PermissionScope::Test => pkg_config.test.as_deref(),
+        PermissionScope::ProcMacro => pkg_config.proc_macro.as_deref(),
+        PermissionScope::FromBuild => pkg_config.from.as_ref()?.build.as_deref(),
+        PermissionScope::FromTest => pkg_config.from.as_ref()?.test.as_deref(),
+    }
+}
+
+/// Returns the name of the config table for `perm_sel`, quoting the package name if needed.
+fn table_name(perm_sel: &PermSel) -> String {
+    let name = perm_sel.package_name.as_ref();
+    let mut table = if name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        format!("pkg.{name}")
+    } else {
+        format!("pkg.\"{name}\"")
+    };
+    if let Some(selector) = perm_sel.scope.config_selector() {
+        table = format!("{table}.{selector}");
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grants() {
+        let config = crate::config::testing::parse(
+            r#"
+            [api.net]
+            include = ["std::net"]
+
+            [api.fs]
+            include = ["std::fs"]
+
+            [api.process]
+            include = ["std::process"]
+
+            [defaults.third_party.build]
+            allow_apis = ["process"]
+
+            [pkg.foo]
+            allow_apis = ["net"]
+
+            [pkg.foo-sys]
+            allow_unsafe = true
+
+            [pkg."foo-*"]
+            allow_apis = ["fs"]
+            "#,
+        )
+        .unwrap();
+        let crate_index =
+            crate::crate_index::testing::index_with_package_names(&["foo", "foo-sys"]);
+        let check = |pkg_name: &str, scope, api: &str| {
+            let perm_sel = PermSel::for_primary(pkg_name).clone_with_scope(scope);
+            verdict(&config, &crate_index, &perm_sel, &ApiName::new(api)).unwrap()
+        };
+
+        let direct = check("foo", PermissionScope::All, "net");
+        assert!(direct.allowed);
+        assert!(direct.explanation.contains("`pkg.foo.allow_apis`"));
+
+        let inherited = check("foo", PermissionScope::Build, "net");
+        assert!(inherited.allowed);
+        assert!(inherited.explanation.contains("`pkg.foo.allow_apis`"));
+
+        let pattern = check("foo-sys", PermissionScope::Test, "fs");
+        assert!(pattern.allowed);
+        assert!(pattern.explanation.contains("`pkg.\"foo-*\".allow_apis`"));
+
+        let defaults = check("foo", PermissionScope::Build, "process");
+        assert!(defaults.allowed);
+        assert!(defaults
+            .explanation
+            .contains("`defaults.third_party.build.allow_apis`"));
+
+        let denied = check("foo", PermissionScope::All, "process");
+        assert!(!denied.allowed);
+        assert!(denied.explanation.contains("`pkg.foo.allow_apis`"));
+        assert!(!denied.explanation.contains("isn't currently a dependency"));
+
+        let new_dep = check("bar", PermissionScope::All, "net");
+        assert!(!new_dep.allowed);
+        assert!(new_dep.explanation.contains("isn't currently a dependency"));
+
+        let perm_sel = PermSel::for_primary("foo");
+        assert!(verdict(&config, &crate_index, &perm_sel, &ApiName::new("nope")).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+//! A programmatic interface to the analysis, for tools such as registry scanners or dashboards that
+//! want to embed it rather than run `cargo acl` and parse its output.
+
+use crate::colour::Colour;
+use crate::crate_index::CrateIndex;
+use crate::problem::Problem;
+use crate::problem::Severity;
+use crate::Args;
+use crate::Cackle;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// Where problems are collected when we're running as a library.
+pub(crate) type ReportSink = Arc<Mutex<Vec<ReportedProblem>>>;
+
+/// The results of analysing a workspace.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct AnalysisReport {
+    /// Whether the workspace passed the checks. If false, `problems` will contain at least one
+    /// error.
+    pub success: bool,
+
+    /// Errors and warnings that were found. Analysis stops after the first batch of errors, so if
+    /// there are errors, this may not be everything that would be found once they're fixed.
+    pub problems: Vec<ReportedProblem>,
+
+    /// The permissions granted to each package in the dependency tree, keyed by package name.
+    pub permissions: BTreeMap<String, Vec<String>>,
+}
+
+/// A problem found while analysing a workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ReportedProblem {
+    /// The kind of problem. e.g. "disallowed_api_usage". These are the same names as are used when
+    /// selecting problems in cackle.toml.
+    pub kind: &'static str,
+
+    /// The package that the problem relates to, if any.
+    pub package: Option<String>,
+
+    /// How the package is depended upon: "normal", "build" or "dev". None if the problem doesn't
+    /// relate to a package.
+    pub dependency_kind: Option<String>,
+
+    pub severity: Severity,
+
+    /// A one-line description of the problem.
+    pub message: String,
+
+    /// A more detailed description of the problem, which may span multiple lines.
+    pub details: String,
+
+    /// The problem itself, so that it can be rendered as a diagnostic, e.g. by the daemon.
+    pub(crate) problem: Problem,
+
+    /// Notes about the package that the problem relates to, e.g. its dependency path.
+    pub(crate) notes: Vec<String>,
+}
+
+/// Analyses the workspace or package at `workspace` using the configuration at `config_path`. The
+/// analysis is non-interactive. Output from cargo is passed through to our stdout and stderr.
+///
+/// Cargo is run with the current executable as the rustc wrapper and linker, so any program that
+/// calls this must call `handle_proxy_invocation` at the start of `main`.
+pub fn analyze(workspace: &Path, config_path: &Path) -> Result<AnalysisReport> {
+    let args = Args {
+        path: vec![workspace.to_owned()],
+        cackle_path: Some(config_path.to_owned()),
+        colour: Colour::Never,
+        ui: Some(crate::ui::Kind::None),
+        quiet: true,
+        ..Args::default()
+    };
+    let report = ReportSink::default();
+    let (abort_sender, abort_recv) = std::sync::mpsc::channel();
+    let cackle = Cackle::new(args, abort_sender, Some(report.clone()), None)?;
+    cackle.analyze(abort_recv, &report)
+}
+
+/// If the current process was started by cargo as part of a call to `analyze`, does what's
+/// required and exits. Otherwise returns without doing anything.
+pub fn handle_proxy_invocation() -> Result<()> {
+    crate::proxy::subprocess::handle_wrapped_binaries()
+}
+
+impl ReportedProblem {
+    pub(crate) fn new(problem: &Problem, severity: Severity, crate_index: &CrateIndex) -> Self {
+        Self {
+            kind: problem.kind_name(),
+            package: problem.pkg_id().map(|pkg_id| pkg_id.name_str().to_owned()),
+            dependency_kind: problem
+                .pkg_id()
+                .map(|pkg_id| crate_index.dep_kind(pkg_id).to_string()),
+            severity,
+            message: problem.to_string(),
+            details: format!("{problem:#}"),
+            problem: problem.clone(),
+            notes: crate::ui::problem_notes(crate_index, problem),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! `cargo acl apply-fix`, which applies fixes identified by their IDs rather than chosen from the
+//! user interface. Reports written by `--save-report` list the IDs of the fixes available for each
+//! problem, so review tooling can have a person approve particular fixes, then have a bot apply
+//! exactly those.
+
+use crate::config::approvals;
+use crate::config::Config;
+use crate::config_editor::fixes_for_problem;
+use crate::config_editor::Edit;
+use crate::config_editor::EditOpts;
+use crate::config_file::ConfigFile;
+use crate::problem::Problem;
+use anyhow::bail;
+use anyhow::Result;
+use clap::Parser;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct ApplyFixOptions {
+    /// Apply fixes for the problems in a report written by `--save-report` rather than those left
+    /// unresolved by the previous run.
+    #[clap(long)]
+    pub(crate) report: Option<PathBuf>,
+
+    /// The IDs of the fixes to apply, e.g. `allow-api:tokio:net:all`.
+    #[clap(required = true)]
+    ids: Vec<String>,
+}
+
+/// Applies the fixes in `options` to `config_file`. Each must be a fix for one of `problems`. If
+/// any isn't, nothing is applied.
+pub(crate) fn apply(
+    config_file: &ConfigFile,
+    config: &Config,
+    problems: &[Problem],
+    options: &ApplyFixOptions,
+) -> Result<()> {
+    let available: Vec<(&Problem, Box<dyn Edit>)> = problems
+        .iter()
+        .flat_map(|problem| {
+            fixes_for_problem(problem, config)
+                .into_iter()
+                .map(move |edit| (problem, edit))
+        })
+        .collect();
+    let mut edits = Vec::new();
+    for id in &options.ids {
+        let Some(fix) = available.iter().find(|(_, edit)| &edit.id() == id) else {
+            bail!("No fix with ID `{id}` is available for the unresolved problems");
+        };
+        edits.push(fix);
+    }
+    let opts = EditOpts {
+        is_final: true,
+        ..EditOpts::default()
+    };
+    config_file.edit(|editor| {
+        for (problem, edit) in &edits {
+            approvals::apply_edit(editor, &problem.to_string(), edit.as_ref(), &opts, None)?;
+        }
+        Ok(())
+    })?;
+    for (_, edit) in edits {
+        println!("Applied: {}", edit.title());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::permissions::PermissionScope;
+    use crate::config::ApiName;
+    use crate::crate_index::testing::pkg_id;
+    use crate::problem::ApiUsages;
+    use indoc::indoc;
+
+    #[test]
+    fn apply_by_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit_log = dir.path().join("audit.log");
+        let config_file = ConfigFile::new(dir.path().join("cackle.toml"), false, None)
+            .with_audit_log(audit_log.clone());
+        config_file.write("[common]\nversion = 2\n").unwrap();
+        let config = crate::config::testing::parse("").unwrap();
+        let problems = [Problem::DisallowedApiUsage(ApiUsages {
+            pkg_id: pkg_id("tokio"),
+            scope: PermissionScope::All,
+            api_name: ApiName::from("net"),
+            usages: Vec::new(),
+        })];
+        let options = |ids: &[&str]| ApplyFixOptions {
+            report: None,
+            ids: ids.iter().map(|id| id.to_string()).collect(),
+        };
+
+        assert!(apply(
+            &config_file,
+            &config,
+            &problems,
+            &options(&["allow-api:tokio:net:all", "allow-api:tokio:fs:all"]),
+        )
+        .is_err());
+        assert!(!config_file.read().unwrap().contains("allow_apis"));
+
+        apply(
+            &config_file,
+            &config,
+            &problems,
+            &options(&["allow-api:tokio:net:all"]),
+        )
+        .unwrap();
+        assert_eq!(
+            config_file.read().unwrap(),
+            indoc! {r#"
+                [common]
+                version = 2
+
+                [pkg.tokio]
+                allow_apis = [
+                    "net",
+                ]
+            "#}
+        );
+        let entries = crate::audit::read(&audit_log).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].edit_id, "allow-api:tokio:net:all");
+        assert_eq!(entries[0].problem, problems[0].to_string());
+    }
+}
@@ -0,0 +1,523 @@
+//! Attestations that binaries passed analysis. When `--attest` is passed, a successful check writes
+//! a record of how the build was done, i.e. the toolchain, the cargo arguments and the config, along
+//! with the SHA-256 digest and build ID of each binary and shared object that was analysed. `cargo acl
+//! verify-attestation` can later check that a binary that's about to be shipped is one of those
+//! that was analysed. Attestations can optionally be signed with an SSH key via `ssh-keygen -Y`.
+//!
+//! With `--attestation-out`, we instead write an in-toto statement for each binary, identifying it by
+//! its SHA-256 digest and summarising the permissions granted to each package. These are intended to
+//! be published alongside release artifacts, where tools that understand in-toto can consume them.
+//!
+//! Binaries are matched by their SHA-256 digest, so a binary only matches if its contents are
+//! exactly those that were analysed. A release build with a different profile won't match, so to
+//! attest release binaries, set the profile used by cackle to the one that you ship. Stripping a
+//! binary changes its digest, so stripped binaries can instead be matched by their build ID with
+//! `--build-id-only`. The build ID is just a note within the binary, so this only shows that the
+//! binary claims to come from the analysed build, not that it wasn't modified afterwards.
+
+use crate::checker::Checker;
+use crate::link_info::Artifact;
+use crate::link_info::ArtifactKind;
+use crate::summary::Summary;
+use crate::timing::unix_now;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use object::Object;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+/// The version of the attestation format.
+const ATTESTATION_VERSION: u32 = 2;
+
+/// The namespace that signatures are made in, so that a signature of an attestation can't be
+/// passed off as a signature of something else, or vice versa.
+const SIGNATURE_NAMESPACE: &str = "cackle-attestation";
+
+/// Identifies the format of the in-toto statements that we write.
+const IN_TOTO_STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+
+/// Identifies the format of the predicate within our in-toto statements.
+const PREDICATE_TYPE: &str = "https://github.com/cackle-rs/cackle/attestation/v1";
+
+/// Environment variables that affect how rustc compiles code, so are recorded in attestations.
+const RECORDED_ENV_VARS: &[&str] = &[
+    "RUSTFLAGS",
+    "CARGO_ENCODED_RUSTFLAGS",
+    "CARGO_BUILD_RUSTFLAGS",
+    "CARGO_BUILD_TARGET",
+];
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct VerifyAttestationOptions {
+    /// The attestation written by `--attest`.
+    #[clap(long)]
+    attestation: PathBuf,
+
+    /// A file listing who may sign attestations, in the format used by `ssh-keygen -Y verify`. If
+    /// given, the attestation must have a valid signature by `--signer`.
+    #[clap(long, requires = "signer")]
+    allowed_signers: Option<PathBuf>,
+
+    /// The identity, from `--allowed-signers`, that must have signed the attestation.
+    #[clap(long, requires = "allowed_signers")]
+    signer: Option<String>,
+
+    /// Match binaries by their build ID rather than by the digest of their contents. This allows
+    /// binaries that were stripped after analysis to be checked, but doesn't detect binaries that
+    /// were modified without changing their build ID.
+    #[clap(long)]
+    build_id_only: bool,
+
+    /// The binaries to check.
+    #[clap(required = true)]
+    binaries: Vec<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Attestation {
+    version: u32,
+    /// The version of cackle that did the analysis.
+    cackle_version: String,
+    /// Seconds since the Unix epoch when the attestation was written.
+    timestamp: u64,
+    /// The output of `rustc -vV`.
+    rustc_version: String,
+    /// The cargo profile that was built.
+    profile: String,
+    /// The arguments passed to cargo for each workspace that was built.
+    cargo_args: Vec<Vec<String>>,
+    /// Environment variables that affect compilation.
+    env: BTreeMap<String, String>,
+    /// The contents of the config file that the binaries were checked against.
+    config: String,
+    binaries: Vec<AttestedBinary>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct AttestedBinary {
+    /// The path of the binary, relative to the target directory if it's within it.
+    path: PathBuf,
+    kind: ArtifactKind,
+    /// The SHA-256 digest of the binary's contents as hex.
+    sha256: String,
+    /// The binary's build ID as hex, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    build_id: Option<String>,
+}
+
+/// An in-toto statement about a single binary.
+#[derive(Serialize)]
+struct Statement<'a> {
+    #[serde(rename = "_type")]
+    statement_type: &'static str,
+    subject: Vec<Subject>,
+    #[serde(rename = "predicateType")]
+    predicate_type: &'static str,
+    predicate: Predicate<'a>,
+}
+
+/// A file, identified by its digest.
+#[derive(Serialize)]
+struct Subject {
+    name: String,
+    digest: BTreeMap<&'static str, String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Predicate<'a> {
+    cackle_version: &'static str,
+    /// Seconds since the Unix epoch when the statement was written.
+    timestamp: u64,
+    profile: &'a str,
+    artifact_kind: ArtifactKind,
+    /// The config file that the binary was checked against.
+    config: Subject,
+    /// The permissions granted to each package in the dependency tree.
+    #[serde(flatten)]
+    packages: &'a Summary,
+}
+
+/// Writes an attestation for the binaries analysed by `checker` to `path`. `config` is the
+/// contents of the config file and `cargo_args` are the arguments passed to cargo for each
+/// workspace. If `signing_key` is supplied, the attestation is signed with it, with the signature
+/// written alongside the attestation.
+pub(crate) fn write(
+    path: &Path,
+    checker: &Checker,
+    config: &str,
+    profile: &str,
+    cargo_args: Vec<Vec<String>>,
+    signing_key: Option<&Path>,
+) -> Result<()> {
+    let mut binaries = Vec::new();
+    for (bin, artifact) in shippable_outputs(checker) {
+        binaries.push(AttestedBinary {
+            path: artifact.path.to_path_buf(),
+            kind: artifact.kind,
+            sha256: crate::sha256::hex_digest_file(&bin)?,
+            build_id: build_id(&bin)?,
+        });
+    }
+    let attestation = Attestation {
+        version: ATTESTATION_VERSION,
+        cackle_version: env!("CARGO_PKG_VERSION").to_owned(),
+        timestamp: unix_now(),
+        rustc_version: rustc_version()?,
+        profile: profile.to_owned(),
+        cargo_args,
+        env: RECORDED_ENV_VARS
+            .iter()
+            .filter_map(|var| Some((var.to_string(), std::env::var(var).ok()?)))
+            .collect(),
+        config: config.to_owned(),
+        binaries,
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    }
+    crate::fs::write_atomic(path, &serde_json::to_string_pretty(&attestation)?)?;
+    if let Some(signing_key) = signing_key {
+        sign(path, signing_key)?;
+    }
+    Ok(())
+}
+
+/// Writes an in-toto statement for each binary and shared object analysed by `checker` into `dir`.
+/// `config` is the contents of the config file at `config_path`.
+pub(crate) fn write_in_toto(
+    dir: &Path,
+    checker: &Checker,
+    config_path: &Path,
+    config: &str,
+    profile: &str,
+    summary: &Summary,
+) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    for (bin, artifact) in shippable_outputs(checker) {
+        let name = artifact
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let statement = Statement {
+            statement_type: IN_TOTO_STATEMENT_TYPE,
+            subject: vec![Subject::new(
+                name.clone(),
+                crate::sha256::hex_digest_file(&bin)?,
+            )],
+            predicate_type: PREDICATE_TYPE,
+            predicate: Predicate {
+                cackle_version: env!("CARGO_PKG_VERSION"),
+                timestamp: unix_now(),
+                profile,
+                artifact_kind: artifact.kind,
+                config: Subject::new(
+                    config_path.display().to_string(),
+                    crate::sha256::hex_digest(config.as_bytes()),
+                ),
+                packages: summary,
+            },
+        };
+        crate::fs::write_atomic(
+            &dir.join(format!("{name}.intoto.json")),
+            &serde_json::to_string_pretty(&statement)?,
+        )?;
+    }
+    Ok(())
+}
+
+impl Subject {
+    fn new(name: String, sha256: String) -> Self {
+        Self {
+            name,
+            digest: [("sha256", sha256)].into(),
+        }
+    }
+}
+
+/// Returns the binaries and shared objects that were analysed, i.e. those that might be shipped,
+/// together with the path from which each can currently be read.
+fn shippable_outputs(checker: &Checker) -> impl Iterator<Item = (PathBuf, &Artifact)> {
+    checker
+        .linked_outputs
+        .iter()
+        .filter(|(_, artifact)| {
+            matches!(
+                artifact.kind,
+                ArtifactKind::Bin | ArtifactKind::SharedObject
+            )
+        })
+        .map(|(output_file, artifact)| {
+            // Binaries that we run get replaced with a wrapper, in which case the binary itself has
+            // been moved.
+            let orig_bin = crate::proxy::subprocess::orig_bin_path(output_file);
+            if orig_bin.exists() {
+                (orig_bin.to_path_buf(), artifact)
+            } else {
+                (output_file.to_path_buf(), artifact)
+            }
+        })
+}
+
+/// Checks that each of the binaries in `options` matches a binary in the attestation, by digest
+/// unless `--build-id-only` was passed. Prints the result for each binary and returns whether all
+/// matched.
+pub(crate) fn verify(options: &VerifyAttestationOptions) -> Result<bool> {
+    if let (Some(allowed_signers), Some(signer)) = (&options.allowed_signers, &options.signer) {
+        verify_signature(&options.attestation, allowed_signers, signer)?;
+    }
+    let attestation = load(&options.attestation)?;
+    let mut all_matched = true;
+    for bin in &options.binaries {
+        let attested = if options.build_id_only {
+            build_id(bin).map(|build_id| attestation.find_by_build_id(build_id.as_deref()?))
+        } else {
+            crate::sha256::hex_digest_file(bin).map(|sha256| attestation.find(&sha256))
+        };
+        let attested = match attested {
+            Ok(attested) => attested,
+            Err(error) => {
+                println!("{}: {error:#}", bin.display());
+                all_matched = false;
+                continue;
+            }
+        };
+        match attested {
+            Some(attested) => println!(
+                "{}: matches analysed {} `{}`",
+                bin.display(),
+                attested.kind,
+                attested.path.display()
+            ),
+            None => {
+                println!("{}: doesn't match any analysed binary", bin.display());
+                all_matched = false;
+            }
+        }
+    }
+    Ok(all_matched)
+}
+
+fn load(path: &Path) -> Result<Attestation> {
+    let json = crate::fs::read_to_string(path)?;
+    let attestation: Attestation = serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse attestation `{}`", path.display()))?;
+    if attestation.version != ATTESTATION_VERSION {
+        bail!(
+            "Attestation `{}` has format version {}, but this version of cackle only reads \
+             version {ATTESTATION_VERSION}",
+            path.display(),
+            attestation.version
+        );
+    }
+    Ok(attestation)
+}
+
+impl Attestation {
+    /// Returns the binary whose contents had the SHA-256 digest `sha256`.
+    fn find(&self, sha256: &str) -> Option<&AttestedBinary> {
+        self.binaries.iter().find(|bin| bin.sha256 == sha256)
+    }
+
+    fn find_by_build_id(&self, build_id: &str) -> Option<&AttestedBinary> {
+        self.binaries
+            .iter()
+            .find(|bin| bin.build_id.as_deref() == Some(build_id))
+    }
+}
+
+/// Returns the build ID of the binary at `path` as hex, or None if it doesn't have one.
+fn build_id(path: &Path) -> Result<Option<String>> {
+    let data =
+        std::fs::read(path).with_context(|| format!("Failed to read `{}`", path.display()))?;
+    let object = object::File::parse(data.as_slice())
+        .with_context(|| format!("Failed to parse `{}`", path.display()))?;
+    Ok(object
+        .build_id()
+        .with_context(|| format!("Failed to read build ID of `{}`", path.display()))?
+        .map(|build_id| build_id.iter().map(|byte| format!("{byte:02x}")).collect()))
+}
+
+fn rustc_version() -> Result<String> {
+    let output = Command::new("rustc")
+        .arg("-vV")
+        .output()
+        .context("Failed to run `rustc -vV`")?;
+    if !output.status.success() {
+        bail!("`rustc -vV` failed");
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Returns the path of the signature of the attestation at `path`.
+fn signature_path(path: &Path) -> PathBuf {
+    let mut signature = path.as_os_str().to_owned();
+    signature.push(".sig");
+    PathBuf::from(signature)
+}
+
+fn sign(path: &Path, signing_key: &Path) -> Result<()> {
+    // ssh-keygen won't overwrite an existing signature.
+    let _ = std::fs::remove_file(signature_path(path));
+    let status = Command::new("ssh-keygen")
+        .args(["-Y", "sign", "-n", SIGNATURE_NAMESPACE, "-f"])
+        .arg(signing_key)
+        .arg(path)
+        .stdout(Stdio::null())
+        .status()
+        .context("Failed to run `ssh-keygen` to sign attestation")?;
+    if !status.success() {
+        bail!("Failed to sign attestation `{}`", path.display());
+    }
+    Ok(())
+}
+
+fn verify_signature(path: &Path, allowed_signers: &Path, signer: &str) -> Result<()> {
+    let attestation = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open `{}`", path.display()))?;
+    let status = Command::new("ssh-keygen")
+        .args(["-Y", "verify", "-n", SIGNATURE_NAMESPACE, "-f"])
+        .arg(allowed_signers)
+        .arg("-I")
+        .arg(signer)
+        .arg("-s")
+        .arg(signature_path(path))
+        .stdin(attestation)
+        .stdout(Stdio::null())
+        .status()
+        .context("Failed to run `ssh-keygen` to verify attestation")?;
+    if !status.success() {
+        bail!(
+            "Attestation `{}` doesn't have a valid signature by `{signer}`",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn find_binaries() {
+        let exe = std::env::current_exe().unwrap();
+        let build_id = build_id(&exe).unwrap().unwrap();
+        let sha256 = crate::sha256::hex_digest_file(&exe).unwrap();
+        let attestation = Attestation {
+            version: ATTESTATION_VERSION,
+            cackle_version: String::new(),
+            timestamp: 0,
+            rustc_version: String::new(),
+            profile: "release".to_owned(),
+            cargo_args: Vec::new(),
+            env: BTreeMap::new(),
+            config: String::new(),
+            binaries: vec![AttestedBinary {
+                path: PathBuf::from("release/foo"),
+                kind: ArtifactKind::Bin,
+                sha256: sha256.clone(),
+                build_id: Some(build_id.clone()),
+            }],
+        };
+        assert_eq!(
+            attestation.find(&sha256).map(|bin| &bin.path),
+            Some(&PathBuf::from("release/foo"))
+        );
+        assert!(attestation.find(&build_id).is_none());
+        assert!(attestation.find_by_build_id(&build_id).is_some());
+        assert!(attestation.find_by_build_id("0123").is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("attestation.json");
+        std::fs::write(&path, serde_json::to_string(&attestation).unwrap()).unwrap();
+        assert!(load(&path).unwrap().find(&sha256).is_some());
+
+        // A binary that was modified after analysis, but kept its build ID, only matches if we're
+        // told to match by build ID.
+        let modified = dir.path().join("modified");
+        let mut contents = std::fs::read(&exe).unwrap();
+        contents.extend_from_slice(b"appended");
+        std::fs::write(&modified, contents).unwrap();
+        assert_eq!(super::build_id(&modified).unwrap(), Some(build_id));
+        let verify_modified = |build_id_only| {
+            verify(&VerifyAttestationOptions {
+                attestation: path.clone(),
+                build_id_only,
+                binaries: vec![modified.clone()],
+                ..Default::default()
+            })
+            .unwrap()
+        };
+        assert!(!verify_modified(false));
+        assert!(verify_modified(true));
+        assert_eq!(
+            signature_path(&path),
+            dir.path().join("attestation.json.sig")
+        );
+    }
+
+    #[test]
+    fn in_toto_statements() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("foo");
+        std::fs::write(&bin, "abc").unwrap();
+        let test_bin = dir.path().join("foo-test");
+        std::fs::write(&test_bin, "test").unwrap();
+        let mut checker = crate::checker::testing::checker_for_testing();
+        for (path, kind) in [(&bin, ArtifactKind::Bin), (&test_bin, ArtifactKind::Test)] {
+            checker.linked_outputs.insert(
+                Arc::from(path.as_path()),
+                Artifact {
+                    path: Arc::from(path.as_path()),
+                    kind,
+                },
+            );
+        }
+        let summary = Summary::new(
+            &Default::default(),
+            &checker.config,
+            &checker.generated_code,
+            &Default::default(),
+        );
+        let out_dir = dir.path().join("attestations");
+        write_in_toto(
+            &out_dir,
+            &checker,
+            Path::new("cackle.toml"),
+            "",
+            "release",
+            &summary,
+        )
+        .unwrap();
+
+        // Test binaries aren't shipped, so don't get attested.
+        assert!(!out_dir.join("foo-test.intoto.json").exists());
+        let statement: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(out_dir.join("foo.intoto.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(statement["_type"], IN_TOTO_STATEMENT_TYPE);
+        assert_eq!(statement["predicateType"], PREDICATE_TYPE);
+        assert_eq!(statement["subject"][0]["name"], "foo");
+        assert_eq!(
+            statement["subject"][0]["digest"]["sha256"],
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let predicate = &statement["predicate"];
+        assert_eq!(predicate["artifactKind"], "bin");
+        assert_eq!(predicate["config"]["name"], "cackle.toml");
+        assert!(predicate["packages"].is_array());
+    }
+}
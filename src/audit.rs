@@ -0,0 +1,182 @@
+//! An append-only log of the edits that were made to the config through cackle, recording when each
+//! was made, by whom, which problem it fixed and a digest of the change. This gives a trail of who
+//! granted what and when that doesn't depend on how the config's history is kept in version control.
+
+use crate::config_editor::Edit;
+use crate::config_file::unified_diff;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use serde::Deserialize;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct AuditLogOptions {
+    /// Print each entry as a line of JSON, exactly as it was logged.
+    #[clap(long)]
+    json: bool,
+}
+
+/// Returns where the audit log is written if `--audit-log` wasn't passed.
+pub(crate) fn default_path(target_dir: &Path) -> PathBuf {
+    target_dir.join("cackle").join("audit.log")
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Entry {
+    /// When the edit was applied, in UTC, e.g. `2024-03-01T12:00:00Z`.
+    pub(crate) timestamp: String,
+    pub(crate) user: String,
+    /// The problem that the edit was applied to fix.
+    pub(crate) problem: String,
+    pub(crate) edit_id: String,
+    pub(crate) edit_title: String,
+    /// The SHA-256 digest of the unified diff that the edit made to the config.
+    pub(crate) diff_sha256: String,
+}
+
+impl Entry {
+    /// Creates an entry for `edit`, which was applied to fix `problem`, changing the config from
+    /// `before` to `after`.
+    pub(crate) fn new(problem: &str, edit: &dyn Edit, before: &str, after: &str) -> Self {
+        let diff = unified_diff("cackle.toml", Some(before), after);
+        Self {
+            timestamp: crate::config::expiry::now().to_string(),
+            user: current_user(),
+            problem: problem.to_owned(),
+            edit_id: edit.id(),
+            edit_title: edit.title(),
+            diff_sha256: crate::sha256::hex_digest(diff.as_bytes()),
+        }
+    }
+}
+
+/// Returns the name of the user running us, as best we can tell.
+fn current_user() -> String {
+    ["USER", "USERNAME", "LOGNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|user| !user.is_empty()))
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+/// Appends `entries` to the log at `path`. Existing entries are never rewritten.
+pub(crate) fn append(path: &Path, entries: &[Entry]) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    }
+    let mut out = String::new();
+    for entry in entries {
+        out.push_str(&serde_json::to_string(entry)?);
+        out.push('\n');
+    }
+    // Each batch of entries is written with a single append so that concurrent writers don't
+    // interleave partial lines.
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(out.as_bytes()))
+        .with_context(|| format!("Failed to append to audit log `{}`", path.display()))
+}
+
+/// Reads all entries from the log at `path`. A missing log is treated as being empty.
+pub(crate) fn read(path: &Path) -> Result<Vec<Entry>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("Failed to read audit log `{}`", path.display()))
+        }
+    };
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).with_context(|| {
+                format!(
+                    "Failed to parse line {} of audit log `{}`",
+                    index + 1,
+                    path.display()
+                )
+            })
+        })
+        .collect()
+}
+
+/// Prints the entries in the log at `path`.
+pub(crate) fn print(path: &Path, options: &AuditLogOptions) -> Result<()> {
+    let entries = read(path)?;
+    if entries.is_empty() && !options.json {
+        println!("No config edits have been logged to `{}`", path.display());
+        return Ok(());
+    }
+    for entry in &entries {
+        if options.json {
+            println!("{}", serde_json::to_string(entry)?);
+            continue;
+        }
+        println!(
+            "{} {} applied `{}`",
+            entry.timestamp, entry.user, entry.edit_id
+        );
+        println!("  problem: {}", entry.problem);
+        println!("  edit: {}", entry.edit_title);
+        println!("  diff sha256: {}", entry.diff_sha256);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_editor::ConfigEditor;
+    use crate::config_editor::EditOpts;
+    use std::borrow::Cow;
+
+    struct AllowNet;
+
+    impl Edit for AllowNet {
+        fn title(&self) -> String {
+            "Allow net".to_owned()
+        }
+
+        fn id(&self) -> String {
+            "allow-api:foo:net:all".to_owned()
+        }
+
+        fn help(&self) -> Cow<'static, str> {
+            "".into()
+        }
+
+        fn apply(&self, _editor: &mut ConfigEditor, _opts: &EditOpts) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn append_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cackle").join("audit.log");
+        assert_eq!(read(&path).unwrap(), Vec::new());
+
+        let first = Entry::new("foo uses net", &AllowNet, "", "[pkg.foo]\n");
+        let second = Entry::new("foo uses net", &AllowNet, "", "[pkg.bar]\n");
+        assert_ne!(first.diff_sha256, second.diff_sha256);
+        append(&path, std::slice::from_ref(&first)).unwrap();
+        append(&path, &[]).unwrap();
+        append(&path, std::slice::from_ref(&second)).unwrap();
+        let entries = read(&path).unwrap();
+        assert_eq!(entries, vec![first, second]);
+        assert_eq!(entries[0].edit_id, "allow-api:foo:net:all");
+        assert!(entries[0].timestamp.ends_with('Z'));
+    }
+}
@@ -0,0 +1,175 @@
+//! Attributes the size of each linked binary to the packages that contributed code and data to it.
+//! We're already reading the symbol table of each binary in order to check API usage, so this is
+//! cheap to compute and gives a sense of how much each dependency contributes.
+
+use crate::crate_index::CrateIndex;
+use crate::crate_index::CrateSel;
+use crate::crate_index::PackageId;
+use crate::symbol::Symbol;
+use anyhow::Result;
+use clap::Parser;
+use fxhash::FxHashMap;
+use object::Object;
+use object::ObjectSymbol;
+use object::SymbolKind;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// What we report for code and data that we can't attribute to a package in the dependency tree.
+/// e.g. the standard library or code written in other languages.
+const OTHER: &str = "[other]";
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct BloatOptions {
+    /// The number of largest packages to show for each binary.
+    #[clap(long, default_value_t = 10)]
+    top: usize,
+
+    /// Show all packages.
+    #[clap(long)]
+    all: bool,
+}
+
+/// The number of bytes contributed by each package to each binary.
+#[derive(Default)]
+pub(crate) struct SizeReport {
+    bins: BTreeMap<PathBuf, BinSizes>,
+}
+
+struct BinSizes {
+    crate_sel: CrateSel,
+    sizes: FxHashMap<Option<PackageId>, u64>,
+}
+
+impl SizeReport {
+    /// Attributes the size of each function and static in `obj` to the package that defined it.
+    /// The package is determined from the crate name at the start of the symbol's demangled name.
+    pub(crate) fn record_bin(
+        &mut self,
+        bin_path: &Path,
+        crate_sel: &CrateSel,
+        obj: &object::File,
+        crate_index: &CrateIndex,
+    ) -> Result<()> {
+        let name_prefix_to_pkg_id = crate_index.name_prefix_to_pkg_id();
+        let mut sizes: FxHashMap<Option<PackageId>, u64> = FxHashMap::default();
+        for sym in obj.symbols() {
+            if sym.size() == 0 || !matches!(sym.kind(), SymbolKind::Text | SymbolKind::Data) {
+                continue;
+            }
+            let symbol = Symbol::borrowed(sym.name_bytes()?);
+            let mut names = symbol.names()?;
+            let pkg_id = names
+                .next_name()?
+                .and_then(|(mut parts, _)| parts.next())
+                .and_then(|crate_name| name_prefix_to_pkg_id.get(crate_name))
+                .cloned();
+            *sizes.entry(pkg_id).or_default() += sym.size();
+        }
+        self.bins.insert(
+            bin_path.to_owned(),
+            BinSizes {
+                crate_sel: crate_sel.clone(),
+                sizes,
+            },
+        );
+        Ok(())
+    }
+
+    pub(crate) fn print(&self, options: &BloatOptions) {
+        if self.bins.is_empty() {
+            println!("No binaries were linked");
+        }
+        for line in self.report_lines(options) {
+            println!("{line}");
+        }
+    }
+
+    fn report_lines(&self, options: &BloatOptions) -> Vec<String> {
+        let mut lines = Vec::new();
+        for BinSizes { crate_sel, sizes } in self.bins.values() {
+            let total: u64 = sizes.values().sum();
+            lines.push(format!("{crate_sel} ({})", format_size(total)));
+            let mut entries: Vec<(String, u64)> = sizes
+                .iter()
+                .map(|(pkg_id, size)| {
+                    let name = pkg_id
+                        .as_ref()
+                        .map_or_else(|| OTHER.to_owned(), |pkg_id| pkg_id.to_string());
+                    (name, *size)
+                })
+                .collect();
+            entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            let num_shown = if options.all {
+                entries.len()
+            } else {
+                options.top.min(entries.len())
+            };
+            for (name, size) in &entries[..num_shown] {
+                let percent = *size as f64 * 100.0 / total.max(1) as f64;
+                lines.push(format!(
+                    "  {:>10} {percent:>5.1}%  {name}",
+                    format_size(*size)
+                ));
+            }
+            if num_shown < entries.len() {
+                lines.push(format!("  ... and {} more", entries.len() - num_shown));
+            }
+        }
+        lines
+    }
+}
+
+pub(crate) fn format_size(bytes: u64) -> String {
+    const KIB: u64 = 1024;
+    const MIB: u64 = 1024 * KIB;
+    if bytes < KIB {
+        format!("{bytes} B")
+    } else if bytes < MIB {
+        format!("{:.1} KiB", bytes as f64 / KIB as f64)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / MIB as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BinSizes;
+    use super::BloatOptions;
+    use super::SizeReport;
+    use crate::crate_index::testing::pkg_id;
+    use crate::crate_index::CrateSel;
+    use std::path::PathBuf;
+
+    #[test]
+    fn report() {
+        let mut report = SizeReport::default();
+        report.bins.insert(
+            PathBuf::from("bin1"),
+            BinSizes {
+                crate_sel: CrateSel::primary(pkg_id("bin1")),
+                sizes: [
+                    (Some(pkg_id("crab1")), 3 * 1024),
+                    (Some(pkg_id("crab2")), 512),
+                    (None, 4608),
+                ]
+                .into_iter()
+                .collect(),
+            },
+        );
+        assert_eq!(
+            report.report_lines(&BloatOptions { top: 2, all: false }),
+            vec![
+                "bin1 (8.0 KiB)".to_owned(),
+                "     4.5 KiB  56.2%  [other]".to_owned(),
+                "     3.0 KiB  37.5%  crab1".to_owned(),
+                "  ... and 1 more".to_owned(),
+            ]
+        );
+        assert_eq!(
+            report.report_lines(&BloatOptions { top: 2, all: true })[3],
+            "       512 B   6.2%  crab2"
+        );
+    }
+}
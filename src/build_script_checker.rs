@@ -1,9 +1,11 @@
 use crate::config::permissions::PermSel;
 use crate::config::Config;
 use crate::crate_index::PackageId;
+use crate::problem::CapabilityManifestViolation;
 use crate::problem::DisallowedBuildInstruction;
 use crate::problem::Problem;
 use crate::problem::ProblemList;
+use crate::problem::UndeclaredCapability;
 use crate::proxy::rpc::BinExecutionOutput;
 use anyhow::Result;
 
@@ -33,6 +35,7 @@ impl BuildScriptReport {
             )));
             return Ok(report);
         };
+        let manifest = config.capability_manifests.get(&crate_sel.pkg_id);
         for line in stdout.lines() {
             if line.starts_with("cargo:") {
                 report.problems.merge(check_directive(
@@ -40,6 +43,16 @@ impl BuildScriptReport {
                     &crate_sel.pkg_id,
                     allow_build_instructions,
                 ));
+                if let Some(manifest) = manifest {
+                    if !is_permitted(line, manifest.build_instructions()) {
+                        report.problems.push(Problem::CapabilityManifestViolation(
+                            CapabilityManifestViolation {
+                                crate_sel: crate_sel.clone(),
+                                capability: UndeclaredCapability::BuildInstruction(line.to_owned()),
+                            },
+                        ));
+                    }
+                }
             }
             if let Some(rest) = line.strip_prefix("cargo:rustc-env=") {
                 if let Some((var_name, _value)) = rest.split_once('=') {
@@ -65,16 +78,7 @@ fn check_directive(
     pkg_id: &PackageId,
     allow_build_instructions: &[String],
 ) -> ProblemList {
-    if ALWAYS_PERMITTED
-        .iter()
-        .any(|prefix| instruction.starts_with(prefix))
-    {
-        return ProblemList::default();
-    }
-    if allow_build_instructions
-        .iter()
-        .any(|i| matches(instruction, i))
-    {
+    if is_permitted(instruction, allow_build_instructions) {
         return ProblemList::default();
     }
     Problem::DisallowedBuildInstruction(DisallowedBuildInstruction {
@@ -84,6 +88,17 @@ fn check_directive(
     .into()
 }
 
+/// Returns whether `instruction` is either always permitted or matches one of
+/// `allow_build_instructions`.
+fn is_permitted(instruction: &str, allow_build_instructions: &[String]) -> bool {
+    ALWAYS_PERMITTED
+        .iter()
+        .any(|prefix| instruction.starts_with(prefix))
+        || allow_build_instructions
+            .iter()
+            .any(|i| matches(instruction, i))
+}
+
 fn matches(instruction: &str, rule: &str) -> bool {
     if let Some(prefix) = rule.strip_suffix('*') {
         instruction.starts_with(prefix)
@@ -98,15 +113,24 @@ mod tests {
     use crate::config::SandboxConfig;
     use crate::crate_index::testing::pkg_id;
     use crate::crate_index::CrateSel;
+    use crate::problem::CapabilityManifestViolation;
     use crate::problem::DisallowedBuildInstruction;
     use crate::problem::Problem;
     use crate::problem::ProblemList;
+    use crate::problem::UndeclaredCapability;
     use crate::proxy::rpc::BinExecutionOutput;
     use std::path::PathBuf;
 
     #[track_caller]
     fn check(stdout: &str, config_str: &str) -> ProblemList {
-        let config = config::testing::parse(config_str).unwrap();
+        check_with_manifest(stdout, config_str, None)
+    }
+
+    #[track_caller]
+    fn check_with_manifest(stdout: &str, config_str: &str, manifest: Option<&str>) -> ProblemList {
+        let manifests: Vec<_> = manifest.into_iter().map(|m| ("my_pkg", m)).collect();
+        let config =
+            config::testing::parse_with_capability_manifests(config_str, &manifests).unwrap();
         let outputs = BinExecutionOutput {
             exit_code: 0,
             stdout: stdout.as_bytes().to_owned(),
@@ -115,6 +139,8 @@ mod tests {
             sandbox_config: SandboxConfig::default(),
             binary_path: PathBuf::new(),
             sandbox_config_display: None,
+            waiting_for_input: None,
+            failed_tests: Vec::new(),
         };
         super::BuildScriptReport::build(&outputs, &config)
             .unwrap()
@@ -165,4 +191,36 @@ mod tests {
             ProblemList::default()
         );
     }
+
+    #[test]
+    fn test_capability_manifest() {
+        let config = r#"
+            [pkg.my_pkg.build]
+            allow_build_instructions = [ "cargo:rustc-link-*" ]
+            "#;
+        let manifest = r#"
+            version = 1
+
+            [build]
+            allow_build_instructions = [ "cargo:rustc-link-lib=foo" ]
+            "#;
+        assert_eq!(
+            check_with_manifest(
+                "cargo:rerun-if-changed=a.txt\ncargo:rustc-link-lib=foo",
+                config,
+                Some(manifest)
+            ),
+            ProblemList::default()
+        );
+        assert_eq!(
+            check_with_manifest("cargo:rustc-link-lib=bar", config, Some(manifest)),
+            Problem::CapabilityManifestViolation(CapabilityManifestViolation {
+                crate_sel: CrateSel::build_script(pkg_id("my_pkg")),
+                capability: UndeclaredCapability::BuildInstruction(
+                    "cargo:rustc-link-lib=bar".to_owned()
+                ),
+            })
+            .into()
+        );
+    }
 }
@@ -0,0 +1,343 @@
+//! Capability manifests. A capability manifest is a file, `cackle/capabilities.toml`, that a crate
+//! author can publish alongside their crate in order to declare what their crate needs. It's
+//! generated by `cargo acl manifest` from the APIs that the package was observed using when it was
+//! checked. Manifests shipped by dependencies are verified against what we observe
+//! those dependencies doing and can optionally be trusted in place of per-package configuration.
+
+use crate::config::permissions::PermSel;
+use crate::config::permissions::PermissionScope;
+use crate::config::ApiName;
+use crate::config::Config;
+use crate::config::PackageConfig;
+use crate::crate_index::CrateIndex;
+use crate::crate_index::CrateKind;
+use crate::crate_index::PackageId;
+use crate::metrics::UsageCounter;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use fxhash::FxHashMap;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The current version of the capability manifest format.
+const MANIFEST_VERSION: i64 = 1;
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CapabilityManifest {
+    pub(crate) version: i64,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) allow_apis: Vec<ApiName>,
+
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub(crate) allow_unsafe: bool,
+
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub(crate) allow_proc_macro: bool,
+
+    /// Capabilities needed by the package's build script in addition to those of the package.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) build: Option<BuildCapabilities>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct BuildCapabilities {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) allow_apis: Vec<ApiName>,
+
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub(crate) allow_unsafe: bool,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) allow_build_instructions: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub(crate) allow_network: bool,
+}
+
+impl CapabilityManifest {
+    /// Builds a manifest for `pkg_name` from `used_apis`, the APIs that were observed being used,
+    /// keyed by permission selector. APIs that `config` grants, but that weren't used, aren't
+    /// declared. Everything else, e.g. unsafe and build instructions, isn't recorded as it's used,
+    /// so is taken from the permissions granted by `config`.
+    pub(crate) fn from_usages<'a>(
+        pkg_name: &str,
+        used_apis: impl Iterator<Item = (&'a PermSel, &'a ApiName)>,
+        config: &Config,
+    ) -> Self {
+        let mut manifest = CapabilityManifest {
+            version: MANIFEST_VERSION,
+            ..Default::default()
+        };
+        let mut build = BuildCapabilities::default();
+        for (perm_sel, api) in used_apis {
+            if perm_sel.package_name.as_ref() != pkg_name {
+                continue;
+            }
+            match perm_sel.scope {
+                PermissionScope::All
+                | PermissionScope::FromBuild
+                | PermissionScope::FromTest
+                | PermissionScope::ProcMacro => manifest.allow_apis.push(api.clone()),
+                PermissionScope::Build => build.allow_apis.push(api.clone()),
+                // Manifests don't cover tests.
+                PermissionScope::Test => {}
+            }
+        }
+        manifest.allow_apis = sorted(&manifest.allow_apis);
+        // Build scripts get the package's capabilities, so there's no need to repeat them.
+        build.allow_apis = sorted(&build.allow_apis);
+        build
+            .allow_apis
+            .retain(|api| !manifest.allow_apis.contains(api));
+        let primary_sel = PermSel::for_primary(pkg_name);
+        if let Some(pkg_config) = config.permissions_no_inheritance.get(&primary_sel) {
+            manifest.allow_unsafe =
+                pkg_config.allow_unsafe || !pkg_config.allow_unsafe_in.is_empty();
+            manifest.allow_proc_macro = pkg_config.allow_proc_macro;
+        }
+        let build_sel = PermSel::for_build_script(pkg_name);
+        if let Some(build_config) = config.permissions_no_inheritance.get(&build_sel) {
+            build.allow_unsafe =
+                build_config.allow_unsafe || !build_config.allow_unsafe_in.is_empty();
+            build.allow_build_instructions = sorted(&build_config.allow_build_instructions);
+        }
+        // Network access is generally granted via inheritance from the global sandbox config, so
+        // we look at the effective permissions here.
+        if let Some(build_config) = config.permissions.get(&build_sel) {
+            build.allow_network = build_config.sandbox.allow_network == Some(true);
+        }
+        if build != BuildCapabilities::default() {
+            manifest.build = Some(build);
+        }
+        manifest
+    }
+
+    pub(crate) fn serialise(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Returns whether `api` is declared for the specified kind of crate. Build scripts get the
+    /// package's capabilities plus those of `build`. Tests aren't covered by manifests.
+    pub(crate) fn declares_api(&self, api: &ApiName, kind: CrateKind) -> bool {
+        match kind {
+            CrateKind::Primary => self.allow_apis.contains(api),
+            CrateKind::BuildScript => {
+                self.allow_apis.contains(api)
+                    || self
+                        .build
+                        .as_ref()
+                        .is_some_and(|build| build.allow_apis.contains(api))
+            }
+            CrateKind::Test => true,
+        }
+    }
+
+    /// Returns whether unsafe is declared for the specified kind of crate.
+    pub(crate) fn declares_unsafe(&self, kind: CrateKind) -> bool {
+        match kind {
+            CrateKind::Primary => self.allow_unsafe,
+            CrateKind::BuildScript => {
+                self.allow_unsafe || self.build.as_ref().is_some_and(|build| build.allow_unsafe)
+            }
+            CrateKind::Test => true,
+        }
+    }
+
+    /// Returns the build instructions that the build script is declared to emit.
+    pub(crate) fn build_instructions(&self) -> &[String] {
+        self.build
+            .as_ref()
+            .map(|build| build.allow_build_instructions.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Returns the permissions for the package that this manifest declares.
+    pub(crate) fn package_config(&self) -> PackageConfig {
+        PackageConfig {
+            allow_apis: self.allow_apis.clone(),
+            allow_unsafe: self.allow_unsafe,
+            allow_proc_macro: self.allow_proc_macro,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the permissions for the package's build script that this manifest declares, if any.
+    pub(crate) fn build_package_config(&self) -> Option<PackageConfig> {
+        let build = self.build.as_ref()?;
+        let mut config = PackageConfig {
+            allow_apis: build.allow_apis.clone(),
+            allow_unsafe: build.allow_unsafe,
+            allow_build_instructions: build.allow_build_instructions.clone(),
+            ..Default::default()
+        };
+        if build.allow_network {
+            config.sandbox.allow_network = Some(true);
+        }
+        Some(config)
+    }
+}
+
+/// Loads the capability manifests published by all packages that aren't part of our workspace.
+pub(crate) fn load_dependency_manifests(
+    crate_index: &CrateIndex,
+) -> Result<FxHashMap<PackageId, CapabilityManifest>> {
+    let mut manifests = FxHashMap::default();
+    for (pkg_id, info) in &crate_index.package_infos {
+        if info.is_workspace_member {
+            continue;
+        }
+        let manifest = load_manifest(info.directory.as_std_path())
+            .with_context(|| format!("Invalid capability manifest for package `{pkg_id}`"))?;
+        if let Some(manifest) = manifest {
+            manifests.insert(pkg_id.clone(), manifest);
+        }
+    }
+    Ok(manifests)
+}
+
+/// Loads the capability manifest published by the package with sources in `pkg_dir`, if it has one.
+pub(crate) fn load_manifest(pkg_dir: &Path) -> Result<Option<CapabilityManifest>> {
+    let path = manifest_path(pkg_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    parse_file(&path).map(Some)
+}
+
+fn parse_file(path: &Path) -> Result<CapabilityManifest> {
+    let manifest: CapabilityManifest = toml::from_str(&crate::fs::read_to_string(path)?)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    if manifest.version > MANIFEST_VERSION {
+        bail!(
+            "{} has version {}, but the maximum supported version is {MANIFEST_VERSION}",
+            path.display(),
+            manifest.version
+        );
+    }
+    Ok(manifest)
+}
+
+/// Returns the path of the capability manifest for a package with sources in `pkg_dir`.
+pub(crate) fn manifest_path(pkg_dir: &Path) -> PathBuf {
+    pkg_dir.join("cackle").join("capabilities.toml")
+}
+
+/// Writes a capability manifest for `pkg_id` into the package's directory, returning the path that
+/// was written.
+pub(crate) fn write_for_package(
+    pkg_id: &PackageId,
+    crate_index: &CrateIndex,
+    usage_counter: &UsageCounter,
+    config: &Config,
+) -> Result<PathBuf> {
+    let pkg_dir = crate_index
+        .pkg_dir(pkg_id)
+        .ok_or_else(|| anyhow!("Missing pkg_dir for package `{pkg_id}`"))?;
+    let path = manifest_path(pkg_dir);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    }
+    let manifest = CapabilityManifest::from_usages(
+        pkg_id.name_str(),
+        usage_counter
+            .usages()
+            .map(|(perm_sel, api, _)| (perm_sel, api)),
+        config,
+    );
+    let contents = format!(
+        "# Capabilities required by `{}`. Generated by `cargo acl manifest`.\n{}",
+        pkg_id.name_str(),
+        manifest.serialise()?
+    );
+    crate::fs::write_atomic(&path, &contents)?;
+    Ok(path)
+}
+
+fn sorted<T: Ord + Clone>(values: &[T]) -> Vec<T> {
+    let mut values = values.to_vec();
+    values.sort();
+    values.dedup();
+    values
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CapabilityManifest;
+    use crate::config::permissions::PermSel;
+    use crate::config::testing::parse;
+    use crate::config::ApiName;
+
+    #[test]
+    fn manifest_from_usages() {
+        let config = parse(
+            r#"
+            [api.fs]
+            include = ["std::fs"]
+
+            [api.net]
+            include = ["std::net"]
+
+            [api.process]
+            include = ["std::process"]
+
+            [pkg.foo]
+            allow_apis = ["net", "fs"]
+            allow_unsafe = true
+
+            [pkg.foo.build]
+            allow_apis = ["process"]
+            allow_build_instructions = ["cargo:rustc-link-lib=*"]
+            sandbox.allow_network = true
+
+            [pkg.bar]
+            allow_apis = ["fs", "net"]
+            "#,
+        )
+        .unwrap();
+        let used_apis = [
+            (PermSel::for_primary("foo"), ApiName::from("net")),
+            (PermSel::for_primary("foo"), ApiName::from("fs")),
+            (PermSel::for_build_script("foo"), ApiName::from("process")),
+            (PermSel::for_build_script("foo"), ApiName::from("fs")),
+            (PermSel::for_primary("bar"), ApiName::from("fs")),
+        ];
+        let used = || used_apis.iter().map(|(perm_sel, api)| (perm_sel, api));
+
+        let manifest = CapabilityManifest::from_usages("foo", used(), &config);
+        assert_eq!(
+            manifest.serialise().unwrap(),
+            indoc::indoc! {r#"
+                version = 1
+                allow_apis = ["fs", "net"]
+                allow_unsafe = true
+
+                [build]
+                allow_apis = ["process"]
+                allow_build_instructions = ["cargo:rustc-link-lib=*"]
+                allow_network = true
+            "#}
+        );
+        let round_tripped: CapabilityManifest =
+            toml::from_str(&manifest.serialise().unwrap()).unwrap();
+        assert_eq!(round_tripped, manifest);
+
+        // `bar` is allowed `net`, but doesn't use it, so it's not declared.
+        let manifest = CapabilityManifest::from_usages("bar", used(), &config);
+        assert_eq!(
+            manifest.serialise().unwrap(),
+            "version = 1\nallow_apis = [\"fs\"]\n"
+        );
+    }
+}
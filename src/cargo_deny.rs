@@ -0,0 +1,426 @@
+//! Support for a cargo-deny config (`deny.toml`). Projects that already list banned crates and
+//! allowed sources there can point `[deny] cargo_deny` at it, rather than duplicating that policy in
+//! cackle.toml. Only the parts of `[bans]` and `[sources]` that are about individual packages are
+//! used. Licenses, advisories and duplicate versions are left to cargo-deny.
+
+use crate::crate_index::CrateIndex;
+use crate::crate_index::PackageId;
+use crate::crate_index::PackageInfo;
+use crate::problem::CargoDenyViolation;
+use crate::problem::Problem;
+use crate::problem::ProblemList;
+use crate::problem::Severity;
+use anyhow::Context;
+use anyhow::Result;
+use cargo_metadata::semver::VersionReq;
+use serde::Deserialize;
+use std::path::Path;
+
+const CRATES_IO_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+const CRATES_IO_SPARSE_INDEX: &str = "https://index.crates.io";
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+struct DenyToml {
+    #[serde(default)]
+    bans: Bans,
+    #[serde(default)]
+    sources: Sources,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Bans {
+    #[serde(default)]
+    deny: Vec<BanEntry>,
+}
+
+/// An entry in `bans.deny`. Either a package spec such as `openssl` or `openssl@<0.10`, or a table.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum BanEntry {
+    Spec(String),
+    Table(BanTable),
+}
+
+#[derive(Deserialize, Debug)]
+struct BanTable {
+    /// A package spec. Newer versions of cargo-deny use this rather than `name` and `version`.
+    #[serde(rename = "crate")]
+    spec: Option<String>,
+    name: Option<String>,
+    version: Option<String>,
+    reason: Option<String>,
+    /// Packages that are allowed to depend on the banned package. Since we don't check who depends
+    /// on what, entries with wrappers are ignored.
+    #[serde(default)]
+    wrappers: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct Sources {
+    #[serde(default = "warn")]
+    unknown_registry: Level,
+    #[serde(default = "warn")]
+    unknown_git: Level,
+    #[serde(default = "crates_io")]
+    allow_registry: Vec<String>,
+    #[serde(default)]
+    allow_git: Vec<String>,
+    #[serde(default)]
+    allow_org: AllowOrg,
+}
+
+impl Default for Sources {
+    fn default() -> Self {
+        Self {
+            unknown_registry: warn(),
+            unknown_git: warn(),
+            allow_registry: crates_io(),
+            allow_git: Vec::new(),
+            allow_org: AllowOrg::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct AllowOrg {
+    #[serde(default)]
+    github: Vec<String>,
+    #[serde(default)]
+    gitlab: Vec<String>,
+    #[serde(default)]
+    bitbucket: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Level {
+    Deny,
+    Warn,
+    Allow,
+}
+
+fn warn() -> Level {
+    Level::Warn
+}
+
+fn crates_io() -> Vec<String> {
+    vec![CRATES_IO_INDEX.to_owned()]
+}
+
+/// A banned package, with the version requirement parsed.
+struct Ban {
+    name: String,
+    version: Option<VersionReq>,
+    reason: Option<String>,
+}
+
+/// Returns problems for packages in `crate_index` that the cargo-deny config at `path` bans, or
+/// that come from sources that it doesn't allow.
+pub(crate) fn problems(path: &Path, crate_index: &CrateIndex) -> Result<ProblemList> {
+    let deny_toml: DenyToml = toml::from_str(&crate::fs::read_to_string(path)?)
+        .with_context(|| format!("Failed to parse `{}`", path.display()))?;
+    let mut packages: Vec<(&PackageId, &PackageInfo)> = crate_index.package_infos.iter().collect();
+    packages.sort_by_key(|(pkg_id, _)| pkg_id.to_string());
+    check(&deny_toml, packages)
+        .with_context(|| format!("Invalid cargo-deny config `{}`", path.display()))
+}
+
+fn check<'a>(
+    deny_toml: &DenyToml,
+    packages: impl IntoIterator<Item = (&'a PackageId, &'a PackageInfo)>,
+) -> Result<ProblemList> {
+    let bans = deny_toml
+        .bans
+        .deny
+        .iter()
+        .filter_map(|entry| Ban::from_entry(entry).transpose())
+        .collect::<Result<Vec<_>>>()?;
+    let mut problems = ProblemList::default();
+    for (pkg_id, info) in packages {
+        for ban in &bans {
+            if ban.matches(pkg_id) {
+                let mut reason = "is banned by cargo-deny config".to_owned();
+                if let Some(ban_reason) = &ban.reason {
+                    reason = format!("{reason}: {ban_reason}");
+                }
+                problems.push(violation(pkg_id, reason, Severity::Error));
+            }
+        }
+        if let Some(source) = &info.source {
+            if let Some((reason, severity)) = deny_toml.sources.check(source) {
+                problems.push(violation(pkg_id, reason, severity));
+            }
+        }
+    }
+    Ok(problems)
+}
+
+fn violation(pkg_id: &PackageId, reason: String, severity: Severity) -> Problem {
+    Problem::CargoDenyViolation(CargoDenyViolation {
+        pkg_id: pkg_id.clone(),
+        reason,
+        severity,
+    })
+}
+
+impl Ban {
+    /// Returns the ban for `entry`, or None if the entry is one that we ignore.
+    fn from_entry(entry: &BanEntry) -> Result<Option<Self>> {
+        let (spec, version, reason) = match entry {
+            BanEntry::Spec(spec) => (spec.as_str(), None, None),
+            BanEntry::Table(table) => {
+                if !table.wrappers.is_empty() {
+                    return Ok(None);
+                }
+                let spec = table
+                    .spec
+                    .as_deref()
+                    .or(table.name.as_deref())
+                    .context("Entry in `bans.deny` has neither `crate` nor `name`")?;
+                (spec, table.version.as_deref(), table.reason.clone())
+            }
+        };
+        let (name, spec_version) = match spec.split_once('@') {
+            Some((name, version)) => (name, Some(version)),
+            None => (spec, None),
+        };
+        let version = spec_version
+            .or(version)
+            .filter(|version| *version != "*")
+            .map(|version| {
+                VersionReq::parse(version)
+                    .with_context(|| format!("Invalid version `{version}` for `{name}`"))
+            })
+            .transpose()?;
+        Ok(Some(Self {
+            name: name.to_owned(),
+            version,
+            reason,
+        }))
+    }
+
+    fn matches(&self, pkg_id: &PackageId) -> bool {
+        pkg_id.unqualified_name() == self.name
+            && self
+                .version
+                .as_ref()
+                .map_or(true, |req| req.matches(pkg_id.version()))
+    }
+}
+
+impl Sources {
+    /// Returns why `source` isn't allowed and how severe that is, or None if it's allowed.
+    fn check(&self, source: &str) -> Option<(String, Severity)> {
+        if let Some(url) = source.strip_prefix("git+") {
+            let url = normalise_git_url(url);
+            let allowed = self
+                .allow_git
+                .iter()
+                .any(|allowed| normalise_git_url(allowed) == url)
+                || self.org_urls().any(|prefix| url.starts_with(&prefix));
+            return (!allowed)
+                .then(|| severity(self.unknown_git))
+                .flatten()
+                .map(|severity| {
+                    (
+                        format!("comes from git repository `{url}`, which cargo-deny config doesn't allow"),
+                        severity,
+                    )
+                });
+        }
+        let url = normalise_registry_url(source);
+        let allowed = self
+            .allow_registry
+            .iter()
+            .any(|allowed| normalise_registry_url(allowed) == url);
+        (!allowed)
+            .then(|| severity(self.unknown_registry))
+            .flatten()
+            .map(|severity| {
+                (
+                    format!("comes from registry `{url}`, which cargo-deny config doesn't allow"),
+                    severity,
+                )
+            })
+    }
+
+    /// Returns the URL prefixes of the repositories in `allow-org`.
+    fn org_urls(&self) -> impl Iterator<Item = String> + '_ {
+        self.allow_org
+            .github
+            .iter()
+            .map(|org| format!("https://github.com/{org}/"))
+            .chain(
+                self.allow_org
+                    .gitlab
+                    .iter()
+                    .map(|org| format!("https://gitlab.com/{org}/")),
+            )
+            .chain(
+                self.allow_org
+                    .bitbucket
+                    .iter()
+                    .map(|org| format!("https://bitbucket.org/{org}/")),
+            )
+    }
+}
+
+fn severity(level: Level) -> Option<Severity> {
+    match level {
+        Level::Deny => Some(Severity::Error),
+        Level::Warn => Some(Severity::Warning),
+        Level::Allow => None,
+    }
+}
+
+/// Returns `url` without any query or fragment, which cargo uses for the branch and revision, and
+/// without a trailing `.git` or slash.
+fn normalise_git_url(url: &str) -> &str {
+    let url = url.split(['?', '#']).next().unwrap_or(url);
+    let url = url.trim_end_matches('/');
+    url.strip_suffix(".git").unwrap_or(url)
+}
+
+/// Returns the URL of a registry index without cargo's protocol prefix or a trailing slash. Both of
+/// crates.io's indexes are returned as the git index URL, since cargo-deny treats them as the same.
+fn normalise_registry_url(url: &str) -> &str {
+    let url = url
+        .strip_prefix("registry+")
+        .or_else(|| url.strip_prefix("sparse+"))
+        .unwrap_or(url)
+        .trim_end_matches('/');
+    if url == CRATES_IO_SPARSE_INDEX {
+        CRATES_IO_INDEX
+    } else {
+        url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crate_index::testing::pkg_id_with_version;
+    use indoc::indoc;
+    use std::sync::Arc;
+
+    fn info(source: Option<&str>) -> PackageInfo {
+        let mut info = crate::crate_index::testing::index_with_package_names(&["x"])
+            .package_infos
+            .values()
+            .next()
+            .unwrap()
+            .clone();
+        info.source = source.map(Arc::from);
+        info
+    }
+
+    #[track_caller]
+    fn check_packages(deny_toml: &str, packages: &[(&str, &str, Option<&str>)]) -> Vec<String> {
+        let deny_toml: DenyToml = toml::from_str(deny_toml).unwrap();
+        let packages: Vec<(PackageId, PackageInfo)> = packages
+            .iter()
+            .map(|(name, version, source)| {
+                (
+                    pkg_id_with_version(name, version.parse().unwrap()),
+                    info(*source),
+                )
+            })
+            .collect();
+        check(&deny_toml, packages.iter().map(|(id, info)| (id, info)))
+            .unwrap()
+            .take()
+            .into_iter()
+            .map(|problem| {
+                let severity = match &problem {
+                    Problem::CargoDenyViolation(info) => info.severity,
+                    _ => panic!("Unexpected problem {problem}"),
+                };
+                format!("{severity:?}: {problem}")
+            })
+            .collect()
+    }
+
+    const CRATES_IO: Option<&str> = Some("registry+https://github.com/rust-lang/crates.io-index");
+
+    #[test]
+    fn bans() {
+        let deny_toml = indoc! {r#"
+            [licenses]
+            allow = ["MIT"]
+
+            [bans]
+            multiple-versions = "warn"
+            deny = [
+                "openssl-sys",
+                { name = "old", version = "<1.0" },
+                { crate = "chrono@<0.4.20", reason = "Unsound" },
+                { crate = "wrapped", wrappers = ["our-wrapper"] },
+            ]
+        "#};
+        assert_eq!(
+            check_packages(
+                deny_toml,
+                &[
+                    ("openssl-sys", "0.9.0", CRATES_IO),
+                    ("old", "0.5.0", CRATES_IO),
+                    ("old", "1.2.0", CRATES_IO),
+                    ("chrono", "0.4.19", CRATES_IO),
+                    ("chrono", "0.4.31", CRATES_IO),
+                    ("wrapped", "1.0.0", CRATES_IO),
+                ]
+            ),
+            vec![
+                "Error: `openssl-sys[0.9.0]` is banned by cargo-deny config",
+                "Error: `old[0.5.0]` is banned by cargo-deny config",
+                "Error: `chrono[0.4.19]` is banned by cargo-deny config: Unsound",
+            ]
+        );
+    }
+
+    #[test]
+    fn sources() {
+        let deny_toml = indoc! {r#"
+            [sources]
+            unknown-registry = "deny"
+            allow-git = ["https://github.com/allowed/repo.git"]
+
+            [sources.allow-org]
+            github = ["our-org"]
+        "#};
+        assert_eq!(
+            check_packages(
+                deny_toml,
+                &[
+                    ("a", "1.0.0", CRATES_IO),
+                    ("b", "1.0.0", Some("sparse+https://index.crates.io/")),
+                    ("c", "1.0.0", Some("sparse+https://example.com/index/")),
+                    (
+                        "d",
+                        "1.0.0",
+                        Some("git+https://github.com/allowed/repo#abc")
+                    ),
+                    (
+                        "e",
+                        "1.0.0",
+                        Some("git+https://github.com/our-org/thing?branch=main#abc")
+                    ),
+                    ("f", "1.0.0", Some("git+https://github.com/other/repo#abc")),
+                    ("g", "1.0.0", None),
+                ]
+            ),
+            vec![
+                "Error: `c[1.0.0]` comes from registry `https://example.com/index`, which cargo-deny \
+                 config doesn't allow",
+                "Warning: `f[1.0.0]` comes from git repository `https://github.com/other/repo`, which \
+                 cargo-deny config doesn't allow",
+            ]
+        );
+        assert!(check_packages(
+            "[sources]\nunknown-git = \"allow\"\n",
+            &[("f", "1.0.0", Some("git+https://github.com/other/repo#abc"))]
+        )
+        .is_empty());
+    }
+}
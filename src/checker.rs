@@ -1,27 +1,47 @@
+use crate::bloat::SizeReport;
 use crate::build_script_checker;
+use crate::config::built_in::RustcVersion;
 use crate::config::permissions::PermSel;
 use crate::config::permissions::PermissionScope;
+use crate::config::ApiConfig;
 use crate::config::ApiName;
 use crate::config::Config;
+use crate::config::GenericAttribution;
+use crate::config_file::ConfigFile;
 use crate::crate_index::CrateIndex;
 use crate::crate_index::CrateKind;
+use crate::crate_index::CrateSel;
 use crate::crate_index::PackageId;
+use crate::fs::PathRemap;
+use crate::link_info::Artifact;
 use crate::link_info::LinkInfo;
 use crate::location::SourceLocation;
+use crate::metrics::UsageCounter;
 use crate::names::Name;
+use crate::names::SymbolAndName;
 use crate::names::SymbolOrDebugName;
+use crate::plugin::PluginInputs;
 use crate::problem::ApiUsages;
+use crate::problem::CapabilityManifestViolation;
+use crate::problem::GeneratedCode;
 use crate::problem::OffTreeApiUsage;
 use crate::problem::PossibleExportedApi;
 use crate::problem::Problem;
 use crate::problem::ProblemList;
+use crate::problem::UndeclaredCapability;
 use crate::problem::UnusedAllowApi;
 use crate::proxy::cargo::profile_name;
 use crate::proxy::rpc;
 use crate::proxy::rpc::UnsafeUsage;
 use crate::proxy::subprocess::SubprocessConfig;
+use crate::remote_scan::ScanJob;
+use crate::scan_cache::ScanCache;
+use crate::status::StatusFile;
+use crate::symbol::Symbol;
 use crate::symbol_graph::backtrace::Backtracer;
+use crate::symbol_graph::object_memo::ObjectMemo;
 use crate::symbol_graph::NameSource;
+use crate::symbol_graph::ScanOutputs;
 use crate::symbol_graph::UsageDebugData;
 use crate::timing::TimingCollector;
 use crate::tmpdir::TempDir;
@@ -34,7 +54,12 @@ use anyhow::Result;
 use fxhash::FxHashMap;
 use fxhash::FxHashSet;
 use log::info;
+use log::warn;
+use serde::Deserialize;
+use serde::Serialize;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -46,20 +71,45 @@ pub(crate) struct Checker {
     /// For each name, the set of APIs active for that name and all names that have this name as a
     /// prefix.
     apis_by_prefix: api_map::ApiMap,
+    /// For each non-Rust symbol, such as `open`, the APIs that references to it count as using.
+    apis_by_native_symbol: FxHashMap<String, FxHashSet<ApiName>>,
+    /// For each link section name prefix, such as `.init_array`, the APIs that putting a reference
+    /// into a matching section counts as using. Longest prefixes come first.
+    apis_by_link_section: Vec<(String, FxHashSet<ApiName>)>,
     pub(crate) crate_infos: FxHashMap<PermSel, CrateInfo>,
-    config_path: PathBuf,
+    /// The config file that we load our config from.
+    pub(crate) config_file: ConfigFile,
     pub(crate) config: Arc<Config>,
-    target_dir: PathBuf,
+    pub(crate) target_dir: PathBuf,
     tmpdir: Arc<TempDir>,
     pub(crate) args: Arc<Args>,
     pub(crate) crate_index: Arc<CrateIndex>,
     pub(crate) sysroot: Arc<Path>,
 
+    /// The version of rustc in `sysroot`, if it could be determined. Used to select which paths
+    /// are included in built-in API definitions.
+    rustc_version: Option<RustcVersion>,
+
+    /// Path prefixes under which source files are considered to be part of the Rust standard
+    /// library. Derived from `sysroot` and from `std_path_prefixes` in our config.
+    std_path_prefixes: Vec<PathBuf>,
+
+    /// Directories of packages that were built as part of the standard library because build-std
+    /// is in use.
+    build_std_dirs: Vec<PathBuf>,
+
+    /// Source files generated by build scripts, keyed by the package whose crates included them.
+    pub(crate) generated_code: FxHashMap<PackageId, BTreeSet<PathBuf>>,
+
     /// Mapping from Rust source paths to the packages that contains them. Generally a source path
     /// will map to a single package, but in rare cases multiple packages could reference the same
     /// path outside of their source tree.
     path_to_pkg_ids: FxHashMap<PathBuf, Vec<PackageId>>,
 
+    /// `--remap-path-prefix` rules that were passed to rustc, in the order we first saw them. Used
+    /// to map source paths in debug info back to the paths in `path_to_pkg_ids`.
+    path_remaps: Vec<PathRemap>,
+
     pub(crate) timings: TimingCollector,
 
     backtracers: FxHashMap<Arc<Path>, Backtracer>,
@@ -68,6 +118,32 @@ pub(crate) struct Checker {
     /// corresponding notification that rustc has completed. We defer processing of these until
     /// rustc completes because we need information from the .deps file that rustc writes.
     outstanding_linker_invocations: Vec<LinkInfo>,
+
+    /// Counts of API usages, recorded so that we can track how they change between runs.
+    pub(crate) usage_counter: UsageCounter,
+
+    /// Sizes of each binary attributed to packages. Only populated if `collect_size_report` is set.
+    pub(crate) size_report: SizeReport,
+
+    /// Whether to attribute the sizes of binaries to packages in `size_report`. Collecting sizes
+    /// needs local access to the whole binary, so this also disables remote and parallel scans.
+    pub(crate) collect_size_report: bool,
+
+    /// Outputs from scanning binaries in previous checks. Only set when running as a daemon.
+    pub(crate) scan_cache: Option<Arc<ScanCache>>,
+
+    /// Which sections of object files have been found not to reference any APIs.
+    pub(crate) object_memo: ObjectMemo,
+
+    /// Our progress, which is written to a file for external tools if enabled.
+    pub(crate) status: StatusFile,
+
+    /// The output of each linker invocation that we've analysed, keyed by its path.
+    pub(crate) linked_outputs: BTreeMap<Arc<Path>, Artifact>,
+
+    /// The package and scope of each binary that we've analysed, e.g. `pkg.foo.build` for foo's
+    /// build script. Used to avoid reporting grants for targets that weren't built as unused.
+    linked_targets: FxHashSet<PermSel>,
 }
 
 #[derive(Default, Debug)]
@@ -78,12 +154,17 @@ pub(crate) struct CrateInfo {
     /// APIs that are allowed for this crate according to cackle.toml, but haven't yet been found to
     /// be used by the crate.
     unused_allowed_apis: FxHashSet<ApiName>,
+
+    /// APIs that are referenced by code from this crate that the linker discarded.
+    dead_code_apis: FxHashSet<ApiName>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) struct ApiUsage {
     pub(crate) bin_location: BinLocation,
     pub(crate) bin_path: Arc<Path>,
+    /// The linker output in which the usage was found, as shown to the user.
+    pub(crate) artifact: Artifact,
     pub(crate) permission_scope: PermissionScope,
     pub(crate) source_location: SourceLocation,
     /// The source location of the outer (non-inlined) function or variable.
@@ -92,11 +173,86 @@ pub(crate) struct ApiUsage {
     pub(crate) to: SymbolOrDebugName,
     pub(crate) to_name: Name,
     pub(crate) to_source: NameSource<'static>,
+    #[serde(skip)]
     pub(crate) debug_data: Option<UsageDebugData>,
 }
 
+/// Returns the name of the pseudo-API that we use to find references to paths listed in `[deny]
+/// paths`. The name isn't a valid bare key, so can't clash with any API defined in the config.
+fn denied_paths_api() -> ApiName {
+    ApiName::new("[deny]")
+}
+
+/// Returns the APIs that we should look for. These are the APIs defined in `config` plus, if any
+/// paths are denied, a pseudo-API that includes those paths.
+pub(crate) fn api_definitions(config: &Config) -> Cow<'_, BTreeMap<ApiName, ApiConfig>> {
+    if config.raw.deny.paths.is_empty() {
+        return Cow::Borrowed(&config.raw.apis);
+    }
+    let mut apis = config.raw.apis.clone();
+    apis.insert(
+        denied_paths_api(),
+        ApiConfig {
+            include: config.raw.deny.paths.clone(),
+            ..ApiConfig::default()
+        },
+    );
+    Cow::Owned(apis)
+}
+
+/// Returns the path prefixes under which source files are considered to be part of the Rust
+/// standard library or the precompiled crates that are bundled with it (e.g. hashbrown).
+fn std_path_prefixes(sysroot: &Path, configured: &[PathBuf]) -> Vec<PathBuf> {
+    let mut prefixes: Vec<PathBuf> = [
+        // Pre 2023-10-26
+        "/rustc/",
+        "/cargo/registry",
+        // Post 2023-10-26
+        "/rust/",
+    ]
+    .into_iter()
+    .map(PathBuf::from)
+    .collect();
+    prefixes.push(sysroot.to_owned());
+    // Toolchains built from source generally aren't built with remapped paths, so debug info
+    // refers to wherever the source actually lives. The sysroot links to that location.
+    for dir in [sysroot, &sysroot.join("lib/rustlib/src/rust")] {
+        if let Ok(resolved) = dir.canonicalize() {
+            if !prefixes.contains(&resolved) {
+                prefixes.push(resolved);
+            }
+        }
+    }
+    prefixes.extend(configured.iter().cloned());
+    prefixes
+}
+
+/// Where an off-tree API usage most likely came from.
+#[derive(Debug, PartialEq, Eq)]
+enum MacroOrigin {
+    /// The outer location is in the package that defines the name being referenced, so the
+    /// reference is just that package referencing its own name.
+    DefiningPackage,
+    /// The outer location is in a package that depends on the package that defines the name being
+    /// referenced.
+    DependentPackage(PackageId),
+    /// We couldn't explain the usage.
+    Unknown,
+}
+
+impl ApiUsage {
+    /// Returns a copy of this usage with the outer location used as the source location.
+    fn reattributed_to_outer_location(&self) -> ApiUsage {
+        let mut usage = self.clone();
+        if let Some(outer_location) = usage.outer_location.take() {
+            usage.source_location = outer_location;
+        }
+        usage
+    }
+}
+
 /// A location within a bin file (executable or shared object).
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) struct BinLocation {
     pub(crate) address: u64,
     /// The address of the start of the symbol (or section) containing `address`.
@@ -110,29 +266,81 @@ impl Checker {
         args: Arc<Args>,
         sysroot: Arc<Path>,
         crate_index: Arc<CrateIndex>,
-        config_path: PathBuf,
+        config_file: ConfigFile,
     ) -> Self {
-        let timings = TimingCollector::new(args.print_timing);
+        let timings = TimingCollector::new(args.print_timing || args.save_report.is_some());
+        let status = StatusFile::new(crate_index.package_ids().count());
         Self {
             apis_by_prefix: Default::default(),
+            apis_by_native_symbol: Default::default(),
+            apis_by_link_section: Default::default(),
             crate_infos: Default::default(),
-            config_path,
+            config_file,
             config: Default::default(),
             target_dir,
             tmpdir,
             args,
             crate_index,
             path_to_pkg_ids: Default::default(),
+            path_remaps: Default::default(),
             timings,
             backtracers: Default::default(),
             outstanding_linker_invocations: Default::default(),
+            usage_counter: Default::default(),
+            size_report: Default::default(),
+            collect_size_report: false,
+            scan_cache: None,
+            object_memo: Default::default(),
+            linked_outputs: Default::default(),
+            linked_targets: Default::default(),
+            status,
+            rustc_version: RustcVersion::from_sysroot(&sysroot),
+            std_path_prefixes: std_path_prefixes(&sysroot, &[]),
+            build_std_dirs: Vec::new(),
+            generated_code: Default::default(),
             sysroot,
         }
     }
 
+    /// Creates a checker that is only used to scan binaries on behalf of a remote client.
+    pub(crate) fn for_remote_scan(
+        tmpdir: Arc<TempDir>,
+        sysroot: Arc<Path>,
+        crate_index: CrateIndex,
+        apis: BTreeMap<ApiName, ApiConfig>,
+        std_path_prefixes: Vec<PathBuf>,
+        path_to_pkg_ids: FxHashMap<PathBuf, Vec<PackageId>>,
+        path_remaps: Vec<PathRemap>,
+    ) -> Self {
+        let args = Arc::new(Args {
+            no_backtrace: true,
+            ..Args::default()
+        });
+        let mut checker = Self::new(
+            tmpdir,
+            PathBuf::new(),
+            args,
+            sysroot,
+            Arc::new(crate_index),
+            ConfigFile::default(),
+        );
+        checker.path_to_pkg_ids = path_to_pkg_ids;
+        checker.path_remaps = path_remaps;
+        let mut config = Config::default();
+        config.raw.apis = apis;
+        config.raw.common.std_path_prefixes = std_path_prefixes;
+        checker.update_config(Arc::new(config));
+        checker
+    }
+
     /// Load (or reload) config. Note in the case of reloading, APIs are only ever additive.
     pub(crate) fn load_config(&mut self) -> Result<()> {
-        let config = crate::config::parse_file(&self.config_path, &self.crate_index)?;
+        let config = crate::config::parse_file(
+            &self.config_file,
+            &self.args.config_layer,
+            &self.crate_index,
+            self.rustc_version,
+        )?;
         // Every time we reload our configuration, we rewrite the flattened configuration. The
         // flattened configuration is used by subprocesses rather than using the original
         // configuration since using the original would require each subprocess to run `cargo
@@ -165,13 +373,14 @@ impl Checker {
 
     fn update_config(&mut self, config: Arc<Config>) {
         self.apis_by_prefix.clear();
-        for api in config.raw.apis.values() {
+        let apis = api_definitions(&config);
+        for api in apis.values() {
             for path in api.include.iter().chain(api.exclude.iter()) {
                 self.apis_by_prefix
                     .create_entry(crate::names::split_simple(&path.prefix).parts())
             }
         }
-        for (api_name, api) in &config.raw.apis {
+        for (api_name, api) in apis.iter() {
             for path in &api.include {
                 let name = &crate::names::split_simple(&path.prefix);
                 self.apis_by_prefix
@@ -181,7 +390,31 @@ impl Checker {
                     });
             }
         }
-        for (api_name, api_config) in &config.raw.apis {
+        self.apis_by_native_symbol.clear();
+        for (api_name, api_config) in apis.iter() {
+            for symbol in &api_config.native_symbols {
+                self.apis_by_native_symbol
+                    .entry(symbol.clone())
+                    .or_default()
+                    .insert(api_name.clone());
+            }
+        }
+        let mut apis_by_link_section: FxHashMap<&str, FxHashSet<ApiName>> = FxHashMap::default();
+        for (api_name, api_config) in apis.iter() {
+            for section in &api_config.link_sections {
+                apis_by_link_section
+                    .entry(section)
+                    .or_default()
+                    .insert(api_name.clone());
+            }
+        }
+        self.apis_by_link_section = apis_by_link_section
+            .into_iter()
+            .map(|(section, apis)| (section.to_owned(), apis))
+            .collect();
+        self.apis_by_link_section
+            .sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        for (api_name, api_config) in apis.iter() {
             for path in &api_config.exclude {
                 let name = &crate::names::split_simple(&path.prefix);
                 self.apis_by_prefix
@@ -200,6 +433,13 @@ impl Checker {
                     crate_info.unused_allowed_apis.insert(api.clone());
                 }
             }
+            // Expired grants don't permit anything, but if they're not used either, we report them
+            // as unused so that they get cleaned up.
+            for api in &crate_config.expired_apis {
+                if !crate_info.allowed_apis.contains(api) {
+                    crate_info.unused_allowed_apis.insert(api.clone());
+                }
+            }
         }
         // Then process with inheritance, but leaving unused_allow_apis alone. We don't want to get
         // warnings that an allow_api was unused when it was inherited and was actually used
@@ -210,6 +450,8 @@ impl Checker {
                 crate_info.allowed_apis.insert(api.clone());
             }
         }
+        self.std_path_prefixes =
+            std_path_prefixes(&self.sysroot, &config.raw.common.std_path_prefixes);
         self.config = config;
     }
 
@@ -238,15 +480,41 @@ impl Checker {
         };
         match request {
             rpc::Request::CrateUsesUnsafe(usage) => Ok(self.crate_uses_unsafe(usage)),
+            rpc::Request::CompilationFailed(failure) => {
+                Ok(Problem::CompilationFailed(failure.clone()).into())
+            }
             rpc::Request::LinkerInvoked(link_info) => {
                 self.outstanding_linker_invocations.push(link_info.clone());
                 Ok(ProblemList::default())
             }
+            rpc::Request::LinkerInvokedFile(path) => {
+                self.outstanding_linker_invocations
+                    .push(rpc::read_link_info(path)?);
+                Ok(ProblemList::default())
+            }
             rpc::Request::BinExecutionComplete(output) => {
-                if output.exit_code != 0 {
+                if output.waiting_for_input.is_some() {
+                    Ok(
+                        Problem::WaitingForInput(crate::problem::BinExecutionFailed {
+                            output: output.as_ref().clone(),
+                            crate_sel: output.crate_sel.clone(),
+                        })
+                        .into(),
+                    )
+                } else if output.exit_code != 0 && !output.failed_tests.is_empty() {
+                    let mut problems = ProblemList::default();
+                    for test in &output.failed_tests {
+                        problems.push(Problem::TestFailed(crate::problem::TestFailure {
+                            crate_sel: output.crate_sel.clone(),
+                            test: test.clone(),
+                            sandbox_config: output.sandbox_config.clone(),
+                        }));
+                    }
+                    Ok(problems)
+                } else if output.exit_code != 0 {
                     Ok(
                         Problem::ExecutionFailed(crate::problem::BinExecutionFailed {
-                            output: output.clone(),
+                            output: output.as_ref().clone(),
                             crate_sel: output.crate_sel.clone(),
                         })
                         .into(),
@@ -260,27 +528,51 @@ impl Checker {
                         &output.crate_sel,
                         &report.env_vars,
                     )?;
-                    Ok(report.problems)
+                    Ok(check_state
+                        .remove_reported_warnings(report.problems, &self.config.permissions))
                 } else {
                     Ok(ProblemList::default())
                 }
             }
             rpc::Request::RustcComplete(info) => {
+                self.status.rustc_complete(
+                    &info.crate_sel.to_string(),
+                    info.crate_sel.pkg_id.name_str(),
+                );
                 self.record_crate_paths(info)?;
+                let generated_code = self.record_generated_code(info);
+                let generated_code =
+                    check_state.remove_reported_warnings(generated_code, &self.config.permissions);
                 if let Some(link_info) = self.get_link_info(info) {
                     let problems = self.check_linker_invocation(&link_info, check_state)?;
+                    let mut problems =
+                        check_state.remove_reported_warnings(problems, &self.config.permissions);
                     if !problems.is_empty() {
                         // Since we found some problems, add our LinkInfo back so that if we fix the
                         // problems via the UI we can recheck once we have fixes.
                         self.outstanding_linker_invocations.push(link_info);
                     }
+                    problems.merge(generated_code);
                     return Ok(problems);
                 }
-
+                let mut problems = generated_code;
+                if info.is_staticlib {
+                    problems.push(Problem::UnanalysedStaticLib(info.crate_sel.clone()));
+                }
+                Ok(problems)
+            }
+            rpc::Request::StdCrateBuilt(dir) => {
+                info!("Built standard library package in {}", dir.display());
+                self.add_build_std_dir(dir);
                 Ok(ProblemList::default())
             }
             rpc::Request::RustcStarted(crate_sel) => {
                 info!("Rustc started compiling {crate_sel}");
+                self.status.rustc_started(&crate_sel.to_string());
+                Ok(ProblemList::default())
+            }
+            // These are handled by the caller, since they need to interact with the user interface.
+            rpc::Request::AttachTerminal | rpc::Request::DetachTerminal => {
                 Ok(ProblemList::default())
             }
         }
@@ -292,6 +584,13 @@ impl Checker {
         check_state: &mut CheckState,
     ) -> Result<ProblemList> {
         let start = std::time::Instant::now();
+        self.linked_outputs
+            .insert(info.output_file.clone(), info.artifact(&self.target_dir));
+        let pkg_id = &info.crate_sel.pkg_id;
+        self.linked_targets.insert(PermSel::with_scope(
+            pkg_id,
+            PermissionScope::determine(pkg_id, &info.crate_sel, info.is_proc_macro),
+        ));
         let mut problems = ProblemList::default();
         if info.crate_sel.kind == CrateKind::BuildScript {
             problems.merge(self.verify_build_script_permitted(&info.crate_sel.pkg_id));
@@ -320,18 +619,45 @@ impl Checker {
         if check_state
             .graph_outputs
             .as_ref()
-            .is_some_and(|outputs| outputs.apis != self.config.raw.apis)
+            .is_some_and(|outputs| outputs.apis != *api_definitions(&self.config))
         {
             // APIs have changed, invalidate cache.
             check_state.graph_outputs = None;
         }
         if check_state.graph_outputs.is_none() {
-            let (mut graph_outputs, backtracer) =
-                crate::symbol_graph::scan_objects(paths, link_info, self)?;
-            graph_outputs.apis = self.config.raw.apis.clone();
-            check_state.graph_outputs = Some(graph_outputs);
-            if let Some(b) = backtracer {
-                self.backtracers.insert(link_info.output_file.clone(), b);
+            let cache_key = self
+                .scan_cache
+                .as_ref()
+                .map(|_| {
+                    ScanCache::key(
+                        paths,
+                        link_info,
+                        &api_definitions(&self.config),
+                        self.args.debug,
+                    )
+                })
+                .transpose()?;
+            let cached = self
+                .scan_cache
+                .as_ref()
+                .zip(cache_key)
+                .and_then(|(cache, key)| cache.get(key));
+            if let Some(graph_outputs) = cached {
+                info!("Using cached scan of {}", link_info.output_file.display());
+                check_state.graph_outputs = Some(graph_outputs);
+            } else {
+                let (mut graph_outputs, backtracer) = match self.scan_remotely(paths, link_info) {
+                    Some(graph_outputs) => (graph_outputs, None),
+                    None => crate::symbol_graph::scan_objects(paths, link_info, self)?,
+                };
+                graph_outputs.apis = api_definitions(&self.config).into_owned();
+                if let (Some(cache), Some(key)) = (&self.scan_cache, cache_key) {
+                    cache.insert(key, &graph_outputs);
+                }
+                check_state.graph_outputs = Some(graph_outputs);
+                if let Some(b) = backtracer {
+                    self.backtracers.insert(link_info.output_file.clone(), b);
+                }
             }
         }
         let graph_outputs = check_state.graph_outputs.as_ref().unwrap();
@@ -339,8 +665,162 @@ impl Checker {
         Ok(problems)
     }
 
+    /// Scans the binary on one of the workers passed via `--scan-worker`, if any. Returns None if
+    /// there are no workers, or the outputs we need can't be produced remotely, or the remote scan
+    /// failed, in which case the caller should scan locally.
+    fn scan_remotely(&self, paths: &[PathBuf], link_info: &LinkInfo) -> Option<ScanOutputs> {
+        if self.args.scan_worker.is_empty() || self.args.debug || self.collect_size_report {
+            return None;
+        }
+        let job = self.scan_job(paths, link_info);
+        match crate::remote_scan::scan(&self.args.scan_worker, &job) {
+            Ok(mut graph_outputs) => {
+                graph_outputs.set_bin(link_info, &self.target_dir);
+                Some(graph_outputs)
+            }
+            Err(error) => {
+                warn!("Scanning locally since remote scan failed: {error:#}");
+                None
+            }
+        }
+    }
+
+    fn scan_job(&self, paths: &[PathBuf], link_info: &LinkInfo) -> ScanJob {
+        let mut job = ScanJob::new(
+            link_info,
+            paths,
+            &self.config,
+            &self.crate_index,
+            self.path_to_pkg_ids.clone(),
+            self.path_remaps.clone(),
+            &self.sysroot,
+        );
+        job.add_std_path_prefixes(&self.build_std_dirs);
+        job
+    }
+
+    /// Returns a job for scanning the binary that `request` will check, if the scan can be done
+    /// without holding the checker, i.e. by `remote_scan::scan_locally`. Returns None if
+    /// `--analysis-threads` wasn't greater than 1, or the request doesn't need a scan, or the
+    /// outputs we need can't be produced that way. If we have a cached scan, it's stored in
+    /// `check_state` rather than returning a job.
+    pub(crate) fn parallel_scan_job(
+        &mut self,
+        request: &Option<rpc::Request>,
+        check_state: &mut CheckState,
+    ) -> Result<Option<ScanJob>> {
+        let Some(rpc::Request::RustcComplete(info)) = request else {
+            return Ok(None);
+        };
+        if self
+            .args
+            .analysis_threads
+            .map_or(true, |threads| threads.get() == 1)
+            || check_state.graph_outputs.is_some()
+            || !self.args.scan_worker.is_empty()
+            || self.args.debug
+            || self.collect_size_report
+        {
+            return Ok(None);
+        }
+        let Some(link_info) = self
+            .outstanding_linker_invocations
+            .iter()
+            .find(|link_info| link_info.crate_sel == info.crate_sel)
+            .cloned()
+        else {
+            return Ok(None);
+        };
+        // The scan attributes source files to packages, so it needs to know about this crate's.
+        self.record_crate_paths(info)?;
+        let paths = link_info.object_paths_under(&self.target_dir);
+        if let Some(cache) = &self.scan_cache {
+            let key = ScanCache::key(
+                &paths,
+                &link_info,
+                &api_definitions(&self.config),
+                self.args.debug,
+            )?;
+            if let Some(graph_outputs) = cache.get(key) {
+                info!("Using cached scan of {}", link_info.output_file.display());
+                check_state.graph_outputs = Some(graph_outputs);
+                return Ok(None);
+            }
+        }
+        Ok(Some(self.scan_job(&paths, &link_info)))
+    }
+
+    /// Stores the outputs of scanning `job` in `check_state` and in the scan cache, so that the
+    /// request that `job` was created for uses them.
+    pub(crate) fn finish_parallel_scan(
+        &self,
+        job: &ScanJob,
+        mut graph_outputs: ScanOutputs,
+        check_state: &mut CheckState,
+    ) -> Result<()> {
+        graph_outputs.set_bin(job.link_info(), &self.target_dir);
+        // The outputs are discarded if the APIs are changed while we're scanning.
+        graph_outputs.apis = job.apis().clone();
+        if let Some(cache) = &self.scan_cache {
+            cache.insert(
+                ScanCache::key(job.paths(), job.link_info(), job.apis(), self.args.debug)?,
+                &graph_outputs,
+            );
+        }
+        check_state.graph_outputs = Some(graph_outputs);
+        Ok(())
+    }
+
     pub(crate) fn crate_uses_unsafe(&self, usage: &UnsafeUsage) -> ProblemList {
-        Problem::DisallowedUnsafe(usage.clone()).into()
+        let mut problems: ProblemList = Problem::DisallowedUnsafe(usage.clone()).into();
+        if self
+            .config
+            .capability_manifests
+            .get(usage.crate_sel.pkg_id())
+            .is_some_and(|manifest| !manifest.declares_unsafe(usage.crate_sel.kind))
+        {
+            problems.push(Problem::CapabilityManifestViolation(
+                CapabilityManifestViolation {
+                    crate_sel: usage.crate_sel.clone(),
+                    capability: UndeclaredCapability::Unsafe,
+                },
+            ));
+        }
+        problems
+    }
+
+    /// Reports a problem if the package using an API has a capability manifest that doesn't declare
+    /// that API.
+    fn check_api_declared(&self, api_usage: &ApiUsages, problems: &mut ProblemList) {
+        let Some(manifest) = self.config.capability_manifests.get(&api_usage.pkg_id) else {
+            return;
+        };
+        let crate_sel = match api_usage.scope {
+            PermissionScope::Build => CrateSel::build_script(api_usage.pkg_id.clone()),
+            PermissionScope::Test => return,
+            _ => CrateSel::primary(api_usage.pkg_id.clone()),
+        };
+        if !manifest.declares_api(&api_usage.api_name, crate_sel.kind) {
+            problems.push(Problem::CapabilityManifestViolation(
+                CapabilityManifestViolation {
+                    crate_sel,
+                    capability: UndeclaredCapability::Api(api_usage.api_name.clone()),
+                },
+            ));
+        }
+    }
+
+    /// Reports a problem if a third-party package references a path listed in `[deny] paths`.
+    /// Permissions granted to the package are deliberately not considered.
+    fn denied_path_used(&self, api_usage: &ApiUsages, problems: &mut ProblemList) {
+        if self
+            .config
+            .raw
+            .is_first_party(api_usage.pkg_id.name_str(), &self.crate_index)
+        {
+            return;
+        }
+        problems.push(Problem::DeniedPathUsage(api_usage.clone()));
     }
 
     pub(crate) fn verify_build_script_permitted(&mut self, pkg_id: &PackageId) -> ProblemList {
@@ -359,18 +839,64 @@ impl Checker {
     pub(crate) fn pkg_ids_from_source_path(
         &self,
         source_path: &Path,
-    ) -> Result<Cow<Vec<PackageId>>> {
+    ) -> Result<Cow<'_, [PackageId]>> {
         self.opt_pkg_ids_from_source_path(source_path)
             .ok_or_else(|| anyhow!("Couldn't find crate name for {}", source_path.display(),))
     }
 
+    /// Returns the packages to which a reference from `from` should be attributed, given that the
+    /// reference is in source belonging to `location_pkg_ids`. Generic code, such as the code an
+    /// async runtime uses to poll spawned tasks, gets monomorphized with the types passed to it, so
+    /// API usage that's really done by, for example, a closure can end up with a source location in
+    /// the runtime. By default, the package that defined the generic code is still charged, since
+    /// otherwise any package could avoid being charged for its API usage by taking a closure
+    /// parameter. Which of the packages involved gets charged is controlled by
+    /// `common.generic_attribution`.
+    pub(crate) fn attributed_pkg_ids<'a>(
+        &self,
+        from: &SymbolAndName,
+        location_pkg_ids: Cow<'a, [PackageId]>,
+    ) -> Result<Cow<'a, [PackageId]>> {
+        let mode = self.config.raw.common.generic_attribution;
+        // Code from the standard library isn't attributed to any package. We keep it that way,
+        // since otherwise all of the APIs that the standard library uses internally when, for
+        // example, spawning a thread, would get attributed to the caller.
+        if location_pkg_ids.is_empty() || mode == Some(GenericAttribution::Definer) {
+            return Ok(location_pkg_ids);
+        }
+        let name_prefix_to_pkg_id = self.crate_index.name_prefix_to_pkg_id();
+        let mut instantiator_pkg_ids = Vec::new();
+        for param in from.generic_params()? {
+            // By default, only closures are considered, since it's much more likely that a closure
+            // is responsible for API usage than that some other type is.
+            if mode.is_none() && !param.is_closure {
+                continue;
+            }
+            let Some(pkg_id) = name_prefix_to_pkg_id.get(param.prefix) else {
+                continue;
+            };
+            if !location_pkg_ids.contains(pkg_id) && !instantiator_pkg_ids.contains(pkg_id) {
+                instantiator_pkg_ids.push(pkg_id.clone());
+            }
+        }
+        if instantiator_pkg_ids.is_empty() {
+            return Ok(location_pkg_ids);
+        }
+        if mode != Some(GenericAttribution::Instantiator) {
+            instantiator_pkg_ids.splice(0..0, location_pkg_ids.iter().cloned());
+        }
+        Ok(Cow::Owned(instantiator_pkg_ids))
+    }
+
     pub(crate) fn opt_pkg_ids_from_source_path(
         &self,
         source_path: &Path,
-    ) -> Option<Cow<Vec<PackageId>>> {
+    ) -> Option<Cow<'_, [PackageId]>> {
+        let source_path = self.unmap_source_path(source_path);
+        let source_path = source_path.as_ref();
         self.path_to_pkg_ids
-            .get(source_path)
-            .map(Cow::Borrowed)
+            .get(crate::fs::normalize(source_path).as_ref())
+            .map(|pkg_ids| Cow::Borrowed(pkg_ids.as_slice()))
             .or_else(|| {
                 // If the source path is from the rust standard library, or from one of the
                 // precompiled crates that comes with the standard library, then report no crates.
@@ -385,15 +911,43 @@ impl Checker {
             })
     }
 
+    /// Undoes any `--remap-path-prefix` that rustc applied to `source_path` when writing it to
+    /// debug info, so that it can be matched against the source paths of our packages.
+    fn unmap_source_path<'a>(&self, source_path: &'a Path) -> Cow<'a, Path> {
+        if self.path_remaps.is_empty()
+            || self
+                .path_to_pkg_ids
+                .contains_key(crate::fs::normalize(source_path).as_ref())
+        {
+            return Cow::Borrowed(source_path);
+        }
+        // When multiple rules match, rustc applies the last one, so that's the one we reverse.
+        self.path_remaps
+            .iter()
+            .rev()
+            .find_map(|remap| remap.unmap(source_path))
+            .map_or(Cow::Borrowed(source_path), Cow::Owned)
+    }
+
     // Returns whether `source_path` is from the rust standard library or precompiled crates that are
     // bundled with the standard library (e.g. hashbrown).
     pub(crate) fn is_in_rust_std(&self, source_path: &Path) -> bool {
-        // Pre 2023-10-26
-        source_path.starts_with("/rustc/")
-            || source_path.starts_with("/cargo/registry")
-            // Post 2023-10-26
-            || source_path.starts_with("/rust/")
-            || source_path.starts_with(&self.sysroot)
+        self.std_path_prefixes
+            .iter()
+            .chain(&self.build_std_dirs)
+            .any(|prefix| source_path.starts_with(prefix))
+    }
+
+    /// Records that the package in `dir` was built as part of the standard library, so that code
+    /// from it is treated as being from the standard library.
+    fn add_build_std_dir(&mut self, dir: &Path) {
+        // Debug info may refer to the directory via a path with symlinks resolved.
+        let canonical = dir.canonicalize().ok();
+        for dir in [Some(dir.to_owned()), canonical].into_iter().flatten() {
+            if !self.build_std_dirs.contains(&dir) {
+                self.build_std_dirs.push(dir);
+            }
+        }
     }
 
     /// Returns all APIs that are matched by `name`. e.g. The name `["std", "fs", "write"]` might
@@ -405,6 +959,32 @@ impl Checker {
         self.apis_by_prefix.get(key_it)
     }
 
+    /// Returns the APIs that a reference to `symbol` counts as using if it's a non-Rust symbol
+    /// listed in an API's `native_symbols`.
+    pub(crate) fn apis_for_native_symbol(&self, symbol: &Symbol) -> Option<&FxHashSet<ApiName>> {
+        if self.apis_by_native_symbol.is_empty() {
+            return None;
+        }
+        self.apis_by_native_symbol.get(symbol.to_str().ok()?)
+    }
+
+    /// Returns the APIs that putting a reference into the link section `section_name` counts as
+    /// using. If multiple prefixes match, the longest is used.
+    pub(crate) fn apis_for_link_section(&self, section_name: &str) -> Option<&FxHashSet<ApiName>> {
+        self.apis_by_link_section
+            .iter()
+            .find(|(prefix, _)| section_name.starts_with(prefix.as_str()))
+            .map(|(_, apis)| apis)
+    }
+
+    /// Records `api_usages` in our usage counter. Usages of the pseudo-API for denied paths aren't
+    /// recorded, since it's an implementation detail that shouldn't show up in metrics or reports.
+    pub(crate) fn record_api_usages(&mut self, api_usages: &ApiUsages) {
+        if api_usages.api_name != denied_paths_api() {
+            self.usage_counter.record(api_usages);
+        }
+    }
+
     /// Reports an API usage. If it's not permitted, then a problem will be added to `problems`.
     pub(crate) fn api_used(
         &mut self,
@@ -412,39 +992,84 @@ impl Checker {
         problems: &mut ProblemList,
     ) -> Result<()> {
         let api = &api_usage.api_name;
+        if *api == denied_paths_api() {
+            self.denied_path_used(api_usage, problems);
+            return Ok(());
+        }
         let perm_sel = api_usage.perm_sel();
+        self.check_api_declared(api_usage, problems);
         if let Some(crate_info) = self.crate_infos.get_mut(&perm_sel) {
             if crate_info.allowed_apis.contains(api) {
                 crate_info.unused_allowed_apis.remove(api);
                 self.mark_parent_allow_apis_used(api, &perm_sel);
                 return Ok(());
             }
+            // The API may have been granted by a grant that has since expired. It's still in use,
+            // so isn't unused.
+            crate_info.unused_allowed_apis.remove(api);
         }
 
+        // Usages that have individually been reviewed and acknowledged are permitted, but any others
+        // still need the API to be granted.
+        let unacknowledged;
+        let api_usage = if let Some(acknowledgements) = self
+            .config
+            .acknowledgements
+            .get(&(perm_sel.clone(), api.clone()))
+        {
+            let pkg_dir = self.crate_index.pkg_dir(&api_usage.pkg_id);
+            unacknowledged = api_usage.with_usages(
+                api_usage
+                    .usages
+                    .iter()
+                    .filter(|usage| {
+                        !acknowledgements
+                            .iter()
+                            .any(|ack| ack.matches(pkg_dir, usage))
+                    })
+                    .cloned()
+                    .collect(),
+            );
+            if unacknowledged.usages.is_empty() {
+                return Ok(());
+            }
+            &unacknowledged
+        } else {
+            api_usage
+        };
+
         // Partition all usages into on-tree and off-tree usages. On-tree are those usages that are
         // referencing a name from one of our dependencies. Off-tree are those that reference names
         // from packages not in our package's dependency tree.
         let mut on_tree = Vec::new();
         let mut off_tree: FxHashMap<&PackageId, Vec<ApiUsage>> = FxHashMap::default();
+        let mut reattributed: FxHashMap<PackageId, Vec<ApiUsage>> = FxHashMap::default();
 
-        let all_deps = self.crate_index.name_prefix_to_pkg_id();
-        if let Some(crate_deps) = self.crate_index.transitive_deps(&api_usage.pkg_id) {
+        let crate_index = self.crate_index.clone();
+        let all_deps = crate_index.name_prefix_to_pkg_id();
+        if let Some(crate_deps) = crate_index.transitive_deps(&api_usage.pkg_id) {
             for usage in &api_usage.usages {
                 if let Some(first_name_part) = usage.to_name.parts.first() {
                     if !crate_deps.contains(first_name_part) {
                         if let Some(pkg_id) = all_deps.get(first_name_part) {
-                            // If we detect an off-tree usage where the outer function/variable is
-                            // defined by crate that also defined the restricted API that's being
-                            // accessed, then we ignore it completely.
-                            //
-                            // This can happen if for example a macro defines a variable that is
-                            // then referenced by an inlined function. The macro and the inlined
-                            // function can both be from leaf crates, while the code calling the
-                            // macro is from a higher level crate that provides a restricted API.
-                            // The end effect is that it looks like the inlined function is
-                            // referencing the restricted API.
-                            if !self.is_to_name_from_outer_location(usage)? {
-                                off_tree.entry(pkg_id).or_default().push(usage.clone());
+                            // An off-tree usage can happen if for example a macro defines a
+                            // variable that is then referenced by an inlined function. The macro
+                            // and the inlined function can both be from leaf crates, while the
+                            // code calling the macro is from a higher level crate that provides or
+                            // depends on a restricted API. The end effect is that it looks like
+                            // the inlined function is referencing the restricted API. In that
+                            // case, we attribute the usage to the crate that invoked the macro.
+                            match self.macro_origin(usage, first_name_part)? {
+                                MacroOrigin::DefiningPackage => {}
+                                MacroOrigin::DependentPackage(outer_pkg_id) => {
+                                    reattributed
+                                        .entry(outer_pkg_id)
+                                        .or_default()
+                                        .push(usage.reattributed_to_outer_location());
+                                }
+                                MacroOrigin::Unknown => {
+                                    off_tree.entry(pkg_id).or_default().push(usage.clone());
+                                }
                             }
                             continue;
                         }
@@ -459,6 +1084,20 @@ impl Checker {
             on_tree.extend(api_usage.usages.iter().cloned());
         }
 
+        // Check usages that we've attributed to the package that invoked a macro as if that package
+        // had used the API directly.
+        for (pkg_id, usages) in reattributed {
+            self.api_used(
+                &ApiUsages {
+                    pkg_id,
+                    scope: api_usage.scope.for_other_package(),
+                    api_name: api_usage.api_name.clone(),
+                    usages,
+                },
+                problems,
+            )?;
+        }
+
         // Report off-tree problems for each off-tree package that we appear to reference.
         for (pkg_id, off_tree_usages) in off_tree {
             let usages = api_usage.with_usages(off_tree_usages);
@@ -475,21 +1114,75 @@ impl Checker {
         Ok(())
     }
 
-    /// Returns whether the to-name of `usage` starts with a crate name that matches the package
-    /// that defined the outer location of the usage.
-    fn is_to_name_from_outer_location(&self, usage: &ApiUsage) -> Result<bool> {
-        if let Some(outer_location) = usage.outer_location.as_ref() {
-            for pkg_id in self
-                .pkg_ids_from_source_path(outer_location.filename())?
-                .as_ref()
+    /// Runs any configured plugins over `scan_outputs`.
+    pub(crate) fn run_plugins(
+        &self,
+        scan_outputs: &ScanOutputs,
+        problems: &mut ProblemList,
+    ) -> Result<()> {
+        let config_dir = self.config_file.path().parent().unwrap_or(Path::new("."));
+        let inputs = PluginInputs {
+            scan_outputs,
+            crate_index: &self.crate_index,
+            config: &self.config,
+        };
+        for plugin in crate::plugin::plugins(&self.config, config_dir) {
+            crate::plugin::run_plugin(plugin.as_ref(), &inputs, problems)
+                .with_context(|| format!("Plugin `{}` failed", plugin.name()))?;
+        }
+        Ok(())
+    }
+
+    /// Determines whether an off-tree `usage` of a name from `to_crate` can be explained by the
+    /// package that defined the outer location of the usage. i.e. the package that most likely
+    /// invoked the macro that produced the code.
+    fn macro_origin(&self, usage: &ApiUsage, to_crate: &str) -> Result<MacroOrigin> {
+        let Some(outer_location) = usage.outer_location.as_ref() else {
+            return Ok(MacroOrigin::Unknown);
+        };
+        let outer_pkg_ids = self.pkg_ids_from_source_path(outer_location.filename())?;
+        if outer_pkg_ids
+            .iter()
+            .any(|pkg_id| pkg_id.crate_name() == to_crate)
+        {
+            return Ok(MacroOrigin::DefiningPackage);
+        }
+        for pkg_id in outer_pkg_ids.iter() {
+            if self
+                .crate_index
+                .transitive_deps(pkg_id)
+                .is_some_and(|deps| deps.contains(to_crate))
             {
-                let crate_name = pkg_id.crate_name();
-                if usage.to_name.starts_with(crate_name.as_ref()) {
-                    return Ok(true);
-                }
+                return Ok(MacroOrigin::DependentPackage(pkg_id.clone()));
+            }
+        }
+        Ok(MacroOrigin::Unknown)
+    }
+
+    /// Returns whether any binary was linked that could have exercised grants to `perm_sel`. If not,
+    /// e.g. because `build_flags` excluded tests or because cargo stopped before linking some
+    /// targets, then we can't tell whether the grants are unused. If nothing at all was linked, we
+    /// don't know what was built, so assume that everything was.
+    fn scope_was_linked(&self, perm_sel: &PermSel) -> bool {
+        if self.linked_targets.is_empty() || perm_sel.package_name.is_pattern() {
+            return true;
+        }
+        let any_linked_with_scope = |scopes: &[PermissionScope]| {
+            self.linked_targets
+                .iter()
+                .any(|linked| scopes.contains(&linked.scope))
+        };
+        match perm_sel.scope {
+            // Other packages use this package from all kinds of binaries.
+            PermissionScope::All => true,
+            PermissionScope::FromBuild => {
+                any_linked_with_scope(&[PermissionScope::Build, PermissionScope::ProcMacro])
+            }
+            PermissionScope::FromTest => any_linked_with_scope(&[PermissionScope::Test]),
+            PermissionScope::Build | PermissionScope::Test | PermissionScope::ProcMacro => {
+                self.linked_targets.contains(perm_sel)
             }
         }
-        Ok(false)
     }
 
     pub(crate) fn check_unused(&self) -> Result<ProblemList> {
@@ -502,14 +1195,42 @@ impl Checker {
 
         let mut problems = ProblemList::default();
         let perm_sels_in_index = &self.crate_index.permission_selectors;
+        let mut feature_gated: Option<FxHashSet<Arc<str>>> = None;
         for (perm_sel, crate_info) in &self.crate_infos {
-            if !perm_sels_in_index.contains(perm_sel) {
+            // Patterns are checked to match at least one package when the config is loaded.
+            if !perm_sels_in_index.contains(perm_sel) && !perm_sel.package_name.is_pattern() {
+                let feature_gated = feature_gated.get_or_insert_with(|| {
+                    self.crate_index
+                        .feature_gated_package_names()
+                        .cloned()
+                        .unwrap_or_else(|error| {
+                            warn!("Failed to determine packages enabled by features: {error:#}");
+                            FxHashSet::default()
+                        })
+                });
+                if feature_gated.contains(&perm_sel.package_name.0) {
+                    problems.push(Problem::PackageNotBuilt(perm_sel.clone()));
+                    // We've no idea which of its allowed APIs the package would use if it were
+                    // built, so we don't report any as unused.
+                    continue;
+                }
                 problems.push(Problem::UnusedPackageConfig(perm_sel.clone()));
             }
-            if !crate_info.unused_allowed_apis.is_empty() {
+            let (dead_code_apis, unused_apis): (Vec<ApiName>, Vec<ApiName>) = crate_info
+                .unused_allowed_apis
+                .iter()
+                .cloned()
+                .partition(|api| crate_info.dead_code_apis.contains(api));
+            if !unused_apis.is_empty() && self.scope_was_linked(perm_sel) {
                 problems.push(Problem::UnusedAllowApi(UnusedAllowApi {
                     perm_sel: perm_sel.clone(),
-                    apis: crate_info.unused_allowed_apis.iter().cloned().collect(),
+                    apis: unused_apis,
+                }));
+            }
+            if !dead_code_apis.is_empty() {
+                problems.push(Problem::AllowApiUsedOnlyByDeadCode(UnusedAllowApi {
+                    perm_sel: perm_sel.clone(),
+                    apis: dead_code_apis,
                 }));
             }
         }
@@ -534,13 +1255,47 @@ impl Checker {
         ProblemList::default()
     }
 
+    /// Records which, if any, of the sources of the crate in `info` were generated by its build
+    /// script. Generated code won't have been seen by anyone reviewing the package's source, so we
+    /// report it.
+    fn record_generated_code(&mut self, info: &rpc::RustcOutput) -> ProblemList {
+        if info.generated_paths.is_empty() {
+            return ProblemList::default();
+        }
+        self.generated_code
+            .entry(info.crate_sel.pkg_id.clone())
+            .or_default()
+            .extend(info.generated_paths.iter().cloned());
+        if self
+            .config
+            .permissions
+            .get(&PermSel::for_non_build_output(&info.crate_sel))
+            .is_some_and(|pkg_config| pkg_config.allow_generated_code)
+        {
+            return ProblemList::default();
+        }
+        Problem::GeneratedCode(GeneratedCode {
+            crate_sel: info.crate_sel.clone(),
+            paths: info.generated_paths.clone(),
+        })
+        .into()
+    }
+
     fn record_crate_paths(&mut self, info: &rpc::RustcOutput) -> Result<()> {
         for path in &info.source_paths {
-            let selectors = &mut self.path_to_pkg_ids.entry(path.to_owned()).or_default();
+            let selectors = &mut self
+                .path_to_pkg_ids
+                .entry(crate::fs::normalize(path).into_owned())
+                .or_default();
             if !selectors.contains(&info.crate_sel.pkg_id) {
                 selectors.push(info.crate_sel.pkg_id.clone());
             }
         }
+        for remap in &info.path_remaps {
+            if !self.path_remaps.contains(remap) {
+                self.path_remaps.push(remap.clone());
+            }
+        }
         Ok(())
     }
 
@@ -588,6 +1343,7 @@ impl Checker {
     }
 
     fn mark_parent_allow_apis_used(&mut self, api: &ApiName, perm_sel: &PermSel) {
+        self.mark_pattern_allow_apis_used(api, perm_sel);
         let Some(parent) = perm_sel.parent() else {
             return;
         };
@@ -596,24 +1352,72 @@ impl Checker {
         }
         self.mark_parent_allow_apis_used(api, &parent);
     }
+
+    /// Records that `api` is referenced from code belonging to `perm_sel` that isn't present in the
+    /// final binary. This is recorded against the same selectors that would have been marked as
+    /// used had the code been live.
+    pub(crate) fn dead_code_api_used(&mut self, api: &ApiName, perm_sel: &PermSel) {
+        let config = self.config.clone();
+        let mut selectors = Vec::new();
+        let mut next = Some(perm_sel.clone());
+        while let Some(sel) = next {
+            for (pattern_name, pattern) in &config.package_patterns {
+                if pattern.matches(sel.package_name.as_ref()) {
+                    selectors.push(sel.clone_with_package_name(pattern_name.clone()));
+                }
+            }
+            next = sel.parent();
+            selectors.push(sel);
+        }
+        for sel in selectors {
+            if let Some(info) = self.crate_infos.get_mut(&sel) {
+                info.dead_code_apis.insert(api.clone());
+            }
+        }
+    }
+
+    /// Marks `api` as used for any `[pkg]` selectors with patterns that match `perm_sel`.
+    fn mark_pattern_allow_apis_used(&mut self, api: &ApiName, perm_sel: &PermSel) {
+        let config = self.config.clone();
+        for (pattern_name, pattern) in &config.package_patterns {
+            if !pattern.matches(perm_sel.package_name.as_ref()) {
+                continue;
+            }
+            if let Some(info) = self
+                .crate_infos
+                .get_mut(&perm_sel.clone_with_package_name(pattern_name.clone()))
+            {
+                info.unused_allowed_apis.remove(api);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod testing {
     use super::*;
-    use crate::config::testing::parse;
-    use crate::symbol::Symbol;
 
-    fn checker_for_testing() -> Checker {
+    pub(crate) fn checker_for_testing() -> Checker {
         Checker::new(
             Arc::new(TempDir::new(None).unwrap()),
             PathBuf::default(),
             Arc::new(Args::default()),
             Arc::from(Path::new("")),
             Arc::new(CrateIndex::default()),
-            PathBuf::default(),
+            ConfigFile::default(),
         )
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::checker_for_testing;
+    use super::*;
+    use crate::config::testing::parse;
+    use crate::link_info::ArtifactKind;
+    use crate::names::DebugName;
+    use crate::names::Namespace;
+    use crate::symbol::Symbol;
 
     #[track_caller]
     fn assert_apis(config: &str, path: &[&str], expected: &[&str]) {
@@ -647,6 +1451,80 @@ mod tests {
         assert_apis(config, &["std", "env", "exe"], &["env", "env2", "fs"]);
     }
 
+    #[test]
+    fn native_symbols() {
+        let mut checker = checker_for_testing();
+        checker.update_config(
+            parse(
+                r#"
+                import_std = ["fs"]
+
+                [api.files]
+                native_symbols = ["open"]
+                "#,
+            )
+            .unwrap(),
+        );
+        let apis = |symbol: &[u8]| -> Vec<&str> {
+            let mut apis: Vec<&str> = checker
+                .apis_for_native_symbol(&Symbol::borrowed(symbol))
+                .into_iter()
+                .flatten()
+                .map(AsRef::as_ref)
+                .collect();
+            apis.sort();
+            apis
+        };
+        assert_eq!(apis(b"open"), vec!["files", "fs"]);
+        assert_eq!(apis(b"openat64"), vec!["fs"]);
+        assert!(apis(b"mmap").is_empty());
+        // Native symbols only match whole symbols, not paths starting with them.
+        assert!(checker
+            .apis_for_name_iterator(["open", "that"].into_iter())
+            .is_empty());
+        assert_apis(
+            "import_std = [\"fs\"]",
+            &["memmap2", "Mmap", "map"],
+            &["fs"],
+        );
+        assert_apis(
+            "import_std = [\"fs\"]",
+            &["memmap2", "MmapOptions", "map_anon"],
+            &[],
+        );
+    }
+
+    #[test]
+    fn link_sections() {
+        let mut checker = checker_for_testing();
+        checker.update_config(
+            parse(
+                r#"
+                import_std = ["lifecycle-hooks"]
+
+                [api.early]
+                link_sections = [".init_array.0"]
+                "#,
+            )
+            .unwrap(),
+        );
+        let apis = |section_name: &str| -> Vec<&str> {
+            let mut apis: Vec<&str> = checker
+                .apis_for_link_section(section_name)
+                .into_iter()
+                .flatten()
+                .map(AsRef::as_ref)
+                .collect();
+            apis.sort();
+            apis
+        };
+        assert_eq!(apis(".init_array"), vec!["lifecycle-hooks"]);
+        assert_eq!(apis(".init_array.00099"), vec!["early"]);
+        assert_eq!(apis("__mod_init_func"), vec!["lifecycle-hooks"]);
+        assert!(apis(".text.init_array").is_empty());
+        assert!(apis(".data").is_empty());
+    }
+
     #[test]
     fn reload_config() {
         let config = parse(
@@ -686,6 +1564,10 @@ mod tests {
                         symbol_start: 0,
                     },
                     bin_path: Arc::from(Path::new("bin")),
+                    artifact: Artifact {
+                        path: Arc::from(Path::new("bin")),
+                        kind: ArtifactKind::Bin,
+                    },
                     permission_scope: PermissionScope::All,
                     source_location: SourceLocation::new(Path::new("lib.rs"), 1, None),
                     outer_location: None,
@@ -706,4 +1588,648 @@ mod tests {
         checker.update_config(config);
         assert!(checker.check_unused().unwrap().is_empty());
     }
+
+    #[test]
+    fn acknowledged_usages() {
+        let mut checker = Checker {
+            crate_index: crate::crate_index::testing::index_with_package_names(&["foo"]),
+            ..checker_for_testing()
+        };
+        checker.update_config(
+            parse(
+                r#"
+                [api.net]
+                include = ["std::net"]
+
+                [[acknowledgements]]
+                pkg = "foo"
+                api = "net"
+                path = "src/client.rs"
+                symbol = "std::net::TcpStream::connect"
+                "#,
+            )
+            .unwrap(),
+        );
+        let usage = |filename: &str, name: &str| ApiUsage {
+            bin_location: BinLocation {
+                address: 0,
+                symbol_start: 0,
+            },
+            bin_path: Arc::from(Path::new("bin")),
+            artifact: Artifact {
+                path: Arc::from(Path::new("bin")),
+                kind: ArtifactKind::Bin,
+            },
+            permission_scope: PermissionScope::All,
+            source_location: SourceLocation::new(Path::new(filename), 1, None),
+            outer_location: None,
+            from: SymbolOrDebugName::Symbol(Symbol::borrowed(&[])),
+            to_name: crate::names::split_simple(name),
+            to: SymbolOrDebugName::Symbol(Symbol::borrowed(&[])),
+            to_source: NameSource::Symbol(Symbol::borrowed(b"")),
+            debug_data: None,
+        };
+        let api_usages = |usages| ApiUsages {
+            pkg_id: crate::crate_index::testing::pkg_id("foo"),
+            scope: PermissionScope::All,
+            api_name: ApiName::from("net"),
+            usages,
+        };
+        let acknowledged = usage("src/client.rs", "std::net::TcpStream::connect");
+        let mut problems = ProblemList::default();
+        checker
+            .api_used(&api_usages(vec![acknowledged.clone()]), &mut problems)
+            .unwrap();
+        assert!(problems.is_empty());
+
+        // Other usages of the same API by the same package still need to be permitted.
+        let new_usages = vec![
+            usage("src/server.rs", "std::net::TcpStream::connect"),
+            usage("src/client.rs", "std::net::TcpListener::bind"),
+        ];
+        let mut usages = new_usages.clone();
+        usages.push(acknowledged);
+        checker
+            .api_used(&api_usages(usages), &mut problems)
+            .unwrap();
+        assert_eq!(
+            problems.take(),
+            vec![Problem::DisallowedApiUsage(api_usages(new_usages))]
+        );
+    }
+
+    #[test]
+    fn generic_attribution() {
+        let mut checker = Checker {
+            crate_index: crate::crate_index::testing::index_with_package_names(&[
+                "tokio", "my-crate", "other",
+            ]),
+            ..checker_for_testing()
+        };
+        let tokio = crate::crate_index::testing::pkg_id("tokio");
+        let my_crate = crate::crate_index::testing::pkg_id("my-crate");
+        let other = crate::crate_index::testing::pkg_id("other");
+        let attributed = |checker: &Checker, name: &str, location_pkg_ids: &[PackageId]| {
+            let from = SymbolAndName {
+                symbol: None,
+                debug_name: Some(DebugName::new(Namespace::empty(), name)),
+            };
+            checker
+                .attributed_pkg_ids(&from, Cow::Borrowed(location_pkg_ids))
+                .unwrap()
+                .into_owned()
+        };
+        let task_poll = "tokio::runtime::task::core::Core<my_crate::main::{async_block_env#0}, \
+             alloc::sync::Arc<tokio::runtime::scheduler::current_thread::Handle>>::poll";
+        let map_insert = "hashbrown::map::HashMap<other::Key, u32>::insert";
+        let location = std::slice::from_ref(&tokio);
+
+        // By default, closures from other packages are charged in addition to the package that
+        // defined the generic code, so that the definer can't avoid being charged for its own API
+        // usage by taking a closure parameter.
+        checker.update_config(parse("").unwrap());
+        assert_eq!(
+            attributed(&checker, task_poll, location),
+            vec![tokio.clone(), my_crate.clone()]
+        );
+        assert_eq!(
+            attributed(&checker, map_insert, location),
+            vec![tokio.clone()]
+        );
+        assert_eq!(
+            attributed(
+                &checker,
+                "tokio::runtime::task::core::Core<T, S>::poll",
+                location
+            ),
+            vec![tokio.clone()]
+        );
+        assert_eq!(
+            attributed(
+                &checker,
+                "tokio::runtime::blocking::pool::Spawner::spawn_blocking<tokio::fs::read::{closure_env#0}>",
+                location
+            ),
+            vec![tokio.clone()]
+        );
+        // References from the standard library stay unattributed.
+        assert!(attributed(&checker, task_poll, &[]).is_empty());
+
+        checker.update_config(parse("generic_attribution = \"instantiator\"").unwrap());
+        assert_eq!(
+            attributed(&checker, task_poll, location),
+            vec![my_crate.clone()]
+        );
+        assert_eq!(
+            attributed(&checker, map_insert, location),
+            vec![other.clone()]
+        );
+
+        checker.update_config(parse("generic_attribution = \"definer\"").unwrap());
+        assert_eq!(
+            attributed(&checker, task_poll, location),
+            vec![tokio.clone()]
+        );
+
+        checker.update_config(parse("generic_attribution = \"both\"").unwrap());
+        assert_eq!(
+            attributed(&checker, task_poll, location),
+            vec![tokio.clone(), my_crate]
+        );
+        assert_eq!(
+            attributed(&checker, map_insert, location),
+            vec![tokio, other]
+        );
+    }
+
+    #[test]
+    fn off_tree_usage_from_macro() {
+        let crate_root = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
+        let test_crates_dir = crate_root.join("test_crates");
+        let mut checker = Checker::new(
+            Arc::new(TempDir::new(None).unwrap()),
+            PathBuf::default(),
+            Arc::new(Args::default()),
+            Arc::from(Path::new("/sysroot")),
+            Arc::new(
+                CrateIndex::new(&[test_crates_dir.join("Cargo.toml")], Default::default()).unwrap(),
+            ),
+            ConfigFile::default(),
+        );
+        checker.update_config(
+            parse(
+                r#"
+                [api.res1]
+                include = ["res_1"]
+                "#,
+            )
+            .unwrap(),
+        );
+        let crab_5 = checker
+            .crate_index
+            .name_prefix_to_pkg_id()
+            .get("crab_5")
+            .unwrap()
+            .clone();
+
+        // Mirrors the reference that we see from code in tracing-core (here crab-5) to a static
+        // that was defined by a macro from tracing (here crab-6) and invoked by some other crate.
+        let problems_for_outer_location = |checker: &mut Checker, outer: Option<&str>| {
+            let usages = ApiUsages {
+                pkg_id: crab_5.clone(),
+                scope: PermissionScope::All,
+                api_name: ApiName::from("res1"),
+                usages: vec![ApiUsage {
+                    bin_location: BinLocation {
+                        address: 0,
+                        symbol_start: 0,
+                    },
+                    bin_path: Arc::from(Path::new("bin")),
+                    artifact: Artifact {
+                        path: Arc::from(Path::new("bin")),
+                        kind: ArtifactKind::Bin,
+                    },
+                    permission_scope: PermissionScope::All,
+                    source_location: SourceLocation::new(
+                        test_crates_dir.join("crab-5/src/lib.rs"),
+                        10,
+                        None,
+                    ),
+                    outer_location: outer
+                        .map(|path| SourceLocation::new(test_crates_dir.join(path), 20, None)),
+                    from: SymbolOrDebugName::Symbol(Symbol::borrowed(&[])),
+                    to_name: crate::names::split_simple("res_1::print_something::CALLSITE"),
+                    to: SymbolOrDebugName::Symbol(Symbol::borrowed(&[])),
+                    to_source: NameSource::Symbol(Symbol::borrowed(
+                        b"res_1::print_something::CALLSITE",
+                    )),
+                    debug_data: None,
+                }],
+            };
+            let mut problems = ProblemList::default();
+            checker.api_used(&usages, &mut problems).unwrap();
+            problems.take()
+        };
+
+        // Without an outer location, we can't explain the usage.
+        let problems = problems_for_outer_location(&mut checker, None);
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(problems[0], Problem::OffTreeApiUsage(..)));
+
+        // The macro was invoked from the crate that defines the static.
+        assert!(problems_for_outer_location(&mut checker, Some("res-1/src/lib.rs")).is_empty());
+
+        // The macro was invoked from a crate that depends on the crate that defines the static, so
+        // the usage should be attributed to that crate.
+        let problems = problems_for_outer_location(&mut checker, Some("crab-bin/src/main.rs"));
+        assert_eq!(problems.len(), 1);
+        let Problem::DisallowedApiUsage(usages) = &problems[0] else {
+            panic!("Unexpected problem {:?}", problems[0]);
+        };
+        assert_eq!(usages.pkg_id.name_str(), "crab-bin");
+        assert_eq!(
+            usages.usages[0].source_location.filename(),
+            test_crates_dir.join("crab-bin/src/main.rs")
+        );
+    }
+
+    #[test]
+    fn api_not_declared_in_capability_manifest() {
+        let mut checker = checker_for_testing();
+        checker.update_config(
+            crate::config::testing::parse_with_capability_manifests(
+                r#"
+                [api.fs]
+                include = ["std::fs"]
+
+                [api.net]
+                include = ["std::net"]
+
+                [pkg.foo]
+                allow_apis = ["fs", "net"]
+                "#,
+                &[("foo", "version = 1\nallow_apis = [\"fs\"]")],
+            )
+            .unwrap(),
+        );
+        let mut check = |api: &'static str, scope| {
+            let mut problems = ProblemList::default();
+            checker
+                .api_used(
+                    &ApiUsages {
+                        pkg_id: crate::crate_index::testing::pkg_id("foo"),
+                        scope,
+                        api_name: ApiName::from(api),
+                        usages: vec![],
+                    },
+                    &mut problems,
+                )
+                .unwrap();
+            problems.take()
+        };
+        assert!(check("fs", PermissionScope::All).is_empty());
+        assert_eq!(
+            check("net", PermissionScope::FromBuild),
+            vec![Problem::CapabilityManifestViolation(
+                CapabilityManifestViolation {
+                    crate_sel: CrateSel::primary(crate::crate_index::testing::pkg_id("foo")),
+                    capability: UndeclaredCapability::Api(ApiName::from("net")),
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn denied_path() {
+        let mut checker = checker_for_testing();
+        checker.update_config(
+            parse(
+                r#"
+                [api.process]
+                include = ["std::process"]
+
+                [deny]
+                paths = ["std::process::Command::new"]
+
+                [pkg.foo]
+                allow_apis = ["process"]
+                "#,
+            )
+            .unwrap(),
+        );
+        let apis_for = |checker: &Checker, name: &str| {
+            let mut apis: Vec<ApiName> = checker
+                .apis_for_name_iterator(crate::names::split_simple(name).parts())
+                .iter()
+                .cloned()
+                .collect();
+            apis.sort();
+            apis
+        };
+        assert_eq!(
+            apis_for(&checker, "std::process::Command::new"),
+            vec![denied_paths_api(), ApiName::from("process")]
+        );
+        assert_eq!(
+            apis_for(&checker, "std::process::Command::spawn"),
+            vec![ApiName::from("process")]
+        );
+
+        // Even though `foo` is allowed to use the `process` API, it's not allowed to use the denied
+        // path.
+        let mut problems = ProblemList::default();
+        for api in apis_for(&checker, "std::process::Command::new") {
+            let api_usages = ApiUsages {
+                pkg_id: crate::crate_index::testing::pkg_id("foo"),
+                scope: PermissionScope::All,
+                api_name: api,
+                usages: vec![],
+            };
+            checker.record_api_usages(&api_usages);
+            checker.api_used(&api_usages, &mut problems).unwrap();
+        }
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(problems[0], Problem::DeniedPathUsage(..)));
+
+        // The pseudo-API for denied paths shouldn't show up in metrics or reports.
+        let recorded: Vec<&ApiName> = checker
+            .usage_counter
+            .usages()
+            .map(|(_, api, _)| api)
+            .collect();
+        assert_eq!(recorded, vec![&ApiName::from("process")]);
+    }
+
+    #[test]
+    fn allow_api_used_only_by_dead_code() {
+        let mut checker = checker_for_testing();
+        checker.update_config(
+            parse(
+                r#"
+                [api.fs]
+                include = ["std::fs"]
+
+                [api.net]
+                include = ["std::net"]
+
+                [pkg.foo]
+                allow_apis = ["fs", "net"]
+                "#,
+            )
+            .unwrap(),
+        );
+        // Dead code in foo's tests references `fs`. This should be attributed to the grant on
+        // `pkg.foo`, which the tests inherit.
+        checker.dead_code_api_used(
+            &ApiName::from("fs"),
+            &PermSel::for_primary("foo").clone_with_scope(PermissionScope::Test),
+        );
+        let problems: Vec<Problem> = checker
+            .check_unused()
+            .unwrap()
+            .into_iter()
+            .filter(|problem| !matches!(problem, Problem::UnusedPackageConfig(..)))
+            .cloned()
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                Problem::UnusedAllowApi(UnusedAllowApi {
+                    perm_sel: PermSel::for_primary("foo"),
+                    apis: vec![ApiName::from("net")],
+                }),
+                Problem::AllowApiUsedOnlyByDeadCode(UnusedAllowApi {
+                    perm_sel: PermSel::for_primary("foo"),
+                    apis: vec![ApiName::from("fs")],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn unused_allow_apis_for_targets_not_linked() {
+        let mut checker = checker_for_testing();
+        checker.update_config(
+            parse(
+                r#"
+                [api.fs]
+                include = ["std::fs"]
+
+                [pkg.foo]
+                allow_apis = ["fs"]
+
+                [pkg.foo.build]
+                allow_apis = ["fs"]
+
+                [pkg.foo.test]
+                allow_apis = ["fs"]
+
+                [pkg.bar.from.test]
+                allow_apis = ["fs"]
+                "#,
+            )
+            .unwrap(),
+        );
+        // Only foo's build script was linked, so we can't tell whether the grants to tests are
+        // used.
+        checker
+            .linked_targets
+            .insert(PermSel::for_build_script("foo"));
+        let unused: FxHashSet<PermSel> = checker
+            .check_unused()
+            .unwrap()
+            .into_iter()
+            .filter_map(|problem| match problem {
+                Problem::UnusedAllowApi(unused) => Some(unused.perm_sel.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            unused,
+            [
+                PermSel::for_primary("foo"),
+                PermSel::for_build_script("foo")
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn config_for_packages_not_built() {
+        let mut checker = checker_for_testing();
+        checker.crate_index =
+            crate::crate_index::testing::index_with_feature_gated_packages(&["foo"], &["bar"]);
+        checker.update_config(
+            parse(
+                r#"
+                [api.fs]
+                include = ["std::fs"]
+
+                [pkg.foo]
+                allow_apis = ["fs"]
+
+                [pkg.bar]
+                allow_apis = ["fs"]
+
+                [pkg.baz]
+                allow_apis = ["fs"]
+                "#,
+            )
+            .unwrap(),
+        );
+        let problems: FxHashSet<Problem> = checker
+            .check_unused()
+            .unwrap()
+            .into_iter()
+            .cloned()
+            .collect();
+        let unused_fs = |name| {
+            Problem::UnusedAllowApi(UnusedAllowApi {
+                perm_sel: PermSel::for_primary(name),
+                apis: vec![ApiName::from("fs")],
+            })
+        };
+        // `bar` would be in the dependency tree with other features, so we don't suggest removing
+        // its config, or report its APIs as unused.
+        let bar = PermSel::for_primary("bar");
+        assert!(problems.contains(&Problem::PackageNotBuilt(bar.clone())));
+        assert!(!problems.contains(&Problem::UnusedPackageConfig(bar)));
+        assert!(!problems.contains(&unused_fs("bar")));
+        assert!(problems.contains(&Problem::UnusedPackageConfig(PermSel::for_primary("baz"))));
+        assert!(problems.contains(&unused_fs("baz")));
+        assert!(problems.contains(&unused_fs("foo")));
+    }
+
+    #[test]
+    fn std_path_prefixes() {
+        let tmpdir = TempDir::new(None).unwrap();
+        let sysroot = tmpdir.path().join("sysroot");
+        let rust_src = tmpdir.path().join("rust-checkout");
+        std::fs::create_dir_all(sysroot.join("lib/rustlib/src")).unwrap();
+        std::fs::create_dir_all(rust_src.join("library/std/src")).unwrap();
+        std::os::unix::fs::symlink(&rust_src, sysroot.join("lib/rustlib/src/rust")).unwrap();
+        let mut checker = Checker::new(
+            Arc::new(TempDir::new(None).unwrap()),
+            PathBuf::default(),
+            Arc::new(Args::default()),
+            Arc::from(sysroot.as_path()),
+            Arc::new(CrateIndex::default()),
+            ConfigFile::default(),
+        );
+        checker.update_config(
+            parse(
+                r#"
+                std_path_prefixes = ["/remapped/std"]
+                "#,
+            )
+            .unwrap(),
+        );
+        let rust_src = rust_src.canonicalize().unwrap();
+        assert!(checker.is_in_rust_std(Path::new("/rustc/abc123/library/std/src/fs.rs")));
+        assert!(checker.is_in_rust_std(&rust_src.join("library/std/src/fs.rs")));
+        assert!(checker.is_in_rust_std(Path::new("/remapped/std/library/core/src/lib.rs")));
+        assert!(!checker.is_in_rust_std(Path::new("/remapped/stdx/src/lib.rs")));
+        assert!(!checker.is_in_rust_std(&tmpdir.path().join("my-crate/src/lib.rs")));
+    }
+
+    #[test]
+    fn build_std_dirs() {
+        let mut checker = Checker::new(
+            Arc::new(TempDir::new(None).unwrap()),
+            PathBuf::default(),
+            Arc::new(Args::default()),
+            Arc::from(Path::new("/sysroot")),
+            Arc::new(CrateIndex::default()),
+            ConfigFile::default(),
+        );
+        let foo = crate::crate_index::testing::pkg_id("foo");
+        checker
+            .record_crate_paths(&rpc::RustcOutput {
+                crate_sel: CrateSel::primary(foo.clone()),
+                source_paths: vec![PathBuf::from("/registry/src/hashbrown-0.14.0/src/lib.rs")],
+                is_staticlib: false,
+                path_remaps: Vec::new(),
+                generated_paths: Vec::new(),
+            })
+            .unwrap();
+        checker
+            .handle_request(
+                &Some(rpc::Request::StdCrateBuilt(PathBuf::from(
+                    "/registry/src/hashbrown-0.14.0",
+                ))),
+                &mut CheckState::default(),
+            )
+            .unwrap();
+        let std_path = Path::new("/registry/src/hashbrown-0.14.0/src/map.rs");
+        assert!(checker.is_in_rust_std(std_path));
+        assert_eq!(
+            checker.opt_pkg_ids_from_source_path(std_path).as_deref(),
+            Some([].as_slice())
+        );
+        // Source files that were also compiled as part of a regular package are still attributed
+        // to that package.
+        assert_eq!(
+            checker
+                .opt_pkg_ids_from_source_path(Path::new(
+                    "/registry/src/hashbrown-0.14.0/src/lib.rs"
+                ))
+                .as_deref(),
+            Some([foo].as_slice())
+        );
+        assert!(!checker.is_in_rust_std(Path::new("/registry/src/hashbrown-0.15.0/src/map.rs")));
+    }
+
+    #[test]
+    fn remapped_source_paths() {
+        let mut checker = checker_for_testing();
+        let foo = crate::crate_index::testing::pkg_id("foo");
+        checker
+            .record_crate_paths(&rpc::RustcOutput {
+                crate_sel: CrateSel::primary(foo.clone()),
+                source_paths: vec![PathBuf::from("/home/user/ws/foo/src/lib.rs")],
+                is_staticlib: false,
+                path_remaps: vec![
+                    PathRemap::parse("/home/user=/home").unwrap(),
+                    PathRemap::parse("/home/user/ws=/ws").unwrap(),
+                ],
+                generated_paths: Vec::new(),
+            })
+            .unwrap();
+        for path in ["/ws/foo/src/lib.rs", "/home/user/ws/foo/src/lib.rs"] {
+            assert_eq!(
+                checker
+                    .opt_pkg_ids_from_source_path(Path::new(path))
+                    .as_deref(),
+                Some([foo.clone()].as_slice())
+            );
+        }
+    }
+
+    #[test]
+    fn generated_code() {
+        let mut checker = checker_for_testing();
+        let foo = crate::crate_index::testing::pkg_id("foo");
+        let generated = PathBuf::from("/target/debug/build/foo-1234/out/bindings.rs");
+        let request = Some(rpc::Request::RustcComplete(rpc::RustcOutput {
+            crate_sel: CrateSel::primary(foo.clone()),
+            source_paths: vec![PathBuf::from("/foo/src/lib.rs"), generated.clone()],
+            is_staticlib: false,
+            path_remaps: Vec::new(),
+            generated_paths: vec![generated.clone()],
+        }));
+        let mut check_state = CheckState::default();
+        let problems = checker.handle_request(&request, &mut check_state).unwrap();
+        assert_eq!(
+            problems.take(),
+            vec![Problem::GeneratedCode(GeneratedCode {
+                crate_sel: CrateSel::primary(foo.clone()),
+                paths: vec![generated.clone()],
+            })]
+        );
+        assert_eq!(
+            checker.generated_code.get(&foo),
+            Some(&[generated].into_iter().collect())
+        );
+        // If the request is handled again, e.g. after the config was changed, we don't report the
+        // same warning again.
+        let problems = checker.handle_request(&request, &mut check_state).unwrap();
+        assert!(problems.is_empty());
+
+        let mut checker = checker_for_testing();
+        checker.update_config(
+            parse(
+                r#"
+                [pkg.foo]
+                allow_generated_code = true
+                "#,
+            )
+            .unwrap(),
+        );
+        let problems = checker
+            .handle_request(&request, &mut CheckState::default())
+            .unwrap();
+        assert!(problems.is_empty());
+        assert!(checker.generated_code.contains_key(&foo));
+    }
 }
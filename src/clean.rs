@@ -0,0 +1,241 @@
+//! `cargo acl clean`, which removes the files that we leave behind in the target directory, such as
+//! saved problems, status and metrics files, scratch directories and saved requests. Binaries that
+//! we replaced with wrappers, so that we could sandbox them, are put back. Cargo's own build outputs
+//! are left alone unless `--cargo` is passed.
+
+use crate::config::CommonConfig;
+use crate::existing_build::profile_dir;
+use crate::proxy::cargo::profile_name;
+use crate::proxy::subprocess::orig_bin_path;
+use crate::proxy::subprocess::wrapped_bin;
+use crate::Args;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct CleanOptions {
+    /// Also run `cargo clean` for the profile that we build with.
+    #[clap(long)]
+    cargo: bool,
+}
+
+pub(crate) fn clean(
+    workspace_roots: &[PathBuf],
+    target_dir: &Path,
+    args: &Args,
+    config: &CommonConfig,
+    options: &CleanOptions,
+) -> Result<()> {
+    let profile = profile_name(args, config);
+    let mut reclaimed = remove_artifacts(target_dir, profile, args.tmpdir.as_deref())?;
+    if options.cargo {
+        let profile_dirs = profile_dirs(target_dir, profile)?;
+        let before = total_size(&profile_dirs)?;
+        for root in workspace_roots {
+            crate::proxy::clean(root, args, config)?;
+        }
+        reclaimed += before.saturating_sub(total_size(&profile_dirs)?);
+    }
+    if !args.quiet {
+        println!("Reclaimed {}", crate::bloat::format_size(reclaimed));
+    }
+    Ok(())
+}
+
+/// Removes the files that we create in `target_dir` when building with `profile` and in `tmpdir`,
+/// then restores any binaries that we wrapped. Returns the number of bytes reclaimed.
+fn remove_artifacts(target_dir: &Path, profile: &str, tmpdir: Option<&Path>) -> Result<u64> {
+    let mut paths = vec![
+        crate::status::status_path(target_dir),
+        crate::problem_store::saved_problems_path(target_dir),
+        crate::metrics::metrics_path(target_dir),
+        crate::provenance::provenance_path(target_dir),
+        crate::config_file::changes_patch_path(target_dir),
+        crate::preflight::preflight_dir(target_dir),
+        crate::sandbox::scratch_root(target_dir),
+        crate::sandbox::env_vars_dir(target_dir, profile),
+        crate::saved_requests_dir(target_dir, profile),
+    ];
+    // Our temporary directory is normally removed when we exit, so there's only something to clean
+    // if one was passed to us.
+    if let Some(tmpdir) = tmpdir {
+        paths.push(crate::config::flattened_config_path(tmpdir));
+        paths.push(crate::proxy::ipc_dir(tmpdir));
+    }
+    let mut reclaimed = 0;
+    for path in paths {
+        reclaimed += remove(&path)?;
+    }
+    for dir in profile_dirs(target_dir, profile)? {
+        reclaimed += restore_wrapped_bins(&dir)?;
+    }
+    Ok(reclaimed)
+}
+
+/// Returns the directories in which cargo puts the outputs of `profile`, both when building for
+/// the host and when building for a specified target.
+fn profile_dirs(target_dir: &Path, profile: &str) -> Result<Vec<PathBuf>> {
+    let mut dirs = vec![profile_dir(target_dir, None, profile)];
+    if target_dir.is_dir() {
+        for entry in read_dir(target_dir)? {
+            if let Some(target) = entry.file_name().and_then(|name| name.to_str()) {
+                dirs.push(profile_dir(target_dir, Some(target), profile));
+            }
+        }
+    }
+    dirs.retain(|dir| dir.is_dir());
+    Ok(dirs)
+}
+
+/// Puts back binaries under `dir` that we replaced with wrappers, removing the wrappers, including
+/// any copies that cargo made of them. Returns the number of bytes reclaimed.
+fn restore_wrapped_bins(dir: &Path) -> Result<u64> {
+    let mut wrappers: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    find_wrappers(dir, &mut wrappers)?;
+    let mut reclaimed = 0;
+    for (orig_bin, wrappers) in wrappers {
+        if !orig_bin.is_file() {
+            continue;
+        }
+        // The wrapper in the original location of the binary gets the binary moved back over it.
+        // Any other wrappers are copies and get replaced by links to the binary.
+        let original = wrappers
+            .iter()
+            .find(|wrapper| *orig_bin_path(wrapper) == *orig_bin);
+        for wrapper in &wrappers {
+            reclaimed += remove(wrapper)?;
+        }
+        let restored = match original {
+            Some(original) => {
+                std::fs::rename(&orig_bin, original).with_context(|| {
+                    format!(
+                        "Failed to rename `{}` to `{}`",
+                        orig_bin.display(),
+                        original.display()
+                    )
+                })?;
+                original
+            }
+            None => &orig_bin,
+        };
+        for wrapper in wrappers.iter().filter(|wrapper| *wrapper != restored) {
+            if std::fs::hard_link(restored, wrapper).is_err() {
+                std::fs::copy(restored, wrapper).with_context(|| {
+                    format!(
+                        "Failed to copy `{}` to `{}`",
+                        restored.display(),
+                        wrapper.display()
+                    )
+                })?;
+            }
+        }
+    }
+    Ok(reclaimed)
+}
+
+/// Adds the wrappers under `dir` to `wrappers`, keyed by the binary that they wrap.
+fn find_wrappers(dir: &Path, wrappers: &mut BTreeMap<PathBuf, Vec<PathBuf>>) -> Result<()> {
+    for path in read_dir(dir)? {
+        let file_type = std::fs::symlink_metadata(&path)
+            .with_context(|| format!("Failed to get metadata for `{}`", path.display()))?
+            .file_type();
+        if file_type.is_dir() {
+            find_wrappers(&path, wrappers)?;
+        } else if file_type.is_file() {
+            if let Some(orig_bin) = wrapped_bin(&path) {
+                wrappers.entry(orig_bin).or_default().push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes the file or directory at `path` if it exists. Returns the number of bytes removed.
+fn remove(path: &Path) -> Result<u64> {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return Ok(0);
+    };
+    let size = size(path)?;
+    if metadata.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+    .with_context(|| format!("Failed to remove `{}`", path.display()))?;
+    Ok(size)
+}
+
+fn total_size(paths: &[PathBuf]) -> Result<u64> {
+    paths
+        .iter()
+        .filter(|path| path.exists())
+        .map(|path| size(path))
+        .sum()
+}
+
+/// Returns the total size of the files at or under `path`.
+fn size(path: &Path) -> Result<u64> {
+    let metadata = std::fs::symlink_metadata(path)
+        .with_context(|| format!("Failed to get metadata for `{}`", path.display()))?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    read_dir(path)?.iter().map(|path| size(path)).sum()
+}
+
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    dir.read_dir()
+        .with_context(|| format!("Failed to read directory `{}`", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_artifacts_and_restores_bins() {
+        let dir = tempfile::tempdir().unwrap();
+        let target_dir = dir.path();
+        let write = |path: &Path, contents: &str| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        let status = crate::status::status_path(target_dir);
+        write(&status, "{}");
+        write(
+            &crate::sandbox::scratch_root(target_dir).join("foo/data"),
+            "scratch",
+        );
+        let profile_dir = target_dir.join("x86_64-unknown-linux-gnu/cackle");
+        let bin = profile_dir.join("deps/foo-1234");
+        let orig = orig_bin_path(&bin);
+        write(&orig, "binary");
+        let wrapper = crate::proxy::subprocess::bin_wrapper_script(
+            "/bin/cackle",
+            "token",
+            orig.to_str().unwrap(),
+        );
+        write(&bin, &wrapper);
+        let uplifted = profile_dir.join("foo");
+        write(&uplifted, &wrapper);
+        let unrelated = profile_dir.join("deps/libbar.rlib");
+        write(&unrelated, "rlib");
+
+        let reclaimed = remove_artifacts(target_dir, "cackle", None).unwrap();
+        assert_eq!(reclaimed, 2 + 7 + 2 * wrapper.len() as u64);
+        assert!(!status.exists());
+        assert!(!crate::sandbox::scratch_root(target_dir).exists());
+        assert!(!orig.exists());
+        assert_eq!(std::fs::read_to_string(&bin).unwrap(), "binary");
+        assert_eq!(std::fs::read_to_string(&uplifted).unwrap(), "binary");
+        assert_eq!(std::fs::read_to_string(&unrelated).unwrap(), "rlib");
+
+        assert_eq!(remove_artifacts(target_dir, "cackle", None).unwrap(), 0);
+    }
+}
@@ -1,4 +1,9 @@
+use self::acknowledgements::Acknowledgement;
+use self::built_in::RustcVersion;
+use self::permissions::PermSel;
 use self::permissions::Permissions;
+use crate::capabilities::CapabilityManifest;
+use crate::config_file::ConfigFile;
 use crate::crate_index::CrateIndex;
 use crate::crate_index::PackageId;
 use crate::problem::AvailableApi;
@@ -8,6 +13,7 @@ use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
+use fxhash::FxHashMap;
 use serde::Deserialize;
 use serde::Serialize;
 use std::collections::BTreeMap;
@@ -17,7 +23,11 @@ use std::path::PathBuf;
 use std::sync::Arc;
 pub(crate) use versions::MAX_VERSION;
 
+pub(crate) mod acknowledgements;
+pub(crate) mod approvals;
 pub(crate) mod built_in;
+pub(crate) mod expiry;
+pub(crate) mod inheritance;
 pub(crate) mod permissions;
 pub(crate) mod versions;
 
@@ -32,6 +42,22 @@ pub(crate) struct Config {
     /// Permissions without inheritance. This should only be used when checking for unused
     /// configuration.
     pub(crate) permissions_no_inheritance: Permissions,
+
+    /// Capability manifests published by our dependencies.
+    pub(crate) capability_manifests: FxHashMap<PackageId, CapabilityManifest>,
+
+    /// Patterns used as `[pkg]` selectors, together with the selector that they came from.
+    pub(crate) package_patterns: Vec<(PackageName, PackagePattern)>,
+
+    /// The names of the first-party packages in the dependency tree, sorted.
+    pub(crate) first_party_packages: Vec<PackageName>,
+
+    /// Acknowledged usages, keyed by the package selector and API that they apply to.
+    pub(crate) acknowledgements: FxHashMap<(PermSel, ApiName), Vec<Acknowledgement>>,
+
+    /// The directory of each package, as reported by the crate index. Paths in acknowledgements
+    /// are relative to these.
+    pub(crate) pkg_dirs: FxHashMap<PackageId, PathBuf>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
@@ -50,13 +76,45 @@ pub(crate) struct RawConfig {
 
     #[serde(default)]
     pub(crate) rustc: RustcConfig,
+
+    #[serde(default)]
+    pub(crate) deny: DenyConfig,
+
+    #[serde(default)]
+    pub(crate) defaults: DefaultsConfig,
+
+    #[serde(default, rename = "plugin")]
+    pub(crate) plugins: BTreeMap<String, PluginConfig>,
+
+    #[serde(default, rename = "fix")]
+    pub(crate) fixes: BTreeMap<String, FixConfig>,
+
+    #[serde(default)]
+    pub(crate) ui: UiConfig,
+
+    /// Who approved each grant of a permission, keyed by the grant. e.g.
+    /// `pkg.foo.allow_apis.net`.
+    #[serde(default)]
+    pub(crate) approvals: BTreeMap<String, approvals::Approval>,
+
+    /// Individual API usages that have been reviewed and accepted.
+    #[serde(default)]
+    pub(crate) acknowledgements: Vec<acknowledgements::Acknowledgement>,
 }
 
-/// The name of a package. Doesn't include any version information.
+/// The name of a package. Doesn't include any version information. When used as a `[pkg]`
+/// selector, this may instead be a pattern that matches a family of packages.
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Serialize, Deserialize, PartialOrd, Ord)]
 #[serde(transparent)]
 pub(crate) struct PackageName(pub(crate) Arc<str>);
 
+/// A pattern matching the names of multiple packages. e.g. `windows-*` or `regex(^tokio-)`.
+#[derive(Debug, Clone)]
+pub(crate) enum PackagePattern {
+    Glob(Arc<str>),
+    Regex(regex::Regex),
+}
+
 #[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct CommonConfig {
@@ -76,6 +134,90 @@ pub(crate) struct CommonConfig {
 
     #[serde(default)]
     pub(crate) profile: Option<String>,
+
+    #[serde(default)]
+    pub(crate) trust_capability_manifests: bool,
+
+    /// Globs matching the names of packages that, in addition to the members of our workspace,
+    /// are considered first-party.
+    #[serde(default)]
+    pub(crate) first_party: Vec<String>,
+
+    /// APIs that packages are allowed to use if they're only used via dev-dependencies of our
+    /// workspace, e.g. by test helpers.
+    #[serde(default)]
+    pub(crate) dev_dependencies_default_allow: Vec<ApiName>,
+
+    /// Which package is charged with API usage by generic code that has been instantiated with
+    /// types from another package. If not set, usage is charged to the package that defined the
+    /// generic code and also to the packages that defined any closures in the generic parameters.
+    #[serde(default)]
+    pub(crate) generic_attribution: Option<GenericAttribution>,
+
+    /// Whether to reject configuration that's made redundant by, or that contradicts, what would
+    /// be inherited from a less specific scope.
+    #[serde(default)]
+    pub(crate) strict_inheritance: bool,
+
+    /// Additional path prefixes under which source files are considered to be part of the Rust
+    /// standard library. Needed if the standard library was built with remapped paths that we
+    /// can't detect from the sysroot.
+    #[serde(default)]
+    pub(crate) std_path_prefixes: Vec<PathBuf>,
+
+    /// Whether the standard library is built from source with build-std. Only needed if this isn't
+    /// evident from the arguments that we pass to cargo, e.g. because it's enabled in
+    /// `.cargo/config.toml`.
+    #[serde(default)]
+    pub(crate) build_std: bool,
+
+    /// The number of people who must approve each grant of a permission. When set, the UI prompts
+    /// for approval metadata when applying fixes that add grants.
+    #[serde(default)]
+    pub(crate) required_approvals: Option<u32>,
+
+    /// The linker to run after we've intercepted a link. One of `mold`, `lld`, `gold` or `bfd` is
+    /// passed to the C compiler driver via `-fuse-ld`, anything else is the program to run. Takes
+    /// precedence over any linker configured for cargo.
+    #[serde(default)]
+    pub(crate) linker: Option<String>,
+}
+
+/// Preferences for the interactive user interface. These are most useful in the local config layer,
+/// since they're a matter of personal taste.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct UiConfig {
+    #[serde(default)]
+    pub(crate) theme: Option<UiTheme>,
+
+    /// Whether to colour source code excerpts according to their syntax. Defaults to true.
+    #[serde(default)]
+    pub(crate) syntax_highlighting: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum UiTheme {
+    /// Colours suited to a terminal with a dark background.
+    #[default]
+    Dark,
+    /// Colours suited to a terminal with a light background.
+    Light,
+    /// Distinguishes things using bold and reversed text rather than colour.
+    HighContrast,
+}
+
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum GenericAttribution {
+    /// Only the packages that supplied the types used to instantiate the generic code. This weakens
+    /// checks, since a package that takes a closure parameter isn't charged for its own API usage.
+    Instantiator,
+    /// The package that defined the generic code.
+    Definer,
+    /// Both the instantiating packages and the defining package.
+    Both,
 }
 
 #[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
@@ -97,6 +239,47 @@ pub(crate) struct SandboxConfig {
 
     #[serde(default)]
     pub(crate) pass_env: Vec<String>,
+
+    /// Input to supply to the sandboxed process on stdin. If not set, the process gets no input and
+    /// if it tries to read some, we report a problem.
+    pub(crate) stdin: Option<String>,
+
+    /// Whether to provide a writable scratch directory for the package. The directory is created
+    /// under the target directory and its path is passed via the `CACKLE_SCRATCH_DIR` environment
+    /// variable.
+    pub(crate) scratch_dir: Option<bool>,
+
+    /// Overrides for individual tests, keyed by test name. Only valid for tests. A test with an
+    /// override that fails when run with the other tests is rerun by itself with the override
+    /// applied on top of this config.
+    #[serde(default)]
+    pub(crate) per_test: BTreeMap<String, SandboxConfig>,
+
+    /// Rules controlling access to particular paths. Applied in order after the default sandbox
+    /// setup, so later rules take precedence over earlier rules for the same or nested paths.
+    #[serde(default)]
+    pub(crate) fs_rules: Vec<FsRule>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FsRule {
+    pub(crate) path: PathBuf,
+    pub(crate) access: FsAccess,
+    /// Whether to create the directory if it doesn't exist. Only applies to write access.
+    #[serde(default)]
+    pub(crate) create: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FsAccess {
+    /// The path is visible, but read-only.
+    Read,
+    /// The path is visible and writable.
+    Write,
+    /// The path is hidden by an empty directory.
+    None,
 }
 
 #[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
@@ -106,6 +289,98 @@ pub(crate) struct RustcConfig {
     pub(crate) sandbox: SandboxConfig,
 }
 
+/// Permissions granted to all packages of a particular class, in addition to whatever is configured
+/// for each package.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DefaultsConfig {
+    #[serde(default)]
+    pub(crate) first_party: PackageConfig,
+
+    #[serde(default)]
+    pub(crate) third_party: PackageConfig,
+
+    /// Granted to first-party packages that only have binary targets, in addition to
+    /// `first_party`.
+    #[serde(default)]
+    pub(crate) first_party_bin: PackageConfig,
+
+    /// Granted to first-party packages that have a library or proc macro, in addition to
+    /// `first_party`.
+    #[serde(default)]
+    pub(crate) first_party_lib: PackageConfig,
+}
+
+impl DefaultsConfig {
+    /// Returns each table of defaults together with its name within `[defaults]`.
+    pub(crate) fn tables(&self) -> [(&'static str, &PackageConfig); 4] {
+        [
+            ("first_party", &self.first_party),
+            ("third_party", &self.third_party),
+            ("first_party_bin", &self.first_party_bin),
+            ("first_party_lib", &self.first_party_lib),
+        ]
+    }
+
+    pub(crate) fn tables_mut(&mut self) -> [(&'static str, &mut PackageConfig); 4] {
+        [
+            ("first_party", &mut self.first_party),
+            ("third_party", &mut self.third_party),
+            ("first_party_bin", &mut self.first_party_bin),
+            ("first_party_lib", &mut self.first_party_lib),
+        ]
+    }
+}
+
+/// Things that are never permitted for third-party packages, regardless of what permissions they've
+/// been granted.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DenyConfig {
+    #[serde(default)]
+    pub(crate) paths: Vec<ApiPath>,
+
+    /// A cargo-deny config, e.g. `deny.toml`. Packages that it bans, or that come from sources
+    /// that it doesn't allow, are reported as problems. Relative to the workspace root.
+    #[serde(default)]
+    pub(crate) cargo_deny: Option<PathBuf>,
+}
+
+/// A check implemented by an external command. See `crate::plugin` for the protocol.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct PluginConfig {
+    /// The program to run followed by its arguments. The program is run from the directory
+    /// containing cackle.toml.
+    pub(crate) command: Vec<String>,
+}
+
+/// A fix implemented by an external command. See `crate::plugin` for the protocol.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FixConfig {
+    /// What to show in the list of fixes.
+    pub(crate) title: String,
+
+    #[serde(default)]
+    pub(crate) help: Option<String>,
+
+    /// The kinds of problem that this fix is offered for. If empty, it's offered for all problems.
+    #[serde(default)]
+    pub(crate) problems: Vec<String>,
+
+    /// The program to run followed by its arguments.
+    pub(crate) command: Vec<String>,
+
+    /// The name under which the fix is configured, i.e. `name` in `[fix.name]`.
+    #[serde(skip)]
+    pub(crate) name: String,
+
+    /// The directory containing cackle.toml, which is where the command is run from.
+    #[serde(skip)]
+    pub(crate) dir: Option<PathBuf>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default, Hash)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct ApiConfig {
@@ -117,6 +392,17 @@ pub(crate) struct ApiConfig {
 
     #[serde(default)]
     pub(crate) no_auto_detect: Vec<PackageName>,
+
+    /// Names of non-Rust symbols, such as C library functions, references to which are considered
+    /// usage of the API. e.g. `open`. Unlike `include`, these must match the whole symbol.
+    #[serde(default)]
+    pub(crate) native_symbols: Vec<String>,
+
+    /// Prefixes of the names of link sections, such as `.init_array`. Code that puts a reference to a
+    /// function into a matching section is considered to use the API. This is how functions are
+    /// registered to run before `main` or at exit.
+    #[serde(default)]
+    pub(crate) link_sections: Vec<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -140,32 +426,74 @@ pub(crate) enum SandboxKind {
 
 pub(crate) const SANDBOX_KINDS: &[SandboxKind] = &[SandboxKind::Disabled, SandboxKind::Bubblewrap];
 
+/// Kinds of problem that can be downgraded from errors to warnings on a per-package basis. These
+/// are problems that are produced by heuristics that are known to sometimes give false positives,
+/// or that relate to claims made by a package rather than to what's permitted by our config.
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DowngradableProblem {
+    OffTreeApiUsage,
+    PossibleExportedApi,
+    CapabilityManifestViolation,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct PackageConfig {
     #[serde(default)]
     pub(crate) allow_unsafe: bool,
 
+    /// Globs, relative to the package's directory, matching the source files in which the package
+    /// may use unsafe. When set, rustc is no longer asked to forbid unsafe for the package, so
+    /// unsafe that comes from macro expansion isn't detected in any of its files.
+    #[serde(default)]
+    pub(crate) allow_unsafe_in: Vec<String>,
+
     #[serde(default)]
     pub(crate) allow_build_instructions: Vec<String>,
 
     #[serde(default)]
     pub(crate) allow_apis: Vec<ApiName>,
 
+    /// APIs that the package may use until the start of the specified date. Once loaded, those that
+    /// haven't yet expired are also present in `allow_apis`.
+    #[serde(default, deserialize_with = "expiry::deserialize_dates")]
+    pub(crate) allow_apis_until: BTreeMap<ApiName, toml::value::Datetime>,
+
+    /// APIs from `allow_apis_until` that have expired.
+    #[serde(skip)]
+    pub(crate) expired_apis: Vec<ApiName>,
+
     #[serde(default)]
     pub(crate) allow_proc_macro: bool,
 
+    /// Whether the package may include code generated by its build script without this being
+    /// reported.
+    #[serde(default)]
+    pub(crate) allow_generated_code: bool,
+
     pub(crate) build: Option<Box<PackageConfig>>,
     pub(crate) test: Option<Box<PackageConfig>>,
 
+    /// Permissions for the package's proc macro, which runs in the compiler when building packages
+    /// that use it.
+    #[serde(rename = "proc-macro")]
+    pub(crate) proc_macro: Option<Box<PackageConfig>>,
+
     #[serde()]
     pub(crate) from: Option<FromConfig>,
 
     #[serde(default)]
     pub(crate) sandbox: SandboxConfig,
 
+    /// Overrides of `[rustc]` for when the package is being compiled.
+    pub(crate) rustc: Option<RustcConfig>,
+
     #[serde(default)]
     pub(crate) import: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub(crate) downgrade: Vec<DowngradableProblem>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
@@ -175,49 +503,186 @@ pub(crate) struct FromConfig {
     pub(crate) test: Option<Box<PackageConfig>>,
 }
 
-pub(crate) fn parse_file(cackle_path: &Path, crate_index: &CrateIndex) -> Result<Arc<Config>> {
-    let mut raw_config = parse_file_raw(cackle_path)?;
+/// The name of the layer that's applied last if present. It's intended for developers' own
+/// overrides and shouldn't be committed.
+const LOCAL_LAYER: &str = "local";
+
+/// Parses `config_file` together with any layers applied on top of it. `layers` are the names of
+/// layers that were explicitly requested, e.g. "ci" for `cackle.ci.toml`.
+pub(crate) fn parse_file(
+    config_file: &ConfigFile,
+    layers: &[String],
+    crate_index: &CrateIndex,
+    rustc_version: Option<RustcVersion>,
+) -> Result<Arc<Config>> {
+    let cackle_path = config_file.path();
+    let cackle = config_file.read()?;
+    let layer_paths = layer_paths(cackle_path, layers)?;
+    let mut raw_config = if layer_paths.is_empty() {
+        parse_raw(&cackle, rustc_version)
+            .with_context(|| format!("Failed to parse {}", cackle_path.display()))?
+    } else {
+        parse_layered(cackle_path, &cackle, &layer_paths, rustc_version)?
+    };
     raw_config.load_imports(crate_index)?;
     raw_config.make_paths_absolute(crate_index.manifest_path.parent())?;
-    let config = Config::from_raw(raw_config, crate_index)?;
+    for (name, fix) in &mut raw_config.fixes {
+        fix.name = name.clone();
+        fix.dir = cackle_path.parent().map(Path::to_owned);
+    }
+    let capability_manifests = crate::capabilities::load_dependency_manifests(crate_index)?;
+    let config = Config::from_raw(raw_config, crate_index, capability_manifests)?;
     crate::config_validation::validate(&config, cackle_path)?;
     Ok(config)
 }
 
 impl Config {
-    fn from_raw(raw_config: RawConfig, crate_index: &CrateIndex) -> Result<Arc<Config>> {
+    fn from_raw(
+        raw_config: RawConfig,
+        crate_index: &CrateIndex,
+        capability_manifests: FxHashMap<PackageId, CapabilityManifest>,
+    ) -> Result<Arc<Config>> {
+        let package_patterns = raw_config.package_patterns(crate_index)?;
         let permissions_no_inheritance = Permissions::from_config(&raw_config);
-        let permissions = Permissions::from_config_with_inheritance(&raw_config, crate_index);
+        let permissions = Permissions::from_config_with_inheritance(
+            &raw_config,
+            crate_index,
+            &capability_manifests,
+            &package_patterns,
+        );
+        let mut first_party_packages: Vec<PackageName> = crate_index
+            .package_ids()
+            .filter(|pkg_id| raw_config.is_first_party(pkg_id.name_str(), crate_index))
+            .map(|pkg_id| PackageName(pkg_id.pkg_name()))
+            .collect();
+        first_party_packages.sort();
+        first_party_packages.dedup();
+        let mut acknowledgements: FxHashMap<(PermSel, ApiName), Vec<Acknowledgement>> =
+            FxHashMap::default();
+        for ack in &raw_config.acknowledgements {
+            acknowledgements
+                .entry((ack.perm_sel(), ack.api.clone()))
+                .or_default()
+                .push(ack.clone());
+        }
+        let pkg_dirs = crate_index
+            .package_ids()
+            .filter_map(|pkg_id| Some((pkg_id.clone(), crate_index.pkg_dir(pkg_id)?.to_owned())))
+            .collect();
         let config = Config {
             raw: raw_config,
             permissions,
             permissions_no_inheritance,
+            capability_manifests,
+            package_patterns,
+            first_party_packages,
+            acknowledgements,
+            pkg_dirs,
         };
         Ok(Arc::new(config))
     }
 }
 
-fn parse_file_raw(cackle_path: &Path) -> Result<RawConfig> {
+fn parse_file_raw(cackle_path: &Path, rustc_version: Option<RustcVersion>) -> Result<RawConfig> {
     let cackle: String = std::fs::read_to_string(cackle_path)
         .with_context(|| format!("Failed to open {}", cackle_path.display()))?;
-    let raw_config =
-        parse_raw(&cackle).with_context(|| format!("Failed to parse {}", cackle_path.display()))?;
+    let raw_config = parse_raw(&cackle, rustc_version)
+        .with_context(|| format!("Failed to parse {}", cackle_path.display()))?;
     Ok(raw_config)
 }
 
-fn parse_raw(cackle: &str) -> Result<RawConfig> {
-    let mut config = toml::from_str(cackle)?;
-    merge_built_ins(&mut config)?;
+/// Returns the paths of the layers to apply on top of `cackle_path`, in the order in which they
+/// should be applied. Explicitly requested layers are applied in the order given and must exist. The
+/// local layer is applied last, but only if it exists.
+pub(crate) fn layer_paths(cackle_path: &Path, layers: &[String]) -> Result<Vec<PathBuf>> {
+    let stem = cackle_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow!("Invalid config path `{}`", cackle_path.display()))?;
+    let layer_path = |layer: &str| cackle_path.with_file_name(format!("{stem}.{layer}.toml"));
+    let mut paths = Vec::new();
+    for layer in layers {
+        let path = layer_path(layer);
+        if !path.exists() {
+            bail!(
+                "Config layer `{layer}` was requested, but `{}` doesn't exist",
+                path.display()
+            );
+        }
+        paths.push(path);
+    }
+    let local = layer_path(LOCAL_LAYER);
+    if local.exists() && !paths.contains(&local) {
+        paths.push(local);
+    }
+    Ok(paths)
+}
+
+/// Parses `cackle`, the contents of `cackle_path`, with each of `layer_paths` merged over it. Tables
+/// are merged recursively. Any other value, including arrays, replaces the value from earlier files.
+fn parse_layered(
+    cackle_path: &Path,
+    cackle: &str,
+    layer_paths: &[PathBuf],
+    rustc_version: Option<RustcVersion>,
+) -> Result<RawConfig> {
+    let parse_table = |toml: &str, path: &Path| -> Result<toml::Table> {
+        toml::from_str(toml).with_context(|| format!("Failed to parse {}", path.display()))
+    };
+    let mut table = parse_table(cackle, cackle_path)?;
+    for path in layer_paths {
+        merge_tables(
+            &mut table,
+            parse_table(&crate::fs::read_to_string(path)?, path)?,
+        );
+    }
+    let config = RawConfig::deserialize(table).with_context(|| {
+        format!(
+            "Failed to parse {} with layers {}",
+            cackle_path.display(),
+            layer_paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    })?;
+    finish_raw(config, rustc_version)
+}
+
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_tables(base_table, overlay_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Parses `cackle`, merging in any imported built-in APIs. `rustc_version` determines which
+/// standard library paths the built-in APIs include. If None, all known paths are included.
+pub(crate) fn parse_raw(cackle: &str, rustc_version: Option<RustcVersion>) -> Result<RawConfig> {
+    finish_raw(toml::from_str(cackle)?, rustc_version)
+}
+
+/// Applies the processing that's needed after deserialising a config.
+fn finish_raw(mut config: RawConfig, rustc_version: Option<RustcVersion>) -> Result<RawConfig> {
+    merge_built_ins(&mut config, rustc_version)?;
     versions::apply_runtime_patches(&mut config);
     config.rustc.sandbox.inherit(&config.sandbox);
+    expiry::apply(&mut config, expiry::today())?;
     Ok(config)
 }
 
-fn merge_built_ins(config: &mut RawConfig) -> Result<()> {
+fn merge_built_ins(config: &mut RawConfig, rustc_version: Option<RustcVersion>) -> Result<()> {
     if config.common.import_std.is_empty() {
         return Ok(());
     }
-    let built_ins = built_in::get_built_ins();
+    let built_ins = built_in::built_ins_for_rustc(rustc_version);
     for imp in config.common.import_std.drain(..) {
         let api = ApiName::new(imp.as_str());
         let built_in_api = built_ins
@@ -230,6 +695,12 @@ fn merge_built_ins(config: &mut RawConfig) -> Result<()> {
         api_config
             .exclude
             .extend(built_in_api.exclude.iter().cloned());
+        api_config
+            .native_symbols
+            .extend(built_in_api.native_symbols.iter().cloned());
+        api_config
+            .link_sections
+            .extend(built_in_api.link_sections.iter().cloned());
     }
     Ok(())
 }
@@ -306,16 +777,86 @@ impl RawConfig {
 
 impl RawConfig {
     fn make_paths_absolute(&mut self, workspace_root: Option<&Path>) -> Result<()> {
-        for pkg_config in self.packages.values_mut() {
+        for pkg_config in self.packages.values_mut().chain(
+            self.defaults
+                .tables_mut()
+                .into_iter()
+                .map(|(_, pkg_config)| pkg_config),
+        ) {
             pkg_config.make_paths_absolute(workspace_root)?;
         }
+        self.sandbox.make_paths_absolute(workspace_root)?;
+        self.rustc.sandbox.make_paths_absolute(workspace_root)?;
+        if let Some(path) = self.deny.cargo_deny.as_mut() {
+            make_path_absolute(path, workspace_root)?;
+        }
         Ok(())
     }
+
+    /// Returns the patterns used as `[pkg]` selectors. Checks that each is valid and matches at
+    /// least one package.
+    fn package_patterns(
+        &self,
+        crate_index: &CrateIndex,
+    ) -> Result<Vec<(PackageName, PackagePattern)>> {
+        let mut patterns = Vec::new();
+        for pkg_name in self.packages.keys() {
+            let Some(pattern) = pkg_name.pattern()? else {
+                continue;
+            };
+            if !crate_index
+                .package_ids()
+                .any(|pkg_id| pattern.matches(pkg_id.name_str()))
+            {
+                bail!("Package selector `[pkg.\"{pkg_name}\"]` doesn't match any packages");
+            }
+            patterns.push((pkg_name.clone(), pattern));
+        }
+        Ok(patterns)
+    }
+
+    /// Returns whether packages named `pkg_name` are first-party. That is, whether they're members
+    /// of our workspace, path dependencies or match one of the globs in `common.first_party`.
+    pub(crate) fn is_first_party(&self, pkg_name: &str, crate_index: &CrateIndex) -> bool {
+        self.common
+            .first_party
+            .iter()
+            .any(|pattern| glob_matches(pattern, pkg_name))
+            || crate_index.package_infos.iter().any(|(pkg_id, info)| {
+                (info.is_workspace_member || info.is_path_dependency)
+                    && pkg_id.name_str() == pkg_name
+            })
+    }
+}
+
+/// Returns whether `value` matches `pattern`, where `*` in `pattern` matches any sequence of
+/// characters.
+pub(crate) fn glob_matches(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcards.
+        return rest.is_empty();
+    };
+    for part in middle {
+        let Some(index) = rest.find(part) else {
+            return false;
+        };
+        rest = &rest[index + part.len()..];
+    }
+    rest.ends_with(last)
 }
 
 impl PackageConfig {
     fn make_paths_absolute(&mut self, workspace_root: Option<&Path>) -> Result<()> {
         self.sandbox.make_paths_absolute(workspace_root)?;
+        if let Some(rustc) = self.rustc.as_mut() {
+            rustc.sandbox.make_paths_absolute(workspace_root)?;
+        }
         if let Some(sub_config) = self.build.as_mut() {
             sub_config.make_paths_absolute(workspace_root)?;
         }
@@ -330,32 +871,116 @@ impl SandboxConfig {
     fn make_paths_absolute(&mut self, workspace_root: Option<&Path>) -> Result<()> {
         make_paths_absolute(&mut self.bind_writable, workspace_root)?;
         make_paths_absolute(&mut self.make_writable, workspace_root)?;
+        for rule in &mut self.fs_rules {
+            make_path_absolute(&mut rule.path, workspace_root)?;
+        }
+        for sub_config in self.per_test.values_mut() {
+            sub_config.make_paths_absolute(workspace_root)?;
+        }
         Ok(())
     }
 }
 
+/// Environment variables that may be referenced from paths in the config. Paths that differ between
+/// machines are generally under one of these. We don't allow arbitrary variables, since we don't
+/// want the config to be able to depend on things like credentials.
+const PATH_ENV_VARS: &[&str] = &[
+    "CARGO_HOME",
+    "HOME",
+    "RUSTUP_HOME",
+    "TMPDIR",
+    "USER",
+    "XDG_CACHE_HOME",
+    "XDG_CONFIG_HOME",
+    "XDG_DATA_HOME",
+    "XDG_RUNTIME_DIR",
+    "XDG_STATE_HOME",
+];
+
 fn make_paths_absolute(paths: &mut [PathBuf], workspace_root: Option<&Path>) -> Result<()> {
     for path in paths {
-        if !path.is_absolute() {
-            // When we process the config file in the main cackle process, we should
-            // always have a workspace root. At that point all paths should be made
-            // absolute. Subprocesses won't know the workspace root, but the paths
-            // should already be absolute, since they should be reading a processed
-            // version of the config written by the main process.
-            let workspace_root = workspace_root
-                .ok_or_else(|| anyhow!("Internal error: relative path with no workspace root"))?;
-            *path = workspace_root.join(&path);
-        }
+        make_path_absolute(path, workspace_root)?;
+    }
+    Ok(())
+}
+
+fn make_path_absolute(path: &mut PathBuf, workspace_root: Option<&Path>) -> Result<()> {
+    *path = interpolate_env_vars(path, |var| std::env::var(var).ok())?;
+    if !path.is_absolute() {
+        // When we process the config file in the main cackle process, we should always have a
+        // workspace root. At that point all paths should be made absolute. Subprocesses won't know
+        // the workspace root, but the paths should already be absolute, since they should be
+        // reading a processed version of the config written by the main process.
+        let workspace_root = workspace_root
+            .ok_or_else(|| anyhow!("Internal error: relative path with no workspace root"))?;
+        *path = workspace_root.join(&path);
     }
     Ok(())
 }
 
+/// Replaces references of the form `${VAR}` in `path` with the value of `VAR`, which must be one of
+/// `PATH_ENV_VARS`.
+fn interpolate_env_vars(path: &Path, get_var: impl Fn(&str) -> Option<String>) -> Result<PathBuf> {
+    let Some(mut rest) = path.to_str() else {
+        return Ok(path.to_owned());
+    };
+    if !rest.contains('$') {
+        return Ok(path.to_owned());
+    }
+    let mut out = String::new();
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let Some(after) = rest[start..].strip_prefix("${") else {
+            bail!(
+                "Path `{}` contains `$` that isn't followed by `{{`. Environment variables must be \
+                 referenced as `${{VAR}}`",
+                path.display()
+            );
+        };
+        let end = after
+            .find('}')
+            .ok_or_else(|| anyhow!("Unterminated `${{` in path `{}`", path.display()))?;
+        let var = &after[..end];
+        if !PATH_ENV_VARS.contains(&var) {
+            bail!(
+                "Environment variable `{var}` in path `{}` isn't permitted. Permitted variables \
+                 are: {}",
+                path.display(),
+                PATH_ENV_VARS.join(", ")
+            );
+        }
+        let value = get_var(var).ok_or_else(|| {
+            anyhow!(
+                "Environment variable `{var}` referenced by path `{}` isn't set",
+                path.display()
+            )
+        })?;
+        out.push_str(&value);
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(PathBuf::from(out))
+}
+
 /// Attempts to load "cackle/export.toml" from the specified package.
 fn exported_config_for_package(pkg_id: &PackageId, crate_index: &CrateIndex) -> Result<RawConfig> {
     let pkg_dir = crate_index
         .pkg_dir(pkg_id)
         .ok_or_else(|| anyhow!("Missing pkg_dir for package `{pkg_id}`"))?;
-    parse_file_raw(&pkg_dir.join("cackle").join("export.toml"))
+    parse_file_raw(&export_path(pkg_dir), None)
+}
+
+/// Returns the names of the APIs that the package with sources in `pkg_dir` exports, if any.
+pub(crate) fn exported_api_names(pkg_dir: &Path) -> Result<Vec<ApiName>> {
+    let path = export_path(pkg_dir);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(parse_file_raw(&path, None)?.apis.into_keys().collect())
+}
+
+fn export_path(pkg_dir: &Path) -> PathBuf {
+    pkg_dir.join("cackle").join("export.toml")
 }
 
 impl Display for ApiName {
@@ -417,12 +1042,56 @@ impl AsRef<str> for ApiPath {
     }
 }
 
+impl AsRef<str> for DowngradableProblem {
+    /// Returns the name used for this kind of problem in the config file.
+    fn as_ref(&self) -> &str {
+        match self {
+            DowngradableProblem::OffTreeApiUsage => "off_tree_api_usage",
+            DowngradableProblem::PossibleExportedApi => "possible_exported_api",
+            DowngradableProblem::CapabilityManifestViolation => "capability_manifest_violation",
+        }
+    }
+}
+
 impl From<&str> for PackageName {
     fn from(value: &str) -> Self {
         Self(Arc::from(value))
     }
 }
 
+impl PackageName {
+    /// Returns whether this is a pattern rather than the name of a single package.
+    pub(crate) fn is_pattern(&self) -> bool {
+        self.0.contains('*') || (self.0.starts_with("regex(") && self.0.ends_with(')'))
+    }
+
+    /// Returns the pattern that this selector represents, if it's a pattern.
+    fn pattern(&self) -> Result<Option<PackagePattern>> {
+        if let Some(regex) = self
+            .0
+            .strip_prefix("regex(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            let regex = regex::Regex::new(regex)
+                .with_context(|| format!("Invalid regex in package selector `{self}`"))?;
+            return Ok(Some(PackagePattern::Regex(regex)));
+        }
+        if self.0.contains('*') {
+            return Ok(Some(PackagePattern::Glob(self.0.clone())));
+        }
+        Ok(None)
+    }
+}
+
+impl PackagePattern {
+    pub(crate) fn matches(&self, pkg_name: &str) -> bool {
+        match self {
+            PackagePattern::Glob(glob) => glob_matches(glob, pkg_name),
+            PackagePattern::Regex(regex) => regex.is_match(pkg_name),
+        }
+    }
+}
+
 impl AsRef<str> for PackageName {
     fn as_ref(&self) -> &str {
         &self.0
@@ -448,15 +1117,38 @@ pub(crate) mod testing {
     use std::sync::Arc;
 
     pub(crate) fn parse(cackle: &str) -> anyhow::Result<Arc<super::Config>> {
+        parse_with_capability_manifests(cackle, &[])
+    }
+
+    /// Parses `cackle` as if the named packages had published the supplied capability manifests.
+    pub(crate) fn parse_with_capability_manifests(
+        cackle: &str,
+        manifests: &[(&str, &str)],
+    ) -> anyhow::Result<Arc<super::Config>> {
         let cackle_with_header = format!(
             "[common]\nversion = 1\n\
             {cackle}
         "
         );
-        let raw = super::parse_raw(&cackle_with_header)?;
-        let package_names: Vec<_> = raw.packages.keys().map(|k| k.as_ref()).collect();
+        let raw = super::parse_raw(&cackle_with_header, None)?;
+        let mut package_names: Vec<_> = raw
+            .packages
+            .keys()
+            .filter(|k| !k.is_pattern())
+            .map(|k| k.as_ref())
+            .collect();
+        package_names.extend(manifests.iter().map(|(pkg_name, _)| *pkg_name));
         let crate_index = crate::crate_index::testing::index_with_package_names(&package_names);
-        let config = Config::from_raw(raw, &crate_index).unwrap();
+        let capability_manifests = manifests
+            .iter()
+            .map(|(pkg_name, manifest)| {
+                (
+                    crate::crate_index::testing::pkg_id(pkg_name),
+                    toml::from_str(manifest).unwrap(),
+                )
+            })
+            .collect();
+        let config = Config::from_raw(raw, &crate_index, capability_manifests)?;
         validate(&config, std::path::Path::new("/dev/null"))?;
         Ok(config)
     }
@@ -465,8 +1157,15 @@ pub(crate) mod testing {
 #[cfg(test)]
 mod tests {
     use super::testing::parse;
+    use super::testing::parse_with_capability_manifests;
+    use crate::config::glob_matches;
     use crate::config::permissions::PermSel;
+    use crate::config::ApiName;
+    use crate::config::DowngradableProblem;
+    use crate::config::PackageConfig;
+    use crate::config::PackageName;
     use crate::config::SandboxKind;
+    use std::path::Path;
 
     #[test]
     fn empty() {
@@ -572,6 +1271,327 @@ mod tests {
         assert_eq!(sandbox_b.kind, Some(SandboxKind::Disabled));
     }
 
+    #[test]
+    fn per_test_sandbox() {
+        let config = parse(
+            r#"
+                [pkg.a.test.sandbox]
+                kind = "Bubblewrap"
+
+                [pkg.a.test.sandbox.per_test."tests::downloads"]
+                allow_network = true
+            "#,
+        )
+        .unwrap();
+        let sandbox = config
+            .permissions
+            .sandbox_config_for_package(&PermSel::with_scope(
+                &crate::crate_index::testing::pkg_id("a"),
+                crate::config::permissions::PermissionScope::Test,
+            ));
+        assert_eq!(
+            sandbox.per_test["tests::downloads"].allow_network,
+            Some(true)
+        );
+
+        assert!(parse(
+            r#"
+                [pkg.a.build.sandbox.per_test."tests::downloads"]
+                allow_network = true
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn fs_rules() {
+        let config = parse(
+            r#"
+                [sandbox]
+                kind = "Bubblewrap"
+                fs_rules = [
+                    { path = "/etc", access = "read" },
+                ]
+
+                [pkg.a.build.sandbox]
+                fs_rules = [
+                    { path = "/scratch", access = "write", create = true },
+                    { path = "/scratch/secret", access = "none" },
+                ]
+            "#,
+        )
+        .unwrap();
+        let sandbox = config
+            .permissions
+            .sandbox_config_for_package(&PermSel::for_build_script("a"));
+        let rules: Vec<_> = sandbox
+            .fs_rules
+            .iter()
+            .map(|rule| (rule.path.to_str().unwrap(), rule.access, rule.create))
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                ("/etc", super::FsAccess::Read, false),
+                ("/scratch", super::FsAccess::Write, true),
+                ("/scratch/secret", super::FsAccess::None, false),
+            ]
+        );
+
+        assert!(parse(
+            r#"
+                [sandbox]
+                fs_rules = [{ path = "/etc", access = "execute" }]
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn scratch_dir() {
+        let config = parse(
+            r#"
+                [sandbox]
+                scratch_dir = true
+
+                [pkg.a.build]
+                allow_unsafe = true
+
+                [pkg.b.build.sandbox]
+                scratch_dir = false
+            "#,
+        )
+        .unwrap();
+        let scratch_dir = |perm_sel: PermSel| {
+            config
+                .permissions
+                .sandbox_config_for_package(&perm_sel)
+                .scratch_dir
+        };
+        assert_eq!(scratch_dir(PermSel::for_build_script("a")), Some(true));
+        assert_eq!(scratch_dir(PermSel::for_build_script("b")), Some(false));
+    }
+
+    #[test]
+    fn trusted_capability_manifests() {
+        let manifests = [(
+            "foo",
+            r#"
+                version = 1
+                allow_apis = ["net"]
+                allow_unsafe = true
+
+                [build]
+                allow_apis = ["fs"]
+            "#,
+        )];
+        let cackle = r#"
+            [api.fs]
+            include = ["std::fs"]
+
+            [api.net]
+            include = ["std::net"]
+        "#;
+
+        let untrusted = parse_with_capability_manifests(cackle, &manifests).unwrap();
+        let foo = untrusted.permissions.get(&PermSel::for_primary("foo"));
+        assert_eq!(foo, Some(&PackageConfig::default()));
+
+        let trusted = parse_with_capability_manifests(
+            &format!("trust_capability_manifests = true\n{cackle}"),
+            &manifests,
+        )
+        .unwrap();
+        let foo = trusted
+            .permissions
+            .get(&PermSel::for_primary("foo"))
+            .unwrap();
+        assert_eq!(foo.allow_apis, vec![ApiName::from("net")]);
+        assert!(foo.allow_unsafe);
+        // `foo` doesn't have a build script, so the build permissions shouldn't be applied.
+        assert!(trusted
+            .permissions
+            .get(&PermSel::for_build_script("foo"))
+            .is_none());
+        // Trusted manifests shouldn't show up as configuration that the user wrote.
+        assert!(trusted
+            .permissions_no_inheritance
+            .get(&PermSel::for_primary("foo"))
+            .is_none());
+    }
+
+    #[test]
+    fn class_defaults() {
+        let config = parse(
+            r#"
+            first_party = ["my-*"]
+
+            [api.fs]
+            include = ["std::fs"]
+
+            [defaults.first_party]
+            allow_unsafe = true
+
+            [defaults.third_party]
+            allow_apis = ["fs"]
+
+            [pkg.my-core]
+
+            [pkg.serde]
+            "#,
+        )
+        .unwrap();
+        let my_core = config
+            .permissions
+            .get(&PermSel::for_primary("my-core"))
+            .unwrap();
+        assert!(my_core.allow_unsafe);
+        assert!(my_core.allow_apis.is_empty());
+        let serde = config
+            .permissions
+            .get(&PermSel::for_primary("serde"))
+            .unwrap();
+        assert!(!serde.allow_unsafe);
+        assert_eq!(serde.allow_apis, vec![ApiName::from("fs")]);
+        // Defaults shouldn't show up as configuration that the user wrote for the package.
+        assert_eq!(
+            config
+                .permissions_no_inheritance
+                .get(&PermSel::for_primary("serde")),
+            Some(&PackageConfig::default())
+        );
+    }
+
+    #[test]
+    fn bin_and_lib_defaults() {
+        let raw = super::parse_raw(
+            r#"
+            [common]
+            version = 1
+
+            [api.process]
+            include = ["std::process"]
+
+            [defaults.first_party_bin]
+            allow_apis = ["process"]
+
+            [defaults.first_party_lib]
+            allow_unsafe = true
+            "#,
+            None,
+        )
+        .unwrap();
+        let crate_index =
+            crate::crate_index::testing::index_with_workspace_members(&["app", "core"], &["app"]);
+        let config = super::Config::from_raw(raw, &crate_index, Default::default()).unwrap();
+        let app = config
+            .permissions
+            .get(&PermSel::for_primary("app"))
+            .unwrap();
+        assert_eq!(app.allow_apis, vec![ApiName::from("process")]);
+        assert!(!app.allow_unsafe);
+        let core = config
+            .permissions
+            .get(&PermSel::for_primary("core"))
+            .unwrap();
+        assert!(core.allow_apis.is_empty());
+        assert!(core.allow_unsafe);
+    }
+
+    #[test]
+    fn package_patterns() {
+        let config = parse(
+            r#"
+            [api.fs]
+            include = ["std::fs"]
+
+            [api.net]
+            include = ["std::net"]
+
+            [pkg."windows-*"]
+            allow_unsafe = true
+
+            [pkg."regex(^tokio-)"]
+            allow_apis = ["net"]
+
+            [pkg.windows-sys]
+            allow_apis = ["fs"]
+
+            [pkg.tokio-util]
+
+            [pkg.my-tokio-thing]
+            "#,
+        )
+        .unwrap();
+        let get = |name: &str| config.permissions.get(&PermSel::for_primary(name)).unwrap();
+        assert!(get("windows-sys").allow_unsafe);
+        assert_eq!(get("windows-sys").allow_apis, vec![ApiName::from("fs")]);
+        assert!(!get("tokio-util").allow_unsafe);
+        assert_eq!(get("tokio-util").allow_apis, vec![ApiName::from("net")]);
+        assert!(get("my-tokio-thing").allow_apis.is_empty());
+
+        assert!(parse(
+            r#"
+            [pkg."unmatched-*"]
+            allow_unsafe = true
+
+            [pkg.foo]
+            "#,
+        )
+        .is_err());
+        assert!(parse(
+            r#"
+            [pkg."regex(()"]
+            allow_unsafe = true
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_matches("my-*", "my-crate"));
+        assert!(glob_matches("my-*", "my-"));
+        assert!(!glob_matches("my-*", "other"));
+        assert!(glob_matches("*-sys", "openssl-sys"));
+        assert!(glob_matches("a*b*c", "a-b-b-c"));
+        assert!(!glob_matches("a*b*c", "a-c-b"));
+        assert!(glob_matches("exact", "exact"));
+        assert!(!glob_matches("exact", "exactly"));
+        assert!(glob_matches("*", "anything"));
+    }
+
+    #[test]
+    fn downgrade_inheritance() {
+        let config = parse(
+            r#"
+                [pkg.a]
+                downgrade = ["off_tree_api_usage"]
+                build.downgrade = ["possible_exported_api"]
+            "#,
+        )
+        .unwrap();
+
+        let build = config
+            .permissions
+            .get(&PermSel::for_build_script("a"))
+            .unwrap();
+        assert_eq!(
+            build.downgrade,
+            vec![
+                DowngradableProblem::OffTreeApiUsage,
+                DowngradableProblem::PossibleExportedApi
+            ]
+        );
+        assert!(parse(
+            r#"
+                [pkg.a]
+                downgrade = ["disallowed_api_usage"]
+            "#,
+        )
+        .is_err());
+    }
+
     #[test]
     fn duplicate_allow_api() {
         let result = parse(
@@ -599,4 +1619,106 @@ mod tests {
         assert!(parse("[pkg.x.test.dep]").is_err());
         assert!(parse("[pkg.x.test.test]").is_err());
     }
+
+    #[test]
+    fn ui_config() {
+        let config = parse(
+            r#"
+            [ui]
+            theme = "high-contrast"
+            syntax_highlighting = false
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.raw.ui.theme, Some(super::UiTheme::HighContrast));
+        assert_eq!(config.raw.ui.syntax_highlighting, Some(false));
+        assert!(parse("[ui]\ntheme = \"purple\"").is_err());
+    }
+
+    #[test]
+    fn config_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, contents: &str| {
+            std::fs::write(dir.path().join(name), contents).unwrap();
+        };
+        let cackle_path = dir.path().join("cackle.toml");
+        write(
+            "cackle.toml",
+            r#"
+            [common]
+            version = 2
+            import_std = ["fs"]
+
+            [sandbox]
+            kind = "Bubblewrap"
+            pass_env = ["A"]
+
+            [pkg.foo]
+            allow_apis = ["fs"]
+            "#,
+        );
+        write(
+            "cackle.ci.toml",
+            r#"
+            [sandbox]
+            pass_env = ["B"]
+            "#,
+        );
+        write(
+            "cackle.local.toml",
+            r#"
+            [sandbox]
+            kind = "Disabled"
+            "#,
+        );
+
+        let paths = super::layer_paths(&cackle_path, &["ci".to_owned()]).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                dir.path().join("cackle.ci.toml"),
+                dir.path().join("cackle.local.toml")
+            ]
+        );
+        assert!(super::layer_paths(&cackle_path, &["missing".to_owned()]).is_err());
+
+        let cackle = std::fs::read_to_string(&cackle_path).unwrap();
+        let config = super::parse_layered(&cackle_path, &cackle, &paths, None).unwrap();
+        // Values in later layers take precedence, but tables are merged.
+        assert_eq!(config.sandbox.kind, Some(SandboxKind::Disabled));
+        assert_eq!(config.sandbox.pass_env, vec!["B".to_owned()]);
+        assert_eq!(
+            config.packages[&PackageName::from("foo")].allow_apis,
+            vec![ApiName::from("fs")]
+        );
+        assert!(config.apis.contains_key(&ApiName::from("fs")));
+    }
+
+    #[test]
+    fn interpolate_env_vars() {
+        let get_var = |var: &str| match var {
+            "HOME" => Some("/home/u".to_owned()),
+            "XDG_RUNTIME_DIR" => Some("/run/user/1000".to_owned()),
+            _ => None,
+        };
+        let interpolate = |path: &str| super::interpolate_env_vars(Path::new(path), get_var);
+        assert_eq!(
+            interpolate("${HOME}/.cache/foo").unwrap(),
+            Path::new("/home/u/.cache/foo")
+        );
+        assert_eq!(
+            interpolate("${XDG_RUNTIME_DIR}").unwrap(),
+            Path::new("/run/user/1000")
+        );
+        assert_eq!(
+            interpolate("relative/dir").unwrap(),
+            Path::new("relative/dir")
+        );
+        // Not permitted.
+        assert!(interpolate("${SECRET_TOKEN}/x").is_err());
+        // Permitted, but not set.
+        assert!(interpolate("${XDG_CACHE_HOME}/x").is_err());
+        assert!(interpolate("$HOME/x").is_err());
+        assert!(interpolate("${HOME").is_err());
+    }
 }
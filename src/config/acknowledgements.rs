@@ -0,0 +1,57 @@
+//! Individual API usages that have been reviewed and accepted without granting the package the API.
+//! Each acknowledgement is keyed by package, API, source file and the name that was referenced, so
+//! any other usage of the API by the package still needs to be reviewed. e.g.
+//!
+//! ```toml
+//! [[acknowledgements]]
+//! pkg = "foo"
+//! api = "net"
+//! path = "src/client.rs"
+//! symbol = "std::net::TcpStream::connect"
+//! ```
+
+use super::permissions::PermSel;
+use super::ApiName;
+use crate::checker::ApiUsage;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Acknowledgement {
+    /// The package selector, e.g. `foo` or `foo.build`.
+    pub(crate) pkg: String,
+
+    pub(crate) api: ApiName,
+
+    /// The source file containing the usage. Relative to the package's directory if the file is
+    /// within it.
+    pub(crate) path: PathBuf,
+
+    /// The name that the usage referenced, e.g. `std::net::TcpStream::connect`.
+    pub(crate) symbol: String,
+}
+
+impl Acknowledgement {
+    /// Returns the package selector that this acknowledgement applies to.
+    pub(crate) fn perm_sel(&self) -> PermSel {
+        PermSel::parse(&self.pkg)
+    }
+
+    /// Returns whether this acknowledges `usage`, by a package whose source is in `pkg_dir`. The
+    /// caller is responsible for checking that the package selector and API match.
+    pub(crate) fn matches(&self, pkg_dir: Option<&Path>, usage: &ApiUsage) -> bool {
+        self.symbol == usage.to_name.to_string()
+            && self.path == usage_path(pkg_dir, usage.source_location.filename())
+    }
+}
+
+/// Returns the path that an acknowledgement of a usage in `filename` should have. This is relative
+/// to `pkg_dir` if the file is within it, so that it doesn't depend on where the package is.
+pub(crate) fn usage_path<'a>(pkg_dir: Option<&Path>, filename: &'a Path) -> &'a Path {
+    pkg_dir
+        .and_then(|pkg_dir| filename.strip_prefix(pkg_dir).ok())
+        .unwrap_or(filename)
+}
@@ -0,0 +1,490 @@
+//! Records who approved each permission grant. Approvals are stored in an `[approvals]` side-table
+//! keyed by the grant, e.g. `[approvals."pkg.foo.allow_apis.net"]`, so that they survive edits to
+//! the grants themselves and can be reviewed alongside them.
+
+use super::expiry;
+use super::DefaultsConfig;
+use super::PackageConfig;
+use super::PackageName;
+use super::SandboxConfig;
+use crate::config::Config;
+use crate::config_editor::ConfigEditor;
+use crate::config_editor::Edit;
+use crate::config_editor::EditOpts;
+use anyhow::bail;
+use anyhow::Result;
+use clap::Parser;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use toml::value::Datetime;
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Approval {
+    /// The people who reviewed and approved the grant.
+    #[serde(default)]
+    pub(crate) approved_by: Vec<String>,
+
+    /// A reference to where the grant was discussed, e.g. an issue or ticket number.
+    #[serde(default)]
+    pub(crate) ticket: Option<String>,
+
+    /// The date after which the approval is no longer valid and the grant needs to be reviewed
+    /// again.
+    #[serde(default)]
+    pub(crate) expires: Option<Datetime>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct ApprovalsOptions {
+    /// Only report grants that lack a valid approval and approvals that no longer match a grant.
+    /// Exits with an error if there are any.
+    #[clap(long)]
+    check: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ApprovalIssue {
+    Missing(String),
+    TooFewApprovers {
+        grant: String,
+        approvers: usize,
+        required: u32,
+    },
+    Expired {
+        grant: String,
+        expires: Datetime,
+    },
+    InvalidExpiry {
+        grant: String,
+        expires: Datetime,
+    },
+    Stale(String),
+}
+
+/// The subset of the config that can grant permissions. Unlike `RawConfig`, parsing this doesn't
+/// require the rest of the config to be valid, which lets us compare the grants in partial configs
+/// before and after an edit.
+#[derive(Deserialize, Default)]
+struct Grants {
+    #[serde(default, rename = "pkg")]
+    packages: BTreeMap<PackageName, PackageConfig>,
+
+    #[serde(default)]
+    defaults: DefaultsConfig,
+
+    #[serde(default)]
+    sandbox: SandboxConfig,
+}
+
+/// Prints the approval status of each grant in `config`, or with `--check`, just the problems.
+/// Returns whether there were no problems.
+pub(crate) fn report(config: &Config, options: &ApprovalsOptions) -> bool {
+    let issues = issues(config, options.check, expiry::today());
+    if !options.check {
+        let grants = grant_entries(
+            &config.raw.packages,
+            &config.raw.defaults,
+            &config.raw.sandbox,
+        );
+        for grant in &grants {
+            match config.raw.approvals.get(grant) {
+                Some(approval) => println!("{grant}: {approval}"),
+                None => println!("{grant}: not approved"),
+            }
+        }
+    }
+    for issue in &issues {
+        println!("{issue}");
+    }
+    if options.check && issues.is_empty() {
+        println!("All grants are approved");
+    }
+    issues.is_empty()
+}
+
+/// Returns problems with the approvals in `config`. Grants without approvals are only problems if
+/// approvals are required, or if `force` is set, in which case at least one approver is required.
+pub(crate) fn issues(config: &Config, force: bool, today: Datetime) -> Vec<ApprovalIssue> {
+    let required = match config.raw.common.required_approvals {
+        Some(required) => required,
+        None if force => 1,
+        None => 0,
+    };
+    let grants = grant_entries(
+        &config.raw.packages,
+        &config.raw.defaults,
+        &config.raw.sandbox,
+    );
+    let mut issues = Vec::new();
+    for grant in &grants {
+        let Some(approval) = config.raw.approvals.get(grant) else {
+            if required > 0 {
+                issues.push(ApprovalIssue::Missing(grant.clone()));
+            }
+            continue;
+        };
+        if approval.approved_by.len() < required as usize {
+            issues.push(ApprovalIssue::TooFewApprovers {
+                grant: grant.clone(),
+                approvers: approval.approved_by.len(),
+                required,
+            });
+        }
+        if let Some(expires) = approval.expires {
+            match (expires.date, expires.time) {
+                (Some(_), None) if expiry::is_expired(&expires, &today) => {
+                    issues.push(ApprovalIssue::Expired {
+                        grant: grant.clone(),
+                        expires,
+                    });
+                }
+                (Some(_), None) => {}
+                _ => issues.push(ApprovalIssue::InvalidExpiry {
+                    grant: grant.clone(),
+                    expires,
+                }),
+            }
+        }
+    }
+    for grant in config.raw.approvals.keys() {
+        if !grants.contains(grant) {
+            issues.push(ApprovalIssue::Stale(grant.clone()));
+        }
+    }
+    issues
+}
+
+/// Applies `edit`, which was chosen to fix `problem`, then records `approval`, if any, against each
+/// grant that the edit added. The edit is recorded so that it's added to the audit log when the
+/// editor is written.
+pub(crate) fn apply_edit(
+    editor: &mut ConfigEditor,
+    problem: &str,
+    edit: &dyn Edit,
+    opts: &EditOpts,
+    approval: Option<&Approval>,
+) -> Result<()> {
+    let before = editor.to_toml();
+    edit.apply(editor, opts)?;
+    if let Some(approval) = approval {
+        let after = grants_in_toml(&editor.to_toml())?;
+        for grant in after.difference(&grants_in_toml(&before)?) {
+            editor.set_approval(grant, approval)?;
+        }
+    }
+    editor.record_edit(problem, edit, &before);
+    Ok(())
+}
+
+/// Returns whether applying `edit` to `editor` would add any grants. `editor` isn't modified.
+pub(crate) fn edit_adds_grants(editor: &ConfigEditor, edit: &dyn Edit) -> Result<bool> {
+    let before = grants_in_toml(&editor.to_toml())?;
+    let mut editor = editor.clone();
+    edit.apply(&mut editor, &EditOpts::default())?;
+    let after = grants_in_toml(&editor.to_toml())?;
+    Ok(after.difference(&before).next().is_some())
+}
+
+/// Parses a comma-separated list of approvers, at least one of which is required.
+pub(crate) fn parse_approvers(input: &str) -> Result<Vec<String>> {
+    let approvers: Vec<String> = input
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_owned)
+        .collect();
+    if approvers.is_empty() {
+        bail!("At least one approver is required");
+    }
+    Ok(approvers)
+}
+
+/// Parses an optional expiry date such as `2025-06-30`.
+pub(crate) fn parse_expiry(input: &str) -> Result<Option<Datetime>> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(None);
+    }
+    match input.parse::<Datetime>() {
+        Ok(expires) if expires.date.is_some() && expires.time.is_none() => Ok(Some(expires)),
+        _ => bail!("Expected a date like 2025-06-30, got `{input}`"),
+    }
+}
+
+fn grants_in_toml(toml: &str) -> Result<BTreeSet<String>> {
+    let grants: Grants = toml::from_str(toml)?;
+    Ok(grant_entries(
+        &grants.packages,
+        &grants.defaults,
+        &grants.sandbox,
+    ))
+}
+
+/// Returns the names of all grants of permissions in the supplied config.
+fn grant_entries(
+    packages: &BTreeMap<PackageName, PackageConfig>,
+    defaults: &DefaultsConfig,
+    sandbox: &SandboxConfig,
+) -> BTreeSet<String> {
+    let mut grants = BTreeSet::new();
+    for (name, pkg_config) in packages {
+        add_grants(&format!("pkg.{name}"), pkg_config, &mut grants);
+    }
+    for (name, pkg_config) in defaults.tables() {
+        add_grants(&format!("defaults.{name}"), pkg_config, &mut grants);
+    }
+    if sandbox.allow_network == Some(true) {
+        grants.insert("sandbox.allow_network".to_owned());
+    }
+    grants
+}
+
+fn add_grants(prefix: &str, pkg_config: &PackageConfig, grants: &mut BTreeSet<String>) {
+    for api in pkg_config
+        .allow_apis
+        .iter()
+        .chain(pkg_config.allow_apis_until.keys())
+    {
+        grants.insert(format!("{prefix}.allow_apis.{api}"));
+    }
+    if pkg_config.allow_unsafe {
+        grants.insert(format!("{prefix}.allow_unsafe"));
+    }
+    for glob in &pkg_config.allow_unsafe_in {
+        grants.insert(format!("{prefix}.allow_unsafe_in.{glob}"));
+    }
+    if pkg_config.allow_proc_macro {
+        grants.insert(format!("{prefix}.allow_proc_macro"));
+    }
+    for instruction in &pkg_config.allow_build_instructions {
+        grants.insert(format!("{prefix}.allow_build_instructions.{instruction}"));
+    }
+    if pkg_config.sandbox.allow_network == Some(true) {
+        grants.insert(format!("{prefix}.sandbox.allow_network"));
+    }
+    let nested = [
+        ("build", pkg_config.build.as_deref()),
+        ("test", pkg_config.test.as_deref()),
+        ("proc-macro", pkg_config.proc_macro.as_deref()),
+        (
+            "from.build",
+            pkg_config
+                .from
+                .as_ref()
+                .and_then(|from| from.build.as_deref()),
+        ),
+        (
+            "from.test",
+            pkg_config
+                .from
+                .as_ref()
+                .and_then(|from| from.test.as_deref()),
+        ),
+    ];
+    for (selector, nested_config) in nested {
+        if let Some(nested_config) = nested_config {
+            add_grants(&format!("{prefix}.{selector}"), nested_config, grants);
+        }
+    }
+}
+
+impl Display for Approval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.approved_by.is_empty() {
+            write!(f, "no approvers")?;
+        } else {
+            write!(f, "approved by {}", self.approved_by.join(", "))?;
+        }
+        if let Some(ticket) = &self.ticket {
+            write!(f, "; ticket {ticket}")?;
+        }
+        if let Some(expires) = &self.expires {
+            write!(f, "; expires {expires}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ApprovalIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApprovalIssue::Missing(grant) => write!(f, "`{grant}` hasn't been approved"),
+            ApprovalIssue::TooFewApprovers {
+                grant,
+                approvers,
+                required,
+            } => write!(
+                f,
+                "`{grant}` has {approvers} approver(s), but {required} are required"
+            ),
+            ApprovalIssue::Expired { grant, expires } => {
+                write!(f, "Approval of `{grant}` expired on {expires}")
+            }
+            ApprovalIssue::InvalidExpiry { grant, expires } => write!(
+                f,
+                "Approval of `{grant}` has expiry `{expires}`, which should be a date like 2025-06-30"
+            ),
+            ApprovalIssue::Stale(grant) => write!(
+                f,
+                "`[approvals.\"{grant}\"]` doesn't match any grant and should be removed"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_editor::ConfigEditor;
+    use std::borrow::Cow;
+
+    struct AllowNet;
+
+    impl Edit for AllowNet {
+        fn title(&self) -> String {
+            "Allow net".to_owned()
+        }
+
+        fn id(&self) -> String {
+            "allow-net".to_owned()
+        }
+
+        fn help(&self) -> Cow<'static, str> {
+            "".into()
+        }
+
+        fn apply(&self, editor: &mut ConfigEditor, _opts: &EditOpts) -> Result<()> {
+            let table = editor.table(["pkg", "foo"].into_iter())?;
+            table.insert(
+                "allow_apis",
+                toml_edit::value(toml_edit::Array::from_iter(["fs", "net"])),
+            );
+            Ok(())
+        }
+    }
+
+    fn date(s: &str) -> Datetime {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn approval_issues() {
+        let config = crate::config::testing::parse(
+            r#"
+            required_approvals = 2
+
+            [api.net]
+            include = ["std::net"]
+
+            [api.fs]
+            include = ["std::fs"]
+
+            [pkg.foo]
+            allow_apis = ["net", "fs"]
+            build.allow_unsafe = true
+
+            [approvals."pkg.foo.allow_apis.net"]
+            approved_by = ["alice", "bob"]
+            ticket = "SEC-12"
+            expires = 2030-01-01
+
+            [approvals."pkg.foo.allow_apis.fs"]
+            approved_by = ["alice"]
+            expires = 2020-01-01
+
+            [approvals."pkg.bar.allow_unsafe"]
+            approved_by = ["alice", "bob"]
+            "#,
+        )
+        .unwrap();
+        let issues = issues(&config, false, date("2024-05-01"));
+        assert_eq!(
+            issues,
+            vec![
+                ApprovalIssue::TooFewApprovers {
+                    grant: "pkg.foo.allow_apis.fs".to_owned(),
+                    approvers: 1,
+                    required: 2
+                },
+                ApprovalIssue::Expired {
+                    grant: "pkg.foo.allow_apis.fs".to_owned(),
+                    expires: date("2020-01-01")
+                },
+                ApprovalIssue::Missing("pkg.foo.build.allow_unsafe".to_owned()),
+                ApprovalIssue::Stale("pkg.bar.allow_unsafe".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn approvals_only_checked_when_required_or_forced() {
+        let config = crate::config::testing::parse(
+            r#"
+            [pkg.foo]
+            allow_unsafe = true
+            "#,
+        )
+        .unwrap();
+        assert!(issues(&config, false, date("2024-05-01")).is_empty());
+        assert_eq!(
+            issues(&config, true, date("2024-05-01")),
+            vec![ApprovalIssue::Missing("pkg.foo.allow_unsafe".to_owned())]
+        );
+    }
+
+    #[test]
+    fn record_approval_for_added_grants() {
+        let mut editor = ConfigEditor::from_toml_string(
+            r#"
+            [pkg.foo]
+            allow_apis = ["fs"]
+            "#,
+        )
+        .unwrap();
+        let edit: Box<dyn Edit> = Box::new(AllowNet);
+        assert!(edit_adds_grants(&editor, edit.as_ref()).unwrap());
+        let approval = Approval {
+            approved_by: vec!["alice".to_owned(), "bob".to_owned()],
+            ticket: Some("SEC-12".to_owned()),
+            expires: Some(date("2030-01-01")),
+        };
+        apply_edit(
+            &mut editor,
+            "foo uses net",
+            edit.as_ref(),
+            &EditOpts::default(),
+            Some(&approval),
+        )
+        .unwrap();
+        assert!(!edit_adds_grants(&editor, edit.as_ref()).unwrap());
+        #[derive(Deserialize)]
+        struct Approvals {
+            approvals: BTreeMap<String, Approval>,
+        }
+        let recorded: Approvals = toml::from_str(&editor.to_toml()).unwrap();
+        assert_eq!(
+            recorded.approvals,
+            BTreeMap::from([("pkg.foo.allow_apis.net".to_owned(), approval)])
+        );
+    }
+
+    #[test]
+    fn parse_inputs() {
+        assert_eq!(
+            parse_approvers(" alice, bob ,").unwrap(),
+            vec!["alice".to_owned(), "bob".to_owned()]
+        );
+        assert!(parse_approvers(" , ").is_err());
+        assert_eq!(parse_expiry("").unwrap(), None);
+        assert_eq!(
+            parse_expiry("2025-06-30").unwrap(),
+            Some(date("2025-06-30"))
+        );
+        assert!(parse_expiry("2025-06-30T10:00:00").is_err());
+        assert!(parse_expiry("next week").is_err());
+    }
+}
@@ -1,61 +1,466 @@
+//! Definitions of the APIs that can be imported from the standard library with `import_std`. Each
+//! path records the version of Rust in which it was stabilised, so that the definitions we use
+//! match the toolchain that's building the code being checked.
+//!
+//! Some APIs also cover widely used crates and C library functions that do the same thing as the
+//! standard library, since otherwise these would be an easy way around the standard library
+//! definitions. For example, `fs` covers memory-mapping files with `memmap2` and calling `open`
+//! directly. Similarly, `lifecycle-hooks` covers registering constructors via link sections such as
+//! `.init_array`, which is how the `ctor` crate works.
+
 use super::ApiConfig;
 use super::ApiName;
 use super::ApiPath;
+use log::info;
 use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The version of rustc in the sysroot. Only the major and minor versions are recorded, since
+/// patch releases don't stabilise new APIs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct RustcVersion {
+    major: u64,
+    minor: u64,
+}
+
+struct BuiltInApi {
+    name: &'static str,
+    include: &'static [StdPath],
+    exclude: &'static [StdPath],
+    native_symbols: &'static [&'static str],
+    link_sections: &'static [&'static str],
+}
+
+/// A path in the standard library together with the version of Rust in which it became available.
+/// Paths in other crates are treated as always available.
+struct StdPath {
+    path: &'static str,
+    since: RustcVersion,
+}
 
+const fn path(path: &'static str) -> StdPath {
+    since(1, 0, path)
+}
+
+const fn since(major: u64, minor: u64, path: &'static str) -> StdPath {
+    StdPath {
+        path,
+        since: RustcVersion { major, minor },
+    }
+}
+
+/// Paths within each list are kept sorted, since that's the order in which they're written when a
+/// built-in API is inlined into a user's config.
+const BUILT_INS: &[BuiltInApi] = &[
+    BuiltInApi {
+        name: "fs",
+        include: &[
+            path("memmap"),
+            path("memmap2"),
+            path("nix::dir"),
+            path("nix::fcntl::open"),
+            path("nix::fcntl::openat"),
+            path("rustix::fs"),
+            path("std::fs"),
+            since(1, 87, "std::io::PipeReader"),
+            since(1, 87, "std::io::PipeWriter"),
+            since(1, 87, "std::io::pipe"),
+            since(1, 66, "std::os::fd"),
+            since(1, 1, "std::os::linux::fs"),
+            path("std::os::unix::fs"),
+            path("std::os::unix::io"),
+            path("std::os::wasi::fs"),
+            path("std::os::wasi::io"),
+            path("std::os::windows::fs"),
+            path("std::os::windows::io"),
+            path("std::path"),
+        ],
+        // Anonymous mappings don't involve any files.
+        exclude: &[
+            path("memmap2::MmapMut::map_anon"),
+            path("memmap2::MmapOptions::map_anon"),
+            path("memmap::MmapMut::map_anon"),
+            path("memmap::MmapOptions::map_anon"),
+        ],
+        // C library functions that access the filesystem by path. Functions that only operate on an
+        // already open file descriptor, such as `mmap`, aren't included, since the descriptor had
+        // to come from somewhere. `__xstat` and friends are what older versions of glibc call
+        // `stat`.
+        native_symbols: &[
+            "__lxstat",
+            "__lxstat64",
+            "__xstat",
+            "__xstat64",
+            "access",
+            "chdir",
+            "chmod",
+            "chown",
+            "chroot",
+            "creat",
+            "creat64",
+            "faccessat",
+            "fchmodat",
+            "fchownat",
+            "fopen",
+            "fopen64",
+            "freopen",
+            "freopen64",
+            "lchown",
+            "link",
+            "linkat",
+            "lstat",
+            "lstat64",
+            "mkdir",
+            "mkdirat",
+            "mkfifo",
+            "mknod",
+            "mknodat",
+            "open",
+            "open64",
+            "openat",
+            "openat64",
+            "opendir",
+            "readlink",
+            "readlinkat",
+            "realpath",
+            "rename",
+            "renameat",
+            "renameat2",
+            "rmdir",
+            "stat",
+            "stat64",
+            "statx",
+            "symlink",
+            "symlinkat",
+            "truncate",
+            "truncate64",
+            "unlink",
+            "unlinkat",
+            "utime",
+            "utimensat",
+            "utimes",
+        ],
+        link_sections: &[],
+    },
+    BuiltInApi {
+        name: "env",
+        include: &[path("std::env")],
+        exclude: &[],
+        native_symbols: &[],
+        link_sections: &[],
+    },
+    BuiltInApi {
+        name: "env-mutate",
+        // Changes to the environment are inherited by child processes, so can alter what they do,
+        // e.g. via `PATH` or `LD_PRELOAD`. These paths are also covered by `env` and `process`
+        // respectively, so this is for packages that we're happy to have read the environment or
+        // run commands, but not modify what those commands see.
+        include: &[
+            path("std::env::remove_var"),
+            path("std::env::set_var"),
+            path("std::process::Command::env"),
+            path("std::process::Command::env_clear"),
+            path("std::process::Command::env_remove"),
+            since(1, 19, "std::process::Command::envs"),
+        ],
+        exclude: &[],
+        native_symbols: &[],
+        link_sections: &[],
+    },
+    BuiltInApi {
+        name: "net",
+        include: &[
+            since(1, 77, "core::net"),
+            path("std::net"),
+            since(1, 70, "std::os::linux::net"),
+            path("std::os::wasi::net"),
+            path("std::os::windows::net"),
+        ],
+        exclude: &[],
+        native_symbols: &[],
+        link_sections: &[],
+    },
+    BuiltInApi {
+        name: "unix_sockets",
+        include: &[since(1, 10, "std::os::unix::net")],
+        exclude: &[],
+        native_symbols: &[],
+        link_sections: &[],
+    },
+    BuiltInApi {
+        name: "process",
+        include: &[
+            path("std::os::unix::process"),
+            path("std::os::windows::process"),
+            path("std::process"),
+        ],
+        exclude: &[path("std::process::abort"), path("std::process::exit")],
+        native_symbols: &[],
+        link_sections: &[],
+    },
+    BuiltInApi {
+        name: "terminate",
+        // Panic hooks are included since they can change what happens when any code panics, e.g. to
+        // exit the process. `std::panic` re-exports them from `std::panicking`, which is the name
+        // that appears in binaries. `no_std` code has no `std::process`, so aborts via `core` or
+        // `alloc` instead.
+        include: &[
+            since(1, 28, "alloc::alloc::handle_alloc_error"),
+            path("core::intrinsics::abort"),
+            since(1, 10, "std::panicking::set_hook"),
+            since(1, 10, "std::panicking::take_hook"),
+            path("std::process::abort"),
+            path("std::process::exit"),
+        ],
+        exclude: &[],
+        native_symbols: &[],
+        link_sections: &[],
+    },
+    BuiltInApi {
+        name: "lifecycle-hooks",
+        // Code that runs outside of `main`, either before it, via a constructor, or after it, at exit
+        // or from a panic hook. Such code is easy to miss when reviewing, since nothing calls it.
+        include: &[
+            since(1, 10, "std::panicking::set_hook"),
+            since(1, 10, "std::panicking::take_hook"),
+        ],
+        exclude: &[],
+        native_symbols: &[
+            "__cxa_atexit",
+            "at_quick_exit",
+            "atexit",
+            "on_exit",
+            "pthread_atfork",
+        ],
+        link_sections: &[
+            ".CRT$XC",
+            ".CRT$XI",
+            ".CRT$XP",
+            ".CRT$XT",
+            ".ctors",
+            ".dtors",
+            ".fini_array",
+            ".init_array",
+            ".preinit_array",
+            "__mod_init_func",
+            "__mod_term_func",
+        ],
+    },
+    BuiltInApi {
+        name: "panic",
+        // Almost all crates can panic, e.g. via `unwrap` or indexing, so this is mostly useful for
+        // finding the few crates that can't.
+        include: &[
+            path("core::panicking"),
+            since(1, 51, "std::panic::panic_any"),
+            since(1, 9, "std::panic::resume_unwind"),
+            path("std::panicking::begin_panic"),
+        ],
+        exclude: &[],
+        native_symbols: &[],
+        link_sections: &[],
+    },
+    BuiltInApi {
+        name: "time",
+        // Most of `std::time` is just arithmetic on durations, so we only include the functions that
+        // read a clock.
+        include: &[
+            path("std::time::Instant::elapsed"),
+            path("std::time::Instant::now"),
+            path("std::time::SystemTime::elapsed"),
+            path("std::time::SystemTime::now"),
+        ],
+        exclude: &[],
+        native_symbols: &[],
+        link_sections: &[],
+    },
+    BuiltInApi {
+        name: "thread",
+        include: &[
+            path("std::os::unix::thread"),
+            path("std::os::windows::thread"),
+            path("std::thread"),
+        ],
+        // Thread-locals and checking whether we're panicking are used by lots of code that never
+        // starts a thread.
+        exclude: &[
+            since(1, 26, "std::thread::AccessError"),
+            path("std::thread::LocalKey"),
+            path("std::thread::local"),
+            since(1, 72, "std::thread::local_impl"),
+            path("std::thread::panicking"),
+        ],
+        native_symbols: &[],
+        link_sections: &[],
+    },
+];
+
+/// Returns all built-in APIs with the paths available in the newest version of Rust that we know
+/// about.
 pub(crate) fn get_built_ins() -> BTreeMap<ApiName, ApiConfig> {
-    let mut result = BTreeMap::new();
-    result.insert(
-        ApiName::from("fs"),
-        perm(
-            &[
-                "std::fs",
-                "std::os::linux::fs",
-                "std::os::unix::fs",
-                "std::os::unix::io",
-                "std::os::wasi::fs",
-                "std::os::wasi::io",
-                "std::os::windows::fs",
-                "std::os::windows::io",
-                "std::path",
-            ],
-            &[],
-        ),
-    );
-    result.insert(ApiName::from("env"), perm(&["std::env"], &[]));
-    result.insert(
-        ApiName::from("net"),
-        perm(
-            &["std::net", "std::os::wasi::net", "std::os::windows::net"],
-            &[],
-        ),
-    );
-    result.insert(
-        ApiName::from("unix_sockets"),
-        perm(&["std::os::unix::net"], &[]),
-    );
-    result.insert(
-        ApiName::from("process"),
-        perm(
-            &[
-                "std::process",
-                "std::unix::process",
-                "std::windows::process",
-            ],
-            &["std::process::abort", "std::process::exit"],
-        ),
-    );
-    result.insert(
-        ApiName::from("terminate"),
-        perm(&["std::process::abort", "std::process::exit"], &[]),
-    );
-    result
-}
-
-fn perm(include: &[&str], exclude: &[&str]) -> ApiConfig {
-    ApiConfig {
-        include: include.iter().map(|s| ApiPath::from_str(s)).collect(),
-        exclude: exclude.iter().map(|s| ApiPath::from_str(s)).collect(),
-        no_auto_detect: Vec::new(),
+    built_ins_for_rustc(None)
+}
+
+/// Returns the built-in APIs with only the paths that are available in `rustc_version`. If the
+/// version isn't known, all paths are included.
+pub(crate) fn built_ins_for_rustc(
+    rustc_version: Option<RustcVersion>,
+) -> BTreeMap<ApiName, ApiConfig> {
+    let paths = |paths: &[StdPath]| -> Vec<ApiPath> {
+        paths
+            .iter()
+            .filter(|p| rustc_version.map_or(true, |version| p.since <= version))
+            .map(|p| ApiPath::from_str(p.path))
+            .collect()
+    };
+    BUILT_INS
+        .iter()
+        .map(|api| {
+            (
+                ApiName::from(api.name),
+                ApiConfig {
+                    include: paths(api.include),
+                    exclude: paths(api.exclude),
+                    no_auto_detect: Vec::new(),
+                    native_symbols: api.native_symbols.iter().map(|s| s.to_string()).collect(),
+                    link_sections: api.link_sections.iter().map(|s| s.to_string()).collect(),
+                },
+            )
+        })
+        .collect()
+}
+
+impl RustcVersion {
+    /// Determines the version of the rustc in `sysroot`. Returns None if it couldn't be determined,
+    /// e.g. because the sysroot doesn't contain rustc.
+    pub(crate) fn from_sysroot(sysroot: &Path) -> Option<Self> {
+        let output = std::process::Command::new(sysroot.join("bin").join("rustc"))
+            .arg("-vV")
+            .output()
+            .ok()?;
+        let version = Self::parse(std::str::from_utf8(&output.stdout).ok()?);
+        if version.is_none() {
+            info!("Couldn't determine rustc version for {}", sysroot.display());
+        }
+        version
+    }
+
+    /// Parses the output of `rustc -vV`.
+    fn parse(verbose_version: &str) -> Option<Self> {
+        let release = verbose_version
+            .lines()
+            .find_map(|line| line.strip_prefix("release: "))?;
+        let mut parts = release.split(['.', '-']);
+        Some(Self {
+            major: parts.next()?.parse().ok()?,
+            minor: parts.next()?.parse().ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rustc_version() {
+        let output = "rustc 1.79.0-nightly (c9f8f3438 2024-03-27)\n\
+            binary: rustc\n\
+            release: 1.79.0-nightly\n\
+            LLVM version: 18.1.2\n";
+        assert_eq!(
+            RustcVersion::parse(output),
+            Some(RustcVersion {
+                major: 1,
+                minor: 79
+            })
+        );
+        assert_eq!(RustcVersion::parse("rustc 1.79.0"), None);
+    }
+
+    #[test]
+    fn paths_filtered_by_version() {
+        let has_path = |version, api: &str, path: &str| {
+            built_ins_for_rustc(version)[&ApiName::new(api)]
+                .include
+                .contains(&ApiPath::from_str(path))
+        };
+        let old = Some(RustcVersion {
+            major: 1,
+            minor: 70,
+        });
+        assert!(has_path(old, "fs", "std::fs"));
+        assert!(!has_path(old, "fs", "std::io::pipe"));
+        assert!(!has_path(old, "net", "core::net"));
+        assert!(has_path(None, "fs", "std::io::pipe"));
+        assert!(has_path(None, "net", "core::net"));
+        assert!(has_path(None, "terminate", "std::panicking::set_hook"));
+        assert!(!has_path(None, "panic", "std::panicking::set_hook"));
+        assert!(!has_path(
+            Some(RustcVersion {
+                major: 1,
+                minor: 18
+            }),
+            "env-mutate",
+            "std::process::Command::envs"
+        ));
+        assert!(has_path(old, "env-mutate", "std::env::set_var"));
+        assert!(has_path(old, "fs", "memmap2"));
+        assert!(built_ins_for_rustc(old)[&ApiName::new("fs")]
+            .native_symbols
+            .contains(&"open".to_owned()));
+        let lifecycle_hooks = &built_ins_for_rustc(old)[&ApiName::new("lifecycle-hooks")];
+        assert!(lifecycle_hooks
+            .native_symbols
+            .contains(&"atexit".to_owned()));
+        assert!(lifecycle_hooks
+            .link_sections
+            .contains(&".init_array".to_owned()));
+    }
+
+    #[test]
+    fn terminate_and_panic() {
+        let built_ins = get_built_ins();
+        let terminate = &built_ins[&ApiName::new("terminate")].include;
+        let panic = &built_ins[&ApiName::new("panic")].include;
+        for path in [
+            "alloc::alloc::handle_alloc_error",
+            "core::intrinsics::abort",
+            "std::process::abort",
+            "std::process::exit",
+            "std::panicking::set_hook",
+            "std::panicking::take_hook",
+        ] {
+            assert!(terminate.contains(&ApiPath::from_str(path)), "{path}");
+            assert!(!panic.contains(&ApiPath::from_str(path)), "{path}");
+        }
+        for path in [
+            "core::panicking",
+            "std::panic::panic_any",
+            "std::panic::resume_unwind",
+            "std::panicking::begin_panic",
+        ] {
+            assert!(panic.contains(&ApiPath::from_str(path)), "{path}");
+            assert!(!terminate.contains(&ApiPath::from_str(path)), "{path}");
+        }
+    }
+
+    #[test]
+    fn env_mutate() {
+        let built_ins = get_built_ins();
+        let env_mutate = &built_ins[&ApiName::new("env-mutate")].include;
+        for path in [
+            "std::env::set_var",
+            "std::env::remove_var",
+            "std::process::Command::env",
+            "std::process::Command::envs",
+            "std::process::Command::env_clear",
+            "std::process::Command::env_remove",
+        ] {
+            assert!(env_mutate.contains(&ApiPath::from_str(path)), "{path}");
+        }
+        // Reading the environment isn't mutation.
+        assert!(!env_mutate.contains(&ApiPath::from_str("std::env::var")));
+        assert!(!env_mutate.contains(&ApiPath::from_str("std::env")));
     }
 }
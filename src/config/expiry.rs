@@ -0,0 +1,202 @@
+//! Support for grants of APIs that expire on a particular date, e.g. temporary exceptions while a
+//! dependency is being replaced.
+
+use super::ApiName;
+use super::PackageConfig;
+use super::RawConfig;
+use anyhow::bail;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use toml::value::Date;
+use toml::value::Datetime;
+use toml::value::Offset;
+use toml::value::Time;
+
+/// How far into the future an expired grant is extended by when the user asks to extend it.
+pub(crate) const EXTENSION_DAYS: i64 = 90;
+
+/// An expiry date, which may be written either as a TOML date or as a string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DateValue {
+    Date(Datetime),
+    String(String),
+}
+
+/// Deserialises the values of `allow_apis_until`, accepting both `2025-06-30` and `"2025-06-30"`.
+pub(crate) fn deserialize_dates<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<ApiName, Datetime>, D::Error> {
+    BTreeMap::<ApiName, DateValue>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(api, value)| {
+            let date = match value {
+                DateValue::Date(date) => date,
+                DateValue::String(s) => s.parse().map_err(serde::de::Error::custom)?,
+            };
+            Ok((api, date))
+        })
+        .collect()
+}
+
+/// Moves APIs from `allow_apis_until` either into `allow_apis` or, if they've expired, into
+/// `expired_apis`.
+pub(crate) fn apply(config: &mut RawConfig, today: Datetime) -> Result<()> {
+    for (name, pkg_config) in &mut config.packages {
+        apply_to_package(pkg_config, today, &format!("pkg.{name}"))?;
+    }
+    for (name, pkg_config) in config.defaults.tables_mut() {
+        apply_to_package(pkg_config, today, &format!("defaults.{name}"))?;
+    }
+    Ok(())
+}
+
+fn apply_to_package(pkg_config: &mut PackageConfig, today: Datetime, table: &str) -> Result<()> {
+    for (api, until) in &pkg_config.allow_apis_until {
+        if until.date.is_none() || until.time.is_some() {
+            bail!(
+                "`{table}.allow_apis_until.{api}` should be a date like 2025-06-30, got `{until}`"
+            );
+        }
+        if is_expired(until, &today) {
+            pkg_config.expired_apis.push(api.clone());
+        } else if !pkg_config.allow_apis.contains(api) {
+            pkg_config.allow_apis.push(api.clone());
+        }
+    }
+    let from = pkg_config.from.as_mut();
+    let (from_build, from_test) = match from {
+        Some(from) => (from.build.as_deref_mut(), from.test.as_deref_mut()),
+        None => (None, None),
+    };
+    let nested = [
+        ("build", pkg_config.build.as_deref_mut()),
+        ("test", pkg_config.test.as_deref_mut()),
+        ("proc-macro", pkg_config.proc_macro.as_deref_mut()),
+        ("from.build", from_build),
+        ("from.test", from_test),
+    ];
+    for (selector, nested_config) in nested {
+        if let Some(nested_config) = nested_config {
+            apply_to_package(nested_config, today, &format!("{table}.{selector}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether something that expires at the start of `expires` has expired by `today`.
+pub(crate) fn is_expired(expires: &Datetime, today: &Datetime) -> bool {
+    date_parts(expires) <= date_parts(today)
+}
+
+/// Returns the current date in UTC.
+pub(crate) fn today() -> Datetime {
+    days_from_now(0)
+}
+
+/// Returns the date `days` days from today.
+pub(crate) fn days_from_now(days: i64) -> Datetime {
+    let today = (crate::timing::unix_now() / 86400) as i64;
+    let (year, month, day) = civil_from_days(today + days);
+    Datetime {
+        date: Some(Date { year, month, day }),
+        time: None,
+        offset: None,
+    }
+}
+
+/// Returns the current date and time in UTC, to the nearest second.
+pub(crate) fn now() -> Datetime {
+    let seconds = crate::timing::unix_now() as i64;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let seconds_of_day = seconds.rem_euclid(86400);
+    Datetime {
+        date: Some(Date { year, month, day }),
+        time: Some(Time {
+            hour: (seconds_of_day / 3600) as u8,
+            minute: (seconds_of_day / 60 % 60) as u8,
+            second: (seconds_of_day % 60) as u8,
+            nanosecond: 0,
+        }),
+        offset: Some(Offset::Z),
+    }
+}
+
+fn date_parts(datetime: &Datetime) -> (u16, u8, u8) {
+    datetime
+        .date
+        .map_or((0, 0, 0), |date| (date.year, date.month, date.day))
+}
+
+/// Converts a number of days since 1970-01-01 into a year, month and day. See
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i64) -> (u16, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year as u16, month as u8, day as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expiring_grants() {
+        let config = crate::config::testing::parse(
+            r#"
+            [api.net]
+            include = ["std::net"]
+
+            [api.fs]
+            include = ["std::fs"]
+
+            [api.process]
+            include = ["std::process"]
+
+            [pkg.foo]
+            allow_apis = ["process"]
+            allow_apis_until = { net = 2000-01-01, fs = "2999-12-31" }
+
+            [pkg.foo.build]
+            allow_apis_until.net = 2999-12-31
+            "#,
+        )
+        .unwrap();
+        let foo = &config.raw.packages[&crate::config::PackageName::from("foo")];
+        assert_eq!(
+            foo.allow_apis,
+            [ApiName::new("process"), ApiName::new("fs")]
+        );
+        assert_eq!(foo.expired_apis, [ApiName::new("net")]);
+        let build = foo.build.as_deref().unwrap();
+        assert_eq!(build.allow_apis, [ApiName::new("net")]);
+        assert!(build.expired_apis.is_empty());
+
+        assert!(crate::config::testing::parse(
+            r#"
+            [api.net]
+            include = ["std::net"]
+
+            [pkg.foo]
+            allow_apis_until.net = 2999-12-31T10:00:00
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_723), (2024, 1, 1));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    }
+}
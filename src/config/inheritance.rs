@@ -0,0 +1,386 @@
+//! Explains how the effective configuration for a permission selector is built up by inheritance
+//! and finds settings that inheritance makes redundant or that contradict an inherited setting.
+
+use super::permissions::PermSel;
+use super::Config;
+use super::PackageConfig;
+use crate::config_editor::ConfigEditor;
+use anyhow::Result;
+use std::fmt::Display;
+use std::fmt::Write;
+
+/// Settings that aren't inherited, so can't be shadowed or contradicted by an ancestor.
+const NOT_INHERITED: &[&str] = &["sandbox.pass_env"];
+
+/// A single setting from a package's config. Lists produce a separate setting for each entry.
+#[derive(Debug, PartialEq, Eq)]
+struct Setting {
+    /// The key within the package's config. e.g. `allow_apis` or `sandbox.allow_network`.
+    key: &'static str,
+    /// For list entries, the entry, or in the case of `fs_rules`, the path that the rule is for.
+    entry: Option<String>,
+    /// The value formatted as TOML.
+    value: String,
+}
+
+/// A place from which configuration can be inherited.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Layer {
+    Pkg(PermSel),
+    /// The top-level `[sandbox]` table.
+    Sandbox,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct InheritanceIssue {
+    perm_sel: PermSel,
+    key: &'static str,
+    entry: Option<String>,
+    value: String,
+    from: Layer,
+    kind: IssueKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum IssueKind {
+    /// The setting is the same as what would be inherited anyway.
+    Shadowed,
+    /// The setting overrides a different value set at a less specific scope of the same package.
+    Contradicted { inherited: String },
+}
+
+/// Returns the layers that `perm_sel` inherits from, most specific first, starting with
+/// `perm_sel` itself.
+fn layers(perm_sel: &PermSel) -> Vec<Layer> {
+    let mut layers = vec![Layer::Pkg(perm_sel.clone())];
+    let mut current = perm_sel.clone();
+    while let Some(parent) = current.parent() {
+        layers.push(Layer::Pkg(parent.clone()));
+        current = parent;
+    }
+    layers.push(Layer::Sandbox);
+    layers
+}
+
+fn layer_config(config: &Config, layer: &Layer) -> Option<PackageConfig> {
+    match layer {
+        Layer::Pkg(perm_sel) => config.permissions_no_inheritance.get(perm_sel).cloned(),
+        Layer::Sandbox => Some(PackageConfig {
+            sandbox: config.raw.sandbox.clone(),
+            ..PackageConfig::default()
+        }),
+    }
+}
+
+/// Returns settings that are shadowed or contradicted by settings at less specific scopes.
+pub(crate) fn find_issues(config: &Config) -> Vec<InheritanceIssue> {
+    let mut issues = Vec::new();
+    for (perm_sel, pkg_config) in &config.permissions_no_inheritance.packages {
+        let ancestors: Vec<(Layer, Vec<Setting>)> = layers(perm_sel)
+            .into_iter()
+            .skip(1)
+            .filter_map(|layer| {
+                let settings = settings(&layer_config(config, &layer)?);
+                Some((layer, settings))
+            })
+            .collect();
+        for setting in settings(pkg_config) {
+            if NOT_INHERITED.contains(&setting.key) {
+                continue;
+            }
+            let Some((layer, inherited)) = ancestors.iter().find_map(|(layer, settings)| {
+                settings
+                    .iter()
+                    .find(|s| s.key == setting.key && s.entry == setting.entry)
+                    .map(|s| (layer, s))
+            }) else {
+                continue;
+            };
+            let kind = if inherited.value == setting.value {
+                IssueKind::Shadowed
+            } else if *layer == Layer::Sandbox {
+                // Overriding the global sandbox config for particular packages is expected.
+                continue;
+            } else {
+                IssueKind::Contradicted {
+                    inherited: inherited.value.clone(),
+                }
+            };
+            issues.push(InheritanceIssue {
+                perm_sel: perm_sel.clone(),
+                key: setting.key,
+                entry: setting.entry,
+                value: setting.value,
+                from: layer.clone(),
+                kind,
+            });
+        }
+    }
+    issues.sort_by_cached_key(|issue| (issue.perm_sel.to_string(), issue.key, issue.entry.clone()));
+    issues
+}
+
+/// Returns a human-readable explanation of where the effective configuration for `perm_sel` comes
+/// from.
+pub(crate) fn explain(config: &Config, perm_sel: &PermSel) -> String {
+    let mut out = String::new();
+    let layers = layers(perm_sel);
+    let _ = writeln!(
+        out,
+        "Inheritance for `{perm_sel}`, highest precedence first:"
+    );
+    let layer_settings: Vec<(&Layer, Vec<Setting>)> = layers
+        .iter()
+        .map(|layer| {
+            let settings = layer_config(config, layer)
+                .map(|c| settings(&c))
+                .unwrap_or_default();
+            let plural = if settings.len() == 1 { "" } else { "s" };
+            let _ = writeln!(out, "  {layer} ({} setting{plural})", settings.len());
+            (layer, settings)
+        })
+        .collect();
+    let _ = writeln!(out, "Effective config:");
+    let Some(effective) = config.permissions.get(perm_sel) else {
+        let _ = writeln!(out, "  None. `{perm_sel}` isn't used by any package");
+        return out;
+    };
+    for setting in settings(effective) {
+        let origin = layer_settings
+            .iter()
+            .find(|(_, settings)| settings.contains(&setting))
+            .map(|(layer, _)| format!("`{layer}`"))
+            .unwrap_or_else(|| "defaults, a pattern or a capability manifest".to_owned());
+        let _ = writeln!(out, "  {}  (from {origin})", setting.assignment());
+    }
+    let issues: Vec<InheritanceIssue> = find_issues(config)
+        .into_iter()
+        .filter(|issue| layers.contains(&Layer::Pkg(issue.perm_sel.clone())))
+        .collect();
+    if !issues.is_empty() {
+        let _ = writeln!(out, "Issues:");
+        for issue in issues {
+            let _ = writeln!(out, "  {issue}");
+        }
+    }
+    out
+}
+
+/// Returns the `[pkg]` tables affected by `issues`, as they'd be after removing shadowed settings
+/// from `toml`. Contradictions need a decision as to which value is wanted, so are left as is.
+pub(crate) fn normalized_pkg_config(toml: &str, issues: &[InheritanceIssue]) -> Result<String> {
+    let mut editor = ConfigEditor::from_toml_string(toml)?;
+    for issue in issues {
+        if issue.kind == IssueKind::Shadowed {
+            editor.remove_pkg_setting(&issue.perm_sel, issue.key, issue.entry.as_deref())?;
+        }
+    }
+    let mut pkg_names: Vec<&str> = issues
+        .iter()
+        .map(|issue| issue.perm_sel.package_name.as_ref())
+        .collect();
+    pkg_names.sort();
+    pkg_names.dedup();
+    Ok(editor.pkg_tables_toml(&pkg_names))
+}
+
+impl Setting {
+    fn assignment(&self) -> String {
+        if self.entry.is_some() {
+            format!("{} += {}", self.key, self.value)
+        } else {
+            format!("{} = {}", self.key, self.value)
+        }
+    }
+}
+
+fn settings(config: &PackageConfig) -> Vec<Setting> {
+    fn scalar(out: &mut Vec<Setting>, key: &'static str, value: Option<impl serde::Serialize>) {
+        if let Some(value) = value.and_then(|v| toml_value(&v)) {
+            out.push(Setting {
+                key,
+                entry: None,
+                value,
+            });
+        }
+    }
+
+    fn list<T: serde::Serialize>(out: &mut Vec<Setting>, key: &'static str, values: &[T]) {
+        for value in values {
+            if let Some(value) = toml_value(value) {
+                out.push(Setting {
+                    key,
+                    entry: Some(value.trim_matches('"').to_owned()),
+                    value,
+                });
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    scalar(
+        &mut out,
+        "allow_unsafe",
+        config.allow_unsafe.then_some(true),
+    );
+    list(&mut out, "allow_unsafe_in", &config.allow_unsafe_in);
+    list(&mut out, "allow_apis", &config.allow_apis);
+    list(
+        &mut out,
+        "allow_build_instructions",
+        &config.allow_build_instructions,
+    );
+    scalar(
+        &mut out,
+        "allow_proc_macro",
+        config.allow_proc_macro.then_some(true),
+    );
+    scalar(
+        &mut out,
+        "allow_generated_code",
+        config.allow_generated_code.then_some(true),
+    );
+    list(&mut out, "downgrade", &config.downgrade);
+    let sandbox = &config.sandbox;
+    scalar(&mut out, "sandbox.kind", sandbox.kind);
+    scalar(&mut out, "sandbox.allow_network", sandbox.allow_network);
+    scalar(&mut out, "sandbox.stdin", sandbox.stdin.as_ref());
+    scalar(&mut out, "sandbox.scratch_dir", sandbox.scratch_dir);
+    list(&mut out, "sandbox.extra_args", &sandbox.extra_args);
+    list(&mut out, "sandbox.bind_writable", &sandbox.bind_writable);
+    list(&mut out, "sandbox.make_writable", &sandbox.make_writable);
+    list(&mut out, "sandbox.pass_env", &sandbox.pass_env);
+    for rule in &sandbox.fs_rules {
+        if let Some(value) = toml_value(rule) {
+            out.push(Setting {
+                key: "sandbox.fs_rules",
+                entry: Some(rule.path.display().to_string()),
+                value,
+            });
+        }
+    }
+    if let Some(rustc) = &config.rustc {
+        scalar(&mut out, "rustc.sandbox.kind", rustc.sandbox.kind);
+        scalar(
+            &mut out,
+            "rustc.sandbox.allow_network",
+            rustc.sandbox.allow_network,
+        );
+    }
+    out
+}
+
+fn toml_value(value: &impl serde::Serialize) -> Option<String> {
+    toml::Value::try_from(value).ok().map(|v| v.to_string())
+}
+
+impl Display for Layer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Layer::Pkg(perm_sel) => write!(f, "pkg.{perm_sel}"),
+            Layer::Sandbox => write!(f, "[sandbox]"),
+        }
+    }
+}
+
+impl Display for InheritanceIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            perm_sel,
+            key,
+            value,
+            from,
+            ..
+        } = self;
+        let setting = if self.entry.is_some() {
+            format!("`pkg.{perm_sel}.{key}` entry {value}")
+        } else {
+            format!("`pkg.{perm_sel}.{key} = {value}`")
+        };
+        match &self.kind {
+            IssueKind::Shadowed => write!(
+                f,
+                "{setting} is already inherited from `{from}` and can be removed"
+            ),
+            IssueKind::Contradicted { inherited } => write!(
+                f,
+                "{setting} contradicts {inherited} inherited from `{from}`. Set it in only one \
+                 of these places"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::explain;
+    use super::find_issues;
+    use super::normalized_pkg_config;
+    use crate::config::permissions::PermSel;
+
+    const CONFIG: &str = r#"
+        [sandbox]
+        kind = "Bubblewrap"
+
+        [api.fs]
+        include = ["std::fs"]
+
+        [api.net]
+        include = ["std::net"]
+
+        [pkg.foo]
+        allow_apis = ["fs"]
+        sandbox.allow_network = true
+
+        [pkg.foo.build]
+        allow_apis = ["fs", "net"]
+        sandbox.kind = "Bubblewrap"
+        sandbox.allow_network = false
+
+        [pkg.bar]
+        build.sandbox.kind = "Disabled"
+    "#;
+
+    #[test]
+    fn shadowed_and_contradicted() {
+        let config = crate::config::testing::parse(CONFIG).unwrap();
+        let issues: Vec<String> = find_issues(&config)
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                "`pkg.foo.build.allow_apis` entry \"fs\" is already inherited from `pkg.foo` and \
+                 can be removed",
+                "`pkg.foo.build.sandbox.allow_network = false` contradicts true inherited from \
+                 `pkg.foo`. Set it in only one of these places",
+                "`pkg.foo.build.sandbox.kind = \"Bubblewrap\"` is already inherited from \
+                 `[sandbox]` and can be removed",
+            ]
+        );
+    }
+
+    #[test]
+    fn normalized() {
+        let config = crate::config::testing::parse(CONFIG).unwrap();
+        let toml = format!("[common]\nversion = 1\n{CONFIG}");
+        let normalized = normalized_pkg_config(&toml, &find_issues(&config)).unwrap();
+        assert!(normalized.contains(r#"allow_apis = ["net"]"#));
+        assert!(normalized.contains("sandbox.allow_network = false"));
+        assert!(!normalized.contains("Bubblewrap"));
+        // Packages without issues aren't included.
+        assert!(!normalized.contains("pkg.bar"));
+    }
+
+    #[test]
+    fn explanation() {
+        let config = crate::config::testing::parse(CONFIG).unwrap();
+        let explanation = explain(&config, &PermSel::for_build_script("foo"));
+        assert!(explanation.contains("pkg.foo.from.build"));
+        assert!(explanation.contains("sandbox.kind = \"Bubblewrap\"  (from `pkg.foo.build`)"));
+        assert!(explanation.contains("allow_apis += \"net\"  (from `pkg.foo.build`)"));
+        assert!(explanation.contains("allow_apis += \"fs\"  (from `pkg.foo.build`)"));
+        assert!(explanation.contains("Issues:"));
+    }
+}
@@ -1,16 +1,21 @@
+use super::glob_matches;
 use super::PackageConfig;
 use super::PackageName;
+use super::PackagePattern;
 use super::RawConfig;
 use super::SandboxConfig;
+use crate::capabilities::CapabilityManifest;
 use crate::crate_index::CrateIndex;
 use crate::crate_index::CrateKind;
 use crate::crate_index::CrateSel;
 use crate::crate_index::PackageId;
+use crate::location::SourceLocation;
 use anyhow::Result;
 use fxhash::FxHashMap;
 use serde::Deserialize;
 use serde::Serialize;
 use std::fmt::Display;
+use std::path::Path;
 use std::sync::Arc;
 
 #[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
@@ -40,58 +45,16 @@ pub(crate) enum PermissionScope {
     FromBuild,
     /// Permission is granted to the package, but only when used via tests of other packages.
     FromTest,
+    /// Permission is granted to the proc macro of the specified package, which runs in the compiler
+    /// when building packages that use it.
+    ProcMacro,
 }
 
 impl Permissions {
     pub(crate) fn from_config(config: &RawConfig) -> Self {
         let mut packages = FxHashMap::default();
         for (name, pkg_config) in &config.packages {
-            let mut pkg_config = pkg_config.clone();
-            if let Some(sub_cfg) = pkg_config.build.take() {
-                packages.insert(
-                    PermSel {
-                        package_name: name.clone(),
-                        scope: PermissionScope::Build,
-                    },
-                    *sub_cfg,
-                );
-            }
-            if let Some(sub_cfg) = pkg_config.test.take() {
-                packages.insert(
-                    PermSel {
-                        package_name: name.clone(),
-                        scope: PermissionScope::Test,
-                    },
-                    *sub_cfg,
-                );
-            }
-            if let Some(mut dep) = pkg_config.from.take() {
-                if let Some(sub_cfg) = dep.build.take() {
-                    packages.insert(
-                        PermSel {
-                            package_name: name.clone(),
-                            scope: PermissionScope::FromBuild,
-                        },
-                        *sub_cfg,
-                    );
-                }
-                if let Some(sub_cfg) = dep.test.take() {
-                    packages.insert(
-                        PermSel {
-                            package_name: name.clone(),
-                            scope: PermissionScope::FromTest,
-                        },
-                        *sub_cfg,
-                    );
-                }
-            }
-            packages.insert(
-                PermSel {
-                    package_name: name.clone(),
-                    scope: PermissionScope::All,
-                },
-                pkg_config,
-            );
+            insert_with_scopes(&mut packages, name, pkg_config);
         }
         Self { packages }
     }
@@ -99,15 +62,130 @@ impl Permissions {
     pub(crate) fn from_config_with_inheritance(
         config: &RawConfig,
         crate_index: &CrateIndex,
+        capability_manifests: &FxHashMap<PackageId, CapabilityManifest>,
+        package_patterns: &[(PackageName, PackagePattern)],
     ) -> Self {
         let mut new = Self::from_config(config);
         for sel in &crate_index.permission_selectors {
             new.packages.entry(sel.clone()).or_default();
         }
+        new.apply_class_defaults(config, crate_index);
+        new.apply_package_patterns(package_patterns, crate_index);
+        if config.common.trust_capability_manifests {
+            new.apply_capability_manifests(capability_manifests, crate_index);
+        }
         apply_inheritance(&mut new.packages, config);
         new
     }
 
+    /// Grants each package the permissions from `[defaults.first_party]` or
+    /// `[defaults.third_party]` according to the package's class. First-party packages are also
+    /// granted `[defaults.first_party_bin]` or `[defaults.first_party_lib]` according to whether
+    /// they only have binary targets. Packages that are only used via dev-dependencies are also
+    /// granted `common.dev_dependencies_default_allow`.
+    fn apply_class_defaults(&mut self, config: &RawConfig, crate_index: &CrateIndex) {
+        let by_scope = |defaults: &PackageConfig| {
+            let mut packages = FxHashMap::default();
+            insert_with_scopes(&mut packages, &PackageName::from(""), defaults);
+            packages
+                .into_iter()
+                .map(|(perm_sel, config)| (perm_sel.scope, config))
+                .collect::<FxHashMap<PermissionScope, PackageConfig>>()
+        };
+        let first_party = by_scope(&config.defaults.first_party);
+        let third_party = by_scope(&config.defaults.third_party);
+        let first_party_bin = by_scope(&config.defaults.first_party_bin);
+        let first_party_lib = by_scope(&config.defaults.first_party_lib);
+        let dev_only = PackageConfig {
+            allow_apis: config.common.dev_dependencies_default_allow.clone(),
+            ..PackageConfig::default()
+        };
+        let mut is_first_party = FxHashMap::default();
+        for (perm_sel, pkg_config) in &mut self.packages {
+            if !crate_index.permission_selectors.contains(perm_sel) {
+                continue;
+            }
+            let pkg_name = perm_sel.package_name.as_ref();
+            let class_defaults: &[&FxHashMap<PermissionScope, PackageConfig>] = if *is_first_party
+                .entry(perm_sel.package_name.clone())
+                .or_insert_with(|| config.is_first_party(pkg_name, crate_index))
+            {
+                if crate_index.is_bin_only(pkg_name) {
+                    &[&first_party, &first_party_bin]
+                } else {
+                    &[&first_party, &first_party_lib]
+                }
+            } else {
+                &[&third_party]
+            };
+            for defaults in class_defaults {
+                if let Some(defaults) = defaults.get(&perm_sel.scope) {
+                    pkg_config.inherit(defaults);
+                }
+            }
+            if perm_sel.scope == PermissionScope::All
+                && !dev_only.allow_apis.is_empty()
+                && crate_index.is_dev_only(perm_sel.package_name.as_ref())
+            {
+                pkg_config.inherit(&dev_only);
+            }
+        }
+    }
+
+    /// Grants each package the permissions from any `[pkg]` tables with patterns that match the
+    /// package's name.
+    fn apply_package_patterns(
+        &mut self,
+        package_patterns: &[(PackageName, PackagePattern)],
+        crate_index: &CrateIndex,
+    ) {
+        for (pattern_name, pattern) in package_patterns {
+            let pattern_configs: Vec<(PermissionScope, PackageConfig)> = self
+                .packages
+                .iter()
+                .filter(|(perm_sel, _)| perm_sel.package_name == *pattern_name)
+                .map(|(perm_sel, config)| (perm_sel.scope, config.clone()))
+                .collect();
+            for (scope, pattern_config) in pattern_configs {
+                for (perm_sel, pkg_config) in &mut self.packages {
+                    if perm_sel.scope == scope
+                        && crate_index.permission_selectors.contains(perm_sel)
+                        && pattern.matches(perm_sel.package_name.as_ref())
+                    {
+                        pkg_config.inherit(&pattern_config);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Grants each package the permissions declared by its capability manifest. Permissions are
+    /// only added to selectors that exist, so that we don't report unused configuration for, say,
+    /// a build script that the package doesn't have.
+    fn apply_capability_manifests(
+        &mut self,
+        capability_manifests: &FxHashMap<PackageId, CapabilityManifest>,
+        crate_index: &CrateIndex,
+    ) {
+        for (pkg_id, manifest) in capability_manifests {
+            let primary = PermSel::for_primary(pkg_id.pkg_name());
+            let build = PermSel::for_build_script(pkg_id.pkg_name());
+            for (perm_sel, declared) in [
+                (primary, Some(manifest.package_config())),
+                (build, manifest.build_package_config()),
+            ] {
+                if let Some(declared) = declared {
+                    if crate_index.permission_selectors.contains(&perm_sel) {
+                        self.packages
+                            .entry(perm_sel)
+                            .or_default()
+                            .inherit(&declared);
+                    }
+                }
+            }
+        }
+    }
+
     pub(crate) fn sandbox_config_for_package(&self, perm_sel: &PermSel) -> SandboxConfig {
         self.packages
             .get(perm_sel)
@@ -121,11 +199,107 @@ impl Permissions {
             .is_some_and(|crate_config| crate_config.allow_unsafe)
     }
 
+    /// Returns whether `crate_sel` is permitted to use unsafe in at least some of its source files.
+    pub(crate) fn unsafe_permitted_in_some_files(&self, crate_sel: &CrateSel) -> bool {
+        self.packages
+            .get(&PermSel::for_non_build_output(crate_sel))
+            .is_some_and(|crate_config| !crate_config.allow_unsafe_in.is_empty())
+    }
+
+    /// Returns whether `crate_sel`, whose source is in `pkg_dir`, is permitted to use unsafe at
+    /// `location`.
+    pub(crate) fn unsafe_permitted_at(
+        &self,
+        crate_sel: &CrateSel,
+        pkg_dir: &Path,
+        location: &SourceLocation,
+    ) -> bool {
+        let Some(crate_config) = self.packages.get(&PermSel::for_non_build_output(crate_sel))
+        else {
+            return false;
+        };
+        if crate_config.allow_unsafe {
+            return true;
+        }
+        let Ok(relative_path) = location.filename().strip_prefix(pkg_dir) else {
+            return false;
+        };
+        let relative_path = relative_path.to_string_lossy();
+        crate_config
+            .allow_unsafe_in
+            .iter()
+            .any(|pattern| glob_matches(pattern, &relative_path))
+    }
+
     pub(crate) fn get(&self, perm_sel: &PermSel) -> Option<&PackageConfig> {
         self.packages.get(perm_sel)
     }
 }
 
+/// Inserts `pkg_config` into `packages`, splitting out any sub-configs (e.g. `build`) into their own
+/// selectors.
+fn insert_with_scopes(
+    packages: &mut FxHashMap<PermSel, PackageConfig>,
+    name: &PackageName,
+    pkg_config: &PackageConfig,
+) {
+    let mut pkg_config = pkg_config.clone();
+    if let Some(sub_cfg) = pkg_config.build.take() {
+        packages.insert(
+            PermSel {
+                package_name: name.clone(),
+                scope: PermissionScope::Build,
+            },
+            *sub_cfg,
+        );
+    }
+    if let Some(sub_cfg) = pkg_config.test.take() {
+        packages.insert(
+            PermSel {
+                package_name: name.clone(),
+                scope: PermissionScope::Test,
+            },
+            *sub_cfg,
+        );
+    }
+    if let Some(sub_cfg) = pkg_config.proc_macro.take() {
+        packages.insert(
+            PermSel {
+                package_name: name.clone(),
+                scope: PermissionScope::ProcMacro,
+            },
+            *sub_cfg,
+        );
+    }
+    if let Some(mut dep) = pkg_config.from.take() {
+        if let Some(sub_cfg) = dep.build.take() {
+            packages.insert(
+                PermSel {
+                    package_name: name.clone(),
+                    scope: PermissionScope::FromBuild,
+                },
+                *sub_cfg,
+            );
+        }
+        if let Some(sub_cfg) = dep.test.take() {
+            packages.insert(
+                PermSel {
+                    package_name: name.clone(),
+                    scope: PermissionScope::FromTest,
+                },
+                *sub_cfg,
+            );
+        }
+    }
+    packages.insert(
+        PermSel {
+            package_name: name.clone(),
+            scope: PermissionScope::All,
+        },
+        pkg_config,
+    );
+}
+
 fn apply_inheritance(packages: &mut FxHashMap<PermSel, PackageConfig>, config: &RawConfig) {
     // Determine a global config. We may eventually make this an actual thing in our configuration
     // file.
@@ -144,6 +318,7 @@ fn apply_inheritance(packages: &mut FxHashMap<PermSel, PackageConfig>, config: &
             PermissionScope::All => all.insert(perm_sel, config),
             PermissionScope::Build => local.insert(perm_sel, config),
             PermissionScope::Test => local.insert(perm_sel, config),
+            PermissionScope::ProcMacro => local.insert(perm_sel, config),
             PermissionScope::FromBuild => dep.insert(perm_sel, config),
             PermissionScope::FromTest => dep.insert(perm_sel, config),
         };
@@ -159,11 +334,7 @@ fn apply_inheritance(packages: &mut FxHashMap<PermSel, PackageConfig>, config: &
         }
     }
     for (perm_sel, config) in local.iter_mut() {
-        let parent_scope = match perm_sel.scope {
-            PermissionScope::Build => PermissionScope::FromBuild,
-            PermissionScope::Test => PermissionScope::FromTest,
-            _ => unreachable!(),
-        };
+        let parent_scope = perm_sel.scope.parent_scope().unwrap();
         if let Some(parent) = dep.get(&perm_sel.clone_with_scope(parent_scope)) {
             config.inherit(parent);
         }
@@ -182,9 +353,18 @@ impl PackageConfig {
             &mut self.allow_build_instructions,
             &other.allow_build_instructions,
         );
+        merge_string_vec(&mut self.downgrade, &other.downgrade);
         self.allow_proc_macro |= other.allow_proc_macro;
+        self.allow_generated_code |= other.allow_generated_code;
         self.allow_unsafe |= other.allow_unsafe;
+        merge_string_vec(&mut self.allow_unsafe_in, &other.allow_unsafe_in);
         self.sandbox.inherit(&other.sandbox);
+        if let Some(other_rustc) = &other.rustc {
+            self.rustc
+                .get_or_insert_with(Default::default)
+                .sandbox
+                .inherit(&other_rustc.sandbox);
+        }
     }
 }
 
@@ -199,6 +379,25 @@ impl SandboxConfig {
         if self.allow_network.is_none() {
             self.allow_network = other.allow_network;
         }
+        if self.stdin.is_none() {
+            self.stdin = other.stdin.clone();
+        }
+        if self.scratch_dir.is_none() {
+            self.scratch_dir = other.scratch_dir;
+        }
+        // Inherited rules go first, so that our own rules take precedence.
+        let mut fs_rules = other.fs_rules.clone();
+        for rule in std::mem::take(&mut self.fs_rules) {
+            if !fs_rules.contains(&rule) {
+                fs_rules.push(rule);
+            }
+        }
+        self.fs_rules = fs_rules;
+        for (test, sub_config) in &other.per_test {
+            self.per_test
+                .entry(test.clone())
+                .or_insert_with(|| sub_config.clone());
+        }
     }
 }
 
@@ -243,6 +442,13 @@ impl PermSel {
         }
     }
 
+    pub(crate) fn clone_with_package_name(&self, package_name: PackageName) -> Self {
+        Self {
+            package_name,
+            scope: self.scope,
+        }
+    }
+
     pub(crate) fn clone_with_scope(&self, scope: PermissionScope) -> Self {
         Self {
             package_name: self.package_name.clone(),
@@ -250,6 +456,29 @@ impl PermSel {
         }
     }
 
+    /// Parses a selector in the form produced by `Display`. e.g. "foo.build".
+    pub(crate) fn parse(selector: &str) -> Self {
+        for scope in [
+            PermissionScope::FromBuild,
+            PermissionScope::FromTest,
+            PermissionScope::Build,
+            PermissionScope::Test,
+            PermissionScope::ProcMacro,
+        ] {
+            if let Some(name) = scope
+                .config_selector()
+                .and_then(|suffix| selector.strip_suffix(suffix))
+                .and_then(|rest| rest.strip_suffix('.'))
+            {
+                return Self {
+                    package_name: PackageName(Arc::from(name)),
+                    scope,
+                };
+            }
+        }
+        Self::for_primary(selector)
+    }
+
     pub(crate) fn parent(&self) -> Option<PermSel> {
         Some(self.clone_with_scope(self.scope.parent_scope()?))
     }
@@ -295,11 +524,26 @@ impl PermissionScope {
             Self::Test => Some("test"),
             Self::FromBuild => Some("from.build"),
             Self::FromTest => Some("from.test"),
+            Self::ProcMacro => Some("proc-macro"),
         }
     }
 
-    pub(crate) fn determine(use_pkg: &PackageId, bin_selector: &CrateSel) -> PermissionScope {
-        if use_pkg == &bin_selector.pkg_id {
+    /// Determines the scope of a usage by `use_pkg` in the binary `bin_selector`.
+    /// `bin_is_proc_macro` should be set if the binary is a proc macro, which is loaded by the
+    /// compiler. Everything in a proc macro runs at build time, so the proc macro's dependencies are
+    /// treated like dependencies of build scripts.
+    pub(crate) fn determine(
+        use_pkg: &PackageId,
+        bin_selector: &CrateSel,
+        bin_is_proc_macro: bool,
+    ) -> PermissionScope {
+        if bin_is_proc_macro {
+            if use_pkg == &bin_selector.pkg_id {
+                PermissionScope::ProcMacro
+            } else {
+                PermissionScope::FromBuild
+            }
+        } else if use_pkg == &bin_selector.pkg_id {
             match bin_selector.kind {
                 CrateKind::Primary => PermissionScope::All,
                 CrateKind::BuildScript => PermissionScope::Build,
@@ -314,6 +558,18 @@ impl PermissionScope {
         }
     }
 
+    /// Converts a scope that was determined for one package into the equivalent scope for another
+    /// package used by the same binary. Scopes that relate to a package's own build script or tests
+    /// become the corresponding scope for packages used from build scripts or tests.
+    pub(crate) fn for_other_package(self) -> PermissionScope {
+        match self {
+            PermissionScope::Build => PermissionScope::FromBuild,
+            PermissionScope::Test => PermissionScope::FromTest,
+            PermissionScope::ProcMacro => PermissionScope::FromBuild,
+            other => other,
+        }
+    }
+
     pub(crate) fn parent_scope(self) -> Option<PermissionScope> {
         match self {
             PermissionScope::All => None,
@@ -321,6 +577,7 @@ impl PermissionScope {
             PermissionScope::Test => Some(PermissionScope::FromTest),
             PermissionScope::FromBuild => Some(PermissionScope::All),
             PermissionScope::FromTest => Some(PermissionScope::All),
+            PermissionScope::ProcMacro => Some(PermissionScope::FromBuild),
         }
     }
 
@@ -329,7 +586,8 @@ impl PermissionScope {
             PermissionScope::All => &[PermissionScope::FromBuild, PermissionScope::FromTest],
             PermissionScope::Build => &[],
             PermissionScope::Test => &[],
-            PermissionScope::FromBuild => &[PermissionScope::Build],
+            PermissionScope::ProcMacro => &[],
+            PermissionScope::FromBuild => &[PermissionScope::Build, PermissionScope::ProcMacro],
             PermissionScope::FromTest => &[PermissionScope::Test],
         }
     }
@@ -387,8 +645,8 @@ fn test_inheritance() {
         crate_index: &CrateIndex,
         cackle: &str,
     ) -> anyhow::Result<Arc<crate::config::Config>> {
-        let raw = super::parse_raw(cackle)?;
-        crate::config::Config::from_raw(raw, crate_index)
+        let raw = super::parse_raw(cackle, None)?;
+        crate::config::Config::from_raw(raw, crate_index, Default::default())
     }
 
     let bar1 = PermSel::for_primary("bar1");
@@ -426,3 +684,130 @@ fn test_inheritance() {
     assert!(bar1_test_config.allow_unsafe);
     assert_eq!(bar1_test_config.allow_apis, &["fs", "process"])
 }
+
+#[test]
+fn proc_macro_scope() {
+    use crate::crate_index::testing::pkg_id;
+
+    let foo = PermSel::for_primary("foo");
+    let foo_proc_macro = foo.clone_with_scope(PermissionScope::ProcMacro);
+    assert_eq!(foo_proc_macro.to_string(), "foo.proc-macro");
+    assert_eq!(PermSel::parse("foo.proc-macro"), foo_proc_macro);
+
+    let mut crate_index = CrateIndex::default();
+    crate_index
+        .permission_selectors
+        .insert(foo_proc_macro.clone());
+    let raw = super::parse_raw(
+        r#"
+        [common]
+        version = 1
+        import_std = ["fs", "net"]
+
+        [pkg.foo.from.build]
+        allow_apis = ["net"]
+
+        [pkg.foo.proc-macro]
+        allow_apis = ["fs"]
+    "#,
+        None,
+    )
+    .unwrap();
+    let config = crate::config::Config::from_raw(raw, &crate_index, Default::default()).unwrap();
+    let mut allow_apis = config
+        .permissions
+        .get(&foo_proc_macro)
+        .unwrap()
+        .allow_apis
+        .clone();
+    allow_apis.sort();
+    assert_eq!(allow_apis, ["fs", "net"]);
+    assert!(config.permissions.get(&foo).unwrap().allow_apis.is_empty());
+
+    let bin_sel = CrateSel::primary(pkg_id("foo"));
+    assert_eq!(
+        PermissionScope::determine(&pkg_id("foo"), &bin_sel, true),
+        PermissionScope::ProcMacro
+    );
+    assert_eq!(
+        PermissionScope::determine(&pkg_id("bar"), &bin_sel, true),
+        PermissionScope::FromBuild
+    );
+    assert_eq!(
+        PermissionScope::determine(&pkg_id("foo"), &bin_sel, false),
+        PermissionScope::All
+    );
+}
+
+#[test]
+fn dev_dependencies_default_allow() {
+    use crate::crate_index::testing::pkg_id;
+    use crate::crate_index::DepKind;
+
+    let mut crate_index =
+        Arc::try_unwrap(crate::crate_index::testing::index_with_package_names(&[
+            "dev-helper",
+            "runtime",
+        ]))
+        .unwrap();
+    crate_index
+        .package_infos
+        .get_mut(&pkg_id("dev-helper"))
+        .unwrap()
+        .dep_kind = DepKind::Dev;
+    let raw = super::parse_raw(
+        r#"
+        [common]
+        version = 1
+        import_std = ["fs", "net"]
+        dev_dependencies_default_allow = ["fs"]
+    "#,
+        None,
+    )
+    .unwrap();
+    let config = crate::config::Config::from_raw(raw, &crate_index, Default::default()).unwrap();
+    let allow_apis = |pkg_name: &str, scope| {
+        config
+            .permissions
+            .get(&PermSel::for_primary(pkg_name).clone_with_scope(scope))
+            .map(|pkg_config| pkg_config.allow_apis.clone())
+            .unwrap_or_default()
+    };
+    assert_eq!(allow_apis("dev-helper", PermissionScope::All), ["fs"]);
+    assert_eq!(allow_apis("dev-helper", PermissionScope::FromTest), ["fs"]);
+    assert!(allow_apis("runtime", PermissionScope::All).is_empty());
+}
+
+#[test]
+fn test_unsafe_permitted_at() {
+    use crate::crate_index::testing::pkg_id;
+
+    let config = crate::config::testing::parse(
+        r#"
+        [pkg.foo]
+        allow_unsafe_in = ["src/ffi.rs", "src/sys/*"]
+
+        [pkg.bar]
+        allow_unsafe = true
+    "#,
+    )
+    .unwrap();
+    let permissions = &config.permissions;
+    let pkg_dir = Path::new("/src/foo");
+    let at = |path: &str| SourceLocation::new(Path::new(path), 1, None);
+
+    let foo = CrateSel::primary(pkg_id("foo"));
+    assert!(permissions.unsafe_permitted_in_some_files(&foo));
+    assert!(!permissions.unsafe_permitted_for_crate(&foo));
+    assert!(permissions.unsafe_permitted_at(&foo, pkg_dir, &at("/src/foo/src/ffi.rs")));
+    assert!(permissions.unsafe_permitted_at(&foo, pkg_dir, &at("/src/foo/src/sys/unix/mod.rs")));
+    assert!(!permissions.unsafe_permitted_at(&foo, pkg_dir, &at("/src/foo/src/lib.rs")));
+    assert!(!permissions.unsafe_permitted_at(&foo, pkg_dir, &at("/other/src/ffi.rs")));
+
+    let bar = CrateSel::primary(pkg_id("bar"));
+    assert!(!permissions.unsafe_permitted_in_some_files(&bar));
+    assert!(permissions.unsafe_permitted_at(&bar, pkg_dir, &at("/anywhere/lib.rs")));
+
+    let baz = CrateSel::primary(pkg_id("baz"));
+    assert!(!permissions.unsafe_permitted_at(&baz, pkg_dir, &at("/src/foo/src/ffi.rs")));
+}
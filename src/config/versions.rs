@@ -92,9 +92,9 @@ mod tests {
             version.apply(&mut editor).unwrap();
             let edited_toml = editor.to_toml();
 
-            let mut config = crate::config::parse_raw(&toml).unwrap();
+            let mut config = crate::config::parse_raw(&toml, None).unwrap();
             (version.apply_fn)(&mut config);
-            let edited_config = crate::config::parse_raw(&edited_toml).unwrap();
+            let edited_config = crate::config::parse_raw(&edited_toml, None).unwrap();
             assert_eq!(config.common.version, version.number - 1);
             config.common.version = version.number;
             assert_eq!(config, edited_config);
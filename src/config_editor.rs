@@ -1,25 +1,36 @@
 //! This module is responsible for applying automatic edits to cackle.toml.
 
+use crate::audit;
 use crate::checker::common_prefix::common_to_prefixes;
+use crate::config::acknowledgements;
+use crate::config::approvals::Approval;
+use crate::config::expiry;
 use crate::config::permissions::PermSel;
+use crate::config::permissions::PermissionScope;
 use crate::config::versions::Version;
 use crate::config::ApiName;
 use crate::config::ApiPath;
 use crate::config::Config;
+use crate::config::DowngradableProblem;
 use crate::config::PackageName;
 use crate::config::SandboxKind;
+use crate::config_file::ConfigFile;
+use crate::problem::ApiChecklist;
 use crate::problem::ApiUsages;
 use crate::problem::AvailableApi;
+use crate::problem::ChecklistApi;
 use crate::problem::PossibleExportedApi;
 use crate::problem::Problem;
 use crate::problem::ProblemList;
 use crate::problem::UnusedAllowApi;
+use crate::test_output::SandboxDenial;
 use anyhow::anyhow;
 use anyhow::Result;
 use std::borrow::Borrow;
 use std::borrow::Cow;
+use std::collections::BTreeSet;
 use std::fmt::Display;
-use std::path::Path;
+use std::path::PathBuf;
 use toml_edit::Array;
 use toml_edit::Document;
 use toml_edit::Formatted;
@@ -29,12 +40,24 @@ use toml_edit::Value;
 #[derive(Clone)]
 pub(crate) struct ConfigEditor {
     document: Document,
+    /// Edits that have been recorded with `record_edit`, to be added to the audit log when we're
+    /// written.
+    audit_entries: Vec<audit::Entry>,
+    /// The contents of the config file that we were read from, if any. If the file no longer has
+    /// these contents when we're written, something else changed it and writing would clobber
+    /// those changes.
+    base: Option<String>,
 }
 
 pub(crate) trait Edit {
     /// Returns a short name for this edit, suitable for display in a menu.
     fn title(&self) -> String;
 
+    /// Returns an identifier for this edit that's stable across runs, e.g.
+    /// `allow-api:tokio:net:all`. This lets tools outside of cackle refer to a particular fix, for
+    /// example to have it applied with `cargo acl apply-fix`.
+    fn id(&self) -> String;
+
     fn help(&self) -> Cow<'static, str>;
 
     /// Applies the edit to the editor.
@@ -59,6 +82,11 @@ pub(crate) struct EditOpts {
     /// A comment that the user requested be attached to an edit. Not all edits support adding
     /// comments.
     pub(crate) comment: Option<String>,
+
+    /// Whether the edit is actually being applied, as opposed to being applied in order to show a
+    /// diff or to see if it would change anything. Edits that have effects outside of cackle.toml
+    /// should only perform them when this is set.
+    pub(crate) is_final: bool,
 }
 
 /// Returns possible fixes for `problem`. The applicability of some fixes depends on the current
@@ -68,6 +96,9 @@ pub(crate) fn fixes_for_problem(problem: &Problem, config: &Config) -> Vec<Box<d
     match problem {
         Problem::MissingConfiguration(_) => {
             edits.push(Box::new(CreateRecommendedConfig));
+            for preset in Preset::ALL {
+                edits.push(Box::new(CreatePresetConfig(*preset)));
+            }
             edits.push(Box::new(CreateCustomConfig));
         }
         Problem::SelectSandbox => {
@@ -75,10 +106,17 @@ pub(crate) fn fixes_for_problem(problem: &Problem, config: &Config) -> Vec<Box<d
                 edits.push(Box::new(SelectSandbox(*kind)));
             }
         }
-        Problem::ImportStdApi(api) => {
-            edits.push(Box::new(ImportStdApi(api.clone())));
-            edits.push(Box::new(InlineStdApi(api.clone())));
-            edits.push(Box::new(IgnoreStdApi(api.clone())));
+        Problem::SelectApis(checklist) => {
+            edits.push(Box::new(ImportSelectedApis(checklist.clone())));
+            if checklist.selected().next().is_some() {
+                edits.push(Box::new(InlineSelectedApis(checklist.clone())));
+            }
+            for index in 0..checklist.entries.len() {
+                edits.push(Box::new(ToggleApi {
+                    checklist: checklist.clone(),
+                    index,
+                }));
+            }
         }
         Problem::AvailableApi(available) => {
             edits.push(Box::new(ImportApi(available.clone())));
@@ -86,23 +124,80 @@ pub(crate) fn fixes_for_problem(problem: &Problem, config: &Config) -> Vec<Box<d
             edits.push(Box::new(IgnoreApi(available.clone())));
         }
         Problem::DisallowedApiUsage(usage) => {
-            usage.add_allow_api_fixes(&mut edits);
+            usage.add_extend_grant_fix(&mut edits, config);
+            usage.add_allow_api_fixes(&mut edits, config);
             let _ = usage.add_exclude_fixes(&mut edits, config);
+            if !usage.usages.is_empty() {
+                edits.push(Box::new(AcknowledgeUsages {
+                    usage: usage.clone(),
+                    pkg_dir: config.pkg_dirs.get(&usage.pkg_id).cloned(),
+                }));
+            }
         }
         Problem::IsProcMacro(pkg_id) => {
             edits.push(Box::new(AllowProcMacro {
                 perm_sel: PermSel::for_primary(pkg_id.pkg_name()),
             }));
         }
-        Problem::ExecutionFailed(failure) => {
-            if failure.output.sandbox_config.kind != Some(SandboxKind::Disabled) {
-                let perm_sel = PermSel::for_build_script(failure.crate_sel.pkg_name());
-                if !failure.output.sandbox_config.allow_network.unwrap_or(false) {
-                    edits.push(Box::new(SandboxAllowNetwork {
-                        perm_sel: perm_sel.clone(),
-                    }));
-                }
-                edits.push(Box::new(DisableSandbox { perm_sel }));
+        Problem::GeneratedCode(generated) => {
+            edits.push(Box::new(AllowGeneratedCode {
+                perm_sel: PermSel::for_non_build_output(&generated.crate_sel),
+            }));
+        }
+        Problem::ExecutionFailed(failure)
+            if failure.output.sandbox_config.kind != Some(SandboxKind::Disabled) =>
+        {
+            let perm_sel = PermSel::for_build_script(failure.crate_sel.pkg_name());
+            if !failure.output.sandbox_config.allow_network.unwrap_or(false) {
+                edits.push(Box::new(SandboxAllowNetwork {
+                    perm_sel: perm_sel.clone(),
+                }));
+            }
+            edits.push(Box::new(DisableSandbox { perm_sel }));
+        }
+        Problem::TestFailed(failure) => {
+            let perm_sel = PermSel::for_non_build_output(&failure.crate_sel);
+            let denial = failure.test.sandbox_denial();
+            // If the output suggests that filesystem access was denied, permitting network access
+            // is unlikely to help.
+            let offer_network = denial != Some(SandboxDenial::Filesystem);
+            let test = &failure.test.name;
+            if offer_network
+                && !failure
+                    .effective_sandbox_config()
+                    .allow_network
+                    .unwrap_or(false)
+            {
+                edits.push(Box::new(TestSandboxOverride {
+                    perm_sel: perm_sel.clone(),
+                    test: test.clone(),
+                    change: SandboxChange::AllowNetwork,
+                }));
+            }
+            edits.push(Box::new(TestSandboxOverride {
+                perm_sel: perm_sel.clone(),
+                test: test.clone(),
+                change: SandboxChange::Disable,
+            }));
+            if offer_network && !failure.sandbox_config.allow_network.unwrap_or(false) {
+                edits.push(Box::new(SandboxAllowNetwork {
+                    perm_sel: perm_sel.clone(),
+                }));
+            }
+            edits.push(Box::new(DisableSandbox { perm_sel }));
+        }
+        Problem::WaitingForInput(failure) => {
+            let perm_sel = PermSel::for_non_build_output(&failure.crate_sel);
+            if let Some(marker) = &failure.output.waiting_for_input {
+                edits.push(Box::new(AttachTerminal {
+                    marker: marker.clone(),
+                }));
+            }
+            for input in ["y\n", ""] {
+                edits.push(Box::new(ProvideStdin {
+                    perm_sel: perm_sel.clone(),
+                    input,
+                }));
             }
         }
         Problem::DisallowedBuildInstruction(failure) => {
@@ -111,9 +206,10 @@ pub(crate) fn fixes_for_problem(problem: &Problem, config: &Config) -> Vec<Box<d
         Problem::DisallowedUnsafe(failure) => edits.push(Box::new(AllowUnsafe {
             perm_sel: PermSel::for_non_build_output(&failure.crate_sel),
         })),
-        Problem::UnusedAllowApi(failure) => edits.push(Box::new(RemoveUnusedAllowApis {
-            unused: failure.clone(),
-        })),
+        Problem::UnusedAllowApi(failure) | Problem::AllowApiUsedOnlyByDeadCode(failure) => edits
+            .push(Box::new(RemoveUnusedAllowApis {
+                unused: failure.clone(),
+            })),
         Problem::UnusedPackageConfig(crate_name) => edits.push(Box::new(RemoveUnusedPkgConfig {
             perm_sel: crate_name.clone(),
         })),
@@ -123,13 +219,18 @@ pub(crate) fn fixes_for_problem(problem: &Problem, config: &Config) -> Vec<Box<d
                 api_path: info.api_path(),
             }));
             edits.push(Box::new(NoDetectApi(info.clone())));
+            edits.extend(DowngradeProblem::for_problem(problem));
         }
         Problem::OffTreeApiUsage(info) => {
             // Ignore errors while adding includes/excludes. Any errors here will likely already
             // have shown up elsewhere and it seems nicer to just degrade to not show those edits.
             let _ = info.usages.add_include_fixes(&mut edits, config);
             let _ = info.usages.add_exclude_fixes(&mut edits, config);
-            info.usages.add_allow_api_fixes(&mut edits);
+            info.usages.add_allow_api_fixes(&mut edits, config);
+            edits.extend(DowngradeProblem::for_problem(problem));
+        }
+        Problem::CapabilityManifestViolation(..) => {
+            edits.extend(DowngradeProblem::for_problem(problem));
         }
         Problem::NewConfigVersionAvailable(version) => {
             if let Some(version) = crate::config::versions::VERSIONS.get(*version as usize) {
@@ -143,27 +244,46 @@ pub(crate) fn fixes_for_problem(problem: &Problem, config: &Config) -> Vec<Box<d
         }
         _ => {}
     }
+    edits.extend(crate::plugin::custom_fixes(problem, config));
     edits
 }
 
 impl ConfigEditor {
-    pub(crate) fn from_file(filename: &Path) -> Result<Self> {
-        let toml = std::fs::read_to_string(filename).unwrap_or_default();
-        Self::from_toml_string(&toml)
-    }
-
     pub(crate) fn initial() -> Self {
         Self::from_toml_string(r#""#).unwrap()
     }
 
     pub(crate) fn from_toml_string(toml: &str) -> Result<Self> {
         let document = toml.parse()?;
-        Ok(Self { document })
+        Ok(Self {
+            document,
+            audit_entries: Vec::new(),
+            base: None,
+        })
     }
 
-    pub(crate) fn write(&self, filename: &Path) -> Result<()> {
-        crate::fs::write_atomic(filename, &self.to_toml())?;
-        Ok(())
+    /// Records that we were read from a config file that contained `base`.
+    pub(crate) fn with_base(mut self, base: String) -> Self {
+        self.base = Some(base);
+        self
+    }
+
+    pub(crate) fn write(&self, config_file: &ConfigFile) -> Result<()> {
+        if let Some(base) = &self.base {
+            config_file.check_unchanged(base)?;
+        }
+        config_file.write(&self.to_toml())?;
+        config_file.append_to_audit_log(&self.audit_entries)
+    }
+
+    /// Records that `edit` was applied to fix `problem`, changing the config from `before` to what
+    /// it is now. Edits that didn't change anything aren't recorded.
+    pub(crate) fn record_edit(&mut self, problem: &str, edit: &dyn Edit, before: &str) {
+        let after = self.to_toml();
+        if after != before {
+            self.audit_entries
+                .push(audit::Entry::new(problem, edit, before, &after));
+        }
     }
 
     pub(crate) fn to_toml(&self) -> String {
@@ -183,6 +303,26 @@ impl ConfigEditor {
         Ok(table)
     }
 
+    /// Returns the table in `[defaults.first_party]` for `scope`, creating it if needed.
+    fn first_party_defaults_table(
+        &mut self,
+        scope: PermissionScope,
+    ) -> Result<&mut toml_edit::Table> {
+        let mut table = self.table(["defaults", "first_party"].into_iter())?;
+        for part in scope
+            .config_selector()
+            .into_iter()
+            .flat_map(|s| s.split('.'))
+        {
+            table = table
+                .entry(part)
+                .or_insert_with(create_dotted_table)
+                .as_table_mut()
+                .ok_or_else(|| anyhow!("[defaults.first_party.{part}] should be a table"))?;
+        }
+        Ok(table)
+    }
+
     fn opt_pkg_table(&mut self, perm_sel: &PermSel) -> Result<Option<&mut toml_edit::Table>> {
         self.opt_table(pkg_path(perm_sel))
     }
@@ -240,6 +380,102 @@ impl ConfigEditor {
         Ok(Some(table))
     }
 
+    /// Removes the configuration for `perm_sel`, if any.
+    pub(crate) fn remove_pkg_config(&mut self, perm_sel: &PermSel) -> Result<()> {
+        let mut path: Vec<_> = pkg_path(perm_sel).collect();
+        let last_part = path.pop().unwrap();
+        if let Some(parent_table) = self.opt_table(path.into_iter())? {
+            parent_table.remove(last_part);
+        }
+        Ok(())
+    }
+
+    /// Removes `key` from the configuration for `perm_sel`. `key` may be dotted, e.g.
+    /// `sandbox.kind`. If `entry` is supplied, then just that entry is removed from the list at
+    /// `key`. For `fs_rules`, `entry` is the path of the rule to remove.
+    pub(crate) fn remove_pkg_setting(
+        &mut self,
+        perm_sel: &PermSel,
+        key: &str,
+        entry: Option<&str>,
+    ) -> Result<()> {
+        let (table_path, last_part) = key.rsplit_once('.').unwrap_or(("", key));
+        let path = pkg_path(perm_sel).chain(table_path.split('.').filter(|p| !p.is_empty()));
+        let Some(table) = self.opt_table(path)? else {
+            return Ok(());
+        };
+        let Some(entry) = entry else {
+            table.remove(last_part);
+            return Ok(());
+        };
+        let Some(array) = get_array(table, last_part)? else {
+            return Ok(());
+        };
+        array.retain(|value| {
+            let value = value
+                .as_inline_table()
+                .and_then(|rule| rule.get("path"))
+                .unwrap_or(value);
+            value.as_str() != Some(entry)
+        });
+        array.fmt();
+        if array.is_empty() {
+            table.remove(last_part);
+        }
+        Ok(())
+    }
+
+    /// Returns the TOML for just the `[pkg]` tables for `pkg_names`.
+    pub(crate) fn pkg_tables_toml(&self, pkg_names: &[&str]) -> String {
+        let mut out = Self::initial();
+        for pkg_name in pkg_names {
+            if let Some(item) = self.document.get("pkg").and_then(|pkg| pkg.get(pkg_name)) {
+                if let Ok(table) = out.table(["pkg"].into_iter()) {
+                    table.set_implicit(true);
+                    table.insert(pkg_name, item.clone());
+                }
+            }
+        }
+        out.to_toml()
+    }
+
+    /// Removes `pkg_name` from the `no_auto_detect` list of `api`, removing the list if it becomes
+    /// empty.
+    pub(crate) fn remove_no_auto_detect(
+        &mut self,
+        api: &ApiName,
+        pkg_name: &PackageName,
+    ) -> Result<()> {
+        let Some(table) = self.opt_table(["api", api.name.as_ref()].into_iter())? else {
+            return Ok(());
+        };
+        let Some(no_auto_detect) = get_array(table, "no_auto_detect")? else {
+            return Ok(());
+        };
+        no_auto_detect.retain(|value| value.as_str() != Some(pkg_name.as_ref()));
+        if no_auto_detect.is_empty() {
+            table.remove("no_auto_detect");
+        }
+        Ok(())
+    }
+
+    /// Records `approval` against `grant`, replacing any existing approval.
+    pub(crate) fn set_approval(&mut self, grant: &str, approval: &Approval) -> Result<()> {
+        let table = self.table(["approvals", grant].into_iter())?;
+        table.clear();
+        table.insert(
+            "approved_by",
+            toml_edit::value(toml_edit::Array::from_iter(&approval.approved_by)),
+        );
+        if let Some(ticket) = &approval.ticket {
+            table.insert("ticket", toml_edit::value(ticket));
+        }
+        if let Some(expires) = approval.expires {
+            table.insert("expires", toml_edit::value(expires));
+        }
+        Ok(())
+    }
+
     pub(crate) fn set_version(&mut self, version: i64) -> Result<()> {
         let table = self.table(["common"].into_iter())?;
         table.insert("version", toml_edit::value(version));
@@ -268,6 +504,19 @@ impl ConfigEditor {
         Ok(())
     }
 
+    /// Adds `api` to `import_std` if it isn't already there.
+    pub(crate) fn add_std_import(&mut self, api: &str) -> Result<()> {
+        let already_imported = self
+            .common_table()?
+            .get("import_std")
+            .and_then(Item::as_array)
+            .is_some_and(|imports| imports.iter().any(|item| item.as_str() == Some(api)));
+        if already_imported {
+            return Ok(());
+        }
+        self.toggle_std_import(api)
+    }
+
     pub(crate) fn set_sandbox_kind(&mut self, sandbox_kind: SandboxKind) -> Result<()> {
         crate::sandbox::verify_kind(sandbox_kind)?;
         let sandbox_kind = match sandbox_kind {
@@ -346,7 +595,23 @@ impl ApiUsages {
         Ok(())
     }
 
-    fn add_allow_api_fixes(&self, edits: &mut Vec<Box<dyn Edit>>) {
+    /// Adds an edit to extend the grant of the API if there's a grant that has expired.
+    fn add_extend_grant_fix(&self, edits: &mut Vec<Box<dyn Edit>>, config: &Config) {
+        let perm_sel = self.perm_sel();
+        let has_expired_grant = config
+            .permissions_no_inheritance
+            .get(&perm_sel)
+            .is_some_and(|pkg_config| pkg_config.expired_apis.contains(&self.api_name));
+        if has_expired_grant {
+            edits.push(Box::new(ExtendApiGrant {
+                perm_sel,
+                api: self.api_name.clone(),
+                until: expiry::days_from_now(expiry::EXTENSION_DAYS),
+            }));
+        }
+    }
+
+    fn add_allow_api_fixes(&self, edits: &mut Vec<Box<dyn Edit>>, config: &Config) {
         edits.push(Box::new(AllowApiUsage {
             usage: self.clone(),
         }));
@@ -360,6 +625,16 @@ impl ApiUsages {
             }));
             scope = parent_scope;
         }
+        // We generally trust our own code uniformly, so if a first-party package needs an API, it's
+        // likely that others will too.
+        let pkg_name = PackageName(self.pkg_id.pkg_name());
+        if config.first_party_packages.contains(&pkg_name) {
+            edits.push(Box::new(AllowApiForFirstParty {
+                api: self.api_name.clone(),
+                scope: self.scope,
+                packages: config.first_party_packages.clone(),
+            }));
+        }
     }
 }
 
@@ -375,6 +650,11 @@ fn pkg_path(perm_sel: &PermSel) -> impl Iterator<Item = &str> + Clone {
         )
 }
 
+/// Returns how `scope` appears in edit IDs.
+fn scope_id(scope: PermissionScope) -> &'static str {
+    scope.config_selector().unwrap_or("all")
+}
+
 fn edits_for_build_instruction(
     failure: &crate::problem::DisallowedBuildInstruction,
 ) -> Vec<Box<dyn Edit>> {
@@ -435,6 +715,10 @@ impl Edit for CreateCustomConfig {
         "Create custom initial config".to_owned()
     }
 
+    fn id(&self) -> String {
+        "create-config:custom".to_owned()
+    }
+
     fn help(&self) -> Cow<'static, str> {
         "Writes a cackle.toml into your workspace / crate root. This will initially only set the \
         configuration version. Subsequent action items will prompt you to select a sandbox kind, \
@@ -449,23 +733,25 @@ impl Edit for CreateCustomConfig {
     fn replacement_problems(&self) -> ProblemList {
         let mut problems = ProblemList::default();
         problems.push(Problem::SelectSandbox);
-        for api in crate::config::built_in::get_built_ins().keys() {
-            problems.push(Problem::ImportStdApi(api.clone()));
-        }
+        problems.push(Problem::SelectApis(ApiChecklist::std_apis()));
         problems
     }
 }
 
-struct CreateRecommendedConfig;
+pub(crate) struct CreateRecommendedConfig;
 
 impl Edit for CreateRecommendedConfig {
     fn title(&self) -> String {
         "Create recommended initial config".to_owned()
     }
 
+    fn id(&self) -> String {
+        "create-config:recommended".to_owned()
+    }
+
     fn help(&self) -> Cow<'static, str> {
-        "Writes a cackle.toml into your workspace / crate root with imports for the fs, net and \
-        process APIs."
+        "Writes a cackle.toml into your workspace / crate root with imports for the fs, net, \
+        process and env-mutate APIs."
             .into()
     }
 
@@ -474,6 +760,7 @@ impl Edit for CreateRecommendedConfig {
         editor.toggle_std_import("fs")?;
         editor.toggle_std_import("net")?;
         editor.toggle_std_import("process")?;
+        editor.toggle_std_import("env-mutate")?;
         Ok(())
     }
 
@@ -484,40 +771,97 @@ impl Edit for CreateRecommendedConfig {
     }
 }
 
-struct SelectSandbox(SandboxKind);
+/// A set of std APIs that are commonly of interest for a particular kind of project.
+#[derive(Debug, Clone, Copy)]
+enum Preset {
+    Server,
+    Cli,
+    Embedded,
+}
 
-impl Edit for SelectSandbox {
+impl Preset {
+    const ALL: &'static [Preset] = &[Preset::Server, Preset::Cli, Preset::Embedded];
+
+    fn id_name(self) -> &'static str {
+        match self {
+            Preset::Server => "server",
+            Preset::Cli => "cli",
+            Preset::Embedded => "embedded",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Preset::Server => "server projects",
+            Preset::Cli => "command-line tools",
+            Preset::Embedded => "embedded projects",
+        }
+    }
+
+    fn std_apis(self) -> &'static [&'static str] {
+        match self {
+            Preset::Server => &["env", "fs", "net", "process", "unix_sockets"],
+            Preset::Cli => &["fs", "net", "process", "terminate"],
+            Preset::Embedded => &["net", "panic"],
+        }
+    }
+}
+
+struct CreatePresetConfig(Preset);
+
+impl Edit for CreatePresetConfig {
     fn title(&self) -> String {
-        format!("{:?}", self.0)
+        format!(
+            "Create initial config recommended for {}",
+            self.0.description()
+        )
+    }
+
+    fn id(&self) -> String {
+        format!("create-config:{}", self.0.id_name())
     }
 
     fn help(&self) -> Cow<'static, str> {
-        "Select what kind of sandbox you'd like to use. This is used when running build scripts \
-         (build.rs), tests and when running rustc. To use Bubblewrap, it must be installed. On \
-         Debian-based systems you can `sudo apt install bubblewrap`"
-            .into()
+        format!(
+            "Writes a cackle.toml into your workspace / crate root with imports for the {} APIs.",
+            self.0.std_apis().join(", ")
+        )
+        .into()
     }
 
     fn apply(&self, editor: &mut ConfigEditor, _opts: &EditOpts) -> Result<()> {
-        editor.set_sandbox_kind(self.0)
+        editor.set_version(crate::config::MAX_VERSION)?;
+        for api in self.0.std_apis() {
+            editor.add_std_import(api)?;
+        }
+        Ok(())
+    }
+
+    fn replacement_problems(&self) -> ProblemList {
+        Problem::SelectSandbox.into()
     }
 }
 
-struct ImportStdApi(ApiName);
+struct SelectSandbox(SandboxKind);
 
-impl Edit for ImportStdApi {
+impl Edit for SelectSandbox {
     fn title(&self) -> String {
-        format!("Import std API `{}`", self.0)
+        format!("{:?}", self.0)
+    }
+
+    fn id(&self) -> String {
+        format!("select-sandbox:{:?}", self.0).to_lowercase()
     }
 
     fn help(&self) -> Cow<'static, str> {
-        "This imports an std API that's built into Cackle. Subsequent versions of Cackle may \
-         add/remove paths from this API if it turns out that there were inaccuracies."
+        "Select what kind of sandbox you'd like to use. This is used when running build scripts \
+         (build.rs), tests and when running rustc. To use Bubblewrap, it must be installed. On \
+         Debian-based systems you can `sudo apt install bubblewrap`"
             .into()
     }
 
     fn apply(&self, editor: &mut ConfigEditor, _opts: &EditOpts) -> Result<()> {
-        editor.toggle_std_import(self.0.name.borrow())
+        editor.set_sandbox_kind(self.0)
     }
 }
 
@@ -532,6 +876,10 @@ impl Edit for ImportApi {
         )
     }
 
+    fn id(&self) -> String {
+        format!("import-api:{}:{}", self.0.pkg_id.pkg_name(), self.0.api)
+    }
+
     fn help(&self) -> Cow<'static, str> {
         "Imports an API definition that was provided by a third-party crate. Future versions of \
          that crate may adjust these API definitions, hopefully to make them more accurate or \
@@ -557,6 +905,10 @@ impl Edit for InlineStdApi {
         format!("Inline std API `{}`", self.0)
     }
 
+    fn id(&self) -> String {
+        format!("inline-std-api:{}", self.0)
+    }
+
     fn help(&self) -> Cow<'static, str> {
         "This copies the built-in API definition into your cackle.toml. Changes to the API \
          definition in future versions of cackle will not affect your configuration. Selecting \
@@ -573,6 +925,8 @@ impl Edit for InlineStdApi {
             .ok_or_else(|| anyhow!("Attempted to inline unknown API `{}`", self.0))?;
         add_to_array(table, "include", &api_config.include, None)?;
         add_to_array(table, "exclude", &api_config.exclude, None)?;
+        add_to_array(table, "native_symbols", &api_config.native_symbols, None)?;
+        add_to_array(table, "link_sections", &api_config.link_sections, None)?;
         Ok(())
     }
 }
@@ -587,6 +941,10 @@ impl Edit for InlineApi {
         )
     }
 
+    fn id(&self) -> String {
+        format!("inline-api:{}:{}", self.0.pkg_id.pkg_name(), self.0.api)
+    }
+
     fn help(&self) -> Cow<'static, str> {
         "Inlines an API definition from a third-party crate. This lets you adjust this API \
          definition. It does however mean that any changes made to the API definition by the \
@@ -600,6 +958,8 @@ impl Edit for InlineApi {
         let table = editor.table(["api", self.0.api.name.as_ref()].into_iter())?;
         add_to_array(table, "include", &self.0.config.include, None)?;
         add_to_array(table, "exclude", &self.0.config.exclude, None)?;
+        add_to_array(table, "native_symbols", &self.0.config.native_symbols, None)?;
+        add_to_array(table, "link_sections", &self.0.config.link_sections, None)?;
         // We also need to ignore it, otherwise we'll keep warning about it.
         IgnoreApi(self.0.clone()).apply(editor, &Default::default())
     }
@@ -638,16 +998,119 @@ fn add_to_array<S: AsRef<str>>(
     Ok(())
 }
 
-struct IgnoreStdApi(ApiName);
+/// Imports the std and third-party APIs that are selected in a checklist. Third-party APIs that
+/// aren't selected are ignored, so that we don't keep asking about them.
+struct ImportSelectedApis(ApiChecklist);
+
+impl Edit for ImportSelectedApis {
+    fn title(&self) -> String {
+        let count = self.0.selected().count();
+        if count == 0 {
+            "Don't import any of these APIs".to_owned()
+        } else {
+            format!("Import {count} selected API(s)")
+        }
+    }
+
+    fn id(&self) -> String {
+        format!("import-apis:{}", selected_apis_id(&self.0))
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Imports all the selected APIs. Built-in APIs may have paths added or removed by future \
+         versions of Cackle and third-party APIs by future versions of the crates that provide \
+         them. Unselected third-party APIs will be ignored."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        for entry in &self.0.entries {
+            match (&entry.api, entry.selected) {
+                (ChecklistApi::Std(api), true) => editor.add_std_import(api.name.borrow())?,
+                (ChecklistApi::Std(_), false) => {}
+                (ChecklistApi::Exported(available), true) => {
+                    ImportApi(available.clone()).apply(editor, opts)?
+                }
+                (ChecklistApi::Exported(available), false) => {
+                    IgnoreApi(available.clone()).apply(editor, opts)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_problem_if_edit_is_empty(&self) -> bool {
+        false
+    }
+}
+
+/// Inlines the definitions of the APIs that are selected in a checklist.
+struct InlineSelectedApis(ApiChecklist);
 
-impl Edit for IgnoreStdApi {
+impl Edit for InlineSelectedApis {
     fn title(&self) -> String {
-        format!("Ignore std API `{}`", self.0)
+        format!("Inline {} selected API(s)", self.0.selected().count())
+    }
+
+    fn id(&self) -> String {
+        format!("inline-apis:{}", selected_apis_id(&self.0))
     }
 
     fn help(&self) -> Cow<'static, str> {
-        "Don't import or inline this API definition. Select this if you don't care if crates use \
-        this category of API."
+        "Copies the definitions of the selected APIs into your cackle.toml. This lets you adjust \
+         the definitions, but changes made by future versions of Cackle or of the crates that \
+         provide the APIs won't be picked up. Unselected third-party APIs will be ignored."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        for entry in &self.0.entries {
+            match (&entry.api, entry.selected) {
+                (ChecklistApi::Std(api), true) => InlineStdApi(api.clone()).apply(editor, opts)?,
+                (ChecklistApi::Std(_), false) => {}
+                (ChecklistApi::Exported(available), true) => {
+                    InlineApi(available.clone()).apply(editor, opts)?
+                }
+                (ChecklistApi::Exported(available), false) => {
+                    IgnoreApi(available.clone()).apply(editor, opts)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_problem_if_edit_is_empty(&self) -> bool {
+        false
+    }
+}
+
+/// Returns the selected APIs in `checklist` as they appear in edit IDs.
+fn selected_apis_id(checklist: &ApiChecklist) -> String {
+    let selected: Vec<String> = checklist.selected().map(|api| api.to_string()).collect();
+    selected.join(",")
+}
+
+/// Selects or deselects a single entry in a checklist. The config isn't changed until the
+/// checklist is imported.
+struct ToggleApi {
+    checklist: ApiChecklist,
+    index: usize,
+}
+
+impl Edit for ToggleApi {
+    fn title(&self) -> String {
+        let entry = &self.checklist.entries[self.index];
+        let mark = if entry.selected { 'x' } else { ' ' };
+        format!("[{mark}] {}", entry.api)
+    }
+
+    fn id(&self) -> String {
+        format!("toggle-api:{}", self.checklist.entries[self.index].api)
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Toggles whether this API is selected. Once you've selected the APIs you care about, \
+         choose to import or inline them."
             .into()
     }
 
@@ -655,6 +1118,10 @@ impl Edit for IgnoreStdApi {
         Ok(())
     }
 
+    fn replacement_problems(&self) -> ProblemList {
+        Problem::SelectApis(self.checklist.with_toggled(self.index)).into()
+    }
+
     fn resolve_problem_if_edit_is_empty(&self) -> bool {
         false
     }
@@ -670,6 +1137,10 @@ impl Edit for IgnoreApi {
         )
     }
 
+    fn id(&self) -> String {
+        format!("ignore-api:{}:{}", self.0.pkg_id.pkg_name(), self.0.api)
+    }
+
     fn help(&self) -> Cow<'static, str> {
         "Don't import or inline this API definition. Select this if you don't care if crates use \
         this category of API."
@@ -699,6 +1170,10 @@ impl Edit for ExtendApi {
         format!("Include `{}` in API `{}`", self.api_path, self.api)
     }
 
+    fn id(&self) -> String {
+        format!("extend-api:{}:{}", self.api, self.api_path)
+    }
+
     fn help(&self) -> Cow<'static, str> {
         format!(
             "Classify usages of `{}` as the API `{}`",
@@ -724,6 +1199,10 @@ impl Edit for ExcludeFromApi {
         format!("Exclude `{}` from API `{}`", self.api_path, self.api)
     }
 
+    fn id(&self) -> String {
+        format!("exclude-from-api:{}:{}", self.api, self.api_path)
+    }
+
     fn help(&self) -> Cow<'static, str> {
         format!(
             "Don't classify paths starting with `{}` as the API `{}`",
@@ -750,6 +1229,10 @@ impl Edit for NoDetectApi {
         )
     }
 
+    fn id(&self) -> String {
+        format!("no-detect-api:{}:{}", self.0.pkg_id.pkg_name(), self.0.api)
+    }
+
     fn help(&self) -> Cow<'static, str> {
         format!(
             "Ignore this possible exported API. Select this if you've looked at `{}` and \
@@ -796,9 +1279,21 @@ impl Edit for AllowApiUsage {
             crate::config::permissions::PermissionScope::FromTest => {
                 format!("Allow `{pkg}` to use `{api}` API when building tests")
             }
+            crate::config::permissions::PermissionScope::ProcMacro => {
+                format!("Allow proc macro `{pkg}` to use `{api}` API at build time")
+            }
         }
     }
 
+    fn id(&self) -> String {
+        format!(
+            "allow-api:{}:{}:{}",
+            self.usage.pkg_id.pkg_name(),
+            self.usage.api_name,
+            scope_id(self.usage.scope)
+        )
+    }
+
     fn help(&self) -> Cow<'static, str> {
         let pkg = &self.usage.pkg_id;
         let api = &self.usage.api_name;
@@ -818,6 +1313,9 @@ impl Edit for AllowApiUsage {
             crate::config::permissions::PermissionScope::FromTest => {
                 format!("Allow `{pkg}` to use `{api}` API, but only from tests")
             }
+            crate::config::permissions::PermissionScope::ProcMacro => {
+                format!("Allow `{pkg}` to use `{api}` API, but only in its own proc macro")
+            }
         }
         .into()
     }
@@ -830,6 +1328,8 @@ impl Edit for AllowApiUsage {
             &[&self.usage.api_name],
             opts.comment.as_deref(),
         )?;
+        // The API is now allowed indefinitely, so any expiry no longer applies.
+        remove_api_expiry(table, &self.usage.api_name)?;
         // Remove the API from any selectors that inherit from the one that we just added to, since
         // they're now redundant.
         for perm_sel in self.usage.perm_sel().descendants() {
@@ -845,32 +1345,185 @@ impl Edit for AllowApiUsage {
     }
 }
 
-struct RemoveUnusedAllowApis {
-    unused: UnusedAllowApi,
+/// Grants an API to all first-party packages via `[defaults.first_party]`.
+struct AllowApiForFirstParty {
+    api: ApiName,
+    scope: PermissionScope,
+    /// The first-party packages, any of whose grants of the API are made redundant by this edit.
+    packages: Vec<PackageName>,
 }
 
-impl Edit for RemoveUnusedAllowApis {
+impl Edit for AllowApiForFirstParty {
     fn title(&self) -> String {
-        "Remove unused allowed APIs".to_owned()
+        let api = &self.api;
+        match self.scope.config_selector() {
+            Some(selector) => {
+                format!("Allow all first-party packages to use `{api}` API ({selector})")
+            }
+            None => format!("Allow all first-party packages to use `{api}` API"),
+        }
+    }
+
+    fn id(&self) -> String {
+        format!(
+            "allow-api-first-party:{}:{}",
+            self.api,
+            scope_id(self.scope)
+        )
     }
 
     fn help(&self) -> Cow<'static, str> {
-        "Remove these APIs from the list of APIs that this package is allowed to used.".into()
+        "Grant the API in [defaults.first_party], which applies to all workspace members, path \
+         dependencies and packages matching `common.first_party`. Grants of the API to individual \
+         first-party packages are removed, since they're no longer needed."
+            .into()
     }
 
-    fn apply(&self, editor: &mut ConfigEditor, _opts: &EditOpts) -> Result<()> {
-        let Some(table) = editor.opt_pkg_table(&self.unused.perm_sel)? else {
-            return Ok(());
-        };
-        let Some(allow_apis) = get_array(table, "allow_apis")? else {
-            return Ok(());
-        };
-        for api in &self.unused.apis {
-            let index_and_entry = allow_apis
-                .iter()
-                .enumerate()
-                .find(|(_, allowed)| allowed.as_str() == Some(api.to_string().as_str()));
-            if let Some((index, _)) = index_and_entry {
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.first_party_defaults_table(self.scope)?;
+        add_to_array(table, "allow_apis", &[&self.api], opts.comment.as_deref())?;
+        remove_api_expiry(table, &self.api)?;
+        for pkg_name in &self.packages {
+            let perm_sel = PermSel::for_primary(pkg_name.0.clone()).clone_with_scope(self.scope);
+            for perm_sel in std::iter::once(perm_sel.clone()).chain(perm_sel.descendants()) {
+                RemoveUnusedAllowApis {
+                    unused: UnusedAllowApi {
+                        perm_sel,
+                        apis: vec![self.api.clone()],
+                    },
+                }
+                .apply(editor, opts)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Permits just the particular usages in a problem, without granting the API to the package.
+struct AcknowledgeUsages {
+    usage: ApiUsages,
+    /// The directory of the package, as used by the checker when matching acknowledgements.
+    pkg_dir: Option<PathBuf>,
+}
+
+impl AcknowledgeUsages {
+    /// Returns the distinct source paths and referenced names of our usages. Paths are relative to
+    /// the directory of the package.
+    fn entries(&self) -> BTreeSet<(PathBuf, String)> {
+        self.usage
+            .usages
+            .iter()
+            .map(|usage| {
+                let filename = usage.source_location.filename();
+                (
+                    acknowledgements::usage_path(self.pkg_dir.as_deref(), filename).to_owned(),
+                    usage.to_name.to_string(),
+                )
+            })
+            .collect()
+    }
+}
+
+impl Edit for AcknowledgeUsages {
+    fn title(&self) -> String {
+        format!(
+            "Acknowledge only these usages of `{}` by `{}`",
+            self.usage.api_name,
+            self.usage.perm_sel()
+        )
+    }
+
+    fn id(&self) -> String {
+        format!(
+            "acknowledge-usages:{}:{}",
+            self.usage.perm_sel(),
+            self.usage.api_name
+        )
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Permits just these usages, without granting the API to the package. Select this if \
+         you've reviewed these usages and are happy with them, but want any other usage of the \
+         API by the package to be reported for review."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let pkg = self.usage.perm_sel().to_string();
+        let api = self.usage.api_name.to_string();
+        let acknowledgements = editor
+            .document
+            .as_table_mut()
+            .entry("acknowledgements")
+            .or_insert_with(|| Item::ArrayOfTables(Default::default()))
+            .as_array_of_tables_mut()
+            .ok_or_else(|| anyhow!("`acknowledgements` should be an array of tables"))?;
+        for (path, symbol) in self.entries() {
+            let path = path.to_string_lossy();
+            let fields = [
+                ("pkg", pkg.as_str()),
+                ("api", api.as_str()),
+                ("path", path.as_ref()),
+                ("symbol", symbol.as_str()),
+            ];
+            if acknowledgements.iter().any(|existing| {
+                fields
+                    .iter()
+                    .all(|(key, value)| existing.get(key).and_then(Item::as_str) == Some(value))
+            }) {
+                continue;
+            }
+            let mut table = toml_edit::Table::new();
+            for (key, value) in fields {
+                table.insert(key, toml_edit::value(value));
+            }
+            if let Some(comment) = opts.comment.as_deref() {
+                table.decor_mut().set_prefix(format!("\n# {comment}\n"));
+            }
+            acknowledgements.push(table);
+        }
+        Ok(())
+    }
+}
+
+struct RemoveUnusedAllowApis {
+    unused: UnusedAllowApi,
+}
+
+impl Edit for RemoveUnusedAllowApis {
+    fn title(&self) -> String {
+        "Remove unused allowed APIs".to_owned()
+    }
+
+    fn id(&self) -> String {
+        let apis: Vec<String> = self.unused.apis.iter().map(|api| api.to_string()).collect();
+        format!(
+            "remove-allow-apis:{}:{}",
+            self.unused.perm_sel,
+            apis.join(",")
+        )
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Remove these APIs from the list of APIs that this package is allowed to used.".into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, _opts: &EditOpts) -> Result<()> {
+        let Some(table) = editor.opt_pkg_table(&self.unused.perm_sel)? else {
+            return Ok(());
+        };
+        for api in &self.unused.apis {
+            remove_api_expiry(table, api)?;
+        }
+        let Some(allow_apis) = get_array(table, "allow_apis")? else {
+            return Ok(());
+        };
+        for api in &self.unused.apis {
+            let index_and_entry = allow_apis
+                .iter()
+                .enumerate()
+                .find(|(_, allowed)| allowed.as_str() == Some(api.to_string().as_str()));
+            if let Some((index, _)) = index_and_entry {
                 allow_apis.remove(index);
             }
         }
@@ -881,6 +1534,46 @@ impl Edit for RemoveUnusedAllowApis {
     }
 }
 
+struct ExtendApiGrant {
+    perm_sel: PermSel,
+    api: ApiName,
+    until: toml::value::Datetime,
+}
+
+impl Edit for ExtendApiGrant {
+    fn title(&self) -> String {
+        format!(
+            "Extend expired grant of `{}` API to `{}` until {}",
+            self.api, self.perm_sel, self.until
+        )
+    }
+
+    fn id(&self) -> String {
+        // The date isn't included, since it depends on when the fix was generated.
+        format!("extend-api-grant:{}:{}", self.perm_sel, self.api)
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        format!(
+            "Permission to use this API was granted temporarily and has expired. This extends the \
+             grant by a further {} days, after which use of the API will be reported again.",
+            expiry::EXTENSION_DAYS
+        )
+        .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, _opts: &EditOpts) -> Result<()> {
+        let table = editor.pkg_table(&self.perm_sel)?;
+        let until = table
+            .entry("allow_apis_until")
+            .or_insert_with(|| toml_edit::value(toml_edit::InlineTable::new()))
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("allow_apis_until should be a table"))?;
+        until.insert(self.api.as_ref(), toml_edit::value(self.until));
+        Ok(())
+    }
+}
+
 struct RemoveUnusedPkgConfig {
     perm_sel: PermSel,
 }
@@ -890,17 +1583,16 @@ impl Edit for RemoveUnusedPkgConfig {
         "Remove unused crate configuration".to_owned()
     }
 
+    fn id(&self) -> String {
+        format!("remove-pkg-config:{}", self.perm_sel)
+    }
+
     fn help(&self) -> Cow<'static, str> {
         "Remove the configuration for this package.".into()
     }
 
     fn apply(&self, editor: &mut ConfigEditor, _opts: &EditOpts) -> Result<()> {
-        let mut path: Vec<_> = pkg_path(&self.perm_sel).collect();
-        let last_part = path.pop().unwrap();
-        if let Some(parent_table) = editor.opt_table(path.into_iter())? {
-            parent_table.remove(last_part);
-        }
-        Ok(())
+        editor.remove_pkg_config(&self.perm_sel)
     }
 }
 
@@ -913,6 +1605,10 @@ impl Edit for UpdateConfigVersion {
         format!("Update config to version {}", self.version.number)
     }
 
+    fn id(&self) -> String {
+        format!("update-config-version:{}", self.version.number)
+    }
+
     fn help(&self) -> Cow<'static, str> {
         "Edit config to just use the newer version and accept any changes in behaviour.".into()
     }
@@ -934,6 +1630,13 @@ impl Edit for UpdateConfigVersionPreserveBehaviour {
         )
     }
 
+    fn id(&self) -> String {
+        format!(
+            "update-config-version-preserve-behaviour:{}",
+            self.version.number
+        )
+    }
+
     fn help(&self) -> Cow<'static, str> {
         "Edit config to use the newer version and also adjust the config to preserve the old \
          behaviour"
@@ -945,6 +1648,21 @@ impl Edit for UpdateConfigVersionPreserveBehaviour {
     }
 }
 
+/// Removes any expiry date for `api` from `table`'s `allow_apis_until`.
+fn remove_api_expiry(table: &mut toml_edit::Table, api: &ApiName) -> Result<()> {
+    let Some(item) = table.get_mut("allow_apis_until") else {
+        return Ok(());
+    };
+    let until = item
+        .as_table_like_mut()
+        .ok_or_else(|| anyhow!("allow_apis_until should be a table"))?;
+    until.remove(api.as_ref());
+    if until.is_empty() {
+        table.remove("allow_apis_until");
+    }
+    Ok(())
+}
+
 fn get_array<'table>(
     table: &'table mut toml_edit::Table,
     array_name: &str,
@@ -991,6 +1709,10 @@ impl Edit for AllowProcMacro {
         format!("Allow proc macro `{}`", self.perm_sel)
     }
 
+    fn id(&self) -> String {
+        format!("allow-proc-macro:{}", self.perm_sel)
+    }
+
     fn help(&self) -> Cow<'static, str> {
         "Allow this crate to be a proc macro. Proc macros can generate arbitrary code. They're \
          also not currently run in a sandbox."
@@ -1004,6 +1726,33 @@ impl Edit for AllowProcMacro {
     }
 }
 
+struct AllowGeneratedCode {
+    perm_sel: PermSel,
+}
+
+impl Edit for AllowGeneratedCode {
+    fn title(&self) -> String {
+        format!("Allow generated code in `{}`", self.perm_sel)
+    }
+
+    fn id(&self) -> String {
+        format!("allow-generated-code:{}", self.perm_sel)
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Stop reporting that this crate includes code generated by its build script. Generated \
+         code isn't present in the package's source, so should be reviewed by other means, e.g. by \
+         reviewing the build script that produces it."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.pkg_table(&self.perm_sel)?;
+        set_table_value(table, "allow_generated_code", toml_edit::value(true), opts);
+        Ok(())
+    }
+}
+
 struct AllowBuildInstruction {
     perm_sel: PermSel,
     instruction: String,
@@ -1017,6 +1766,13 @@ impl Edit for AllowBuildInstruction {
         )
     }
 
+    fn id(&self) -> String {
+        format!(
+            "allow-build-instruction:{}:{}",
+            self.perm_sel, self.instruction
+        )
+    }
+
     fn help(&self) -> Cow<'static, str> {
         "Allow this crate's build.rs to emit build instructions that match the specified pattern. \
          Some build instructions can be used to add arguments to the linker, which can then be \
@@ -1044,6 +1800,10 @@ impl Edit for DisableSandbox {
         format!("Disable sandbox for `{}`", self.perm_sel)
     }
 
+    fn id(&self) -> String {
+        format!("disable-sandbox:{}", self.perm_sel)
+    }
+
     fn help(&self) -> Cow<'static, str> {
         "Don't run this crate's build script (build.rs) in a sandbox. You might select this \
          option if the build script is doing something weird like writing to the source \
@@ -1067,6 +1827,10 @@ impl Edit for AllowUnsafe {
         format!("Allow package `{}` to use unsafe code", self.perm_sel)
     }
 
+    fn id(&self) -> String {
+        format!("allow-unsafe:{}", self.perm_sel)
+    }
+
     fn help(&self) -> Cow<'static, str> {
         "Allow this crate to use unsafe code. With unsafe code, this crate could do just about \
          anything, so this is like a bit like a wildcard permission. Crates that use unsafe \
@@ -1092,6 +1856,10 @@ impl Edit for SandboxAllowNetwork {
         format!("Permit network from sandbox for `{}`", self.perm_sel)
     }
 
+    fn id(&self) -> String {
+        format!("sandbox-allow-network:{}", self.perm_sel)
+    }
+
     fn help(&self) -> Cow<'static, str> {
         "Allow this crate's build script (build.rs) to access the network. This might be necessary \
          if the build script is downloading stuff from the Internet."
@@ -1105,6 +1873,188 @@ impl Edit for SandboxAllowNetwork {
     }
 }
 
+/// A change to the sandbox configuration for a single test.
+#[derive(Clone, Copy)]
+enum SandboxChange {
+    AllowNetwork,
+    Disable,
+}
+
+struct TestSandboxOverride {
+    perm_sel: PermSel,
+    test: String,
+    change: SandboxChange,
+}
+
+impl Edit for TestSandboxOverride {
+    fn title(&self) -> String {
+        match self.change {
+            SandboxChange::AllowNetwork => {
+                format!("Permit network from sandbox for test `{}`", self.test)
+            }
+            SandboxChange::Disable => format!("Disable sandbox for test `{}`", self.test),
+        }
+    }
+
+    fn id(&self) -> String {
+        let change = match self.change {
+            SandboxChange::AllowNetwork => "allow-network",
+            SandboxChange::Disable => "disable-sandbox",
+        };
+        format!("test-{change}:{}:{}", self.perm_sel, self.test)
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        match self.change {
+            SandboxChange::AllowNetwork => {
+                "Allow just this test to access the network. When this test fails with the other \
+                 tests, it will be rerun by itself with network access permitted."
+            }
+            SandboxChange::Disable => {
+                "Don't sandbox this test. When this test fails with the other tests, it will be \
+                 rerun by itself without a sandbox. Other tests remain sandboxed."
+            }
+        }
+        .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.table(pkg_path(&self.perm_sel).chain([
+            "sandbox",
+            "per_test",
+            self.test.as_str(),
+        ]))?;
+        match self.change {
+            SandboxChange::AllowNetwork => {
+                set_table_value(table, "allow_network", toml_edit::value(true), opts)
+            }
+            SandboxChange::Disable => {
+                set_table_value(table, "kind", toml_edit::value("Disabled"), opts)
+            }
+        }
+        Ok(())
+    }
+}
+
+struct ProvideStdin {
+    perm_sel: PermSel,
+    input: &'static str,
+}
+
+impl Edit for ProvideStdin {
+    fn title(&self) -> String {
+        if self.input.is_empty() {
+            format!("Provide empty stdin to `{}`", self.perm_sel)
+        } else {
+            format!("Provide {:?} on stdin to `{}`", self.input, self.perm_sel)
+        }
+    }
+
+    fn id(&self) -> String {
+        let input = if self.input.is_empty() {
+            "empty"
+        } else {
+            self.input.trim_end()
+        };
+        format!("provide-stdin:{}:{input}", self.perm_sel)
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Each time this runs in the sandbox, supply the specified input on stdin. Useful if it \
+         prompts for something like license acceptance. You can edit `stdin` in cackle.toml if \
+         different input is required."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.pkg_sandbox_table(&self.perm_sel)?;
+        set_table_value(table, "stdin", toml_edit::value(self.input), opts);
+        Ok(())
+    }
+}
+
+/// Requests that the next run of a binary that was waiting for input be attached to the user's
+/// terminal. This doesn't change the config.
+struct AttachTerminal {
+    marker: PathBuf,
+}
+
+impl Edit for AttachTerminal {
+    fn title(&self) -> String {
+        "Rerun once attached to the terminal".to_owned()
+    }
+
+    fn id(&self) -> String {
+        format!("attach-terminal:{}", self.marker.display())
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Rerun in the sandbox, but with your terminal attached so that you can respond to any \
+         prompts. Only applies to this run. Output is still captured, so build scripts work as \
+         normal."
+            .into()
+    }
+
+    fn apply(&self, _editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        if opts.is_final {
+            crate::fs::write(&self.marker, "")?;
+        }
+        Ok(())
+    }
+
+    fn resolve_problem_if_edit_is_empty(&self) -> bool {
+        false
+    }
+}
+
+struct DowngradeProblem {
+    perm_sel: PermSel,
+    kind: DowngradableProblem,
+}
+
+impl DowngradeProblem {
+    fn for_problem(problem: &Problem) -> Option<Box<dyn Edit>> {
+        let (perm_sel, kind) = problem.downgrade_selector()?;
+        Some(Box::new(DowngradeProblem { perm_sel, kind }))
+    }
+}
+
+impl Edit for DowngradeProblem {
+    fn title(&self) -> String {
+        format!(
+            "Downgrade `{}` to a warning for `{}`",
+            self.kind.as_ref(),
+            self.perm_sel
+        )
+    }
+
+    fn id(&self) -> String {
+        format!("downgrade:{}:{}", self.perm_sel, self.kind.as_ref())
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        match self.kind {
+            DowngradableProblem::CapabilityManifestViolation => {
+                "Report behaviour of this package that isn't declared in its capability manifest \
+                 as warnings rather than errors. The behaviour still needs to be permitted by \
+                 your config. Consider adding a comment explaining why the discrepancy is OK."
+            }
+            _ => {
+                "Report problems of this kind for this package as warnings rather than errors. \
+                 This is intended for when a heuristic gives false positives for a particular \
+                 package. Consider adding a comment explaining why you believe the problem to be \
+                 a false positive."
+            }
+        }
+        .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.pkg_table(&self.perm_sel)?;
+        add_to_array(table, "downgrade", &[self.kind], opts.comment.as_deref())
+    }
+}
+
 impl Display for dyn Edit {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.title())
@@ -1129,6 +2079,7 @@ fn set_table_value(
 mod tests {
     use super::ConfigEditor;
     use super::Edit;
+    use super::EditOpts;
     use super::InlineStdApi;
     use crate::config::permissions::PermSel;
     use crate::config::permissions::PermissionScope;
@@ -1140,10 +2091,18 @@ mod tests {
     use crate::crate_index::CrateSel;
     use crate::crate_index::PackageId;
     use crate::location::SourceLocation;
+    use crate::problem::ApiChecklist;
     use crate::problem::ApiUsages;
+    use crate::problem::AvailableApi;
+    use crate::problem::ChecklistApi;
+    use crate::problem::ChecklistEntry;
     use crate::problem::DisallowedBuildInstruction;
+    use crate::problem::GeneratedCode;
+    use crate::problem::PossibleExportedApi;
     use crate::problem::Problem;
     use crate::proxy::rpc::BinExecutionOutput;
+    use crate::symbol::Symbol;
+    use crate::test_output::FailedTest;
     use indoc::indoc;
     use std::path::Path;
     use std::path::PathBuf;
@@ -1158,6 +2117,19 @@ mod tests {
         })
     }
 
+    #[test]
+    fn recommended_config_imports_env_mutate() {
+        let mut editor = ConfigEditor::from_toml_string("").unwrap();
+        super::CreateRecommendedConfig
+            .apply(&mut editor, &Default::default())
+            .unwrap();
+        let toml: toml::Table = editor.to_toml().parse().unwrap();
+        let imports = toml["common"]["import_std"].as_array().unwrap();
+        for api in ["fs", "net", "process", "env-mutate"] {
+            assert!(imports.iter().any(|imp| imp.as_str() == Some(api)), "{api}");
+        }
+    }
+
     #[track_caller]
     fn check(initial_config: &str, problem: &Problem, fix_index: usize, expected: &str) {
         let config = crate::config::testing::parse(initial_config).unwrap();
@@ -1173,6 +2145,82 @@ mod tests {
         assert_eq!(editor.to_toml(), expected);
     }
 
+    #[test]
+    fn acknowledge_usages() {
+        let pkg_dir = PathBuf::from("/ws/crab1");
+        let usage = |filename: PathBuf, name: &str| crate::checker::ApiUsage {
+            bin_location: crate::checker::BinLocation {
+                address: 0,
+                symbol_start: 0,
+            },
+            bin_path: Arc::from(Path::new("bin")),
+            artifact: crate::link_info::Artifact {
+                path: Arc::from(Path::new("bin")),
+                kind: crate::link_info::ArtifactKind::Bin,
+            },
+            permission_scope: PermissionScope::Build,
+            source_location: SourceLocation::new(filename, 10, None),
+            outer_location: None,
+            from: crate::names::SymbolOrDebugName::Symbol(Symbol::borrowed(&[])),
+            to_name: crate::names::split_simple(name),
+            to: crate::names::SymbolOrDebugName::Symbol(Symbol::borrowed(&[])),
+            to_source: crate::symbol_graph::NameSource::Symbol(Symbol::borrowed(b"")),
+            debug_data: None,
+        };
+        let problem = Problem::DisallowedApiUsage(ApiUsages {
+            pkg_id: pkg_id("crab1"),
+            scope: PermissionScope::Build,
+            api_name: ApiName::from("net"),
+            usages: vec![
+                usage(pkg_dir.join("src/lib.rs"), "std::net::TcpStream::connect"),
+                usage(pkg_dir.join("src/lib.rs"), "std::net::TcpStream::connect"),
+                usage(
+                    PathBuf::from("/elsewhere/net.rs"),
+                    "std::net::UdpSocket::bind",
+                ),
+            ],
+        });
+        let initial_config = indoc! {r#"
+            [api.net]
+            include = ["std::net"]
+        "#};
+        let mut config = crate::config::testing::parse(initial_config).unwrap();
+        // Paths are made relative to the package directory from the crate index, not by looking
+        // for a Cargo.toml, since that's what the checker matches against.
+        Arc::get_mut(&mut config)
+            .unwrap()
+            .pkg_dirs
+            .insert(pkg_id("crab1"), pkg_dir.clone());
+        let edits = fixes_for_problem(&problem, &config);
+        let edit = edits
+            .iter()
+            .find(|edit| edit.title().starts_with("Acknowledge"))
+            .unwrap();
+        let expected = indoc! {r#"
+            [api.net]
+            include = ["std::net"]
+
+            [[acknowledgements]]
+            pkg = "crab1.build"
+            api = "net"
+            path = "/elsewhere/net.rs"
+            symbol = "std::net::UdpSocket::bind"
+
+            [[acknowledgements]]
+            pkg = "crab1.build"
+            api = "net"
+            path = "src/lib.rs"
+            symbol = "std::net::TcpStream::connect"
+        "#};
+        let mut editor = ConfigEditor::from_toml_string(initial_config).unwrap();
+        for _ in 0..2 {
+            edit.apply(&mut editor, &Default::default()).unwrap();
+            assert_eq!(editor.to_toml(), expected);
+        }
+        let config = crate::config::testing::parse(expected).unwrap();
+        assert_eq!(config.raw.acknowledgements.len(), 2);
+    }
+
     #[test]
     fn fix_missing_api_no_existing_config() {
         check(
@@ -1205,6 +2253,91 @@ mod tests {
         );
     }
 
+    #[test]
+    fn fix_missing_api_proc_macro() {
+        let problem = disallowed_api(pkg_id("crab1"), PermissionScope::ProcMacro, "fs");
+        assert_eq!(
+            problem.to_string(),
+            "Proc macro `crab1` uses the `fs` API at build time"
+        );
+        check(
+            "",
+            &problem,
+            0,
+            indoc! {r#"
+                [pkg.crab1]
+                proc-macro.allow_apis = [
+                    "fs",
+                ]
+            "#,
+            },
+        );
+    }
+
+    #[test]
+    fn fix_ids() {
+        let config = crate::config::testing::parse(indoc! {r#"
+            [api.fs]
+            include = ["std::fs"]
+        "#})
+        .unwrap();
+        let problem = disallowed_api(pkg_id("crab1"), PermissionScope::FromBuild, "fs");
+        let ids: Vec<_> = fixes_for_problem(&problem, &config)
+            .iter()
+            .map(|fix| fix.id())
+            .collect();
+        assert_eq!(
+            ids,
+            vec!["allow-api:crab1:fs:from.build", "allow-api:crab1:fs:all"]
+        );
+    }
+
+    #[test]
+    fn allow_api_for_first_party() {
+        let initial_config = indoc! {r#"
+            first_party = ["crab*"]
+            import_std = ["fs"]
+            [pkg.crab1]
+            allow_unsafe = true
+            [pkg.crab2]
+            build.allow_apis = [
+                "fs",
+            ]
+            [pkg.other]
+        "#};
+        let problem = disallowed_api(pkg_id("crab1"), PermissionScope::Build, "fs");
+        let config = crate::config::testing::parse(initial_config).unwrap();
+        let fixes = fixes_for_problem(&problem, &config);
+        let index = fixes
+            .iter()
+            .position(|fix| fix.title() == "Allow all first-party packages to use `fs` API (build)")
+            .unwrap();
+        check(
+            initial_config,
+            &problem,
+            index,
+            indoc! {r#"
+                first_party = ["crab*"]
+                import_std = ["fs"]
+                [pkg.crab1]
+                allow_unsafe = true
+                [pkg.crab2]
+                [pkg.other]
+
+                [defaults.first_party]
+                build.allow_apis = [
+                    "fs",
+                ]
+            "#},
+        );
+
+        // Third-party packages don't get offered the fix.
+        let problem = disallowed_api(pkg_id("other"), PermissionScope::All, "fs");
+        assert!(fixes_for_problem(&problem, &config)
+            .iter()
+            .all(|fix| !fix.title().contains("first-party")));
+    }
+
     #[test]
     fn allow_api_existing_sub_selector() {
         check(
@@ -1235,6 +2368,62 @@ mod tests {
         );
     }
 
+    #[test]
+    fn expired_api_grant() {
+        let initial_config = indoc! {r#"
+            import_std = ["fs", "net"]
+            [pkg.crab1]
+            allow_apis = [
+                "fs",
+            ]
+            allow_apis_until = { net = 2000-01-01 }
+        "#};
+        let problem = disallowed_api(pkg_id("crab1"), PermissionScope::All, "net");
+
+        let config = crate::config::testing::parse(initial_config).unwrap();
+        let fixes = fixes_for_problem(&problem, &config);
+        assert!(fixes[0].title().starts_with("Extend expired grant"));
+        let mut editor = ConfigEditor::from_toml_string(initial_config).unwrap();
+        fixes[0].apply(&mut editor, &Default::default()).unwrap();
+        let extended = crate::config::testing::parse(&editor.to_toml()).unwrap();
+        let perm_sel = PermSel::for_primary("crab1");
+        let pkg_config = extended.permissions_no_inheritance.get(&perm_sel).unwrap();
+        assert!(pkg_config.allow_apis.contains(&ApiName::from("net")));
+        assert!(pkg_config.expired_apis.is_empty());
+
+        // Allowing the API permanently removes the expiry.
+        check(
+            initial_config,
+            &problem,
+            1,
+            indoc! {r#"
+                import_std = ["fs", "net"]
+                [pkg.crab1]
+                allow_apis = [
+                    "fs",
+                    "net",
+                ]
+            "#},
+        );
+
+        // If the API is no longer used, the expired grant can be removed.
+        check(
+            initial_config,
+            &Problem::UnusedAllowApi(crate::problem::UnusedAllowApi {
+                perm_sel,
+                apis: vec![ApiName::from("net")],
+            }),
+            0,
+            indoc! {r#"
+                import_std = ["fs", "net"]
+                [pkg.crab1]
+                allow_apis = [
+                    "fs",
+                ]
+            "#},
+        );
+    }
+
     #[test]
     fn fix_disallowed_build_instruction() {
         let problem = Problem::DisallowedBuildInstruction(DisallowedBuildInstruction {
@@ -1317,6 +2506,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn fix_allow_generated_code() {
+        check(
+            "",
+            &Problem::GeneratedCode(GeneratedCode {
+                crate_sel: CrateSel::primary(pkg_id("crab1")),
+                paths: vec![PathBuf::from("/target/debug/build/crab1-1234/out/gen.rs")],
+            }),
+            0,
+            indoc! {r#"
+                [pkg.crab1]
+                allow_generated_code = true
+            "#,
+            },
+        );
+    }
+
     #[test]
     fn fix_allow_unsafe() {
         check(
@@ -1349,6 +2555,8 @@ mod tests {
                 },
                 binary_path: PathBuf::new(),
                 sandbox_config_display: None,
+                waiting_for_input: None,
+                failed_tests: Vec::new(),
             },
             crate_sel,
         });
@@ -1374,6 +2582,152 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_failed() {
+        let problem = |output: &str| {
+            Problem::TestFailed(crate::problem::TestFailure {
+                crate_sel: CrateSel {
+                    pkg_id: pkg_id("crab1"),
+                    kind: crate::crate_index::CrateKind::Test,
+                },
+                test: FailedTest {
+                    name: "tests::downloads".to_owned(),
+                    output: output.to_owned(),
+                },
+                sandbox_config: SandboxConfig {
+                    kind: Some(crate::config::SandboxKind::Bubblewrap),
+                    ..Default::default()
+                },
+            })
+        };
+        let network_failure = problem("Network is unreachable (os error 101)");
+        check(
+            "",
+            &network_failure,
+            0,
+            indoc! {r#"
+                [pkg.crab1.test.sandbox.per_test."tests::downloads"]
+                allow_network = true
+            "#,
+            },
+        );
+        check(
+            "",
+            &network_failure,
+            1,
+            indoc! {r#"
+                [pkg.crab1.test.sandbox.per_test."tests::downloads"]
+                kind = "Disabled"
+            "#,
+            },
+        );
+        check(
+            "",
+            &network_failure,
+            2,
+            indoc! {r#"
+                [pkg.crab1.test.sandbox]
+                allow_network = true
+            "#,
+            },
+        );
+
+        // When the test already has network access, we don't offer to grant it again.
+        let config = crate::config::testing::parse(indoc! {r#"
+            [pkg.crab1.test.sandbox.per_test."tests::downloads"]
+            allow_network = true
+        "#})
+        .unwrap();
+        let Problem::TestFailed(mut failure) = network_failure else {
+            unreachable!();
+        };
+        failure.sandbox_config = config
+            .permissions
+            .sandbox_config_for_package(&PermSel::for_non_build_output(&failure.crate_sel));
+        let titles: Vec<_> = fixes_for_problem(&Problem::TestFailed(failure), &config)
+            .iter()
+            .map(|fix| fix.title())
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                "Disable sandbox for test `tests::downloads`",
+                "Permit network from sandbox for `crab1.test`",
+                "Disable sandbox for `crab1.test`",
+            ]
+        );
+
+        // Filesystem denials aren't fixed by permitting network access.
+        let config = crate::config::testing::parse("").unwrap();
+        let fixes = fixes_for_problem(&problem("Read-only file system (os error 30)"), &config);
+        assert_eq!(fixes.len(), 2);
+    }
+
+    #[test]
+    fn waiting_for_input() {
+        let crate_sel = CrateSel::build_script(pkg_id("crab1"));
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("attach");
+        let problem = Problem::WaitingForInput(crate::problem::BinExecutionFailed {
+            output: BinExecutionOutput {
+                exit_code: -1,
+                stdout: b"Accept license? [y/n]".to_vec(),
+                stderr: Vec::new(),
+                crate_sel: crate_sel.clone(),
+                sandbox_config: SandboxConfig {
+                    kind: Some(crate::config::SandboxKind::Bubblewrap),
+                    ..Default::default()
+                },
+                binary_path: PathBuf::new(),
+                sandbox_config_display: None,
+                waiting_for_input: Some(marker.clone()),
+                failed_tests: Vec::new(),
+            },
+            crate_sel,
+        });
+        check(
+            "",
+            &problem,
+            1,
+            indoc! {r#"
+                [pkg.crab1.build.sandbox]
+                stdin = """
+                y
+                """
+            "#,
+            },
+        );
+        check(
+            "",
+            &problem,
+            2,
+            indoc! {r#"
+                [pkg.crab1.build.sandbox]
+                stdin = ""
+            "#,
+            },
+        );
+
+        // Attaching the terminal doesn't change the config, but requests it via the marker file.
+        let config = crate::config::testing::parse("").unwrap();
+        let fixes = fixes_for_problem(&problem, &config);
+        let mut editor = ConfigEditor::from_toml_string("").unwrap();
+        fixes[0].apply(&mut editor, &EditOpts::default()).unwrap();
+        assert!(!marker.exists());
+        fixes[0]
+            .apply(
+                &mut editor,
+                &EditOpts {
+                    is_final: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(editor.to_toml(), "");
+        assert!(marker.exists());
+        assert!(!fixes[0].resolve_problem_if_edit_is_empty());
+    }
+
     #[test]
     fn unused_allow_api() {
         let failure = Problem::UnusedAllowApi(crate::problem::UnusedAllowApi {
@@ -1502,12 +2856,115 @@ mod tests {
         );
     }
 
+    #[test]
+    fn downgrade_possible_exported_api() {
+        let problem = Problem::PossibleExportedApi(PossibleExportedApi {
+            pkg_id: pkg_id("crab1"),
+            api: ApiName::new("fs"),
+            symbol: Symbol::borrowed(b"crab1::fs::read"),
+        });
+        let expected = indoc! {r#"
+            [pkg.crab1]
+            downgrade = [
+                "possible_exported_api",
+            ]
+        "#};
+        check("", &problem, 2, expected);
+
+        let config = crate::config::testing::parse(expected).unwrap();
+        assert!(problem.is_downgraded(&config.permissions));
+        assert_eq!(
+            problem.severity(&config.permissions),
+            crate::problem::Severity::Warning
+        );
+    }
+
     fn apply_edit_and_parse(toml: &str, edit: &InlineStdApi) -> Arc<Config> {
         let mut editor = ConfigEditor::from_toml_string(toml).unwrap();
         edit.apply(&mut editor, &Default::default()).unwrap();
         crate::config::testing::parse(&editor.to_toml()).unwrap()
     }
 
+    #[test]
+    fn select_apis_from_checklist() {
+        let checklist = ApiChecklist {
+            entries: vec![
+                ChecklistEntry {
+                    api: ChecklistApi::Std(ApiName::new("fs")),
+                    selected: false,
+                },
+                ChecklistEntry {
+                    api: ChecklistApi::Std(ApiName::new("net")),
+                    selected: false,
+                },
+                ChecklistEntry {
+                    api: ChecklistApi::Exported(AvailableApi {
+                        pkg_id: pkg_id("crab1"),
+                        api: ApiName::new("foo"),
+                        config: Default::default(),
+                    }),
+                    selected: false,
+                },
+            ],
+        };
+        let config = crate::config::testing::parse("").unwrap();
+        let problem = Problem::SelectApis(checklist);
+
+        // With nothing selected, there's no option to inline, so the first toggle follows import.
+        let fixes = fixes_for_problem(&problem, &config);
+        assert_eq!(fixes.len(), 4);
+        assert!(!fixes[1].resolve_problem_if_edit_is_empty());
+        let toggled = fixes[1].replacement_problems();
+        assert_eq!(toggled.len(), 1);
+        let problem = toggled[0].clone();
+        let Problem::SelectApis(checklist) = &problem else {
+            panic!("Unexpected replacement problem {problem:?}");
+        };
+        assert_eq!(
+            checklist
+                .selected()
+                .map(|api| api.to_string())
+                .collect::<Vec<_>>(),
+            vec!["fs".to_owned()]
+        );
+
+        check(
+            "",
+            &problem,
+            0,
+            indoc! {r#"
+                [common]
+                import_std = [
+                    "fs",
+                ]
+
+                [pkg.crab1]
+                import = [
+                ]
+            "#,
+            },
+        );
+    }
+
+    #[test]
+    fn preset_config() {
+        // Recommended, server, CLI, then embedded.
+        check(
+            "",
+            &Problem::MissingConfiguration(PathBuf::from("cackle.toml")),
+            3,
+            indoc! {r#"
+                [common]
+                version = 2
+                import_std = [
+                    "net",
+                    "panic",
+                ]
+            "#,
+            },
+        );
+    }
+
     #[test]
     fn inline_std_api() {
         let fs_api = ApiName::new("fs");
@@ -0,0 +1,517 @@
+//! Reading and writing of the config file that edits are made to. All changes made during a run are
+//! recorded as a patch. With `--no-write-config`, edits are kept in memory rather than being
+//! written to disk, so that they can be reviewed as a patch.
+
+use crate::audit;
+use crate::config_editor::ConfigEditor;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ValueEnum;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+/// How many lines of unchanged context to include around each change in a diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// How many times `edit` reapplies edits after the config was changed by something else before
+/// giving up, so that something that keeps rewriting the config can't keep us looping forever.
+const MAX_REAPPLY_ATTEMPTS: usize = 10;
+
+/// Returns where the patch of the changes made to the config during a run is written.
+pub(crate) fn changes_patch_path(target_dir: &Path) -> PathBuf {
+    target_dir.join("cackle").join("config-changes.patch")
+}
+
+/// What to do when the config file is changed by something else, e.g. the user editing it by hand,
+/// between us reading it and writing our edits back.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum ConflictPolicy {
+    /// Ask whether to reload the config and reapply our edits to it. If there's no one to ask, the
+    /// edits are abandoned.
+    #[default]
+    Prompt,
+    /// Reload the config and reapply our edits to it.
+    Reapply,
+    /// Abandon our edits, leaving the config as it was changed.
+    Abort,
+}
+
+/// The error returned when our edits weren't written because the config file was changed by
+/// something else after we read it.
+#[derive(Debug)]
+pub(crate) struct ConfigChanged {
+    path: PathBuf,
+}
+
+impl std::fmt::Display for ConfigChanged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "`{}` was changed by something else while cackle was editing it, so cackle's edits \
+             weren't written",
+            self.path.display()
+        )
+    }
+}
+
+impl std::error::Error for ConfigChanged {}
+
+/// Returns whether `error` is because the config file changed while we were editing it.
+pub(crate) fn is_conflict(error: &anyhow::Error) -> bool {
+    error.is::<ConfigChanged>()
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ConfigFile {
+    path: PathBuf,
+    /// Set unless we were created with `default`, which is only used when we never write.
+    state: Option<Arc<Mutex<State>>>,
+    conflict_policy: ConflictPolicy,
+}
+
+#[derive(Debug)]
+struct State {
+    /// The contents of the config file when we started. None if it didn't exist.
+    original: Option<String>,
+    /// What we most recently wrote to the config file. None if we haven't written to it.
+    written: Option<String>,
+    /// Whether writes should only be made to `written`.
+    in_memory: bool,
+    /// Where to write a patch of the changes that we've made, if anywhere.
+    changes_patch: Option<PathBuf>,
+    /// Where to log the edits that we make, if anywhere.
+    audit_log: Option<PathBuf>,
+}
+
+impl ConfigFile {
+    /// Creates an instance for the config file at `path`. If `in_memory` is true, any subsequent
+    /// writes are only made to an in-memory copy. Otherwise, the accumulated changes are written
+    /// as a patch to `changes_patch` each time the config is written.
+    pub(crate) fn new(path: PathBuf, in_memory: bool, changes_patch: Option<PathBuf>) -> Self {
+        let state = State {
+            original: std::fs::read_to_string(&path).ok(),
+            written: None,
+            in_memory,
+            changes_patch: changes_patch.filter(|_| !in_memory),
+            audit_log: None,
+        };
+        Self {
+            path,
+            state: Some(Arc::new(Mutex::new(state))),
+            conflict_policy: ConflictPolicy::default(),
+        }
+    }
+
+    /// Sets where edits recorded by a `ConfigEditor` are logged when it's written. Nothing is logged
+    /// when edits are kept in memory.
+    pub(crate) fn with_audit_log(self, audit_log: PathBuf) -> Self {
+        if let Some(state) = self.state.as_ref() {
+            let mut state = state.lock().unwrap();
+            if !state.in_memory {
+                state.audit_log = Some(audit_log);
+            }
+        }
+        self
+    }
+
+    /// Sets what `edit` does when the config is changed by something else while we're editing it.
+    pub(crate) fn with_conflict_policy(self, conflict_policy: ConflictPolicy) -> Self {
+        Self {
+            conflict_policy,
+            ..self
+        }
+    }
+
+    pub(crate) fn conflict_policy(&self) -> ConflictPolicy {
+        self.conflict_policy
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns our state if edits are being kept in memory.
+    fn in_memory_state(&self) -> Option<MutexGuard<'_, State>> {
+        let state = self.state.as_ref()?.lock().unwrap();
+        state.in_memory.then_some(state)
+    }
+
+    pub(crate) fn exists(&self) -> bool {
+        match self.in_memory_state() {
+            Some(state) => state.written.is_some() || state.original.is_some(),
+            None => self.path.exists(),
+        }
+    }
+
+    /// Returns the contents of the config file, including any edits that were kept in memory.
+    pub(crate) fn read(&self) -> Result<String> {
+        match self.in_memory_state() {
+            Some(state) => state
+                .written
+                .as_ref()
+                .or(state.original.as_ref())
+                .cloned()
+                .with_context(|| format!("Failed to open {}", self.path.display())),
+            None => std::fs::read_to_string(&self.path)
+                .with_context(|| format!("Failed to open {}", self.path.display())),
+        }
+    }
+
+    /// Returns the contents of the config file like `read`, but returns None rather than an error
+    /// if the file doesn't exist.
+    fn read_if_exists(&self) -> Result<Option<String>> {
+        if let Some(state) = self.in_memory_state() {
+            return Ok(state.written.as_ref().or(state.original.as_ref()).cloned());
+        }
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => {
+                Err(error).with_context(|| format!("Failed to open {}", self.path.display()))
+            }
+        }
+    }
+
+    /// Returns an editor for the config file. If the file doesn't exist, the editor starts empty.
+    /// Writing the editor fails with `ConfigChanged` if the file is changed in the meantime.
+    pub(crate) fn editor(&self) -> Result<ConfigEditor> {
+        let contents = self.read_if_exists()?.unwrap_or_default();
+        Ok(ConfigEditor::from_toml_string(&contents)?.with_base(contents))
+    }
+
+    /// Reads the config, applies `apply` to it and writes the result, returning the editor that was
+    /// written. If the config is changed by something else before we write it, then depending on
+    /// our conflict policy, we either start again from the new contents or fail with
+    /// `ConfigChanged`. We also fail with `ConfigChanged` if we've already started again
+    /// `MAX_REAPPLY_ATTEMPTS` times.
+    pub(crate) fn edit(
+        &self,
+        mut apply: impl FnMut(&mut ConfigEditor) -> Result<()>,
+    ) -> Result<ConfigEditor> {
+        let mut attempts = 0;
+        loop {
+            let mut editor = self.editor()?;
+            apply(&mut editor)?;
+            match editor.write(self) {
+                Ok(()) => return Ok(editor),
+                Err(error)
+                    if is_conflict(&error)
+                        && self.conflict_policy() == ConflictPolicy::Reapply
+                        && attempts < MAX_REAPPLY_ATTEMPTS =>
+                {
+                    attempts += 1;
+                    log::info!("{error}. Reapplying edits");
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Returns an error if the contents of the config file are no longer `base`.
+    pub(crate) fn check_unchanged(&self, base: &str) -> Result<()> {
+        if self.read_if_exists()?.unwrap_or_default() != base {
+            return Err(ConfigChanged {
+                path: self.path.clone(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write(&self, contents: &str) -> Result<()> {
+        let Some(state) = self.state.as_ref() else {
+            return crate::fs::write_atomic(&self.path, contents);
+        };
+        let mut state = state.lock().unwrap();
+        if !state.in_memory {
+            crate::fs::write_atomic(&self.path, contents)?;
+        }
+        state.written = Some(contents.to_owned());
+        if let Some(patch_path) = &state.changes_patch {
+            if let Some(dir) = patch_path.parent() {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+            }
+            crate::fs::write_atomic(patch_path, &self.diff(&state))?;
+        }
+        Ok(())
+    }
+
+    /// Appends `entries` to our audit log, if we have one.
+    pub(crate) fn append_to_audit_log(&self, entries: &[audit::Entry]) -> Result<()> {
+        let Some(state) = self.state.as_ref() else {
+            return Ok(());
+        };
+        match &state.lock().unwrap().audit_log {
+            Some(audit_log) => audit::append(audit_log, entries),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns a unified diff of the changes that would have been made to the config file. Returns
+    /// None if edits aren't being kept in memory.
+    pub(crate) fn in_memory_diff(&self) -> Option<String> {
+        let state = self.in_memory_state()?;
+        Some(self.diff(&state))
+    }
+
+    /// Returns the path to which a patch of our changes was written. Returns None if we haven't
+    /// made any changes or aren't recording them.
+    pub(crate) fn changes_patch(&self) -> Option<PathBuf> {
+        let state = self.state.as_ref()?.lock().unwrap();
+        state.written.as_ref()?;
+        state.changes_patch.clone()
+    }
+
+    /// Returns a unified diff of the changes that we've made to the config file.
+    fn diff(&self, state: &State) -> String {
+        let label = std::env::current_dir()
+            .ok()
+            .and_then(|dir| self.path.strip_prefix(dir).ok())
+            .unwrap_or(&self.path);
+        let updated = state.written.as_ref().or(state.original.as_ref());
+        unified_diff(
+            &label.display().to_string(),
+            state.original.as_deref(),
+            updated.map(String::as_str).unwrap_or_default(),
+        )
+    }
+}
+
+/// Returns a unified diff from `original` to `updated`, which can be applied with `git apply`.
+/// `original` is None if the file didn't previously exist. Returns an empty string if there are no
+/// changes.
+pub(crate) fn unified_diff(label: &str, original: Option<&str>, updated: &str) -> String {
+    use std::fmt::Write;
+
+    let old_lines: Vec<&str> = original.unwrap_or_default().lines().collect();
+    let new_lines: Vec<&str> = updated.lines().collect();
+    let ops = diff::slice(&old_lines, &new_lines);
+    // The 1-based line numbers in the original and updated files at each op.
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut old_line, mut new_line) = (1, 1);
+    for op in &ops {
+        positions.push((old_line, new_line));
+        match op {
+            diff::Result::Both(..) => {
+                old_line += 1;
+                new_line += 1;
+            }
+            diff::Result::Left(..) => old_line += 1,
+            diff::Result::Right(..) => new_line += 1,
+        }
+    }
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, diff::Result::Both(..)))
+        .map(|(index, _)| index)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Group changes into hunks, merging changes whose context would overlap.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &index in &changes {
+        let start = index.saturating_sub(DIFF_CONTEXT);
+        let end = (index + 1 + DIFF_CONTEXT).min(ops.len());
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.1 => hunk.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = String::new();
+    if original.is_some() {
+        writeln!(out, "--- a/{label}").unwrap();
+    } else {
+        writeln!(out, "--- /dev/null").unwrap();
+    }
+    writeln!(out, "+++ b/{label}").unwrap();
+    for (start, end) in hunks {
+        let ops = &ops[start..end];
+        let old_count = ops
+            .iter()
+            .filter(|op| !matches!(op, diff::Result::Right(..)))
+            .count();
+        let new_count = ops
+            .iter()
+            .filter(|op| !matches!(op, diff::Result::Left(..)))
+            .count();
+        // An empty range is identified by the line before it.
+        let (old_start, new_start) = positions[start];
+        let old_start = if old_count == 0 {
+            old_start - 1
+        } else {
+            old_start
+        };
+        let new_start = if new_count == 0 {
+            new_start - 1
+        } else {
+            new_start
+        };
+        writeln!(
+            out,
+            "@@ -{old_start},{old_count} +{new_start},{new_count} @@"
+        )
+        .unwrap();
+        for op in ops {
+            match op {
+                diff::Result::Both(line, _) => writeln!(out, " {line}"),
+                diff::Result::Left(line) => writeln!(out, "-{line}"),
+                diff::Result::Right(line) => writeln!(out, "+{line}"),
+            }
+            .unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn diff_hunks() {
+        let original = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n";
+        let updated = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\n";
+        assert_eq!(
+            unified_diff("cackle.toml", Some(original), updated),
+            indoc! {"
+                --- a/cackle.toml
+                +++ b/cackle.toml
+                @@ -1,5 +1,5 @@
+                 a
+                -b
+                +B
+                 c
+                 d
+                 e
+                @@ -10,3 +10,4 @@
+                 j
+                 k
+                 l
+                +m
+            "}
+        );
+        assert_eq!(unified_diff("cackle.toml", Some(original), original), "");
+        assert_eq!(
+            unified_diff("cackle.toml", None, "a\n"),
+            indoc! {"
+                --- /dev/null
+                +++ b/cackle.toml
+                @@ -0,0 +1,1 @@
+                +a
+            "}
+        );
+    }
+
+    #[test]
+    fn in_memory_and_changes_patch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cackle.toml");
+        std::fs::write(&path, "a = 1\n").unwrap();
+        let patch_path = dir.path().join("changes.patch");
+        let config_file = ConfigFile::new(path.clone(), true, Some(patch_path.clone()));
+        config_file.write("a = 2\n").unwrap();
+        assert_eq!(config_file.read().unwrap(), "a = 2\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a = 1\n");
+        assert!(config_file
+            .in_memory_diff()
+            .unwrap()
+            .contains("-a = 1\n+a = 2\n"));
+
+        // Changes kept in memory aren't recorded in the changes patch.
+        assert!(!patch_path.exists());
+        assert!(config_file.changes_patch().is_none());
+
+        let config_file = ConfigFile::new(path.clone(), false, Some(patch_path.clone()));
+        assert!(config_file.in_memory_diff().is_none());
+        config_file.write("a = 2\n").unwrap();
+        config_file.write("a = 3\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a = 3\n");
+        assert_eq!(config_file.changes_patch(), Some(patch_path.clone()));
+        assert!(std::fs::read_to_string(&patch_path)
+            .unwrap()
+            .contains("-a = 1\n+a = 3\n"));
+    }
+
+    #[test]
+    fn conflicting_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cackle.toml");
+        std::fs::write(&path, "a = 1\n").unwrap();
+        let import_fs = |editor: &mut ConfigEditor| editor.toggle_std_import("fs");
+
+        // An editor that was read before the file was changed by hand mustn't clobber the change.
+        let config_file = ConfigFile::new(path.clone(), false, None);
+        let mut editor = config_file.editor().unwrap();
+        import_fs(&mut editor).unwrap();
+        std::fs::write(&path, "a = 3\n").unwrap();
+        assert!(is_conflict(&editor.write(&config_file).unwrap_err()));
+        assert_eq!(config_file.read().unwrap(), "a = 3\n");
+
+        // With the reapply policy, edits are reapplied to whatever the file contains when they're
+        // about to be written.
+        let config_file = config_file.with_conflict_policy(ConflictPolicy::Reapply);
+        let mut attempts = 0;
+        config_file
+            .edit(|editor| {
+                attempts += 1;
+                if attempts == 1 {
+                    std::fs::write(&path, "a = 4\n")?;
+                }
+                import_fs(editor)
+            })
+            .unwrap();
+        assert_eq!(attempts, 2);
+        let contents = config_file.read().unwrap();
+        assert!(contents.starts_with("a = 4\n"));
+        assert!(contents.contains("\"fs\""));
+
+        let config_file = config_file.with_conflict_policy(ConflictPolicy::Abort);
+        let result = config_file.edit(|editor| {
+            std::fs::write(&path, "a = 5\n")?;
+            import_fs(editor)
+        });
+        assert!(matches!(result, Err(error) if is_conflict(&error)));
+        assert_eq!(config_file.read().unwrap(), "a = 5\n");
+
+        // If the file keeps changing, we eventually give up rather than reapplying forever.
+        let config_file = config_file.with_conflict_policy(ConflictPolicy::Reapply);
+        let mut attempts = 0;
+        let result = config_file.edit(|editor| {
+            attempts += 1;
+            std::fs::write(&path, format!("a = {attempts}\n"))?;
+            import_fs(editor)
+        });
+        assert!(matches!(result, Err(error) if is_conflict(&error)));
+        assert_eq!(attempts, MAX_REAPPLY_ATTEMPTS + 1);
+    }
+
+    #[test]
+    fn conflict_policy_without_state() {
+        let config_file = ConfigFile::default().with_conflict_policy(ConflictPolicy::Reapply);
+        assert_eq!(config_file.conflict_policy(), ConflictPolicy::Reapply);
+    }
+
+    #[test]
+    fn read_errors_not_treated_as_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        // Reading a directory fails with something other than "not found".
+        let config_file = ConfigFile::new(dir.path().to_owned(), false, None);
+        assert!(config_file.editor().is_err());
+        assert!(config_file.check_unchanged("").is_err());
+        assert!(!is_conflict(&config_file.check_unchanged("").unwrap_err()));
+
+        // A file that doesn't exist is treated as empty.
+        let config_file = ConfigFile::new(dir.path().join("cackle.toml"), false, None);
+        assert_eq!(config_file.editor().unwrap().to_toml(), "");
+        config_file.check_unchanged("").unwrap();
+    }
+}
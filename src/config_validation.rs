@@ -1,3 +1,6 @@
+use crate::config::inheritance::InheritanceIssue;
+use crate::config::permissions::PermSel;
+use crate::config::permissions::PermissionScope;
 use crate::config::ApiName;
 use crate::config::Config;
 use crate::config::MAX_VERSION;
@@ -10,6 +13,8 @@ use std::path::PathBuf;
 pub(crate) struct InvalidConfig {
     config_path: PathBuf,
     problems: Vec<Problem>,
+    /// A suggested replacement for the parts of the config that have inheritance problems.
+    normalized_config: Option<String>,
 }
 
 #[derive(Debug)]
@@ -18,6 +23,13 @@ enum Problem {
     DuplicateAllowedApi(ApiName),
     UnsupportedVersion(i64),
     InvalidPkgSelector(String),
+    EmptyPluginCommand(String),
+    EmptyFixCommand(String),
+    UnknownProblemKind(String),
+    MisplacedPerTestSandbox(PermSel),
+    NonRelativeUnsafePath(PermSel, String),
+    RelativeStdPathPrefix(PathBuf),
+    Inheritance(InheritanceIssue),
 }
 
 pub(crate) fn validate(config: &Config, config_path: &Path) -> Result<(), InvalidConfig> {
@@ -36,15 +48,106 @@ pub(crate) fn validate(config: &Config, config_path: &Path) -> Result<(), Invali
                 problems.push(Problem::DuplicateAllowedApi(permission_name.clone()))
             }
         }
+        for permission_name in &crate_config.expired_apis {
+            if !permission_names.contains(permission_name) {
+                problems.push(Problem::UnknownPermission(permission_name.clone()));
+            }
+        }
         if crate_config.build.is_some() {
             problems.push(Problem::InvalidPkgSelector(format!("{perm_sel}.build")));
         }
         if crate_config.test.is_some() {
             problems.push(Problem::InvalidPkgSelector(format!("{perm_sel}.test")));
         }
+        if crate_config.proc_macro.is_some() {
+            problems.push(Problem::InvalidPkgSelector(format!(
+                "{perm_sel}.proc-macro"
+            )));
+        }
         if crate_config.from.is_some() {
             problems.push(Problem::InvalidPkgSelector(format!("{perm_sel}.dep")));
         }
+        // Rustc overrides only make sense for things that get compiled on their own.
+        if crate_config.rustc.is_some()
+            && !matches!(
+                perm_sel.scope,
+                PermissionScope::All | PermissionScope::Build | PermissionScope::Test
+            )
+        {
+            problems.push(Problem::InvalidPkgSelector(format!("{perm_sel}.rustc")));
+        }
+        let sandbox = &crate_config.sandbox;
+        if (perm_sel.scope != PermissionScope::Test && !sandbox.per_test.is_empty())
+            || sandbox.per_test.values().any(|c| !c.per_test.is_empty())
+        {
+            problems.push(Problem::MisplacedPerTestSandbox(perm_sel.clone()));
+        }
+        for pattern in &crate_config.allow_unsafe_in {
+            let path = Path::new(pattern);
+            if path.is_absolute() || path.components().any(|c| c.as_os_str() == "..") {
+                problems.push(Problem::NonRelativeUnsafePath(
+                    perm_sel.clone(),
+                    pattern.clone(),
+                ));
+            }
+        }
+    }
+    for prefix in &config.raw.common.std_path_prefixes {
+        if !prefix.is_absolute() {
+            problems.push(Problem::RelativeStdPathPrefix(prefix.clone()));
+        }
+    }
+    for (_, pkg_config) in config.raw.defaults.tables() {
+        for sub_config in [
+            Some(pkg_config),
+            pkg_config.build.as_deref(),
+            pkg_config.test.as_deref(),
+            pkg_config.proc_macro.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            for permission_name in &sub_config.allow_apis {
+                if !permission_names.contains(permission_name) {
+                    problems.push(Problem::UnknownPermission(permission_name.clone()));
+                }
+            }
+        }
+    }
+    for permission_name in &config.raw.common.dev_dependencies_default_allow {
+        if !permission_names.contains(permission_name) {
+            problems.push(Problem::UnknownPermission(permission_name.clone()));
+        }
+    }
+    for acknowledgement in &config.raw.acknowledgements {
+        if !permission_names.contains(&acknowledgement.api) {
+            problems.push(Problem::UnknownPermission(acknowledgement.api.clone()));
+        }
+    }
+    for (name, plugin) in &config.raw.plugins {
+        if plugin.command.is_empty() {
+            problems.push(Problem::EmptyPluginCommand(name.clone()));
+        }
+    }
+    for (name, fix) in &config.raw.fixes {
+        if fix.command.is_empty() {
+            problems.push(Problem::EmptyFixCommand(name.clone()));
+        }
+        for kind in &fix.problems {
+            if !crate::problem::Problem::KIND_NAMES.contains(&kind.as_str()) {
+                problems.push(Problem::UnknownProblemKind(kind.clone()));
+            }
+        }
+    }
+    let mut normalized_config = None;
+    if config.raw.common.strict_inheritance {
+        let issues = crate::config::inheritance::find_issues(config);
+        if !issues.is_empty() {
+            normalized_config = std::fs::read_to_string(config_path).ok().and_then(|toml| {
+                crate::config::inheritance::normalized_pkg_config(&toml, &issues).ok()
+            });
+            problems.extend(issues.into_iter().map(Problem::Inheritance));
+        }
     }
     if problems.is_empty() {
         Ok(())
@@ -52,6 +155,7 @@ pub(crate) fn validate(config: &Config, config_path: &Path) -> Result<(), Invali
         Err(InvalidConfig {
             config_path: config_path.to_owned(),
             problems,
+            normalized_config,
         })
     }
 }
@@ -59,7 +163,10 @@ pub(crate) fn validate(config: &Config, config_path: &Path) -> Result<(), Invali
 impl Display for InvalidConfig {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Invalid config {}", self.config_path.display())?;
-        for problem in &self.problems {
+        for (index, problem) in self.problems.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
             match problem {
                 Problem::UnknownPermission(x) => write!(f, "  Unknown permission '{}'", x.name)?,
                 Problem::DuplicateAllowedApi(x) => {
@@ -71,8 +178,37 @@ impl Display for InvalidConfig {
                 Problem::InvalidPkgSelector(sel) => {
                     write!(f, "  Unsupported package selector `pkg.{sel}`")?
                 }
+                Problem::EmptyPluginCommand(name) => {
+                    write!(f, "  Plugin `{name}` has an empty command")?
+                }
+                Problem::EmptyFixCommand(name) => write!(f, "  Fix `{name}` has an empty command")?,
+                Problem::UnknownProblemKind(kind) => write!(f, "  Unknown problem kind `{kind}`")?,
+                Problem::MisplacedPerTestSandbox(sel) => write!(
+                    f,
+                    "  Unsupported `per_test` in `pkg.{sel}`. Per-test overrides go in \
+                     `pkg.{}.test.sandbox.per_test`",
+                    sel.package_name
+                )?,
+                Problem::NonRelativeUnsafePath(sel, pattern) => write!(
+                    f,
+                    "  `allow_unsafe_in` for `pkg.{sel}` contains `{pattern}`, but paths must be \
+                     within the package's directory"
+                )?,
+                Problem::RelativeStdPathPrefix(prefix) => write!(
+                    f,
+                    "  `std_path_prefixes` contains `{}`, but prefixes must be absolute paths",
+                    prefix.display()
+                )?,
+                Problem::Inheritance(issue) => write!(f, "  {issue}")?,
             }
         }
+        if let Some(normalized_config) = &self.normalized_config {
+            write!(
+                f,
+                "\nWith redundant settings removed, the affected packages would be:\n{}",
+                normalized_config.trim()
+            )?;
+        }
         Ok(())
     }
 }
@@ -1,3 +1,5 @@
+use serde::Deserialize;
+use serde::Serialize;
 use std::hash::Hash;
 use std::ops::Deref;
 use std::sync::Arc;
@@ -63,6 +65,22 @@ impl<'data> CowArc<'data, str> {
     }
 }
 
+/// Serialises just the data. Deserialising always produces an instance that's on the heap.
+impl<'data, T: Serialize + ?Sized> Serialize for CowArc<'data, T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.data().serialize(serializer)
+    }
+}
+
+impl<'de, T: ?Sized> Deserialize<'de> for CowArc<'static, T>
+where
+    Arc<T>: Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(CowArc::Heap(Arc::deserialize(deserializer)?))
+    }
+}
+
 impl<'data, T: ?Sized> Deref for CowArc<'data, T> {
     type Target = T;
 
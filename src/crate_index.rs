@@ -2,9 +2,11 @@
 //! to which crates, which are proc macros etc.
 
 use self::lib_tree::LibTree;
+use self::registry::Registries;
 use crate::config::permissions::PermSel;
 use crate::config::permissions::PermissionScope;
 use crate::config::PackageName;
+use crate::proxy::cargo::NetworkMode;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
@@ -16,25 +18,50 @@ use fxhash::FxHashSet;
 use serde::Deserialize;
 use serde::Serialize;
 use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::fmt::Display;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::OnceLock;
 
 pub(crate) mod lib_tree;
+mod registry;
+mod vendor;
 
 #[derive(Default, Debug)]
 pub(crate) struct CrateIndex {
     pub(crate) manifest_path: PathBuf,
     pub(crate) package_infos: FxHashMap<PackageId, PackageInfo>,
     dir_to_pkg_id: FxHashMap<PathBuf, PackageId>,
+    /// Directories of packages from git, keyed by `git_checkout_key`.
+    git_checkout_dirs: FxHashMap<PathBuf, PackageId>,
     pkg_name_to_ids: FxHashMap<Arc<str>, Vec<PackageId>>,
     lib_tree: LibTree,
     pub(crate) permission_selectors: FxHashSet<PermSel>,
+    /// The package whose Cargo.toml we were run on. None if that Cargo.toml is a virtual manifest or
+    /// if we're indexing multiple workspaces.
+    pub(crate) root_pkg_id: Option<PackageId>,
+    /// The root directories of the workspaces that we've indexed.
+    pub(crate) workspace_roots: Vec<PathBuf>,
+    /// Whether cargo may access the network when we run it to query the dependency tree.
+    pub(crate) network_mode: NetworkMode,
+    /// The direct dependencies of each package, together with the kind of each dependency. Sorted
+    /// by name, then version.
+    pub(crate) dependencies: FxHashMap<PackageId, Vec<(PackageId, DepKind)>>,
+    /// The qualified names of packages whose names are qualified with their registry, keyed by
+    /// package directory.
+    qualified_names_by_dir: FxHashMap<PathBuf, Arc<str>>,
+    /// Names of packages that are only in the dependency tree when features that weren't enabled
+    /// when we resolved it are enabled. Determined on first use.
+    feature_gated_package_names: OnceLock<FxHashSet<Arc<str>>>,
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) struct PackageId {
+    /// The name of the package. If a package with the same name is also present from another
+    /// source, then packages from alternative registries are qualified with the name of the
+    /// registry. e.g. `myreg::foo`.
     name: Arc<str>,
     version: Version,
     /// Whether this is the only version of this package present in the dependency tree. This is
@@ -57,12 +84,77 @@ pub(crate) enum CrateKind {
     Test,
 }
 
-#[derive(Debug)]
+/// How a package is depended upon by the workspace. A package that's reachable in several ways has
+/// whichever kind warrants the most scrutiny, i.e. the first of these that applies.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DepKind {
+    /// A workspace member or a package that's reachable from one via regular dependencies.
+    #[default]
+    Normal,
+    /// Only reachable via build dependencies, so only used at build time.
+    Build,
+    /// Only reachable via dev-dependencies of workspace members, so only used by tests, examples
+    /// and benchmarks.
+    Dev,
+}
+
+#[derive(Debug, Clone)]
 pub(crate) struct PackageInfo {
     pub(crate) directory: Utf8PathBuf,
     pub(crate) description: Option<String>,
     pub(crate) documentation: Option<String>,
     is_proc_macro: bool,
+    /// Whether the package's only targets, other than tests, examples and benchmarks, are
+    /// binaries. i.e. it has no library or proc macro.
+    is_bin_only: bool,
+    pub(crate) is_workspace_member: bool,
+    /// Whether the package comes from a local path rather than a registry or git. Such packages
+    /// are first-party even if they're outside of our workspace. Vendored packages aren't path
+    /// dependencies, even if they're referenced by path, e.g. via `[patch]`.
+    pub(crate) is_path_dependency: bool,
+    /// Whether the package's source is in a directory that's used for source replacement, e.g. by
+    /// `cargo vendor`.
+    pub(crate) is_vendored: bool,
+    /// The roots of the workspaces whose dependency trees contain this package.
+    pub(crate) workspaces: Vec<Arc<Path>>,
+    /// The name of the alternative registry that the package came from, if any. None for packages
+    /// from crates.io.
+    pub(crate) registry: Option<Arc<str>>,
+    /// Where cargo got the package from, as cargo reports it. e.g.
+    /// `registry+https://github.com/rust-lang/crates.io-index` or `git+https://...#<rev>`. None for
+    /// path dependencies.
+    pub(crate) source: Option<Arc<str>>,
+    pub(crate) dep_kind: DepKind,
+}
+
+/// Returns the value for the deepest ancestor of `path`, including `path` itself, that's in `map`.
+fn find_in_ancestors<'a>(
+    map: &'a FxHashMap<PathBuf, PackageId>,
+    path: &Path,
+) -> Option<&'a PackageId> {
+    path.ancestors().find_map(|dir| map.get(dir))
+}
+
+/// If `path` is within a checkout of a git repository that cargo made, returns the path with the
+/// part that identifies the revision removed. e.g.
+/// `~/.cargo/git/checkouts/foo-0123456789abcdef/abc1234/sub/src/lib.rs` becomes
+/// `foo-0123456789abcdef/sub/src/lib.rs`. The first component is derived from the repository URL,
+/// so is the same for all checkouts of a repository.
+fn git_checkout_key(path: &Path) -> Option<PathBuf> {
+    let components: Vec<_> = path.components().collect();
+    let checkouts_index = components
+        .windows(2)
+        .position(|pair| pair[0].as_os_str() == "git" && pair[1].as_os_str() == "checkouts")?;
+    let mut rest = components.get(checkouts_index + 2..)?.iter();
+    let repo = rest.next()?;
+    // Skip the revision.
+    rest.next()?;
+    let mut key = PathBuf::from(repo.as_os_str());
+    key.extend(rest);
+    Some(key)
 }
 
 /// The name of the environment variable that we use to pass a list of non-unique package names to
@@ -72,81 +164,314 @@ pub(crate) struct PackageInfo {
 /// there are multiple versions of that package.
 pub(crate) const MULTIPLE_VERSION_PKG_NAMES_ENV: &str = "CACKLE_MULTIPLE_VERSION_PKG_NAMES";
 
+/// The name of the environment variable that we use to pass the qualified names of packages from
+/// alternative registries to our subprocesses. It contains a JSON map from package directory to
+/// qualified name.
+pub(crate) const QUALIFIED_PKG_NAMES_ENV: &str = "CACKLE_QUALIFIED_PKG_NAMES";
+
+/// Runs `cargo metadata` for the workspace containing `manifest_path`, passing `extra_args`.
+pub(crate) fn cargo_metadata(
+    manifest_path: &Path,
+    network_mode: NetworkMode,
+    extra_args: &[String],
+) -> Result<cargo_metadata::Metadata> {
+    let mut metadata_command = cargo_metadata::MetadataCommand::new();
+    metadata_command.manifest_path(manifest_path);
+    // Cargo finds its config, which may for example specify source replacement, based on the
+    // current directory.
+    if let Some(dir) = manifest_path.parent() {
+        metadata_command.current_dir(dir);
+    }
+    metadata_command.other_options(
+        network_mode
+            .cargo_arg()
+            .map(str::to_owned)
+            .into_iter()
+            .chain(extra_args.iter().cloned())
+            .collect::<Vec<_>>(),
+    );
+    metadata_command.exec().with_context(|| {
+        if let Some(arg) = network_mode.cargo_arg() {
+            format!(
+                "`cargo metadata {arg}` failed. If some dependencies haven't been downloaded, \
+                 run `cargo fetch` while network access is available"
+            )
+        } else {
+            "`cargo metadata` failed".to_owned()
+        }
+    })
+}
+
 impl CrateIndex {
-    pub(crate) fn new(dir: &Path) -> Result<Self> {
-        let manifest_path = dir.join("Cargo.toml");
-        let metadata = cargo_metadata::MetadataCommand::new()
-            .manifest_path(&manifest_path)
-            .exec()?;
+    /// Builds a single index covering the workspaces containing each of `manifest_paths`. Each may
+    /// be the manifest of a workspace member. If there's just one, then that member will be our
+    /// root package. Packages that are in the dependency trees of several of the workspaces are
+    /// only indexed once.
+    pub(crate) fn new(manifest_paths: &[PathBuf], network_mode: NetworkMode) -> Result<Self> {
+        let all_metadata = manifest_paths
+            .iter()
+            .map(|manifest_path| cargo_metadata(manifest_path, network_mode, &[]))
+            .collect::<Result<Vec<_>>>()?;
         let mut mapping = CrateIndex {
-            manifest_path,
+            manifest_path: manifest_paths.first().cloned().unwrap_or_default(),
+            network_mode,
             ..Self::default()
         };
-        let mut name_counts = FxHashMap::default();
-        for package in &metadata.packages {
-            *name_counts.entry(&package.name).or_default() += 1;
+        // Determine which alternative registry, if any, each package came from. Registry names
+        // are configured per workspace, so the first workspace to mention a package decides.
+        let mut registries_by_id: FxHashMap<&cargo_metadata::PackageId, Option<Arc<str>>> =
+            FxHashMap::default();
+        for metadata in &all_metadata {
+            let registries = Registries::for_workspace(metadata.workspace_root.as_std_path())?;
+            for package in &metadata.packages {
+                registries_by_id.entry(&package.id).or_insert_with(|| {
+                    package
+                        .source
+                        .as_ref()
+                        .and_then(|source| registries.registry_name(source))
+                });
+            }
         }
-        let mut direct_deps: FxHashMap<PackageId, Vec<Arc<str>>> = FxHashMap::default();
-        for package in &metadata.packages {
-            let pkg_id = PackageId {
-                name: Arc::from(package.name.as_str()),
-                version: package.version.clone(),
-                name_is_unique: name_counts.get(&package.name) == Some(&1),
-            };
-            let mut is_proc_macro = false;
-            let mut has_build_script = false;
-            let mut has_test = false;
-            for target in &package.targets {
-                if target.kind.iter().any(|kind| kind == "proc-macro") {
-                    is_proc_macro = true;
+        // Packages from alternative registries get qualified names if their names collide with
+        // packages from anywhere else.
+        let mut origins_by_name: FxHashMap<&str, FxHashSet<Option<&Arc<str>>>> =
+            FxHashMap::default();
+        for metadata in &all_metadata {
+            for package in &metadata.packages {
+                origins_by_name
+                    .entry(package.name.as_str())
+                    .or_default()
+                    .insert(registries_by_id[&package.id].as_ref());
+            }
+        }
+        let qualified_name = |package: &cargo_metadata::Package| -> Arc<str> {
+            match &registries_by_id[&package.id] {
+                Some(registry) if origins_by_name[package.name.as_str()].len() > 1 => {
+                    Arc::from(format!("{registry}::{}", package.name).as_str())
                 }
-                has_build_script |= target.kind.iter().any(|kind| kind == "custom-build");
-                has_test |= target.test;
+                _ => Arc::from(package.name.as_str()),
             }
-            if let Some(dir) = package.manifest_path.parent() {
-                direct_deps.insert(
-                    pkg_id.clone(),
-                    package
-                        .dependencies
-                        .iter()
-                        .filter(|dep| dep.kind == DependencyKind::Normal && !dep.optional)
-                        .map(|dep| Arc::from(dep.name.as_str()))
-                        .collect(),
-                );
-                mapping.package_infos.insert(
-                    pkg_id.clone(),
-                    PackageInfo {
-                        directory: dir.to_path_buf(),
-                        description: package.description.clone(),
-                        documentation: package.documentation.clone(),
+        };
+        // Whether a package name is unique is determined across all workspaces, since that's what
+        // our subprocesses will see.
+        let mut name_counts: FxHashMap<Arc<str>, usize> = FxHashMap::default();
+        let mut counted = FxHashSet::default();
+        for metadata in &all_metadata {
+            for package in &metadata.packages {
+                if counted.insert(&package.id) {
+                    *name_counts.entry(qualified_name(package)).or_default() += 1;
+                }
+            }
+        }
+        let mut indexed: FxHashMap<&cargo_metadata::PackageId, PackageId> = FxHashMap::default();
+        let mut direct_deps: FxHashMap<PackageId, Vec<Arc<str>>> = FxHashMap::default();
+        for metadata in &all_metadata {
+            let workspace_root: Arc<Path> = Arc::from(metadata.workspace_root.as_std_path());
+            mapping.workspace_roots.push(workspace_root.to_path_buf());
+            let vendor_dirs = vendor::vendor_dirs(&workspace_root)?;
+            let root_package_id = metadata
+                .root_package()
+                .filter(|_| all_metadata.len() == 1)
+                .map(|root| &root.id);
+            for package in &metadata.packages {
+                let is_workspace_member = metadata.workspace_members.contains(&package.id);
+                if let Some(pkg_id) = indexed.get(&package.id) {
+                    // We already indexed this package from an earlier workspace.
+                    if let Some(info) = mapping.package_infos.get_mut(pkg_id) {
+                        info.is_workspace_member |= is_workspace_member;
+                        info.workspaces.push(workspace_root.clone());
+                    }
+                    continue;
+                }
+                let name = qualified_name(package);
+                let pkg_id = PackageId {
+                    name_is_unique: name_counts.get(&name) == Some(&1),
+                    name,
+                    version: package.version.clone(),
+                };
+                indexed.insert(&package.id, pkg_id.clone());
+                if root_package_id == Some(&package.id) {
+                    mapping.root_pkg_id = Some(pkg_id.clone());
+                }
+                let mut is_proc_macro = false;
+                let mut has_build_script = false;
+                let mut has_test = false;
+                let mut has_bin = false;
+                let mut has_lib = false;
+                for target in &package.targets {
+                    if target.kind.iter().any(|kind| kind == "proc-macro") {
+                        is_proc_macro = true;
+                    }
+                    has_bin |= target.kind.iter().any(|kind| kind == "bin");
+                    has_lib |= target.kind.iter().any(|kind| {
+                        matches!(
+                            kind.as_str(),
+                            "lib" | "rlib" | "dylib" | "cdylib" | "staticlib" | "proc-macro"
+                        )
+                    });
+                    has_build_script |= target.kind.iter().any(|kind| kind == "custom-build");
+                    has_test |= target.test;
+                }
+                if let Some(dir) = package.manifest_path.parent() {
+                    let canonical_dir = dir.canonicalize().ok();
+                    let is_vendored = vendor_dirs.iter().any(|vendor_dir| {
+                        dir.starts_with(vendor_dir)
+                            || canonical_dir
+                                .as_ref()
+                                .is_some_and(|canonical| canonical.starts_with(vendor_dir))
+                    });
+                    direct_deps.insert(
+                        pkg_id.clone(),
+                        package
+                            .dependencies
+                            .iter()
+                            .filter(|dep| dep.kind == DependencyKind::Normal && !dep.optional)
+                            .map(|dep| Arc::from(dep.name.as_str()))
+                            .collect(),
+                    );
+                    mapping.package_infos.insert(
+                        pkg_id.clone(),
+                        PackageInfo {
+                            directory: dir.to_path_buf(),
+                            description: package.description.clone(),
+                            documentation: package.documentation.clone(),
+                            is_proc_macro,
+                            is_bin_only: has_bin && !has_lib,
+                            is_workspace_member,
+                            is_path_dependency: package.source.is_none() && !is_vendored,
+                            is_vendored,
+                            workspaces: vec![workspace_root.clone()],
+                            registry: registries_by_id[&package.id].clone(),
+                            source: package
+                                .source
+                                .as_ref()
+                                .map(|source| Arc::from(source.repr.as_str())),
+                            dep_kind: DepKind::Normal,
+                        },
+                    );
+                    add_permission_selectors(
+                        &mut mapping.permission_selectors,
+                        package.name.as_str(),
+                        has_build_script,
+                        has_test,
                         is_proc_macro,
-                    },
-                );
-                add_permission_selectors(
-                    &mut mapping.permission_selectors,
-                    package.name.as_str(),
-                    has_build_script,
-                    has_test,
-                );
-                mapping
-                    .pkg_name_to_ids
-                    .entry(Arc::from(package.name.as_str()))
-                    .or_default()
-                    .push(pkg_id.clone());
-                mapping
-                    .dir_to_pkg_id
-                    .insert(dir.as_std_path().to_owned(), pkg_id.clone());
+                    );
+                    mapping
+                        .pkg_name_to_ids
+                        .entry(pkg_id.name.clone())
+                        .or_default()
+                        .push(pkg_id.clone());
+                    let dir = dir.as_std_path();
+                    if pkg_id.name.as_ref() != package.name.as_str() {
+                        mapping
+                            .qualified_names_by_dir
+                            .insert(dir.to_owned(), pkg_id.name.clone());
+                    }
+                    mapping.dir_to_pkg_id.insert(dir.to_owned(), pkg_id.clone());
+                    if let Some(key) = git_checkout_key(dir) {
+                        mapping.git_checkout_dirs.insert(key, pkg_id.clone());
+                    }
+                    // Paths that we look up come from debug info and may have had symlinks
+                    // resolved, e.g. if the workspace is accessed via a symlink or path
+                    // dependencies live elsewhere.
+                    if let Some(canonical) = canonical_dir {
+                        if canonical != dir {
+                            mapping.dir_to_pkg_id.insert(canonical, pkg_id.clone());
+                        }
+                    }
+                }
             }
         }
-        mapping.lib_tree = LibTree::from_workspace(dir, &mapping.pkg_name_to_ids)?;
+        // `cargo tree` reports packages by their unqualified names.
+        let mut unqualified_name_to_ids: FxHashMap<Arc<str>, Vec<PackageId>> = FxHashMap::default();
+        for pkg_id in mapping.package_infos.keys() {
+            unqualified_name_to_ids
+                .entry(Arc::from(pkg_id.unqualified_name()))
+                .or_default()
+                .push(pkg_id.clone());
+        }
+        for metadata in &all_metadata {
+            mapping.lib_tree.merge(LibTree::from_workspace(
+                metadata.workspace_root.as_std_path(),
+                network_mode,
+                &unqualified_name_to_ids,
+            )?);
+        }
         for package_ids in mapping.pkg_name_to_ids.values_mut() {
             package_ids.sort_by_key(|pkg_id| pkg_id.version.clone());
         }
+        let mut dep_edges: FxHashMap<PackageId, Vec<(PackageId, DepKind)>> = FxHashMap::default();
+        for resolve in all_metadata
+            .iter()
+            .filter_map(|metadata| metadata.resolve.as_ref())
+        {
+            for node in &resolve.nodes {
+                let Some(from) = indexed.get(&node.id) else {
+                    continue;
+                };
+                for dep in &node.deps {
+                    let Some(to) = indexed.get(&dep.pkg) else {
+                        continue;
+                    };
+                    for dep_kind_info in &dep.dep_kinds {
+                        let kind = match dep_kind_info.kind {
+                            DependencyKind::Development => DepKind::Dev,
+                            DependencyKind::Build => DepKind::Build,
+                            _ => DepKind::Normal,
+                        };
+                        dep_edges
+                            .entry(from.clone())
+                            .or_default()
+                            .push((to.clone(), kind));
+                    }
+                }
+            }
+        }
+        let members: FxHashSet<PackageId> = mapping
+            .package_infos
+            .iter()
+            .filter(|(_, info)| info.is_workspace_member)
+            .map(|(pkg_id, _)| pkg_id.clone())
+            .collect();
+        let dep_kinds = classify_deps(&members, &dep_edges);
+        for (pkg_id, info) in &mut mapping.package_infos {
+            info.dep_kind = dep_kinds.get(pkg_id).copied().unwrap_or_default();
+        }
+        for deps in dep_edges.values_mut() {
+            deps.sort_by(|(a, a_kind), (b, b_kind)| {
+                (&a.name, &a.version, a_kind).cmp(&(&b.name, &b.version, b_kind))
+            });
+            deps.dedup();
+        }
+        mapping.dependencies = dep_edges;
         Ok(mapping)
     }
 
-    /// Adds an environment variable to `command` that allows subprocesses to determine whether a
-    /// package name is unique.
+    /// Returns the names of packages that aren't in our dependency tree, but would be if all features
+    /// were enabled. This runs `cargo metadata` again, so is only done when needed, which is when
+    /// some package config appears to be unused.
+    pub(crate) fn feature_gated_package_names(&self) -> Result<&FxHashSet<Arc<str>>> {
+        if let Some(names) = self.feature_gated_package_names.get() {
+            return Ok(names);
+        }
+        let mut names = FxHashSet::default();
+        for workspace_root in &self.workspace_roots {
+            let metadata = cargo_metadata(
+                &workspace_root.join("Cargo.toml"),
+                self.network_mode,
+                &["--all-features".to_owned()],
+            )?;
+            for package in &metadata.packages {
+                if !self.pkg_name_to_ids.contains_key(package.name.as_str()) {
+                    names.insert(Arc::from(package.name.as_str()));
+                }
+            }
+        }
+        Ok(self.feature_gated_package_names.get_or_init(|| names))
+    }
+
+    /// Adds environment variables to `command` that allow subprocesses to determine whether a
+    /// package name is unique and whether it's qualified with its registry.
     pub(crate) fn add_internal_env(&self, command: &mut std::process::Command) {
         let non_unique_names: Vec<&str> = self
             .package_ids()
@@ -159,6 +484,13 @@ impl CrateIndex {
             })
             .collect();
         command.env(MULTIPLE_VERSION_PKG_NAMES_ENV, non_unique_names.join(","));
+        if !self.qualified_names_by_dir.is_empty() {
+            // Package directories can contain any character, so we use JSON rather than some
+            // separator.
+            if let Ok(json) = serde_json::to_string(&self.qualified_names_by_dir) {
+                command.env(QUALIFIED_PKG_NAMES_ENV, json);
+            }
+        }
     }
 
     pub(crate) fn newest_package_id_with_name(&self, pkg_name: &PackageName) -> Option<&PackageId> {
@@ -191,22 +523,25 @@ impl CrateIndex {
         })
     }
 
+    pub(crate) fn is_proc_macro(&self, pkg_id: &PackageId) -> bool {
+        self.package_infos
+            .get(pkg_id)
+            .is_some_and(|info| info.is_proc_macro)
+    }
+
     /// Returns the ID of the package that contains the specified path, if any. This is used as a
     /// fallback if we can't locate a source file in the deps emitted by rustc. This can happen for
     /// example in the case of crates that compile C code, since the C code won't be in the deps
     /// file. This function however doesn't differentiate between the build script for a package and
     /// the other source files in that package, so should only be used as a fallback.
-    pub(crate) fn package_id_for_path(&self, mut path: &Path) -> Option<&PackageId> {
-        loop {
-            if let Some(pkg_id) = self.dir_to_pkg_id.get(path) {
-                return Some(pkg_id);
-            }
-            if let Some(parent) = path.parent() {
-                path = parent;
-            } else {
-                return None;
-            }
+    pub(crate) fn package_id_for_path(&self, path: &Path) -> Option<&PackageId> {
+        let path = crate::fs::normalize(path);
+        if let Some(pkg_id) = find_in_ancestors(&self.dir_to_pkg_id, &path) {
+            return Some(pkg_id);
         }
+        // A git dependency may have been built from a different checkout of the same repository
+        // than the one cargo metadata reported, e.g. if the build was done elsewhere.
+        find_in_ancestors(&self.git_checkout_dirs, &git_checkout_key(&path)?)
     }
 
     /// Returns the transitive deps for `pkg_id`. All deps will be in "crate form", i.e. with '-'
@@ -219,6 +554,136 @@ impl CrateIndex {
     pub(crate) fn name_prefix_to_pkg_id(&self) -> &FxHashMap<Arc<str>, PackageId> {
         &self.lib_tree.lib_name_to_pkg_id
     }
+
+    /// Returns the shortest chain of dependencies from a workspace member to `pkg_id`, starting
+    /// with the workspace member and ending with `pkg_id`. Returns None if `pkg_id` isn't reachable
+    /// via normal dependencies, e.g. because it's only a build or dev dependency.
+    pub(crate) fn dependency_path(&self, pkg_id: &PackageId) -> Option<Vec<PackageId>> {
+        let mut roots: Vec<&PackageId> = self
+            .package_infos
+            .iter()
+            .filter(|(_, info)| info.is_workspace_member)
+            .map(|(id, _)| id)
+            .collect();
+        roots.sort_by_key(|id| (&id.name, &id.version));
+        let mut parents: FxHashMap<&PackageId, Option<&PackageId>> =
+            roots.iter().map(|id| (*id, None)).collect();
+        let mut queue: VecDeque<&PackageId> = roots.into_iter().collect();
+        while let Some(current) = queue.pop_front() {
+            if current == pkg_id {
+                let mut path = vec![current.clone()];
+                let mut next = parents.get(current).copied().flatten();
+                while let Some(parent) = next {
+                    path.push(parent.clone());
+                    next = parents.get(parent).copied().flatten();
+                }
+                path.reverse();
+                return Some(path);
+            }
+            let Some(deps) = self.lib_tree.direct_deps.get(current) else {
+                continue;
+            };
+            let mut deps: Vec<&PackageId> = deps.iter().collect();
+            deps.sort_by_key(|id| (&id.name, &id.version));
+            for dep in deps {
+                if !parents.contains_key(dep) {
+                    parents.insert(dep, Some(current));
+                    queue.push_back(dep);
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns how `pkg_id` is depended upon. Packages that we know nothing about are treated as
+    /// normal dependencies.
+    pub(crate) fn dep_kind(&self, pkg_id: &PackageId) -> DepKind {
+        self.package_info(pkg_id)
+            .map(|info| info.dep_kind)
+            .unwrap_or_default()
+    }
+
+    /// Returns whether all packages named `pkg_name` only have binary targets, as opposed to being
+    /// libraries that other code may link against. A package with both a library and binaries
+    /// isn't bin-only, so its binaries are classified along with its library.
+    pub(crate) fn is_bin_only(&self, pkg_name: &str) -> bool {
+        self.pkg_name_to_ids.get(pkg_name).is_some_and(|pkg_ids| {
+            pkg_ids.iter().all(|pkg_id| {
+                self.package_infos
+                    .get(pkg_id)
+                    .is_some_and(|info| info.is_bin_only)
+            })
+        })
+    }
+
+    /// Returns whether all packages named `pkg_name` are only used via dev-dependencies.
+    pub(crate) fn is_dev_only(&self, pkg_name: &str) -> bool {
+        self.pkg_name_to_ids.get(pkg_name).is_some_and(|pkg_ids| {
+            pkg_ids
+                .iter()
+                .all(|pkg_id| self.dep_kind(pkg_id) == DepKind::Dev)
+        })
+    }
+
+    /// Returns the parts of the index that are needed to scan a binary.
+    pub(crate) fn scan_index(&self) -> ScanIndex {
+        ScanIndex {
+            lib_name_to_pkg_id: self.lib_tree.lib_name_to_pkg_id.clone(),
+            dir_to_pkg_id: self.dir_to_pkg_id.clone(),
+            git_checkout_dirs: self.git_checkout_dirs.clone(),
+        }
+    }
+
+    /// Creates an index that only supports what's needed to scan a binary. Used by remote scan
+    /// workers, which don't have access to the workspace.
+    pub(crate) fn from_scan_index(scan_index: ScanIndex) -> Self {
+        Self {
+            dir_to_pkg_id: scan_index.dir_to_pkg_id,
+            git_checkout_dirs: scan_index.git_checkout_dirs,
+            lib_tree: LibTree {
+                lib_name_to_pkg_id: scan_index.lib_name_to_pkg_id,
+                ..LibTree::default()
+            },
+            ..Self::default()
+        }
+    }
+}
+
+/// Determines the kind of each package that's reachable from `roots` via `edges`. Everything reached
+/// via a build dependency is used at build time and likewise, everything reached via a
+/// dev-dependency is only used by tests etc. Cargo ignores dev-dependencies of packages that aren't
+/// workspace members, so we only follow those from `roots`.
+fn classify_deps(
+    roots: &FxHashSet<PackageId>,
+    edges: &FxHashMap<PackageId, Vec<(PackageId, DepKind)>>,
+) -> FxHashMap<PackageId, DepKind> {
+    let mut kinds = FxHashMap::default();
+    // For each kind in order of decreasing scrutiny, find what's reachable via edges of that kind
+    // or more scrutinised kinds. Anything not already classified gets that kind.
+    for max_kind in [DepKind::Normal, DepKind::Build, DepKind::Dev] {
+        let mut visited = FxHashSet::default();
+        let mut queue: VecDeque<&PackageId> = roots.iter().collect();
+        while let Some(pkg_id) = queue.pop_front() {
+            if !visited.insert(pkg_id) {
+                continue;
+            }
+            kinds.entry(pkg_id.clone()).or_insert(max_kind);
+            for (dep, kind) in edges.get(pkg_id).into_iter().flatten() {
+                if *kind <= max_kind && (*kind != DepKind::Dev || roots.contains(pkg_id)) {
+                    queue.push_back(dep);
+                }
+            }
+        }
+    }
+    kinds
+}
+
+/// The subset of a `CrateIndex` that's needed to attribute code in a binary to packages.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct ScanIndex {
+    lib_name_to_pkg_id: FxHashMap<Arc<str>, PackageId>,
+    dir_to_pkg_id: FxHashMap<PathBuf, PackageId>,
+    git_checkout_dirs: FxHashMap<PathBuf, PackageId>,
 }
 
 fn add_permission_selectors(
@@ -226,6 +691,7 @@ fn add_permission_selectors(
     pkg_name: &str,
     has_build_script: bool,
     has_test: bool,
+    is_proc_macro: bool,
 ) {
     let perm_sel = PermSel::for_primary(pkg_name);
     permission_selectors.insert(perm_sel.clone());
@@ -237,6 +703,9 @@ fn add_permission_selectors(
     if has_test {
         permission_selectors.insert(perm_sel.clone_with_scope(PermissionScope::Test));
     }
+    if is_proc_macro {
+        permission_selectors.insert(perm_sel.clone_with_scope(PermissionScope::ProcMacro));
+    }
 }
 
 impl PackageId {
@@ -245,7 +714,7 @@ impl PackageId {
     }
 
     pub(crate) fn from_env() -> Result<Self> {
-        let name = get_env("CARGO_PKG_NAME")?;
+        let name = qualified_name_from_env(get_env("CARGO_PKG_NAME")?)?;
         let version_string = get_env("CARGO_PKG_VERSION")?;
         let version = Version::parse(&version_string).with_context(|| {
             format!(
@@ -267,13 +736,48 @@ impl PackageId {
         &self.version
     }
 
-    pub(crate) fn crate_name(&self) -> Cow<str> {
-        if self.name.contains('-') {
-            self.name.replace('-', "_").into()
+    /// Returns an ID that is the same for all versions of this package. This isn't the ID of any
+    /// actual package, so it should only be used for grouping things by package name.
+    pub(crate) fn for_all_versions(&self) -> PackageId {
+        PackageId {
+            name: self.name.clone(),
+            version: Version::new(0, 0, 0),
+            name_is_unique: true,
+        }
+    }
+
+    pub(crate) fn crate_name(&self) -> Cow<'_, str> {
+        let name = self.unqualified_name();
+        if name.contains('-') {
+            name.replace('-', "_").into()
         } else {
-            Cow::Borrowed(&self.name)
+            Cow::Borrowed(name)
         }
     }
+
+    /// Returns the name of the package without any registry qualification. This is the name that
+    /// cargo uses for the package.
+    pub(crate) fn unqualified_name(&self) -> &str {
+        self.name
+            .rsplit_once("::")
+            .map_or(&self.name, |(_, name)| name)
+    }
+}
+
+/// Returns the name by which we know the package that cargo has told us is named `name`, which
+/// differs if the package comes from an alternative registry and its name collides with another.
+fn qualified_name_from_env(name: String) -> Result<String> {
+    let Ok(json) = std::env::var(QUALIFIED_PKG_NAMES_ENV) else {
+        return Ok(name);
+    };
+    let manifest_dir = PathBuf::from(get_env("CARGO_MANIFEST_DIR")?);
+    qualified_name(name, &manifest_dir, &json)
+}
+
+fn qualified_name(name: String, manifest_dir: &Path, json: &str) -> Result<String> {
+    let qualified_names: FxHashMap<PathBuf, String> = serde_json::from_str(json)
+        .with_context(|| format!("Invalid value for {QUALIFIED_PKG_NAMES_ENV}"))?;
+    Ok(qualified_names.get(manifest_dir).cloned().unwrap_or(name))
 }
 
 fn get_env(key: &str) -> Result<String> {
@@ -369,6 +873,16 @@ impl Display for CrateSel {
     }
 }
 
+impl Display for DepKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DepKind::Normal => write!(f, "normal"),
+            DepKind::Build => write!(f, "build"),
+            DepKind::Dev => write!(f, "dev"),
+        }
+    }
+}
+
 impl Display for PackageId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         CrateSel::primary(self.clone()).fmt(f)
@@ -398,6 +912,15 @@ pub(crate) mod testing {
         }
     }
 
+    /// Returns a package ID for `name` that's one of several versions of that package.
+    pub(crate) fn pkg_id_with_version(name: &str, version: Version) -> PackageId {
+        PackageId {
+            name: Arc::from(name),
+            version,
+            name_is_unique: false,
+        }
+    }
+
     pub(crate) fn index_with_package_names(package_names: &[&str]) -> Arc<CrateIndex> {
         let package_infos = package_names
             .iter()
@@ -409,20 +932,76 @@ pub(crate) mod testing {
                         description: Default::default(),
                         documentation: Default::default(),
                         is_proc_macro: Default::default(),
+                        is_bin_only: Default::default(),
+                        is_workspace_member: Default::default(),
+                        is_path_dependency: Default::default(),
+                        is_vendored: Default::default(),
+                        workspaces: Default::default(),
+                        registry: Default::default(),
+                        source: Default::default(),
+                        dep_kind: Default::default(),
                     },
                 )
             })
             .collect();
         let mut permission_selectors = FxHashSet::default();
         for pkg_name in package_names {
-            super::add_permission_selectors(&mut permission_selectors, pkg_name, false, false);
+            super::add_permission_selectors(
+                &mut permission_selectors,
+                pkg_name,
+                false,
+                false,
+                false,
+            );
         }
+        let pkg_name_to_ids = package_names
+            .iter()
+            .map(|name| (Arc::from(*name), vec![pkg_id(name)]))
+            .collect();
+        let lib_name_to_pkg_id = package_names
+            .iter()
+            .map(|name| (Arc::from(name.replace('-', "_")), pkg_id(name)))
+            .collect();
         Arc::new(CrateIndex {
             package_infos,
             permission_selectors,
+            pkg_name_to_ids,
+            lib_tree: super::LibTree {
+                lib_name_to_pkg_id,
+                ..Default::default()
+            },
             ..CrateIndex::default()
         })
     }
+
+    /// Like `index_with_package_names`, but all packages are workspace members and those in
+    /// `bin_only` only have binary targets.
+    pub(crate) fn index_with_workspace_members(
+        package_names: &[&str],
+        bin_only: &[&str],
+    ) -> Arc<CrateIndex> {
+        let mut index = Arc::try_unwrap(index_with_package_names(package_names)).unwrap();
+        for (pkg_id, info) in &mut index.package_infos {
+            info.is_workspace_member = true;
+            info.is_bin_only = bin_only.contains(&pkg_id.name_str());
+        }
+        Arc::new(index)
+    }
+
+    /// Like `index_with_package_names`, but the packages in `feature_gated` are treated as being in
+    /// the dependency tree only when other features are enabled.
+    pub(crate) fn index_with_feature_gated_packages(
+        package_names: &[&str],
+        feature_gated: &[&str],
+    ) -> Arc<CrateIndex> {
+        let mut index = Arc::try_unwrap(index_with_package_names(package_names)).unwrap();
+        index.feature_gated_package_names = feature_gated
+            .iter()
+            .map(|name| Arc::from(*name))
+            .collect::<FxHashSet<_>>()
+            .into();
+        Arc::new(index)
+    }
 }
 
 #[test]
@@ -442,7 +1021,7 @@ fn test_crate_index() {
 
     let crate_root = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
     let test_crates_dir = crate_root.join("test_crates");
-    let index = CrateIndex::new(&test_crates_dir).unwrap();
+    let index = CrateIndex::new(&[test_crates_dir.join("Cargo.toml")], Default::default()).unwrap();
 
     check(&index, "crab_2", &["crab_1", "crab_3"]);
     check(&index, "crab_4", &[]);
@@ -453,4 +1032,210 @@ fn test_crate_index() {
             "crab_1", "crab_2", "crab_3", "crab_4", "crab_5", "crab_6", "crab_7", "crab_8", "res_1",
         ],
     );
+
+    let path_names = |pkg_id: &PackageId| -> Vec<String> {
+        index
+            .dependency_path(pkg_id)
+            .unwrap()
+            .iter()
+            .map(|id| id.to_string())
+            .collect()
+    };
+    let crab_3v2 = index
+        .newest_package_id_with_name(&PackageName::from("crab-3"))
+        .unwrap();
+    assert_eq!(path_names(crab_3v2), ["crab-bin", "crab-3[2.0.0]"]);
+    let crab_5 = index.name_prefix_to_pkg_id().get("crab_5").unwrap();
+    assert_eq!(path_names(crab_5), ["crab-5"]);
+
+    assert!(index.is_bin_only("crab-bin"));
+    // `crab-2` has a binary as well as its library, so the whole package is treated as a library.
+    assert!(!index.is_bin_only("crab-2"));
+    assert!(!index.is_bin_only("crab-1"));
+}
+
+#[test]
+fn index_from_workspace_member() {
+    let crate_root = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let test_crates_dir = crate_root.join("test_crates");
+    let index = CrateIndex::new(
+        &[test_crates_dir.join("crab-bin").join("Cargo.toml")],
+        Default::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        index.root_pkg_id.as_ref().map(|pkg_id| pkg_id.to_string()),
+        Some("crab-bin".to_owned())
+    );
+    // Other members of the workspace should still be indexed.
+    let pmacro = index
+        .newest_package_id_with_name(&PackageName::from("pmacro-1"))
+        .unwrap();
+    assert!(index.package_info(pmacro).unwrap().is_workspace_member);
+
+    // crab-3v2 is excluded from the workspace, but is a path dependency, so is still first-party.
+    let crab_3v2 = index
+        .newest_package_id_with_name(&PackageName::from("crab-3"))
+        .unwrap();
+    let info = index.package_info(crab_3v2).unwrap();
+    assert!(!info.is_workspace_member);
+    assert!(info.is_path_dependency);
+}
+
+#[test]
+fn index_multiple_workspaces() {
+    let crate_root = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let test_crates_dir = crate_root.join("test_crates");
+    let vendored_dir = test_crates_dir.join("vendored");
+    let index = CrateIndex::new(
+        &[
+            test_crates_dir.join("Cargo.toml"),
+            vendored_dir.join("Cargo.toml"),
+        ],
+        NetworkMode::Offline,
+    )
+    .unwrap();
+
+    assert_eq!(
+        index.workspace_roots,
+        [test_crates_dir.clone(), vendored_dir.clone()]
+    );
+    assert!(index.root_pkg_id.is_none());
+    let workspaces_of = |name: &str| {
+        let pkg_id = index
+            .newest_package_id_with_name(&PackageName::from(name))
+            .unwrap();
+        let info = index.package_info(pkg_id).unwrap();
+        info.workspaces.clone()
+    };
+    assert_eq!(
+        workspaces_of("crab-bin"),
+        [Arc::from(test_crates_dir.as_path())]
+    );
+    assert_eq!(workspaces_of("vcrab"), [Arc::from(vendored_dir.as_path())]);
+    // Both trees contribute to the lib tree.
+    assert!(index.name_prefix_to_pkg_id().contains_key("crab_bin"));
+    assert!(index.name_prefix_to_pkg_id().contains_key("vcrab"));
+}
+
+#[test]
+fn index_vendored_workspace() {
+    let crate_root = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let workspace = crate_root.join("test_crates").join("vendored");
+    let index = CrateIndex::new(&[workspace.join("Cargo.toml")], NetworkMode::Offline).unwrap();
+
+    let vcrab = index
+        .package_id_for_path(&workspace.join("vendor/vcrab/src/lib.rs"))
+        .unwrap()
+        .clone();
+    assert_eq!(vcrab.name_str(), "vcrab");
+    let info = index.package_info(&vcrab).unwrap();
+    assert!(info.is_vendored);
+    assert!(!info.is_path_dependency);
+    assert!(!info.is_workspace_member);
+
+    let app = index
+        .package_id_for_path(&workspace.join("app/src/main.rs"))
+        .unwrap();
+    assert!(!index.package_info(app).unwrap().is_vendored);
+}
+
+#[test]
+fn git_checkout_paths() {
+    let key = |path: &str| git_checkout_key(Path::new(path));
+    assert_eq!(
+        key("/home/u/.cargo/git/checkouts/foo-0123456789abcdef/abc1234/sub/src/lib.rs"),
+        Some(PathBuf::from("foo-0123456789abcdef/sub/src/lib.rs"))
+    );
+    assert_eq!(
+        key("/home/u/.cargo/git/checkouts/foo-0123456789abcdef"),
+        None
+    );
+    assert_eq!(key("/home/u/project/src/lib.rs"), None);
+
+    let foo = testing::pkg_id("foo");
+    let bar = testing::pkg_id("bar");
+    let mut index = CrateIndex::default();
+    for (dir, pkg_id) in [
+        ("/h/.cargo/git/checkouts/foo-0123/abc1234", &foo),
+        ("/h/.cargo/git/checkouts/foo-0123/abc1234/bar", &bar),
+    ] {
+        let dir = Path::new(dir);
+        index.dir_to_pkg_id.insert(dir.to_owned(), pkg_id.clone());
+        index
+            .git_checkout_dirs
+            .insert(git_checkout_key(dir).unwrap(), pkg_id.clone());
+    }
+    index
+        .dir_to_pkg_id
+        .insert(PathBuf::from("/ws/local"), foo.clone());
+
+    let lookup = |path: &str| index.package_id_for_path(Path::new(path));
+    assert_eq!(
+        lookup("/h/.cargo/git/checkouts/foo-0123/abc1234/src/lib.rs"),
+        Some(&foo)
+    );
+    // A different revision of the same repository.
+    assert_eq!(
+        lookup("/other/.cargo/git/checkouts/foo-0123/def5678/bar/src/lib.rs"),
+        Some(&bar)
+    );
+    assert_eq!(
+        lookup("/h/.cargo/git/checkouts/baz-4567/abc1234/src/lib.rs"),
+        None
+    );
+    // Paths such as those from `[patch]` entries may contain `..`.
+    assert_eq!(lookup("/ws/app/../local/src/lib.rs"), Some(&foo));
+}
+
+#[test]
+fn registry_qualified_names() {
+    let json = r#"{"/reg/foo-1.0.0":"myreg::foo"}"#;
+    assert_eq!(
+        qualified_name("foo".to_owned(), Path::new("/reg/foo-1.0.0"), json).unwrap(),
+        "myreg::foo"
+    );
+    assert_eq!(
+        qualified_name("foo".to_owned(), Path::new("/cratesio/foo-1.0.0"), json).unwrap(),
+        "foo"
+    );
+    assert!(qualified_name("foo".to_owned(), Path::new("/reg/foo-1.0.0"), "bad").is_err());
+
+    let pkg_id = testing::pkg_id("myreg::foo-bar");
+    assert_eq!(pkg_id.unqualified_name(), "foo-bar");
+    assert_eq!(pkg_id.crate_name(), "foo_bar");
+    assert_eq!(testing::pkg_id("foo").unqualified_name(), "foo");
+}
+
+#[test]
+fn dependency_kinds() {
+    let id = testing::pkg_id;
+    let mut edges: FxHashMap<PackageId, Vec<(PackageId, DepKind)>> = FxHashMap::default();
+    for (from, to, kind) in [
+        ("app", "a", DepKind::Normal),
+        ("a", "b", DepKind::Build),
+        ("b", "c", DepKind::Normal),
+        ("app", "d", DepKind::Dev),
+        ("d", "c", DepKind::Normal),
+        ("d", "e", DepKind::Normal),
+        ("e", "a", DepKind::Normal),
+        // Dev-dependencies of packages other than workspace members aren't built.
+        ("a", "f", DepKind::Dev),
+        ("app", "g", DepKind::Dev),
+        ("app", "g", DepKind::Build),
+    ] {
+        edges.entry(id(from)).or_default().push((id(to), kind));
+    }
+    let roots = [id("app")].into_iter().collect();
+    let kinds = classify_deps(&roots, &edges);
+    let kind = |name| kinds.get(&id(name)).copied();
+    assert_eq!(kind("app"), Some(DepKind::Normal));
+    assert_eq!(kind("a"), Some(DepKind::Normal));
+    assert_eq!(kind("b"), Some(DepKind::Build));
+    assert_eq!(kind("c"), Some(DepKind::Build));
+    assert_eq!(kind("d"), Some(DepKind::Dev));
+    assert_eq!(kind("e"), Some(DepKind::Dev));
+    assert_eq!(kind("f"), None);
+    assert_eq!(kind("g"), Some(DepKind::Build));
 }
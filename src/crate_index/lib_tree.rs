@@ -1,3 +1,4 @@
+use super::NetworkMode;
 use super::PackageId;
 use anyhow::anyhow;
 use anyhow::bail;
@@ -19,11 +20,14 @@ pub(super) struct LibTree {
     /// underscore.
     pub(super) lib_name_to_pkg_id: FxHashMap<Arc<str>, PackageId>,
     pub(super) pkg_transitive_deps: FxHashMap<PackageId, FxHashSet<Arc<str>>>,
+    /// Map from a package to the packages that it directly depends on.
+    pub(super) direct_deps: FxHashMap<PackageId, FxHashSet<PackageId>>,
 }
 
 impl LibTree {
     pub(super) fn from_workspace(
         dir: &Path,
+        network_mode: NetworkMode,
         pkg_name_to_ids: &FxHashMap<Arc<str>, Vec<PackageId>>,
     ) -> Result<Self> {
         let builder = LibTreeBuilder {
@@ -31,7 +35,23 @@ impl LibTree {
             tree: LibTree::default(),
             pkg_name_to_ids,
         };
-        builder.build(dir)
+        builder.build(dir, network_mode)
+    }
+
+    /// Adds the packages and dependencies from `other`, which is the tree of another workspace.
+    pub(super) fn merge(&mut self, other: LibTree) {
+        for (lib_name, pkg_id) in other.lib_name_to_pkg_id {
+            self.lib_name_to_pkg_id.entry(lib_name).or_insert(pkg_id);
+        }
+        for (pkg_id, deps) in other.pkg_transitive_deps {
+            self.pkg_transitive_deps
+                .entry(pkg_id)
+                .or_default()
+                .extend(deps);
+        }
+        for (pkg_id, deps) in other.direct_deps {
+            self.direct_deps.entry(pkg_id).or_default().extend(deps);
+        }
     }
 }
 
@@ -42,10 +62,12 @@ struct LibTreeBuilder<'a> {
 }
 
 impl<'a> LibTreeBuilder<'a> {
-    fn build(mut self, dir: &Path) -> Result<LibTree> {
+    fn build(mut self, dir: &Path, network_mode: NetworkMode) -> Result<LibTree> {
         let output = Command::new("cargo")
             .current_dir(dir)
             .arg("tree")
+            .arg("--workspace")
+            .args(network_mode.cargo_arg())
             .args(["--edges", "normal,no-proc-macro"])
             .args(["--prefix", "depth"])
             .args(["--format", " {lib} {p}"])
@@ -105,6 +127,13 @@ impl<'a> LibTreeBuilder<'a> {
         if level - 1 <= self.stack.len() {
             self.pop_to_level(level - 1);
         }
+        if let Some(parent) = self.stack.last() {
+            self.tree
+                .direct_deps
+                .entry(parent.package_id.clone())
+                .or_default()
+                .insert(package_id.clone());
+        }
         // If we're already encountered this package before, then add all of its transitive
         // dependencies to the deps of all the packages that depend on it (up the stack).
         if let Some(deps) = self.tree.pkg_transitive_deps.get(package_id) {
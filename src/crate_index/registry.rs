@@ -0,0 +1,113 @@
+//! Support for packages from alternative registries, e.g. a company's internal registry. Cargo
+//! identifies these by the URL of their index, but users refer to them by the names given to them
+//! in `.cargo/config.toml`.
+
+use super::vendor::cargo_configs;
+use anyhow::Result;
+use cargo_metadata::Source;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// The source of packages from crates.io when accessed via its sparse index.
+const CRATES_IO_SPARSE: &str = "sparse+https://index.crates.io/";
+
+/// The names of the registries configured for a workspace, keyed by index URL.
+#[derive(Default)]
+pub(super) struct Registries {
+    names_by_index: BTreeMap<String, Arc<str>>,
+}
+
+impl Registries {
+    pub(super) fn for_workspace(workspace_root: &Path) -> Result<Self> {
+        let mut registries = Registries::default();
+        for (_, config) in cargo_configs(workspace_root)? {
+            for (name, registry) in config.registries {
+                if let Some(index) = registry.index {
+                    // Configs closer to the workspace take precedence.
+                    registries
+                        .names_by_index
+                        .entry(normalise_index(&index).to_owned())
+                        .or_insert_with(|| Arc::from(name.as_str()));
+                }
+            }
+        }
+        Ok(registries)
+    }
+
+    /// Returns the name of the alternative registry that `source` refers to. Returns None if
+    /// `source` is crates.io or isn't a registry at all, e.g. a git repository. If the registry
+    /// isn't named in cargo config, then its hostname is used instead.
+    pub(super) fn registry_name(&self, source: &Source) -> Option<Arc<str>> {
+        if source.is_crates_io() || source.repr == CRATES_IO_SPARSE {
+            return None;
+        }
+        let index = source.repr.strip_prefix("registry+").or_else(|| {
+            source
+                .repr
+                .strip_prefix("sparse+")
+                .map(|_| source.repr.as_str())
+        })?;
+        let index = normalise_index(index);
+        if let Some(name) = self.names_by_index.get(index) {
+            return Some(name.clone());
+        }
+        let without_scheme = index.split_once("://").map_or(index, |(_, rest)| rest);
+        let host = without_scheme.split('/').next().unwrap_or(without_scheme);
+        Some(Arc::from(host))
+    }
+}
+
+/// Removes any trailing slash, since cargo config and cargo metadata aren't always consistent about
+/// whether it's present.
+fn normalise_index(index: &str) -> &str {
+    index.trim_end_matches('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(repr: &str) -> Source {
+        Source {
+            repr: repr.to_owned(),
+        }
+    }
+
+    #[test]
+    fn registry_names() {
+        let registries = Registries {
+            names_by_index: [
+                (
+                    "https://git.example.com/index".to_owned(),
+                    Arc::from("corp"),
+                ),
+                (
+                    "sparse+https://cargo.example.com/api".to_owned(),
+                    Arc::from("sparse-corp"),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let name = |repr: &str| registries.registry_name(&source(repr));
+        assert_eq!(
+            name("registry+https://github.com/rust-lang/crates.io-index"),
+            None
+        );
+        assert_eq!(name("sparse+https://index.crates.io/"), None);
+        assert_eq!(name("git+https://github.com/foo/bar#0123abc"), None);
+        assert_eq!(
+            name("registry+https://git.example.com/index"),
+            Some(Arc::from("corp"))
+        );
+        assert_eq!(
+            name("sparse+https://cargo.example.com/api/"),
+            Some(Arc::from("sparse-corp"))
+        );
+        assert_eq!(
+            name("sparse+https://other.example.org/index/"),
+            Some(Arc::from("other.example.org"))
+        );
+    }
+}
@@ -0,0 +1,93 @@
+//! Support for workspaces that use `cargo vendor`. Vendored packages keep their original source
+//! (e.g. crates.io), but their code lives in a local directory that's configured via source
+//! replacement in `.cargo/config.toml`.
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Deserialize, Default)]
+pub(super) struct CargoConfig {
+    #[serde(default)]
+    source: BTreeMap<String, SourceConfig>,
+    #[serde(default)]
+    pub(super) registries: BTreeMap<String, RegistryConfig>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+struct SourceConfig {
+    directory: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Default)]
+pub(super) struct RegistryConfig {
+    pub(super) index: Option<String>,
+}
+
+/// Returns the directories configured as directory sources in the cargo config files that apply to
+/// `workspace_root`. These are the directories into which `cargo vendor` writes packages.
+pub(super) fn vendor_dirs(workspace_root: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for (config_dir, config) in cargo_configs(workspace_root)? {
+        // Relative paths in cargo config are relative to the directory containing `.cargo`.
+        let base = config_dir.parent().unwrap_or(&config_dir);
+        for directory in config.source.into_values().filter_map(|s| s.directory) {
+            let dir = base.join(directory);
+            dirs.push(dir.canonicalize().unwrap_or(dir));
+        }
+    }
+    Ok(dirs)
+}
+
+/// Returns the cargo config files that apply to `workspace_root`, together with the `.cargo`
+/// directory that each was found in. Configs closer to the workspace come first.
+pub(super) fn cargo_configs(workspace_root: &Path) -> Result<Vec<(PathBuf, CargoConfig)>> {
+    let mut config_dirs: Vec<PathBuf> = workspace_root
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .collect();
+    if let Some(cargo_home) = cargo_home() {
+        if !config_dirs.contains(&cargo_home) {
+            config_dirs.push(cargo_home);
+        }
+    }
+    let mut configs = Vec::new();
+    for config_dir in config_dirs {
+        for filename in ["config.toml", "config"] {
+            let path = config_dir.join(filename);
+            if !path.exists() {
+                continue;
+            }
+            let config: CargoConfig = toml::from_str(&crate::fs::read_to_string(&path)?)
+                .with_context(|| format!("Failed to parse `{}`", path.display()))?;
+            configs.push((config_dir, config));
+            // Cargo ignores `config` if `config.toml` exists.
+            break;
+        }
+    }
+    Ok(configs)
+}
+
+fn cargo_home() -> Option<PathBuf> {
+    if let Some(cargo_home) = std::env::var_os("CARGO_HOME") {
+        return Some(PathBuf::from(cargo_home));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vendored_test_workspace() {
+        let crate_root = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
+        let workspace = crate_root.join("test_crates").join("vendored");
+        let dirs = vendor_dirs(&workspace).unwrap();
+        assert!(dirs.contains(&workspace.join("vendor").canonicalize().unwrap()));
+    }
+}
@@ -0,0 +1,364 @@
+//! A long-running process that keeps expensive state warm between checks. Cold start costs, such as
+//! running `cargo metadata` and scanning binaries that haven't changed, otherwise dominate when
+//! checking repeatedly during development. Checks are requested with `cargo acl check
+//! --use-daemon`, which sends a request over a Unix socket and prints the results.
+
+use crate::analysis::ReportSink;
+use crate::crate_index::CrateIndex;
+use crate::diagnostic::Diagnostic;
+use crate::outcome;
+use crate::outcome::ExitCode;
+use crate::problem::Problem;
+use crate::proxy::cargo::NetworkMode;
+use crate::proxy::rpc::read_from_stream;
+use crate::proxy::rpc::write_to_stream;
+use crate::scan_cache::ScanCache;
+use crate::Args;
+use crate::Cackle;
+use crate::Command;
+use crate::Severity;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use log::info;
+use serde::Deserialize;
+use serde::Serialize;
+use std::os::unix::net::UnixListener;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct DaemonOptions {
+    /// The Unix socket that the daemon listens on. Defaults to `cackle/daemon.sock` in the target
+    /// directory.
+    #[clap(long)]
+    socket: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct CheckOptions {
+    /// Send the check to a daemon started with `cargo acl daemon` rather than running it directly.
+    #[clap(long)]
+    pub(crate) use_daemon: bool,
+
+    /// Analyse the artifacts left in the target directory by a previous build rather than
+    /// building. The build must have been done with
+    /// RUSTFLAGS="-C debuginfo=2 -C save-temps -C codegen-units=1".
+    #[clap(long)]
+    pub(crate) no_build: bool,
+
+    /// Only rebuild and re-analyse this package and whatever depends on it. Everything else is
+    /// assumed to be unchanged since the last check.
+    #[clap(short, long, conflicts_with = "no_build")]
+    pub(crate) package: Option<String>,
+
+    #[clap(flatten)]
+    daemon: DaemonOptions,
+}
+
+/// State that's expensive to compute, which the daemon keeps between checks.
+#[derive(Clone)]
+pub(crate) struct WarmState {
+    pub(crate) crate_index: Arc<CrateIndex>,
+    pub(crate) sysroot: Arc<Path>,
+    pub(crate) scan_cache: Arc<ScanCache>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CheckRequest {
+    fail_on_warnings: bool,
+    separate_versions: bool,
+    package: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CheckResponse {
+    success: bool,
+    problems: Vec<ResponseProblem>,
+    error: Option<String>,
+}
+
+/// A problem found by the daemon, with what's needed to render it as a diagnostic in the same way
+/// as when we run without the daemon.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct ResponseProblem {
+    severity: Severity,
+    problem: Problem,
+    notes: Vec<String>,
+}
+
+impl DaemonOptions {
+    fn socket_path(&self, root_path: &Path, args: &Args) -> Result<PathBuf> {
+        if let Some(socket) = &self.socket {
+            return Ok(socket.clone());
+        }
+        Ok(crate::target_dir(root_path, args)?
+            .join("cackle")
+            .join("daemon.sock"))
+    }
+}
+
+/// Listens for check requests until we're killed.
+pub(crate) fn run(args: &Args, options: &DaemonOptions) -> Result<()> {
+    let root_path = crate::canonical_root_path(args)?;
+    let socket_path = options.socket_path(&root_path, args)?;
+    if let Some(dir) = socket_path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    }
+    let _ = std::fs::remove_file(&socket_path);
+    let listener = UnixListener::bind(&socket_path)
+        .with_context(|| format!("Failed to create Unix socket `{}`", socket_path.display()))?;
+    println!("Listening on {}", socket_path.display());
+    let mut daemon = Daemon {
+        root_path,
+        args: args.clone(),
+        warm_state: None,
+        manifest_times: Vec::new(),
+        scan_cache: Arc::new(ScanCache::default()),
+    };
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        if let Err(error) = daemon.handle_connection(&mut stream) {
+            println!("Failed to handle request: {error:#}");
+        }
+    }
+    Ok(())
+}
+
+/// Sends a check request to the daemon and prints the results.
+pub(crate) fn check(args: &Args, options: &CheckOptions) -> Result<ExitCode> {
+    let root_path = crate::canonical_root_path(args)?;
+    let socket_path = options.daemon.socket_path(&root_path, args)?;
+    let mut stream = UnixStream::connect(&socket_path).with_context(|| {
+        format!(
+            "Failed to connect to daemon at `{}`. Start one with `cargo acl daemon`",
+            socket_path.display()
+        )
+    })?;
+    write_to_stream(
+        &CheckRequest {
+            fail_on_warnings: args.fail_on_warnings,
+            separate_versions: args.separate_versions,
+            package: options.package.clone(),
+        },
+        &mut stream,
+    )?;
+    let response: CheckResponse = read_from_stream(&mut stream)?;
+    let github_workspace = std::env::var_os("GITHUB_WORKSPACE").map(PathBuf::from);
+    for problem in response.problems {
+        let mut diagnostic = Diagnostic::new(&problem.problem, problem.severity);
+        for note in problem.notes {
+            diagnostic.add_note(note);
+        }
+        println!(
+            "{}",
+            diagnostic.render(args.format, github_workspace.as_deref())
+        );
+    }
+    if let Some(error) = &response.error {
+        println!("Error: {error}");
+    }
+    if response.success && !args.quiet {
+        println!("Completed successfully");
+    }
+    Ok(if response.success {
+        outcome::SUCCESS
+    } else {
+        outcome::FAILURE
+    })
+}
+
+struct Daemon {
+    root_path: PathBuf,
+    args: Args,
+    warm_state: Option<WarmState>,
+
+    /// The modification times of the manifests and lock file when we loaded the crate index. If
+    /// any of these change, we reload it.
+    manifest_times: Vec<(PathBuf, Option<SystemTime>)>,
+
+    scan_cache: Arc<ScanCache>,
+}
+
+impl Daemon {
+    fn handle_connection(&mut self, stream: &mut UnixStream) -> Result<()> {
+        let request: CheckRequest = read_from_stream(stream)?;
+        info!("Daemon received check request");
+        let response = match self.check(&request) {
+            Ok(report) => CheckResponse {
+                success: report.success,
+                problems: report
+                    .problems
+                    .into_iter()
+                    .map(|problem| ResponseProblem {
+                        severity: problem.severity,
+                        problem: problem.problem,
+                        notes: problem.notes,
+                    })
+                    .collect(),
+                error: None,
+            },
+            Err(error) => CheckResponse {
+                success: false,
+                problems: Vec::new(),
+                error: Some(format!("{error:#}")),
+            },
+        };
+        write_to_stream(&response, stream)
+    }
+
+    fn check(&mut self, request: &CheckRequest) -> Result<crate::AnalysisReport> {
+        let warm_state = self.warm_state()?;
+        let args = Args {
+            fail_on_warnings: request.fail_on_warnings,
+            separate_versions: request.separate_versions,
+            ui: Some(crate::ui::Kind::None),
+            colour: crate::colour::Colour::Never,
+            quiet: true,
+            command: Some(Command::Check(CheckOptions {
+                package: request.package.clone(),
+                ..CheckOptions::default()
+            })),
+            ..self.args.clone()
+        };
+        let report = ReportSink::default();
+        let (abort_sender, abort_recv) = std::sync::mpsc::channel();
+        let cackle = Cackle::new(args, abort_sender, Some(report.clone()), Some(warm_state))?;
+        cackle.analyze(abort_recv, &report)
+    }
+
+    /// Returns our warm state, reloading the crate index if any manifests have changed.
+    fn warm_state(&mut self) -> Result<WarmState> {
+        let is_stale = manifests_changed(&self.manifest_times);
+        if let (Some(warm_state), false) = (&self.warm_state, is_stale) {
+            return Ok(warm_state.clone());
+        }
+        info!("Loading crate index");
+        let crate_index = Arc::new(CrateIndex::new(
+            &crate::manifest_paths(&self.args)?,
+            NetworkMode::from_args(&self.args),
+        )?);
+        // The contents of object files don't change when we reload the crate index, but how they're
+        // attributed to packages might.
+        self.scan_cache.clear();
+        self.manifest_times = manifest_times(watched_paths(&crate_index));
+        let warm_state = WarmState {
+            crate_index,
+            sysroot: crate::determine_sysroot(&self.root_path, self.args.target.as_deref())?,
+            scan_cache: self.scan_cache.clone(),
+        };
+        self.warm_state = Some(warm_state.clone());
+        Ok(warm_state)
+    }
+}
+
+/// Returns the manifests of packages within our workspaces together with their lock files.
+fn watched_paths(crate_index: &CrateIndex) -> Vec<PathBuf> {
+    let mut paths = vec![crate_index.manifest_path.clone()];
+    for root_path in &crate_index.workspace_roots {
+        paths.push(root_path.join("Cargo.lock"));
+    }
+    for pkg_id in crate_index.package_ids() {
+        if let Some(dir) = crate_index.pkg_dir(pkg_id) {
+            if crate_index
+                .workspace_roots
+                .iter()
+                .any(|root_path| dir.starts_with(root_path))
+            {
+                paths.push(dir.join("Cargo.toml"));
+            }
+        }
+    }
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+fn manifest_times(paths: Vec<PathBuf>) -> Vec<(PathBuf, Option<SystemTime>)> {
+    paths
+        .into_iter()
+        .map(|path| {
+            let time = modification_time(&path);
+            (path, time)
+        })
+        .collect()
+}
+
+/// Returns whether any of the files have been modified, created or deleted since their times were
+/// recorded.
+fn manifests_changed(manifest_times: &[(PathBuf, Option<SystemTime>)]) -> bool {
+    manifest_times
+        .iter()
+        .any(|(path, time)| modification_time(path) != *time)
+}
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_response_round_trip() {
+        let mut buf = Vec::new();
+        write_to_stream(
+            &CheckRequest {
+                fail_on_warnings: true,
+                separate_versions: false,
+                package: Some("foo".to_owned()),
+            },
+            &mut buf,
+        )
+        .unwrap();
+        let request: CheckRequest = read_from_stream(&mut buf.as_slice()).unwrap();
+        assert!(request.fail_on_warnings);
+        assert!(!request.separate_versions);
+        assert_eq!(request.package.as_deref(), Some("foo"));
+
+        let problem = || ResponseProblem {
+            severity: Severity::Error,
+            problem: Problem::new("Crate `foo` uses `fs`"),
+            notes: vec!["Dependency path: bar -> foo".to_owned()],
+        };
+        let mut buf = Vec::new();
+        write_to_stream(
+            &CheckResponse {
+                success: false,
+                problems: vec![problem()],
+                error: None,
+            },
+            &mut buf,
+        )
+        .unwrap();
+        let response: CheckResponse = read_from_stream(&mut buf.as_slice()).unwrap();
+        assert!(!response.success);
+        assert_eq!(response.problems, vec![problem()]);
+        assert_eq!(response.error, None);
+    }
+
+    #[test]
+    fn stale_when_manifest_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("Cargo.toml");
+        let lock_file = dir.path().join("Cargo.lock");
+        std::fs::write(&manifest, "").unwrap();
+
+        let times = manifest_times(vec![manifest.clone(), lock_file.clone()]);
+        assert!(!manifests_changed(&times));
+
+        std::fs::write(&lock_file, "").unwrap();
+        assert!(manifests_changed(&times));
+
+        let times = manifest_times(vec![manifest.clone(), lock_file]);
+        assert!(!manifests_changed(&times));
+
+        std::fs::remove_file(&manifest).unwrap();
+        assert!(manifests_changed(&times));
+    }
+}
@@ -7,30 +7,71 @@ use anyhow::Result;
 use std::path::Path;
 use std::path::PathBuf;
 
+/// What we extract from a deps file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct DepInfo {
+    /// The paths of all the source files that were compiled.
+    pub(crate) source_paths: Vec<PathBuf>,
+
+    /// Those of `source_paths` that are in the build script's output directory, i.e. that the build
+    /// script generated.
+    pub(crate) generated_paths: Vec<PathBuf>,
+}
+
 /// Uses the supplied rustc arguments to determine where the deps file will be located, then reads
-/// it and extracts the paths of all the source files.
-pub(crate) fn source_files_from_rustc_args(
-    args: impl Iterator<Item = String>,
-) -> Result<Vec<PathBuf>> {
+/// it.
+pub(crate) fn dep_info_from_rustc_args(args: impl Iterator<Item = String>) -> Result<DepInfo> {
     let Some(deps_path) = deps_path_from_rustc_args(args)? else {
-        return Ok(vec![]);
+        return Ok(DepInfo::default());
     };
-    let deps = std::fs::read_to_string(&deps_path)
+    dep_info_from_deps_file(&deps_path, Path::new(""))
+}
+
+/// Reads the deps file at `deps_path`. Relative paths are resolved against `base_dir`, which should
+/// be the directory in which rustc was run.
+pub(crate) fn dep_info_from_deps_file(deps_path: &Path, base_dir: &Path) -> Result<DepInfo> {
+    let deps = std::fs::read_to_string(deps_path)
         .with_context(|| format!("Failed to read deps file `{}`", deps_path.display()))?;
-    Ok(parse_deps(&deps)?
+    let parsed = parse_deps(&deps)?;
+    let source_paths: Vec<PathBuf> = parsed
+        .deps
         .into_iter()
-        .flat_map(|dep| dep.canonicalize())
-        .collect())
+        .flat_map(|dep| base_dir.join(dep).canonicalize())
+        .collect();
+    let out_dir = parsed.out_dir.map(|dir| dir.canonicalize().unwrap_or(dir));
+    let generated_paths = out_dir
+        .map(|out_dir| {
+            source_paths
+                .iter()
+                .filter(|path| path.starts_with(&out_dir))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(DepInfo {
+        source_paths,
+        generated_paths,
+    })
 }
 
-fn parse_deps(deps_text: &str) -> Result<Vec<PathBuf>> {
+struct ParsedDeps {
+    deps: Vec<PathBuf>,
+    /// The value of `OUT_DIR`, if the crate referenced it, which it will have done if it included
+    /// anything generated by its build script.
+    out_dir: Option<PathBuf>,
+}
+
+fn parse_deps(deps_text: &str) -> Result<ParsedDeps> {
     let mut deps = Vec::new();
+    let mut out_dir = None;
     for line in deps_text.lines() {
         if let Some(filename) = line.strip_suffix(':') {
             deps.push(PathBuf::from(filename));
+        } else if let Some(dir) = line.strip_prefix("# env-dep:OUT_DIR=") {
+            out_dir = Some(PathBuf::from(dir));
         }
     }
-    Ok(deps)
+    Ok(ParsedDeps { deps, out_dir })
 }
 
 fn deps_path_from_rustc_args(mut args: impl Iterator<Item = String>) -> Result<Option<PathBuf>> {
@@ -72,6 +113,7 @@ fn deps_path_from_rustc_args(mut args: impl Iterator<Item = String>) -> Result<O
 
 #[cfg(test)]
 mod tests {
+    use super::dep_info_from_deps_file;
     use super::deps_path_from_rustc_args;
     use super::parse_deps;
     use anyhow::Result;
@@ -151,8 +193,38 @@ mod tests {
             "#})
         .unwrap();
         assert_eq!(
-            path_strings(&deps),
+            path_strings(&deps.deps),
             &["foo/src/lib.rs", "/some/absolute/path/extra.rs"]
+        );
+        assert_eq!(
+            deps.out_dir,
+            Some(PathBuf::from("/some/path/target/debug/build/foo-1235/out"))
+        );
+    }
+
+    #[test]
+    fn test_generated_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        let out_dir = dir.join("target/debug/build/foo-1235/out");
+        std::fs::create_dir_all(&out_dir).unwrap();
+        std::fs::create_dir_all(dir.join("foo/src")).unwrap();
+        std::fs::write(dir.join("foo/src/lib.rs"), "").unwrap();
+        std::fs::write(out_dir.join("generated.rs"), "").unwrap();
+        let deps_path = dir.join("foo-1235.d");
+        std::fs::write(
+            &deps_path,
+            format!(
+                "foo/src/lib.rs:\n{out}/generated.rs:\n\n# env-dep:OUT_DIR={out}\n",
+                out = out_dir.display()
+            ),
         )
+        .unwrap();
+        let dep_info = dep_info_from_deps_file(&deps_path, &dir).unwrap();
+        assert_eq!(
+            dep_info.source_paths,
+            vec![dir.join("foo/src/lib.rs"), out_dir.join("generated.rs")]
+        );
+        assert_eq!(dep_info.generated_paths, vec![out_dir.join("generated.rs")]);
     }
 }
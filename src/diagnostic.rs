@@ -0,0 +1,389 @@
+//! Rendering of problems in the same format that rustc uses for its diagnostics. This lets tools
+//! that already understand rustc's output, such as IDE problem matchers, pick up our problems.
+
+use crate::location::SourceLocation;
+use crate::problem::help::ProblemHelp;
+use crate::problem::Problem;
+use crate::problem::Severity;
+use clap::ValueEnum;
+use colored::Colorize;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::path::Path;
+
+/// How problems are printed when running without a user interface.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// The same format that rustc uses for errors and warnings.
+    #[default]
+    Rustc,
+    /// GitHub Actions workflow commands, so that problems show up as annotations.
+    Github,
+    /// A JSON object per line, including an explanation of each kind of problem.
+    Json,
+}
+
+/// The maximum number of locations, other than the primary location, that we list.
+const MAX_EXTRA_LOCATIONS: usize = 10;
+
+pub(crate) struct Diagnostic {
+    severity: Severity,
+    code: String,
+    kind: &'static str,
+    message: String,
+    span: Option<Span>,
+    notes: Vec<String>,
+    help: Option<&'static ProblemHelp>,
+}
+
+/// What we print for each diagnostic when `--format json` is selected.
+#[derive(Serialize)]
+struct JsonDiagnostic<'a> {
+    code: &'a str,
+    kind: &'a str,
+    severity: &'a str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<u32>,
+    notes: &'a [String],
+    explanation: &'a str,
+    causes: &'a [&'a str],
+    remediation: &'a [&'a str],
+    help_url: Option<String>,
+}
+
+/// The primary location of a diagnostic together with the line of source at that location, if we
+/// were able to read it.
+struct Span {
+    location: SourceLocation,
+    source_line: Option<String>,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(problem: &Problem, severity: Severity) -> Self {
+        let mut locations = problem.source_locations();
+        locations.sort();
+        locations.dedup();
+        let mut notes = Vec::new();
+        if locations.is_empty() {
+            // Without a location, the detailed form of the problem is all we have to go on. Its
+            // first line generally repeats the message.
+            let details = format!("{problem:#}");
+            let rest = details.split_once('\n').map_or("", |(_, rest)| rest);
+            let rest = rest.trim_end();
+            if !rest.is_empty() {
+                notes.push(rest.to_owned());
+            }
+        } else {
+            notes.extend(
+                locations
+                    .iter()
+                    .skip(1)
+                    .take(MAX_EXTRA_LOCATIONS)
+                    .map(|location| format!("also at {}", location_string(location))),
+            );
+            let num_omitted = locations.len().saturating_sub(MAX_EXTRA_LOCATIONS + 1);
+            if num_omitted > 0 {
+                notes.push(format!("and {num_omitted} other location(s)"));
+            }
+        }
+        Self {
+            severity,
+            code: problem.code(),
+            kind: problem.kind_name(),
+            message: problem.to_string(),
+            span: locations.first().map(|location| Span {
+                location: (*location).clone(),
+                source_line: read_line(location),
+            }),
+            notes,
+            help: problem.help(),
+        }
+    }
+
+    pub(crate) fn add_note(&mut self, note: String) {
+        self.notes.push(note);
+    }
+
+    /// Returns this diagnostic in `format`. For GitHub workflow commands, file paths are made
+    /// relative to `github_workspace` if supplied.
+    pub(crate) fn render(&self, format: Format, github_workspace: Option<&Path>) -> String {
+        match format {
+            Format::Rustc => self.to_string(),
+            Format::Github => self.github_command(github_workspace),
+            Format::Json => self.json(),
+        }
+    }
+
+    /// Returns a GitHub Actions workflow command that reports this diagnostic as an annotation.
+    /// File paths are made relative to `workspace` if supplied, since that's what GitHub expects.
+    pub(crate) fn github_command(&self, workspace: Option<&Path>) -> String {
+        let command = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        let mut properties = Vec::new();
+        if let Some(span) = &self.span {
+            let location = &span.location;
+            let path = relative_to(location.filename(), workspace);
+            properties.push(format!(
+                "file={}",
+                escape_property(&path.display().to_string())
+            ));
+            properties.push(format!("line={}", location.line()));
+            if let Some(column) = location.column() {
+                properties.push(format!("col={column}"));
+            }
+        }
+        properties.push(format!("title={}", self.code));
+        let mut message = self.message.clone();
+        for note in &self.notes {
+            message.push('\n');
+            message.push_str(note);
+        }
+        format!(
+            "::{command} {}::{}",
+            properties.join(","),
+            escape_data(&message)
+        )
+    }
+
+    /// Returns this diagnostic as a single line of JSON, together with the explanation of its kind
+    /// of problem.
+    pub(crate) fn json(&self) -> String {
+        let location = self.span.as_ref().map(|span| &span.location);
+        let json = JsonDiagnostic {
+            code: &self.code,
+            kind: self.kind,
+            severity: match self.severity {
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            },
+            message: &self.message,
+            file: location.map(|location| location.filename().display().to_string()),
+            line: location.map(|location| location.line()),
+            column: location.and_then(|location| location.column()),
+            notes: &self.notes,
+            explanation: self.help.map_or("", |help| help.explanation),
+            causes: self.help.map_or(&[], |help| help.causes),
+            remediation: self.help.map_or(&[], |help| help.remediation),
+            help_url: self.help.map(|help| help.url()),
+        };
+        // Everything that we serialise is a string or a number, so this can't fail.
+        serde_json::to_string(&json).unwrap()
+    }
+}
+
+fn relative_to<'a>(path: &'a Path, workspace: Option<&Path>) -> Cow<'a, Path> {
+    workspace
+        .and_then(|workspace| path.strip_prefix(workspace).ok())
+        .map_or(Cow::Borrowed(path), |relative| {
+            Cow::Owned(relative.to_owned())
+        })
+}
+
+/// Escapes the message part of a workflow command.
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes the value of a property of a workflow command.
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+fn read_line(location: &SourceLocation) -> Option<String> {
+    let source = std::fs::read_to_string(location.filename()).ok()?;
+    let index = (location.line() as usize).checked_sub(1)?;
+    source.lines().nth(index).map(|line| line.to_owned())
+}
+
+fn location_string(location: &SourceLocation) -> String {
+    let mut out = format!("{}:{}", location.filename().display(), location.line());
+    if let Some(column) = location.column() {
+        out.push_str(&format!(":{column}"));
+    }
+    out
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = match self.severity {
+            Severity::Warning => "warning".yellow().bold(),
+            Severity::Error => "error".red().bold(),
+        };
+        writeln!(f, "{level}[{}]: {}", self.code, self.message.bold())?;
+        // The gutter is wide enough for the line number, so that the source line lines up with the
+        // lines around it.
+        let gutter = " ".repeat(
+            self.span
+                .as_ref()
+                .map_or(1, |span| span.location.line().to_string().len()),
+        );
+        if let Some(span) = &self.span {
+            let arrow = "-->".blue().bold();
+            writeln!(f, "{gutter}{arrow} {}", location_string(&span.location))?;
+            if let Some(source_line) = &span.source_line {
+                let bar = "|".blue().bold();
+                writeln!(f, "{gutter} {bar}")?;
+                let line_number = span.location.line().to_string().blue().bold();
+                writeln!(f, "{line_number} {bar} {source_line}")?;
+                if let Some(column) = span.location.column() {
+                    // Preserve tabs, so that the marker lines up with the source.
+                    let indent: String = source_line
+                        .chars()
+                        .take((column as usize).saturating_sub(1))
+                        .map(|c| if c == '\t' { '\t' } else { ' ' })
+                        .collect();
+                    let marker = match self.severity {
+                        Severity::Warning => "^".yellow().bold(),
+                        Severity::Error => "^".red().bold(),
+                    };
+                    writeln!(f, "{gutter} {bar} {indent}{marker}")?;
+                }
+            }
+        }
+        for note in &self.notes {
+            let mut lines = note.lines();
+            if let Some(first) = lines.next() {
+                writeln!(
+                    f,
+                    "{gutter} {} {}: {first}",
+                    "=".blue().bold(),
+                    "note".bold()
+                )?;
+            }
+            // Continuation lines are aligned with the start of the note's text.
+            for line in lines {
+                writeln!(f, "{gutter}         {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::permissions::PermSel;
+    use crate::crate_index::testing::pkg_id;
+    use crate::crate_index::CrateSel;
+    use crate::proxy::rpc::UnsafeUsage;
+
+    #[test]
+    fn with_source_location() {
+        let dir = tempfile::tempdir().unwrap();
+        let source_path = dir.path().join("lib.rs");
+        std::fs::write(&source_path, "fn f() {\n\tunsafe { g() }\n}\n").unwrap();
+        let problem = Problem::DisallowedUnsafe(UnsafeUsage {
+            crate_sel: CrateSel::primary(pkg_id("foo")),
+            locations: vec![
+                SourceLocation::new(source_path.as_path(), 2, Some(2)),
+                SourceLocation::new(source_path.as_path(), 20, None),
+            ],
+        });
+        let mut diagnostic = Diagnostic::new(&problem, Severity::Error);
+        diagnostic.add_note("Dependency path: bar -> foo".to_owned());
+        let path = source_path.display();
+        assert_eq!(
+            diagnostic.to_string(),
+            format!(
+                "{}[CACKLE004]: {}\n \
+                 {} {path}:2:2\n  \
+                 {}\n\
+                 {} {} \tunsafe {{ g() }}\n  \
+                 {} \t{}\n  \
+                 {} {}: also at {path}:20\n  \
+                 {} {}: Dependency path: bar -> foo\n",
+                "error".red().bold(),
+                "`foo` uses unsafe".bold(),
+                "-->".blue().bold(),
+                "|".blue().bold(),
+                "2".blue().bold(),
+                "|".blue().bold(),
+                "|".blue().bold(),
+                "^".red().bold(),
+                "=".blue().bold(),
+                "note".bold(),
+                "=".blue().bold(),
+                "note".bold(),
+            )
+        );
+    }
+
+    #[test]
+    fn github() {
+        let problem = Problem::DisallowedUnsafe(UnsafeUsage {
+            crate_sel: CrateSel::primary(pkg_id("foo")),
+            locations: vec![SourceLocation::new(
+                Path::new("/work/foo/src/lib.rs"),
+                2,
+                Some(5),
+            )],
+        });
+        let mut diagnostic = Diagnostic::new(&problem, Severity::Error);
+        diagnostic.add_note("Dependency path: bar -> foo".to_owned());
+        assert_eq!(
+            diagnostic.github_command(Some(Path::new("/work"))),
+            "::error file=foo/src/lib.rs,line=2,col=5,title=CACKLE004::`foo` uses unsafe%0A\
+             Dependency path: bar -> foo"
+        );
+
+        let problem = Problem::UnusedPackageConfig(PermSel::for_primary("foo"));
+        let diagnostic = Diagnostic::new(&problem, Severity::Warning);
+        assert_eq!(
+            diagnostic.github_command(None),
+            "::warning title=CACKLE013::Config supplied for package `foo` not in dependency tree"
+        );
+        assert_eq!(escape_property("a:b,c%"), "a%3Ab%2Cc%25");
+    }
+
+    #[test]
+    fn json() {
+        let problem = Problem::DisallowedUnsafe(UnsafeUsage {
+            crate_sel: CrateSel::primary(pkg_id("foo")),
+            locations: vec![SourceLocation::new(
+                Path::new("/work/foo/src/lib.rs"),
+                2,
+                Some(5),
+            )],
+        });
+        let diagnostic = Diagnostic::new(&problem, Severity::Error);
+        let json: serde_json::Value = serde_json::from_str(&diagnostic.json()).unwrap();
+        assert_eq!(json["code"], "CACKLE004");
+        assert_eq!(json["kind"], "disallowed_unsafe");
+        assert_eq!(json["severity"], "error");
+        assert_eq!(json["message"], "`foo` uses unsafe");
+        assert_eq!(json["file"], "/work/foo/src/lib.rs");
+        assert_eq!(json["line"], 2);
+        assert_eq!(json["column"], 5);
+        assert!(json["explanation"].as_str().unwrap().contains("unsafe"));
+        assert!(json["remediation"].is_array());
+        assert_eq!(
+            json["help_url"],
+            "https://github.com/cackle-rs/cackle/blob/main/PROBLEMS.md#disallowed_unsafe"
+        );
+    }
+
+    #[test]
+    fn without_source_location() {
+        let problem = Problem::UnusedPackageConfig(PermSel::for_primary("foo"));
+        let diagnostic = Diagnostic::new(&problem, Severity::Warning);
+        assert_eq!(
+            diagnostic.to_string(),
+            format!(
+                "{}[CACKLE013]: {}\n",
+                "warning".yellow().bold(),
+                "Config supplied for package `foo` not in dependency tree".bold(),
+            )
+        );
+        assert_eq!(problem.code(), "CACKLE013");
+    }
+}
@@ -4,4 +4,9 @@ pub(crate) enum AppEvent {
     Shutdown,
     /// New problems have been added to the problem store.
     ProblemsAdded,
+    /// A binary needs to be attached to the terminal. The UI should stop using the terminal, then
+    /// call `ProblemStore::terminal_released`.
+    ReleaseTerminal,
+    /// The binary that was attached to the terminal has finished. The UI can use it again.
+    TerminalAvailable,
 }
@@ -0,0 +1,419 @@
+//! Analysis of the artifacts of a build that has already been done, for when rebuilding just so that
+//! we can intercept rustc and the linker would take too long. We find the dep-info files that rustc
+//! wrote for each crate, then produce the same requests that our rustc and linker wrappers would
+//! have sent had we done the build ourselves.
+
+use crate::config::permissions::Permissions;
+use crate::crate_index::CrateIndex;
+use crate::crate_index::CrateKind;
+use crate::crate_index::CrateSel;
+use crate::crate_index::PackageId;
+use crate::link_info::LinkInfo;
+use crate::proxy::rpc::Request;
+use crate::proxy::rpc::RustcOutput;
+use crate::proxy::rpc::UnsafeUsage;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use fxhash::FxHashMap;
+use log::info;
+use object::Object;
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Flags that the build needs to have been done with in order for us to be able to analyse it.
+/// These match what we pass to rustc when we do the build ourselves.
+pub(crate) const REQUIRED_RUSTFLAGS: &str = "-C debuginfo=2 -C save-temps -C codegen-units=1";
+
+/// A crate that rustc compiled, as determined from its dep-info file.
+struct Artifact {
+    crate_sel: CrateSel,
+    crate_name: String,
+    /// The hash that cargo appends to file names. e.g. "0188200cb614ae3d".
+    hash: String,
+    /// The directory containing the dep-info file.
+    dir: PathBuf,
+    source_paths: Vec<PathBuf>,
+    /// Those of `source_paths` that were generated by the package's build script.
+    generated_paths: Vec<PathBuf>,
+    modified: SystemTime,
+    /// The executable or shared object produced by the linker, if any.
+    linked_output: Option<LinkedOutput>,
+    is_staticlib: bool,
+}
+
+struct LinkedOutput {
+    path: PathBuf,
+    is_shared: bool,
+}
+
+/// Returns the directory in which cargo puts artifacts when building with `profile`.
+pub(crate) fn profile_dir(target_dir: &Path, target: Option<&str>, profile: &str) -> PathBuf {
+    let dir_name = match profile {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        other => other,
+    };
+    let mut dir = target_dir.to_owned();
+    if let Some(target) = target {
+        dir.push(target);
+    }
+    dir.join(dir_name)
+}
+
+/// Returns requests equivalent to those that would have been sent while building the artifacts in
+/// `profile_dir`.
+pub(crate) fn requests(
+    profile_dir: &Path,
+    root_path: &Path,
+    crate_index: &CrateIndex,
+    permissions: &Permissions,
+) -> Result<Vec<Request>> {
+    let artifacts = find_artifacts(profile_dir, root_path, crate_index)?;
+    if artifacts.is_empty() {
+        bail!(
+            "No build artifacts found in `{}`. Build first, or omit --no-build",
+            profile_dir.display()
+        );
+    }
+    let rlibs = newest_rlibs(&profile_dir.join("deps"))?;
+    // Crates that get linked are checked once rustc completes, so we first record the sources of
+    // all the crates that don't get linked, so that the source paths are known by then.
+    let (linked, unlinked): (Vec<_>, Vec<_>) = artifacts
+        .into_iter()
+        .partition(|artifact| artifact.linked_output.is_some());
+    let mut requests = Vec::new();
+    for artifact in unlinked.iter().chain(&linked) {
+        if !permissions.unsafe_permitted_for_crate(&artifact.crate_sel) {
+            let pkg_dir = crate_index
+                .pkg_dir(artifact.crate_sel.pkg_id())
+                .map(|dir| dir.canonicalize().unwrap_or_else(|_| dir.to_owned()))
+                .unwrap_or_default();
+            let mut locations = Vec::new();
+            for path in &artifact.source_paths {
+                for site in crate::unsafe_checker::scan_path(path)? {
+                    log::debug!(
+                        "Found {} in `{}` at {}",
+                        site.kind,
+                        artifact.crate_sel,
+                        site.location
+                    );
+                    if !permissions.unsafe_permitted_at(
+                        &artifact.crate_sel,
+                        &pkg_dir,
+                        &site.location,
+                    ) {
+                        locations.push(site.location);
+                    }
+                }
+            }
+            if !locations.is_empty() {
+                locations.sort();
+                locations.dedup();
+                requests.push(Request::CrateUsesUnsafe(UnsafeUsage {
+                    crate_sel: artifact.crate_sel.clone(),
+                    locations,
+                }));
+            }
+        }
+        if let Some(output) = &artifact.linked_output {
+            requests.push(Request::LinkerInvoked(link_info(
+                artifact,
+                output,
+                &rlibs,
+                crate_index,
+            )?));
+        }
+        requests.push(Request::RustcComplete(RustcOutput {
+            crate_sel: artifact.crate_sel.clone(),
+            source_paths: artifact.source_paths.clone(),
+            is_staticlib: artifact.is_staticlib,
+            path_remaps: Vec::new(),
+            generated_paths: artifact.generated_paths.clone(),
+        }));
+    }
+    Ok(requests)
+}
+
+fn find_artifacts(
+    profile_dir: &Path,
+    root_path: &Path,
+    crate_index: &CrateIndex,
+) -> Result<Vec<Artifact>> {
+    let mut dep_info_paths = files_with_extension(&profile_dir.join("deps"), "d")?;
+    let build_dir = profile_dir.join("build");
+    if build_dir.exists() {
+        for entry in read_dir(&build_dir)? {
+            if entry.is_dir() {
+                dep_info_paths.extend(files_with_extension(&entry, "d")?);
+            }
+        }
+    }
+    // If a crate has been built more than once, e.g. with different features, then only the most
+    // recent build is used.
+    let mut newest: FxHashMap<(CrateSel, String, bool), Artifact> = FxHashMap::default();
+    for path in dep_info_paths {
+        let Some(artifact) = Artifact::from_dep_info(&path, profile_dir, root_path, crate_index)?
+        else {
+            continue;
+        };
+        let key = (
+            artifact.crate_sel.clone(),
+            artifact.crate_name.clone(),
+            artifact.linked_output.is_some(),
+        );
+        if newest
+            .get(&key)
+            .map_or(true, |existing| existing.modified < artifact.modified)
+        {
+            newest.insert(key, artifact);
+        }
+    }
+    let mut artifacts: Vec<Artifact> = newest.into_values().collect();
+    // Make the order of requests deterministic.
+    artifacts.sort_by_key(|artifact| artifact.dir.join(&artifact.hash));
+    Ok(artifacts)
+}
+
+impl Artifact {
+    fn from_dep_info(
+        path: &Path,
+        profile_dir: &Path,
+        root_path: &Path,
+        crate_index: &CrateIndex,
+    ) -> Result<Option<Self>> {
+        let Some((crate_name, hash)) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.rsplit_once('-'))
+        else {
+            return Ok(None);
+        };
+        let dep_info = crate::deps::dep_info_from_deps_file(path, root_path)?;
+        let Some(pkg_id) = dep_info
+            .source_paths
+            .first()
+            .and_then(|root| crate_index.package_id_for_path(root))
+        else {
+            info!(
+                "Skipping `{}` since it's not from a known package",
+                path.display()
+            );
+            return Ok(None);
+        };
+        let dir = path.parent().unwrap_or(Path::new("")).to_owned();
+        let stem = format!("{crate_name}-{hash}");
+        let linked_output = linked_output(&dir, &stem, crate_name, hash);
+        let kind = if crate_name.starts_with("build_script_") {
+            CrateKind::BuildScript
+        } else if linked_output.is_some() && is_test(profile_dir, hash)? {
+            CrateKind::Test
+        } else {
+            CrateKind::Primary
+        };
+        Ok(Some(Artifact {
+            crate_sel: CrateSel {
+                pkg_id: pkg_id.clone(),
+                kind,
+            },
+            crate_name: crate_name.to_owned(),
+            hash: hash.to_owned(),
+            is_staticlib: dir.join(format!("lib{stem}.a")).exists(),
+            modified: std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .with_context(|| {
+                    format!("Failed to get modification time of `{}`", path.display())
+                })?,
+            dir,
+            source_paths: dep_info.source_paths,
+            generated_paths: dep_info.generated_paths,
+            linked_output,
+        }))
+    }
+}
+
+/// Returns the output of the linker for the crate with the specified dep-info file stem.
+fn linked_output(dir: &Path, stem: &str, crate_name: &str, hash: &str) -> Option<LinkedOutput> {
+    // If we did the build, then we moved the executable aside and replaced it with a wrapper.
+    let executable = [dir.join(format!("{stem}.orig")), dir.join(stem)]
+        .into_iter()
+        .find(|path| path.is_file());
+    if let Some(path) = executable {
+        return Some(LinkedOutput {
+            path,
+            is_shared: false,
+        });
+    }
+    let shared = dir.join(format!("lib{crate_name}-{hash}.so"));
+    shared.is_file().then_some(LinkedOutput {
+        path: shared,
+        is_shared: true,
+    })
+}
+
+/// Returns whether cargo built the artifact with the specified hash as a test. We determine this
+/// from the fingerprint files that cargo writes, which are named according to the kind of target.
+fn is_test(profile_dir: &Path, hash: &str) -> Result<bool> {
+    let fingerprint_dir = profile_dir.join(".fingerprint");
+    if !fingerprint_dir.exists() {
+        return Ok(false);
+    }
+    let suffix = format!("-{hash}");
+    for dir in read_dir(&fingerprint_dir)? {
+        if dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(&suffix))
+        {
+            return Ok(read_dir(&dir)?.iter().any(|file| {
+                file.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("test-"))
+            }));
+        }
+    }
+    Ok(false)
+}
+
+fn link_info(
+    artifact: &Artifact,
+    output: &LinkedOutput,
+    rlibs: &FxHashMap<String, PathBuf>,
+    crate_index: &CrateIndex,
+) -> Result<LinkInfo> {
+    if !has_debug_info(&output.path)? {
+        bail!(
+            "`{}` has no debug info. Artifacts analysed with --no-build must be built with \
+             RUSTFLAGS=\"{REQUIRED_RUSTFLAGS}\"",
+            output.path.display()
+        );
+    }
+    // Object files for the crate itself are normally deleted once linking completes. They're only
+    // kept if the build was done with `-C save-temps`.
+    let object_prefix = format!("{}-{}.", artifact.crate_name, artifact.hash);
+    let mut object_paths: Vec<PathBuf> = files_with_extension(&artifact.dir, "o")?
+        .into_iter()
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&object_prefix))
+        })
+        .collect();
+    if object_paths.is_empty() {
+        bail!(
+            "No object files found for `{}`. Artifacts analysed with --no-build must be built with \
+             RUSTFLAGS=\"{REQUIRED_RUSTFLAGS}\"",
+            output.path.display()
+        );
+    }
+    object_paths.extend(
+        rlibs
+            .iter()
+            .filter(|(crate_name, _)| may_link(&artifact.crate_sel, crate_name, crate_index))
+            .map(|(_, path)| path.clone()),
+    );
+    object_paths.sort();
+    Ok(LinkInfo::new(
+        artifact.crate_sel.clone(),
+        object_paths,
+        Arc::from(output.path.as_path()),
+        output.is_shared,
+        crate_index.is_proc_macro(&artifact.crate_sel.pkg_id),
+    ))
+}
+
+/// Returns whether the crate `crate_sel` might have linked against the library `crate_name`.
+/// Linking against libraries that weren't actually used only results in more code being treated as
+/// dead, so we can be conservative. We only know the normal dependencies of packages, so for other
+/// kinds of crates we assume that everything might have been linked.
+fn may_link(crate_sel: &CrateSel, crate_name: &str, crate_index: &CrateIndex) -> bool {
+    if crate_sel.kind != CrateKind::Primary {
+        return true;
+    }
+    is_lib_of(crate_name, &crate_sel.pkg_id, crate_index)
+        || crate_index
+            .transitive_deps(&crate_sel.pkg_id)
+            .map_or(true, |deps| deps.contains(crate_name))
+}
+
+fn is_lib_of(crate_name: &str, pkg_id: &PackageId, crate_index: &CrateIndex) -> bool {
+    crate_index.name_prefix_to_pkg_id().get(crate_name) == Some(pkg_id)
+}
+
+/// Returns the most recently built rlib for each crate name in `deps_dir`.
+fn newest_rlibs(deps_dir: &Path) -> Result<FxHashMap<String, PathBuf>> {
+    let mut newest: FxHashMap<String, (SystemTime, PathBuf)> = FxHashMap::default();
+    for path in files_with_extension(deps_dir, "rlib")? {
+        let Some(crate_name) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.strip_prefix("lib"))
+            .and_then(|stem| stem.rsplit_once('-'))
+            .map(|(crate_name, _)| crate_name.to_owned())
+        else {
+            continue;
+        };
+        let modified = std::fs::metadata(&path).and_then(|metadata| metadata.modified())?;
+        if newest
+            .get(&crate_name)
+            .map_or(true, |(existing, _)| *existing < modified)
+        {
+            newest.insert(crate_name, (modified, path));
+        }
+    }
+    Ok(newest
+        .into_iter()
+        .map(|(crate_name, (_, path))| (crate_name, path))
+        .collect())
+}
+
+fn has_debug_info(path: &Path) -> Result<bool> {
+    let file = File::open(path).with_context(|| format!("Failed to open `{}`", path.display()))?;
+    let cache = object::read::ReadCache::new(file);
+    let obj = object::File::parse(&cache)
+        .with_context(|| format!("Failed to parse `{}`", path.display()))?;
+    Ok(obj.section_by_name(".debug_info").is_some())
+}
+
+fn files_with_extension(dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(read_dir(dir)?
+        .into_iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect())
+}
+
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    dir.read_dir()
+        .with_context(|| format!("Failed to read directory `{}`", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::profile_dir;
+    use std::path::Path;
+
+    #[test]
+    fn test_profile_dir() {
+        let target = Path::new("/w/target");
+        assert_eq!(
+            profile_dir(target, None, "dev"),
+            Path::new("/w/target/debug")
+        );
+        assert_eq!(
+            profile_dir(target, Some("x86_64-unknown-linux-gnu"), "release"),
+            Path::new("/w/target/x86_64-unknown-linux-gnu/release")
+        );
+        assert_eq!(
+            profile_dir(target, None, "cackle"),
+            Path::new("/w/target/cackle")
+        );
+    }
+}
@@ -1,6 +1,11 @@
 use anyhow::Context;
 use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::path::Component;
 use std::path::Path;
+use std::path::PathBuf;
 
 /// Writes `contents` to `path`. The write is first done to a temporary filename then renamed to
 /// `path`. This means that other processes will either see the old contents or the new contents,
@@ -27,3 +32,95 @@ pub(crate) fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Res
     let path = path.as_ref();
     std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
 }
+
+/// Returns `path` with `.` components removed and `..` components applied to the preceding
+/// component. Unlike `canonicalize`, this doesn't access the filesystem, so doesn't resolve
+/// symlinks. This is what cargo does to paths in manifests, so it lets us match paths from other
+/// sources, e.g. debug info, against paths from cargo metadata.
+pub(crate) fn normalize(path: &Path) -> Cow<'_, Path> {
+    if !path
+        .components()
+        .any(|c| matches!(c, Component::CurDir | Component::ParentDir))
+    {
+        return Cow::Borrowed(path);
+    }
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            // `..` only cancels out a preceding normal component. The parent of the root is the
+            // root, while leading `..` components need to be kept.
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(component),
+            },
+            other => normalized.push(other),
+        }
+    }
+    Cow::Owned(normalized)
+}
+
+/// A `--remap-path-prefix` rule that was passed to rustc. Rustc rewrites paths that start with
+/// `from` to instead start with `to` when writing them to debug info.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Hash)]
+pub(crate) struct PathRemap {
+    pub(crate) from: PathBuf,
+    pub(crate) to: PathBuf,
+}
+
+impl PathRemap {
+    /// Parses the value of a `--remap-path-prefix` flag. Like rustc, we split on the last `=`.
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        let (from, to) = value.rsplit_once('=')?;
+        Some(Self {
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+        })
+    }
+
+    /// Reverses this rule, returning the path that rustc would have rewritten to `path`, or None if
+    /// `path` doesn't start with `to`.
+    pub(crate) fn unmap(&self, path: &Path) -> Option<PathBuf> {
+        let rest = path.strip_prefix(&self.to).ok()?;
+        Some(self.from.join(rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize;
+    use super::PathRemap;
+    use std::path::Path;
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(normalize(Path::new("/a/b/c")), Path::new("/a/b/c"));
+        assert_eq!(normalize(Path::new("/a/./b/../c")), Path::new("/a/c"));
+        assert_eq!(
+            normalize(Path::new("/ws/../foo/src")),
+            Path::new("/foo/src")
+        );
+        assert_eq!(normalize(Path::new("../foo")), Path::new("../foo"));
+        assert_eq!(normalize(Path::new("../../foo")), Path::new("../../foo"));
+        assert_eq!(normalize(Path::new("./../../foo")), Path::new("../../foo"));
+        assert_eq!(normalize(Path::new("a/../../b")), Path::new("../b"));
+        assert_eq!(normalize(Path::new("../a/../b")), Path::new("../b"));
+        assert_eq!(normalize(Path::new("/../a")), Path::new("/a"));
+    }
+
+    #[test]
+    fn path_remap() {
+        let remap = PathRemap::parse("/home/user/a=b/proj=/build").unwrap();
+        assert_eq!(remap.from, Path::new("/home/user/a=b/proj"));
+        assert_eq!(remap.to, Path::new("/build"));
+        assert_eq!(
+            remap.unmap(Path::new("/build/src/lib.rs")).as_deref(),
+            Some(Path::new("/home/user/a=b/proj/src/lib.rs"))
+        );
+        assert_eq!(remap.unmap(Path::new("/builder/src/lib.rs")), None);
+        assert!(PathRemap::parse("/no/separator").is_none());
+    }
+}
@@ -0,0 +1,163 @@
+//! `cargo acl init`, which writes an initial cackle.toml without building anything. By default this
+//! is the recommended config, however an organisation can instead start new repositories from a
+//! shared template, which might contain its own API definitions, sandbox settings and first-party
+//! globs.
+
+use crate::config::MAX_VERSION;
+use crate::config_editor::ConfigEditor;
+use crate::config_editor::CreateRecommendedConfig;
+use crate::config_editor::Edit;
+use crate::config_file::ConfigFile;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use std::path::Path;
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct InitOptions {
+    /// Start from a template rather than the recommended config. May be a path or an https URL.
+    /// The template is copied as-is, except that the config version and sandbox kind are filled in
+    /// if it doesn't set them.
+    #[clap(long, value_name = "PATH_OR_URL")]
+    from_template: Option<String>,
+
+    /// Replace cackle.toml if it already exists.
+    #[clap(long)]
+    force: bool,
+}
+
+pub(crate) fn init(config_file: &ConfigFile, options: &InitOptions) -> Result<()> {
+    if config_file.exists() && !options.force {
+        bail!(
+            "`{}` already exists. Pass --force to replace it",
+            config_file.path().display()
+        );
+    }
+    let toml = match &options.from_template {
+        Some(template) => from_template(&read_template(template)?)
+            .with_context(|| format!("Invalid template `{template}`"))?,
+        None => {
+            let mut editor = ConfigEditor::initial();
+            CreateRecommendedConfig.apply(&mut editor, &Default::default())?;
+            editor.set_sandbox_kind(crate::sandbox::available_kind())?;
+            editor.to_toml()
+        }
+    };
+    config_file.write(&toml)
+}
+
+/// Returns the contents of the template at `template`, which may be either a path or a URL. Since
+/// the template determines what's permitted, we only fetch it over https, including when following
+/// redirects.
+fn read_template(template: &str) -> Result<String> {
+    if template.starts_with("http://") {
+        bail!("Template `{template}` must be fetched over https");
+    }
+    if !template.starts_with("https://") {
+        return crate::fs::read_to_string(Path::new(template));
+    }
+    let output = std::process::Command::new("curl")
+        .args([
+            "--proto",
+            "=https",
+            "--proto-redir",
+            "=https",
+            "--fail",
+            "--silent",
+            "--show-error",
+            "--location",
+            template,
+        ])
+        .output()
+        .context("Failed to run curl to fetch template")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to fetch template `{template}`: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout).with_context(|| format!("Template `{template}` isn't UTF-8"))
+}
+
+/// Returns a config based on `template`, with anything that's required, but that the template
+/// doesn't set, filled in.
+fn from_template(template: &str) -> Result<String> {
+    let table: toml::Table = toml::from_str(template)?;
+    let mut editor = ConfigEditor::from_toml_string(template)?;
+    let common = table.get("common");
+    if common.and_then(|common| common.get("version")).is_none() {
+        editor.set_version(MAX_VERSION)?;
+    }
+    let sandbox = table.get("sandbox");
+    if sandbox.and_then(|sandbox| sandbox.get("kind")).is_none() {
+        editor.set_sandbox_kind(crate::sandbox::available_kind())?;
+    }
+    let toml = editor.to_toml();
+    crate::config::parse_raw(&toml, None)?;
+    Ok(toml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn init_from_template() {
+        let dir = tempfile::tempdir().unwrap();
+        let template_path = dir.path().join("template.toml");
+        std::fs::write(
+            &template_path,
+            indoc! {r#"
+                # Our organisation's baseline.
+                [common]
+                import_std = ["fs", "net"]
+
+                [sandbox]
+                kind = "Disabled"
+
+                [api.telemetry]
+                include = ["our_telemetry"]
+            "#},
+        )
+        .unwrap();
+        let config_file = ConfigFile::new(dir.path().join("cackle.toml"), false, None);
+        let options = InitOptions {
+            from_template: Some(template_path.to_str().unwrap().to_owned()),
+            force: false,
+        };
+        init(&config_file, &options).unwrap();
+        let written = config_file.read().unwrap();
+        assert!(written.starts_with("# Our organisation's baseline."));
+        assert!(written.contains(&format!("version = {MAX_VERSION}")));
+        assert!(written.contains("[api.telemetry]"));
+
+        // We don't replace an existing config unless asked to.
+        assert!(init(&config_file, &options).is_err());
+        init(
+            &config_file,
+            &InitOptions {
+                force: true,
+                ..options
+            },
+        )
+        .unwrap();
+
+        std::fs::write(&template_path, "[common]\nversion = 2\nunknown = 1\n").unwrap();
+        assert!(init(
+            &config_file,
+            &InitOptions {
+                from_template: Some(template_path.to_str().unwrap().to_owned()),
+                force: true,
+            },
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn http_template_rejected() {
+        let error = read_template("http://example.com/cackle-template.toml").unwrap_err();
+        assert!(error.to_string().contains("https"));
+    }
+}
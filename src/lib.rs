@@ -0,0 +1,1838 @@
+//! Analyses rust crates and their dependent crates to see what categories of APIs and language
+//! features are used.
+//!
+//! This is mostly used via the `cargo-acl` binary, but the analysis can also be run from other
+//! tools by calling `analyze`.
+
+#![forbid(unsafe_code)]
+#![cfg_attr(not(feature = "ui"), allow(dead_code, unused_variables))]
+#![allow(unknown_lints)]
+#![allow(clippy::assigning_clones)]
+#![allow(clippy::needless_borrows_for_generic_args)]
+
+mod allowed;
+mod analysis;
+mod apply_fix;
+mod attestation;
+mod audit;
+mod bloat;
+mod build_script_checker;
+mod capabilities;
+mod cargo_deny;
+mod checker;
+mod clean;
+mod colour;
+mod config;
+mod config_editor;
+mod config_file;
+mod config_validation;
+mod cowarc;
+mod crate_index;
+mod daemon;
+mod demangle;
+mod deps;
+mod diagnostic;
+pub(crate) mod events;
+mod existing_build;
+pub(crate) mod fs;
+mod init;
+pub(crate) mod link_info;
+pub(crate) mod location;
+mod logging;
+mod metrics;
+mod muted_problems;
+mod names;
+mod outcome;
+mod plugin;
+mod preflight;
+pub(crate) mod problem;
+pub(crate) mod problem_store;
+mod provenance;
+mod proxy;
+mod prune;
+mod remote_scan;
+mod report;
+mod sandbox;
+mod scan_cache;
+mod semaphore;
+mod sha256;
+mod status;
+mod summary;
+pub(crate) mod symbol;
+mod symbol_graph;
+mod test_output;
+pub mod test_support;
+mod timing;
+mod tmpdir;
+mod tree;
+mod ui;
+mod unsafe_checker;
+
+pub use analysis::analyze;
+pub use analysis::handle_proxy_invocation;
+pub use analysis::AnalysisReport;
+pub use analysis::ReportedProblem;
+pub use link_info::Artifact;
+pub use link_info::ArtifactKind;
+pub use plugin::register_fix_provider;
+pub use plugin::register_plugin;
+pub use plugin::CheckPlugin;
+pub use plugin::FixProvider;
+pub use plugin::PluginApiUsage;
+pub use plugin::PluginApiUsages;
+pub use plugin::PluginFix;
+pub use plugin::PluginInputs;
+pub use plugin::PluginReport;
+pub use plugin::ProblemInfo;
+pub use problem::Severity;
+
+use crate::proxy::subprocess::PROXY_BIN_ARG;
+use allowed::AllowedOptions;
+use analysis::ReportSink;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use apply_fix::ApplyFixOptions;
+use attestation::VerifyAttestationOptions;
+use audit::AuditLogOptions;
+use bloat::BloatOptions;
+use checker::Checker;
+use clap::Parser;
+use clap::Subcommand;
+use clean::CleanOptions;
+use config::approvals::ApprovalsOptions;
+use config::permissions::PermSel;
+use config::permissions::Permissions;
+use config::Config;
+use config::PackageName;
+use config_file::ConfigFile;
+use config_file::ConflictPolicy;
+use crate_index::CrateIndex;
+use crate_index::PackageId;
+use daemon::CheckOptions;
+use daemon::DaemonOptions;
+use daemon::WarmState;
+use events::AppEvent;
+use fxhash::FxHashSet;
+use init::InitOptions;
+use log::info;
+use log::warn;
+use metrics::TrendsOptions;
+use outcome::ExitCode;
+use outcome::Outcome;
+use preflight::PreflightOptions;
+use problem::ApiChecklist;
+use problem::Problem;
+use problem::ProblemList;
+use problem_store::ProblemStoreRef;
+use problem_store::ResolveOptions;
+use provenance::WhyOptions;
+use proxy::cargo::profile_name;
+use proxy::cargo::CargoOptions;
+use proxy::cargo::NetworkMode;
+use proxy::rpc::Request;
+use proxy::CargoOutputWaiter;
+use prune::PruneOptions;
+use remote_scan::ScanWorkerOptions;
+use sandbox::plan::SandboxPlanOptions;
+use semaphore::Semaphore;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use summary::SummaryOptions;
+use symbol_graph::lookup::LookupSymbolOptions;
+use symbol_graph::ScanOutputs;
+use tmpdir::TempDir;
+use tree::TreeOptions;
+
+#[derive(Parser, Debug, Clone)]
+#[clap()]
+struct OuterArgs {
+    #[command(subcommand)]
+    command: OuterCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum OuterCommand {
+    Acl(Args),
+}
+
+#[derive(Parser, Debug, Clone, Default)]
+#[clap(version, about)]
+struct Args {
+    /// Directory containing crate to analyze. Defaults to current working directory. May be given
+    /// multiple times to analyze several independent workspaces together with a shared config.
+    #[clap(long)]
+    path: Vec<PathBuf>,
+
+    /// Path to the Cargo.toml of the package or workspace to analyze. If this is a member of a
+    /// workspace, the whole workspace is analyzed.
+    #[clap(long, conflicts_with = "path")]
+    manifest_path: Option<PathBuf>,
+
+    /// Path to cackle.toml. Defaults to cackle.toml in the directory containing Cargo.toml, or in
+    /// the current directory if multiple workspaces are being analyzed.
+    #[clap(short, long)]
+    cackle_path: Option<PathBuf>,
+
+    /// Apply a config layer on top of cackle.toml. e.g. `--config-layer ci` applies
+    /// `cackle.ci.toml`. May be given multiple times, with later layers taking precedence.
+    /// `cackle.local.toml` is always applied last if it exists.
+    #[clap(long)]
+    config_layer: Vec<String>,
+
+    /// Print the mapping from paths to crate names. Useful for debugging.
+    #[clap(long, hide = true)]
+    print_path_to_crate_map: bool,
+
+    /// Explain how the config for a permission selector such as `foo` or `foo.build` is built up
+    /// by inheritance, then exit.
+    #[clap(long, value_name = "PERM_SEL")]
+    explain_inheritance: Option<String>,
+
+    /// Promotes warnings (e.g. due to unused permissions) to errors.
+    #[clap(long)]
+    fail_on_warnings: bool,
+
+    /// Report problems separately for each version of a package, rather than grouping problems
+    /// that only differ by package version.
+    #[clap(long)]
+    separate_versions: bool,
+
+    /// Ignore newer config versions.
+    #[clap(long)]
+    ignore_newer_config_versions: bool,
+
+    /// Whether to use coloured output.
+    #[clap(long, alias = "color", default_value = "auto")]
+    colour: colour::Colour,
+
+    /// Don't print anything on success.
+    #[clap(long)]
+    quiet: bool,
+
+    /// Pass --offline to cargo. Dependencies must already have been fetched, e.g. with `cargo
+    /// fetch`.
+    #[clap(long)]
+    offline: bool,
+
+    /// Pass --frozen to cargo. Like --offline, but additionally requires Cargo.lock to be
+    /// up-to-date.
+    #[clap(long)]
+    frozen: bool,
+
+    /// Override the target used when compiling. e.g. "x86_64-unknown-linux-gnu".
+    #[clap(long)]
+    target: Option<String>,
+
+    /// Directory for build artifacts. Passed through to cargo. Defaults to the directory that cargo
+    /// would use, which takes into account CARGO_TARGET_DIR and `build.target-dir` in cargo config.
+    #[clap(long)]
+    target_dir: Option<PathBuf>,
+
+    /// Override build profile.
+    #[clap(long)]
+    profile: Option<String>,
+
+    /// Features to pass to cargo. These are in addition to common.features in config, unless
+    /// --no-default-features or --exact-features is given.
+    #[clap(long)]
+    features: Option<String>,
+
+    /// Pass --no-default-features to cargo. Features from common.features in config are also
+    /// disabled, so only those given by --features are enabled.
+    #[clap(long)]
+    no_default_features: bool,
+
+    /// Enable only the features given by --features, ignoring common.features in config.
+    #[clap(long)]
+    exact_features: bool,
+
+    /// Number of parallel jobs for cargo to run. Passed through to cargo. Defaults to what cargo
+    /// would use, which takes into account CARGO_BUILD_JOBS and `build.jobs` in cargo config.
+    #[clap(short, long, value_name = "N", allow_hyphen_values = true)]
+    jobs: Option<String>,
+
+    /// The maximum number of binaries to scan at once. Scanning is what uses most of our memory, so
+    /// this is separate from --jobs, letting machines with little RAM keep cargo parallel. Defaults
+    /// to 1, or for `scan-worker`, to the number of CPUs. Backtraces aren't available when this is
+    /// greater than 1.
+    #[clap(long, value_name = "N")]
+    analysis_threads: Option<NonZeroUsize>,
+
+    /// Print how long various things take to run.
+    #[clap(long)]
+    print_timing: bool,
+
+    /// Print additional information that's probably only useful for debugging.
+    #[clap(long)]
+    debug: bool,
+
+    /// Output file for logs that might be useful for diagnosing problems.
+    #[clap(long)]
+    log_file: Option<PathBuf>,
+
+    /// How detailed the logs should be.
+    #[clap(long, default_value = "info")]
+    log_level: logging::LevelFilter,
+
+    /// When specified, writes all requests into a subdirectory of the target directory. For
+    /// debugging use.
+    #[clap(long, hide = true)]
+    save_requests: bool,
+
+    /// Instead of running `cargo build`, replay requests saved by a previous run where
+    /// --write-requests was specified. For debugging use.
+    #[clap(long, hide = true)]
+    replay_requests: bool,
+
+    /// Temporary directory for Cackle to use. This is intended for testing purposes.
+    #[clap(long, hide = true)]
+    tmpdir: Option<PathBuf>,
+
+    /// What kind of user interface to use.
+    #[clap(long)]
+    ui: Option<ui::Kind>,
+
+    /// The colour theme for the full terminal UI. Overrides `theme` in the `[ui]` section of the
+    /// config.
+    #[clap(long)]
+    theme: Option<config::UiTheme>,
+
+    /// Disable interactive UI.
+    #[clap(long, short)]
+    no_ui: bool,
+
+    /// Print the available fixes for each problem, together with the changes that they'd make to
+    /// cackle.toml, then exit without modifying anything. Implies `--ui basic`.
+    #[cfg(feature = "ui")]
+    #[clap(long, conflicts_with_all = ["ui", "no_ui"])]
+    dry_run: bool,
+
+    /// Don't modify cackle.toml. Edits are instead made to an in-memory copy and a diff of all the
+    /// changes is printed on exit. Unlike `--dry-run`, this can be used with the interactive UIs.
+    #[clap(long)]
+    no_write_config: bool,
+
+    /// Where to log the edits that are made to the config, and where `audit-log` reads them from.
+    /// Defaults to `cackle/audit.log` in the target directory.
+    #[clap(long, value_name = "PATH")]
+    audit_log: Option<PathBuf>,
+
+    /// What to do if the config is changed by something else, e.g. by hand, while cackle is writing
+    /// edits to it. `prompt` asks when there's a user interface and otherwise abandons the edits.
+    #[clap(long, value_enum, default_value_t)]
+    on_config_conflict: ConflictPolicy,
+
+    /// With `--no-write-config`, write the diff to this file rather than printing it.
+    #[clap(long, requires = "no_write_config")]
+    patch_file: Option<PathBuf>,
+
+    /// Write a report of the analysis to this file when done. The report includes the problems
+    /// found, the usages of each API, the config and timings. Commands such as `resolve` and `why`
+    /// can then be pointed at it with `--report`.
+    #[clap(long, value_name = "PATH")]
+    save_report: Option<PathBuf>,
+
+    /// After a successful check, write an attestation to this file recording how the build was done
+    /// and the build ID of each binary and shared object that was analysed. Binaries can then be
+    /// checked against it with `verify-attestation`.
+    #[clap(long, value_name = "PATH")]
+    attest: Option<PathBuf>,
+
+    /// Sign the attestation with this SSH private key, writing the signature alongside it with a
+    /// `.sig` extension. Requires `ssh-keygen`.
+    #[clap(long, value_name = "PATH", requires = "attest")]
+    attest_signing_key: Option<PathBuf>,
+
+    /// After a successful check, write an in-toto statement to this directory for each binary and
+    /// shared object that was analysed. Each identifies the binary by its SHA-256 digest and
+    /// records the config's digest, the cackle version and the permissions granted to each package.
+    #[clap(long, value_name = "DIR")]
+    attestation_out: Option<PathBuf>,
+
+    /// How to print problems when there's no interactive UI.
+    #[clap(long, default_value = "rustc")]
+    format: diagnostic::Format,
+
+    /// Disable backtraces (may reduce peak memory consumption).
+    #[clap(long)]
+    no_backtrace: bool,
+
+    /// Address (host:port) of a worker started with `cargo acl scan-worker` to send binaries to for
+    /// scanning. May be given multiple times, in which case workers are used in turn. Workers'
+    /// token is read from `CACKLE_SCAN_WORKER_TOKEN`. Backtraces aren't available for binaries
+    /// scanned remotely.
+    #[clap(long)]
+    scan_worker: Vec<String>,
+
+    // We may at some point allow this to be a short flag, but should probably wait a few releases.
+    // -p was previously accepted for --path.
+    /// Packages to build and analyse.
+    #[clap(long)]
+    package: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Check everything. This is the default if no subcommand is given.
+    Check(CheckOptions),
+
+    /// Write an initial cackle.toml, either the recommended config or one based on a template.
+    /// Doesn't build anything.
+    Init(InitOptions),
+
+    /// Stay running, keeping state warm between checks requested with `check --use-daemon`.
+    Daemon(DaemonOptions),
+
+    /// Scan binaries on behalf of other machines that pass `--scan-worker`.
+    ScanWorker(ScanWorkerOptions),
+
+    /// Print summary of permissions used.
+    Summary(SummaryOptions),
+
+    /// Print the sandbox that would be used for each build script and test, after inheritance.
+    SandboxPlan(SandboxPlanOptions),
+
+    /// Show where a symbol in the binaries from the last build is defined, which package that's
+    /// attributed to and which APIs it matches. Doesn't build anything.
+    LookupSymbol(LookupSymbolOptions),
+
+    /// Print the dependency tree, annotating each package with the APIs that it's allowed to use
+    /// and those that the last full check observed it using. Doesn't build anything.
+    Tree(TreeOptions),
+
+    /// Run `cargo test`, analysing whatever gets built.
+    Test(CargoOptions),
+
+    /// Run `cargo run`, analysing whatever gets built.
+    Run(CargoOptions),
+
+    /// Show how the number of usages of each API by each package changed over recent runs.
+    Trends(TrendsOptions),
+
+    /// Explain why a config entry such as `pkg.tokio.allow_apis.net` is needed, using the usages
+    /// found by the last full check.
+    Why(WhyOptions),
+
+    /// Report whether the config allows a package to use an API, without building anything. Exits
+    /// with 0 if it's allowed and 1 if it isn't.
+    Allowed(AllowedOptions),
+
+    /// List who approved each grant of a permission. With `--check`, fails if any grant lacks a
+    /// valid approval.
+    Approvals(ApprovalsOptions),
+
+    /// Show which packages adding a dependency would bring in and the config changes that they're
+    /// likely to need. Doesn't build anything.
+    Preflight(PreflightOptions),
+
+    /// Remove (or with `--check`, report) config entries for packages that are no longer
+    /// dependencies. Doesn't build anything.
+    Prune(PruneOptions),
+
+    /// Reopen the interactive session for resolving problems that were left unresolved by the
+    /// previous run, without rebuilding anything.
+    Resolve(ResolveOptions),
+
+    /// Apply fixes, identified by the IDs listed in reports written by `--save-report`, to the
+    /// problems left unresolved by the previous run. Doesn't build anything.
+    ApplyFix(ApplyFixOptions),
+
+    /// Print the log of edits that were made to the config through cackle, including when each was
+    /// made, by whom and which problem it fixed.
+    AuditLog(AuditLogOptions),
+
+    /// Check everything, then write `cackle/capabilities.toml` declaring the permissions needed by
+    /// the current package.
+    Manifest,
+
+    /// Check everything, then show how much of each binary's size comes from each package.
+    Bloat(BloatOptions),
+
+    /// Check that binaries match those recorded in an attestation written by `--attest`, and
+    /// optionally that the attestation was signed by a trusted key. Exits with 0 if all match.
+    VerifyAttestation(VerifyAttestationOptions),
+
+    /// Remove the files that we leave in the target directory, such as saved problems and metrics,
+    /// and restore binaries that we wrapped. Doesn't remove cargo's build outputs unless `--cargo`
+    /// is passed.
+    Clean(CleanOptions),
+
+    #[clap(hide = true, name = PROXY_BIN_ARG)]
+    ProxyBin(ProxyBinOptions),
+}
+
+impl Args {
+    /// Returns whether we're analysing everything in the workspace, as opposed to running a cargo
+    /// command like `cargo test` where only some things get built.
+    fn is_full_analysis(&self) -> bool {
+        matches!(
+            self.command,
+            None | Some(Command::Check(..)) | Some(Command::Manifest) | Some(Command::Bloat(..))
+        )
+    }
+
+    /// Returns whether we're analysing the outputs of a previous build instead of building.
+    fn no_build(&self) -> bool {
+        matches!(&self.command, Some(Command::Check(options)) if options.no_build)
+    }
+
+    /// Returns an error if we've been asked to do something that isn't supported when analysing
+    /// multiple workspaces.
+    fn check_multi_workspace_support(&self) -> Result<()> {
+        if self.no_build() {
+            bail!("`check --no-build` isn't supported with multiple workspaces");
+        }
+        if !self.package.is_empty() {
+            bail!("`--package` isn't supported with multiple workspaces");
+        }
+        if matches!(self.command, Some(Command::Manifest)) {
+            bail!("`manifest` isn't supported with multiple workspaces");
+        }
+        Ok(())
+    }
+
+    /// Returns the package that we've been asked to recheck by itself, if any.
+    fn recheck_package(&self) -> Option<&str> {
+        match &self.command {
+            Some(Command::Check(options)) => options.package.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct ProxyBinOptions {
+    #[clap(allow_hyphen_values = true)]
+    remaining: Vec<String>,
+}
+
+/// Runs `cargo acl` with the arguments that the current process was invoked with. This is the
+/// entry point of the `cargo-acl` binary.
+#[doc(hidden)]
+pub fn run_cli() -> Result<()> {
+    proxy::subprocess::handle_wrapped_binaries()?;
+
+    if std::env::args_os()
+        .nth(1)
+        .is_some_and(|arg| arg == PROXY_BIN_ARG)
+    {
+        // If we get here and the call to handle_wrapped_binaries above didn't diverge, then either
+        // a user invoked a bin wrapper directly, or we've been invoked when we're already inside a
+        // cackle sandbox. In either case, we just run the original binary directly.
+        return invoke_wrapped_binary();
+    }
+
+    let outer = OuterArgs::parse();
+    let OuterCommand::Acl(mut args) = outer.command;
+    args.colour = args.colour.detect();
+    // We run cargo from the root of the workspace, so relative paths need to be made absolute.
+    let current_dir = std::env::current_dir()?;
+    for path in args
+        .target_dir
+        .iter_mut()
+        .chain(args.manifest_path.iter_mut())
+        .chain(args.path.iter_mut())
+    {
+        if path.is_relative() {
+            *path = current_dir.join(&*path);
+        }
+    }
+    if let Some(log_file) = &args.log_file {
+        logging::init(log_file, args.log_level)?;
+    }
+    match &args.command {
+        Some(Command::Daemon(options)) => return daemon::run(&args, options),
+        Some(Command::ScanWorker(options)) => {
+            return remote_scan::run_worker(options, args.analysis_threads)
+        }
+        Some(Command::Check(options)) if options.use_daemon => {
+            let exit_code = daemon::check(&args, options)?;
+            std::process::exit(exit_code.code());
+        }
+        _ => {}
+    }
+    let (abort_send, abort_recv) = std::sync::mpsc::channel();
+    let cackle = Cackle::new(args, abort_send, None, None)?;
+    let exit_code = cackle.run_and_report_errors(abort_recv);
+    info!("Shutdown with exit code {}", exit_code);
+    std::process::exit(exit_code.code());
+}
+
+struct Cackle {
+    problem_store: ProblemStoreRef,
+    /// The root of the first workspace that we're analysing.
+    root_path: PathBuf,
+    /// The roots of all the workspaces that we're analysing.
+    workspace_roots: Vec<PathBuf>,
+    config_file: ConfigFile,
+    checker: Arc<Mutex<Checker>>,
+    tmpdir: Arc<TempDir>,
+    target_dir: PathBuf,
+    args: Arc<Args>,
+    event_sender: Sender<AppEvent>,
+    ui_join_handle: JoinHandle<Result<()>>,
+    cargo_output_waiters: Vec<CargoOutputWaiter>,
+    crate_index: Arc<CrateIndex>,
+    abort_sender: Sender<()>,
+    /// Limits how many binaries we scan at once when `--analysis-threads` is greater than 1.
+    scan_slots: Arc<Semaphore>,
+}
+
+impl Cackle {
+    /// Creates a new instance. `report`, if supplied, is where problems are collected when we're
+    /// run as a library. `warm_state` is supplied when we're run from the daemon.
+    fn new(
+        args: Args,
+        abort_sender: Sender<()>,
+        report: Option<ReportSink>,
+        warm_state: Option<WarmState>,
+    ) -> Result<Self> {
+        let mut args = args;
+        let workspace_roots = canonical_root_paths(&args)?;
+        let root_path = workspace_roots[0].clone();
+        if workspace_roots.len() > 1 {
+            args.check_multi_workspace_support()?;
+            // All workspaces share a target directory, so that our records of what we've built,
+            // scanned and found all live in one place.
+            if args.target_dir.is_none() {
+                args.target_dir = Some(target_dir(&root_path, &args)?);
+            }
+        }
+        let args = Arc::new(args);
+
+        let config_path = match args.cackle_path.clone() {
+            Some(path) => path,
+            None if workspace_roots.len() > 1 => std::env::current_dir()?.join("cackle.toml"),
+            None => root_path.join("cackle.toml"),
+        };
+
+        let (crate_index, sysroot, scan_cache) = match warm_state {
+            Some(warm_state) => (
+                warm_state.crate_index,
+                warm_state.sysroot,
+                Some(warm_state.scan_cache),
+            ),
+            None => (
+                Arc::new(CrateIndex::new(
+                    &manifest_paths(&args)?,
+                    NetworkMode::from_args(&args),
+                )?),
+                determine_sysroot(&root_path, args.target.as_deref())?,
+                None,
+            ),
+        };
+        let target_dir = target_dir(&root_path, &args)?;
+        let config_file = ConfigFile::new(
+            config_path,
+            args.no_write_config,
+            Some(config_file::changes_patch_path(&target_dir)),
+        )
+        .with_audit_log(audit_log_path(&target_dir, &args))
+        .with_conflict_policy(args.on_config_conflict);
+        let tmpdir = Arc::new(TempDir::new(args.tmpdir.as_deref())?);
+        let mut checker = Checker::new(
+            tmpdir.clone(),
+            target_dir.clone(),
+            args.clone(),
+            sysroot,
+            crate_index.clone(),
+            config_file.clone(),
+        );
+        checker.scan_cache = scan_cache;
+        checker.collect_size_report = matches!(args.command, Some(Command::Bloat(..)));
+        let checker = Arc::new(Mutex::new(checker));
+        let (event_sender, event_receiver) = std::sync::mpsc::channel();
+        let problem_store = crate::problem_store::create(event_sender.clone());
+        problem_store.lock().group_versions = !args.separate_versions;
+        let ui_join_handle = ui::start_ui(
+            &args,
+            &config_file,
+            &checker,
+            problem_store.clone(),
+            event_receiver,
+            abort_sender.clone(),
+            report,
+        )?;
+        let scan_slots = Arc::new(Semaphore::new(
+            args.analysis_threads.map_or(1, NonZeroUsize::get),
+        ));
+        Ok(Self {
+            problem_store,
+            root_path,
+            workspace_roots,
+            config_file,
+            checker,
+            args,
+            event_sender,
+            ui_join_handle,
+            crate_index,
+            tmpdir,
+            target_dir,
+            abort_sender,
+            cargo_output_waiters: Vec::new(),
+            scan_slots,
+        })
+    }
+
+    /// Runs, reports any error and returns the exit code. Takes self by value so that it's dropped
+    /// before we return. That way the user interface will be cleaned up before we exit.
+    fn run_and_report_errors(mut self, abort_recv: Receiver<()>) -> ExitCode {
+        if let Some(perm_sel) = &self.args.explain_inheritance {
+            return self.explain_inheritance(perm_sel);
+        }
+        if let Some(Command::Summary(options)) = &self.args.command {
+            return self.print_summary(options);
+        }
+        if let Some(Command::SandboxPlan(options)) = &self.args.command {
+            return self.print_sandbox_plan(options);
+        }
+        if let Some(Command::Tree(options)) = &self.args.command {
+            return self.print_tree(options);
+        }
+        if let Some(Command::LookupSymbol(options)) = &self.args.command {
+            return self.lookup_symbol(options);
+        }
+        if let Some(Command::Trends(options)) = &self.args.command {
+            if let Err(error) = metrics::print_trends(&self.target_dir, options) {
+                println!("{error:#}");
+                return outcome::FAILURE;
+            }
+            return outcome::SUCCESS;
+        }
+        if let Some(Command::Why(options)) = &self.args.command {
+            return self.print_why(options);
+        }
+        if let Some(Command::Allowed(options)) = &self.args.command {
+            return self.check_allowed(options);
+        }
+        if let Some(Command::Approvals(options)) = &self.args.command {
+            return self.approvals(options);
+        }
+        if let Some(Command::Preflight(options)) = &self.args.command {
+            return self.preflight(options);
+        }
+        if let Some(Command::Prune(options)) = &self.args.command {
+            return self.prune(options);
+        }
+        if let Some(Command::Clean(options)) = &self.args.command {
+            return self.clean(options);
+        }
+        if let Some(Command::Init(options)) = &self.args.command {
+            return self.init(options);
+        }
+        if let Some(Command::ApplyFix(options)) = &self.args.command {
+            return self.apply_fix(options);
+        }
+        if let Some(Command::AuditLog(options)) = &self.args.command {
+            return match audit::print(&audit_log_path(&self.target_dir, &self.args), options) {
+                Ok(()) => outcome::SUCCESS,
+                Err(error) => {
+                    println!("{error:#}");
+                    outcome::FAILURE
+                }
+            };
+        }
+        if let Some(Command::VerifyAttestation(options)) = &self.args.command {
+            return match attestation::verify(options) {
+                Ok(true) => outcome::SUCCESS,
+                Ok(false) => outcome::FAILURE,
+                Err(error) => {
+                    println!("{error:#}");
+                    outcome::FAILURE
+                }
+            };
+        }
+        // Save unresolved problems so that if the user interface goes away, the user can resume
+        // resolving them with `cargo acl resolve`. We only do this here, not when running as a
+        // library or from the daemon, since in those cases there's no one to resume.
+        let saved_problems_path = problem_store::saved_problems_path(&self.target_dir);
+        if !matches!(self.args.command, Some(Command::Resolve(..))) {
+            // Anything saved by a previous run is now out of date.
+            let _ = std::fs::remove_file(&saved_problems_path);
+        }
+        self.problem_store.lock().save_to(saved_problems_path);
+        // Problems muted from the user interface stay muted across runs until they expire.
+        if let Err(error) = self
+            .problem_store
+            .lock()
+            .load_mutes(muted_problems::muted_problems_path(&self.target_dir))
+        {
+            warn!("Failed to load muted problems: {error:#}");
+        }
+        // Keep a status file up to date for the benefit of external tools that want to show our
+        // progress.
+        self.checker
+            .lock()
+            .unwrap()
+            .status
+            .write_to(status::status_path(&self.target_dir));
+        let mut error = None;
+        let exit_code = match self.run(abort_recv) {
+            Err(e) => {
+                error = Some(e);
+                outcome::FAILURE
+            }
+            Ok(exit_code) => exit_code,
+        };
+        let _ = self.event_sender.send(AppEvent::Shutdown);
+        if let Ok(Err(error)) = self.ui_join_handle.join() {
+            println!("UI error: {error}");
+            return outcome::FAILURE;
+        }
+        for mut output_waiter in std::mem::take(&mut self.cargo_output_waiters) {
+            output_waiter.wait_for_output();
+        }
+        // Now that the UI (if any) has shut down, print any errors.
+        if let Some(error) = error {
+            println!();
+            println!("Error: {error:#}");
+        }
+        if let Err(error) =
+            report_config_changes(&self.config_file, self.args.patch_file.as_deref())
+        {
+            println!("{error:#}");
+            return outcome::FAILURE;
+        }
+
+        let mut checker = self.checker.lock().unwrap();
+        checker.status.finish(exit_code == outcome::SUCCESS);
+        if let Some(report_path) = &self.args.save_report {
+            let problems: Vec<Problem> = self
+                .problem_store
+                .lock()
+                .deduplicated_into_iter()
+                .map(|(_, problem)| problem.clone())
+                .collect();
+            let config = self.config_file.read().unwrap_or_default();
+            let success = exit_code == outcome::SUCCESS;
+            if let Err(error) = report::save(report_path, &checker, &config, &problems, success) {
+                println!("Failed to save report: {error:#}");
+                return outcome::FAILURE;
+            }
+        }
+        if let Some(attestation_path) = &self.args.attest {
+            if exit_code == outcome::SUCCESS {
+                let cargo_args = self
+                    .workspace_roots
+                    .iter()
+                    .map(|workspace_root| {
+                        let cargo_runner = proxy::CargoRunner {
+                            manifest_dir: workspace_root,
+                            tmpdir: self.tmpdir.path(),
+                            target_dir: &self.target_dir,
+                            config: &checker.config,
+                            args: &self.args,
+                            crate_index: &self.crate_index,
+                        };
+                        cargo_runner
+                            .build_command()
+                            .get_args()
+                            .map(|arg| arg.to_string_lossy().into_owned())
+                            .collect()
+                    })
+                    .collect();
+                let config = self.config_file.read().unwrap_or_default();
+                if let Err(error) = attestation::write(
+                    attestation_path,
+                    &checker,
+                    &config,
+                    profile_name(&self.args, &checker.config.raw.common),
+                    cargo_args,
+                    self.args.attest_signing_key.as_deref(),
+                ) {
+                    println!("Failed to write attestation: {error:#}");
+                    return outcome::FAILURE;
+                }
+            }
+        }
+        if let Some(attestation_dir) = &self.args.attestation_out {
+            if exit_code == outcome::SUCCESS {
+                let summary = summary::Summary::new(
+                    &self.crate_index,
+                    &checker.config,
+                    &checker.generated_code,
+                    &self.args,
+                );
+                if let Err(error) = attestation::write_in_toto(
+                    attestation_dir,
+                    &checker,
+                    self.config_file.path(),
+                    &self.config_file.read().unwrap_or_default(),
+                    profile_name(&self.args, &checker.config.raw.common),
+                    &summary,
+                ) {
+                    println!("Failed to write in-toto attestations: {error:#}");
+                    return outcome::FAILURE;
+                }
+            }
+        }
+        if self.args.print_path_to_crate_map {
+            checker.print_path_to_crate_map();
+        }
+        if self.args.print_timing {
+            checker.print_timing();
+        }
+        if exit_code == outcome::SUCCESS {
+            if let Some(Command::Bloat(options)) = &self.args.command {
+                checker.size_report.print(options);
+            }
+        }
+        if exit_code == outcome::SUCCESS
+            && !self.args.quiet
+            && matches!(self.args.command, Some(Command::Resolve(..)))
+        {
+            println!("All saved problems resolved. Run a check to confirm that they're fixed.");
+        }
+        if exit_code == outcome::SUCCESS
+            && !self.args.quiet
+            && matches!(self.args.command, None | Some(Command::Check(..)))
+        {
+            println!(
+                "Completed successfully for configuration {}",
+                self.config_file.path().display()
+            );
+            let summary = summary::Summary::new(
+                &self.crate_index,
+                &checker.config,
+                &checker.generated_code,
+                &self.args,
+            );
+            println!("{summary}");
+        }
+        exit_code
+    }
+
+    /// Runs the analysis, returning the problems found rather than reporting them. Problems are
+    /// only collected if `report` was supplied when we were created.
+    fn analyze(mut self, abort_recv: Receiver<()>, report: &ReportSink) -> Result<AnalysisReport> {
+        let result = self.run(abort_recv);
+        let _ = self.event_sender.send(AppEvent::Shutdown);
+        self.ui_join_handle
+            .join()
+            .map_err(|_| anyhow!("UI thread panicked"))??;
+        for mut output_waiter in std::mem::take(&mut self.cargo_output_waiters) {
+            output_waiter.wait_for_output();
+        }
+        let exit_code = result?;
+        let checker = self.checker.lock().unwrap();
+        Ok(AnalysisReport {
+            success: exit_code == outcome::SUCCESS,
+            problems: std::mem::take(&mut report.lock().unwrap()),
+            permissions: summary::Summary::new(
+                &self.crate_index,
+                &checker.config,
+                &checker.generated_code,
+                &self.args,
+            )
+            .permissions_by_package(),
+        })
+    }
+
+    fn print_summary(&self, options: &SummaryOptions) -> ExitCode {
+        let mut checker = self.checker.lock().unwrap();
+        if let Err(error) = checker.load_config() {
+            println!("{error:#}");
+            return outcome::FAILURE;
+        }
+        let summary = summary::Summary::new(
+            &self.crate_index,
+            &checker.config,
+            &checker.generated_code,
+            &self.args,
+        );
+        summary.print(options);
+        outcome::SUCCESS
+    }
+
+    fn explain_inheritance(&self, perm_sel: &str) -> ExitCode {
+        let mut checker = self.checker.lock().unwrap();
+        if let Err(error) = checker.load_config() {
+            println!("{error:#}");
+            return outcome::FAILURE;
+        }
+        let perm_sel = PermSel::parse(perm_sel.strip_prefix("pkg.").unwrap_or(perm_sel));
+        print!(
+            "{}",
+            config::inheritance::explain(&checker.config, &perm_sel)
+        );
+        outcome::SUCCESS
+    }
+
+    fn print_sandbox_plan(&self, options: &SandboxPlanOptions) -> ExitCode {
+        let mut checker = self.checker.lock().unwrap();
+        if let Err(error) = checker.load_config() {
+            println!("{error:#}");
+            return outcome::FAILURE;
+        }
+        sandbox::plan::print(
+            &self.crate_index,
+            &checker.config,
+            &self.target_dir,
+            options,
+        );
+        outcome::SUCCESS
+    }
+
+    fn print_tree(&self, options: &TreeOptions) -> ExitCode {
+        let mut checker = self.checker.lock().unwrap();
+        let result = checker.load_config().and_then(|_| {
+            tree::print(
+                &self.crate_index,
+                &checker.config,
+                &self.target_dir,
+                options,
+            )
+        });
+        if let Err(error) = result {
+            println!("{error:#}");
+            return outcome::FAILURE;
+        }
+        outcome::SUCCESS
+    }
+
+    fn lookup_symbol(&self, options: &LookupSymbolOptions) -> ExitCode {
+        let mut checker = self.checker.lock().unwrap();
+        let result = checker.load_config().and_then(|_| {
+            let profile_dir = existing_build::profile_dir(
+                &self.target_dir,
+                self.args.target.as_deref(),
+                profile_name(&self.args, &checker.config.raw.common),
+            );
+            symbol_graph::lookup::print(&checker, &profile_dir, options)
+        });
+        if let Err(error) = result {
+            println!("{error:#}");
+            return outcome::FAILURE;
+        }
+        outcome::SUCCESS
+    }
+
+    fn print_why(&self, options: &WhyOptions) -> ExitCode {
+        let mut checker = self.checker.lock().unwrap();
+        let result = checker.load_config().and_then(|_| {
+            let report = options
+                .report
+                .as_deref()
+                .map(report::Report::load)
+                .transpose()?;
+            provenance::print_why(
+                &self.target_dir,
+                report.as_ref().map(report::Report::provenance),
+                &checker.config,
+                options,
+            )
+        });
+        if let Err(error) = result {
+            println!("{error:#}");
+            return outcome::FAILURE;
+        }
+        outcome::SUCCESS
+    }
+
+    fn check_allowed(&self, options: &AllowedOptions) -> ExitCode {
+        let mut checker = self.checker.lock().unwrap();
+        let result = checker
+            .load_config()
+            .and_then(|_| allowed::check(&checker.config, &self.crate_index, options));
+        match result {
+            Ok(true) => outcome::SUCCESS,
+            Ok(false) => ExitCode(1),
+            Err(error) => {
+                println!("{error:#}");
+                outcome::FAILURE
+            }
+        }
+    }
+
+    fn approvals(&self, options: &ApprovalsOptions) -> ExitCode {
+        let mut checker = self.checker.lock().unwrap();
+        match checker.load_config() {
+            Ok(()) if config::approvals::report(&checker.config, options) => outcome::SUCCESS,
+            Ok(()) => outcome::FAILURE,
+            Err(error) => {
+                println!("{error:#}");
+                outcome::FAILURE
+            }
+        }
+    }
+
+    fn preflight(&self, options: &PreflightOptions) -> ExitCode {
+        let mut checker = self.checker.lock().unwrap();
+        let result = checker.load_config().and_then(|_| {
+            preflight::print(
+                &checker.config,
+                &self.crate_index,
+                &self.target_dir,
+                self.args.target.as_deref(),
+                options,
+            )
+        });
+        if let Err(error) = result {
+            println!("{error:#}");
+            return outcome::FAILURE;
+        }
+        outcome::SUCCESS
+    }
+
+    fn prune(&self, options: &PruneOptions) -> ExitCode {
+        let mut checker = self.checker.lock().unwrap();
+        let result = checker.load_config().and_then(|_| {
+            prune::prune(
+                &self.config_file,
+                &checker.config,
+                &self.crate_index,
+                options,
+            )
+        });
+        let result = result.and_then(|clean| {
+            report_config_changes(&self.config_file, self.args.patch_file.as_deref())
+                .map(|()| clean)
+        });
+        match result {
+            Ok(true) => outcome::SUCCESS,
+            Ok(false) => outcome::FAILURE,
+            Err(error) => {
+                println!("{error:#}");
+                outcome::FAILURE
+            }
+        }
+    }
+
+    fn clean(&self, options: &CleanOptions) -> ExitCode {
+        let mut checker = self.checker.lock().unwrap();
+        let result = (|| {
+            // We only need the config for the profile name, so it's fine if there isn't one.
+            if self.config_file.exists() {
+                checker.load_config()?;
+            }
+            clean::clean(
+                &self.workspace_roots,
+                &self.target_dir,
+                &self.args,
+                &checker.config.raw.common,
+                options,
+            )
+        })();
+        match result {
+            Ok(()) => outcome::SUCCESS,
+            Err(error) => {
+                println!("{error:#}");
+                outcome::FAILURE
+            }
+        }
+    }
+
+    fn apply_fix(&self, options: &ApplyFixOptions) -> ExitCode {
+        let mut checker = self.checker.lock().unwrap();
+        let result = checker.load_config().and_then(|_| {
+            let problems = if let Some(report_path) = &options.report {
+                report::Report::load(report_path)?.problems()
+            } else {
+                let path = problem_store::saved_problems_path(&self.target_dir);
+                let Some(problems) = problem_store::load_saved(&path)? else {
+                    bail!("There are no unresolved problems saved from a previous run");
+                };
+                problems
+            };
+            apply_fix::apply(
+                &self.config_file,
+                &checker.config,
+                &problems.take(),
+                options,
+            )?;
+            report_config_changes(&self.config_file, self.args.patch_file.as_deref())
+        });
+        if let Err(error) = result {
+            println!("{error:#}");
+            return outcome::FAILURE;
+        }
+        outcome::SUCCESS
+    }
+
+    fn init(&self, options: &InitOptions) -> ExitCode {
+        match init::init(&self.config_file, options) {
+            Ok(()) => {
+                if !self.args.quiet {
+                    println!("Wrote {}", self.config_file.path().display());
+                }
+                outcome::SUCCESS
+            }
+            Err(error) => {
+                println!("{error:#}");
+                outcome::FAILURE
+            }
+        }
+    }
+
+    fn run(&mut self, abort_recv: Receiver<()>) -> Result<ExitCode> {
+        if matches!(self.args.command, Some(Command::Manifest)) {
+            // Check this before we do a potentially lengthy build.
+            self.manifest_pkg_id()?;
+        }
+        if self.maybe_create_config()? == Outcome::GiveUp {
+            info!("Gave up creating initial configuration");
+            return Ok(outcome::FAILURE);
+        }
+        {
+            let should_run_cargo_clean = self.should_run_cargo_clean();
+            let checker = &mut self.checker.lock().unwrap();
+            checker.load_config()?;
+
+            if should_run_cargo_clean {
+                proxy::clean(&self.root_path, &self.args, &checker.config.raw.common)?;
+            } else if let Some(pkg_name) = self.args.recheck_package() {
+                // Cleaning just the one package means that it, and anything that depends on it,
+                // gets rebuilt. Everything else is left as it was after the last check.
+                if self
+                    .crate_index
+                    .newest_package_id_with_name(&PackageName(Arc::from(pkg_name)))
+                    .is_none()
+                {
+                    bail!("Package `{pkg_name}` isn't in the dependency tree");
+                }
+                proxy::clean_package(
+                    self.workspace_containing(pkg_name),
+                    &self.args,
+                    &checker.config.raw.common,
+                    pkg_name,
+                )?;
+            }
+        }
+        if let Some(Command::Resolve(options)) = &self.args.command {
+            self.set_phase(status::Phase::Resolving);
+            return self.resolve_saved_problems(&options.clone());
+        }
+        if !self.args.ignore_newer_config_versions {
+            let update_problems = self.checker.lock().unwrap().check_for_new_config_version();
+            if !update_problems.is_empty() {
+                self.problem_store.fix_problems(update_problems);
+            }
+        }
+        let ignored_features = proxy::cargo::Features::new(
+            &self.args,
+            &self.checker.lock().unwrap().config.raw.common,
+        )
+        .ignored_from_config;
+        if !ignored_features.is_empty() {
+            self.problem_store
+                .fix_problems(Problem::IgnoredConfigFeatures(ignored_features).into());
+        }
+        let cargo_deny_problems = {
+            let checker = self.checker.lock().unwrap();
+            match &checker.config.raw.deny.cargo_deny {
+                Some(path) => cargo_deny::problems(path, &checker.crate_index)?,
+                None => ProblemList::default(),
+            }
+        };
+        if !cargo_deny_problems.is_empty() {
+            self.problem_store.fix_problems(cargo_deny_problems);
+        }
+
+        let mut initial_outcome = self.new_request_handler(None).handle_request()?;
+        let config = self.checker.lock().unwrap().config.clone();
+        let crate_index = self.checker.lock().unwrap().crate_index.clone();
+        let mut unused_imports = config.raw.unused_imports(&crate_index);
+        if self.args.is_interactive() {
+            // Asking about each API separately gets tedious, so let the user pick from a list.
+            unused_imports = ApiChecklist::group_available_apis(unused_imports);
+        }
+        initial_outcome = initial_outcome.and(self.problem_store.fix_problems(unused_imports));
+
+        {
+            let mut checker = self.checker.lock().unwrap();
+
+            // The following call to load_config is only really necessary if we fixed unused-import
+            // problems above. It might be worthwhile at some point refactoring so that we don't do an
+            // unnecessary reload here.
+            checker.load_config()?;
+        }
+
+        let args = self.args.clone();
+        self.set_phase(status::Phase::Building);
+        let build_result = if initial_outcome == Outcome::Continue {
+            if self.args.replay_requests {
+                self.replay_requests()
+            } else if self.args.no_build() {
+                self.check_existing_build()
+            } else {
+                self.build_workspaces(&abort_recv, &config, &args, &crate_index)
+            }
+        } else {
+            // We've already detected problems before running cargo, don't run cargo.
+            Ok(())
+        };
+
+        if self.problem_store.lock().has_aborted {
+            return Ok(outcome::FAILURE);
+        }
+
+        // We only check if the build failed if there were no ACL check errors.
+        build_result?;
+
+        // If we didn't run `cargo clean` when we started, then our records of what is an isn't used
+        // won't be complete, so we shouldn't emit unused warnings. When analysing an existing
+        // build, we see everything that it built.
+        if self.should_run_cargo_clean() || self.args.no_build() {
+            self.set_phase(status::Phase::CheckingUnused);
+            let unused_problems = self.checker.lock().unwrap().check_unused()?;
+            let resolution = self.problem_store.fix_problems(unused_problems);
+            if resolution != Outcome::Continue {
+                return Ok(outcome::FAILURE);
+            }
+            // Similarly, we only record metrics and provenance if we've seen everything.
+            let checker = self.checker.lock().unwrap();
+            metrics::record_run(&self.target_dir, &checker.usage_counter)?;
+            provenance::record_run(&self.target_dir, &checker.usage_counter, &checker.config)?;
+        }
+
+        if matches!(self.args.command, Some(Command::Manifest)) {
+            self.write_capability_manifest()?;
+        }
+
+        Ok(outcome::SUCCESS)
+    }
+
+    /// Builds each of our workspaces in turn, checking everything that gets built.
+    fn build_workspaces(
+        &mut self,
+        abort_recv: &Receiver<()>,
+        config: &Config,
+        args: &Args,
+        crate_index: &CrateIndex,
+    ) -> Result<()> {
+        for workspace_root in &self.workspace_roots {
+            if self.workspace_roots.len() > 1 {
+                info!("Building workspace `{}`", workspace_root.display());
+            }
+            let cargo_runner = proxy::CargoRunner {
+                manifest_dir: workspace_root,
+                tmpdir: self.tmpdir.path(),
+                target_dir: &self.target_dir,
+                config,
+                args,
+                crate_index,
+            };
+            let output_waiter = cargo_runner.invoke_cargo_build(
+                abort_recv,
+                self.abort_sender.clone(),
+                |request| {
+                    if self.args.save_requests {
+                        if let Err(error) = self.save_request(&request) {
+                            println!("Failed to save request: {error}");
+                        }
+                    }
+                    self.new_request_handler(Some(request))
+                },
+            )?;
+            self.cargo_output_waiters.push(output_waiter);
+            if self.problem_store.lock().has_aborted {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the root of a workspace whose dependency tree contains `pkg_name`.
+    fn workspace_containing(&self, pkg_name: &str) -> &Path {
+        self.crate_index
+            .newest_package_id_with_name(&PackageName(Arc::from(pkg_name)))
+            .and_then(|pkg_id| self.crate_index.package_info(pkg_id))
+            .and_then(|info| info.workspaces.first())
+            .map_or(&self.root_path, |root| root)
+    }
+
+    fn set_phase(&self, phase: status::Phase) {
+        self.checker.lock().unwrap().status.set_phase(phase);
+    }
+
+    /// Presents the problems saved by a previous run, or in a saved report, for resolution.
+    fn resolve_saved_problems(&mut self, options: &ResolveOptions) -> Result<ExitCode> {
+        let problems = if let Some(report_path) = &options.report {
+            let problems = report::Report::load(report_path)?.problems();
+            if problems.is_empty() {
+                bail!(
+                    "Report `{}` has no unresolved problems",
+                    report_path.display()
+                );
+            }
+            problems
+        } else {
+            let path = problem_store::saved_problems_path(&self.target_dir);
+            let Some(problems) = problem_store::load_saved(&path)? else {
+                bail!("There are no unresolved problems saved from a previous run");
+            };
+            problems
+        };
+        if self.problem_store.fix_problems(problems) != Outcome::Continue {
+            return Ok(outcome::FAILURE);
+        }
+        Ok(outcome::SUCCESS)
+    }
+
+    /// Returns the package for which we should write a capability manifest.
+    fn manifest_pkg_id(&self) -> Result<&PackageId> {
+        self.crate_index.root_pkg_id.as_ref().ok_or_else(|| {
+            anyhow!(
+                "`{}` is a virtual manifest. Run `cargo acl manifest` from a package directory",
+                self.crate_index.manifest_path.display()
+            )
+        })
+    }
+
+    fn write_capability_manifest(&self) -> Result<()> {
+        let pkg_id = self.manifest_pkg_id()?;
+        let checker = self.checker.lock().unwrap();
+        let path = capabilities::write_for_package(
+            pkg_id,
+            &self.crate_index,
+            &checker.usage_counter,
+            &checker.config,
+        )?;
+        if !self.args.quiet {
+            println!("Wrote capability manifest to {}", path.display());
+        }
+        Ok(())
+    }
+
+    fn should_run_cargo_clean(&mut self) -> bool {
+        !self.args.replay_requests
+            && !self.args.no_build()
+            && self.args.is_full_analysis()
+            && self.args.recheck_package().is_none()
+    }
+
+    fn new_request_handler(&self, request: Option<Request>) -> RequestHandler {
+        RequestHandler {
+            check_state: CheckState::default(),
+            checker: self.checker.clone(),
+            problem_store: self.problem_store.clone(),
+            request,
+            scan_slots: self.scan_slots.clone(),
+        }
+    }
+
+    fn maybe_create_config(&mut self) -> Result<Outcome> {
+        if !self.config_file.exists() {
+            return Ok(self.problem_store.fix_problems(
+                Problem::MissingConfiguration(self.config_file.path().to_owned()).into(),
+            ));
+        }
+        Ok(Outcome::Continue)
+    }
+
+    fn saved_request_path(&self) -> PathBuf {
+        saved_requests_dir(
+            &self.target_dir,
+            profile_name(&self.args, &self.checker.lock().unwrap().config.raw.common),
+        )
+    }
+
+    fn replay_requests(&self) -> Result<()> {
+        let rpcs_dir = &self.saved_request_path();
+        let mut rpc_paths: Vec<PathBuf> = rpcs_dir
+            .read_dir()
+            .with_context(|| format!("Failed to read saved RPCs dir `{}`", rpcs_dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        rpc_paths.sort();
+        for path in rpc_paths {
+            info!("Replaying RPC `{}`", path.display());
+            if self
+                .replay_request(&path)
+                .with_context(|| format!("Replay of request `{}` failed", path.display()))?
+                == Outcome::GiveUp
+            {
+                bail!("Request gave error");
+            }
+        }
+        Ok(())
+    }
+
+    fn replay_request(&self, path: &Path) -> Result<Outcome> {
+        let request_str = crate::fs::read_to_string(path)?;
+        let request: Request = serde_json::from_str(&request_str)?;
+        let mut handler = self.new_request_handler(Some(request));
+        handler.handle_request()
+    }
+
+    /// Handles requests reconstructed from the artifacts of a build that's already been done.
+    fn check_existing_build(&self) -> Result<()> {
+        let config = self.checker.lock().unwrap().config.clone();
+        let profile_dir = existing_build::profile_dir(
+            &self.target_dir,
+            self.args.target.as_deref(),
+            profile_name(&self.args, &config.raw.common),
+        );
+        let requests = existing_build::requests(
+            &profile_dir,
+            &self.root_path,
+            &self.crate_index,
+            &config.permissions,
+        )?;
+        for request in requests {
+            if self.new_request_handler(Some(request)).handle_request()? == Outcome::GiveUp {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn save_request(&self, request: &Request) -> Result<()> {
+        let rpcs_dir = self.saved_request_path();
+        std::fs::create_dir_all(&rpcs_dir)?;
+        let num_entries = rpcs_dir.read_dir()?.count();
+        // The file referenced by a `LinkerInvokedFile` request won't outlive this run, so we save
+        // its contents instead.
+        let serialized = match request {
+            Request::LinkerInvokedFile(path) => {
+                serde_json::to_string(&Request::LinkerInvoked(proxy::rpc::read_link_info(path)?))?
+            }
+            _ => serde_json::to_string(request)?,
+        };
+        crate::fs::write(
+            rpcs_dir.join(format!("{num_entries:03}.cackle-rpc")),
+            serialized,
+        )?;
+        Ok(())
+    }
+}
+
+/// Returns the directory into which requests are written when `--save-requests` is passed.
+fn saved_requests_dir(target_dir: &Path, profile: &str) -> PathBuf {
+    target_dir.join(profile).join("saved-cackle-rpcs")
+}
+
+/// Returns the root directory of the workspace that we're analysing. This is where we look for
+/// cackle.toml and is the directory from which we run cargo.
+fn canonical_root_path(args: &Args) -> Result<PathBuf> {
+    workspace_root(&manifest_path(args)?)
+}
+
+/// Reports where the changes that were made to the config were recorded. With `--no-write-config`,
+/// prints a diff of the changes that would have been made, or writes it to `patch_file` if
+/// supplied.
+fn report_config_changes(config_file: &ConfigFile, patch_file: Option<&Path>) -> Result<()> {
+    let config_path = config_file.path().display();
+    let Some(diff) = config_file.in_memory_diff() else {
+        if let Some(changes_patch) = config_file.changes_patch() {
+            println!(
+                "Changes made to {config_path} recorded in {}",
+                changes_patch.display()
+            );
+        }
+        return Ok(());
+    };
+    if let Some(patch_file) = patch_file {
+        crate::fs::write(patch_file, &diff)?;
+        println!(
+            "{config_path} wasn't modified. Changes written to {}",
+            patch_file.display()
+        );
+    } else if diff.is_empty() {
+        println!("No changes would have been made to {config_path}");
+    } else {
+        println!("{config_path} wasn't modified. Changes that would have been made:");
+        print!("{diff}");
+    }
+    Ok(())
+}
+
+/// Returns the root directories of each of the workspaces that we're analysing.
+fn canonical_root_paths(args: &Args) -> Result<Vec<PathBuf>> {
+    manifest_paths(args)?
+        .iter()
+        .map(|manifest_path| workspace_root(manifest_path))
+        .collect()
+}
+
+/// Returns the root directory of the workspace containing `manifest_path`.
+fn workspace_root(manifest_path: &Path) -> Result<PathBuf> {
+    let output = std::process::Command::new("cargo")
+        .arg("locate-project")
+        .arg("--workspace")
+        .args(["--message-format", "plain"])
+        .arg("--manifest-path")
+        .arg(manifest_path)
+        .output()
+        .context("Failed to run `cargo locate-project`")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to find workspace for `{}`: {}",
+            manifest_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let workspace_manifest = PathBuf::from(
+        std::str::from_utf8(&output.stdout)
+            .context("`cargo locate-project` produced invalid UTF-8")?
+            .trim(),
+    );
+    let root_path = workspace_manifest
+        .parent()
+        .ok_or_else(|| anyhow!("Workspace manifest has no parent directory"))?;
+    root_path
+        .canonicalize()
+        .with_context(|| format!("Failed to read directory `{}`", root_path.display()))
+}
+
+/// Returns where edits made to the config are logged.
+fn audit_log_path(target_dir: &Path, args: &Args) -> PathBuf {
+    args.audit_log
+        .clone()
+        .unwrap_or_else(|| audit::default_path(target_dir))
+}
+
+/// Returns the directory into which cargo will write build artifacts.
+fn target_dir(root_path: &Path, args: &Args) -> Result<PathBuf> {
+    if let Some(target_dir) = &args.target_dir {
+        return Ok(target_dir.clone());
+    }
+    // Cargo's config can come from several places, so rather than trying to reimplement its lookup
+    // we ask cargo. This doesn't need to resolve dependencies, so is fast and doesn't need the
+    // network.
+    // We run cargo from the workspace root, so a relative CARGO_TARGET_DIR is relative to that.
+    let metadata = cargo_metadata::MetadataCommand::new()
+        .current_dir(root_path)
+        .manifest_path(root_path.join("Cargo.toml"))
+        .no_deps()
+        .exec()
+        .context("Failed to determine target directory")?;
+    Ok(metadata.target_directory.into_std_path_buf())
+}
+
+/// Returns the Cargo.toml that we were asked to analyze. This may be for a package within a
+/// workspace, in which case the workspace root is found by `canonical_root_path`.
+fn manifest_path(args: &Args) -> Result<PathBuf> {
+    if let Some(manifest_path) = args.manifest_path.clone() {
+        return Ok(manifest_path);
+    }
+    if let Some(path) = args.path.first() {
+        return Ok(path.join("Cargo.toml"));
+    }
+    let current_dir = std::env::current_dir()
+        .ok()
+        .ok_or_else(|| anyhow!("Failed to get current working directory"))?;
+    let mut dir = current_dir.as_path();
+    loop {
+        let manifest_path = dir.join("Cargo.toml");
+        if manifest_path.exists() {
+            return Ok(manifest_path);
+        }
+        if let Some(parent) = dir.parent() {
+            dir = parent;
+        } else {
+            bail!(
+                "No Cargo.toml found in `{}` or any parent directory",
+                current_dir.display()
+            );
+        }
+    }
+}
+
+/// Returns the Cargo.toml of each of the workspaces that we were asked to analyze. Clap doesn't
+/// allow `--manifest-path` together with `--path`, but if both are set, we analyze all of them.
+fn manifest_paths(args: &Args) -> Result<Vec<PathBuf>> {
+    if args.path.len() + usize::from(args.manifest_path.is_some()) < 2 {
+        return Ok(vec![manifest_path(args)?]);
+    }
+    Ok(args
+        .path
+        .iter()
+        .map(|path| path.join("Cargo.toml"))
+        .chain(args.manifest_path.clone())
+        .collect())
+}
+
+fn determine_sysroot(root_path: &PathBuf, target: Option<&str>) -> Result<Arc<Path>> {
+    let mut command = std::process::Command::new("rustc");
+    command.current_dir(root_path).arg("--print").arg("sysroot");
+    // Ask for the sysroot that will be used for the target we're building for, since source paths
+    // in its standard library are what we need to recognise.
+    if let Some(target) = target {
+        command.arg("--target").arg(target);
+    }
+    let output = command
+        .output()
+        .context("Failed to run `rustc --print sysroot`")?;
+    let stdout = std::str::from_utf8(&output.stdout).context("rust sysroot isn't UTF-8")?;
+    Ok(Arc::from(Path::new(stdout.trim())))
+}
+
+#[derive(Default)]
+struct CheckState {
+    graph_outputs: Option<ScanOutputs>,
+
+    /// Warnings that we've already reported while handling this request. When we recheck a request
+    /// after problems were fixed, we don't report these again, otherwise we'd keep rechecking
+    /// forever, since warnings don't need to be fixed.
+    reported_warnings: FxHashSet<Problem>,
+}
+
+impl CheckState {
+    /// Returns `problems` without any warnings that were previously reported.
+    fn remove_reported_warnings(
+        &mut self,
+        problems: ProblemList,
+        permissions: &Permissions,
+    ) -> ProblemList {
+        let mut unreported = ProblemList::default();
+        for problem in problems.take() {
+            if problem.severity(permissions) == Severity::Warning
+                && !self.reported_warnings.insert(problem.deduplication_key())
+            {
+                continue;
+            }
+            unreported.push(problem);
+        }
+        unreported
+    }
+}
+
+struct RequestHandler {
+    check_state: CheckState,
+    checker: Arc<Mutex<Checker>>,
+    problem_store: ProblemStoreRef,
+    request: Option<proxy::rpc::Request>,
+    scan_slots: Arc<Semaphore>,
+}
+
+impl RequestHandler {
+    fn handle_request(&mut self) -> Result<Outcome> {
+        // Load any link info that was passed via a file now, so that we don't do so while holding
+        // the checker.
+        if let Some(request) = self.request.take() {
+            self.request = Some(request.load_link_info()?);
+        }
+        match &self.request {
+            Some(proxy::rpc::Request::AttachTerminal) => {
+                return Ok(self.problem_store.attach_terminal());
+            }
+            Some(proxy::rpc::Request::DetachTerminal) => {
+                self.problem_store.detach_terminal();
+                return Ok(Outcome::Continue);
+            }
+            _ => {}
+        }
+        self.scan_in_parallel()?;
+        loop {
+            let problems = self
+                .checker
+                .lock()
+                .unwrap()
+                .handle_request(&self.request, &mut self.check_state)?;
+            let return_on_retry = problems.should_send_retry_to_subprocess();
+            if problems.is_empty() {
+                return Ok(Outcome::Continue);
+            }
+            match self.problem_store.fix_problems(problems) {
+                Outcome::Continue => {
+                    self.checker.lock().unwrap().load_config()?;
+                    if return_on_retry {
+                        // If the only problem is that something in a subprocess failed, we return
+                        // an empty error set. This signals the subprocess that it should proceed,
+                        // which since something failed means that it should reload the config and
+                        // retry whatever failed.
+                        return Ok(Outcome::Continue);
+                    }
+                }
+                Outcome::GiveUp => {
+                    return Ok(Outcome::GiveUp);
+                }
+            }
+        }
+    }
+
+    /// If our request needs a binary to be scanned and this can be done without holding the
+    /// checker, then does so. This lets requests for other binaries be scanned at the same time.
+    fn scan_in_parallel(&mut self) -> Result<()> {
+        let job = self
+            .checker
+            .lock()
+            .unwrap()
+            .parallel_scan_job(&self.request, &mut self.check_state)?;
+        let Some(job) = job else {
+            return Ok(());
+        };
+        let graph_outputs = {
+            let _permit = self.scan_slots.acquire();
+            remote_scan::scan_locally(&job)?
+        };
+        self.checker.lock().unwrap().finish_parallel_scan(
+            &job,
+            graph_outputs,
+            &mut self.check_state,
+        )
+    }
+}
+
+/// Directly invokes a wrapped binary, where the binary and arguments were passed to us by the
+/// wrapper shell script.
+fn invoke_wrapped_binary() -> Result<()> {
+    let mut args = std::env::args_os().skip(3);
+    let program = args
+        .next()
+        .ok_or_else(|| anyhow!("Missing proxy-bin program"))?;
+    let status = std::process::Command::new(&program)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to invoke `{}`", program.to_string_lossy()))?;
+    std::process::exit(status.code().unwrap_or(-1));
+}
+
+const _CHECK_OS: () = if cfg!(all(
+    not(target_os = "linux"),
+    not(feature = "unsupported-os")
+)) {
+    panic!("Sorry, only Linux is currently supported. See PORTING.md");
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_dir_resolution() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(
+            root.join("Cargo.toml"),
+            "[package]\nname = \"foo\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "").unwrap();
+        std::fs::create_dir(root.join(".cargo")).unwrap();
+        std::fs::write(
+            root.join(".cargo/config.toml"),
+            "[build]\ntarget-dir = \"custom-target\"\n",
+        )
+        .unwrap();
+
+        // CARGO_TARGET_DIR takes precedence over cargo config, so if it's set in the environment
+        // that's running the tests, then that's what we expect to get.
+        let expected = std::env::var_os("CARGO_TARGET_DIR")
+            .map(|d| root.join(d))
+            .unwrap_or_else(|| root.join("custom-target"));
+        assert_eq!(target_dir(root, &Args::default()).unwrap(), expected);
+
+        let args = Args {
+            target_dir: Some(PathBuf::from("/explicit/target")),
+            ..Args::default()
+        };
+        assert_eq!(
+            target_dir(root, &args).unwrap(),
+            PathBuf::from("/explicit/target")
+        );
+    }
+}
@@ -1,8 +1,10 @@
+use crate::crate_index::CrateKind;
 use crate::crate_index::CrateSel;
 use anyhow::bail;
 use anyhow::Result;
 use serde::Deserialize;
 use serde::Serialize;
+use std::fmt::Display;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -14,24 +16,69 @@ pub(crate) struct LinkInfo {
     pub(crate) object_paths: Vec<PathBuf>,
     pub(crate) output_file: Arc<Path>,
     is_shared: bool,
+    /// Whether the output is a proc macro, which gets loaded by the compiler.
+    #[serde(default)]
+    pub(crate) is_proc_macro: bool,
+}
+
+/// The file produced by a linker invocation, as shown to the user.
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
+pub struct Artifact {
+    /// The output file, relative to the target directory if it's within it.
+    pub path: Arc<Path>,
+    pub kind: ArtifactKind,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArtifactKind {
+    Bin,
+    Test,
+    BuildScript,
+    SharedObject,
+    ProcMacro,
 }
 
 impl LinkInfo {
     pub(crate) fn from_env() -> Result<Self> {
-        let crate_sel = CrateSel::from_env()?;
-        let object_paths = std::env::args()
-            .skip(1)
+        let args = expand_response_files(std::env::args().skip(1))?;
+        let mut link_info = Self::from_args(CrateSel::from_env()?, &args)?;
+        link_info.is_proc_macro =
+            std::env::var_os(crate::proxy::subprocess::ENV_PROC_MACRO).is_some();
+        Ok(link_info)
+    }
+
+    fn from_args(crate_sel: CrateSel, args: &[String]) -> Result<Self> {
+        let object_paths = args
+            .iter()
             .map(PathBuf::from)
             .filter(|path| has_supported_extension(path))
             .collect();
         Ok(LinkInfo {
             crate_sel,
             object_paths,
-            output_file: get_output_file()?,
-            is_shared: get_is_shared(),
+            output_file: get_output_file(args)?,
+            is_shared: get_is_shared(args),
+            is_proc_macro: false,
         })
     }
 
+    pub(crate) fn new(
+        crate_sel: CrateSel,
+        object_paths: Vec<PathBuf>,
+        output_file: Arc<Path>,
+        is_shared: bool,
+        is_proc_macro: bool,
+    ) -> Self {
+        Self {
+            crate_sel,
+            object_paths,
+            output_file,
+            is_shared,
+            is_proc_macro,
+        }
+    }
+
     /// Filters `object_paths` to just those under `dir`.
     pub(crate) fn object_paths_under(&self, dir: &Path) -> Vec<PathBuf> {
         self.object_paths
@@ -45,22 +92,96 @@ impl LinkInfo {
     pub(crate) fn is_executable(&self) -> bool {
         !self.is_shared
     }
+
+    /// Returns a description of our output file, with its path made relative to `target_dir`.
+    pub(crate) fn artifact(&self, target_dir: &Path) -> Artifact {
+        let kind = if self.is_proc_macro {
+            ArtifactKind::ProcMacro
+        } else if self.is_shared {
+            ArtifactKind::SharedObject
+        } else {
+            match self.crate_sel.kind {
+                CrateKind::Primary => ArtifactKind::Bin,
+                CrateKind::Test => ArtifactKind::Test,
+                CrateKind::BuildScript => ArtifactKind::BuildScript,
+            }
+        };
+        let path = self
+            .output_file
+            .strip_prefix(target_dir)
+            .map_or_else(|_| self.output_file.clone(), Arc::from);
+        Artifact { path, kind }
+    }
 }
 
-fn get_output_file() -> Result<Arc<Path>> {
-    let mut args = std::env::args();
-    while let Some(arg) = args.next() {
-        if arg == "-o" {
-            if let Some(output) = args.next() {
-                return Ok(Arc::from(Path::new(&output)));
+impl Display for Artifact {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.path.display(), self.kind)
+    }
+}
+
+impl Display for ArtifactKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArtifactKind::Bin => write!(f, "binary"),
+            ArtifactKind::Test => write!(f, "test"),
+            ArtifactKind::BuildScript => write!(f, "build script"),
+            ArtifactKind::SharedObject => write!(f, "shared object"),
+            ArtifactKind::ProcMacro => write!(f, "proc macro"),
+        }
+    }
+}
+
+/// Returns `args` with any response files (`@path`) replaced by the arguments that they contain.
+/// Rustc uses a response file if the command line would otherwise be too long. It writes one
+/// argument per line, escaping backslashes and spaces with a backslash.
+fn expand_response_files(args: impl Iterator<Item = String>) -> Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    for arg in args {
+        let Some(path) = arg.strip_prefix('@') else {
+            expanded.push(arg);
+            continue;
+        };
+        let contents = crate::fs::read_to_string(Path::new(path))?;
+        for line in contents.lines() {
+            let mut unescaped = String::with_capacity(line.len());
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                if c == '\\' {
+                    unescaped.extend(chars.next());
+                } else {
+                    unescaped.push(c);
+                }
             }
+            expanded.push(unescaped);
+        }
+    }
+    Ok(expanded)
+}
+
+/// Returns the output file from `args`. Rustc passes `-o path`, but linker arguments added via
+/// `-C link-args` could override it with `--output`.
+fn get_output_file(args: &[String]) -> Result<Arc<Path>> {
+    let mut output_file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let output = if arg == "-o" || arg == "--output" {
+            args.next().map(String::as_str)
+        } else {
+            arg.strip_prefix("--output=")
+        };
+        if let Some(output) = output {
+            output_file = Some(output);
         }
     }
+    if let Some(output) = output_file {
+        return Ok(Arc::from(Path::new(output)));
+    }
     bail!("Failed to find output file in linker command line");
 }
 
-fn get_is_shared() -> bool {
-    std::env::args().any(|arg| arg == "-shared")
+fn get_is_shared(args: &[String]) -> bool {
+    args.iter().any(|arg| arg == "-shared" || arg == "--shared")
 }
 
 fn has_supported_extension(path: &Path) -> bool {
@@ -69,3 +190,110 @@ fn has_supported_extension(path: &Path) -> bool {
         .and_then(|ext| ext.to_str())
         .is_some_and(|ext| EXTENSIONS.contains(&ext))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn artifact() {
+        let pkg_id = crate::crate_index::testing::pkg_id("foo");
+        let target_dir = Path::new("/project/target");
+        let link_info = |crate_sel, output_file: &str, is_shared| {
+            LinkInfo::new(
+                crate_sel,
+                Vec::new(),
+                Arc::from(Path::new(output_file)),
+                is_shared,
+                false,
+            )
+            .artifact(target_dir)
+            .to_string()
+        };
+        assert_eq!(
+            link_info(
+                CrateSel::primary(pkg_id.clone()),
+                "/project/target/debug/foo",
+                false
+            ),
+            "debug/foo (binary)"
+        );
+        assert_eq!(
+            link_info(
+                CrateSel::build_script(pkg_id.clone()),
+                "/project/target/debug/build/foo-1234/build_script_build-1234",
+                false
+            ),
+            "debug/build/foo-1234/build_script_build-1234 (build script)"
+        );
+        assert_eq!(
+            link_info(
+                CrateSel::primary(pkg_id.clone()),
+                "/elsewhere/libfoo.so",
+                true
+            ),
+            "/elsewhere/libfoo.so (shared object)"
+        );
+        let mut test_sel = CrateSel::primary(pkg_id);
+        test_sel.kind = CrateKind::Test;
+        assert_eq!(
+            link_info(test_sel, "/project/target/debug/deps/foo-1234", false),
+            "debug/deps/foo-1234 (test)"
+        );
+    }
+
+    #[test]
+    fn linker_args() {
+        let args =
+            |args: &[&str]| -> Vec<String> { args.iter().map(|arg| arg.to_string()).collect() };
+        let crate_sel = CrateSel::primary(crate::crate_index::testing::pkg_id("foo"));
+        let link_info = LinkInfo::from_args(
+            crate_sel.clone(),
+            &args(&[
+                "-m64",
+                "a.o",
+                "libb.rlib",
+                "-o",
+                "out/foo",
+                "-nodefaultlibs",
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            link_info.object_paths,
+            vec![PathBuf::from("a.o"), PathBuf::from("libb.rlib")]
+        );
+        assert_eq!(link_info.output_file.as_ref(), Path::new("out/foo"));
+        assert!(link_info.is_executable());
+
+        for output_args in [
+            &["--output", "out/foo"][..],
+            &["--output=out/foo"],
+            &["-o", "out/bar", "--output=out/foo"],
+        ] {
+            let link_info = LinkInfo::from_args(crate_sel.clone(), &args(output_args)).unwrap();
+            assert_eq!(link_info.output_file.as_ref(), Path::new("out/foo"));
+        }
+        assert!(
+            LinkInfo::from_args(crate_sel.clone(), &args(&["--shared", "-o", "libfoo.so"]))
+                .is_ok_and(|link_info| !link_info.is_executable())
+        );
+        assert!(LinkInfo::from_args(crate_sel, &args(&["--oformat=elf"])).is_err());
+    }
+
+    #[test]
+    fn response_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("linker-arguments");
+        std::fs::write(&path, "a.o\n-o\nmy\\ dir/foo\\\\bar\n").unwrap();
+        let args = [
+            "-m64".to_owned(),
+            format!("@{}", path.display()),
+            "-nodefaultlibs".to_owned(),
+        ];
+        assert_eq!(
+            expand_response_files(args.into_iter()).unwrap(),
+            vec!["-m64", "a.o", "-o", "my dir/foo\\bar", "-nodefaultlibs"]
+        );
+    }
+}
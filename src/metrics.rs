@@ -0,0 +1,273 @@
+//! Per-run metrics about how much each package uses each API. After each complete check, the counts
+//! are appended to a history file in the target directory. `cargo acl trends` then shows how the
+//! counts changed between runs, which can reveal creeping use of capabilities even when all of
+//! that use is permitted.
+
+use crate::config::permissions::PermSel;
+use crate::config::ApiName;
+use crate::location::SourceLocation;
+use crate::names::Name;
+use crate::problem::ApiUsages;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use fxhash::FxHashMap;
+use fxhash::FxHashSet;
+use log::info;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The maximum number of runs that we keep in the history file.
+const MAX_RUNS: usize = 100;
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct TrendsOptions {
+    /// The number of most recent runs to compare.
+    #[clap(long, default_value_t = 10)]
+    runs: usize,
+
+    /// Show counts that didn't change as well as those that did.
+    #[clap(long)]
+    all: bool,
+}
+
+/// Collects the distinct usages of each API by each package during a run.
+#[derive(Default)]
+pub(crate) struct UsageCounter {
+    usages: FxHashMap<PermSel, FxHashMap<ApiName, FxHashSet<(SourceLocation, Name)>>>,
+}
+
+/// Metrics collected during a single run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct RunMetrics {
+    /// Seconds since the Unix epoch when the run completed.
+    timestamp: u64,
+
+    /// The number of distinct usages, keyed by permission selector then by API.
+    api_usage_counts: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct MetricsHistory {
+    runs: Vec<RunMetrics>,
+}
+
+impl UsageCounter {
+    /// Records `api_usages`. Usages are deduplicated by location and name referenced, so the same
+    /// code being linked into several binaries, or being checked again after a config change,
+    /// doesn't inflate the counts.
+    pub(crate) fn record(&mut self, api_usages: &ApiUsages) {
+        let usages = self
+            .usages
+            .entry(api_usages.perm_sel())
+            .or_default()
+            .entry(api_usages.api_name.clone())
+            .or_default();
+        for usage in &api_usages.usages {
+            usages.insert((usage.source_location.clone(), usage.to_name.clone()));
+        }
+    }
+
+    /// Returns the distinct usages recorded for each permission selector and API.
+    pub(crate) fn usages(
+        &self,
+    ) -> impl Iterator<Item = (&PermSel, &ApiName, &FxHashSet<(SourceLocation, Name)>)> {
+        self.usages.iter().flat_map(|(perm_sel, by_api)| {
+            by_api
+                .iter()
+                .map(move |(api, usages)| (perm_sel, api, usages))
+        })
+    }
+
+    fn counts(&self) -> BTreeMap<String, BTreeMap<String, usize>> {
+        self.usages
+            .iter()
+            .map(|(perm_sel, by_api)| {
+                let counts = by_api
+                    .iter()
+                    .map(|(api, usages)| (api.to_string(), usages.len()))
+                    .collect();
+                (perm_sel.to_string(), counts)
+            })
+            .collect()
+    }
+}
+
+/// Returns the path of the file into which we record metrics.
+pub(crate) fn metrics_path(target_dir: &Path) -> PathBuf {
+    target_dir.join("cackle").join("metrics.json")
+}
+
+/// Appends the counts from `counter` to the metrics history in `target_dir`.
+pub(crate) fn record_run(target_dir: &Path, counter: &UsageCounter) -> Result<()> {
+    let path = metrics_path(target_dir);
+    let mut history = match load_history(&path) {
+        Ok(history) => history,
+        Err(error) => {
+            // The history is just informational, so we'd rather start again than fail the check.
+            info!("Discarding metrics history: {error:#}");
+            MetricsHistory::default()
+        }
+    };
+    history.runs.push(RunMetrics {
+        timestamp: crate::timing::unix_now(),
+        api_usage_counts: counter.counts(),
+    });
+    let excess = history.runs.len().saturating_sub(MAX_RUNS);
+    history.runs.drain(..excess);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    }
+    crate::fs::write_atomic(&path, &serde_json::to_string_pretty(&history)?)
+}
+
+fn load_history(path: &Path) -> Result<MetricsHistory> {
+    if !path.exists() {
+        return Ok(MetricsHistory::default());
+    }
+    serde_json::from_str(&crate::fs::read_to_string(path)?)
+        .with_context(|| format!("Failed to parse `{}`", path.display()))
+}
+
+/// Prints how API usage counts changed over recent runs.
+pub(crate) fn print_trends(target_dir: &Path, options: &TrendsOptions) -> Result<()> {
+    let path = metrics_path(target_dir);
+    let history = load_history(&path)?;
+    let first = history.runs.len().saturating_sub(options.runs.max(1));
+    let runs = &history.runs[first..];
+    if runs.len() < 2 {
+        println!(
+            "Need at least 2 recorded runs to show trends, but `{}` has {}. Metrics are recorded \
+             each time `cargo acl` completes a full check",
+            path.display(),
+            runs.len()
+        );
+        return Ok(());
+    }
+    let now = crate::timing::unix_now();
+    let ages: Vec<String> = runs
+        .iter()
+        .map(|run| format_age(now.saturating_sub(run.timestamp)))
+        .collect();
+    println!(
+        "API usage counts over the last {} runs ({})",
+        runs.len(),
+        ages.join(", ")
+    );
+    let lines = trend_lines(runs, options.all);
+    if lines.is_empty() {
+        println!("No changes in API usage counts");
+    }
+    for line in lines {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// Returns a line for each package and API showing the counts in each of `runs`.
+fn trend_lines(runs: &[RunMetrics], all: bool) -> Vec<String> {
+    let mut keys = FxHashSet::default();
+    for run in runs {
+        for (perm_sel, by_api) in &run.api_usage_counts {
+            for api in by_api.keys() {
+                keys.insert((perm_sel, api));
+            }
+        }
+    }
+    let mut keys: Vec<_> = keys.into_iter().collect();
+    keys.sort();
+    let mut lines = Vec::new();
+    for (perm_sel, api) in keys {
+        let counts: Vec<usize> = runs
+            .iter()
+            .map(|run| {
+                run.api_usage_counts
+                    .get(perm_sel)
+                    .and_then(|by_api| by_api.get(api))
+                    .copied()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let (Some(oldest), Some(newest)) = (counts.first(), counts.last()) else {
+            continue;
+        };
+        let changed = counts.windows(2).any(|pair| pair[0] != pair[1]);
+        if !changed && !all {
+            continue;
+        }
+        let counts_str = counts
+            .iter()
+            .map(|count| count.to_string())
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        let delta = *newest as i64 - *oldest as i64;
+        if delta == 0 {
+            lines.push(format!("{perm_sel} {api}: {counts_str}"));
+        } else {
+            lines.push(format!("{perm_sel} {api}: {counts_str} ({delta:+})"));
+        }
+    }
+    lines
+}
+
+pub(crate) fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{seconds}s ago"),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::trend_lines;
+    use super::RunMetrics;
+    use std::collections::BTreeMap;
+
+    fn run(counts: &[(&str, &str, usize)]) -> RunMetrics {
+        let mut api_usage_counts: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+        for (perm_sel, api, count) in counts {
+            api_usage_counts
+                .entry(perm_sel.to_string())
+                .or_default()
+                .insert(api.to_string(), *count);
+        }
+        RunMetrics {
+            timestamp: 0,
+            api_usage_counts,
+        }
+    }
+
+    #[test]
+    fn trends() {
+        let runs = [
+            run(&[("crab1", "fs", 2), ("crab1", "net", 1)]),
+            run(&[("crab1", "fs", 3), ("crab1", "net", 1)]),
+            run(&[
+                ("crab1", "fs", 5),
+                ("crab1", "net", 1),
+                ("crab2.build", "process", 1),
+            ]),
+        ];
+        assert_eq!(
+            trend_lines(&runs, false),
+            vec![
+                "crab1 fs: 2 -> 3 -> 5 (+3)".to_owned(),
+                "crab2.build process: 0 -> 0 -> 1 (+1)".to_owned(),
+            ]
+        );
+        assert_eq!(
+            trend_lines(&runs, true),
+            vec![
+                "crab1 fs: 2 -> 3 -> 5 (+3)".to_owned(),
+                "crab1 net: 1 -> 1 -> 1".to_owned(),
+                "crab2.build process: 0 -> 0 -> 1 (+1)".to_owned(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Problems that the user has muted from the UI for a number of days. Unlike edits, mutes aren't
+//! recorded in cackle.toml, since they're a way to defer a problem rather than a change in policy.
+//! Instead they're kept in a state file in the target directory. Once a mute expires, the problem
+//! is reported again.
+
+use crate::config::expiry;
+use crate::problem::Problem;
+use anyhow::Context;
+use anyhow::Result;
+use log::info;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+use toml::value::Datetime;
+
+/// The number of days that a problem is muted for unless the user says otherwise.
+pub(crate) const DEFAULT_MUTE_DAYS: u32 = 7;
+
+/// Returns the path of the file in which muted problems are recorded.
+pub(crate) fn muted_problems_path(target_dir: &Path) -> PathBuf {
+    target_dir.join("cackle").join("muted-problems.json")
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct MutedProblems {
+    #[serde(skip)]
+    path: PathBuf,
+    muted: Vec<MutedProblem>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MutedProblem {
+    /// The problem with details such as individual usages and the package version removed, so
+    /// that the mute applies to any later report of the same problem.
+    problem: Problem,
+
+    /// The date on which the problem stops being muted, e.g. "2025-06-30".
+    until: String,
+}
+
+impl MutedProblems {
+    /// Loads the muted problems recorded at `path`, dropping any mutes that have expired as of
+    /// `today`.
+    pub(crate) fn load(path: PathBuf, today: &Datetime) -> Result<Self> {
+        let mut muted = if path.exists() {
+            let json = crate::fs::read_to_string(&path)?;
+            serde_json::from_str(&json)
+                .with_context(|| format!("Failed to parse `{}`", path.display()))?
+        } else {
+            Self::default()
+        };
+        muted.path = path;
+        muted.muted.retain(|mute| {
+            let expired = mute
+                .until
+                .parse()
+                .map_or(true, |until| expiry::is_expired(&until, today));
+            if expired {
+                info!("Mute of problem ({}) has expired", mute.problem);
+            }
+            !expired
+        });
+        Ok(muted)
+    }
+
+    /// Returns whether `problem` is currently muted.
+    pub(crate) fn is_muted(&self, problem: &Problem) -> bool {
+        let key = problem.version_independent_key();
+        self.muted.iter().any(|mute| mute.problem == key)
+    }
+
+    /// Mutes `problem` until `until`, then saves.
+    pub(crate) fn mute(&mut self, problem: &Problem, until: Datetime) -> Result<()> {
+        let key = problem.version_independent_key();
+        self.muted.retain(|mute| mute.problem != key);
+        self.muted.push(MutedProblem {
+            problem: key,
+            until: until.to_string(),
+        });
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+        }
+        crate::fs::write_atomic(&self.path, &serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crate_index::testing::pkg_id;
+
+    #[test]
+    fn mute_until_expiry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = muted_problems_path(dir.path());
+        let date = |s: &str| s.parse::<Datetime>().unwrap();
+        let today = date("2024-03-01");
+        let mut muted = MutedProblems::load(path.clone(), &today).unwrap();
+        let problem = Problem::UsesBuildScript(pkg_id("foo"));
+        assert!(!muted.is_muted(&problem));
+        muted.mute(&problem, date("2024-03-08")).unwrap();
+        assert!(muted.is_muted(&problem));
+        assert!(!muted.is_muted(&Problem::UsesBuildScript(pkg_id("bar"))));
+
+        let muted = MutedProblems::load(path.clone(), &date("2024-03-07")).unwrap();
+        assert!(muted.is_muted(&problem));
+
+        let muted = MutedProblems::load(path, &date("2024-03-08")).unwrap();
+        assert!(!muted.is_muted(&problem));
+    }
+}
@@ -5,25 +5,28 @@ use crate::symbol::Symbol;
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::sync::Arc;
 
 /// A name of something. e.g. `std::path::Path`.
-#[derive(Eq, PartialEq, Hash, Clone)]
+#[derive(Eq, PartialEq, Hash, Clone, Serialize, Deserialize)]
 pub(crate) struct Name {
     /// The components of this name. e.g. ["std", "path", "Path"]
     pub(crate) parts: Vec<Arc<str>>,
 }
 
 /// A name obtained from debug info.
-#[derive(Eq, PartialEq, Hash, Clone, Debug, PartialOrd, Ord)]
+#[derive(Eq, PartialEq, Hash, Clone, Debug, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(bound(deserialize = "Utf8Bytes<'input>: Deserialize<'de>"))]
 pub(crate) struct DebugName<'input> {
     pub(crate) namespace: Namespace,
     pub(crate) name: Utf8Bytes<'input>,
 }
 
-#[derive(Eq, PartialEq, Hash, Clone, Debug, PartialOrd, Ord)]
+#[derive(Eq, PartialEq, Hash, Clone, Debug, PartialOrd, Ord, Serialize, Deserialize)]
 pub(crate) struct Namespace {
     pub(crate) parts: Arc<[Arc<str>]>,
 }
@@ -34,7 +37,7 @@ pub(crate) struct SymbolAndName<'input> {
     pub(crate) debug_name: Option<DebugName<'input>>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub(crate) enum SymbolOrDebugName {
     Symbol(Symbol<'static>),
     DebugName(DebugName<'static>),
@@ -82,7 +85,7 @@ impl Namespace {
 }
 
 impl<'input> DebugName<'input> {
-    pub(crate) fn names_iterator(&self) -> NamesIterator<NonMangledIterator> {
+    pub(crate) fn names_iterator(&self) -> NamesIterator<'_, NonMangledIterator<'_>> {
         NamesIterator::new(NonMangledIterator::new(
             &self.namespace.parts,
             self.name.as_ref(),
@@ -254,7 +257,7 @@ impl<'data, I: Clone + Iterator<Item = DemangleToken<'data>>> Iterator
                     if self.as_final == Some(text)
                         && self
                             .as_final
-                            .is_some_and(|t| t.as_ptr() as usize == text.as_ptr() as usize)
+                            .is_some_and(|t| std::ptr::eq(t.as_ptr(), text.as_ptr()))
                     {
                         // This text was already output as the final part of an as-name. Ignore it.
                         continue;
@@ -308,24 +311,20 @@ impl<'data, I: Clone + Iterator<Item = DemangleToken<'data>>> Iterator
                         NamesIteratorState::AsSkip {
                             gt_depth,
                             return_point,
-                        } => {
-                            if *gt_depth == 0 {
-                                match self.it.next() {
-                                    Some(DemangleToken::Text(text)) => {
-                                        self.it = return_point.clone();
-                                        self.as_final = Some(text);
-                                        self.state = NamesIteratorState::OutputtingName;
-                                        return Some(NameToken::Part(text));
-                                    }
-                                    _ => {
-                                        self.it = return_point.clone();
-                                        self.as_final = None;
-                                        self.state = NamesIteratorState::Inactive;
-                                        return Some(NameToken::EndName);
-                                    }
-                                }
+                        } if *gt_depth == 0 => match self.it.next() {
+                            Some(DemangleToken::Text(text)) => {
+                                self.it = return_point.clone();
+                                self.as_final = Some(text);
+                                self.state = NamesIteratorState::OutputtingName;
+                                return Some(NameToken::Part(text));
                             }
-                        }
+                            _ => {
+                                self.it = return_point.clone();
+                                self.as_final = None;
+                                self.state = NamesIteratorState::Inactive;
+                                return Some(NameToken::EndName);
+                            }
+                        },
                         _ => {}
                     }
                 }
@@ -351,7 +350,7 @@ impl<'input> DebugName<'input> {
         }
     }
 
-    pub(crate) fn new(namespace: Namespace, name: &str) -> DebugName {
+    pub(crate) fn new(namespace: Namespace, name: &str) -> DebugName<'_> {
         DebugName {
             namespace,
             name: Utf8Bytes::Borrowed(name),
@@ -387,6 +386,84 @@ impl<'input> SymbolAndName<'input> {
         }
         bail!("Invalid SymbolAndName has neither");
     }
+
+    /// Returns the first part, normally the crate name, of each path that appears in the generic
+    /// parameters of this name. e.g. for
+    /// `tokio::runtime::task::core::Core<my_crate::foo::{{closure}}, alloc::sync::Arc<S>>::poll`,
+    /// returns `my_crate` (a closure), `alloc` and `S`. Closures of the named function itself
+    /// aren't included.
+    pub(crate) fn generic_params(&self) -> Result<Vec<GenericParam<'_>>> {
+        if let Some(debug_name) = self.debug_name.as_ref() {
+            return Ok(generic_params(NonMangledIterator::new(
+                &debug_name.namespace.parts,
+                debug_name.name.as_ref(),
+            )));
+        }
+        if let Some(symbol) = self.symbol.as_ref() {
+            return Ok(generic_params(symbol.demangled_tokens()?));
+        }
+        Ok(Vec::new())
+    }
+}
+
+/// A path that appears in the generic parameters of a name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct GenericParam<'data> {
+    /// The first part of the path. Normally the name of the crate that defined it.
+    pub(crate) prefix: &'data str,
+    /// Whether the path names a closure or async block.
+    pub(crate) is_closure: bool,
+}
+
+fn generic_params<'data>(
+    tokens: impl Iterator<Item = DemangleToken<'data>>,
+) -> Vec<GenericParam<'data>> {
+    let mut params = Vec::new();
+    let mut angle_depth = 0_u32;
+    let mut brace_depth = 0_u32;
+    // The first part of the path that we're currently reading, if any.
+    let mut path_start = None;
+    let mut end_path = |path_start: &mut Option<&'data str>, angle_depth, is_closure| {
+        if let Some(prefix) = path_start.take() {
+            let param = GenericParam { prefix, is_closure };
+            if angle_depth > 0 && !params.contains(&param) {
+                params.push(param);
+            }
+        }
+    };
+    for token in tokens {
+        match token {
+            DemangleToken::Text(text) => {
+                if brace_depth == 0 && path_start.is_none() {
+                    path_start = Some(text);
+                }
+            }
+            // Closures and async blocks are named like `{{closure}}` in symbols and like
+            // `{closure_env#0}` or `{async_block_env#0}` in debug info.
+            DemangleToken::Char('{') => {
+                if brace_depth == 0 {
+                    end_path(&mut path_start, angle_depth, true);
+                }
+                brace_depth += 1;
+            }
+            DemangleToken::Char('}') => {
+                brace_depth = brace_depth.saturating_sub(1);
+            }
+            DemangleToken::Char(ch) => {
+                if brace_depth == 0 {
+                    end_path(&mut path_start, angle_depth, false);
+                    match ch {
+                        '<' => angle_depth += 1,
+                        '>' => angle_depth = angle_depth.saturating_sub(1),
+                        _ => {}
+                    }
+                }
+            }
+            DemangleToken::UnsupportedEscape(_) => path_start = None,
+        }
+    }
+    end_path(&mut path_start, angle_depth, false);
+    params
 }
 
 impl Display for Name {
@@ -536,6 +613,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_generic_params() {
+        fn closure(prefix: &str) -> GenericParam<'_> {
+            GenericParam {
+                prefix,
+                is_closure: true,
+            }
+        }
+        fn other(prefix: &str) -> GenericParam<'_> {
+            GenericParam {
+                prefix,
+                is_closure: false,
+            }
+        }
+
+        let debug_name = DebugName::new(
+            Namespace::empty().plus("tokio").plus("runtime"),
+            "task::core::Core<my_crate::foo::{async_fn_env#0}, alloc::sync::Arc<other::Handle>>",
+        );
+        let name = SymbolAndName {
+            symbol: None,
+            debug_name: Some(debug_name),
+        };
+        assert_eq!(
+            name.generic_params().unwrap(),
+            vec![closure("my_crate"), other("alloc"), other("other")]
+        );
+
+        // A closure of the function itself isn't in a generic parameter.
+        let debug_name = DebugName::new(
+            Namespace::empty(),
+            "<tokio::task::JoinHandle<()> as core::future::Future>::poll::{closure_env#0}",
+        );
+        let name = SymbolAndName {
+            symbol: None,
+            debug_name: Some(debug_name),
+        };
+        assert_eq!(
+            name.generic_params().unwrap(),
+            vec![other("tokio"), other("as"), other("core")]
+        );
+
+        let name = SymbolAndName {
+            symbol: Some(Symbol::borrowed(
+                b"_ZN5tokio7runtime4task3raw54poll$LT$my_crate..foo..$u7b$$u7b$closure$u7d$$u7d$$GT$17h0123456789abcdefE",
+            )),
+            debug_name: None,
+        };
+        assert_eq!(name.generic_params().unwrap(), vec![closure("my_crate")]);
+    }
+
     #[test]
     fn test_debug_name_display() {
         let name = DebugName::new(
@@ -0,0 +1,745 @@
+//! Support for checks that aren't built into cackle. A plugin is run over the outputs of scanning
+//! each binary and can report problems, which then flow through the problem store, UI and edit
+//! system in the same way as problems that cackle finds itself.
+//!
+//! Plugins can be compiled in by implementing `CheckPlugin` and calling `register_plugin` before
+//! running the analysis. They can also be external commands configured in cackle.toml, e.g.:
+//!
+//! ```toml
+//! [plugin.approved-crypto]
+//! command = ["./tools/check-crypto.py"]
+//! ```
+//!
+//! An external command is passed a JSON object on stdin. Its key `api_usages` is an array of objects
+//! with keys `package`, `perm_sel`, `api` and `usages`. Each usage has keys `source_location`,
+//! `from` and `to`. Its key `allowed_apis` maps each permission selector to the APIs that it's
+//! allowed, including any that are inherited. The command should write a JSON array of problems to
+//! stdout, each with keys `message` and optionally `package`, `details` and `severity`, which may
+//! be "error" (the default) or "warning".
+//!
+//! Plugins can also offer fixes for problems, in addition to those built into `config_editor`. Fixes
+//! can be compiled in by implementing `FixProvider` and calling `register_fix_provider`, or
+//! configured as an external command:
+//!
+//! ```toml
+//! [fix.file-ticket]
+//! title = "File a ticket and add a tracking comment"
+//! problems = ["disallowed_api_usage"]
+//! command = ["./tools/file-ticket.py"]
+//! ```
+//!
+//! The command is passed a JSON object on stdin with keys `problem`, `config`, `comment` and
+//! `preview`. `problem` has keys `kind`, `package`, `plugin`, `message`, `details` and `artifacts`.
+//! Each artifact is an object with keys `path`, which is relative to the target directory, and
+//! `kind`, which is one of "bin", "test", "build-script" or "shared-object". `config` is the
+//! current contents of cackle.toml. The command should write the updated contents of
+//! cackle.toml to stdout, or nothing if it doesn't want to change it. Fixes are also applied in
+//! order to show diffs, in which case `preview` is true and the command shouldn't have any side
+//! effects such as filing tickets.
+
+use crate::checker::ApiUsage;
+use crate::config::Config;
+use crate::config::FixConfig;
+use crate::config::PackageName;
+use crate::config::PluginConfig;
+use crate::config_editor::ConfigEditor;
+use crate::config_editor::Edit;
+use crate::config_editor::EditOpts;
+use crate::crate_index::CrateIndex;
+use crate::link_info::Artifact;
+use crate::problem::ApiUsages;
+use crate::problem::PluginProblem;
+use crate::problem::Problem;
+use crate::problem::ProblemList;
+use crate::problem::Severity;
+use crate::symbol_graph::ScanOutputs;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// A check that's run over the outputs of scanning each binary. Plugins can be compiled in by
+/// registering them with `register_plugin`.
+pub trait CheckPlugin: Send + Sync {
+    /// Returns the name of the plugin. Used when reporting problems and errors.
+    fn name(&self) -> &str;
+
+    /// Checks the outputs of scanning a binary, returning any problems found.
+    fn check(&self, inputs: &PluginInputs) -> Result<Vec<PluginReport>>;
+}
+
+/// Offers fixes for problems in addition to those built into cackle. Providers can be compiled in
+/// by registering them with `register_fix_provider`.
+pub trait FixProvider: Send + Sync {
+    /// Returns fixes for `problem`, which will be offered after any built-in fixes.
+    fn fixes(&self, problem: &ProblemInfo) -> Vec<Box<dyn PluginFix>>;
+}
+
+/// A fix offered by a `FixProvider`.
+pub trait PluginFix {
+    /// Returns a short name for this fix, suitable for display in a menu.
+    fn title(&self) -> String;
+
+    /// Returns an identifier for this fix that's stable across runs.
+    fn id(&self) -> String;
+
+    /// Returns a description of what the fix does.
+    fn help(&self) -> String;
+
+    /// Returns the updated contents of cackle.toml, given its current contents `config`, or None
+    /// if the fix doesn't change it. `comment` is a comment that the user asked to have attached.
+    /// If `preview` is true, the fix is only being applied in order to show a diff, so it shouldn't
+    /// have side effects such as filing tickets.
+    fn apply(&self, config: &str, comment: Option<&str>, preview: bool) -> Result<Option<String>>;
+}
+
+/// What a plugin gets to look at.
+pub struct PluginInputs<'a> {
+    pub(crate) scan_outputs: &'a ScanOutputs,
+    pub(crate) crate_index: &'a CrateIndex,
+    pub(crate) config: &'a Config,
+}
+
+/// The usages of an API by a package.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PluginApiUsages {
+    pub package: String,
+    pub perm_sel: String,
+    pub api: String,
+    pub usages: Vec<PluginApiUsage>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PluginApiUsage {
+    pub source_location: String,
+    /// The function from which the API was used.
+    pub from: String,
+    /// The name that was referenced.
+    pub to: String,
+}
+
+/// A problem reported by a plugin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginReport {
+    pub message: String,
+    /// The name of the package that the problem relates to, if any.
+    pub package: Option<String>,
+    pub details: Option<String>,
+    pub severity: Severity,
+}
+
+/// A problem for which fixes are being requested.
+#[derive(Serialize, Debug, Clone)]
+pub struct ProblemInfo {
+    /// The kind of problem. e.g. "disallowed_api_usage".
+    pub kind: &'static str,
+    pub package: Option<String>,
+    /// The name of the plugin that reported the problem, if it was reported by a plugin.
+    pub plugin: Option<String>,
+    pub message: String,
+    pub details: String,
+    /// The binaries in which the problem was found.
+    pub artifacts: Vec<Artifact>,
+}
+
+/// Plugins registered with `register_plugin`.
+static REGISTERED_PLUGINS: Mutex<Vec<Arc<dyn CheckPlugin>>> = Mutex::new(Vec::new());
+
+/// Fix providers registered with `register_fix_provider`.
+static REGISTERED_FIX_PROVIDERS: Mutex<Vec<Arc<dyn FixProvider>>> = Mutex::new(Vec::new());
+
+/// Registers `plugin` to be run, in addition to any configured in cackle.toml, by all subsequent
+/// analysis in this process.
+pub fn register_plugin(plugin: impl CheckPlugin + 'static) {
+    REGISTERED_PLUGINS.lock().unwrap().push(Arc::new(plugin));
+}
+
+/// Registers `provider` to offer fixes, in addition to any configured in cackle.toml, for all
+/// subsequent analysis in this process.
+pub fn register_fix_provider(provider: impl FixProvider + 'static) {
+    REGISTERED_FIX_PROVIDERS
+        .lock()
+        .unwrap()
+        .push(Arc::new(provider));
+}
+
+/// Returns all plugins that should be run. `config_dir` is the directory containing cackle.toml.
+pub(crate) fn plugins(config: &Config, config_dir: &Path) -> Vec<Arc<dyn CheckPlugin>> {
+    let mut plugins = REGISTERED_PLUGINS.lock().unwrap().clone();
+    for (name, plugin_config) in &config.raw.plugins {
+        plugins.push(Arc::new(CommandPlugin {
+            name: Arc::from(name.as_str()),
+            config: plugin_config.clone(),
+            dir: config_dir.to_owned(),
+        }));
+    }
+    plugins
+}
+
+/// Runs `plugin`, adding the problems that it reports to `problems`.
+pub(crate) fn run_plugin(
+    plugin: &dyn CheckPlugin,
+    inputs: &PluginInputs,
+    problems: &mut ProblemList,
+) -> Result<()> {
+    let plugin_name: Arc<str> = Arc::from(plugin.name());
+    for report in plugin.check(inputs)? {
+        let pkg_id = report
+            .package
+            .map(|name| {
+                inputs
+                    .crate_index
+                    .newest_package_id_with_name(&PackageName(Arc::from(name.as_str())))
+                    .cloned()
+                    .ok_or_else(|| anyhow!("Problem reported for unknown package `{name}`"))
+            })
+            .transpose()?;
+        problems.push(Problem::PluginProblem(PluginProblem {
+            plugin: plugin_name.clone(),
+            pkg_id,
+            message: report.message,
+            details: report.details,
+            severity: report.severity,
+        }));
+    }
+    Ok(())
+}
+
+/// Returns all providers of fixes in addition to those built into cackle.
+pub(crate) fn fix_providers(config: &Config) -> Vec<Arc<dyn FixProvider>> {
+    let mut providers = REGISTERED_FIX_PROVIDERS.lock().unwrap().clone();
+    providers.extend(
+        config
+            .raw
+            .fixes
+            .values()
+            .map(|fix_config| -> Arc<dyn FixProvider> { Arc::new(fix_config.clone()) }),
+    );
+    providers
+}
+
+/// Returns fixes for `problem` from all configured and registered providers.
+pub(crate) fn custom_fixes(problem: &Problem, config: &Config) -> Vec<Box<dyn Edit>> {
+    let problem = ProblemInfo::from(problem);
+    fix_providers(config)
+        .iter()
+        .flat_map(|provider| provider.fixes(&problem))
+        .map(|fix| -> Box<dyn Edit> { Box::new(ProvidedFix(fix)) })
+        .collect()
+}
+
+impl PluginInputs<'_> {
+    /// Returns the API usages found in the binary, sorted by permission selector then API. These
+    /// are what's passed to external commands as `api_usages`.
+    pub fn api_usages(&self) -> Vec<PluginApiUsages> {
+        let mut api_usages: Vec<PluginApiUsages> = self
+            .scan_outputs
+            .api_usages()
+            .map(PluginApiUsages::from)
+            .collect();
+        api_usages.sort_by(|a, b| (&a.perm_sel, &a.api).cmp(&(&b.perm_sel, &b.api)));
+        api_usages
+    }
+
+    /// Returns the APIs that each permission selector is allowed, keyed by permission selector.
+    /// These are what's passed to external commands as `allowed_apis`.
+    pub fn allowed_apis(&self) -> BTreeMap<String, Vec<String>> {
+        self.config
+            .permissions
+            .packages
+            .iter()
+            .filter(|(_, pkg_config)| !pkg_config.allow_apis.is_empty())
+            .map(|(perm_sel, pkg_config)| {
+                let apis = pkg_config
+                    .allow_apis
+                    .iter()
+                    .map(|api| api.to_string())
+                    .collect();
+                (perm_sel.to_string(), apis)
+            })
+            .collect()
+    }
+}
+
+/// A plugin implemented by an external command.
+struct CommandPlugin {
+    name: Arc<str>,
+    config: PluginConfig,
+    dir: PathBuf,
+}
+
+#[derive(Serialize)]
+struct CommandInput {
+    api_usages: Vec<PluginApiUsages>,
+    allowed_apis: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CommandProblem {
+    message: String,
+    #[serde(default)]
+    package: Option<String>,
+    #[serde(default)]
+    details: Option<String>,
+    #[serde(default)]
+    severity: CommandSeverity,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum CommandSeverity {
+    #[default]
+    Error,
+    Warning,
+}
+
+impl CheckPlugin for CommandPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self, inputs: &PluginInputs) -> Result<Vec<PluginReport>> {
+        let input = serde_json::to_vec(&CommandInput {
+            api_usages: inputs.api_usages(),
+            allowed_apis: inputs.allowed_apis(),
+        })?;
+        let output = run_command(&self.config.command, Some(&self.dir), &input)?;
+        let reported: Vec<CommandProblem> = serde_json::from_slice(&output)
+            .context("Failed to parse output as a JSON array of problems")?;
+        Ok(reported
+            .into_iter()
+            .map(|problem| PluginReport {
+                message: problem.message,
+                package: problem.package,
+                details: problem.details,
+                severity: match problem.severity {
+                    CommandSeverity::Error => Severity::Error,
+                    CommandSeverity::Warning => Severity::Warning,
+                },
+            })
+            .collect())
+    }
+}
+
+/// Runs `command` from `dir`, passing `input` on stdin and returning what it writes to stdout.
+fn run_command(command: &[String], dir: Option<&Path>, input: &[u8]) -> Result<Vec<u8>> {
+    let Some((program, args)) = command.split_first() else {
+        bail!("Empty command");
+    };
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Failed to run `{program}`"))?;
+    // Write from a separate thread so that a command that writes output before it has read all of
+    // its input can't deadlock us.
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_owned();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output()?;
+    // If the command exits without reading its input, then the write fails, which is fine.
+    let _ = writer.join();
+    if !output.status.success() {
+        bail!(
+            "`{program}` failed with {}:\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(output.stdout)
+}
+
+impl FixProvider for FixConfig {
+    fn fixes(&self, problem: &ProblemInfo) -> Vec<Box<dyn PluginFix>> {
+        if !self.problems.is_empty() && !self.problems.iter().any(|kind| kind == problem.kind) {
+            return Vec::new();
+        }
+        vec![Box::new(CommandFix {
+            config: self.clone(),
+            problem: problem.clone(),
+        })]
+    }
+}
+
+/// A fix implemented by an external command.
+struct CommandFix {
+    config: FixConfig,
+    problem: ProblemInfo,
+}
+
+#[derive(Serialize)]
+struct FixInput<'a> {
+    problem: &'a ProblemInfo,
+    config: &'a str,
+    comment: Option<&'a str>,
+    preview: bool,
+}
+
+impl PluginFix for CommandFix {
+    fn title(&self) -> String {
+        self.config.title.clone()
+    }
+
+    fn id(&self) -> String {
+        let mut id = format!("custom:{}:{}", self.config.name, self.problem.kind);
+        if let Some(package) = &self.problem.package {
+            id.push(':');
+            id.push_str(package);
+        }
+        id
+    }
+
+    fn help(&self) -> String {
+        match &self.config.help {
+            Some(help) => help.clone(),
+            None => format!("Runs `{}`", self.config.command.join(" ")),
+        }
+    }
+
+    fn apply(&self, config: &str, comment: Option<&str>, preview: bool) -> Result<Option<String>> {
+        let input = serde_json::to_vec(&FixInput {
+            problem: &self.problem,
+            config,
+            comment,
+            preview,
+        })?;
+        let output = run_command(&self.config.command, self.config.dir.as_deref(), &input)?;
+        let toml = String::from_utf8(output).context("Fix produced invalid UTF-8")?;
+        Ok(Some(toml).filter(|toml| !toml.trim().is_empty()))
+    }
+}
+
+/// Adapts a fix from a `FixProvider` so that it can be offered alongside the built-in fixes.
+struct ProvidedFix(Box<dyn PluginFix>);
+
+impl Edit for ProvidedFix {
+    fn title(&self) -> String {
+        self.0.title()
+    }
+
+    fn id(&self) -> String {
+        self.0.id()
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        self.0.help().into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let toml = self
+            .0
+            .apply(&editor.to_toml(), opts.comment.as_deref(), !opts.is_final)
+            .with_context(|| format!("Fix `{}` failed", self.0.title()))?;
+        if let Some(toml) = toml {
+            *editor = ConfigEditor::from_toml_string(&toml)
+                .context("Fix produced an invalid cackle.toml")?;
+        }
+        Ok(())
+    }
+
+    fn resolve_problem_if_edit_is_empty(&self) -> bool {
+        // Running the command for every problem each time the config changes would be slow, and a
+        // fix that has side effects may not change the config at all.
+        false
+    }
+}
+
+impl From<&Problem> for ProblemInfo {
+    fn from(problem: &Problem) -> Self {
+        Self {
+            kind: problem.kind_name(),
+            package: problem.pkg_id().map(|pkg_id| pkg_id.name_str().to_owned()),
+            plugin: match problem {
+                Problem::PluginProblem(info) => Some(info.plugin.to_string()),
+                _ => None,
+            },
+            message: problem.to_string(),
+            details: format!("{problem:#}"),
+            artifacts: problem.artifacts().into_iter().cloned().collect(),
+        }
+    }
+}
+
+impl From<&ApiUsages> for PluginApiUsages {
+    fn from(api_usages: &ApiUsages) -> Self {
+        Self {
+            package: api_usages.pkg_id.name_str().to_owned(),
+            perm_sel: api_usages.perm_sel().to_string(),
+            api: api_usages.api_name.to_string(),
+            usages: api_usages.usages.iter().map(PluginApiUsage::from).collect(),
+        }
+    }
+}
+
+impl From<&ApiUsage> for PluginApiUsage {
+    fn from(usage: &ApiUsage) -> Self {
+        Self {
+            source_location: usage.source_location.to_string(),
+            from: usage.from.to_string(),
+            to: usage.to_name.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CheckPlugin;
+    use super::CommandPlugin;
+    use super::FixProvider;
+    use super::PluginFix;
+    use super::PluginInputs;
+    use super::PluginReport;
+    use super::ProblemInfo;
+    use crate::config::PluginConfig;
+    use crate::config_editor::ConfigEditor;
+    use crate::config_editor::EditOpts;
+    use crate::crate_index::testing::index_with_package_names;
+    use crate::crate_index::testing::pkg_id;
+    use crate::problem::PluginProblem;
+    use crate::problem::Problem;
+    use crate::problem::ProblemList;
+    use crate::problem::Severity;
+    use crate::symbol_graph::ScanOutputs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn run_command_plugin(script: &str) -> anyhow::Result<ProblemList> {
+        run_plugin(&CommandPlugin {
+            name: Arc::from("test-plugin"),
+            config: PluginConfig {
+                command: vec!["sh".to_owned(), "-c".to_owned(), script.to_owned()],
+            },
+            dir: PathBuf::from("."),
+        })
+    }
+
+    fn run_plugin(plugin: &dyn CheckPlugin) -> anyhow::Result<ProblemList> {
+        let config = crate::config::testing::parse(
+            r#"
+            [api.fs]
+            include = ["std::fs"]
+
+            [pkg.crab1]
+            allow_apis = ["fs"]
+            "#,
+        )
+        .unwrap();
+        let crate_index = index_with_package_names(&["crab1"]);
+        let mut problems = ProblemList::default();
+        super::run_plugin(
+            plugin,
+            &PluginInputs {
+                scan_outputs: &ScanOutputs::default(),
+                crate_index: &crate_index,
+                config: &config,
+            },
+            &mut problems,
+        )?;
+        Ok(problems)
+    }
+
+    #[test]
+    fn command_plugin() {
+        let problems = run_command_plugin(
+            r#"grep -q '"allowed_apis":{"crab1":\["fs"\]' && echo '[
+                {"package": "crab1", "message": "Uses crypto", "severity": "warning"},
+                {"message": "Something else"}
+            ]'"#,
+        )
+        .unwrap();
+        assert_eq!(
+            problems.into_iter().cloned().collect::<Vec<_>>(),
+            vec![
+                Problem::PluginProblem(PluginProblem {
+                    plugin: Arc::from("test-plugin"),
+                    pkg_id: Some(pkg_id("crab1")),
+                    message: "Uses crypto".to_owned(),
+                    details: None,
+                    severity: Severity::Warning,
+                }),
+                Problem::PluginProblem(PluginProblem {
+                    plugin: Arc::from("test-plugin"),
+                    pkg_id: None,
+                    message: "Something else".to_owned(),
+                    details: None,
+                    severity: Severity::Error,
+                }),
+            ]
+        );
+
+        assert!(run_command_plugin("echo oops >&2; exit 1").is_err());
+        assert!(run_command_plugin(r#"echo '[{"package": "crab2", "message": "x"}]'"#).is_err());
+    }
+
+    struct AllowedApisPlugin;
+
+    impl CheckPlugin for AllowedApisPlugin {
+        fn name(&self) -> &str {
+            "allowed-apis"
+        }
+
+        fn check(&self, inputs: &PluginInputs) -> anyhow::Result<Vec<PluginReport>> {
+            Ok(inputs
+                .allowed_apis()
+                .into_iter()
+                .filter(|(perm_sel, _)| perm_sel == "crab1")
+                .map(|(perm_sel, apis)| PluginReport {
+                    message: format!("{perm_sel}: {}", apis.join(",")),
+                    package: Some(perm_sel),
+                    details: None,
+                    severity: Severity::Warning,
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn compiled_in_plugin() {
+        let problems = run_plugin(&AllowedApisPlugin).unwrap();
+        assert_eq!(
+            problems.into_iter().cloned().collect::<Vec<_>>(),
+            vec![Problem::PluginProblem(PluginProblem {
+                plugin: Arc::from("allowed-apis"),
+                pkg_id: Some(pkg_id("crab1")),
+                message: "crab1: fs".to_owned(),
+                details: None,
+                severity: Severity::Warning,
+            })]
+        );
+    }
+
+    struct AppendComment;
+
+    impl PluginFix for AppendComment {
+        fn title(&self) -> String {
+            "Append a comment".to_owned()
+        }
+
+        fn id(&self) -> String {
+            "append-comment".to_owned()
+        }
+
+        fn help(&self) -> String {
+            String::new()
+        }
+
+        fn apply(
+            &self,
+            config: &str,
+            comment: Option<&str>,
+            _preview: bool,
+        ) -> anyhow::Result<Option<String>> {
+            Ok(comment.map(|comment| format!("{config}# {comment}\n")))
+        }
+    }
+
+    struct RegisteredProvider;
+
+    impl FixProvider for RegisteredProvider {
+        fn fixes(&self, problem: &ProblemInfo) -> Vec<Box<dyn PluginFix>> {
+            // Registration is process-wide, so only offer fixes for problems from this test.
+            if problem.plugin.as_deref() != Some("registered-fix-test") {
+                return Vec::new();
+            }
+            vec![Box::new(AppendComment)]
+        }
+    }
+
+    #[test]
+    fn registered_fix_provider() {
+        super::register_fix_provider(RegisteredProvider);
+        let config = crate::config::testing::parse("").unwrap();
+        let problem = Problem::PluginProblem(PluginProblem {
+            plugin: Arc::from("registered-fix-test"),
+            pkg_id: None,
+            message: "Something".to_owned(),
+            details: None,
+            severity: Severity::Error,
+        });
+        let fixes = crate::config_editor::fixes_for_problem(&problem, &config);
+        let fix = fixes.last().unwrap();
+        assert_eq!(fix.id(), "append-comment");
+
+        let mut editor = ConfigEditor::from_toml_string("").unwrap();
+        fix.apply(
+            &mut editor,
+            &EditOpts {
+                comment: Some("hello".to_owned()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(editor.to_toml(), "# hello\n");
+
+        assert!(
+            crate::config_editor::fixes_for_problem(&Problem::SelectSandbox, &config)
+                .iter()
+                .all(|fix| fix.id() != "append-comment")
+        );
+    }
+
+    #[test]
+    fn command_fix() {
+        let config = crate::config::testing::parse(
+            r##"
+            [fix.add-comment]
+            title = "Add a comment"
+            problems = ["is_proc_macro"]
+            command = ["sh", "-c", '''
+                input=$(cat)
+                echo "# $(echo "$input" | grep -o '"message":"[^"]*"')"
+                echo "# preview=$(echo "$input" | grep -o '"preview":[a-z]*' | cut -d: -f2)"
+            ''']
+            "##,
+        )
+        .unwrap();
+        let problem = Problem::IsProcMacro(pkg_id("crab1"));
+        let fixes = crate::config_editor::fixes_for_problem(&problem, &config);
+        let fix = fixes.last().unwrap();
+        assert_eq!(fix.title(), "Add a comment");
+
+        let mut editor = ConfigEditor::from_toml_string("").unwrap();
+        fix.apply(
+            &mut editor,
+            &EditOpts {
+                is_final: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            editor.to_toml(),
+            "# \"message\":\"`crab1` is a proc macro\"\n# preview=false\n"
+        );
+
+        let mut editor = ConfigEditor::from_toml_string("").unwrap();
+        fix.apply(&mut editor, &Default::default()).unwrap();
+        assert!(editor.to_toml().contains("preview=true"));
+
+        // The fix is only offered for the configured kinds of problem.
+        assert!(
+            crate::config_editor::fixes_for_problem(&Problem::SelectSandbox, &config)
+                .iter()
+                .all(|fix| fix.title() != "Add a comment")
+        );
+    }
+}
@@ -0,0 +1,493 @@
+//! Estimates the impact of adding a dependency without building anything. We resolve what the
+//! dependency tree would be by running `cargo metadata` on a temporary package that depends on the
+//! requested packages, then report the packages that aren't already in our tree and any config
+//! changes that they're likely to need.
+
+use crate::allowed::is_granted;
+use crate::capabilities::CapabilityManifest;
+use crate::config::permissions::PermSel;
+use crate::config::ApiName;
+use crate::config::Config;
+use crate::config::PackageConfig;
+use crate::crate_index::CrateIndex;
+use crate::proxy::cargo::NetworkMode;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use cargo_metadata::semver::Version;
+use clap::Parser;
+use fxhash::FxHashSet;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The name of the temporary package that depends on the packages being added.
+const PREFLIGHT_PKG_NAME: &str = "cackle-preflight";
+
+/// APIs that popular packages are known to use, at least with their commonly enabled features.
+/// Packages can declare their needs more precisely by publishing a capability manifest.
+const KNOWN_PACKAGE_APIS: &[(&str, &[&str])] = &[
+    ("async-std", &["fs", "net"]),
+    ("cc", &["process"]),
+    ("cmake", &["process"]),
+    ("curl", &["net"]),
+    ("duct", &["process"]),
+    ("fs_extra", &["fs"]),
+    ("git2", &["fs", "net"]),
+    ("hyper", &["net"]),
+    ("memmap2", &["fs"]),
+    ("mio", &["net"]),
+    ("notify", &["fs"]),
+    ("pkg-config", &["process"]),
+    ("reqwest", &["net"]),
+    ("rusqlite", &["fs"]),
+    ("socket2", &["net"]),
+    ("tempfile", &["fs"]),
+    ("tokio", &["fs", "net", "process"]),
+    ("ureq", &["net"]),
+    ("walkdir", &["fs"]),
+    ("which", &["fs"]),
+];
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct PreflightOptions {
+    /// A package to add, optionally with a version requirement. e.g. `foo@1.2`. May be repeated.
+    #[clap(long, required = true, value_name = "PACKAGE[@VERSION]")]
+    add: Vec<String>,
+}
+
+/// A package that would be added to the dependency tree.
+#[derive(Debug, Default)]
+struct NewPackage {
+    name: String,
+    version: Option<Version>,
+    is_proc_macro: bool,
+    has_build_script: bool,
+    /// The capability manifest that the package publishes, if any.
+    manifest: Option<CapabilityManifest>,
+    /// APIs defined by the package's `cackle/export.toml`.
+    exported_apis: Vec<ApiName>,
+    /// APIs that the package is known to use from `KNOWN_PACKAGE_APIS`.
+    known_apis: Vec<ApiName>,
+}
+
+/// Prints the packages that adding the packages in `options` would bring in and the config changes
+/// that they're likely to need.
+pub(crate) fn print(
+    config: &Config,
+    crate_index: &CrateIndex,
+    target_dir: &Path,
+    target: Option<&str>,
+    options: &PreflightOptions,
+) -> Result<()> {
+    let deps = options
+        .add
+        .iter()
+        .map(|spec| parse_spec(spec))
+        .collect::<Result<Vec<_>>>()?;
+    let new_packages = resolve_new_packages(crate_index, target_dir, target, &deps)?;
+    print!(
+        "{}",
+        report(config, crate_index, &options.add, &new_packages)
+    );
+    Ok(())
+}
+
+/// Parses a spec such as `foo@1.2` into a package name and version requirement.
+fn parse_spec(spec: &str) -> Result<(&str, &str)> {
+    let (name, version_req) = spec.split_once('@').unwrap_or((spec, "*"));
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("Invalid package name in `{spec}`");
+    }
+    cargo_metadata::semver::VersionReq::parse(version_req)
+        .with_context(|| format!("Invalid version requirement in `{spec}`"))?;
+    Ok((name, version_req))
+}
+
+/// Returns the directory in which we create a package that depends on the packages being checked.
+pub(crate) fn preflight_dir(target_dir: &Path) -> PathBuf {
+    target_dir.join("cackle").join("preflight")
+}
+
+/// Determines which packages would be added to our dependency tree by depending on `deps`.
+fn resolve_new_packages(
+    crate_index: &CrateIndex,
+    target_dir: &Path,
+    target: Option<&str>,
+    deps: &[(&str, &str)],
+) -> Result<Vec<NewPackage>> {
+    // The package goes in our target directory, so that cargo finds the same config, e.g. source
+    // replacement, as it does for our workspace.
+    let dir = preflight_dir(target_dir);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)
+            .with_context(|| format!("Failed to remove `{}`", dir.display()))?;
+    }
+    std::fs::create_dir_all(dir.join("src"))
+        .with_context(|| format!("Failed to create `{}`", dir.display()))?;
+    let mut manifest = format!(
+        "[package]\nname = \"{PREFLIGHT_PKG_NAME}\"\nversion = \"0.0.0\"\n\n[workspace]\n\n\
+         [dependencies]\n"
+    );
+    for (name, version_req) in deps {
+        writeln!(manifest, "{name} = {}", toml::Value::from(*version_req))?;
+    }
+    crate::fs::write(dir.join("Cargo.toml"), manifest)?;
+    crate::fs::write(dir.join("src").join("lib.rs"), "")?;
+    // Starting from our lockfile means that cargo keeps the versions that we already use where it
+    // can, just as it would if the dependency were added to our workspace.
+    if let Some(lock) = crate_index
+        .workspace_roots
+        .first()
+        .map(|root| root.join("Cargo.lock"))
+        .filter(|lock| lock.exists())
+    {
+        std::fs::copy(&lock, dir.join("Cargo.lock"))
+            .with_context(|| format!("Failed to copy `{}`", lock.display()))?;
+    }
+    let platform = match target {
+        Some(target) => target.to_owned(),
+        None => crate::proxy::host_triple(Path::new("rustc"))?,
+    };
+    // The lockfile needs updating, so we can't honour `--frozen`, but we can still avoid the
+    // network.
+    let network_mode = match crate_index.network_mode {
+        NetworkMode::Frozen => NetworkMode::Offline,
+        mode => mode,
+    };
+    let metadata = crate::crate_index::cargo_metadata(
+        &dir.join("Cargo.toml"),
+        network_mode,
+        &["--filter-platform".to_owned(), platform],
+    )?;
+    let resolved: FxHashSet<&cargo_metadata::PackageId> = metadata
+        .resolve
+        .as_ref()
+        .map(|resolve| resolve.nodes.iter().map(|node| &node.id).collect())
+        .unwrap_or_default();
+    let existing: FxHashSet<(&str, &Version)> = crate_index
+        .package_ids()
+        .map(|pkg_id| (pkg_id.unqualified_name(), pkg_id.version()))
+        .collect();
+    let mut new_packages = Vec::new();
+    for package in &metadata.packages {
+        if !resolved.contains(&package.id)
+            || package.name == PREFLIGHT_PKG_NAME
+            || existing.contains(&(package.name.as_str(), &package.version))
+        {
+            continue;
+        }
+        let pkg_dir = package
+            .manifest_path
+            .parent()
+            .ok_or_else(|| anyhow!("Package `{}` has no directory", package.name))?
+            .as_std_path();
+        let has_kind = |wanted: &str| {
+            package
+                .targets
+                .iter()
+                .any(|target| target.kind.iter().any(|kind| kind == wanted))
+        };
+        new_packages.push(NewPackage {
+            name: package.name.clone(),
+            version: Some(package.version.clone()),
+            is_proc_macro: has_kind("proc-macro"),
+            has_build_script: has_kind("custom-build"),
+            manifest: crate::capabilities::load_manifest(pkg_dir).with_context(|| {
+                format!("Invalid capability manifest for package `{}`", package.name)
+            })?,
+            exported_apis: crate::config::exported_api_names(pkg_dir).with_context(|| {
+                format!("Invalid cackle/export.toml for package `{}`", package.name)
+            })?,
+            known_apis: known_apis(&package.name),
+        });
+    }
+    new_packages.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+    Ok(new_packages)
+}
+
+fn known_apis(pkg_name: &str) -> Vec<ApiName> {
+    KNOWN_PACKAGE_APIS
+        .iter()
+        .filter(|(name, _)| *name == pkg_name)
+        .flat_map(|(_, apis)| apis.iter().map(|api| ApiName::new(api)))
+        .collect()
+}
+
+fn report(
+    config: &Config,
+    crate_index: &CrateIndex,
+    specs: &[String],
+    new_packages: &[NewPackage],
+) -> String {
+    let mut out = String::new();
+    let specs = specs
+        .iter()
+        .map(|spec| format!("`{spec}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if new_packages.is_empty() {
+        let _ = writeln!(out, "Adding {specs} wouldn't add any new packages");
+        return out;
+    }
+    let _ = writeln!(
+        out,
+        "Adding {specs} would add {} new package{}:",
+        new_packages.len(),
+        if new_packages.len() == 1 { "" } else { "s" }
+    );
+    for package in new_packages {
+        let _ = write!(out, "  {}", package.name);
+        if let Some(version) = &package.version {
+            let _ = write!(out, " {version}");
+        }
+        if package.is_proc_macro {
+            let _ = write!(out, " (proc macro)");
+        }
+        out.push('\n');
+        if let Some(manifest) = &package.manifest {
+            let _ = writeln!(
+                out,
+                "    declares APIs: {}",
+                join_or_none(&manifest.allow_apis)
+            );
+        } else if !package.known_apis.is_empty() {
+            let _ = writeln!(out, "    known to use: {}", join(&package.known_apis));
+        }
+        if !package.exported_apis.is_empty() {
+            let _ = writeln!(out, "    exports APIs: {}", join(&package.exported_apis));
+        }
+        if package.has_build_script {
+            let _ = writeln!(out, "    has a build script");
+        }
+    }
+    let changes = needed_changes(config, crate_index, new_packages);
+    out.push('\n');
+    if changes.is_empty() {
+        let _ = writeln!(
+            out,
+            "No config changes are known to be needed. A full check may still find some."
+        );
+        return out;
+    }
+    let _ = writeln!(out, "Likely config changes:");
+    for (table, settings) in &changes {
+        let _ = writeln!(out, "[{table}]");
+        for setting in settings {
+            let _ = writeln!(out, "{setting}");
+        }
+    }
+    out
+}
+
+/// Returns the settings that would likely need to be added for `new_packages`, keyed by table.
+fn needed_changes(
+    config: &Config,
+    crate_index: &CrateIndex,
+    new_packages: &[NewPackage],
+) -> BTreeMap<String, Vec<String>> {
+    let mut changes: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for package in new_packages {
+        let primary = PermSel::for_primary(package.name.as_str());
+        let build = PermSel::for_build_script(package.name.as_str());
+        let mut add = |perm_sel: &PermSel, setting: String| {
+            changes
+                .entry(format!("pkg.{perm_sel}"))
+                .or_default()
+                .push(setting);
+        };
+        let manifest = package.manifest.as_ref();
+        let expected_apis = manifest.map_or(&package.known_apis, |manifest| &manifest.allow_apis);
+        let missing = |perm_sel: &PermSel, apis: &[ApiName]| -> Vec<ApiName> {
+            apis.iter()
+                .filter(|api| {
+                    // APIs that aren't defined in our config aren't checked.
+                    config.raw.apis.contains_key(*api)
+                        && !is_granted(config, crate_index, perm_sel, &|pkg_config| {
+                            pkg_config.allow_apis.contains(*api)
+                        })
+                })
+                .cloned()
+                .collect()
+        };
+        let missing_apis = missing(&primary, expected_apis);
+        if !missing_apis.is_empty() {
+            add(
+                &primary,
+                format!("allow_apis = {}", toml_array(&missing_apis)),
+            );
+        }
+        let permits = |perm_sel: &PermSel, grants: &dyn Fn(&PackageConfig) -> bool| {
+            is_granted(config, crate_index, perm_sel, grants)
+        };
+        if manifest.is_some_and(|manifest| manifest.allow_unsafe)
+            && !permits(&primary, &|pkg_config| pkg_config.allow_unsafe)
+        {
+            add(&primary, "allow_unsafe = true".to_owned());
+        }
+        if package.is_proc_macro && !permits(&primary, &|pkg_config| pkg_config.allow_proc_macro) {
+            add(&primary, "allow_proc_macro = true".to_owned());
+        }
+        if !package.exported_apis.is_empty()
+            && !config
+                .permissions_no_inheritance
+                .get(&primary)
+                .is_some_and(|pkg_config| pkg_config.import.is_some())
+        {
+            add(
+                &primary,
+                format!("import = {}", toml_array(&package.exported_apis)),
+            );
+        }
+        if let Some(build_caps) = manifest.and_then(|manifest| manifest.build.as_ref()) {
+            let missing_apis = missing(&build, &build_caps.allow_apis);
+            if !missing_apis.is_empty() {
+                add(
+                    &build,
+                    format!("allow_apis = {}", toml_array(&missing_apis)),
+                );
+            }
+        }
+        if package.has_build_script
+            && config.raw.common.explicit_build_scripts
+            && !config
+                .permissions_no_inheritance
+                .packages
+                .contains_key(&build)
+            && !changes.contains_key(&format!("pkg.{build}"))
+        {
+            // An empty table is enough to permit the build script.
+            changes.insert(format!("pkg.{build}"), Vec::new());
+        }
+    }
+    changes
+}
+
+fn join(apis: &[ApiName]) -> String {
+    apis.iter()
+        .map(ApiName::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn join_or_none(apis: &[ApiName]) -> String {
+    if apis.is_empty() {
+        "none".to_owned()
+    } else {
+        join(apis)
+    }
+}
+
+fn toml_array(apis: &[ApiName]) -> toml::Value {
+    toml::Value::Array(
+        apis.iter()
+            .map(|api| toml::Value::from(api.to_string()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capabilities::BuildCapabilities;
+
+    #[test]
+    fn spec_parsing() {
+        assert_eq!(parse_spec("foo@1.2").unwrap(), ("foo", "1.2"));
+        assert_eq!(parse_spec("foo-bar").unwrap(), ("foo-bar", "*"));
+        assert!(parse_spec("foo@not-a-version").is_err());
+        assert!(parse_spec("foo\"@1").is_err());
+        assert!(parse_spec("@1").is_err());
+    }
+
+    #[test]
+    fn changes_for_new_packages() {
+        let config = crate::config::testing::parse(
+            r#"
+            explicit_build_scripts = true
+
+            [api.net]
+            include = ["std::net"]
+
+            [api.fs]
+            include = ["std::fs"]
+
+            [api.process]
+            include = ["std::process"]
+
+            [defaults.third_party]
+            allow_apis = ["fs"]
+            "#,
+        )
+        .unwrap();
+        let crate_index = crate::crate_index::testing::index_with_package_names(&[]);
+        let new_packages = [
+            NewPackage {
+                name: "tokio".to_owned(),
+                known_apis: known_apis("tokio"),
+                ..NewPackage::default()
+            },
+            NewPackage {
+                name: "foo".to_owned(),
+                has_build_script: true,
+                manifest: Some(CapabilityManifest {
+                    version: 1,
+                    allow_apis: vec![ApiName::new("net"), ApiName::new("unknown")],
+                    build: Some(BuildCapabilities {
+                        allow_apis: vec![ApiName::new("process")],
+                        ..BuildCapabilities::default()
+                    }),
+                    ..CapabilityManifest::default()
+                }),
+                exported_apis: vec![ApiName::new("foo_net")],
+                // The manifest takes precedence.
+                known_apis: vec![ApiName::new("process")],
+                ..NewPackage::default()
+            },
+            NewPackage {
+                name: "bar-derive".to_owned(),
+                is_proc_macro: true,
+                has_build_script: true,
+                ..NewPackage::default()
+            },
+        ];
+        let report = report(
+            &config,
+            &crate_index,
+            &["tokio".to_owned(), "foo@1".to_owned()],
+            &new_packages,
+        );
+        assert_eq!(
+            report,
+            "\
+Adding `tokio`, `foo@1` would add 3 new packages:
+  tokio
+    known to use: fs, net, process
+  foo
+    declares APIs: net, unknown
+    exports APIs: foo_net
+    has a build script
+  bar-derive (proc macro)
+    has a build script
+
+Likely config changes:
+[pkg.bar-derive]
+allow_proc_macro = true
+[pkg.bar-derive.build]
+[pkg.foo]
+allow_apis = [\"net\"]
+import = [\"foo_net\"]
+[pkg.foo.build]
+allow_apis = [\"process\"]
+[pkg.tokio]
+allow_apis = [\"net\", \"process\"]
+"
+        );
+    }
+}
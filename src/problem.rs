@@ -4,29 +4,40 @@
 use crate::checker::ApiUsage;
 use crate::config::permissions::PermSel;
 use crate::config::permissions::PermissionScope;
+use crate::config::permissions::Permissions;
 use crate::config::ApiConfig;
 use crate::config::ApiName;
 use crate::config::ApiPath;
+use crate::config::DowngradableProblem;
+use crate::config::SandboxConfig;
 use crate::crate_index::CrateKind;
 use crate::crate_index::CrateSel;
 use crate::crate_index::PackageId;
+use crate::link_info::Artifact;
+use crate::location::SourceLocation;
 use crate::names::SymbolOrDebugName;
 use crate::proxy::rpc::BinExecutionOutput;
+use crate::proxy::rpc::CompilationFailure;
 use crate::proxy::rpc::UnsafeUsage;
 use crate::symbol::Symbol;
+use crate::test_output::FailedTest;
+use serde::Deserialize;
+use serde::Serialize;
 use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 
+pub(crate) mod help;
+
 #[derive(Default, Debug, PartialEq, Clone)]
 pub(crate) struct ProblemList {
     problems: Vec<Problem>,
 }
 
 #[must_use]
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) enum Problem {
     Message(String),
     MissingConfiguration(PathBuf),
@@ -34,26 +45,48 @@ pub(crate) enum Problem {
     DisallowedUnsafe(UnsafeUsage),
     IsProcMacro(PackageId),
     DisallowedApiUsage(ApiUsages),
+    DeniedPathUsage(ApiUsages),
     OffTreeApiUsage(OffTreeApiUsage),
     ExecutionFailed(BinExecutionFailed),
+    WaitingForInput(BinExecutionFailed),
+    TestFailed(TestFailure),
     DisallowedBuildInstruction(DisallowedBuildInstruction),
     UnusedPackageConfig(PermSel),
     UnusedAllowApi(UnusedAllowApi),
+    AllowApiUsedOnlyByDeadCode(UnusedAllowApi),
     SelectSandbox,
-    ImportStdApi(ApiName),
+    SelectApis(ApiChecklist),
     AvailableApi(AvailableApi),
     PossibleExportedApi(PossibleExportedApi),
     UnusedSandboxConfiguration(PermSel),
     NewConfigVersionAvailable(i64),
+    CapabilityManifestViolation(CapabilityManifestViolation),
+    UnanalysedStaticLib(CrateSel),
+    PluginProblem(PluginProblem),
+    CompilationFailed(CompilationFailure),
+    PackageNotBuilt(PermSel),
+    GeneratedCode(GeneratedCode),
+    /// Features from `common.features` that aren't being enabled due to command-line flags.
+    IgnoredConfigFeatures(Vec<String>),
+    CargoDenyViolation(CargoDenyViolation),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) struct BinExecutionFailed {
     pub(crate) crate_sel: CrateSel,
     pub(crate) output: BinExecutionOutput,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// A single test that failed when its test binary was run in a sandbox.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct TestFailure {
+    pub(crate) crate_sel: CrateSel,
+    pub(crate) test: FailedTest,
+    /// The sandbox config for the test binary, not including any override for this test.
+    pub(crate) sandbox_config: SandboxConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) struct ApiUsages {
     pub(crate) pkg_id: PackageId,
     pub(crate) scope: PermissionScope,
@@ -61,34 +94,96 @@ pub(crate) struct ApiUsages {
     pub(crate) usages: Vec<ApiUsage>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) struct OffTreeApiUsage {
     pub(crate) usages: ApiUsages,
     pub(crate) referenced_pkg_id: PackageId,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) struct UnusedAllowApi {
     pub(crate) perm_sel: PermSel,
     pub(crate) apis: Vec<ApiName>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A package that the cargo-deny config referenced by `[deny] cargo_deny` doesn't permit.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct CargoDenyViolation {
+    pub(crate) pkg_id: PackageId,
+    /// Why the package isn't permitted, e.g. "is banned by cargo-deny config".
+    pub(crate) reason: String,
+    /// cargo-deny can be configured to warn rather than fail for some checks.
+    pub(crate) severity: Severity,
+}
+
+/// A crate that was compiled from sources that its build script generated.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct GeneratedCode {
+    pub(crate) crate_sel: CrateSel,
+    pub(crate) paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) struct DisallowedBuildInstruction {
     pub(crate) pkg_id: PackageId,
     pub(crate) instruction: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A package was observed doing something that its capability manifest doesn't declare.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct CapabilityManifestViolation {
+    pub(crate) crate_sel: CrateSel,
+    pub(crate) capability: UndeclaredCapability,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) enum UndeclaredCapability {
+    Api(ApiName),
+    Unsafe,
+    BuildInstruction(String),
+}
+
+/// A problem reported by a check plugin.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct PluginProblem {
+    pub(crate) plugin: Arc<str>,
+    pub(crate) pkg_id: Option<PackageId>,
+    pub(crate) message: String,
+    pub(crate) details: Option<String>,
+    pub(crate) severity: Severity,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) struct AvailableApi {
     pub(crate) pkg_id: PackageId,
     pub(crate) api: ApiName,
     pub(crate) config: ApiConfig,
 }
 
+/// A list of APIs from which the user can select several to import at once, rather than being
+/// asked about each API separately.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct ApiChecklist {
+    pub(crate) entries: Vec<ChecklistEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct ChecklistEntry {
+    pub(crate) api: ChecklistApi,
+    pub(crate) selected: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) enum ChecklistApi {
+    /// An API that's built into Cackle.
+    Std(ApiName),
+    /// An API exported by a package in the dependency tree.
+    Exported(AvailableApi),
+}
+
 /// The name of a top-level module in a crate that matches the name of a restricted API. For
 /// example, if there's an API named "fs" and we find a crate with a module named "fs".
-#[derive(Clone, Hash, PartialEq, Eq, Debug)]
+#[derive(Clone, Hash, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub(crate) struct PossibleExportedApi {
     pub(crate) pkg_id: PackageId,
     pub(crate) api: ApiName,
@@ -98,7 +193,7 @@ pub(crate) struct PossibleExportedApi {
 impl PossibleExportedApi {
     pub(crate) fn api_path(&self) -> ApiPath {
         ApiPath {
-            prefix: Arc::from(format!("{}::{}", self.pkg_id.name_str(), self.api).as_str()),
+            prefix: Arc::from(format!("{}::{}", self.pkg_id.unqualified_name(), self.api).as_str()),
         }
     }
 }
@@ -149,8 +244,8 @@ impl<'a> IntoIterator for &'a ProblemList {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum Severity {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum Severity {
     Warning,
     Error,
 }
@@ -160,23 +255,74 @@ impl Problem {
         Self::Message(text.into())
     }
 
-    pub(crate) fn severity(&self) -> Severity {
+    /// Returns the severity of this problem, taking into account any downgrades configured for the
+    /// package that the problem relates to.
+    pub(crate) fn severity(&self, permissions: &Permissions) -> Severity {
+        if self.is_downgraded(permissions) {
+            return Severity::Warning;
+        }
         match self {
+            Problem::PluginProblem(info) => info.severity,
+            Problem::CargoDenyViolation(info) => info.severity,
             Problem::UnusedAllowApi(..)
+            | Problem::AllowApiUsedOnlyByDeadCode(..)
+            | Problem::UnanalysedStaticLib(..)
             | Problem::UnusedPackageConfig(..)
+            | Problem::PackageNotBuilt(..)
+            | Problem::GeneratedCode(..)
+            | Problem::IgnoredConfigFeatures(..)
             | Problem::PossibleExportedApi(..)
             | Problem::NewConfigVersionAvailable(..)
-            | Problem::AvailableApi(..) => Severity::Warning,
+            | Problem::AvailableApi(..)
+            | Problem::SelectApis(..) => Severity::Warning,
             _ => Severity::Error,
         }
     }
 
+    /// Returns whether the configuration says that this problem should be downgraded to a warning.
+    pub(crate) fn is_downgraded(&self, permissions: &Permissions) -> bool {
+        let Some((perm_sel, kind)) = self.downgrade_selector() else {
+            return false;
+        };
+        permissions
+            .get(&perm_sel)
+            .is_some_and(|pkg_config| pkg_config.downgrade.contains(&kind))
+    }
+
+    /// Returns the permission selector and kind that would be used to downgrade this problem, if
+    /// it's a kind of problem that can be downgraded.
+    pub(crate) fn downgrade_selector(&self) -> Option<(PermSel, DowngradableProblem)> {
+        match self {
+            Problem::OffTreeApiUsage(info) => {
+                Some((info.usages.perm_sel(), DowngradableProblem::OffTreeApiUsage))
+            }
+            Problem::PossibleExportedApi(info) => Some((
+                PermSel::for_primary(info.pkg_id.pkg_name()),
+                DowngradableProblem::PossibleExportedApi,
+            )),
+            Problem::CapabilityManifestViolation(info) => Some((
+                PermSel::for_primary(info.crate_sel.pkg_name()),
+                DowngradableProblem::CapabilityManifestViolation,
+            )),
+            _ => None,
+        }
+    }
+
     /// Returns whether a retry on this problem needs to be sent to a subprocess.
     fn should_send_retry_to_subprocess(&self) -> bool {
-        matches!(
-            self,
-            &Problem::ExecutionFailed(..) | &Problem::DisallowedUnsafe(..)
-        )
+        match self {
+            Problem::ExecutionFailed(..)
+            | Problem::WaitingForInput(..)
+            | Problem::TestFailed(..)
+            | Problem::CompilationFailed(..)
+            | Problem::DisallowedUnsafe(..) => true,
+            // Use of unsafe is reported by a subprocess, so any manifest violation is reported
+            // alongside that and needs to be retried in the same way.
+            Problem::CapabilityManifestViolation(info) => {
+                info.capability == UndeclaredCapability::Unsafe
+            }
+            _ => false,
+        }
     }
 
     /// Returns `self` or a clone of `self` with any bits that aren't relevant for deduplication
@@ -189,6 +335,9 @@ impl Problem {
                 api_name: api_usage.api_name.clone(),
                 usages: Default::default(),
             }),
+            Problem::DeniedPathUsage(api_usage) => {
+                Problem::DeniedPathUsage(api_usage.with_usages(Default::default()))
+            }
             Problem::PossibleExportedApi(info) => {
                 Problem::PossibleExportedApi(PossibleExportedApi {
                     symbol: Symbol::borrowed(&[]),
@@ -199,11 +348,169 @@ impl Problem {
         }
     }
 
+    /// Returns a deduplication key that is the same for problems that differ only in which version
+    /// of a package they're for. Permissions apply to all versions of a package, so such problems
+    /// have the same fixes.
+    pub(crate) fn version_independent_key(&self) -> Problem {
+        match self.deduplication_key() {
+            Problem::DisallowedApiUsage(mut api_usage) => {
+                api_usage.pkg_id = api_usage.pkg_id.for_all_versions();
+                Problem::DisallowedApiUsage(api_usage)
+            }
+            Problem::DeniedPathUsage(mut api_usage) => {
+                api_usage.pkg_id = api_usage.pkg_id.for_all_versions();
+                Problem::DeniedPathUsage(api_usage)
+            }
+            Problem::DisallowedUnsafe(usage) => Problem::DisallowedUnsafe(UnsafeUsage {
+                crate_sel: CrateSel {
+                    pkg_id: usage.crate_sel.pkg_id.for_all_versions(),
+                    kind: usage.crate_sel.kind,
+                },
+                locations: Vec::new(),
+            }),
+            Problem::UsesBuildScript(pkg_id) => Problem::UsesBuildScript(pkg_id.for_all_versions()),
+            Problem::IsProcMacro(pkg_id) => Problem::IsProcMacro(pkg_id.for_all_versions()),
+            Problem::DisallowedBuildInstruction(info) => {
+                Problem::DisallowedBuildInstruction(DisallowedBuildInstruction {
+                    pkg_id: info.pkg_id.for_all_versions(),
+                    instruction: info.instruction,
+                })
+            }
+            Problem::PossibleExportedApi(info) => {
+                Problem::PossibleExportedApi(PossibleExportedApi {
+                    pkg_id: info.pkg_id.for_all_versions(),
+                    ..info
+                })
+            }
+            key => key,
+        }
+    }
+
     /// Merges `other` into `self`. Should only be called with two problems that are not equal, but
     /// which have equal deduplication_keys.
     pub(crate) fn merge(&mut self, other: Problem) {
-        if let (Problem::DisallowedApiUsage(a), Problem::DisallowedApiUsage(b)) = (self, other) {
-            a.merge(b);
+        match (self, other) {
+            (Problem::DisallowedApiUsage(a), Problem::DisallowedApiUsage(b))
+            | (Problem::DeniedPathUsage(a), Problem::DeniedPathUsage(b)) => a.merge(b),
+            (Problem::DisallowedUnsafe(a), Problem::DisallowedUnsafe(b)) => {
+                for location in b.locations {
+                    if !a.locations.contains(&location) {
+                        a.locations.push(location);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The names of each kind of problem, as returned by `kind_name`. The position of each name
+    /// determines the code returned by `code`, so new kinds should be added at the end.
+    pub(crate) const KIND_NAMES: &[&str] = &[
+        "message",
+        "missing_configuration",
+        "uses_build_script",
+        "disallowed_unsafe",
+        "is_proc_macro",
+        "disallowed_api_usage",
+        "denied_path_usage",
+        "off_tree_api_usage",
+        "execution_failed",
+        "waiting_for_input",
+        "test_failed",
+        "disallowed_build_instruction",
+        "unused_package_config",
+        "unused_allow_api",
+        "allow_api_used_only_by_dead_code",
+        "select_sandbox",
+        "select_apis",
+        "available_api",
+        "possible_exported_api",
+        "unused_sandbox_configuration",
+        "new_config_version_available",
+        "capability_manifest_violation",
+        "unanalysed_static_lib",
+        "plugin",
+        "compilation_failed",
+        "package_not_built",
+        "generated_code",
+        "ignored_config_features",
+        "cargo_deny_violation",
+    ];
+
+    /// Returns a name for the kind of this problem. Used to select problems from configuration and
+    /// when passing problems to plugins.
+    pub(crate) fn kind_name(&self) -> &'static str {
+        match self {
+            Problem::Message(..) => "message",
+            Problem::MissingConfiguration(..) => "missing_configuration",
+            Problem::UsesBuildScript(..) => "uses_build_script",
+            Problem::DisallowedUnsafe(..) => "disallowed_unsafe",
+            Problem::IsProcMacro(..) => "is_proc_macro",
+            Problem::DisallowedApiUsage(..) => "disallowed_api_usage",
+            Problem::DeniedPathUsage(..) => "denied_path_usage",
+            Problem::OffTreeApiUsage(..) => "off_tree_api_usage",
+            Problem::ExecutionFailed(..) => "execution_failed",
+            Problem::WaitingForInput(..) => "waiting_for_input",
+            Problem::TestFailed(..) => "test_failed",
+            Problem::DisallowedBuildInstruction(..) => "disallowed_build_instruction",
+            Problem::UnusedPackageConfig(..) => "unused_package_config",
+            Problem::PackageNotBuilt(..) => "package_not_built",
+            Problem::GeneratedCode(..) => "generated_code",
+            Problem::IgnoredConfigFeatures(..) => "ignored_config_features",
+            Problem::CargoDenyViolation(..) => "cargo_deny_violation",
+            Problem::UnusedAllowApi(..) => "unused_allow_api",
+            Problem::AllowApiUsedOnlyByDeadCode(..) => "allow_api_used_only_by_dead_code",
+            Problem::SelectSandbox => "select_sandbox",
+            Problem::SelectApis(..) => "select_apis",
+            Problem::AvailableApi(..) => "available_api",
+            Problem::PossibleExportedApi(..) => "possible_exported_api",
+            Problem::UnusedSandboxConfiguration(..) => "unused_sandbox_configuration",
+            Problem::NewConfigVersionAvailable(..) => "new_config_version_available",
+            Problem::CapabilityManifestViolation(..) => "capability_manifest_violation",
+            Problem::UnanalysedStaticLib(..) => "unanalysed_static_lib",
+            Problem::PluginProblem(..) => "plugin",
+            Problem::CompilationFailed(..) => "compilation_failed",
+        }
+    }
+
+    /// Returns a code identifying the kind of this problem, e.g. "CACKLE006". Used when reporting
+    /// problems in the same format as rustc reports errors.
+    pub(crate) fn code(&self) -> String {
+        let kind_name = self.kind_name();
+        let index = Self::KIND_NAMES
+            .iter()
+            .position(|name| *name == kind_name)
+            .unwrap_or_default();
+        format!("CACKLE{:03}", index + 1)
+    }
+
+    /// Returns the locations in source code that this problem relates to, if any.
+    pub(crate) fn source_locations(&self) -> Vec<&SourceLocation> {
+        match self {
+            Problem::DisallowedApiUsage(info) | Problem::DeniedPathUsage(info) => info
+                .usages
+                .iter()
+                .map(|usage| &usage.source_location)
+                .collect(),
+            Problem::OffTreeApiUsage(info) => info
+                .usages
+                .usages
+                .iter()
+                .map(|usage| &usage.source_location)
+                .collect(),
+            Problem::DisallowedUnsafe(usage) => usage.locations.iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the linker outputs (binaries, tests etc) in which this problem was found, if any.
+    pub(crate) fn artifacts(&self) -> Vec<&Artifact> {
+        match self {
+            Problem::DisallowedApiUsage(info) | Problem::DeniedPathUsage(info) => {
+                usages_artifacts(&info.usages)
+            }
+            Problem::OffTreeApiUsage(info) => usages_artifacts(&info.usages.usages),
+            _ => Vec::new(),
         }
     }
 
@@ -215,17 +522,28 @@ impl Problem {
             Problem::DisallowedUnsafe(d) => Some(d.crate_sel.pkg_id()),
             Problem::IsProcMacro(pkg_id) => Some(pkg_id),
             Problem::DisallowedApiUsage(d) => Some(&d.pkg_id),
+            Problem::DeniedPathUsage(d) => Some(&d.pkg_id),
             Problem::OffTreeApiUsage(d) => Some(&d.usages.pkg_id),
-            Problem::ExecutionFailed(d) => Some(d.crate_sel.pkg_id()),
+            Problem::ExecutionFailed(d) | Problem::WaitingForInput(d) => Some(d.crate_sel.pkg_id()),
+            Problem::TestFailed(d) => Some(d.crate_sel.pkg_id()),
             Problem::DisallowedBuildInstruction(d) => Some(&d.pkg_id),
             Problem::UnusedPackageConfig(_) => None,
+            Problem::PackageNotBuilt(_) => None,
+            Problem::GeneratedCode(info) => Some(info.crate_sel.pkg_id()),
+            Problem::IgnoredConfigFeatures(_) => None,
+            Problem::CargoDenyViolation(info) => Some(&info.pkg_id),
             Problem::UnusedAllowApi(_) => None,
+            Problem::AllowApiUsedOnlyByDeadCode(_) => None,
             Problem::SelectSandbox => None,
-            Problem::ImportStdApi(_) => None,
+            Problem::SelectApis(_) => None,
             Problem::AvailableApi(d) => Some(&d.pkg_id),
             Problem::PossibleExportedApi(d) => Some(&d.pkg_id),
             Problem::UnusedSandboxConfiguration(_) => None,
             Problem::NewConfigVersionAvailable(_) => None,
+            Problem::CapabilityManifestViolation(d) => Some(d.crate_sel.pkg_id()),
+            Problem::UnanalysedStaticLib(crate_sel) => Some(crate_sel.pkg_id()),
+            Problem::PluginProblem(info) => info.pkg_id.as_ref(),
+            Problem::CompilationFailed(info) => Some(info.crate_sel.pkg_id()),
         }
     }
 }
@@ -265,6 +583,22 @@ impl Display for Problem {
                 CrateSel::primary(pkg_name.clone())
             )?,
             Problem::DisallowedApiUsage(info) => info.fmt(f)?,
+            Problem::DeniedPathUsage(info) => {
+                let mut paths: Vec<String> =
+                    info.usages.iter().map(|u| u.to_name.to_string()).collect();
+                paths.sort();
+                paths.dedup();
+                write!(
+                    f,
+                    "`{}` uses denied path(s) `{}`",
+                    info.perm_sel(),
+                    paths.join("`, `")
+                )?;
+                if f.alternate() {
+                    writeln!(f)?;
+                    display_usages(f, &info.usages)?;
+                }
+            }
             Problem::OffTreeApiUsage(info) => {
                 write!(
                     f,
@@ -277,6 +611,31 @@ impl Display for Problem {
                 }
             }
             Problem::ExecutionFailed(info) => info.fmt(f)?,
+            Problem::WaitingForInput(info) => {
+                let pkg_id = &info.crate_sel.pkg_id;
+                match info.crate_sel.kind {
+                    CrateKind::Primary => write!(f, "Binary for package `{pkg_id}`")?,
+                    CrateKind::BuildScript => write!(f, "Build script for package `{pkg_id}`")?,
+                    CrateKind::Test => write!(f, "Test for package `{pkg_id}`")?,
+                }
+                write!(f, " is waiting for input")?;
+                if f.alternate() {
+                    info.fmt_output(f)?;
+                }
+            }
+            Problem::TestFailed(info) => {
+                write!(
+                    f,
+                    "Test `{}` for package `{}` failed",
+                    info.test.name, info.crate_sel.pkg_id
+                )?;
+                if f.alternate() {
+                    if let Some(denial) = info.test.sandbox_denial() {
+                        write!(f, "\nOutput suggests that the sandbox denied {denial}")?;
+                    }
+                    write!(f, "\n{}", info.test.output)?;
+                }
+            }
             Problem::DisallowedBuildInstruction(info) => {
                 write!(
                     f,
@@ -291,12 +650,72 @@ impl Display for Problem {
                     "Config supplied for package `{pkg_name}` not in dependency tree"
                 )?;
             }
+            Problem::IgnoredConfigFeatures(features) => {
+                write!(
+                    f,
+                    "Features from config not enabled: {}",
+                    features.join(", ")
+                )?;
+                if f.alternate() {
+                    write!(
+                        f,
+                        "\n`common.features` is ignored when --no-default-features or \
+                         --exact-features is passed. Add the features to --features if they're \
+                         wanted."
+                    )?;
+                }
+            }
+            Problem::CargoDenyViolation(info) => {
+                write!(f, "`{}` {}", info.pkg_id, info.reason)?;
+            }
+            Problem::PackageNotBuilt(pkg_name) => {
+                write!(
+                    f,
+                    "Config supplied for package `{pkg_name}`, which isn't built with the enabled \
+                     features"
+                )?;
+                if f.alternate() {
+                    write!(
+                        f,
+                        "\nThe package is in the dependency tree when all features are enabled, so \
+                         the config may still be needed for other builds. Its config wasn't \
+                         checked for unused permissions."
+                    )?;
+                }
+            }
             Problem::UnusedAllowApi(info) => info.fmt(f)?,
+            Problem::AllowApiUsedOnlyByDeadCode(info) => {
+                write!(
+                    f,
+                    "`pkg.{}` allows APIs that are only used by code that the linker discarded",
+                    info.perm_sel
+                )?;
+                if f.alternate() {
+                    writeln!(f, ":")?;
+                    for api in &info.apis {
+                        writeln!(f, "    {api}")?;
+                    }
+                    write!(
+                        f,
+                        "These APIs are candidates for removal, since no code that uses them ends \
+                         up in any binary"
+                    )?;
+                }
+            }
             Problem::MissingConfiguration(path) => {
                 write!(f, "Config file `{}` not found", path.display())?;
             }
             Problem::SelectSandbox => write!(f, "Select sandbox kind")?,
-            Problem::ImportStdApi(api) => write!(f, "Optionally import std API `{api}`")?,
+            Problem::SelectApis(checklist) => {
+                write!(f, "Select APIs to import")?;
+                if f.alternate() {
+                    writeln!(f)?;
+                    for entry in &checklist.entries {
+                        let mark = if entry.selected { 'x' } else { ' ' };
+                        writeln!(f, "  [{mark}] {}", entry.api)?;
+                    }
+                }
+            }
             Problem::AvailableApi(info) => {
                 write!(
                     f,
@@ -329,11 +748,126 @@ impl Display for Problem {
                      Perhaps you meant to configure `{crate_name}.build.sandbox`"
                 )?;
             }
+            Problem::CapabilityManifestViolation(info) => info.fmt(f)?,
+            Problem::PluginProblem(info) => {
+                write!(f, "[{}] {}", info.plugin, info.message)?;
+                if let (true, Some(details)) = (f.alternate(), &info.details) {
+                    write!(f, "\n{details}")?;
+                }
+            }
+            Problem::GeneratedCode(info) => {
+                write!(
+                    f,
+                    "`{}` includes code generated by its build script",
+                    info.crate_sel
+                )?;
+                if f.alternate() {
+                    writeln!(f, ":")?;
+                    for path in &info.paths {
+                        writeln!(f, "    {}", path.display())?;
+                    }
+                    write!(
+                        f,
+                        "Generated code isn't seen by anyone reviewing the package's source, so the \
+                         build script that generates it may warrant a closer look."
+                    )?;
+                }
+            }
+            Problem::UnanalysedStaticLib(crate_sel) => {
+                write!(
+                    f,
+                    "`{crate_sel}` is built as a static library, so its API usage can't be checked"
+                )?;
+                if f.alternate() {
+                    write!(
+                        f,
+                        "\nStatic libraries aren't linked by cargo, so we never see a binary to \
+                         analyse. API usage is checked for any binaries, including tests, that \
+                         link this package as an rlib."
+                    )?;
+                }
+            }
+            Problem::CompilationFailed(info) => {
+                write!(f, "`{}` failed to compile", info.crate_sel)?;
+                if !info.error_codes.is_empty() {
+                    write!(f, " ({})", info.error_codes.join(", "))?;
+                }
+                if f.alternate() {
+                    write!(f, "\n{}", info.rendered)?;
+                }
+            }
         }
         Ok(())
     }
 }
 
+impl ApiChecklist {
+    /// Returns a checklist of all built-in APIs, with none selected.
+    pub(crate) fn std_apis() -> Self {
+        Self {
+            entries: crate::config::built_in::get_built_ins()
+                .into_keys()
+                .map(|api| ChecklistEntry {
+                    api: ChecklistApi::Std(api),
+                    selected: false,
+                })
+                .collect(),
+        }
+    }
+
+    /// Replaces the `AvailableApi` problems in `problems` with a single checklist. If there's only
+    /// one such problem, `problems` is returned unchanged.
+    pub(crate) fn group_available_apis(problems: ProblemList) -> ProblemList {
+        let (available, mut others): (Vec<Problem>, Vec<Problem>) = problems
+            .take()
+            .into_iter()
+            .partition(|problem| matches!(problem, Problem::AvailableApi(..)));
+        if available.len() > 1 {
+            let entries = available
+                .into_iter()
+                .filter_map(|problem| match problem {
+                    Problem::AvailableApi(info) => Some(ChecklistEntry {
+                        api: ChecklistApi::Exported(info),
+                        selected: false,
+                    }),
+                    _ => None,
+                })
+                .collect();
+            others.push(Problem::SelectApis(ApiChecklist { entries }));
+        } else {
+            others.extend(available);
+        }
+        ProblemList { problems: others }
+    }
+
+    pub(crate) fn selected(&self) -> impl Iterator<Item = &ChecklistApi> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.selected)
+            .map(|entry| &entry.api)
+    }
+
+    /// Returns a copy of this checklist with the entry at `index` toggled.
+    pub(crate) fn with_toggled(&self, index: usize) -> Self {
+        let mut toggled = self.clone();
+        if let Some(entry) = toggled.entries.get_mut(index) {
+            entry.selected = !entry.selected;
+        }
+        toggled
+    }
+}
+
+impl Display for ChecklistApi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChecklistApi::Std(api) => write!(f, "{api}"),
+            ChecklistApi::Exported(info) => {
+                write!(f, "{}::{}", info.pkg_id.pkg_name(), info.api)
+            }
+        }
+    }
+}
+
 impl Display for ApiUsages {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if f.alternate() {
@@ -344,9 +878,16 @@ impl Display for ApiUsages {
             )?;
             display_usages(f, &self.usages)?;
         } else {
+            if self.scope == PermissionScope::ProcMacro {
+                return write!(
+                    f,
+                    "Proc macro `{}` uses the `{}` API at build time",
+                    self.pkg_id, self.api_name
+                );
+            }
             write!(f, "`{}` uses the `{}` API", self.pkg_id, self.api_name)?;
             match self.scope {
-                PermissionScope::All => {}
+                PermissionScope::All | PermissionScope::ProcMacro => {}
                 PermissionScope::Build => " in its build script".fmt(f)?,
                 PermissionScope::Test => " in its test(s)".fmt(f)?,
                 PermissionScope::FromBuild => {
@@ -361,6 +902,28 @@ impl Display for ApiUsages {
     }
 }
 
+impl Display for CapabilityManifestViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}` ", self.crate_sel)?;
+        match &self.capability {
+            UndeclaredCapability::Api(api) => write!(f, "uses the `{api}` API")?,
+            UndeclaredCapability::Unsafe => write!(f, "uses unsafe")?,
+            UndeclaredCapability::BuildInstruction(instruction) => {
+                write!(f, "emitted build instruction `{instruction}`")?
+            }
+        }
+        write!(f, ", which isn't declared in its capability manifest")?;
+        if f.alternate() {
+            write!(
+                f,
+                ". The package's cackle/capabilities.toml may be out of date, or the package may \
+                 be doing something that its authors didn't intend."
+            )?;
+        }
+        Ok(())
+    }
+}
+
 impl Display for UnusedAllowApi {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if f.alternate() {
@@ -390,24 +953,54 @@ impl Display for BinExecutionFailed {
             }
         }
         if f.alternate() {
-            write!(
-                f,
-                "\n{}{}",
-                String::from_utf8_lossy(&self.output.stderr),
-                String::from_utf8_lossy(&self.output.stdout)
-            )?;
-            if let Some(sandbox_display) = self.output.sandbox_config_display.as_ref() {
-                writeln!(f, "Sandbox config:\n{sandbox_display}",)?;
-            }
+            self.fmt_output(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl TestFailure {
+    /// Returns the sandbox config that the test ran with, including any override for the test.
+    pub(crate) fn effective_sandbox_config(&self) -> SandboxConfig {
+        let Some(test_config) = self.sandbox_config.per_test.get(&self.test.name) else {
+            return self.sandbox_config.clone();
+        };
+        let mut config = test_config.clone();
+        config.inherit(&self.sandbox_config);
+        config
+    }
+}
+
+impl BinExecutionFailed {
+    fn fmt_output(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "\n{}{}",
+            String::from_utf8_lossy(&self.output.stderr),
+            String::from_utf8_lossy(&self.output.stdout)
+        )?;
+        if let Some(sandbox_display) = self.output.sandbox_config_display.as_ref() {
+            writeln!(f, "Sandbox config:\n{sandbox_display}",)?;
         }
         Ok(())
     }
 }
 
+/// Returns the distinct artifacts in which `usages` were found.
+fn usages_artifacts(usages: &[ApiUsage]) -> Vec<&Artifact> {
+    let mut artifacts: Vec<&Artifact> = usages.iter().map(|u| &u.artifact).collect();
+    artifacts.sort();
+    artifacts.dedup();
+    artifacts
+}
+
 fn display_usages(
     f: &mut std::fmt::Formatter,
     usages: &Vec<ApiUsage>,
 ) -> Result<(), std::fmt::Error> {
+    for artifact in usages_artifacts(usages) {
+        writeln!(f, "  in {artifact}")?;
+    }
     let mut by_source_filename: BTreeMap<&Path, Vec<&ApiUsage>> = BTreeMap::new();
     for u in usages {
         by_source_filename
@@ -455,7 +1048,11 @@ impl std::hash::Hash for ApiUsages {
 
 impl ApiUsages {
     fn merge(&mut self, mut b: ApiUsages) {
-        if self.pkg_id != b.pkg_id || self.api_name != b.api_name || self.scope != b.scope {
+        // Usages from different versions of the same package may be merged.
+        if self.pkg_id.name_str() != b.pkg_id.name_str()
+            || self.api_name != b.api_name
+            || self.scope != b.scope
+        {
             panic!("Attempted to merge ApiUsages with incompatible attributes");
         }
         self.usages.append(&mut b.usages);
@@ -0,0 +1,445 @@
+//! Long-form help for each kind of problem: what the problem means, what typically causes it and what
+//! can be done about it. This is shown in the UI when the user asks for an explanation, is included
+//! in JSON output and is used to generate PROBLEMS.md, so that the same text is available wherever
+//! someone encounters a problem.
+
+use super::Problem;
+use std::fmt::Write;
+
+pub(crate) struct ProblemHelp {
+    /// The name of the kind of problem that this help is for, as returned by `Problem::kind_name`.
+    pub(crate) kind_name: &'static str,
+    pub(crate) explanation: &'static str,
+    pub(crate) causes: &'static [&'static str],
+    pub(crate) remediation: &'static [&'static str],
+}
+
+/// Where PROBLEMS.md can be viewed. Each kind of problem has a heading with its kind name, so can be
+/// linked to as an anchor.
+const DOCS_URL: &str = concat!(env!("CARGO_PKG_REPOSITORY"), "/blob/main/PROBLEMS.md");
+
+/// Help for each kind of problem, in the same order as `Problem::KIND_NAMES`.
+const HELP: &[ProblemHelp] = &[
+    ProblemHelp {
+        kind_name: "message",
+        explanation: "A general error that doesn't fit any of the other kinds of problem, for \
+            example failing to read the config or to parse a binary.",
+        causes: &[
+            "An I/O error or an invalid file.",
+            "Something that cackle doesn't support, such as an unusual build setup.",
+        ],
+        remediation: &[
+            "Read the message for details of what failed.",
+            "If the message doesn't make sense, rerun with `--debug` and consider reporting it as \
+             a bug.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "missing_configuration",
+        explanation: "There's no cackle.toml, so there's nothing to check the dependency tree \
+            against.",
+        causes: &["Running cackle for the first time in a workspace."],
+        remediation: &[
+            "Run interactively, which will guide you through creating an initial config.",
+            "Alternatively run `cargo acl init` or write cackle.toml by hand. See CONFIG.md.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "uses_build_script",
+        explanation: "The package has a build script, which runs arbitrary code on the build \
+            machine. When `common.explicit_build_scripts` is set, build scripts need to be \
+            explicitly allowed so that a new or updated dependency can't start running code at \
+            build time without review.",
+        causes: &[
+            "A dependency that generates code, compiles C code or probes the system.",
+            "A new dependency, or an update that added a build script.",
+        ],
+        remediation: &[
+            "Review the build script. If it's acceptable, add a `[pkg.foo.build]` section for \
+             the package, where `foo` is the package's name.",
+            "Consider running build scripts in a sandbox, so that what they can do is limited.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "disallowed_unsafe",
+        explanation: "The package uses `unsafe`, but isn't allowed to. Unsafe code can bypass the \
+            checks that cackle relies on, for example by calling functions through raw pointers, \
+            so packages that use it can effectively use any API.",
+        causes: &[
+            "A package that does low-level work, such as FFI or performance-sensitive code.",
+            "A new dependency, or an update that added unsafe code.",
+        ],
+        remediation: &[
+            "Review the unsafe code. Press 'd' in the UI to see each location. If it's \
+             acceptable, set `allow_unsafe = true` in the package's config.",
+            "If only part of the package needs it, consider whether a different package could be \
+             used instead.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "is_proc_macro",
+        explanation: "The package is a procedural macro. Proc macros run inside the compiler and \
+            can execute arbitrary code at build time, so need to be explicitly allowed.",
+        causes: &["A dependency that provides derive or attribute macros."],
+        remediation: &[
+            "Review the macro. If it's acceptable, set `allow_proc_macro = true` in the \
+             package's config.",
+            "Consider sandboxing rustc, so that what proc macros can do is limited.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "disallowed_api_usage",
+        explanation: "The package uses an API, such as `fs`, `net` or `process`, that its config \
+            doesn't allow. The usage was found by looking at which functions the package's code \
+            references in the final binary.",
+        causes: &[
+            "A package that needs the API to do its job.",
+            "A generic function or macro from another package that was instantiated in this \
+             package.",
+            "An update that added new functionality.",
+        ],
+        remediation: &[
+            "Press 'd' in the UI to see each usage and where it is in the source.",
+            "If the usage is expected, add the API to the package's `allow_apis`, or acknowledge \
+             the individual usage.",
+            "If it isn't expected, look into why the package needs the API before granting it.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "denied_path_usage",
+        explanation: "The package uses a path that's listed in `[deny] paths`. Denied paths can't \
+            be used by packages outside the workspace, regardless of what APIs they're allowed.",
+        causes: &[
+            "A dependency that uses functionality that you've decided no dependency should \
+             use.",
+        ],
+        remediation: &[
+            "Replace the dependency, or remove the usage.",
+            "If the usage is acceptable after all, remove the path from `[deny] paths`.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "off_tree_api_usage",
+        explanation: "Code attributed to the package uses an API from a package that it doesn't \
+            depend on. This usually isn't a real usage by the package itself, but needs to be \
+            checked.",
+        causes: &[
+            "A generic parameter that gives access to the API, but which hasn't been declared as \
+             belonging to it.",
+            "A macro that defines a symbol, then an inlined function that references that symbol.",
+        ],
+        remediation: &[
+            "Press 'd' in the UI to see each usage.",
+            "If it's a false positive, add `off_tree_api_usage` to the package's `downgrade` list.",
+            "Otherwise allow the API for the package, as for any other API usage.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "execution_failed",
+        explanation: "A build script, test or binary that we ran, usually in a sandbox, exited \
+            with a failure.",
+        causes: &[
+            "The sandbox denied something that the binary needed, such as network access or \
+             writing to a directory.",
+            "The binary failed for reasons unrelated to cackle.",
+        ],
+        remediation: &[
+            "Press 'o' in the UI to view the output and see what failed.",
+            "If the sandbox was responsible, adjust the package's sandbox config, e.g. \
+             `allow_network` or `bind_writable`, then press 'r' to rerun.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "waiting_for_input",
+        explanation: "A binary that we ran appears to be waiting for input on the terminal, which \
+            it won't receive.",
+        causes: &["A build script or test that prompts for input."],
+        remediation: &[
+            "Press 'o' in the UI to view the output so far.",
+            "Change the binary so that it doesn't need input, or provide it some other way.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "test_failed",
+        explanation: "A test failed when its test binary was run in a sandbox.",
+        causes: &[
+            "The sandbox denied something that the test needed.",
+            "A genuine test failure.",
+        ],
+        remediation: &[
+            "Read the test's output. If it suggests that the sandbox was responsible, adjust the \
+             sandbox config for the package's tests, or for just that test.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "disallowed_build_instruction",
+        explanation: "A build script emitted an instruction to cargo that isn't allowed. \
+            Instructions such as `cargo:rustc-link-arg` can change how the final binary is \
+            built, so need to be explicitly allowed.",
+        causes: &["A build script that links native libraries or sets linker arguments."],
+        remediation: &[
+            "If the instruction is expected, add a pattern matching it to the package's \
+             `build.allow_build_instructions`.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "unused_package_config",
+        explanation: "The config has a section for a package that isn't in the dependency tree.",
+        causes: &[
+            "A dependency was removed or renamed.",
+            "A typo in the package name.",
+        ],
+        remediation: &["Remove the package's config, e.g. with `cargo acl prune`."],
+    },
+    ProblemHelp {
+        kind_name: "unused_allow_api",
+        explanation: "The package is allowed to use APIs that it wasn't observed to use. Granting \
+            more than is needed means that a future update could start using the API without \
+            review.",
+        causes: &[
+            "A dependency was updated and no longer uses the API.",
+            "The API is only used with features or targets that weren't built.",
+        ],
+        remediation: &[
+            "Remove the APIs from the package's `allow_apis`, e.g. with `cargo acl prune`.",
+            "If the API is needed for other builds, keep it and ignore the warning.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "allow_api_used_only_by_dead_code",
+        explanation: "The package is allowed to use APIs, but the only code that uses them was \
+            discarded by the linker, so doesn't end up in any binary.",
+        causes: &["A package that provides functionality that isn't used by the workspace."],
+        remediation: &[
+            "Consider removing the APIs from the package's `allow_apis`. If the code starts being \
+             used, the usage will be reported again.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "select_sandbox",
+        explanation: "Part of creating an initial config: choose what kind of sandbox, if any, to \
+            run build scripts and tests in.",
+        causes: &["Creating an initial config interactively."],
+        remediation: &["Select a sandbox kind from the available edits."],
+    },
+    ProblemHelp {
+        kind_name: "select_apis",
+        explanation: "Part of creating an initial config: choose which of the built-in API \
+            definitions for the standard library to import.",
+        causes: &["Creating an initial config interactively."],
+        remediation: &["Select the APIs that you'd like to restrict."],
+    },
+    ProblemHelp {
+        kind_name: "available_api",
+        explanation: "A package in the dependency tree exports definitions of APIs that it \
+            provides, which can be imported so that usage of those APIs is checked too.",
+        causes: &["A dependency that ships a `cackle/export.toml`."],
+        remediation: &["Import the API by adding it to the package's `import` list, or ignore it."],
+    },
+    ProblemHelp {
+        kind_name: "possible_exported_api",
+        explanation: "A package provides a top-level module with the same name as an API. If the \
+            module is public, then other packages may be able to use the API through it without \
+            being detected.",
+        causes: &["A package that wraps an API, for example providing its own `fs` module."],
+        remediation: &[
+            "If the module does provide the API, add the package's path to the API's `include` \
+             list.",
+            "If it's a false positive, add `possible_exported_api` to the package's `downgrade` \
+             list.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "unused_sandbox_configuration",
+        explanation: "Sandbox config was supplied for a part of a package that's never run, so \
+            has no effect.",
+        causes: &["Sandbox config under `[pkg.foo]` rather than `[pkg.foo.build]`."],
+        remediation: &["Move the sandbox config to the part of the package that's run."],
+    },
+    ProblemHelp {
+        kind_name: "new_config_version_available",
+        explanation: "The config specifies an older config version. Newer versions change some \
+            defaults, which may give better results.",
+        causes: &["A config written for an older version of cackle."],
+        remediation: &["Read the change notes for the new version, then update `common.version`."],
+    },
+    ProblemHelp {
+        kind_name: "capability_manifest_violation",
+        explanation: "A package was observed doing something that its published capability \
+            manifest doesn't declare.",
+        causes: &[
+            "The package's manifest is out of date.",
+            "The package's behaviour changed unexpectedly.",
+        ],
+        remediation: &[
+            "Check whether the undeclared capability is expected. If so, report the out-of-date \
+             manifest to the package's authors.",
+            "If it's acceptable in the meantime, add `capability_manifest_violation` to the \
+             package's `downgrade` list.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "unanalysed_static_lib",
+        explanation: "The package is built as a static library. Static libraries aren't linked \
+            by cargo, so we never see a binary containing its code, and can't check its API \
+            usage.",
+        causes: &["A workspace package with `crate-type = [\"staticlib\"]`."],
+        remediation: &[
+            "Add a binary or test that links the package as an rlib, so that its API usage gets \
+             checked.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "plugin",
+        explanation: "A problem reported by a plugin configured in `[[plugins]]`.",
+        causes: &["Whatever the plugin checks for."],
+        remediation: &["See the plugin's documentation."],
+    },
+    ProblemHelp {
+        kind_name: "compilation_failed",
+        explanation: "A package failed to compile while cackle was building it.",
+        causes: &[
+            "A genuine compilation error.",
+            "The rustc sandbox denied something that a proc macro or the compiler needed.",
+        ],
+        remediation: &[
+            "Read the compiler's errors. If the sandbox was responsible, adjust the rustc sandbox \
+             config, then press 'r' to retry.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "package_not_built",
+        explanation: "The config has a section for a package that's in the dependency tree, but \
+            isn't built with the features that are enabled, so its config wasn't checked.",
+        causes: &["A package only needed by optional features."],
+        remediation: &[
+            "Keep the config if the package is used by other builds, or run a full check with \
+             all features enabled.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "generated_code",
+        explanation: "The package was compiled from source that its build script generated. \
+            Generated code isn't seen by anyone reviewing the package's source, so the build \
+            script that generates it may warrant a closer look.",
+        causes: &["A build script that generates bindings or lookup tables."],
+        remediation: &[
+            "Review the build script and the generated code. If it's acceptable, set \
+             `allow_generated_code = true` in the package's config.",
+        ],
+    },
+    ProblemHelp {
+        kind_name: "ignored_config_features",
+        explanation: "Features listed in `common.features` aren't being enabled, because \
+            `--no-default-features` or `--exact-features` was passed.",
+        causes: &["Running with command-line flags that override the config's features."],
+        remediation: &["Add the features to `--features` if they're wanted."],
+    },
+    ProblemHelp {
+        kind_name: "cargo_deny_violation",
+        explanation: "The package isn't permitted by the cargo-deny config referenced by \
+            `[deny] cargo_deny`.",
+        causes: &["A banned package, or one with a license that isn't allowed."],
+        remediation: &[
+            "Replace the dependency, or update the cargo-deny config if it's acceptable.",
+        ],
+    },
+];
+
+/// Returns help for the kind of problem named `kind_name`.
+pub(crate) fn for_kind(kind_name: &str) -> Option<&'static ProblemHelp> {
+    HELP.iter().find(|help| help.kind_name == kind_name)
+}
+
+impl Problem {
+    pub(crate) fn help(&self) -> Option<&'static ProblemHelp> {
+        for_kind(self.kind_name())
+    }
+}
+
+impl ProblemHelp {
+    /// Returns a link to the documentation for this kind of problem.
+    pub(crate) fn url(&self) -> String {
+        format!("{DOCS_URL}#{}", self.kind_name)
+    }
+
+    /// Returns the help as plain text, for display in a terminal.
+    pub(crate) fn text(&self) -> String {
+        let mut out = format!("{}\n", self.explanation);
+        for (heading, items) in [
+            ("Typical causes", self.causes),
+            ("What to do", self.remediation),
+        ] {
+            writeln!(out, "\n{heading}:").unwrap();
+            for item in items {
+                writeln!(out, "  - {item}").unwrap();
+            }
+        }
+        write!(out, "\nSee {}", self.url()).unwrap();
+        out
+    }
+}
+
+/// Returns the contents of PROBLEMS.md.
+#[cfg(test)]
+fn markdown() -> String {
+    let mut out = "# Problems\n\n\
+        This file is generated from `src/problem/help.rs`. It describes each kind of problem that \
+        cackle reports, what typically causes it and what can be done about it. The same \
+        information is shown in the UI by pressing 'e' and is included in `--format json` \
+        output.\n"
+        .to_owned();
+    for (index, help) in HELP.iter().enumerate() {
+        write!(
+            out,
+            "\n## {}\n\nCode: `CACKLE{:03}`\n\n{}\n",
+            help.kind_name,
+            index + 1,
+            help.explanation
+        )
+        .unwrap();
+        for (heading, items) in [
+            ("Typical causes", help.causes),
+            ("What to do", help.remediation),
+        ] {
+            writeln!(out, "\n{heading}:\n").unwrap();
+            for item in items {
+                writeln!(out, "* {item}").unwrap();
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn help_for_every_kind() {
+        let kind_names: Vec<&str> = HELP.iter().map(|help| help.kind_name).collect();
+        assert_eq!(kind_names, Problem::KIND_NAMES);
+        let problem = Problem::Message("foo".to_owned());
+        let help = problem.help().unwrap();
+        assert_eq!(
+            help.url(),
+            "https://github.com/cackle-rs/cackle/blob/main/PROBLEMS.md#message"
+        );
+        assert!(help.text().contains("Typical causes:\n  - "));
+    }
+
+    /// Checks that PROBLEMS.md is up-to-date. Set `UPDATE_PROBLEMS_MD=1` to regenerate it.
+    #[test]
+    fn problems_md_up_to_date() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("PROBLEMS.md");
+        let expected = markdown();
+        if std::env::var_os("UPDATE_PROBLEMS_MD").is_some() {
+            std::fs::write(&path, &expected).unwrap();
+        }
+        let actual = std::fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            actual == expected,
+            "PROBLEMS.md is out of date. Rerun with UPDATE_PROBLEMS_MD=1 to regenerate it"
+        );
+    }
+}
@@ -1,11 +1,21 @@
 use crate::events::AppEvent;
+use crate::muted_problems::MutedProblems;
 use crate::outcome::Outcome;
 use crate::problem::Problem;
 use crate::problem::ProblemList;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
 use fxhash::FxHashMap;
 use fxhash::FxHashSet;
 use log::info;
+use log::warn;
+use serde::Deserialize;
+use serde::Serialize;
 use std::collections::hash_map::Entry;
+use std::path::Path;
+use std::path::PathBuf;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
@@ -18,6 +28,41 @@ pub(crate) fn create(event_sender: Sender<AppEvent>) -> ProblemStoreRef {
     }
 }
 
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct ResolveOptions {
+    /// Resolve the problems in a report written by `--save-report` rather than those left
+    /// unresolved by the previous run.
+    #[clap(long)]
+    pub(crate) report: Option<PathBuf>,
+}
+
+/// Returns the path to which unresolved problems are saved, so that they can be resolved later with
+/// `cargo acl resolve`.
+pub(crate) fn saved_problems_path(target_dir: &Path) -> PathBuf {
+    target_dir.join("cackle").join("unresolved-problems.json")
+}
+
+/// Problems that were unresolved when we last saved.
+#[derive(Serialize, Deserialize)]
+struct SavedProblems {
+    problems: Vec<Problem>,
+}
+
+/// Loads problems saved to `path` by a previous run. Returns None if nothing was saved.
+pub(crate) fn load_saved(path: &Path) -> Result<Option<ProblemList>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json = crate::fs::read_to_string(path)?;
+    let saved: SavedProblems = serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse `{}`", path.display()))?;
+    let mut problems = ProblemList::default();
+    for problem in saved.problems {
+        problems.push(problem);
+    }
+    Ok(Some(problems))
+}
+
 /// A store of multiple `ProblemList` instances that allows signalling when a problem list is
 /// resolved.
 pub(crate) struct ProblemStore {
@@ -28,6 +73,24 @@ pub(crate) struct ProblemStore {
     id_by_deduplication_key: FxHashMap<Problem, ProblemId>,
     event_sender: Sender<AppEvent>,
     pub(crate) has_aborted: bool,
+
+    /// Whether problems that differ only in which version of a package they're for should be
+    /// grouped into a single problem.
+    pub(crate) group_versions: bool,
+
+    /// Whether a binary is currently attached to the terminal.
+    terminal_attached: bool,
+
+    /// Notified once the user interface has released the terminal.
+    terminal_release_sender: Option<Sender<()>>,
+
+    /// Where to save unresolved problems whenever they change. This lets the user resume resolving
+    /// problems if the user interface goes away, e.g. because their SSH session dropped.
+    save_path: Option<PathBuf>,
+
+    /// Problems that the user has muted for a while. Muted problems are treated as resolved as soon
+    /// as they're reported.
+    muted: Option<MutedProblems>,
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
@@ -48,9 +111,41 @@ impl ProblemStoreRef {
         outcome.recv().unwrap_or(Outcome::GiveUp)
     }
 
-    pub(crate) fn lock(&self) -> MutexGuard<ProblemStore> {
+    pub(crate) fn lock(&self) -> MutexGuard<'_, ProblemStore> {
         self.inner.lock().unwrap()
     }
+
+    /// Asks the user interface to release the terminal and waits until it has, or until we abort.
+    /// Only one binary can be attached to the terminal at a time, so if another binary is already
+    /// attached, we wait for it to be detached first.
+    pub(crate) fn attach_terminal(&self) -> Outcome {
+        let released = loop {
+            let mut store = self.lock();
+            if store.has_aborted {
+                return Outcome::GiveUp;
+            }
+            if !store.terminal_attached {
+                store.terminal_attached = true;
+                let (sender, receiver) = std::sync::mpsc::channel();
+                store.terminal_release_sender = Some(sender);
+                let _ = store.event_sender.send(AppEvent::ReleaseTerminal);
+                break receiver;
+            }
+            drop(store);
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        };
+        match released.recv() {
+            Ok(()) => Outcome::Continue,
+            Err(_) => Outcome::GiveUp,
+        }
+    }
+
+    /// Advises the user interface that it can take back the terminal.
+    pub(crate) fn detach_terminal(&self) {
+        let mut store = self.lock();
+        store.terminal_attached = false;
+        let _ = store.event_sender.send(AppEvent::TerminalAvailable);
+    }
 }
 
 impl ProblemStore {
@@ -61,12 +156,77 @@ impl ProblemStore {
             id_by_deduplication_key: Default::default(),
             event_sender,
             has_aborted: false,
+            group_versions: true,
+            terminal_attached: false,
+            terminal_release_sender: None,
+            save_path: None,
+            muted: None,
         }
     }
 
+    /// Starts saving unresolved problems to `path` whenever they change. Once all problems are
+    /// resolved, the file is removed.
+    pub(crate) fn save_to(&mut self, path: PathBuf) {
+        self.save_path = Some(path);
+    }
+
+    /// Loads muted problems from `path`. Any problems reported after this that have been muted
+    /// won't be shown to the user.
+    pub(crate) fn load_mutes(&mut self, path: PathBuf) -> Result<()> {
+        self.muted = Some(MutedProblems::load(path, &crate::config::expiry::today())?);
+        Ok(())
+    }
+
+    /// Mutes the problem with the specified ID for `days` days and resolves it.
+    pub(crate) fn mute(&mut self, id: ProblemId, days: u32) -> Result<()> {
+        let muted = self
+            .muted
+            .as_mut()
+            .ok_or_else(|| anyhow!("Muting problems isn't supported here"))?;
+        let problem = self.problems[id.0]
+            .as_ref()
+            .expect("Called ProblemStore::mute with ID that was already resolved");
+        muted.mute(
+            problem,
+            crate::config::expiry::days_from_now(i64::from(days)),
+        )?;
+        self.resolve(id);
+        Ok(())
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.save_path else {
+            return;
+        };
+        if let Err(error) = self.try_save(path) {
+            warn!("Failed to save unresolved problems: {error:#}");
+        }
+    }
+
+    fn try_save(&self, path: &Path) -> Result<()> {
+        if self.is_empty() {
+            if path.exists() {
+                std::fs::remove_file(path)
+                    .with_context(|| format!("Failed to remove `{}`", path.display()))?;
+            }
+            return Ok(());
+        }
+        let saved = SavedProblems {
+            problems: self
+                .deduplicated_into_iter()
+                .map(|(_, problem)| problem.clone())
+                .collect(),
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+        }
+        crate::fs::write_atomic(path, &serde_json::to_string(&saved)?)
+    }
+
     /// Adds `problems` to this store. The returned receiver will receive a single value once all
     /// problems in the supplied list have been resolved, or abort has been called. The supplied
-    /// problem list must not be empty.
+    /// problem list must not be empty. Problems that have been muted are resolved straight away.
     fn add(&mut self, problems: ProblemList) -> Receiver<Outcome> {
         for problem in &problems {
             info!("Reported problem: {problem}");
@@ -75,12 +235,25 @@ impl ProblemStore {
         let (sender, receiver) = std::sync::mpsc::channel();
         let mut problem_ids = FxHashSet::default();
         for problem in problems.take() {
+            if self
+                .muted
+                .as_ref()
+                .is_some_and(|muted| muted.is_muted(&problem))
+            {
+                info!("Ignoring muted problem: {problem}");
+                continue;
+            }
             problem_ids.insert(self.add_problem(problem));
         }
+        if problem_ids.is_empty() {
+            let _ = sender.send(Outcome::Continue);
+            return receiver;
+        }
         self.notification_entries.push(NotificationEntry {
             problem_ids,
             sender: Some(sender),
         });
+        self.save();
         let _ = self.event_sender.send(AppEvent::ProblemsAdded);
         receiver
     }
@@ -121,6 +294,28 @@ impl ProblemStore {
         }
     }
 
+    /// Resolve all problems that the configuration says should be downgraded to warnings,
+    /// returning them. Interactive UIs use this so that the user isn't repeatedly asked about
+    /// problems that they've already decided to tolerate, but can still report them as warnings.
+    #[cfg(feature = "ui")]
+    pub(crate) fn resolve_downgraded(
+        &mut self,
+        permissions: &crate::config::permissions::Permissions,
+    ) -> Vec<Problem> {
+        let mut downgraded: Vec<_> = self
+            .deduplicated_into_iter()
+            .filter(|(_, problem)| problem.is_downgraded(permissions))
+            .map(|(index, problem)| (index, problem.clone()))
+            .collect();
+        // See comment in `resolve_problems_with_empty_diff` for why we reverse.
+        downgraded.reverse();
+        for (index, _) in &downgraded {
+            self.resolve(*index);
+        }
+        downgraded.reverse();
+        downgraded.into_iter().map(|(_, problem)| problem).collect()
+    }
+
     pub(crate) fn deduplicated_into_iter(&self) -> impl Iterator<Item = (ProblemId, &Problem)> {
         ProblemStoreIterator {
             store: self,
@@ -160,7 +355,8 @@ impl ProblemStore {
         // otherwise we'd be adding entries into middle of the list and we should only ever have new
         // entries show up at the end.
         self.id_by_deduplication_key
-            .remove(&problem.deduplication_key());
+            .remove(&self.deduplication_key(&problem));
+        self.save();
     }
 
     pub(crate) fn abort(&mut self) {
@@ -169,16 +365,32 @@ impl ProblemStore {
                 let _ = sender.send(Outcome::GiveUp);
             }
         }
+        // Dropping the sender causes anyone waiting for the terminal to give up.
+        self.terminal_release_sender = None;
         self.has_aborted = true;
     }
 
+    /// Called by the user interface once it has released the terminal in response to
+    /// `AppEvent::ReleaseTerminal`.
+    pub(crate) fn terminal_released(&mut self) {
+        if let Some(sender) = self.terminal_release_sender.take() {
+            let _ = sender.send(());
+        }
+    }
+
+    fn deduplication_key(&self, problem: &Problem) -> Problem {
+        if self.group_versions {
+            problem.version_independent_key()
+        } else {
+            problem.deduplication_key()
+        }
+    }
+
     /// Adds a problem, possibly merging it into an existing problem. Returns the ID of that
     /// problem.
     fn add_problem(&mut self, problem: Problem) -> ProblemId {
-        match self
-            .id_by_deduplication_key
-            .entry(problem.deduplication_key())
-        {
+        let key = self.deduplication_key(&problem);
+        match self.id_by_deduplication_key.entry(key) {
             Entry::Occupied(entry) => {
                 let id = *entry.get();
                 let existing_problem = self.problems[id.0]
@@ -300,6 +512,42 @@ mod tests {
         assert_eq!(done2.try_recv(), Ok(crate::outcome::Outcome::Continue));
     }
 
+    #[cfg(feature = "ui")]
+    #[test]
+    fn resolve_downgraded() {
+        use crate::config::ApiName;
+        use crate::problem::PossibleExportedApi;
+        use crate::symbol::Symbol;
+
+        let exported = |pkg_name| {
+            Problem::PossibleExportedApi(PossibleExportedApi {
+                pkg_id: pkg_id(pkg_name),
+                api: ApiName::new("fs"),
+                symbol: Symbol::borrowed(b"crab1::fs::read"),
+            })
+        };
+        let mut problems = create_problems();
+        problems.push(exported("crab1"));
+        problems.push(exported("crab3"));
+        let mut store = ProblemStore::new(channel().0);
+        let done = store.add(problems);
+        let config = crate::config::testing::parse(
+            r#"
+            [pkg.crab1]
+            downgrade = ["possible_exported_api"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            store.resolve_downgraded(&config.permissions),
+            vec![exported("crab1")]
+        );
+        assert_eq!(store.len(), 3);
+        assert!(store.resolve_downgraded(&config.permissions).is_empty());
+        assert_eq!(done.try_recv(), Err(TryRecvError::Empty));
+    }
+
     #[test]
     fn add_notifications() {
         let (send, recv) = channel();
@@ -330,4 +578,84 @@ mod tests {
         store.add(create_problems());
         assert_eq!(store.deduplicated_into_iter().count(), 2);
     }
+
+    #[test]
+    fn save_unresolved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = super::saved_problems_path(dir.path());
+        let mut store = ProblemStore::new(channel().0);
+        store.save_to(path.clone());
+        store.add(create_problems());
+        let (id, _) = store.deduplicated_into_iter().next().unwrap();
+        store.resolve(id);
+        let saved = super::load_saved(&path).unwrap().unwrap();
+        assert_eq!(
+            saved.take(),
+            vec![Problem::UsesBuildScript(pkg_id("crab2"))]
+        );
+
+        let (id, _) = store.deduplicated_into_iter().next().unwrap();
+        store.resolve(id);
+        assert!(!path.exists());
+        assert!(super::load_saved(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn mute() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = crate::muted_problems::muted_problems_path(dir.path());
+        let mut store = ProblemStore::new(channel().0);
+        store.add(create_problems());
+        let (id, _) = store.deduplicated_into_iter().next().unwrap();
+        assert!(store.mute(id, 7).is_err());
+
+        store.load_mutes(path.clone()).unwrap();
+        let done = store.add(create_problems());
+        let (id, _) = store.deduplicated_into_iter().next().unwrap();
+        store.mute(id, 7).unwrap();
+        assert_eq!(store.len(), 1);
+        assert_eq!(done.try_recv(), Err(TryRecvError::Empty));
+
+        // Once muted, the problem is resolved as soon as it's reported again, even by a later run.
+        let mut store = ProblemStore::new(channel().0);
+        store.load_mutes(path).unwrap();
+        let mut problems = ProblemList::default();
+        problems.push(Problem::UsesBuildScript(pkg_id("crab1")));
+        let done = store.add(problems);
+        assert_eq!(done.try_recv(), Ok(crate::outcome::Outcome::Continue));
+        assert!(store.is_empty());
+        store.add(create_problems());
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn group_versions() {
+        use crate::crate_index::testing::pkg_id_with_version;
+        use cargo_metadata::semver::Version;
+
+        fn problems() -> ProblemList {
+            let mut problems = ProblemList::default();
+            problems.push(Problem::UsesBuildScript(pkg_id_with_version(
+                "rand",
+                Version::new(0, 7, 3),
+            )));
+            problems.push(Problem::UsesBuildScript(pkg_id_with_version(
+                "rand",
+                Version::new(0, 8, 5),
+            )));
+            problems
+        }
+
+        let mut store = ProblemStore::new(channel().0);
+        let done = store.add(problems());
+        assert_eq!(store.len(), 1);
+        let (id, _) = store.deduplicated_into_iter().next().unwrap();
+        store.resolve(id);
+        assert_eq!(done.try_recv(), Ok(crate::outcome::Outcome::Continue));
+
+        let mut store = ProblemStore::new(channel().0);
+        store.group_versions = false;
+        store.add(problems());
+        assert_eq!(store.len(), 2);
+    }
 }
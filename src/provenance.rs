@@ -0,0 +1,398 @@
+//! Records, for each API grant in the config, which usages it permitted on the most recent complete
+//! check and when it was first seen. `cargo acl why` uses these records to explain why a grant is
+//! needed, since by the time someone asks, the check that justified it may be long gone.
+
+use crate::config::permissions::PermSel;
+use crate::config::ApiName;
+use crate::config::Config;
+use crate::config::PackageName;
+use crate::metrics::UsageCounter;
+use crate::timing::unix_now;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use log::info;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The maximum number of usages that we record for each grant.
+const MAX_USAGES_PER_GRANT: usize = 100;
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct WhyOptions {
+    /// The config entry to explain. e.g. `pkg.tokio.allow_apis.net`.
+    entry: String,
+
+    /// Use the usages recorded in a report written by `--save-report` rather than those recorded
+    /// by the last full check.
+    #[clap(long)]
+    pub(crate) report: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub(crate) struct ProvenanceStore {
+    /// Keyed by the permission selector as written in the config (e.g. "tokio.build"), then by API.
+    grants: BTreeMap<String, BTreeMap<String, GrantRecord>>,
+
+    /// Seconds since the Unix epoch when the records were last updated.
+    timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct GrantRecord {
+    /// Seconds since the Unix epoch of the first complete check at which the grant was present.
+    first_seen: u64,
+
+    /// The number of distinct usages that the grant permitted. May be more than `usages.len()`.
+    num_usages: usize,
+
+    usages: Vec<RecordedUsage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RecordedUsage {
+    /// The selector for the code that used the API. This differs from the grant's selector when the
+    /// grant was inherited. e.g. a grant to "foo" permits usages by "foo.build".
+    perm_sel: String,
+    location: String,
+    name: String,
+}
+
+/// Returns the path of the file into which we record provenance.
+pub(crate) fn provenance_path(target_dir: &Path) -> PathBuf {
+    target_dir.join("cackle").join("provenance.json")
+}
+
+/// Updates the records in `target_dir` with the usages from a complete check.
+pub(crate) fn record_run(target_dir: &Path, counter: &UsageCounter, config: &Config) -> Result<()> {
+    let path = provenance_path(target_dir);
+    let previous = match load_store(&path) {
+        Ok(store) => store,
+        Err(error) => {
+            // Like metrics, these records are just informational.
+            info!("Discarding provenance records: {error:#}");
+            ProvenanceStore::default()
+        }
+    };
+    let store = build_store(&previous, counter, config, unix_now());
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    }
+    crate::fs::write_atomic(&path, &serde_json::to_string_pretty(&store)?)
+}
+
+/// Returns records of the usages from the current run without updating those in `target_dir`. Used
+/// when the run may not have been a complete check, so shouldn't replace the records.
+pub(crate) fn snapshot(
+    target_dir: &Path,
+    counter: &UsageCounter,
+    config: &Config,
+) -> ProvenanceStore {
+    let previous = load_store(&provenance_path(target_dir)).unwrap_or_default();
+    build_store(&previous, counter, config, unix_now())
+}
+
+/// Returns the APIs that each package was observed to use on the most recent complete check, keyed
+/// by package name. Only usages that were permitted by a grant are recorded.
+pub(crate) fn observed_apis(target_dir: &Path) -> Result<BTreeMap<PackageName, BTreeSet<String>>> {
+    let store = load_store(&provenance_path(target_dir))?;
+    let mut observed: BTreeMap<PackageName, BTreeSet<String>> = BTreeMap::new();
+    for apis in store.grants.values() {
+        for (api, record) in apis {
+            for usage in &record.usages {
+                observed
+                    .entry(PermSel::parse(&usage.perm_sel).package_name)
+                    .or_default()
+                    .insert(api.clone());
+            }
+        }
+    }
+    Ok(observed)
+}
+
+/// Builds records for the grants currently in `config`. Grants that have been removed from the
+/// config are dropped, so if one is later added back, it's treated as new.
+fn build_store(
+    previous: &ProvenanceStore,
+    counter: &UsageCounter,
+    config: &Config,
+    timestamp: u64,
+) -> ProvenanceStore {
+    let mut grants: BTreeMap<String, BTreeMap<String, GrantRecord>> = BTreeMap::new();
+    for (grant_sel, pkg_config) in &config.permissions_no_inheritance.packages {
+        for api in &pkg_config.allow_apis {
+            let mut usages: Vec<RecordedUsage> = counter
+                .usages()
+                .filter(|(perm_sel, usage_api, _)| {
+                    *usage_api == api && grant_covers(grant_sel, perm_sel, config)
+                })
+                .flat_map(|(perm_sel, _, usages)| {
+                    usages.iter().map(|(location, name)| RecordedUsage {
+                        perm_sel: perm_sel.to_string(),
+                        location: location.to_string(),
+                        name: name.to_string(),
+                    })
+                })
+                .collect();
+            usages.sort();
+            let num_usages = usages.len();
+            usages.truncate(MAX_USAGES_PER_GRANT);
+            let grant_sel = grant_sel.to_string();
+            let first_seen = previous
+                .grant(&grant_sel, api)
+                .map_or(timestamp, |record| record.first_seen);
+            grants.entry(grant_sel).or_default().insert(
+                api.to_string(),
+                GrantRecord {
+                    first_seen,
+                    num_usages,
+                    usages,
+                },
+            );
+        }
+    }
+    ProvenanceStore { grants, timestamp }
+}
+
+/// Returns whether a grant to `grant_sel` permits usages by `usage_sel`, either directly, by
+/// inheritance or because `grant_sel` is a pattern that matches.
+fn grant_covers(grant_sel: &PermSel, usage_sel: &PermSel, config: &Config) -> bool {
+    let name_matches = grant_sel.package_name == usage_sel.package_name
+        || config.package_patterns.iter().any(|(name, pattern)| {
+            *name == grant_sel.package_name && pattern.matches(usage_sel.package_name.as_ref())
+        });
+    name_matches
+        && (grant_sel.scope == usage_sel.scope
+            || grant_sel
+                .descendants()
+                .iter()
+                .any(|descendant| descendant.scope == usage_sel.scope))
+}
+
+fn load_store(path: &Path) -> Result<ProvenanceStore> {
+    if !path.exists() {
+        return Ok(ProvenanceStore::default());
+    }
+    serde_json::from_str(&crate::fs::read_to_string(path)?)
+        .with_context(|| format!("Failed to parse `{}`", path.display()))
+}
+
+impl ProvenanceStore {
+    fn grant(&self, perm_sel: &str, api: &ApiName) -> Option<&GrantRecord> {
+        self.grants.get(perm_sel)?.get(api.name.as_ref())
+    }
+}
+
+/// Prints an explanation of the config entry specified in `options`, using the records in `store`
+/// if supplied, otherwise those in `target_dir`.
+pub(crate) fn print_why(
+    target_dir: &Path,
+    store: Option<&ProvenanceStore>,
+    config: &Config,
+    options: &WhyOptions,
+) -> Result<()> {
+    let (perm_sel, api) = parse_entry(&options.entry)?;
+    let loaded;
+    let store = match store {
+        Some(store) => store,
+        None => {
+            loaded = load_store(&provenance_path(target_dir))?;
+            &loaded
+        }
+    };
+    for line in why_lines(store, config, &perm_sel, &api, unix_now())? {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// Parses a config entry such as `pkg.tokio.allow_apis.net`.
+fn parse_entry(entry: &str) -> Result<(PermSel, ApiName)> {
+    let Some((selector, api)) = entry
+        .strip_prefix("pkg.")
+        .and_then(|rest| rest.split_once(".allow_apis."))
+    else {
+        bail!("Expected an entry of the form `pkg.{{package}}.allow_apis.{{api}}`, got `{entry}`");
+    };
+    Ok((
+        PermSel::parse(selector.trim_matches('"')),
+        ApiName::new(api),
+    ))
+}
+
+fn why_lines(
+    store: &ProvenanceStore,
+    config: &Config,
+    perm_sel: &PermSel,
+    api: &ApiName,
+    now: u64,
+) -> Result<Vec<String>> {
+    let is_direct = config
+        .permissions_no_inheritance
+        .get(perm_sel)
+        .is_some_and(|pkg_config| pkg_config.allow_apis.contains(api));
+    let is_effective = config
+        .permissions
+        .get(perm_sel)
+        .is_some_and(|pkg_config| pkg_config.allow_apis.contains(api));
+    if !is_direct && !is_effective {
+        bail!("`{perm_sel}` isn't permitted to use `{api}` by the current configuration");
+    }
+    let mut lines = Vec::new();
+    // The grant whose records we show. For an inherited grant, that's the grant it inherits from.
+    let source = if is_direct {
+        lines.push(format!("`{perm_sel}` is granted `{api}` directly"));
+        Some(perm_sel.clone())
+    } else {
+        let source = inherited_from(perm_sel, api, config);
+        match &source {
+            Some(source) => lines.push(format!(
+                "`{perm_sel}` inherits `{api}` from `pkg.{source}.allow_apis`"
+            )),
+            None => lines.push(format!(
+                "`{perm_sel}` is granted `{api}` by `[defaults]` or by a capability manifest"
+            )),
+        }
+        source
+    };
+    let Some(record) = source
+        .as_ref()
+        .and_then(|source| store.grant(&source.to_string(), api))
+    else {
+        lines.push(
+            "No usages recorded. Usages are recorded each time `cargo acl` completes a full check"
+                .to_owned(),
+        );
+        return Ok(lines);
+    };
+    lines.push(format!(
+        "First seen {}",
+        crate::metrics::format_age(now.saturating_sub(record.first_seen))
+    ));
+    let usages: Vec<&RecordedUsage> = record
+        .usages
+        .iter()
+        .filter(|usage| grant_covers(perm_sel, &PermSel::parse(&usage.perm_sel), config))
+        .collect();
+    let checked = crate::metrics::format_age(now.saturating_sub(store.timestamp));
+    if usages.is_empty() {
+        lines.push(format!("Not used as of the last full check ({checked})"));
+        return Ok(lines);
+    }
+    let omitted = if is_direct {
+        record.num_usages - usages.len()
+    } else {
+        0
+    };
+    let count = match usages.len() + omitted {
+        1 => "once".to_owned(),
+        n => format!("{n} times"),
+    };
+    lines.push(format!(
+        "Used {count} as of the last full check ({checked}):"
+    ));
+    for usage in usages {
+        lines.push(format!(
+            "  {}: {} -> {}",
+            usage.perm_sel, usage.location, usage.name
+        ));
+    }
+    if omitted > 0 {
+        lines.push(format!("  ... and {omitted} more"));
+    }
+    Ok(lines)
+}
+
+/// Returns the selector from which `perm_sel` inherits its permission to use `api`, if it's from a
+/// parent selector or a pattern.
+fn inherited_from(perm_sel: &PermSel, api: &ApiName, config: &Config) -> Option<PermSel> {
+    config
+        .permissions_no_inheritance
+        .packages
+        .iter()
+        .filter(|(grant_sel, pkg_config)| {
+            *grant_sel != perm_sel
+                && pkg_config.allow_apis.contains(api)
+                && grant_covers(grant_sel, perm_sel, config)
+        })
+        .map(|(grant_sel, _)| grant_sel.clone())
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_parsing() {
+        let (perm_sel, api) = parse_entry("pkg.foo.build.allow_apis.net").unwrap();
+        assert_eq!(perm_sel, PermSel::for_build_script("foo"));
+        assert_eq!(api, ApiName::new("net"));
+        assert!(parse_entry("foo.allow_apis.net").is_err());
+    }
+
+    #[test]
+    fn why_inherited() {
+        let config = crate::config::testing::parse(
+            r#"
+                [api.net]
+                include = ["std::net"]
+
+                [pkg.foo]
+                allow_apis = ["net"]
+            "#,
+        )
+        .unwrap();
+        let mut store = ProvenanceStore {
+            timestamp: 100,
+            ..ProvenanceStore::default()
+        };
+        let usage = |perm_sel: &str| RecordedUsage {
+            perm_sel: perm_sel.to_owned(),
+            location: "src/lib.rs [1:2]".to_owned(),
+            name: "std::net::TcpStream".to_owned(),
+        };
+        store.grants.entry("foo".to_owned()).or_default().insert(
+            "net".to_owned(),
+            GrantRecord {
+                first_seen: 40,
+                num_usages: 2,
+                usages: vec![usage("foo"), usage("foo.from.build")],
+            },
+        );
+        let net = ApiName::new("net");
+        assert_eq!(
+            why_lines(&store, &config, &PermSel::for_primary("foo"), &net, 100).unwrap(),
+            vec![
+                "`foo` is granted `net` directly",
+                "First seen 1m ago",
+                "Used 2 times as of the last full check (0s ago):",
+                "  foo: src/lib.rs [1:2] -> std::net::TcpStream",
+                "  foo.from.build: src/lib.rs [1:2] -> std::net::TcpStream",
+            ]
+        );
+        assert_eq!(
+            why_lines(
+                &store,
+                &config,
+                &PermSel::parse("foo.from.build"),
+                &net,
+                100
+            )
+            .unwrap(),
+            vec![
+                "`foo.from.build` inherits `net` from `pkg.foo.allow_apis`",
+                "First seen 1m ago",
+                "Used once as of the last full check (0s ago):",
+                "  foo.from.build: src/lib.rs [1:2] -> std::net::TcpStream",
+            ]
+        );
+        assert!(why_lines(&store, &config, &PermSel::for_primary("bar"), &net, 100).is_err());
+    }
+}
@@ -28,6 +28,7 @@ use crate::outcome::ExitCode;
 use crate::outcome::Outcome;
 use crate::Args;
 use crate::RequestHandler;
+use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
@@ -52,9 +53,16 @@ pub(crate) mod subprocess;
 pub(crate) const SOCKET_ENV: &str = "CACKLE_SOCKET_PATH";
 const CONFIG_PATH_ENV: &str = "CACKLE_CONFIG_PATH";
 const ORIG_LINKER_ENV: &str = "CACKLE_ORIG_LINKER";
+/// Set to the linker, e.g. `mold`, that the linker should be told to use via `-fuse-ld`.
+const FUSE_LD_ENV: &str = "CACKLE_FUSE_LD";
 pub(crate) const TARGET_DIR: &str = "CACKLE_TARGET_DIR";
 pub(crate) const MANIFEST_DIR: &str = "CACKLE_MANIFEST_DIR";
 const RUSTC_PATH: &str = "CACKLE_RUSTC_PATH";
+/// The host target triple, e.g. `x86_64-unknown-linux-gnu`, so that our rustc wrapper can tell
+/// whether it's compiling for another target without running `rustc -vV` each time.
+const HOST_TARGET_ENV: &str = "CACKLE_HOST_TARGET";
+/// Set if cargo will build the standard library from source.
+const BUILD_STD_ENV: &str = "CACKLE_BUILD_STD";
 
 /// Environment variables that we need to allow through to rustc when we run rustc in a sandbox.
 pub(crate) const RUSTC_ENV_VARS: &[&str] = &[
@@ -78,11 +86,31 @@ pub(crate) struct CargoOutputWaiter {
     stdout_thread: Option<JoinHandle<()>>,
 }
 
+/// Returns the directory containing the socket via which subprocesses talk to us.
+pub(crate) fn ipc_dir(tmpdir: &Path) -> PathBuf {
+    tmpdir.join("comms")
+}
+
 pub(crate) fn clean(dir: &Path, args: &Args, config: &CommonConfig) -> Result<()> {
     // For now, we always clean before we build. It might be possible to not do this, but we'd need
     // to carefully track changes to things we care about, like cackle.toml.
+    run_clean(cargo::command("clean", dir, args, config), args)
+}
+
+/// Cleans just the artifacts of `pkg_name`, so that it gets rebuilt.
+pub(crate) fn clean_package(
+    dir: &Path,
+    args: &Args,
+    config: &CommonConfig,
+    pkg_name: &str,
+) -> Result<()> {
     let mut command = cargo::command("clean", dir, args, config);
-    if args.should_capture_cargo_output() {
+    command.arg("-p").arg(pkg_name);
+    run_clean(command, args)
+}
+
+fn run_clean(mut command: Command, args: &Args) -> Result<()> {
+    if args.is_interactive() {
         command.stdout(Stdio::null());
         command.stderr(Stdio::null());
     }
@@ -91,30 +119,9 @@ pub(crate) fn clean(dir: &Path, args: &Args, config: &CommonConfig) -> Result<()
 }
 
 impl<'a> CargoRunner<'a> {
-    /// Invokes `cargo build` in the specified directory with us acting as proxy versions of rustc
-    /// and the linker. If calling this, you must call handle_wrapped_binaries from the start of
-    /// main.
-    pub(crate) fn invoke_cargo_build(
-        &self,
-        abort_recv: Receiver<()>,
-        abort_sender: Sender<()>,
-        request_creator: impl Fn(Request) -> RequestHandler,
-    ) -> Result<CargoOutputWaiter> {
-        if !std::env::var(SOCKET_ENV).unwrap_or_default().is_empty() {
-            panic!("{SOCKET_ENV} is already set. Missing call to handle_wrapped_binaries?");
-        }
-
-        // We put `cackle.socket` into a directory by itself. This lets our rustc sandbox have write
-        // permission on this directory without also gaining write access to other files that we put
-        // in our temporary directory.
-        let ipc_dir = self.tmpdir.join("comms");
-        std::fs::create_dir_all(&ipc_dir)
-            .with_context(|| format!("Failed to crate directory `{}`", ipc_dir.display()))?;
-        let ipc_path = ipc_dir.join("cackle.socket");
-        let _ = std::fs::remove_file(&ipc_path);
-        let listener = UnixListener::bind(&ipc_path)
-            .with_context(|| format!("Failed to create Unix socket `{}`", ipc_path.display()))?;
-
+    /// Returns the `cargo build` command that we run, before we add the environment variables
+    /// that make us the proxy for rustc and the linker.
+    pub(crate) fn build_command(&self) -> Command {
         let mut command = cargo::command(
             "build",
             self.manifest_dir,
@@ -124,7 +131,7 @@ impl<'a> CargoRunner<'a> {
         for pkg in &self.args.package {
             command.arg("-p").arg(pkg);
         }
-        if self.args.command.is_none() {
+        if self.args.is_full_analysis() {
             let default_build_flags = ["--all-targets".to_owned()];
             for flag in self
                 .config
@@ -137,35 +144,62 @@ impl<'a> CargoRunner<'a> {
                 command.arg(flag);
             }
         }
-        let rustc_path = rustup_rustc_path().unwrap_or_else(|_| PathBuf::from("rustc"));
         if let Some(target) = &self.args.target {
             command.arg("--target").arg(target);
         }
-        let features = self
-            .args
-            .features
-            .clone()
-            .unwrap_or_else(|| self.config.raw.common.features.join(","));
-        if !features.is_empty() {
-            command.arg("--features");
-            command.arg(features);
+        command.args(cargo::Features::new(self.args, &self.config.raw.common).cargo_args());
+        if let Some(jobs) = &self.args.jobs {
+            command.arg("--jobs").arg(jobs);
         }
+        command
+    }
+
+    /// Invokes `cargo build` in the specified directory with us acting as proxy versions of rustc
+    /// and the linker. If calling this, you must call handle_wrapped_binaries from the start of
+    /// main.
+    pub(crate) fn invoke_cargo_build(
+        &self,
+        abort_recv: &Receiver<()>,
+        abort_sender: Sender<()>,
+        request_creator: impl Fn(Request) -> RequestHandler,
+    ) -> Result<CargoOutputWaiter> {
+        if !std::env::var(SOCKET_ENV).unwrap_or_default().is_empty() {
+            panic!("{SOCKET_ENV} is already set. Missing call to handle_wrapped_binaries?");
+        }
+
+        // We put `cackle.socket` into a directory by itself. This lets our rustc sandbox have write
+        // permission on this directory without also gaining write access to other files that we put
+        // in our temporary directory.
+        let ipc_dir = ipc_dir(self.tmpdir);
+        std::fs::create_dir_all(&ipc_dir)
+            .with_context(|| format!("Failed to crate directory `{}`", ipc_dir.display()))?;
+        let ipc_path = ipc_dir.join("cackle.socket");
+        let _ = std::fs::remove_file(&ipc_path);
+        let listener = UnixListener::bind(&ipc_path)
+            .with_context(|| format!("Failed to create Unix socket `{}`", ipc_path.display()))?;
+
+        let mut command = self.build_command();
+        let rustc_path = rustup_rustc_path().unwrap_or_else(|_| PathBuf::from("rustc"));
         let config_path = crate::config::flattened_config_path(self.tmpdir);
         command
             .env(SOCKET_ENV, &ipc_path)
             .env(CONFIG_PATH_ENV, config_path)
             .env(TARGET_DIR, self.target_dir)
             .env(MANIFEST_DIR, self.manifest_dir)
+            .env(HOST_TARGET_ENV, host_triple(&rustc_path)?)
             .env(RUSTC_PATH, rustc_path)
             .env("RUSTC_WRAPPER", cackle_exe()?);
 
         self.crate_index.add_internal_env(&mut command);
+        if cargo::uses_build_std(self.args, &self.config.raw.common) {
+            command.env(BUILD_STD_ENV, "1");
+        }
 
         // Don't pass through environment variables that might have been set by `cargo run`. If we do,
         // then they might still be set in our subprocesses, which might then get confused and think
         // they're proxying the build of "cackle" itself.
         command.env_remove("CARGO_PKG_NAME");
-        let capture_output = self.args.should_capture_cargo_output();
+        let capture_output = self.args.is_interactive();
         if capture_output {
             command.stdout(Stdio::piped()).stderr(Stdio::piped());
         }
@@ -254,6 +288,18 @@ fn rustup_rustc_path() -> Result<PathBuf> {
     Ok(path)
 }
 
+/// Returns the host target triple of `rustc`, e.g. `x86_64-unknown-linux-gnu`.
+pub(crate) fn host_triple(rustc: &Path) -> Result<String> {
+    let output = Command::new(rustc)
+        .arg("-vV")
+        .output()
+        .context("Failed to run `rustc -vV`")?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: ").map(str::to_owned))
+        .ok_or_else(|| anyhow!("Failed to determine host target from `rustc -vV`"))
+}
+
 impl CargoOutputWaiter {
     /// Wait for all output to pass through and the output threads (if any) to shut down. This
     /// should only be called after the UI thread has been shut down since the output threads block
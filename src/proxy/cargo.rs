@@ -1,6 +1,8 @@
 use crate::config::CommonConfig;
 use crate::Args;
 use clap::Parser;
+use serde::Serialize;
+use std::fmt::Display;
 use std::path::Path;
 use std::process::Command;
 
@@ -8,6 +10,39 @@ use std::process::Command;
 pub(crate) const DEFAULT_PROFILE_NAME: &str = "cackle";
 pub(crate) const PROFILE_NAME_ENV: &str = "CACKLE_BUILD_PROFILE";
 
+/// Whether cargo is permitted to access the network.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum NetworkMode {
+    #[default]
+    Online,
+    /// Passes `--offline` to cargo.
+    Offline,
+    /// Passes `--frozen` to cargo, which is like `--offline`, but additionally requires that
+    /// Cargo.lock is up-to-date.
+    Frozen,
+}
+
+impl NetworkMode {
+    pub(crate) fn from_args(args: &Args) -> Self {
+        if args.frozen {
+            NetworkMode::Frozen
+        } else if args.offline {
+            NetworkMode::Offline
+        } else {
+            NetworkMode::Online
+        }
+    }
+
+    /// Returns the argument, if any, that should be passed to cargo.
+    pub(crate) fn cargo_arg(self) -> Option<&'static str> {
+        match self {
+            NetworkMode::Online => None,
+            NetworkMode::Offline => Some("--offline"),
+            NetworkMode::Frozen => Some("--frozen"),
+        }
+    }
+}
+
 #[derive(Parser, Debug, Clone)]
 pub(crate) struct CargoOptions {
     #[clap(allow_hyphen_values = true)]
@@ -23,6 +58,115 @@ pub(crate) fn profile_name<'a>(args: &'a Args, config: &'a CommonConfig) -> &'a
         .unwrap_or(DEFAULT_PROFILE_NAME)
 }
 
+/// The features that we ask cargo to build with.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct Features {
+    pub(crate) features: Vec<String>,
+    pub(crate) no_default_features: bool,
+    /// Features from the config that aren't enabled because of command-line flags.
+    #[serde(skip)]
+    pub(crate) ignored_from_config: Vec<String>,
+}
+
+impl Features {
+    /// Returns the features to build with. Features from the command line are added to those from
+    /// the config, unless --no-default-features or --exact-features was given, in which case only
+    /// those from the command line are used.
+    pub(crate) fn new(args: &Args, config: &CommonConfig) -> Self {
+        let exact = args.no_default_features || args.exact_features;
+        let mut features = if exact {
+            Vec::new()
+        } else {
+            config.features.clone()
+        };
+        // Like cargo, we accept features separated by commas and/or spaces.
+        for feature in args
+            .features
+            .iter()
+            .flat_map(|features| features.split([',', ' ']))
+            .filter(|feature| !feature.is_empty())
+        {
+            if !features.iter().any(|f| f == feature) {
+                features.push(feature.to_owned());
+            }
+        }
+        let ignored_from_config = config
+            .features
+            .iter()
+            .filter(|feature| !features.contains(feature))
+            .cloned()
+            .collect();
+        Self {
+            features,
+            no_default_features: args.no_default_features,
+            ignored_from_config,
+        }
+    }
+
+    /// Returns the arguments to pass to cargo.
+    pub(crate) fn cargo_args(&self) -> Vec<String> {
+        let mut cargo_args = Vec::new();
+        if self.no_default_features {
+            cargo_args.push("--no-default-features".to_owned());
+        }
+        if !self.features.is_empty() {
+            cargo_args.push("--features".to_owned());
+            cargo_args.push(self.features.join(","));
+        }
+        cargo_args
+    }
+}
+
+impl Display for Features {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.features.is_empty() {
+            write!(f, "(none)")?;
+        } else {
+            write!(f, "{}", self.features.join(", "))?;
+        }
+        if self.no_default_features {
+            write!(f, " without default features")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether cargo will build the standard library from source, either because the config
+/// says so or because `-Zbuild-std` is among the arguments that we pass to cargo.
+pub(crate) fn uses_build_std(args: &Args, config: &CommonConfig) -> bool {
+    let cargo_args = match &args.command {
+        Some(crate::Command::Test(cargo_options) | crate::Command::Run(cargo_options)) => {
+            cargo_options.remaining.as_slice()
+        }
+        _ => &[],
+    };
+    let build_flags = config
+        .build_flags
+        .as_deref()
+        .filter(|_| args.is_full_analysis())
+        .unwrap_or_default();
+    config.build_std || has_build_std_flag(build_flags.iter().chain(cargo_args))
+}
+
+/// Returns whether `cargo_args` contains `-Zbuild-std`, possibly with a list of crates to build.
+fn has_build_std_flag<'a>(cargo_args: impl Iterator<Item = &'a String>) -> bool {
+    let mut previous = "";
+    for arg in cargo_args {
+        let unstable_flag = if previous == "-Z" {
+            Some(arg.as_str())
+        } else {
+            arg.strip_prefix("-Z")
+        };
+        if let Some(flag) = unstable_flag {
+            if flag == "build-std" || flag.starts_with("build-std=") {
+                return true;
+            }
+        }
+        previous = arg;
+    }
+    false
+}
+
 pub(crate) fn command(
     base_command: &str,
     dir: &Path,
@@ -64,6 +208,59 @@ pub(crate) fn command(
     let profile = profile_name(args, config);
     command.arg("--profile").arg(profile);
     command.env(PROFILE_NAME_ENV, profile);
+    command.args(NetworkMode::from_args(args).cargo_arg());
+    if let Some(target_dir) = &args.target_dir {
+        command.arg("--target-dir").arg(target_dir);
+    }
     command.args(extra_args);
     command
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_std_flag() {
+        let check = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            has_build_std_flag(args.iter())
+        };
+        assert!(check(&["--tests", "-Zbuild-std"]));
+        assert!(check(&["-Zbuild-std=core,alloc"]));
+        assert!(check(&["-Z", "build-std"]));
+        assert!(!check(&["-Zbuild-std-features=panic_immediate_abort"]));
+        assert!(!check(&["-Z", "unstable-options", "build-std"]));
+        assert!(!check(&[]));
+    }
+
+    #[test]
+    fn features() {
+        let features = |cli_args: &[&str], config_features: &[&str]| {
+            let args =
+                Args::parse_from(["cackle"].iter().chain(cli_args).map(|arg| arg.to_string()));
+            let config = CommonConfig {
+                features: config_features.iter().map(|f| f.to_string()).collect(),
+                ..Default::default()
+            };
+            Features::new(&args, &config)
+        };
+
+        let merged = features(&["--features", "b,c d"], &["a", "b"]);
+        assert_eq!(merged.features, ["a", "b", "c", "d"]);
+        assert!(merged.ignored_from_config.is_empty());
+        assert_eq!(merged.cargo_args(), ["--features", "a,b,c,d"]);
+        assert_eq!(merged.to_string(), "a, b, c, d");
+
+        let exact = features(&["--exact-features", "--features", "b"], &["a", "b"]);
+        assert_eq!(exact.features, ["b"]);
+        assert_eq!(exact.ignored_from_config, ["a"]);
+        assert_eq!(exact.cargo_args(), ["--features", "b"]);
+
+        let no_default = features(&["--no-default-features"], &["a"]);
+        assert!(no_default.features.is_empty());
+        assert_eq!(no_default.ignored_from_config, ["a"]);
+        assert_eq!(no_default.cargo_args(), ["--no-default-features"]);
+        assert_eq!(no_default.to_string(), "(none) without default features");
+    }
+}
@@ -1,5 +1,7 @@
 //! Handles parsing of errors from rustc.
 
+use super::rpc::CompilationFailure;
+use crate::crate_index::CrateSel;
 use crate::location::SourceLocation;
 use anyhow::Context;
 use anyhow::Result;
@@ -23,7 +25,12 @@ fn get_disallowed_unsafe_locations_str(output: &str) -> Vec<SourceLocation> {
         let Ok(message) = serde_json::from_str::<Message>(line) else {
             continue;
         };
-        if message.level == "error" && message.code.code == "unsafe_code" {
+        if message.level == "error"
+            && message
+                .code
+                .as_ref()
+                .is_some_and(|code| code.code == "unsafe_code")
+        {
             if let Some(first_span) = message.spans.first() {
                 let filename = Path::new(&first_span.file_name);
                 locations.push(SourceLocation::new(
@@ -37,11 +44,74 @@ fn get_disallowed_unsafe_locations_str(output: &str) -> Vec<SourceLocation> {
     locations
 }
 
+/// Returns details of the errors that caused compilation of `crate_sel` to fail. `rustc_output`
+/// should be the output from rustc with --error-format=json. Returns None if rustc didn't report
+/// any errors, e.g. if it crashed.
+pub(crate) fn get_compilation_failure(
+    crate_sel: &CrateSel,
+    rustc_output: &std::process::Output,
+) -> Result<Option<CompilationFailure>> {
+    let stderr =
+        std::str::from_utf8(&rustc_output.stderr).context("rustc emitted invalid UTF-8")?;
+    Ok(get_compilation_failure_str(crate_sel, stderr))
+}
+
+fn get_compilation_failure_str(crate_sel: &CrateSel, output: &str) -> Option<CompilationFailure> {
+    let mut error_codes = Vec::new();
+    let mut rendered = String::new();
+    let mut has_errors = false;
+    for line in output.lines() {
+        let Ok(message) = serde_json::from_str::<Message>(line) else {
+            continue;
+        };
+        if message.level.starts_with("error") {
+            has_errors = true;
+            if let Some(code) = message.code {
+                if !error_codes.contains(&code.code) {
+                    error_codes.push(code.code);
+                }
+            }
+        }
+        if let Some(message_rendered) = &message.rendered {
+            rendered.push_str(&strip_ansi_escapes(message_rendered));
+        }
+    }
+    has_errors.then(|| CompilationFailure {
+        crate_sel: crate_sel.clone(),
+        error_codes,
+        rendered,
+    })
+}
+
+/// Removes terminal escape sequences from `text`. Cargo asks rustc to render diagnostics with
+/// colours, which we need to remove before we can display them ourselves.
+fn strip_ansi_escapes(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\x1b' {
+            out.push(ch);
+            continue;
+        }
+        // Skip a control sequence, which is `[`, then parameters, then a final byte in the range
+        // `@` to `~`.
+        if chars.next() == Some('[') {
+            for ch in chars.by_ref() {
+                if ('@'..='~').contains(&ch) {
+                    break;
+                }
+            }
+        }
+    }
+    out
+}
+
 #[derive(Deserialize, PartialEq, Eq, Debug)]
 struct Message {
-    code: Code,
+    code: Option<Code>,
     level: String,
     spans: Vec<SpannedMessage>,
+    rendered: Option<String>,
 }
 
 #[derive(Deserialize, PartialEq, Eq, Debug)]
@@ -89,4 +159,29 @@ mod tests {
             )]
         );
     }
+
+    #[test]
+    fn test_compilation_failure() {
+        let crate_sel = CrateSel::primary(crate::crate_index::testing::pkg_id("foo"));
+        let output = [
+            r#"{"code": null, "level": "warning", "spans": [], "rendered": "warning: unused\n"}"#,
+            r#"{"code": {"code": "E0425"}, "level": "error", "spans": [], "rendered": "\u001b[1m\u001b[38;5;9merror[E0425]\u001b[0m: cannot find value\n"}"#,
+            r#"{"artifact": "foo.rmeta", "emit": "metadata"}"#,
+            r#"{"code": null, "level": "error", "spans": [], "rendered": "error: aborting\n"}"#,
+        ]
+        .join("\n");
+        assert_eq!(
+            get_compilation_failure_str(&crate_sel, &output),
+            Some(CompilationFailure {
+                crate_sel: crate_sel.clone(),
+                error_codes: vec!["E0425".to_owned()],
+                rendered: "warning: unused\nerror[E0425]: cannot find value\nerror: aborting\n"
+                    .to_owned(),
+            })
+        );
+        assert_eq!(
+            get_compilation_failure_str(&crate_sel, output.lines().next().unwrap()),
+            None
+        );
+    }
 }
@@ -2,19 +2,33 @@
 
 use crate::config::SandboxConfig;
 use crate::crate_index::CrateSel;
+use crate::fs::PathRemap;
 use crate::link_info::LinkInfo;
 use crate::location::SourceLocation;
 use crate::outcome::Outcome;
+use crate::test_output::FailedTest;
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
+use std::io::BufReader;
 use std::io::Read;
 use std::io::Write;
 use std::os::unix::net::UnixStream;
+use std::path::Path;
 use std::path::PathBuf;
 
+/// Link steps with more object files than this have their `LinkInfo` passed via a file rather than
+/// over the socket. This keeps large messages from holding up the loop in the parent process that
+/// accepts connections, since that loop reads each request before handing it off.
+const MAX_INLINE_OBJECTS: usize = 1000;
+
+/// The largest message that `read_from_stream` will accept. This keeps a corrupt or malicious
+/// length prefix from making us allocate an arbitrary amount of memory.
+const MAX_MESSAGE_LEN: usize = 1 << 30;
+
 /// A communication channel to the main Cackle process.
 pub(crate) struct RpcClient {
     socket_path: PathBuf,
@@ -40,6 +54,22 @@ impl RpcClient {
         read_from_stream(&mut ipc)
     }
 
+    /// Advises the parent process that rustc failed to compile a crate. A response of `Continue`
+    /// means that the config has changed and compilation should be retried.
+    pub(crate) fn compilation_failed(&self, failure: CompilationFailure) -> Result<Outcome> {
+        let mut ipc = self.connect()?;
+        write_to_stream(&Request::CompilationFailed(failure), &mut ipc)?;
+        read_from_stream(&mut ipc)
+    }
+
+    /// Advises the parent process that part of the standard library was built from the package in
+    /// `manifest_dir`.
+    pub(crate) fn std_crate_built(&self, manifest_dir: PathBuf) -> Result<Outcome> {
+        let mut ipc = self.connect()?;
+        write_to_stream(&Request::StdCrateBuilt(manifest_dir), &mut ipc)?;
+        read_from_stream(&mut ipc)
+    }
+
     pub(crate) fn rustc_started(&self, crate_sel: &CrateSel) -> Result<Outcome> {
         let mut ipc = self.connect()?;
         let request = Request::RustcStarted(crate_sel.clone());
@@ -48,14 +78,35 @@ impl RpcClient {
     }
 
     pub(crate) fn linker_invoked(&self, info: LinkInfo) -> Result<Outcome> {
+        let request = match self.socket_path.parent() {
+            Some(dir) if info.object_paths.len() > MAX_INLINE_OBJECTS => {
+                Request::LinkerInvokedFile(write_link_info(&info, &dir.join("link-info"))?)
+            }
+            _ => Request::LinkerInvoked(info),
+        };
         let mut ipc = self.connect()?;
-        write_to_stream(&Request::LinkerInvoked(info), &mut ipc)?;
+        write_to_stream(&request, &mut ipc)?;
         read_from_stream(&mut ipc)
     }
 
     pub(crate) fn bin_execution_complete(&self, info: BinExecutionOutput) -> Result<Outcome> {
         let mut ipc = self.connect()?;
-        write_to_stream(&Request::BinExecutionComplete(info), &mut ipc)?;
+        write_to_stream(&Request::BinExecutionComplete(Box::new(info)), &mut ipc)?;
+        read_from_stream(&mut ipc)
+    }
+
+    /// Asks the parent process to release the terminal so that a binary can be run attached to it.
+    /// Returns once the terminal has been released.
+    pub(crate) fn attach_terminal(&self) -> Result<Outcome> {
+        let mut ipc = self.connect()?;
+        write_to_stream(&Request::AttachTerminal, &mut ipc)?;
+        read_from_stream(&mut ipc)
+    }
+
+    /// Advises the parent process that we're done with the terminal.
+    pub(crate) fn detach_terminal(&self) -> Result<Outcome> {
+        let mut ipc = self.connect()?;
+        write_to_stream(&Request::DetachTerminal, &mut ipc)?;
         read_from_stream(&mut ipc)
     }
 
@@ -82,10 +133,63 @@ impl RpcClient {
 pub(crate) enum Request {
     /// Advises that the specified crate failed to compile because it uses unsafe.
     CrateUsesUnsafe(UnsafeUsage),
+    /// Advises that rustc reported errors when compiling the specified crate.
+    CompilationFailed(CompilationFailure),
     LinkerInvoked(LinkInfo),
-    BinExecutionComplete(BinExecutionOutput),
+    /// The same as `LinkerInvoked`, but for link steps with many objects. Contains the path of a
+    /// file containing the `LinkInfo`, named by the digest of its contents.
+    LinkerInvokedFile(PathBuf),
+    BinExecutionComplete(Box<BinExecutionOutput>),
     RustcStarted(CrateSel),
     RustcComplete(RustcOutput),
+    /// Advises that a crate that's part of the standard library, or one of its dependencies, was
+    /// built from source because build-std is in use. Contains the package's directory.
+    StdCrateBuilt(PathBuf),
+    /// Requests that the user interface release the terminal so that a binary can use it.
+    AttachTerminal,
+    /// Advises that the binary that was attached to the terminal has finished with it.
+    DetachTerminal,
+}
+
+impl Request {
+    /// Converts a `LinkerInvokedFile` request into the equivalent `LinkerInvoked` request. Other
+    /// requests are returned unchanged.
+    pub(crate) fn load_link_info(self) -> Result<Self> {
+        match self {
+            Request::LinkerInvokedFile(path) => Ok(Request::LinkerInvoked(read_link_info(&path)?)),
+            other => Ok(other),
+        }
+    }
+}
+
+/// Writes `info` to a file in `dir` named by the digest of its contents and returns the file's path.
+/// If the same link step is run more than once, the file will already exist and is reused.
+fn write_link_info(info: &LinkInfo, dir: &Path) -> Result<PathBuf> {
+    let serialized = serde_json::to_string(info)?;
+    let path = dir.join(format!(
+        "{}.json",
+        crate::sha256::hex_digest(serialized.as_bytes())
+    ));
+    if path.exists() {
+        return Ok(path);
+    }
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    // Another process might be writing the same file, so we write to a unique temporary file first.
+    let mut tmp_file = tempfile::NamedTempFile::new_in(dir)
+        .with_context(|| format!("Failed to create temporary file in `{}`", dir.display()))?;
+    tmp_file.write_all(serialized.as_bytes())?;
+    tmp_file
+        .persist(&path)
+        .with_context(|| format!("Failed to write `{}`", path.display()))?;
+    Ok(path)
+}
+
+pub(crate) fn read_link_info(path: &Path) -> Result<LinkInfo> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open `{}`", path.display()))?;
+    serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("Failed to parse `{}`", path.display()))
 }
 
 /// The output from running a binary such as a build script or a test.
@@ -100,12 +204,27 @@ pub(crate) struct BinExecutionOutput {
     /// A display string for how the sandbox was configured (e.g. the command line). Only present if
     /// the exit code is non-zero.
     pub(crate) sandbox_config_display: Option<String>,
+    /// Set if the binary was killed because it was waiting for input. Creating the file at this
+    /// path requests that the next attempt to run the binary be attached to the user's terminal.
+    pub(crate) waiting_for_input: Option<PathBuf>,
+    /// For test binaries that failed, the tests that failed, if they could be determined from the
+    /// output.
+    pub(crate) failed_tests: Vec<FailedTest>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Hash)]
 pub(crate) struct RustcOutput {
     pub(crate) crate_sel: CrateSel,
     pub(crate) source_paths: Vec<PathBuf>,
+    /// Whether rustc produced a static library. Static libraries don't get linked, so we can't
+    /// analyse them.
+    pub(crate) is_staticlib: bool,
+    /// Any `--remap-path-prefix` rules that were passed to rustc, in the order they were passed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) path_remaps: Vec<PathRemap>,
+    /// Those of `source_paths` that were generated by the package's build script.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) generated_paths: Vec<PathBuf>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Hash)]
@@ -114,6 +233,16 @@ pub(crate) struct UnsafeUsage {
     pub(crate) locations: Vec<SourceLocation>,
 }
 
+/// Errors reported by rustc when compiling a crate.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Hash)]
+pub(crate) struct CompilationFailure {
+    pub(crate) crate_sel: CrateSel,
+    /// The distinct error codes reported, e.g. "E0425". Not all errors have codes.
+    pub(crate) error_codes: Vec<String>,
+    /// The diagnostics as rustc rendered them, with any colours removed.
+    pub(crate) rendered: String,
+}
+
 /// Writes `value` to `stream`. The format used is the length followed by `value` serialised as
 /// JSON.
 pub(crate) fn write_to_stream<T: Serialize>(value: &T, stream: &mut impl Write) -> Result<()> {
@@ -125,9 +254,20 @@ pub(crate) fn write_to_stream<T: Serialize>(value: &T, stream: &mut impl Write)
 
 /// Reads a value of type `T` from `stream`. Format is the same as for `write_to_stream`.
 pub(crate) fn read_from_stream<T: DeserializeOwned>(stream: &mut impl Read) -> Result<T> {
+    read_from_stream_with_limit(stream, MAX_MESSAGE_LEN)
+}
+
+/// Like `read_from_stream`, but fails if the message is longer than `max_len` bytes.
+pub(crate) fn read_from_stream_with_limit<T: DeserializeOwned>(
+    stream: &mut impl Read,
+    max_len: usize,
+) -> Result<T> {
     let mut len_bytes = [0u8; std::mem::size_of::<usize>()];
     stream.read_exact(&mut len_bytes)?;
     let len = usize::from_le_bytes(len_bytes);
+    if len > max_len {
+        bail!("Message of {len} bytes exceeds limit of {max_len} bytes");
+    }
     let mut buf = vec![0u8; len];
     stream.read_exact(&mut buf)?;
     let serialized = std::str::from_utf8(&buf)?;
@@ -152,4 +292,39 @@ mod tests {
 
         assert_eq!(req, req2);
     }
+
+    #[test]
+    fn oversized_message() {
+        let mut buf = Vec::new();
+        write_to_stream(&"a".repeat(100), &mut buf).unwrap();
+        assert!(read_from_stream_with_limit::<String>(&mut buf.as_slice(), 50).is_err());
+        assert_eq!(
+            read_from_stream_with_limit::<String>(&mut buf.as_slice(), 102).unwrap(),
+            "a".repeat(100)
+        );
+
+        let buf = usize::MAX.to_le_bytes();
+        assert!(read_from_stream::<String>(&mut buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn link_info_via_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let object_paths: Vec<PathBuf> = (0..=MAX_INLINE_OBJECTS)
+            .map(|i| PathBuf::from(format!("deps/foo-{i}.o")))
+            .collect();
+        let info = LinkInfo::new(
+            CrateSel::primary(crate::crate_index::testing::pkg_id("foo")),
+            object_paths,
+            Path::new("deps/foo").into(),
+            false,
+            false,
+        );
+        let path = write_link_info(&info, dir.path()).unwrap();
+        assert_eq!(write_link_info(&info, dir.path()).unwrap(), path);
+        assert_eq!(
+            Request::LinkerInvokedFile(path).load_link_info().unwrap(),
+            Request::LinkerInvoked(info)
+        );
+    }
 }
@@ -2,23 +2,32 @@
 //! rustc, the linker or a build script. See comment on parent module for more details.
 
 use super::cackle_exe;
+use super::errors::get_compilation_failure;
 use super::errors::get_disallowed_unsafe_locations;
 use super::rpc::BinExecutionOutput;
 use super::rpc::RustcOutput;
 use super::run_command;
 use super::ExitCode;
+use super::BUILD_STD_ENV;
 use super::CONFIG_PATH_ENV;
+use super::FUSE_LD_ENV;
+use super::ORIG_LINKER_ENV;
 use crate::config::permissions::PermSel;
 use crate::config::permissions::Permissions;
 use crate::config::Config;
 use crate::config::RustcConfig;
+use crate::config::SandboxConfig;
 use crate::crate_index::CrateKind;
 use crate::crate_index::CrateSel;
+use crate::fs::PathRemap;
 use crate::link_info::LinkInfo;
 use crate::location::SourceLocation;
 use crate::outcome::Outcome;
 use crate::proxy::rpc::RpcClient;
+use crate::sandbox::Input;
 use crate::sandbox::RustcSandboxInputs;
+use crate::test_output;
+use crate::test_output::FailedTest;
 use crate::unsafe_checker;
 use anyhow::anyhow;
 use anyhow::bail;
@@ -28,6 +37,7 @@ use serde::Deserialize;
 use serde::Serialize;
 use std::ffi::OsString;
 use std::io::Write;
+use std::os::unix::process::ExitStatusExt;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
@@ -36,6 +46,14 @@ use std::sync::Arc;
 pub(crate) const PROXY_BIN_ARG: &str = "proxy-bin";
 pub(crate) const ENV_CRATE_KIND: &str = "CACKLE_CRATE_KIND";
 
+/// Set when invoking rustc for a target other than the host. Passed through to the linker so that
+/// it knows that the output can't be run on this machine.
+const ENV_FOREIGN_TARGET: &str = "CACKLE_FOREIGN_TARGET";
+
+/// Set when invoking rustc to build a proc macro. Passed through to the linker so that usages of
+/// APIs by the proc macro can be attributed to it running at build time.
+pub(crate) const ENV_PROC_MACRO: &str = "CACKLE_PROC_MACRO";
+
 /// Checks if we're acting as a wrapper for rustc or the linker. If we are, then we do whatever work
 /// we need to do, then invoke the binary that we're wrapping and then exit - i.e. we don't return.
 /// If we're not wrapping a binary, then we just return.
@@ -106,21 +124,45 @@ fn setup_bin_wrapper(link_info: &mut LinkInfo) -> Result<()> {
     // fair.
     std::fs::write(
         bin_path,
-        format!(
-            "#!/bin/bash\n\
-             if [ -x \"{cackle_exe}\" ]; then\n\
-                \"{cackle_exe}\" {PROXY_BIN_ARG} {selector_token} \"{bin_path_utf8}\" \"$@\" \n\
-             else\n\
-                \"{bin_path_utf8}\" \"$@\"\n\
-             fi\n",
-        ),
+        bin_wrapper_script(cackle_exe, selector_token, bin_path_utf8),
     )?;
     link_info.output_file = new_filename;
     Ok(())
 }
 
+pub(crate) fn bin_wrapper_script(cackle_exe: &str, selector_token: &str, orig_bin: &str) -> String {
+    format!(
+        "#!/bin/bash\n\
+         if [ -x \"{cackle_exe}\" ]; then\n\
+            \"{cackle_exe}\" {PROXY_BIN_ARG} {selector_token} \"{orig_bin}\" \"$@\" \n\
+         else\n\
+            \"{orig_bin}\" \"$@\"\n\
+         fi\n",
+    )
+}
+
+/// If `path` is a wrapper written by `setup_bin_wrapper`, returns the path of the binary that it
+/// wraps. Wrappers may also be found where cargo has copied or linked a binary, e.g. from `deps`
+/// into the profile directory.
+pub(crate) fn wrapped_bin(path: &Path) -> Option<PathBuf> {
+    // Our wrappers are small, so this avoids reading binaries.
+    if std::fs::metadata(path).ok()?.len() > 4096 {
+        return None;
+    }
+    let script = std::fs::read_to_string(path).ok()?;
+    if !script.starts_with("#!/bin/bash\n") || !script.contains(PROXY_BIN_ARG) {
+        return None;
+    }
+    let line = script.lines().skip_while(|line| *line != "else").nth(1)?;
+    let orig_bin = line
+        .strip_suffix(" \"$@\"")?
+        .strip_prefix('"')?
+        .strip_suffix('"')?;
+    Some(PathBuf::from(orig_bin))
+}
+
 /// Returns the name of the real bin file after we've renamed it.
-fn orig_bin_path(path: &Path) -> Arc<Path> {
+pub(crate) fn orig_bin_path(path: &Path) -> Arc<Path> {
     Arc::from(
         if let Some(extension) = path.extension() {
             let mut new_extension = OsString::from("orig.");
@@ -148,6 +190,9 @@ fn proxy_binary(
         let Some(sandbox) = crate::sandbox::for_perm_sel(&sandbox_config, &orig_bin, &perm_sel)?
         else {
             // Config says to run without a sandbox.
+            if let Some(scratch_dir) = crate::sandbox::scratch_dir(&sandbox_config, &perm_sel)? {
+                command.env(crate::sandbox::SCRATCH_DIR_ENV, scratch_dir);
+            }
             return Ok(command
                 // If the command is a build script and it runs rustc, we want it to invoke rustc
                 // directly, not to go via our wrapper. This is also consistent with what happens if
@@ -158,7 +203,30 @@ fn proxy_binary(
                 .into());
         };
 
-        let output = sandbox.run(&command)?;
+        let attach_marker = attach_terminal_marker()?;
+        let run = if attach_marker.exists() {
+            // Attaching the terminal is a one-off, so remove the request.
+            let _ = std::fs::remove_file(&attach_marker);
+            if rpc_client.attach_terminal()? == Outcome::GiveUp {
+                std::process::exit(-1);
+            }
+            let run = sandbox.run_interactive(&command, Input::Terminal);
+            rpc_client.detach_terminal()?;
+            run?
+        } else if let Some(stdin) = sandbox_config.stdin.as_deref() {
+            sandbox.run_interactive(&command, Input::Canned(stdin))?
+        } else {
+            sandbox.run_interactive(&command, Input::Detect)?
+        };
+        let mut output = run.output;
+        let failed_tests = if crate_sel.kind == CrateKind::Test
+            && !output.status.success()
+            && !run.waiting_for_input
+        {
+            rerun_tests_with_overrides(&orig_bin, &perm_sel, &sandbox_config, &mut output)?
+        } else {
+            Vec::new()
+        };
         let rpc_response = rpc_client.bin_execution_complete({
             let exit_code = output.status.code().unwrap_or(-1);
             BinExecutionOutput {
@@ -170,6 +238,8 @@ fn proxy_binary(
                 binary_path: orig_bin.clone(),
                 sandbox_config_display: (exit_code != 0)
                     .then(|| sandbox.display_to_run(&command).to_string()),
+                waiting_for_input: run.waiting_for_input.then_some(attach_marker),
+                failed_tests,
             }
         })?;
         match rpc_response {
@@ -187,6 +257,62 @@ fn proxy_binary(
     }
 }
 
+/// Finds which tests failed from `output` and reruns each of them that has a per-test sandbox
+/// override by itself with that override applied. The output of reruns is appended to `output`.
+/// Returns the tests that still failed. If there were failed tests and they all passed when rerun,
+/// then `output` is updated to indicate success.
+fn rerun_tests_with_overrides(
+    orig_bin: &Path,
+    perm_sel: &PermSel,
+    sandbox_config: &SandboxConfig,
+    output: &mut std::process::Output,
+) -> Result<Vec<FailedTest>> {
+    let failed_tests = test_output::parse_failures(&String::from_utf8_lossy(&output.stdout));
+    let had_failures = !failed_tests.is_empty();
+    let mut still_failing = Vec::new();
+    for failed_test in failed_tests {
+        let Some(test_config) = sandbox_config.per_test.get(&failed_test.name) else {
+            still_failing.push(failed_test);
+            continue;
+        };
+        let mut test_config = test_config.clone();
+        test_config.inherit(sandbox_config);
+        let mut command = Command::new(orig_bin);
+        command.args([failed_test.name.as_str(), "--exact"]);
+        let rerun = match crate::sandbox::for_perm_sel(&test_config, orig_bin, perm_sel)? {
+            Some(sandbox) => sandbox.run(&command)?,
+            None => command.env_remove("RUSTC_WRAPPER").output()?,
+        };
+        output.stdout.extend_from_slice(&rerun.stdout);
+        output.stderr.extend_from_slice(&rerun.stderr);
+        if !rerun.status.success() {
+            let rerun_failures =
+                test_output::parse_failures(&String::from_utf8_lossy(&rerun.stdout));
+            if rerun_failures.is_empty() {
+                still_failing.push(failed_test);
+            } else {
+                still_failing.extend(rerun_failures);
+            }
+        }
+    }
+    if had_failures && still_failing.is_empty() {
+        output.status = std::process::ExitStatus::from_raw(0);
+    }
+    Ok(still_failing)
+}
+
+/// Returns the path of a file that, if it exists, requests that the next run of the binary we're
+/// wrapping be attached to the user's terminal. We use the directory above the one containing our
+/// socket, since unlike the socket directory, sandboxed rustc doesn't have write access to it.
+fn attach_terminal_marker() -> Result<PathBuf> {
+    let socket_path = path_from_env(super::SOCKET_ENV)?;
+    let dir = socket_path
+        .parent()
+        .and_then(Path::parent)
+        .ok_or_else(|| anyhow!("Socket path `{}` has no parent", socket_path.display()))?;
+    Ok(dir.join(format!("attach-terminal-{}", std::process::id())))
+}
+
 fn proxy_rustc(rpc_client: &RpcClient) -> Result<ExitCode> {
     if std::env::var("CARGO_PKG_NAME").is_err() {
         // If CARGO_PKG_NAME isn't set, then cargo is probably just invoking rustc to query
@@ -196,8 +322,20 @@ fn proxy_rustc(rpc_client: &RpcClient) -> Result<ExitCode> {
             .status()?
             .into());
     };
+    let args: Vec<String> = std::env::args().collect();
+    if std::env::var_os(BUILD_STD_ENV).is_some() && is_build_std_crate(&args) {
+        // We don't check the standard library, but code from it needs to be recognised as such,
+        // so we tell the parent process where its source is.
+        let status = Command::new(rustc_path_from_env()?)
+            .args(args.iter().skip(2))
+            .status()?;
+        if status.success() {
+            rpc_client.std_crate_built(path_from_env("CARGO_MANIFEST_DIR")?)?;
+        }
+        return Ok(status.into());
+    }
     let mut crate_sel = CrateSel::from_env()?;
-    if std::env::args().any(|arg| arg == "--test") {
+    if args.iter().any(|arg| arg == "--test") {
         crate_sel.kind = CrateKind::Test;
     }
     let mut runner = RustcRunner::new(crate_sel);
@@ -244,12 +382,18 @@ impl RustcRunner {
         // We need to parse the configuration each time, since it might have changed. Specifically
         // it might have been changed to allow unsafe.
         let config = SubprocessConfig::from_env()?;
-        let unsafe_permitted = config
-            .permissions
-            .unsafe_permitted_for_crate(&self.crate_sel);
-        let mut command = self.get_command(unsafe_permitted)?;
+        let permissions = &config.permissions;
+        let unsafe_permitted = permissions.unsafe_permitted_for_crate(&self.crate_sel);
+        // If unsafe is permitted in some files, then we can't have rustc deny it everywhere, so we
+        // rely on our own checking of the source files. `-Funsafe-code` can't be overridden with
+        // `#[allow]` and we don't modify the package's sources to add `#![deny]`. This means that
+        // unsafe that only appears after macro expansion goes undetected in such packages.
+        let mut command = self.get_command(
+            unsafe_permitted || permissions.unsafe_permitted_in_some_files(&self.crate_sel),
+            config.linker.as_deref(),
+        )?;
         let output = match crate::sandbox::for_rustc(
-            &config.rustc,
+            &config.rustc_config(&self.crate_sel),
             &RustcSandboxInputs::from_env(&self.crate_sel)?,
         )? {
             Some(mut sandbox) => {
@@ -261,21 +405,46 @@ impl RustcRunner {
         let mut unsafe_locations = Vec::new();
 
         if output.status.code() == Some(0) {
-            let source_paths = crate::deps::source_files_from_rustc_args(std::env::args())?;
+            let dep_info = crate::deps::dep_info_from_rustc_args(std::env::args())?;
+            let source_paths = dep_info.source_paths;
+            let args: Vec<String> = std::env::args().collect();
             // Tell the main process that rustc has completed. If the linker was invoked, then
             // this will trigger checking of the linker inputs/outputs.
             let response = rpc_client.rustc_complete(RustcOutput {
                 crate_sel: self.crate_sel.clone(),
                 source_paths: source_paths.clone(),
+                is_staticlib: flag_values(&args, "--crate-type")
+                    .flat_map(|types| types.split(','))
+                    .any(|crate_type| crate_type == "staticlib"),
+                path_remaps: flag_values(&args, "--remap-path-prefix")
+                    .filter_map(PathRemap::parse)
+                    .collect(),
+                generated_paths: dep_info.generated_paths,
             })?;
             if response != Outcome::Continue {
                 return Ok(RustcRunStatus::GiveUp);
             }
             if !unsafe_permitted {
-                unsafe_locations.extend(find_unsafe_in_sources(&source_paths)?);
+                let pkg_dir = path_from_env("CARGO_MANIFEST_DIR")?;
+                let pkg_dir = pkg_dir.canonicalize().unwrap_or(pkg_dir);
+                unsafe_locations.extend(find_unsafe_in_sources(&source_paths)?.into_iter().filter(
+                    |location| {
+                        !permissions.unsafe_permitted_at(&self.crate_sel, &pkg_dir, location)
+                    },
+                ));
             }
         } else {
             unsafe_locations.extend(get_disallowed_unsafe_locations(&output)?);
+            if unsafe_locations.is_empty() {
+                if let Some(failure) = get_compilation_failure(&self.crate_sel, &output)? {
+                    // If the user gives up, we still pass through rustc's output so that cargo
+                    // reports the errors as it normally would.
+                    return match rpc_client.compilation_failed(failure)? {
+                        Outcome::Continue => Ok(RustcRunStatus::Retry),
+                        Outcome::GiveUp => Ok(RustcRunStatus::Done(output)),
+                    };
+                }
+            }
         }
         if !unsafe_locations.is_empty() {
             unsafe_locations.sort();
@@ -291,41 +460,31 @@ impl RustcRunner {
         Ok(RustcRunStatus::Done(output))
     }
 
-    fn get_command(&self, unsafe_permitted: bool) -> Result<Command> {
-        let mut args = std::env::args().skip(2).peekable();
+    fn get_command(
+        &self,
+        unsafe_permitted: bool,
+        configured_linker: Option<&str>,
+    ) -> Result<Command> {
+        let rustc_args = RustcArgs::parse(std::env::args().skip(2));
         let mut command = Command::new(rustc_path_from_env()?);
-        let mut linker_arg = OsString::new();
-        let mut orig_linker_arg = None;
-        while let Some(arg) = args.next() {
-            // Look for `-C linker=...`. If we find it, note the value for later use and drop the
-            // argument.
-            if arg == "-C" {
-                if let Some(linker) = args
-                    .peek()
-                    .and_then(|arg| arg.strip_prefix("linker="))
-                    .map(ToOwned::to_owned)
-                {
-                    orig_linker_arg = Some(linker);
-                    args.next();
-                    continue;
-                }
-                // Skip -C debuginfo= if present, so that we can add our own value at the end.
-                if args.peek().is_some_and(|arg| arg.starts_with("debuginfo=")) {
-                    args.next();
-                    continue;
+        command.args(&rustc_args.args);
+        // Force-enable -C debuginfo=2. We need debug info in order to know where code originated.
+        command.arg("-C").arg("debuginfo=2");
+        let linker = LinkerChoice::new(rustc_args.linker, configured_linker);
+        if let Some(program) = &linker.program {
+            command.env(ORIG_LINKER_ENV, program);
+            // Rustc decides what arguments to pass to the linker based on its name, so now that
+            // it's going to be running us instead, we need to tell it what it would have decided.
+            if !rustc_args.has_linker_flavor {
+                if let Some(flavor) = linker_flavor(program) {
+                    command.arg("-C").arg(format!("linker-flavor={flavor}"));
                 }
             }
-            if arg.starts_with("--error-format") {
-                continue;
-            }
-            // For all other arguments, pass them through.
-            command.arg(arg);
         }
-        // Force-enable -C debuginfo=2. We need debug info in order to know where code originated.
-        command.arg("-C").arg("debuginfo=2");
-        if let Some(orig_linker) = orig_linker_arg {
-            command.env(super::ORIG_LINKER_ENV, orig_linker);
+        if let Some(fuse_ld) = &linker.fuse_ld {
+            command.env(FUSE_LD_ENV, fuse_ld);
         }
+        let mut linker_arg = OsString::new();
         linker_arg.push("linker=");
         linker_arg.push(cackle_exe()?);
         command.arg("--error-format=json");
@@ -333,6 +492,15 @@ impl RustcRunner {
         command.arg("-C").arg("save-temps");
         command.arg("-Ccodegen-units=1");
         command.env(ENV_CRATE_KIND, self.crate_sel.selector_token());
+        if is_foreign_target()? {
+            command.env(ENV_FOREIGN_TARGET, "1");
+        }
+        if flag_values(&rustc_args.args, "--crate-type")
+            .flat_map(|types| types.split(','))
+            .any(|crate_type| crate_type == "proc-macro")
+        {
+            command.env(ENV_PROC_MACRO, "1");
+        }
         if !unsafe_permitted {
             command.arg("-Funsafe-code");
         }
@@ -340,11 +508,126 @@ impl RustcRunner {
     }
 }
 
+/// The arguments that cargo passed to rustc, minus those that we override.
+#[derive(Default, Debug, PartialEq, Eq)]
+struct RustcArgs {
+    /// Arguments to pass through to rustc.
+    args: Vec<String>,
+
+    /// The linker from `-C linker=...`, if any.
+    linker: Option<String>,
+
+    /// Whether a linker flavor was specified with `-C linker-flavor=...`.
+    has_linker_flavor: bool,
+}
+
+impl RustcArgs {
+    fn parse(args: impl Iterator<Item = String>) -> Self {
+        let mut args = args.peekable();
+        let mut parsed = RustcArgs::default();
+        while let Some(arg) = args.next() {
+            // Codegen options can be given as `-C opt`, `-Copt`, `--codegen opt` or
+            // `--codegen=opt`, e.g. depending on whether they came from cargo or from RUSTFLAGS.
+            let (option, is_separate) = if arg == "-C" || arg == "--codegen" {
+                (args.peek().cloned(), true)
+            } else {
+                (
+                    arg.strip_prefix("-C")
+                        .or_else(|| arg.strip_prefix("--codegen="))
+                        .map(ToOwned::to_owned),
+                    false,
+                )
+            };
+            if let Some(option) = option {
+                parsed.has_linker_flavor |= option.starts_with("linker-flavor=");
+                // If we find the linker, note the value for later use and drop the argument. Also
+                // drop debuginfo, so that we can add our own value at the end.
+                let drop = if let Some(linker) = option.strip_prefix("linker=") {
+                    parsed.linker = Some(linker.to_owned());
+                    true
+                } else {
+                    option.starts_with("debuginfo=")
+                };
+                if drop {
+                    if is_separate {
+                        args.next();
+                    }
+                    continue;
+                }
+            }
+            if arg.starts_with("--error-format") {
+                continue;
+            }
+            // For all other arguments, pass them through.
+            parsed.args.push(arg);
+        }
+        parsed
+    }
+}
+
+/// Linkers that are selected by passing `-fuse-ld` to the C compiler driver, rather than being
+/// invoked directly.
+const FUSE_LD_LINKERS: &[&str] = &["mold", "lld", "gold", "bfd"];
+
+/// How we should invoke the real linker.
+#[derive(Debug, PartialEq, Eq)]
+struct LinkerChoice {
+    /// The program to run. If `None`, we guess the default.
+    program: Option<String>,
+
+    /// A linker for the program to use via `-fuse-ld`.
+    fuse_ld: Option<String>,
+}
+
+impl LinkerChoice {
+    /// Chooses based on the linker that was configured for cargo, if any, and `linker` from our
+    /// config, which takes precedence.
+    fn new(cargo_linker: Option<String>, configured_linker: Option<&str>) -> Self {
+        match configured_linker {
+            Some(linker) if FUSE_LD_LINKERS.contains(&linker) => Self {
+                program: cargo_linker,
+                fuse_ld: Some(linker.to_owned()),
+            },
+            Some(linker) => Self {
+                program: Some(linker.to_owned()),
+                fuse_ld: None,
+            },
+            None => Self {
+                program: cargo_linker,
+                fuse_ld: None,
+            },
+        }
+    }
+}
+
+/// Returns the linker flavor that rustc would infer from the name of `linker`, if it would infer
+/// one rather than using the target's default. This follows rustc's own logic.
+fn linker_flavor(linker: &str) -> Option<&'static str> {
+    let stem = Path::new(linker).file_stem()?.to_str()?;
+    if stem == "gcc" || stem.ends_with("-gcc") || stem == "clang" || stem.ends_with("-clang") {
+        Some("gcc")
+    } else if stem == "wasm-ld" || stem.ends_with("-wasm-ld") {
+        Some("wasm-ld")
+    } else if stem == "ld" || stem.ends_with("-ld") {
+        Some("ld")
+    } else if stem == "lld-link" {
+        Some("lld-link")
+    } else if stem == "link" {
+        Some("msvc")
+    } else {
+        None
+    }
+}
+
 /// Searches for the unsafe keyword in the specified paths.
 fn find_unsafe_in_sources(paths: &[PathBuf]) -> Result<Vec<SourceLocation>> {
     let mut locations = Vec::new();
     for file in paths {
-        locations.append(&mut unsafe_checker::scan_path(file)?);
+        locations.extend(
+            unsafe_checker::scan_path(file)?
+                .into_iter()
+                .map(|site| site.location),
+        );
     }
     Ok(locations)
 }
@@ -360,7 +643,12 @@ fn proxy_linker(
     // Invoke the actual linker first, since the parent process uses the output file to aid with
     // analysis.
     let exit_status = invoke_real_linker(args)?;
-    if exit_status.is_ok() && link_info.is_executable() {
+    // Binaries for other targets, e.g. embedded targets, can't be run here, so there's no point
+    // wrapping them. Doing so would also replace the binary with something that can't be flashed.
+    if exit_status.is_ok()
+        && link_info.is_executable()
+        && std::env::var_os(ENV_FOREIGN_TARGET).is_none()
+    {
         setup_bin_wrapper(&mut link_info)?;
     }
     // We ignore the return value here since this is an infallible operation. The parent process
@@ -370,26 +658,75 @@ fn proxy_linker(
 }
 
 fn invoke_real_linker(
-    args: std::iter::Peekable<std::env::Args>,
+    mut args: std::iter::Peekable<std::env::Args>,
 ) -> Result<ExitCode, anyhow::Error> {
-    let orig_linker = std::env::var(super::ORIG_LINKER_ENV)
+    let orig_linker = std::env::var(ORIG_LINKER_ENV)
         .ok()
-        .unwrap_or_else(default_linker);
+        .unwrap_or_else(|| default_linker(args.peek().map(String::as_str)));
     let mut command = Command::new(orig_linker);
+    // If rustc is running an LLD-flavoured linker directly, there's no C compiler driver to pass
+    // `-fuse-ld` to.
+    if args.peek().map(String::as_str) != Some("-flavor") {
+        if let Ok(fuse_ld) = std::env::var(FUSE_LD_ENV) {
+            command.arg(format!("-fuse-ld={fuse_ld}"));
+        }
+    }
     command.args(args);
     run_command(&mut command)
 }
 
 /// Returns our best guess as to the default linker.
-fn default_linker() -> String {
-    // Ideally we'd have a way to ask rustc what linker it wants to use, for now we just guess.
-    "cc".to_owned()
+fn default_linker(first_arg: Option<&str>) -> String {
+    // Ideally we'd have a way to ask rustc what linker it wants to use, for now we just guess. If
+    // the target's linker flavour is LLD, e.g. for bare-metal targets like `thumbv7em-none-eabihf`,
+    // then rustc passes `-flavor` as the first argument. In that case rustc would have run
+    // `rust-lld`, which it makes available on the linker's PATH.
+    if first_arg == Some("-flavor") {
+        "rust-lld".to_owned()
+    } else {
+        "cc".to_owned()
+    }
+}
+
+/// Returns whether the rustc that we're proxying is compiling for a target other than the host.
+/// Cargo only passes `--target` when a target was requested and even then, not for build scripts
+/// or proc macros.
+fn is_foreign_target() -> Result<bool> {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(target) = flag_values(&args, "--target").last() else {
+        return Ok(false);
+    };
+    let host = std::env::var(super::HOST_TARGET_ENV)
+        .with_context(|| format!("{} not set", super::HOST_TARGET_ENV))?;
+    Ok(target != host)
+}
+
+/// Returns whether `rustc_args` are for building part of the standard library or one of its
+/// dependencies with build-std. Cargo passes `-Zforce-unstable-if-unmarked` only when building such
+/// crates.
+fn is_build_std_crate(rustc_args: &[String]) -> bool {
+    rustc_args
+        .iter()
+        .any(|arg| arg == "-Zforce-unstable-if-unmarked")
+        || flag_values(rustc_args, "-Z").any(|flag| flag == "force-unstable-if-unmarked")
+}
+
+/// Returns the values supplied for `flag` in `args`, whether as `--flag value` or `--flag=value`.
+fn flag_values<'a>(args: &'a [String], flag: &'a str) -> impl Iterator<Item = &'a str> {
+    args.iter().enumerate().filter_map(move |(index, arg)| {
+        if arg == flag {
+            args.get(index + 1).map(String::as_str)
+        } else {
+            arg.strip_prefix(flag)?.strip_prefix('=')
+        }
+    })
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
 pub(crate) struct SubprocessConfig {
     permissions: Permissions,
     rustc: RustcConfig,
+    linker: Option<String>,
 }
 
 impl SubprocessConfig {
@@ -404,9 +741,26 @@ impl SubprocessConfig {
         Self {
             permissions: full_config.permissions.clone(),
             rustc: full_config.raw.rustc.clone(),
+            linker: full_config.raw.common.linker.clone(),
         }
     }
 
+    /// Returns the rustc config to use when compiling `crate_sel`. Settings from `[pkg.N.rustc]`
+    /// take precedence over those from `[rustc]`.
+    fn rustc_config(&self, crate_sel: &CrateSel) -> RustcConfig {
+        let mut rustc = self
+            .permissions
+            .get(&PermSel::for_non_build_output(crate_sel))
+            .or_else(|| {
+                self.permissions
+                    .get(&PermSel::for_primary(crate_sel.pkg_id.pkg_name()))
+            })
+            .and_then(|pkg_config| pkg_config.rustc.clone())
+            .unwrap_or_default();
+        rustc.sandbox.inherit(&self.rustc.sandbox);
+        rustc
+    }
+
     fn parse_file(path: &Path) -> Result<Self> {
         let toml = crate::fs::read_to_string(path)?;
         Self::deserialise(&toml)
@@ -436,16 +790,188 @@ fn test_orig_bin_path() {
     );
 }
 
+#[test]
+fn test_wrapped_bin() {
+    let dir = tempfile::tempdir().unwrap();
+    let wrapper = dir.path().join("foo");
+    std::fs::write(
+        &wrapper,
+        bin_wrapper_script("/bin/cackle", "token", "/target/foo.orig"),
+    )
+    .unwrap();
+    assert_eq!(
+        wrapped_bin(&wrapper),
+        Some(PathBuf::from("/target/foo.orig"))
+    );
+    std::fs::write(&wrapper, "#!/bin/bash\nexec other\n").unwrap();
+    assert_eq!(wrapped_bin(&wrapper), None);
+    assert_eq!(wrapped_bin(&dir.path().join("missing")), None);
+}
+
+#[test]
+fn test_flag_values() {
+    let args: Vec<String> = [
+        "rustc",
+        "--crate-type",
+        "lib",
+        "--crate-type=staticlib,rlib",
+        "--target",
+        "thumbv7em-none-eabihf",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+    assert_eq!(
+        flag_values(&args, "--crate-type").collect::<Vec<_>>(),
+        vec!["lib", "staticlib,rlib"]
+    );
+    assert_eq!(
+        flag_values(&args, "--target").collect::<Vec<_>>(),
+        vec!["thumbv7em-none-eabihf"]
+    );
+    assert_eq!(default_linker(Some("-flavor")), "rust-lld");
+    assert_eq!(default_linker(Some("-m64")), "cc");
+}
+
+#[test]
+fn test_rustc_args() {
+    let parse = |args: &[&str]| RustcArgs::parse(args.iter().map(|arg| arg.to_string()));
+    assert_eq!(
+        parse(&[
+            "--crate-name",
+            "foo",
+            "-C",
+            "linker=clang",
+            "-Cdebuginfo=1",
+            "--error-format=json",
+            "-C",
+            "link-arg=-fuse-ld=mold",
+        ]),
+        RustcArgs {
+            args: vec![
+                "--crate-name".to_owned(),
+                "foo".to_owned(),
+                "-C".to_owned(),
+                "link-arg=-fuse-ld=mold".to_owned()
+            ],
+            linker: Some("clang".to_owned()),
+            has_linker_flavor: false,
+        }
+    );
+    let parsed = parse(&["-Clinker=/bin/my-linker.sh", "--codegen=linker-flavor=ld"]);
+    assert_eq!(parsed.linker.as_deref(), Some("/bin/my-linker.sh"));
+    assert!(parsed.has_linker_flavor);
+    assert_eq!(
+        parse(&["--codegen", "linker=cc"]).linker.as_deref(),
+        Some("cc")
+    );
+}
+
+#[test]
+fn test_linker_choice() {
+    assert_eq!(
+        LinkerChoice::new(Some("clang".to_owned()), Some("mold")),
+        LinkerChoice {
+            program: Some("clang".to_owned()),
+            fuse_ld: Some("mold".to_owned()),
+        }
+    );
+    assert_eq!(
+        LinkerChoice::new(Some("clang".to_owned()), Some("/bin/my-linker.sh")),
+        LinkerChoice {
+            program: Some("/bin/my-linker.sh".to_owned()),
+            fuse_ld: None,
+        }
+    );
+    assert_eq!(
+        LinkerChoice::new(None, None),
+        LinkerChoice {
+            program: None,
+            fuse_ld: None,
+        }
+    );
+    assert_eq!(linker_flavor("/usr/bin/aarch64-linux-gnu-gcc"), Some("gcc"));
+    assert_eq!(linker_flavor("ld.lld"), Some("ld"));
+    assert_eq!(linker_flavor("clang"), Some("gcc"));
+    assert_eq!(linker_flavor("cc"), None);
+    assert_eq!(linker_flavor("/bin/my-linker.sh"), None);
+}
+
+#[test]
+fn test_is_build_std_crate() {
+    let args = |args: &[&str]| -> Vec<String> { args.iter().map(|arg| arg.to_string()).collect() };
+    assert!(is_build_std_crate(&args(&[
+        "rustc",
+        "--crate-name",
+        "core",
+        "-Z",
+        "force-unstable-if-unmarked",
+    ])));
+    assert!(is_build_std_crate(&args(&[
+        "rustc",
+        "-Zforce-unstable-if-unmarked"
+    ])));
+    assert!(!is_build_std_crate(&args(&[
+        "rustc",
+        "--crate-name",
+        "foo",
+        "-Z",
+        "unstable-options",
+    ])));
+}
+
 #[test]
 fn config_roundtrips() {
     let crate_root = std::path::PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
     let test_crates_dir = crate_root.join("test_crates");
-    let crate_index = crate::crate_index::CrateIndex::new(&test_crates_dir).unwrap();
-    let full_config =
-        crate::config::parse_file(&test_crates_dir.join("cackle.toml"), &crate_index).unwrap();
+    let crate_index = crate::crate_index::CrateIndex::new(
+        &[test_crates_dir.join("Cargo.toml")],
+        Default::default(),
+    )
+    .unwrap();
+    let full_config = crate::config::parse_file(
+        &crate::config_file::ConfigFile::new(test_crates_dir.join("cackle.toml"), false, None),
+        &[],
+        &crate_index,
+        None,
+    )
+    .unwrap();
     let subprocess_config = SubprocessConfig::from_full_config(&full_config);
 
     let roundtripped_config =
         SubprocessConfig::deserialise(&subprocess_config.serialise().unwrap()).unwrap();
     assert_eq!(subprocess_config, roundtripped_config);
 }
+
+#[test]
+fn per_package_rustc_config() {
+    use crate::config::SandboxKind;
+    use crate::crate_index::testing::pkg_id;
+
+    let config = crate::config::testing::parse(
+        r#"
+        [rustc.sandbox]
+        kind = "Disabled"
+        extra_args = ["--unshare-ipc"]
+
+        [pkg.foo.rustc.sandbox]
+        kind = "Bubblewrap"
+        allow_network = true
+
+        [pkg.bar]
+    "#,
+    )
+    .unwrap();
+    let subprocess_config = SubprocessConfig::from_full_config(&config);
+
+    let foo = subprocess_config.rustc_config(&CrateSel::primary(pkg_id("foo")));
+    assert_eq!(foo.sandbox.kind, Some(SandboxKind::Bubblewrap));
+    assert_eq!(foo.sandbox.allow_network, Some(true));
+    assert_eq!(foo.sandbox.extra_args, ["--unshare-ipc"]);
+    let foo_build = subprocess_config.rustc_config(&CrateSel::build_script(pkg_id("foo")));
+    assert_eq!(foo_build, foo);
+
+    let bar = subprocess_config.rustc_config(&CrateSel::primary(pkg_id("bar")));
+    assert_eq!(bar.sandbox.kind, Some(SandboxKind::Disabled));
+    assert_eq!(bar.sandbox.allow_network, None);
+}
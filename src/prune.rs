@@ -0,0 +1,164 @@
+//! Detection and removal of config entries for packages that are no longer in the dependency tree.
+//! Unlike the unused-config checks done during a full check, this only needs the dependency tree
+//! as resolved by cargo, so doesn't build anything.
+
+use crate::config::permissions::PermSel;
+use crate::config::ApiName;
+use crate::config::Config;
+use crate::config::PackageName;
+use crate::config_editor::ConfigEditor;
+use crate::config_file::ConfigFile;
+use crate::crate_index::CrateIndex;
+use anyhow::Result;
+use clap::Parser;
+use std::fmt::Display;
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct PruneOptions {
+    /// Report stale entries without removing them. Exits with a non-zero status if any are found.
+    #[clap(long)]
+    check: bool,
+}
+
+/// A config entry that refers to a package that's no longer in the dependency tree.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum StaleEntry {
+    /// A `[pkg.*]` table.
+    PkgConfig(PermSel),
+    /// An entry in an API's `no_auto_detect` list.
+    NoAutoDetect(ApiName, PackageName),
+}
+
+/// Finds stale entries in `config`, then either reports or removes them. Returns whether the config
+/// is free of stale entries once we're done.
+pub(crate) fn prune(
+    config_file: &ConfigFile,
+    config: &Config,
+    crate_index: &CrateIndex,
+    options: &PruneOptions,
+) -> Result<bool> {
+    let stale = find_stale(config, crate_index);
+    if stale.is_empty() {
+        println!("No stale entries in {}", config_file.path().display());
+        return Ok(true);
+    }
+    if options.check {
+        for entry in &stale {
+            println!("Stale: {entry}");
+        }
+        return Ok(false);
+    }
+    config_file.edit(|editor| {
+        for entry in &stale {
+            entry.remove(editor)?;
+        }
+        Ok(())
+    })?;
+    for entry in &stale {
+        println!("Removed: {entry}");
+    }
+    Ok(true)
+}
+
+fn find_stale(config: &Config, crate_index: &CrateIndex) -> Vec<StaleEntry> {
+    let mut stale: Vec<StaleEntry> = config
+        .permissions_no_inheritance
+        .packages
+        .keys()
+        // Patterns are checked to match at least one package when the config is loaded.
+        .filter(|perm_sel| {
+            !perm_sel.package_name.is_pattern()
+                && !crate_index.permission_selectors.contains(perm_sel)
+        })
+        .map(|perm_sel| StaleEntry::PkgConfig(perm_sel.clone()))
+        .collect();
+    for (api, api_config) in &config.raw.apis {
+        for pkg_name in &api_config.no_auto_detect {
+            if crate_index.newest_package_id_with_name(pkg_name).is_none() {
+                stale.push(StaleEntry::NoAutoDetect(api.clone(), pkg_name.clone()));
+            }
+        }
+    }
+    stale.sort();
+    stale
+}
+
+impl StaleEntry {
+    fn remove(&self, editor: &mut ConfigEditor) -> Result<()> {
+        match self {
+            StaleEntry::PkgConfig(perm_sel) => editor.remove_pkg_config(perm_sel),
+            StaleEntry::NoAutoDetect(api, pkg_name) => editor.remove_no_auto_detect(api, pkg_name),
+        }
+    }
+}
+
+impl Display for StaleEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StaleEntry::PkgConfig(perm_sel) => write!(f, "[pkg.{perm_sel}]"),
+            StaleEntry::NoAutoDetect(api, pkg_name) => {
+                write!(f, "`{pkg_name}` in api.{api}.no_auto_detect")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::parse;
+    use crate::crate_index::testing::index_with_package_names;
+    use indoc::indoc;
+
+    #[test]
+    fn remove_stale_entries() {
+        let toml = indoc! {r#"
+            [api.net]
+            include = ["std::net"]
+            no_auto_detect = ["a", "gone"]
+
+            [api.fs]
+            include = ["std::fs"]
+            no_auto_detect = ["gone"]
+
+            [pkg.a]
+            allow_apis = ["net"]
+            build.allow_apis = ["fs"]
+
+            [pkg.gone]
+            allow_apis = ["fs"]
+        "#};
+        let config = parse(toml).unwrap();
+        let crate_index = index_with_package_names(&["a"]);
+        let stale = find_stale(&config, &crate_index);
+        let stale_strings: Vec<String> = stale.iter().map(|entry| entry.to_string()).collect();
+        assert_eq!(
+            stale_strings,
+            vec![
+                "[pkg.a.build]",
+                "[pkg.gone]",
+                "`gone` in api.fs.no_auto_detect",
+                "`gone` in api.net.no_auto_detect",
+            ]
+        );
+
+        let mut editor = ConfigEditor::from_toml_string(toml).unwrap();
+        for entry in &stale {
+            entry.remove(&mut editor).unwrap();
+        }
+        assert_eq!(
+            editor.to_toml(),
+            indoc! {r#"
+                [api.net]
+                include = ["std::net"]
+                no_auto_detect = ["a"]
+
+                [api.fs]
+                include = ["std::fs"]
+
+                [pkg.a]
+                allow_apis = ["net"]
+            "#}
+        );
+    }
+}
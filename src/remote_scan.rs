@@ -0,0 +1,406 @@
+//! Offloading of the scanning of linked binaries to workers on other machines. Scanning is the most
+//! memory-hungry part of the analysis, so this lets memory-constrained machines such as CI runners
+//! hand it off to bigger hosts. Workers are started with `cargo acl scan-worker` and used by passing
+//! `--scan-worker` to the analysis.
+//!
+//! The protocol is a `Hello` from the client, an acceptance (or rejection) from the worker, then a
+//! `ScanJob` and the contents of the binary and each object file from the client, then finally the
+//! `ScanOutputs` from the worker. Workers only accept clients that present the token from
+//! `TOKEN_ENV`, which must be set to the same secret on both sides.
+
+use crate::checker::api_definitions;
+use crate::checker::Checker;
+use crate::config::ApiConfig;
+use crate::config::ApiName;
+use crate::config::Config;
+use crate::crate_index::CrateIndex;
+use crate::crate_index::PackageId;
+use crate::crate_index::ScanIndex;
+use crate::fs::PathRemap;
+use crate::link_info::LinkInfo;
+use crate::proxy::rpc::read_from_stream;
+use crate::proxy::rpc::read_from_stream_with_limit;
+use crate::proxy::rpc::write_to_stream;
+use crate::semaphore::Semaphore;
+use crate::symbol_graph::ScanOutputs;
+use crate::tmpdir::TempDir;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use fxhash::FxHashMap;
+use log::info;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The environment variable holding the secret that clients present to workers.
+const TOKEN_ENV: &str = "CACKLE_SCAN_WORKER_TOKEN";
+
+/// The largest `Hello` that a worker will read from a client that hasn't yet authenticated.
+const MAX_HELLO_LEN: usize = 4096;
+
+/// The most bytes of files that a worker will receive for a single job.
+const MAX_JOB_FILES_LEN: u64 = 8 << 30;
+
+/// How long a worker waits for a client to send more data before giving up on the job.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct ScanWorkerOptions {
+    /// The address to listen on. Defaults to only accepting connections from this machine, e.g. via
+    /// an SSH tunnel. Use `0.0.0.0:7890` to accept connections from other hosts.
+    #[clap(long, default_value = "127.0.0.1:7890")]
+    listen: String,
+}
+
+/// The first message that a client sends to a worker.
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    /// The version of cackle that the client is running. Workers only accept jobs from the same
+    /// version, since the format of `ScanOutputs` isn't stable.
+    version: String,
+    /// Must match the worker's token.
+    token: String,
+}
+
+/// Everything other than the contents of files that a worker needs in order to scan a binary.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ScanJob {
+    link_info: LinkInfo,
+    paths: Vec<PathBuf>,
+    apis: BTreeMap<ApiName, ApiConfig>,
+    index: ScanIndex,
+    path_to_pkg_ids: FxHashMap<PathBuf, Vec<PackageId>>,
+    path_remaps: Vec<PathRemap>,
+    sysroot: PathBuf,
+    std_path_prefixes: Vec<PathBuf>,
+}
+
+impl ScanJob {
+    pub(crate) fn new(
+        link_info: &LinkInfo,
+        paths: &[PathBuf],
+        config: &Config,
+        crate_index: &CrateIndex,
+        path_to_pkg_ids: FxHashMap<PathBuf, Vec<PackageId>>,
+        path_remaps: Vec<PathRemap>,
+        sysroot: &Path,
+    ) -> Self {
+        Self {
+            link_info: link_info.clone(),
+            paths: paths.to_owned(),
+            apis: api_definitions(config).into_owned(),
+            index: crate_index.scan_index(),
+            path_to_pkg_ids,
+            path_remaps,
+            sysroot: sysroot.to_owned(),
+            std_path_prefixes: config.raw.common.std_path_prefixes.clone(),
+        }
+    }
+
+    pub(crate) fn link_info(&self) -> &LinkInfo {
+        &self.link_info
+    }
+
+    pub(crate) fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    pub(crate) fn apis(&self) -> &BTreeMap<ApiName, ApiConfig> {
+        &self.apis
+    }
+
+    /// Adds additional prefixes under which source files are treated as being from the standard
+    /// library, e.g. directories of packages built with build-std.
+    pub(crate) fn add_std_path_prefixes(&mut self, prefixes: &[PathBuf]) {
+        self.std_path_prefixes.extend(prefixes.iter().cloned());
+    }
+}
+
+/// Sends `job` to one of `workers` and returns the outputs of the scan. Workers are used in turn.
+/// Paths in the outputs refer to where the worker put the files, so the caller needs to fix them
+/// up with `ScanOutputs::set_bin`.
+pub(crate) fn scan(workers: &[String], job: &ScanJob) -> Result<ScanOutputs> {
+    static NEXT_WORKER: AtomicUsize = AtomicUsize::new(0);
+    let worker = &workers[NEXT_WORKER.fetch_add(1, Ordering::Relaxed) % workers.len()];
+    info!(
+        "Sending scan of {} to {worker}",
+        job.link_info.output_file.display()
+    );
+    let mut stream = connect(worker, &token_from_env()?)?;
+    write_to_stream(job, &mut stream)?;
+    send_file(&job.link_info.output_file, &mut stream)?;
+    for path in &job.paths {
+        send_file(path, &mut stream)?;
+    }
+    let response: Result<ScanOutputs, String> = read_from_stream(&mut stream)?;
+    response.map_err(|error| anyhow!("Scan worker `{worker}`: {error}"))
+}
+
+/// Connects to `worker` and authenticates with `token`.
+fn connect(worker: &str, token: &str) -> Result<TcpStream> {
+    let mut stream = TcpStream::connect(worker)
+        .with_context(|| format!("Failed to connect to scan worker `{worker}`"))?;
+    write_to_stream(
+        &Hello {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            token: token.to_owned(),
+        },
+        &mut stream,
+    )?;
+    let accepted: Result<(), String> = read_from_stream(&mut stream)?;
+    accepted.map_err(|error| anyhow!("Scan worker `{worker}` rejected connection: {error}"))?;
+    Ok(stream)
+}
+
+fn token_from_env() -> Result<String> {
+    std::env::var(TOKEN_ENV)
+        .ok()
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| {
+            anyhow!("{TOKEN_ENV} must be set to a secret shared by scan workers and their clients")
+        })
+}
+
+/// Accepts scan jobs until we're killed. At most `analysis_threads` jobs are scanned at once,
+/// defaulting to the number of CPUs. Connections beyond that wait to be accepted until a scan
+/// finishes.
+pub(crate) fn run_worker(
+    options: &ScanWorkerOptions,
+    analysis_threads: Option<NonZeroUsize>,
+) -> Result<()> {
+    let token = token_from_env()?;
+    let listener = TcpListener::bind(&options.listen)
+        .with_context(|| format!("Failed to listen on `{}`", options.listen))?;
+    println!("Listening on {}", listener.local_addr()?);
+    let analysis_threads = analysis_threads
+        .or_else(|| std::thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get);
+    let scan_slots = Semaphore::new(analysis_threads);
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let mut stream = stream?;
+            // Acquiring before spawning means that we don't start a thread per pending connection.
+            let permit = scan_slots.acquire();
+            let token = &token;
+            scope.spawn(move || {
+                let _permit = permit;
+                if let Err(error) = handle_connection(&mut stream, token) {
+                    println!("Failed to handle scan job: {error:#}");
+                }
+            });
+        }
+        Ok(())
+    })
+}
+
+fn handle_connection(stream: &mut TcpStream, token: &str) -> Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let hello: Hello = read_from_stream_with_limit(stream, MAX_HELLO_LEN)?;
+    if !tokens_match(&hello.token, token) {
+        write_to_stream(&Err::<(), _>("Invalid token".to_owned()), stream)?;
+        bail!("Client {} sent an invalid token", stream.peer_addr()?);
+    }
+    if hello.version != env!("CARGO_PKG_VERSION") {
+        let error = format!(
+            "Worker is running version {} of cackle, but client is running version {}",
+            env!("CARGO_PKG_VERSION"),
+            hello.version
+        );
+        return write_to_stream(&Err::<(), _>(error), stream);
+    }
+    write_to_stream(&Ok::<_, String>(()), stream)?;
+    let job: ScanJob = read_from_stream(stream)?;
+    info!(
+        "Scanning {} for {}",
+        job.link_info.output_file.display(),
+        stream.peer_addr()?
+    );
+    let response = run_job(job, stream).map_err(|error| format!("{error:#}"));
+    write_to_stream(&response, stream)
+}
+
+/// Receives the files for `job` from `stream`, then scans them.
+fn run_job(job: ScanJob, stream: &mut TcpStream) -> Result<ScanOutputs> {
+    let tmpdir = Arc::new(TempDir::new(None)?);
+    let mut link_info = job.link_info;
+    let bin_path = local_path(tmpdir.path(), "bin", &link_info.output_file)?;
+    let mut remaining = MAX_JOB_FILES_LEN;
+    remaining -= receive_file(stream, &bin_path, remaining)?;
+    // Object files from different directories may have the same name, so each gets its own
+    // directory.
+    let mut paths = Vec::with_capacity(job.paths.len());
+    for (index, path) in job.paths.iter().enumerate() {
+        let local = local_path(tmpdir.path(), &index.to_string(), path)?;
+        remaining -= receive_file(stream, &local, remaining)?;
+        paths.push(local);
+    }
+    link_info.output_file = Arc::from(bin_path.as_path());
+    link_info.object_paths.clone_from(&paths);
+    let job = ScanJob {
+        link_info,
+        paths,
+        ..job
+    };
+    scan_job(&job, tmpdir)
+}
+
+/// Scans `job` on this machine without needing access to the checker. This lets several binaries
+/// be scanned at once. As with remote scans, the caller needs to call `ScanOutputs::set_bin`.
+pub(crate) fn scan_locally(job: &ScanJob) -> Result<ScanOutputs> {
+    scan_job(job, Arc::new(TempDir::new(None)?))
+}
+
+fn scan_job(job: &ScanJob, tmpdir: Arc<TempDir>) -> Result<ScanOutputs> {
+    let mut checker = Checker::for_remote_scan(
+        tmpdir,
+        Arc::from(job.sysroot.as_path()),
+        CrateIndex::from_scan_index(job.index.clone()),
+        job.apis.clone(),
+        job.std_path_prefixes.clone(),
+        job.path_to_pkg_ids.clone(),
+        job.path_remaps.clone(),
+    );
+    let (outputs, _) = crate::symbol_graph::scan_objects(&job.paths, &job.link_info, &mut checker)?;
+    Ok(outputs)
+}
+
+fn local_path(tmpdir: &Path, subdir: &str, remote_path: &Path) -> Result<PathBuf> {
+    let file_name = remote_path
+        .file_name()
+        .ok_or_else(|| anyhow!("Path `{}` has no file name", remote_path.display()))?;
+    let dir = tmpdir.join(subdir);
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    Ok(dir.join(file_name))
+}
+
+/// Writes the contents of the file at `path` to `stream`, preceded by its length. We stream the
+/// file rather than reading it into memory, since the whole point is to save memory locally.
+fn send_file(path: &Path, stream: &mut impl Write) -> Result<()> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open `{}`", path.display()))?;
+    let len = file.metadata()?.len();
+    stream.write_all(&len.to_le_bytes())?;
+    let copied = std::io::copy(&mut file, stream)
+        .with_context(|| format!("Failed to send `{}`", path.display()))?;
+    if copied != len {
+        bail!("`{}` changed size while being sent", path.display());
+    }
+    Ok(())
+}
+
+/// Reads a file written by `send_file` and writes it to `path`. Fails without writing anything if
+/// the file is larger than `max_len`. Returns the length of the file.
+fn receive_file(stream: &mut impl Read, path: &Path, max_len: u64) -> Result<u64> {
+    let mut len_bytes = [0u8; std::mem::size_of::<u64>()];
+    stream.read_exact(&mut len_bytes)?;
+    let len = u64::from_le_bytes(len_bytes);
+    if len > max_len {
+        bail!(
+            "`{}` is {len} bytes, which exceeds the remaining limit of {max_len} bytes",
+            path.display()
+        );
+    }
+    let mut file =
+        File::create(path).with_context(|| format!("Failed to create `{}`", path.display()))?;
+    let copied = std::io::copy(&mut stream.by_ref().take(len), &mut file)
+        .with_context(|| format!("Failed to write `{}`", path.display()))?;
+    if copied != len {
+        bail!("Connection closed while receiving `{}`", path.display());
+    }
+    Ok(len)
+}
+
+/// Compares tokens in time that doesn't depend on where they differ.
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_and_receive_file() {
+        let dir = TempDir::new(None).unwrap();
+        let source = dir.path().join("source");
+        std::fs::write(&source, b"object file contents").unwrap();
+        let mut buf = Vec::new();
+        send_file(&source, &mut buf).unwrap();
+        send_file(&source, &mut buf).unwrap();
+
+        let mut stream = buf.as_slice();
+        let dest = dir.path().join("dest");
+        assert_eq!(receive_file(&mut stream, &dest, 100).unwrap(), 20);
+        assert_eq!(std::fs::read(&dest).unwrap(), b"object file contents");
+        receive_file(&mut stream, &dest, 100).unwrap();
+        assert!(stream.is_empty());
+
+        assert!(receive_file(&mut &buf[..10], &dest, 100).is_err());
+    }
+
+    #[test]
+    fn oversized_file() {
+        let dir = TempDir::new(None).unwrap();
+        let source = dir.path().join("source");
+        std::fs::write(&source, b"object file contents").unwrap();
+        let mut buf = Vec::new();
+        send_file(&source, &mut buf).unwrap();
+
+        let dest = dir.path().join("dest");
+        assert!(receive_file(&mut buf.as_slice(), &dest, 19).is_err());
+        assert!(!dest.exists());
+    }
+
+    #[test]
+    fn invalid_token_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let worker = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            handle_connection(&mut stream, "secret")
+        });
+        let error = connect(&addr, "guess").unwrap_err();
+        assert!(format!("{error:#}").contains("Invalid token"));
+        assert!(worker.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn oversized_hello_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let worker = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            handle_connection(&mut stream, "secret")
+        });
+        let token = "a".repeat(MAX_HELLO_LEN);
+        assert!(connect(&addr, &token).is_err());
+        assert!(worker.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn token_comparison() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secret", "secreT"));
+        assert!(!tokens_match("secret", "secrets"));
+    }
+}
@@ -0,0 +1,191 @@
+//! A complete record of an analysis, written when `--save-report` is passed. Commands such as
+//! `resolve` and `why` can be pointed at a saved report with `--report`, in which case they operate
+//! on it rather than on whatever the most recent run left in the target directory. This makes it
+//! possible to, for example, look into problems found by CI without reproducing its build.
+
+use crate::checker::Checker;
+use crate::config_editor::fixes_for_problem;
+use crate::problem::Problem;
+use crate::problem::ProblemList;
+use crate::provenance::ProvenanceStore;
+use crate::summary::Summary;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The version of the report format. Incremented whenever the format changes in a way that would
+/// stop older reports from being read.
+const REPORT_VERSION: u32 = 1;
+
+/// What we write. Borrows from the checker where it can, since some parts can be large.
+#[derive(Serialize)]
+struct SavedReport<'a> {
+    version: u32,
+    /// The version of cackle that wrote the report.
+    cackle_version: &'static str,
+    /// Seconds since the Unix epoch when the report was written.
+    timestamp: u64,
+    /// Whether the analysis completed without any unresolved problems.
+    success: bool,
+    /// The contents of the config file at the end of the analysis.
+    config: &'a str,
+    /// Problems that were unresolved at the end of the analysis.
+    problems: &'a [Problem],
+    /// The fixes available for each of `problems`, in the same order.
+    fixes: Vec<ProblemFixes>,
+    /// The distinct usages of each API, keyed by permission selector then by API.
+    api_usages: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+    /// The packages in the dependency tree and the permissions each has been granted.
+    #[serde(flatten)]
+    packages: Summary,
+    provenance: ProvenanceStore,
+    /// Seconds spent in each part of the analysis.
+    timings: BTreeMap<&'static str, f64>,
+}
+
+#[derive(Serialize)]
+struct ProblemFixes {
+    problem: String,
+    fixes: Vec<Fix>,
+}
+
+/// A fix that can be applied with `cargo acl apply-fix`.
+#[derive(Serialize)]
+struct Fix {
+    id: String,
+    title: String,
+}
+
+/// The parts of a saved report that commands read back.
+#[derive(Deserialize)]
+pub(crate) struct Report {
+    version: u32,
+    problems: Vec<Problem>,
+    provenance: ProvenanceStore,
+}
+
+/// Writes a report of the analysis done by `checker` to `path`. `config` is the contents of the
+/// config file and `problems` are those that remain unresolved.
+pub(crate) fn save(
+    path: &Path,
+    checker: &Checker,
+    config: &str,
+    problems: &[Problem],
+    success: bool,
+) -> Result<()> {
+    let mut api_usages: BTreeMap<String, BTreeMap<String, Vec<String>>> = BTreeMap::new();
+    for (perm_sel, api, usages) in checker.usage_counter.usages() {
+        let mut usages: Vec<String> = usages
+            .iter()
+            .map(|(location, name)| format!("{location} -> {name}"))
+            .collect();
+        usages.sort();
+        api_usages
+            .entry(perm_sel.to_string())
+            .or_default()
+            .insert(api.to_string(), usages);
+    }
+    let report = SavedReport {
+        version: REPORT_VERSION,
+        cackle_version: env!("CARGO_PKG_VERSION"),
+        timestamp: crate::timing::unix_now(),
+        success,
+        config,
+        problems,
+        fixes: problems
+            .iter()
+            .map(|problem| ProblemFixes {
+                problem: problem.to_string(),
+                fixes: fixes_for_problem(problem, &checker.config)
+                    .iter()
+                    .map(|edit| Fix {
+                        id: edit.id(),
+                        title: edit.title(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+        api_usages,
+        packages: Summary::new(
+            &checker.crate_index,
+            &checker.config,
+            &checker.generated_code,
+            &checker.args,
+        ),
+        provenance: crate::provenance::snapshot(
+            &checker.target_dir,
+            &checker.usage_counter,
+            &checker.config,
+        ),
+        timings: checker.timings.seconds(),
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    }
+    crate::fs::write_atomic(path, &serde_json::to_string_pretty(&report)?)
+}
+
+impl Report {
+    /// Loads a report previously written by `save`.
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let json = crate::fs::read_to_string(path)?;
+        let report: Report = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse report `{}`", path.display()))?;
+        if report.version != REPORT_VERSION {
+            bail!(
+                "Report `{}` has format version {}, but this version of cackle only reads version \
+                 {REPORT_VERSION}",
+                path.display(),
+                report.version
+            );
+        }
+        Ok(report)
+    }
+
+    pub(crate) fn problems(&self) -> ProblemList {
+        let mut problems = ProblemList::default();
+        for problem in &self.problems {
+            problems.push(problem.clone());
+        }
+        problems
+    }
+
+    pub(crate) fn provenance(&self) -> &ProvenanceStore {
+        &self.provenance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crate_index::testing::pkg_id;
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reports").join("report.json");
+        let checker = crate::checker::testing::checker_for_testing();
+        let problems = vec![Problem::UsesBuildScript(pkg_id("foo"))];
+        save(&path, &checker, "[common]\nversion = 2\n", &problems, false).unwrap();
+
+        let report = Report::load(&path).unwrap();
+        assert_eq!(report.problems().take(), problems);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["config"], "[common]\nversion = 2\n");
+        assert_eq!(json["success"], false);
+        assert_eq!(json["fixes"][0]["problem"], problems[0].to_string());
+        assert!(json["fixes"][0]["fixes"].is_array());
+
+        let mut json = json;
+        json["version"] = serde_json::Value::from(REPORT_VERSION + 1);
+        std::fs::write(&path, json.to_string()).unwrap();
+        assert!(Report::load(&path).is_err());
+    }
+}
@@ -1,4 +1,6 @@
 use crate::config::permissions::PermSel;
+use crate::config::FsAccess;
+use crate::config::FsRule;
 use crate::config::RustcConfig;
 use crate::config::SandboxConfig;
 use crate::config::SandboxKind;
@@ -13,11 +15,20 @@ use std::path::PathBuf;
 use std::process::Command;
 
 mod bubblewrap;
+mod interactive;
+pub(crate) mod plan;
+
+pub(crate) use interactive::Input;
+pub(crate) use interactive::InteractiveOutput;
 
 pub(crate) trait Sandbox {
     /// Runs `command` inside the sandbox.
     fn run(&self, command: &Command) -> Result<std::process::Output>;
 
+    /// Runs `command` inside the sandbox, taking its input from `input`. Detects if the command
+    /// blocks reading from a terminal, waiting for input that it'll never get.
+    fn run_interactive(&self, command: &Command, input: Input) -> Result<InteractiveOutput>;
+
     /// Bind a tmpfs at `dir`.
     fn tmpfs(&mut self, dir: &Path);
 
@@ -58,6 +69,8 @@ pub(crate) trait Sandbox {
     fn display_to_run(&self, command: &Command) -> Box<dyn Display>;
 }
 
+/// Builds a sandbox from `config`. Changes here should be reflected in `plan`, which describes the
+/// sandbox that would be built.
 pub(crate) fn from_config(config: &SandboxConfig) -> Result<Option<Box<dyn Sandbox>>> {
     let mut sandbox = match &config.kind {
         None | Some(SandboxKind::Disabled) => return Ok(None),
@@ -115,6 +128,9 @@ pub(crate) fn from_config(config: &SandboxConfig) -> Result<Option<Box<dyn Sandb
             .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
         sandbox.writable_bind(dir);
     }
+    for rule in &config.fs_rules {
+        apply_fs_rule(sandbox.as_mut(), rule)?;
+    }
     for arg in &config.extra_args {
         sandbox.raw_arg(OsStr::new(arg));
     }
@@ -130,11 +146,39 @@ pub(crate) fn from_config(config: &SandboxConfig) -> Result<Option<Box<dyn Sandb
     Ok(Some(sandbox))
 }
 
+fn apply_fs_rule(sandbox: &mut dyn Sandbox, rule: &FsRule) -> Result<()> {
+    let path = &rule.path;
+    match rule.access {
+        FsAccess::Read => sandbox.ro_bind(path),
+        FsAccess::Write => {
+            if rule.create {
+                std::fs::create_dir_all(path)
+                    .with_context(|| format!("Failed to create directory `{}`", path.display()))?;
+            } else if !path.exists() {
+                bail!(
+                    "Sandbox config has a write rule for `{}`, but that doesn't exist",
+                    path.display()
+                );
+            }
+            sandbox.writable_bind(path);
+        }
+        FsAccess::None => {
+            // Hiding a path that doesn't exist is a no-op.
+            if path.exists() {
+                sandbox.tmpfs(path);
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Information extracted from the rustc command line that's relevant to running it in a sandbox.
 #[derive(Default)]
 pub(crate) struct RustcSandboxInputs {
     output_directories: Vec<PathBuf>,
     input_directories: Vec<PathBuf>,
+    /// Directories that are hidden before any of the above are bound.
+    hidden_directories: Vec<PathBuf>,
     /// The names of environment variables that were set by the build script by printing
     /// "cargo:rustc-env=...". These variables should be allowed through when running rustc.
     build_script_env_vars: Vec<String>,
@@ -148,6 +192,24 @@ impl RustcSandboxInputs {
         let cargo_profile = get_env(crate::proxy::cargo::PROFILE_NAME_ENV)?;
         let manifest_dir = PathBuf::from(get_env(crate::proxy::MANIFEST_DIR)?);
         result.input_directories.push(manifest_dir);
+        // Rustc needs to read the outputs of our dependencies, but should only write its own
+        // outputs. Otherwise a proc macro could tamper with the outputs of other crates.
+        result.input_directories.push(target_dir.clone());
+        // Code generated by the build script is read from OUT_DIR, e.g. via `include!`, but
+        // shouldn't be changed by the compiler.
+        if let Some(out_dir) = std::env::var_os("OUT_DIR") {
+            result.input_directories.push(out_dir.into());
+        }
+        // If CARGO_HOME isn't in our home directory, then it won't have been hidden. We only
+        // expose binaries and package sources, since other parts, e.g. credentials, may be
+        // sensitive.
+        if let Some(cargo_home) = std::env::var_os("CARGO_HOME") {
+            let cargo_home = PathBuf::from(cargo_home);
+            result.input_directories.push(cargo_home.join("bin"));
+            result.input_directories.push(cargo_home.join("registry"));
+            result.input_directories.push(cargo_home.join("git"));
+            result.hidden_directories.push(cargo_home);
+        }
         for arg in std::env::args() {
             if next_is_out {
                 result.output_directories.push(arg.into());
@@ -155,8 +217,15 @@ impl RustcSandboxInputs {
                 continue;
             }
             next_is_out = arg == "--out-dir";
-            if let Some(rest) = arg.strip_prefix("incremental=") {
-                result.output_directories.push(rest.into());
+            let incremental = arg
+                .strip_prefix("incremental=")
+                .or_else(|| arg.strip_prefix("-Cincremental="));
+            if let Some(dir) = incremental {
+                // Rustc creates the directory if it doesn't exist, but it needs to exist in order
+                // for us to bind it.
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create directory `{dir}`"))?;
+                result.output_directories.push(dir.into());
             }
         }
         if let Ok(socket_path) = std::env::var(crate::proxy::SOCKET_ENV) {
@@ -164,11 +233,7 @@ impl RustcSandboxInputs {
                 result.output_directories.push(dir.to_owned());
             }
         }
-        result
-            .output_directories
-            .retain(|d| !d.starts_with(&target_dir));
         result.build_script_env_vars = read_env_vars(&target_dir, &cargo_profile, crate_sel);
-        result.output_directories.push(target_dir);
         Ok(result)
     }
 }
@@ -180,6 +245,9 @@ pub(crate) fn for_rustc(
     let Some(mut sandbox) = from_config(&config.sandbox)? else {
         return Ok(None);
     };
+    for dir in &inputs.hidden_directories {
+        sandbox.tmpfs(dir);
+    }
     for dir in &inputs.input_directories {
         sandbox.ro_bind(dir);
     }
@@ -215,10 +283,41 @@ pub(crate) fn for_perm_sel(
     if let Ok(out_dir) = std::env::var("OUT_DIR") {
         sandbox.writable_bind(Path::new(&out_dir));
     }
+    if let Some(scratch_dir) = scratch_dir(config, perm_sel)? {
+        sandbox.writable_bind(&scratch_dir);
+        sandbox.set_env(OsStr::new(SCRATCH_DIR_ENV), scratch_dir.as_os_str());
+    }
 
     Ok(Some(sandbox))
 }
 
+/// The environment variable through which binaries are told the location of their scratch
+/// directory, if the config for the package enables one.
+pub(crate) const SCRATCH_DIR_ENV: &str = "CACKLE_SCRATCH_DIR";
+
+/// Returns the scratch directory for `perm_sel`, creating it if it doesn't already exist. Returns
+/// `None` if the config doesn't enable a scratch directory.
+pub(crate) fn scratch_dir(config: &SandboxConfig, perm_sel: &PermSel) -> Result<Option<PathBuf>> {
+    if config.scratch_dir != Some(true) {
+        return Ok(None);
+    }
+    let target_dir = PathBuf::from(get_env(crate::proxy::TARGET_DIR)?);
+    let dir = scratch_dir_path(&target_dir, perm_sel);
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create scratch directory `{}`", dir.display()))?;
+    Ok(Some(dir))
+}
+
+/// Scratch directories are per-package, so are shared by a package's build script and tests.
+fn scratch_dir_path(target_dir: &Path, perm_sel: &PermSel) -> PathBuf {
+    scratch_root(target_dir).join(perm_sel.package_name.to_string())
+}
+
+/// Returns the directory containing the scratch directories of all packages.
+pub(crate) fn scratch_root(target_dir: &Path) -> PathBuf {
+    target_dir.join("cackle").join("scratch")
+}
+
 pub(crate) fn available_kind() -> SandboxKind {
     if bubblewrap::has_bwrap() {
         SandboxKind::Bubblewrap
@@ -243,7 +342,7 @@ fn env_vars_file(target_dir: &Path, profile: &str, crate_sel: &CrateSel) -> Path
     env_vars_dir(target_dir, profile).join(crate_sel.pkg_id.to_string())
 }
 
-fn env_vars_dir(target_dir: &Path, profile: &str) -> PathBuf {
+pub(crate) fn env_vars_dir(target_dir: &Path, profile: &str) -> PathBuf {
     target_dir.join(profile).join("env")
 }
 
@@ -307,3 +406,124 @@ fn is_cargo_env(var: &str) -> bool {
     ];
     PREFIXES.iter().any(|prefix| var.starts_with(prefix)) || ONE_OFFS.contains(&var)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rustc_binds() {
+        let dir = tempfile::tempdir().unwrap();
+        let target_dir = dir.path().join("target");
+        let out_dir = target_dir.join("debug/deps");
+        let cargo_home = dir.path().join("cargo");
+        for path in [&out_dir, &cargo_home.join("registry")] {
+            std::fs::create_dir_all(path).unwrap();
+        }
+        let inputs = RustcSandboxInputs {
+            output_directories: vec![out_dir.clone()],
+            input_directories: vec![target_dir.clone(), cargo_home.join("registry")],
+            hidden_directories: vec![cargo_home.clone()],
+            build_script_env_vars: Vec::new(),
+        };
+        let config = RustcConfig {
+            sandbox: SandboxConfig {
+                kind: Some(SandboxKind::Bubblewrap),
+                ..Default::default()
+            },
+        };
+        let sandbox = for_rustc(&config, &inputs).unwrap().unwrap();
+        let args = sandbox.display_to_run(&Command::new("rustc")).to_string();
+        let position = |needle: String| {
+            args.find(&needle)
+                .unwrap_or_else(|| panic!("`{needle}` not found in `{args}`"))
+        };
+        let bind =
+            |kind: &str, path: &Path| format!("{kind} {} {}", path.display(), path.display());
+        // The target directory is read-only, except for where rustc writes its outputs.
+        assert!(position(bind("--ro-bind", &target_dir)) < position(bind("--bind-try", &out_dir)));
+        assert!(
+            position(format!("--tmpfs {}", cargo_home.display()))
+                < position(bind("--ro-bind", &cargo_home.join("registry")))
+        );
+    }
+
+    #[test]
+    fn fs_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let scratch = dir.path().join("scratch");
+        let hidden = dir.path().join("hidden");
+        std::fs::create_dir(&hidden).unwrap();
+        let config = SandboxConfig {
+            kind: Some(SandboxKind::Bubblewrap),
+            fs_rules: vec![
+                FsRule {
+                    path: PathBuf::from("/etc"),
+                    access: FsAccess::Read,
+                    create: false,
+                },
+                FsRule {
+                    path: scratch.clone(),
+                    access: FsAccess::Write,
+                    create: true,
+                },
+                FsRule {
+                    path: hidden.clone(),
+                    access: FsAccess::None,
+                    create: false,
+                },
+            ],
+            ..Default::default()
+        };
+        let sandbox = from_config(&config).unwrap().unwrap();
+        let args = sandbox.display_to_run(&Command::new("true")).to_string();
+        let position = |needle: &str| {
+            args.find(needle)
+                .unwrap_or_else(|| panic!("`{needle}` not found in `{args}`"))
+        };
+        // Rules are applied after the defaults, so that they take precedence.
+        assert!(position("--ro-bind /etc /etc") > position("--tmpfs /tmp"));
+        position(&format!(
+            "--bind-try {} {}",
+            scratch.display(),
+            scratch.display()
+        ));
+        position(&format!("--tmpfs {}", hidden.display()));
+        assert!(scratch.is_dir());
+
+        let missing = FsRule {
+            path: dir.path().join("missing"),
+            access: FsAccess::Write,
+            create: false,
+        };
+        let config = SandboxConfig {
+            kind: Some(SandboxKind::Bubblewrap),
+            fs_rules: vec![missing],
+            ..Default::default()
+        };
+        assert!(from_config(&config).is_err());
+    }
+
+    #[test]
+    fn scratch_dir_is_per_package() {
+        let target_dir = Path::new("/target");
+        assert_eq!(
+            scratch_dir_path(target_dir, &PermSel::for_build_script("crab-9")),
+            Path::new("/target/cackle/scratch/crab-9")
+        );
+        assert_eq!(
+            scratch_dir_path(target_dir, &PermSel::for_build_script("crab-9")),
+            scratch_dir_path(
+                target_dir,
+                &PermSel::with_scope(
+                    &crate::crate_index::testing::pkg_id("crab-9"),
+                    crate::config::permissions::PermissionScope::Test
+                )
+            )
+        );
+        assert_eq!(
+            scratch_dir(&SandboxConfig::default(), &PermSel::for_build_script("a")).unwrap(),
+            None
+        );
+    }
+}
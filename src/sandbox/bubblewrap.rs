@@ -1,3 +1,6 @@
+use super::interactive;
+use super::Input;
+use super::InteractiveOutput;
 use super::Sandbox;
 use anyhow::Context;
 use anyhow::Result;
@@ -25,6 +28,7 @@ impl Bubblewrap {
             .args(["--gid", "1000"])
             .args(["--hostname", "none"])
             .args(["--new-session"])
+            .args(["--die-with-parent"])
             .args(["--clearenv"])
             .args(&self.args)
             .args(["--dev", "/dev"])
@@ -89,6 +93,10 @@ impl Sandbox for Bubblewrap {
         })
     }
 
+    fn run_interactive(&self, command: &Command, input: Input) -> Result<InteractiveOutput> {
+        interactive::run(self.command(command), input)
+    }
+
     fn display_to_run(&self, command: &Command) -> Box<dyn Display> {
         Box::new(CommandDisplay {
             command: self.command(command),
@@ -0,0 +1,288 @@
+//! Running of sandboxed binaries that might want to read from stdin. Sandboxed processes don't have
+//! access to the user's terminal. By default stdin is at end-of-file, as it is for any other
+//! subprocess that we run, so a process that reads stdin just gets no input. A build script that
+//! prompts for input (e.g. to accept a license) by reading from a terminal would however hang
+//! forever, so we watch for any process in the sandbox blocking on a read from a terminal device.
+
+use anyhow::Context;
+use anyhow::Result;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::process::Output;
+use std::process::Stdio;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Where a sandboxed process should get its input from.
+pub(crate) enum Input<'a> {
+    /// The process gets end-of-file on stdin. If it blocks reading from a terminal, it'll be
+    /// killed.
+    Detect,
+    /// The process is given the supplied input, then end-of-file.
+    Canned(&'a str),
+    /// The process is connected to the user's terminal. Anything written to stdout is captured as
+    /// well as being shown to the user.
+    Terminal,
+}
+
+pub(crate) struct InteractiveOutput {
+    pub(crate) output: Output,
+    /// Whether the process was killed because it was waiting for input.
+    pub(crate) waiting_for_input: bool,
+}
+
+/// The longest we'll wait between checks for whether the process is blocked reading input.
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub(super) fn run(mut command: Command, input: Input) -> Result<InteractiveOutput> {
+    let terminal = match input {
+        Input::Terminal => Some(
+            File::options()
+                .read(true)
+                .write(true)
+                .open("/dev/tty")
+                .context("Failed to open /dev/tty")?,
+        ),
+        _ => None,
+    };
+    command.stdout(Stdio::piped());
+    if let Some(terminal) = &terminal {
+        command
+            .stdin(terminal.try_clone()?)
+            .stderr(terminal.try_clone()?);
+    } else {
+        let stdin = if matches!(input, Input::Canned(_)) {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        };
+        command.stdin(stdin).stderr(Stdio::piped());
+    }
+    let mut child = command.spawn().with_context(|| {
+        format!(
+            "Failed to run sandbox command: {}",
+            Path::new(command.get_program()).display()
+        )
+    })?;
+
+    let detect = matches!(input, Input::Detect);
+    if let Input::Canned(input) = input {
+        if let Some(mut pipe) = child.stdin.take() {
+            // Write from a separate thread in case the process doesn't read all its input before
+            // it starts writing output. Dropping the pipe once we're done signals end-of-file.
+            let input = input.to_owned();
+            std::thread::spawn(move || pipe.write_all(input.as_bytes()));
+        }
+    }
+    let stdout = read_in_background(child.stdout.take(), terminal);
+    let stderr = read_in_background(child.stderr.take(), None);
+
+    let mut waiting_for_input = false;
+    let mut poll_interval = Duration::from_millis(1);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if detect && any_descendant_reading_terminal(child.id()) {
+            waiting_for_input = true;
+            // The sandbox is configured such that when it's killed, so is everything in it.
+            let _ = child.kill();
+            break child.wait()?;
+        }
+        std::thread::sleep(poll_interval);
+        poll_interval = (poll_interval * 2).min(MAX_POLL_INTERVAL);
+    };
+
+    Ok(InteractiveOutput {
+        output: Output {
+            status,
+            stdout: join_reader(stdout)?,
+            stderr: join_reader(stderr)?,
+        },
+        waiting_for_input,
+    })
+}
+
+/// Reads all of `source` on a separate thread, optionally echoing what's read to `echo`.
+fn read_in_background(
+    source: Option<impl Read + Send + 'static>,
+    mut echo: Option<File>,
+) -> Option<JoinHandle<std::io::Result<Vec<u8>>>> {
+    let mut source = source?;
+    Some(std::thread::spawn(move || {
+        let mut captured = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let n = source.read(&mut buf)?;
+            if n == 0 {
+                return Ok(captured);
+            }
+            if let Some(echo) = echo.as_mut() {
+                // Failing to echo isn't fatal, since we still capture the output.
+                let _ = echo.write_all(&buf[..n]);
+            }
+            captured.extend_from_slice(&buf[..n]);
+        }
+    }))
+}
+
+fn join_reader(reader: Option<JoinHandle<std::io::Result<Vec<u8>>>>) -> Result<Vec<u8>> {
+    let Some(reader) = reader else {
+        return Ok(Vec::new());
+    };
+    reader
+        .join()
+        .map_err(|_| anyhow::anyhow!("Output reader thread panicked"))?
+        .context("Failed to read output of sandboxed process")
+}
+
+/// Returns whether any thread of `root_pid` or its descendants is blocked reading from a terminal.
+/// Processes that we're not permitted to inspect are ignored.
+fn any_descendant_reading_terminal(root_pid: u32) -> bool {
+    descendants(root_pid)
+        .into_iter()
+        .any(|pid| is_reading_from(pid, is_terminal))
+}
+
+/// Returns whether `path`, the target of a link in `/proc/<pid>/fd`, is a terminal device.
+fn is_terminal(path: &Path) -> bool {
+    let Ok(rest) = path.strip_prefix("/dev") else {
+        return false;
+    };
+    rest.starts_with("pts")
+        || rest == Path::new("console")
+        || rest.to_str().is_some_and(|name| name.starts_with("tty"))
+}
+
+fn descendants(root_pid: u32) -> Vec<u32> {
+    let mut parents = Vec::new();
+    if let Ok(entries) = std::fs::read_dir("/proc") {
+        for entry in entries.flatten() {
+            let Some(pid) = entry.file_name().to_str().and_then(|n| n.parse().ok()) else {
+                continue;
+            };
+            if let Some(ppid) = parent_pid(pid) {
+                parents.push((pid, ppid));
+            }
+        }
+    }
+    let mut result = vec![root_pid];
+    let mut i = 0;
+    while let Some(&pid) = result.get(i) {
+        result.extend(
+            parents
+                .iter()
+                .filter(|(_, ppid)| *ppid == pid)
+                .map(|(child, _)| *child),
+        );
+        i += 1;
+    }
+    result
+}
+
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The command name is in parentheses and may contain spaces, so we start after the last ')'.
+    // The fields following are the state then the parent pid.
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_ascii_whitespace().nth(1)?.parse().ok()
+}
+
+fn is_reading_from(pid: u32, matches: impl Fn(&Path) -> bool) -> bool {
+    let Ok(tasks) = std::fs::read_dir(format!("/proc/{pid}/task")) else {
+        return false;
+    };
+    tasks.flatten().any(|task| {
+        let Ok(syscall) = std::fs::read_to_string(task.path().join("syscall")) else {
+            return false;
+        };
+        let Some(fd) = read_syscall_fd(&syscall) else {
+            return false;
+        };
+        std::fs::read_link(format!("/proc/{pid}/fd/{fd}")).is_ok_and(|link| matches(&link))
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+const READ_SYSCALLS: &[u64] = &[0, 17, 19, 295];
+#[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+const READ_SYSCALLS: &[u64] = &[63, 65, 67, 69];
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+)))]
+const READ_SYSCALLS: &[u64] = &[];
+
+/// Parses the contents of `/proc/<pid>/task/<tid>/syscall`. If the thread is blocked in one of the
+/// read syscalls, returns the file descriptor that it's reading from.
+fn read_syscall_fd(syscall: &str) -> Option<u64> {
+    let mut parts = syscall.split_ascii_whitespace();
+    let number: u64 = parts.next()?.parse().ok()?;
+    if !READ_SYSCALLS.contains(&number) {
+        return None;
+    }
+    u64::from_str_radix(parts.next()?.strip_prefix("0x")?, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_syscall() {
+        assert_eq!(read_syscall_fd("running"), None);
+        assert_eq!(read_syscall_fd("-1 0x7ffd 0x0"), None);
+        if let Some(read) = READ_SYSCALLS.first() {
+            assert_eq!(
+                read_syscall_fd(&format!(
+                    "{read} 0xa 0x7ffd1 0x1000 0x0 0x0 0x0 0x7ffd 0x7f12"
+                )),
+                Some(10)
+            );
+        }
+    }
+
+    #[test]
+    fn terminal_paths() {
+        assert!(is_terminal(Path::new("/dev/tty")));
+        assert!(is_terminal(Path::new("/dev/tty1")));
+        assert!(is_terminal(Path::new("/dev/pts/3")));
+        assert!(is_terminal(Path::new("/dev/console")));
+        assert!(!is_terminal(Path::new("/dev/null")));
+        assert!(!is_terminal(Path::new("pipe:[1234]")));
+        assert!(!is_terminal(Path::new("/home/tty")));
+    }
+
+    #[test]
+    fn stdin_reads_get_eof() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo prompt; read answer; echo got $answer"]);
+        let result = run(command, Input::Detect).unwrap();
+        assert!(!result.waiting_for_input);
+        assert_eq!(
+            String::from_utf8_lossy(&result.output.stdout),
+            "prompt\ngot\n"
+        );
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "read answer; echo got $answer"]);
+        let result = run(command, Input::Canned("yes\n")).unwrap();
+        assert!(!result.waiting_for_input);
+        assert_eq!(String::from_utf8_lossy(&result.output.stdout), "got yes\n");
+    }
+
+    #[test]
+    fn no_input_needed() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo out; echo err >&2"]);
+        let result = run(command, Input::Detect).unwrap();
+        assert!(!result.waiting_for_input);
+        assert!(result.output.status.success());
+        assert_eq!(result.output.stdout, b"out\n");
+        assert_eq!(result.output.stderr, b"err\n");
+    }
+}
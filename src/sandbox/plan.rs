@@ -0,0 +1,303 @@
+//! Describes the sandbox that would be used for each build script and test, after inheritance has
+//! been applied. The description mirrors what `from_config` and `for_perm_sel` do when actually
+//! building a sandbox, but without any side effects such as creating directories.
+
+use super::scratch_dir_path;
+use crate::config::permissions::PermSel;
+use crate::config::permissions::PermissionScope;
+use crate::config::Config;
+use crate::config::FsAccess;
+use crate::config::SandboxConfig;
+use crate::config::SandboxKind;
+use crate::crate_index::CrateIndex;
+use crate::summary::OutputFormat;
+use clap::Parser;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct SandboxPlanOptions {
+    /// Only show sandboxes for the package with this name.
+    #[clap(long)]
+    package: Option<String>,
+
+    /// The format of the output
+    #[clap(long, value_enum, action)]
+    #[clap(default_value_t = OutputFormat::Human)]
+    output_format: OutputFormat,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct SandboxPlan {
+    kind: SandboxKind,
+    allow_network: bool,
+    /// Filesystem changes in the order in which they're applied. Later entries take precedence
+    /// over earlier entries for the same or nested paths.
+    mounts: Vec<Mount>,
+    /// Environment variables that are passed through if set. Entries ending in `*` are prefixes.
+    pass_env: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    extra_args: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stdin: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    per_test: BTreeMap<String, SandboxPlan>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct Mount {
+    access: FsAccess,
+    path: String,
+}
+
+/// Prints the sandbox that would be used for each build script and test.
+pub(crate) fn print(
+    crate_index: &CrateIndex,
+    config: &Config,
+    target_dir: &Path,
+    options: &SandboxPlanOptions,
+) {
+    let plans = plans(crate_index, config, target_dir, options.package.as_deref());
+    match options.output_format {
+        OutputFormat::Human => {
+            for (perm_sel, plan) in &plans {
+                print!("{perm_sel}: {plan}");
+            }
+        }
+        OutputFormat::Json => {
+            let plans: BTreeMap<String, &SandboxPlan> = plans
+                .iter()
+                .map(|(perm_sel, plan)| (perm_sel.to_string(), plan))
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&plans).unwrap());
+        }
+    }
+}
+
+fn plans(
+    crate_index: &CrateIndex,
+    config: &Config,
+    target_dir: &Path,
+    package: Option<&str>,
+) -> Vec<(PermSel, SandboxPlan)> {
+    let mut perm_sels: Vec<&PermSel> = crate_index
+        .permission_selectors
+        .iter()
+        .filter(|perm_sel| {
+            matches!(
+                perm_sel.scope,
+                PermissionScope::Build | PermissionScope::Test
+            )
+        })
+        .filter(|perm_sel| package.map_or(true, |name| perm_sel.package_name.as_ref() == name))
+        .collect();
+    perm_sels.sort_by_key(|perm_sel| perm_sel.to_string());
+    perm_sels
+        .into_iter()
+        .map(|perm_sel| {
+            let sandbox_config = config.permissions.sandbox_config_for_package(perm_sel);
+            let plan = SandboxPlan::for_perm_sel(&sandbox_config, perm_sel, target_dir);
+            (perm_sel.clone(), plan)
+        })
+        .collect()
+}
+
+impl SandboxPlan {
+    fn for_perm_sel(config: &SandboxConfig, perm_sel: &PermSel, target_dir: &Path) -> Self {
+        let mut plan = Self::from_config(config);
+        if plan.kind != SandboxKind::Disabled {
+            plan.mount(FsAccess::Read, "<directory containing the binary>");
+            plan.mount(FsAccess::Write, "${OUT_DIR}");
+            if config.scratch_dir == Some(true) {
+                let scratch_dir = scratch_dir_path(target_dir, perm_sel);
+                plan.mount(FsAccess::Write, scratch_dir.display());
+            }
+        }
+        plan.per_test = config
+            .per_test
+            .iter()
+            .map(|(test_name, test_config)| {
+                let mut test_config = test_config.clone();
+                test_config.inherit(config);
+                // Overrides apply to a single test, so don't themselves have per-test overrides.
+                test_config.per_test.clear();
+                let plan = Self::for_perm_sel(&test_config, perm_sel, target_dir);
+                (test_name.clone(), plan)
+            })
+            .collect();
+        plan
+    }
+
+    fn from_config(config: &SandboxConfig) -> Self {
+        let kind = config.kind.unwrap_or(SandboxKind::Disabled);
+        let mut plan = SandboxPlan {
+            kind,
+            allow_network: kind == SandboxKind::Disabled || config.allow_network.unwrap_or(false),
+            mounts: Vec::new(),
+            pass_env: Vec::new(),
+            extra_args: Vec::new(),
+            stdin: config.stdin.clone(),
+            per_test: BTreeMap::new(),
+        };
+        if kind == SandboxKind::Disabled {
+            return plan;
+        }
+        plan.mount(FsAccess::Read, "/");
+        plan.mount(FsAccess::None, "${HOME}");
+        plan.mount(FsAccess::None, "/var");
+        plan.mount(FsAccess::None, "/tmp");
+        plan.mount(FsAccess::Read, "${HOME}/.cargo/bin");
+        plan.mount(FsAccess::Read, "${HOME}/.cargo/git");
+        plan.mount(FsAccess::Read, "${HOME}/.cargo/registry");
+        plan.mount(FsAccess::Read, "${HOME}/.rustup");
+        plan.pass_env = ["PATH", "HOME"]
+            .into_iter()
+            .map(str::to_owned)
+            .chain(config.pass_env.iter().cloned())
+            .chain(
+                ["LD_LIBRARY_PATH", "OUT_DIR", "CARGO*", "RUSTC*", "DEP_*"]
+                    .into_iter()
+                    .map(str::to_owned),
+            )
+            .collect();
+        plan.mount(FsAccess::Read, "${CARGO_MANIFEST_DIR}");
+        for dir in config.bind_writable.iter().chain(&config.make_writable) {
+            plan.mount(FsAccess::Write, dir.display());
+        }
+        for rule in &config.fs_rules {
+            plan.mount(rule.access, rule.path.display());
+        }
+        plan.extra_args = config.extra_args.clone();
+        if !plan.allow_network {
+            plan.mount(FsAccess::None, "/run");
+        }
+        plan
+    }
+
+    fn mount(&mut self, access: FsAccess, path: impl Display) {
+        self.mounts.push(Mount {
+            access,
+            path: path.to_string(),
+        });
+    }
+
+    fn fmt_indented(&self, f: &mut std::fmt::Formatter<'_>, indent: &str) -> std::fmt::Result {
+        let kind = match self.kind {
+            SandboxKind::Disabled => "Disabled",
+            SandboxKind::Bubblewrap => "Bubblewrap",
+        };
+        writeln!(f, "{kind}")?;
+        if self.kind != SandboxKind::Disabled {
+            let network = if self.allow_network {
+                "allowed"
+            } else {
+                "denied"
+            };
+            writeln!(f, "{indent}  network: {network}")?;
+            writeln!(f, "{indent}  mounts:")?;
+            for mount in &self.mounts {
+                let access = match mount.access {
+                    FsAccess::Read => "read",
+                    FsAccess::Write => "write",
+                    FsAccess::None => "none",
+                };
+                writeln!(f, "{indent}    {access:<5} {}", mount.path)?;
+            }
+            writeln!(f, "{indent}  env: {}", self.pass_env.join(", "))?;
+            if !self.extra_args.is_empty() {
+                writeln!(f, "{indent}  extra args: {}", self.extra_args.join(" "))?;
+            }
+        }
+        if let Some(stdin) = &self.stdin {
+            writeln!(f, "{indent}  stdin: {stdin:?}")?;
+        }
+        for (test_name, plan) in &self.per_test {
+            write!(f, "{indent}  test `{test_name}`: ")?;
+            plan.fmt_indented(f, &format!("{indent}  "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SandboxPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_indented(f, "")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SandboxPlan;
+    use crate::config::permissions::PermSel;
+    use crate::config::permissions::PermissionScope;
+    use crate::config::FsAccess;
+    use crate::config::SandboxKind;
+    use std::path::Path;
+
+    #[test]
+    fn plan_after_inheritance() {
+        let config = crate::config::testing::parse(
+            r#"
+            [sandbox]
+            kind = "Bubblewrap"
+
+            [pkg.foo]
+            build.sandbox.allow_network = true
+            build.sandbox.pass_env = ["FOO_VAR"]
+            test.sandbox.scratch_dir = true
+            test.sandbox.fs_rules = [
+                { path = "/etc/ssl/private", access = "none" },
+            ]
+
+            [pkg.foo.test.sandbox.per_test."tests::net"]
+            allow_network = true
+
+            [pkg.bar]
+            build.sandbox.kind = "Disabled"
+        "#,
+        )
+        .unwrap();
+        let plan = |name: &str, scope| {
+            let perm_sel = PermSel::for_primary(name).clone_with_scope(scope);
+            let sandbox_config = config.permissions.sandbox_config_for_package(&perm_sel);
+            SandboxPlan::for_perm_sel(&sandbox_config, &perm_sel, Path::new("/target"))
+        };
+
+        let build = plan("foo", PermissionScope::Build);
+        assert_eq!(build.kind, SandboxKind::Bubblewrap);
+        assert!(build.allow_network);
+        assert!(build.pass_env.iter().any(|env| env == "FOO_VAR"));
+        assert!(build
+            .mounts
+            .iter()
+            .any(|mount| mount.access == FsAccess::Write && mount.path == "${OUT_DIR}"));
+        assert!(!build.mounts.iter().any(|mount| mount.path == "/run"));
+
+        let test = plan("foo", PermissionScope::Test);
+        assert!(!test.allow_network);
+        let last_mounts: Vec<_> = test.mounts[test.mounts.len() - 4..]
+            .iter()
+            .map(|mount| (mount.access, mount.path.as_str()))
+            .collect();
+        assert_eq!(
+            last_mounts,
+            [
+                (FsAccess::None, "/run"),
+                (FsAccess::Read, "<directory containing the binary>"),
+                (FsAccess::Write, "${OUT_DIR}"),
+                (FsAccess::Write, "/target/cackle/scratch/foo"),
+            ]
+        );
+        assert!(test
+            .mounts
+            .iter()
+            .any(|mount| mount.access == FsAccess::None && mount.path == "/etc/ssl/private"));
+        assert!(test.per_test["tests::net"].allow_network);
+
+        let bar_build = plan("bar", PermissionScope::Build);
+        assert_eq!(bar_build.kind, SandboxKind::Disabled);
+        assert!(bar_build.mounts.is_empty());
+    }
+}
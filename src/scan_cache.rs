@@ -0,0 +1,126 @@
+//! A cache of the outputs of scanning linked binaries, keyed by the contents of the files that were
+//! scanned. This is used by the daemon, so that a binary whose inputs haven't changed since the
+//! last check doesn't need to be scanned again, even if cargo rebuilt it.
+
+use crate::config::ApiConfig;
+use crate::config::ApiName;
+use crate::link_info::LinkInfo;
+use crate::symbol_graph::ScanOutputs;
+use anyhow::Context;
+use anyhow::Result;
+use fxhash::FxHashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// The maximum number of binaries whose scan outputs we keep. When we'd exceed this, the least
+/// recently used outputs are evicted.
+const DEFAULT_CAPACITY: usize = 256;
+
+pub(crate) struct ScanCache {
+    entries: Mutex<Entries>,
+    capacity: usize,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// The outputs for each key together with when they were last used.
+    outputs: FxHashMap<u64, (u64, ScanOutputs)>,
+    /// Incremented each time an entry is used.
+    clock: u64,
+}
+
+impl Default for ScanCache {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl ScanCache {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Default::default(),
+            capacity,
+        }
+    }
+
+    /// Returns the key under which the outputs of scanning `link_info` should be stored. We use
+    /// SipHash rather than FxHash, since a collision would mean that we'd miss problems.
+    pub(crate) fn key(
+        paths: &[PathBuf],
+        link_info: &LinkInfo,
+        apis: &BTreeMap<ApiName, ApiConfig>,
+        debug: bool,
+    ) -> Result<u64> {
+        let mut hasher = DefaultHasher::new();
+        link_info.crate_sel.hash(&mut hasher);
+        apis.hash(&mut hasher);
+        debug.hash(&mut hasher);
+        hash_file(&link_info.output_file, &mut hasher)?;
+        for path in paths {
+            hash_file(path, &mut hasher)?;
+        }
+        Ok(hasher.finish())
+    }
+
+    pub(crate) fn get(&self, key: u64) -> Option<ScanOutputs> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let now = entries.clock;
+        let (last_used, outputs) = entries.outputs.get_mut(&key)?;
+        *last_used = now;
+        Some(outputs.clone())
+    }
+
+    pub(crate) fn insert(&self, key: u64, outputs: &ScanOutputs) {
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.outputs.contains_key(&key) && entries.outputs.len() >= self.capacity {
+            let oldest = entries
+                .outputs
+                .iter()
+                .min_by_key(|(_, (last_used, _))| *last_used)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                entries.outputs.remove(&oldest);
+            }
+        }
+        entries.clock += 1;
+        let now = entries.clock;
+        entries.outputs.insert(key, (now, outputs.clone()));
+    }
+
+    pub(crate) fn clear(&self) {
+        self.entries.lock().unwrap().outputs.clear();
+    }
+}
+
+fn hash_file(path: &Path, hasher: &mut DefaultHasher) -> Result<()> {
+    // Only the file name is included, since rustc puts some object files in temporary directories.
+    path.file_name().hash(hasher);
+    std::fs::read(path)
+        .with_context(|| format!("Failed to read `{}`", path.display()))?
+        .hash(hasher);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScanCache;
+    use crate::symbol_graph::ScanOutputs;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = ScanCache::with_capacity(2);
+        cache.insert(1, &ScanOutputs::default());
+        cache.insert(2, &ScanOutputs::default());
+        assert!(cache.get(1).is_some());
+        cache.insert(3, &ScanOutputs::default());
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(3).is_some());
+    }
+}
@@ -0,0 +1,69 @@
+//! A counting semaphore, used to limit how many binaries we scan at once. Scanning is what uses
+//! most of our memory, so on machines with little RAM it's useful to be able to limit this
+//! separately from how many jobs cargo runs.
+
+use std::sync::Condvar;
+use std::sync::Mutex;
+
+pub(crate) struct Semaphore {
+    available: Mutex<usize>,
+    condvar: Condvar,
+}
+
+/// Holds one of the semaphore's slots until dropped.
+pub(crate) struct Permit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Semaphore {
+    pub(crate) fn new(slots: usize) -> Self {
+        Self {
+            available: Mutex::new(slots),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Blocks until a slot is available, then returns a permit that holds it.
+    pub(crate) fn acquire(&self) -> Permit<'_> {
+        let mut available = self
+            .condvar
+            .wait_while(self.available.lock().unwrap(), |available| *available == 0)
+            .unwrap();
+        *available -= 1;
+        Permit { semaphore: self }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.semaphore.available.lock().unwrap() += 1;
+        self.semaphore.condvar.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn limits_concurrency() {
+        let semaphore = Semaphore::new(2);
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let _permit = semaphore.acquire();
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now_running, Ordering::SeqCst);
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+        assert_eq!(*semaphore.available.lock().unwrap(), 2);
+    }
+}
@@ -0,0 +1,65 @@
+//! SHA-256, used to identify files in attestations. Formats such as in-toto require digests from a
+//! cryptographic hash.
+
+use anyhow::Context;
+use anyhow::Result;
+use sha2::Digest;
+use sha2::Sha256;
+use std::fs::File;
+use std::path::Path;
+
+/// Returns the SHA-256 digest of `data` as lowercase hex.
+pub(crate) fn hex_digest(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+/// Returns the SHA-256 digest of the contents of the file at `path` as lowercase hex. The file is
+/// streamed through the hasher rather than being read into memory, since binaries can be large.
+pub(crate) fn hex_digest_file(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open `{}`", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Failed to read `{}`", path.display()))?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_digests() {
+        assert_eq!(
+            hex_digest(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex_digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // Long enough that padding needs an extra block.
+        assert_eq!(
+            hex_digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            hex_digest(&[b'a'; 1000]),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+
+    #[test]
+    fn file_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data");
+        let data = vec![b'a'; 100_000];
+        std::fs::write(&path, &data).unwrap();
+        assert_eq!(hex_digest_file(&path).unwrap(), hex_digest(&data));
+        assert!(hex_digest_file(&dir.path().join("missing")).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! A status file that we keep up to date while running, so that external tools such as build
+//! orchestrators and IDEs can show progress without having to scrape our user interface. The file
+//! is JSON and is replaced atomically each time it's updated, so readers never see a partial write.
+
+use crate::timing::unix_now;
+use anyhow::Context;
+use anyhow::Result;
+use fxhash::FxHashSet;
+use log::warn;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Returns the path of the file into which we write our status.
+pub(crate) fn status_path(target_dir: &Path) -> PathBuf {
+    target_dir.join("cackle").join("status.json")
+}
+
+/// The broad stages of a run.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Phase {
+    /// Loading config and cleaning up outputs of previous builds.
+    Preparing,
+    /// Running cargo and analysing whatever it builds.
+    Building,
+    /// Checking for parts of the config that weren't used.
+    CheckingUnused,
+    /// Resolving problems saved by a previous run.
+    Resolving,
+    /// We've finished.
+    Done,
+}
+
+/// Tracks our progress and, once enabled, writes it to a status file whenever it changes.
+pub(crate) struct StatusFile {
+    path: Option<PathBuf>,
+    started: Instant,
+    started_at: u64,
+    phase: Phase,
+    phase_started: Instant,
+    /// How long each phase that we've finished took.
+    phase_timings: Vec<PhaseTiming>,
+    packages_total: usize,
+    /// Packages for which at least one crate has finished compiling.
+    packages_completed: FxHashSet<String>,
+    /// Crates that have finished compiling. We use a set, since requests can be handled more than
+    /// once, e.g. if the config is changed to fix problems.
+    crates_completed: FxHashSet<String>,
+    /// Crates that rustc is currently compiling.
+    compiling: Vec<String>,
+    success: Option<bool>,
+}
+
+#[derive(Serialize, Clone)]
+struct PhaseTiming {
+    phase: Phase,
+    seconds: f64,
+}
+
+#[derive(Serialize)]
+struct StatusJson<'a> {
+    phase: Phase,
+    /// Seconds since the Unix epoch when the run started.
+    started_at: u64,
+    /// Seconds since the Unix epoch when the status was written.
+    updated_at: u64,
+    elapsed_seconds: f64,
+    phase_elapsed_seconds: f64,
+    phases: &'a [PhaseTiming],
+    packages_total: usize,
+    packages_completed: usize,
+    crates_completed: usize,
+    compiling: &'a [String],
+    /// Only set once we're done.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    success: Option<bool>,
+}
+
+impl StatusFile {
+    pub(crate) fn new(packages_total: usize) -> Self {
+        let now = Instant::now();
+        Self {
+            path: None,
+            started: now,
+            started_at: unix_now(),
+            phase: Phase::Preparing,
+            phase_started: now,
+            phase_timings: Vec::new(),
+            packages_total,
+            packages_completed: FxHashSet::default(),
+            crates_completed: FxHashSet::default(),
+            compiling: Vec::new(),
+            success: None,
+        }
+    }
+
+    /// Starts writing our status to `path`.
+    pub(crate) fn write_to(&mut self, path: PathBuf) {
+        self.path = Some(path);
+        self.write();
+    }
+
+    pub(crate) fn set_phase(&mut self, phase: Phase) {
+        if phase == self.phase {
+            return;
+        }
+        let now = Instant::now();
+        self.phase_timings.push(PhaseTiming {
+            phase: self.phase,
+            seconds: (now - self.phase_started).as_secs_f64(),
+        });
+        self.phase = phase;
+        self.phase_started = now;
+        self.write();
+    }
+
+    /// Records that we've finished and whether we were successful.
+    pub(crate) fn finish(&mut self, success: bool) {
+        self.success = Some(success);
+        if self.phase == Phase::Done {
+            self.write();
+        } else {
+            self.set_phase(Phase::Done);
+        }
+    }
+
+    pub(crate) fn rustc_started(&mut self, crate_sel: &str) {
+        self.compiling.push(crate_sel.to_owned());
+        self.write();
+    }
+
+    pub(crate) fn rustc_complete(&mut self, crate_sel: &str, pkg_name: &str) {
+        self.compiling.retain(|compiling| compiling != crate_sel);
+        self.crates_completed.insert(crate_sel.to_owned());
+        self.packages_completed.insert(pkg_name.to_owned());
+        self.write();
+    }
+
+    fn write(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(error) = self.try_write(path) {
+            warn!("Failed to write status file: {error:#}");
+        }
+    }
+
+    fn try_write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.to_json())?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+        }
+        crate::fs::write_atomic(path, &json)
+    }
+
+    fn to_json(&self) -> StatusJson<'_> {
+        StatusJson {
+            phase: self.phase,
+            started_at: self.started_at,
+            updated_at: unix_now(),
+            elapsed_seconds: self.started.elapsed().as_secs_f64(),
+            phase_elapsed_seconds: self.phase_started.elapsed().as_secs_f64(),
+            phases: &self.phase_timings,
+            packages_total: self.packages_total,
+            packages_completed: self.packages_completed.len(),
+            crates_completed: self.crates_completed.len(),
+            compiling: &self.compiling,
+            success: self.success,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = status_path(dir.path());
+        let read = || -> serde_json::Value {
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap()
+        };
+        let mut status = StatusFile::new(3);
+        status.rustc_started("foo");
+        assert!(!path.exists());
+
+        status.write_to(path.clone());
+        assert_eq!(read()["phase"], "preparing");
+        assert_eq!(read()["compiling"], serde_json::json!(["foo"]));
+
+        status.set_phase(Phase::Building);
+        status.rustc_started("foo.build");
+        status.rustc_complete("foo", "foo");
+        status.rustc_complete("foo.build", "foo");
+        status.rustc_complete("foo.build", "foo");
+        let json = read();
+        assert_eq!(json["phase"], "building");
+        assert_eq!(json["packages_total"], 3);
+        assert_eq!(json["packages_completed"], 1);
+        assert_eq!(json["crates_completed"], 2);
+        assert_eq!(json["compiling"], serde_json::json!([]));
+        assert_eq!(json["phases"][0]["phase"], "preparing");
+        assert!(json.get("success").is_none());
+
+        status.finish(true);
+        let json = read();
+        assert_eq!(json["phase"], "done");
+        assert_eq!(json["success"], true);
+        assert_eq!(json["phases"][1]["phase"], "building");
+    }
+}
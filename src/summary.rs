@@ -1,19 +1,51 @@
 use crate::config::permissions::PermSel;
+use crate::config::permissions::PermissionScope;
 use crate::config::Config;
 use crate::config::PackageConfig;
 use crate::crate_index::CrateIndex;
+use crate::crate_index::DepKind;
+use crate::crate_index::PackageId;
+use crate::proxy::cargo::Features;
+use crate::Args;
 use clap::{Parser, ValueEnum};
 use fxhash::FxHashMap;
 use serde_json::Value;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 /// Counts of how many packages in the dependency tree use different permissions, how many use no
 /// special permissions etc.
 #[derive(serde::Serialize)]
 pub(crate) struct Summary {
     packages: Vec<PackageSummary>,
+    /// For each scope, the packages that have permissions in that scope. Permissions inherited from
+    /// parent scopes are included, so this answers questions like "what can run code at build
+    /// time?".
+    permissions_by_scope: BTreeMap<SummaryScope, BTreeMap<String, Vec<String>>>,
+    /// The features that were requested when building.
+    features: Features,
+}
+
+/// The permission scopes that can be selected with `--scope`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum SummaryScope {
+    /// Code in the package regardless of how it's used.
+    Normal,
+    /// The package's build script.
+    BuildScript,
+    /// The package's tests.
+    Test,
+    /// The package when used from build scripts of other packages.
+    FromBuild,
+    /// The package when used from tests of other packages.
+    FromTest,
+    /// The package's proc macro.
+    ProcMacro,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
@@ -42,6 +74,15 @@ pub(crate) struct SummaryOptions {
     #[clap(long)]
     counts: bool,
 
+    /// Print the packages with permissions in each scope, e.g. build scripts.
+    #[clap(long)]
+    by_scope: bool,
+
+    /// Only include these scopes when printing by scope. Implies `--by-scope` if no other summary
+    /// is selected.
+    #[clap(long, value_enum, value_delimiter = ',')]
+    scope: Vec<SummaryScope>,
+
     /// Print all summary kinds. This is the default if no options are specified.
     #[clap(long)]
     full: bool,
@@ -61,6 +102,13 @@ pub(crate) struct SummaryOptions {
 struct PackageSummary {
     pub(crate) name: PermSel,
     pub(crate) permissions: Vec<String>,
+    /// The alternative registry that the package came from, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) registry: Option<Arc<str>>,
+    pub(crate) dep_kind: DepKind,
+    /// Whether the package includes code generated by its build script.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) generated_code: bool,
 }
 
 impl PackageSummary {
@@ -74,7 +122,15 @@ impl PackageSummary {
 }
 
 impl Summary {
-    pub(crate) fn new(crate_index: &CrateIndex, config: &Config) -> Self {
+    /// Summarises the permissions granted by `config` to the packages in `crate_index`.
+    /// `generated_code` records which packages included code generated by their build scripts, so
+    /// will be empty if we haven't done a build.
+    pub(crate) fn new(
+        crate_index: &CrateIndex,
+        config: &Config,
+        generated_code: &FxHashMap<PackageId, BTreeSet<PathBuf>>,
+        args: &Args,
+    ) -> Self {
         let pkg_configs: FxHashMap<&PermSel, &PackageConfig> =
             config.permissions.packages.iter().collect();
         let mut packages: Vec<PackageSummary> = crate_index
@@ -85,26 +141,54 @@ impl Summary {
                 let build_script_name = PermSel::for_build_script(pkg_id.name_str());
                 for (crate_name, suffix) in [(&pkg_name, ""), (&build_script_name, "[build]")] {
                     if let Some(pkg_config) = pkg_configs.get(&crate_name) {
-                        if pkg_config.allow_proc_macro {
-                            permissions.push(format!("proc_macro{suffix}"));
-                        }
-                        if pkg_config.allow_unsafe {
-                            permissions.push(format!("unsafe{suffix}"));
-                        }
-                        for api in &pkg_config.allow_apis {
-                            permissions.push(format!("{api}{suffix}"));
-                        }
+                        add_permission_names(pkg_config, suffix, &mut permissions);
                     }
                 }
                 PackageSummary {
                     name: pkg_name,
                     permissions,
+                    registry: crate_index
+                        .package_info(pkg_id)
+                        .and_then(|info| info.registry.clone()),
+                    dep_kind: crate_index.dep_kind(pkg_id),
+                    generated_code: generated_code.contains_key(pkg_id),
                 }
             })
             .collect();
         packages.sort_by(|a, b| a.name.cmp(&b.name));
 
-        Self { packages }
+        let mut permissions_by_scope: BTreeMap<SummaryScope, BTreeMap<String, Vec<String>>> =
+            BTreeMap::new();
+        for pkg_id in crate_index.package_ids() {
+            for scope in SummaryScope::value_variants() {
+                let perm_sel = PermSel::with_scope(pkg_id, scope.permission_scope());
+                let Some(pkg_config) = pkg_configs.get(&perm_sel) else {
+                    continue;
+                };
+                let mut permissions = Vec::new();
+                add_permission_names(pkg_config, "", &mut permissions);
+                if !permissions.is_empty() {
+                    permissions_by_scope
+                        .entry(*scope)
+                        .or_default()
+                        .insert(pkg_id.name_str().to_owned(), permissions);
+                }
+            }
+        }
+
+        Self {
+            packages,
+            permissions_by_scope,
+            features: Features::new(args, &config.raw.common),
+        }
+    }
+
+    /// Returns the permissions of each package, keyed by package name.
+    pub(crate) fn permissions_by_package(self) -> BTreeMap<String, Vec<String>> {
+        self.packages
+            .into_iter()
+            .map(|pkg| (pkg.name.to_string(), pkg.permissions))
+            .collect()
     }
 
     pub(crate) fn print(&self, options: &SummaryOptions) {
@@ -131,6 +215,16 @@ impl Summary {
                 self.json_print_by_permission(&mut json_map);
             }
         }
+        if options.by_scope {
+            if options.output_format == OutputFormat::Human {
+                if options.print_headers {
+                    println!("=== Packages by scope ===");
+                }
+                self.print_by_scope(&options.scope);
+            } else {
+                self.json_print_by_scope(&options.scope, &mut json_map);
+            }
+        }
         if options.impure_proc_macros {
             if options.output_format == OutputFormat::Human {
                 if options.print_headers {
@@ -159,7 +253,26 @@ impl Summary {
 
     fn print_by_crate(&self) {
         for pkg in &self.packages {
-            println!("{}: {}", pkg.name, pkg.permissions.join(", "));
+            let mut notes = Vec::new();
+            if let Some(registry) = &pkg.registry {
+                notes.push(format!("from {registry}"));
+            }
+            if pkg.dep_kind != DepKind::Normal {
+                notes.push(format!("{} dependency", pkg.dep_kind));
+            }
+            if pkg.generated_code {
+                notes.push("includes generated code".to_owned());
+            }
+            if notes.is_empty() {
+                println!("{}: {}", pkg.name, pkg.permissions.join(", "));
+            } else {
+                println!(
+                    "{} ({}): {}",
+                    pkg.name,
+                    notes.join(", "),
+                    pkg.permissions.join(", ")
+                );
+            }
         }
     }
 
@@ -172,6 +285,82 @@ impl Summary {
             "permissions_by_package",
             serde_json::to_value(&map).unwrap(),
         );
+        let registries: HashMap<_, _> = self
+            .packages
+            .iter()
+            .filter_map(|pkg| Some((&pkg.name.package_name, pkg.registry.as_ref()?)))
+            .collect();
+        if !registries.is_empty() {
+            json_map.insert(
+                "package_registries",
+                serde_json::to_value(&registries).unwrap(),
+            );
+        }
+        let dep_kinds: HashMap<_, _> = self
+            .packages
+            .iter()
+            .filter(|pkg| pkg.dep_kind != DepKind::Normal)
+            .map(|pkg| (&pkg.name.package_name, pkg.dep_kind))
+            .collect();
+        if !dep_kinds.is_empty() {
+            json_map.insert(
+                "package_dependency_kinds",
+                serde_json::to_value(&dep_kinds).unwrap(),
+            );
+        }
+        let generated: Vec<_> = self
+            .packages
+            .iter()
+            .filter(|pkg| pkg.generated_code)
+            .map(|pkg| &pkg.name.package_name)
+            .collect();
+        if !generated.is_empty() {
+            json_map.insert(
+                "packages_with_generated_code",
+                serde_json::to_value(&generated).unwrap(),
+            );
+        }
+    }
+
+    /// Returns the selected scopes, or all scopes if `selected` is empty, together with the
+    /// packages that have permissions in each.
+    fn scopes<'a>(
+        &'a self,
+        selected: &'a [SummaryScope],
+    ) -> impl Iterator<Item = (SummaryScope, Option<&'a BTreeMap<String, Vec<String>>>)> + 'a {
+        SummaryScope::value_variants()
+            .iter()
+            .filter(move |scope| selected.is_empty() || selected.contains(scope))
+            .map(|scope| (*scope, self.permissions_by_scope.get(scope)))
+    }
+
+    fn print_by_scope(&self, selected: &[SummaryScope]) {
+        for (scope, packages) in self.scopes(selected) {
+            let count = packages.map_or(0, |packages| packages.len());
+            let plural = if count == 1 { "" } else { "s" };
+            println!("{scope} ({count} package{plural})");
+            for (name, permissions) in packages.into_iter().flatten() {
+                println!("  {name}: {}", permissions.join(", "));
+            }
+        }
+    }
+
+    fn json_print_by_scope(&self, selected: &[SummaryScope], json_map: &mut HashMap<&str, Value>) {
+        let empty = BTreeMap::new();
+        let map: BTreeMap<SummaryScope, Value> = self
+            .scopes(selected)
+            .map(|(scope, packages)| {
+                let packages = packages.unwrap_or(&empty);
+                (
+                    scope,
+                    serde_json::json!({
+                        "count": packages.len(),
+                        "packages": packages,
+                    }),
+                )
+            })
+            .collect();
+        json_map.insert("packages_by_scope", serde_json::to_value(&map).unwrap());
     }
 
     fn print_impure_proc_macros(&self) {
@@ -229,12 +418,17 @@ impl Summary {
             map.insert(&pkg.name.package_name, &pkg.permissions);
         }
         json_map.insert("permission_count", serde_json::to_value(&map).unwrap());
+        json_map.insert("features", serde_json::to_value(&self.features).unwrap());
     }
 }
 
 impl SummaryOptions {
     fn with_defaults(&self) -> SummaryOptions {
         let mut updated = self.clone();
+        if !self.scope.is_empty() && self.num_selected() == 0 {
+            updated.by_scope = true;
+            return updated;
+        }
         match self.num_selected() {
             0 => {
                 updated.full = true;
@@ -246,6 +440,7 @@ impl SummaryOptions {
         if updated.full {
             updated.by_package = true;
             updated.by_permission = true;
+            updated.by_scope = true;
             updated.impure_proc_macros = true;
             updated.counts = true;
         }
@@ -267,6 +462,9 @@ impl SummaryOptions {
         if self.by_permission {
             count += 1;
         }
+        if self.by_scope {
+            count += 1;
+        }
         if self.impure_proc_macros {
             count += 1;
         }
@@ -274,8 +472,50 @@ impl SummaryOptions {
     }
 }
 
+impl SummaryScope {
+    fn permission_scope(self) -> PermissionScope {
+        match self {
+            SummaryScope::Normal => PermissionScope::All,
+            SummaryScope::BuildScript => PermissionScope::Build,
+            SummaryScope::Test => PermissionScope::Test,
+            SummaryScope::FromBuild => PermissionScope::FromBuild,
+            SummaryScope::FromTest => PermissionScope::FromTest,
+            SummaryScope::ProcMacro => PermissionScope::ProcMacro,
+        }
+    }
+}
+
+impl Display for SummaryScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("SummaryScope has no skipped variants")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Appends the names of the permissions granted by `pkg_config` to `permissions`, each followed by
+/// `suffix`.
+fn add_permission_names(pkg_config: &PackageConfig, suffix: &str, permissions: &mut Vec<String>) {
+    if pkg_config.allow_proc_macro {
+        permissions.push(format!("proc_macro{suffix}"));
+    }
+    if pkg_config.allow_unsafe {
+        permissions.push(format!("unsafe{suffix}"));
+    } else if !pkg_config.allow_unsafe_in.is_empty() {
+        permissions.push(format!(
+            "unsafe_in({}){suffix}",
+            pkg_config.allow_unsafe_in.join(", ")
+        ));
+    }
+    for api in &pkg_config.allow_apis {
+        permissions.push(format!("{api}{suffix}"));
+    }
+}
+
 impl Display for Summary {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "features: {}", self.features)?;
         writeln!(f, "num_packages: {}", self.packages.len())?;
         writeln!(
             f,
@@ -285,6 +525,32 @@ impl Display for Summary {
                 .filter(|pkg| pkg.permissions.is_empty())
                 .count()
         )?;
+        for (name, api) in [
+            ("allowed_to_terminate", "terminate"),
+            ("allowed_lifecycle_hooks", "lifecycle-hooks"),
+        ] {
+            writeln!(
+                f,
+                "{name}: {}",
+                self.packages
+                    .iter()
+                    .filter(|pkg| pkg.permissions.iter().any(|p| p == api))
+                    .count()
+            )?;
+        }
+        for (name, kind) in [
+            ("build_only_packages", DepKind::Build),
+            ("dev_only_packages", DepKind::Dev),
+        ] {
+            writeln!(
+                f,
+                "{name}: {}",
+                self.packages
+                    .iter()
+                    .filter(|pkg| pkg.dep_kind == kind)
+                    .count()
+            )?;
+        }
         writeln!(
             f,
             "proc_macros_with_other_permissions: {}",
@@ -293,6 +559,44 @@ impl Display for Summary {
                 .filter(|p| p.is_proc_macro_with_other_permissions())
                 .count()
         )?;
+        // We only know about generated code if we did a build, so we don't print a count of zero,
+        // since that could be misleading.
+        let generated_code = self.packages.iter().filter(|p| p.generated_code).count();
+        if generated_code > 0 {
+            writeln!(f, "packages_with_generated_code: {generated_code}")?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, permissions: &[&str]) -> PackageSummary {
+        PackageSummary {
+            name: PermSel::for_primary(name),
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            registry: None,
+            dep_kind: DepKind::Normal,
+            generated_code: false,
+        }
+    }
+
+    #[test]
+    fn counts_packages_allowed_to_terminate() {
+        let summary = Summary {
+            packages: vec![
+                package("crab1", &["fs", "terminate"]),
+                package("crab2", &["terminate"]),
+                package("crab3", &["panic"]),
+            ],
+            permissions_by_scope: BTreeMap::new(),
+            features: Features::default(),
+        };
+        assert!(summary
+            .to_string()
+            .lines()
+            .any(|line| line == "allowed_to_terminate: 2"));
+    }
+}
@@ -4,19 +4,22 @@ use crate::demangle::DemangleToken;
 use crate::names::NamesIterator;
 use anyhow::Result;
 use rustc_demangle::demangle;
+use serde::Deserialize;
+use serde::Serialize;
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::str::Utf8Error;
 
 /// A symbol from an object file. The symbol might be valid UTF-8 or not. It also may or may not be
 /// mangled. Storage may be borrowed or on the heap.
-#[derive(Eq, Clone, Ord, PartialEq, PartialOrd, Hash)]
+#[derive(Eq, Clone, Ord, PartialEq, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(bound(deserialize = "Bytes<'data>: Deserialize<'de>"))]
 pub(crate) struct Symbol<'data> {
     bytes: Bytes<'data>,
 }
 
 impl<'data> Symbol<'data> {
-    pub(crate) fn borrowed(data: &[u8]) -> Symbol {
+    pub(crate) fn borrowed(data: &[u8]) -> Symbol<'_> {
         Symbol {
             bytes: Bytes::Borrowed(data),
         }
@@ -35,13 +38,18 @@ impl<'data> Symbol<'data> {
         &self.bytes
     }
 
-    fn to_str(&self) -> Result<&str, Utf8Error> {
+    pub(crate) fn to_str(&self) -> Result<&str, Utf8Error> {
         std::str::from_utf8(self.data())
     }
 
     /// Splits the name of this symbol into names. See `crate::names::split_names` for details.
-    pub(crate) fn names(&self) -> Result<NamesIterator<DemangleIterator>> {
-        Ok(NamesIterator::new(DemangleIterator::new(self.to_str()?)))
+    pub(crate) fn names(&self) -> Result<NamesIterator<'_, DemangleIterator<'_>>> {
+        Ok(NamesIterator::new(self.demangled_tokens()?))
+    }
+
+    /// Returns an iterator through the tokens of the demangled form of this symbol.
+    pub(crate) fn demangled_tokens(&self) -> Result<DemangleIterator<'_>> {
+        Ok(DemangleIterator::new(self.to_str()?))
     }
 
     pub(crate) fn len(&self) -> usize {
@@ -75,10 +83,19 @@ impl<'data> Symbol<'data> {
     /// originating from whatever referenced the look-through symbol. So for example, if
     /// foo->core::ops::function::Fn->std::env::var, then we'll consider `foo` as referencing
     /// `std::env::var`.
+    ///
+    /// rustc's anonymous constants, which include vtables, are also looked through. A vtable
+    /// references the methods of a trait implementation, so whatever builds a trait object is
+    /// considered to reference those methods. Without this, on targets where the vtable's symbol
+    /// makes it into the output, we'd stop at the vtable, which has no useful name, and miss any
+    /// APIs that the methods name, e.g. `<std::fs::File as std::io::Write>::write`.
     pub(crate) fn is_look_through(&self) -> bool {
         let Ok(data) = self.to_str() else {
             return false;
         };
+        if is_anonymous_constant(data) {
+            return true;
+        }
         let mut tokens = DemangleIterator::new(data);
         ["core", "ops", "function"]
             .iter()
@@ -86,6 +103,17 @@ impl<'data> Symbol<'data> {
     }
 }
 
+/// Returns whether `name` is one that rustc gives to anonymous constants, e.g. `anon.<hash>.3`, or
+/// `vtable.0` in older versions. Depending on the target, the name may have a prefix that makes it
+/// local to the object file.
+fn is_anonymous_constant(name: &str) -> bool {
+    let name = [".L", "l_", "L"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name);
+    name.starts_with("anon.") || name.starts_with("vtable.")
+}
+
 impl<'data> Display for Symbol<'data> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Ok(sym_string) = self.to_str() {
@@ -167,6 +195,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn look_through() {
+        let look_through = |name: &str| Symbol::borrowed(name.as_bytes()).is_look_through();
+        assert!(look_through(
+            "_ZN4core3ops8function2Fn4call17h0b0e1a8b4f3c3a8dE"
+        ));
+        assert!(look_through("anon.720cbc668296404ef0b3821de96fe6f3.5"));
+        assert!(look_through(".Lanon.720cbc668296404ef0b3821de96fe6f3.5"));
+        assert!(look_through("l_anon.720cbc668296404ef0b3821de96fe6f3.5"));
+        assert!(look_through("vtable.0"));
+        assert!(!look_through("_ZN3std3env3var17h9e8a7f4f2e0e8c1aE"));
+        assert!(!look_through("anonymous_function"));
+        assert!(!look_through("open"));
+    }
+
     #[test]
     fn test_display() {
         let symbol = Symbol::borrowed(b"_ZN4core3ptr85drop_in_place$LT$std..rt..lang_start$LT$$LP$$RP$$GT$..$u7b$$u7b$closure$u7d$$u7d$$GT$17h0bb7e9fe967fc41cE");
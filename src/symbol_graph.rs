@@ -1,6 +1,8 @@
 //! This module builds a graph of relationships between symbols and linker sections. Provided code
 //! was compiled with one symbol per section, which it should have been, there should be a 1:1
-//! relationship between symbols and sections.
+//! relationship between symbols and sections. The exception is Mach-O, where an object file has few
+//! sections, which the linker splits into "atoms" at each symbol. For Mach-O, we treat each atom as
+//! though it were a section of its own.
 //!
 //! We also parse the Dwarf debug information to determine what source file each linker section came
 //! from.
@@ -8,14 +10,17 @@
 use self::backtrace::Backtracer;
 use self::dwarf::SymbolDebugInfo;
 use self::object_file_path::ObjectFilePath;
+use self::object_memo::ObjectMemo;
 use crate::checker::ApiUsage;
 use crate::checker::BinLocation;
 use crate::checker::Checker;
+use crate::config::permissions::PermSel;
 use crate::config::permissions::PermissionScope;
 use crate::config::ApiConfig;
 use crate::config::ApiName;
 use crate::crate_index::CrateSel;
 use crate::crate_index::PackageId;
+use crate::link_info::Artifact;
 use crate::link_info::LinkInfo;
 use crate::location::SourceLocation;
 use crate::names::DebugName;
@@ -30,24 +35,30 @@ use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
-use ar::Archive;
 use fxhash::FxHashMap;
 use fxhash::FxHashSet;
 use gimli::Dwarf;
 use gimli::EndianSlice;
-use gimli::LittleEndian;
+use gimli::RunTimeEndian;
 use log::debug;
 use log::trace;
+use object::read::archive::ArchiveFile;
+use object::read::ReadCache;
+use object::BinaryFormat;
 use object::Object;
 use object::ObjectSection;
 use object::ObjectSymbol;
+use object::ReadRef;
+use object::Relocation;
 use object::RelocationTarget;
 use object::SectionIndex;
+use serde::Deserialize;
+use serde::Serialize;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::fs::File;
-use std::io::Read;
+use std::ops::Range;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -55,7 +66,11 @@ use std::time::Instant;
 
 pub(crate) mod backtrace;
 mod dwarf;
+pub(crate) mod lookup;
 pub(crate) mod object_file_path;
+pub(crate) mod object_memo;
+#[cfg(test)]
+mod test_objects;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Filetype {
@@ -70,6 +85,9 @@ struct ApiUsageCollector<'input, 'backtracer> {
     bin: BinInfo<'input>,
     debug_enabled: bool,
     new_api_usages: FxHashMap<ApiUsageGroupKey, Vec<SingleApiUsage>>,
+
+    /// Sections found not to reference any APIs, to be added to the checker's `ObjectMemo`.
+    new_api_free_sections: Vec<(u64, FxHashSet<usize>)>,
 }
 
 struct SingleApiUsage {
@@ -83,31 +101,73 @@ struct SingleApiUsage {
 /// object (so).
 struct BinInfo<'input> {
     filename: Arc<Path>,
+    artifact: Artifact,
     crate_sel: CrateSel,
+    /// Whether the binary is a proc macro, in which case all of its code runs at build time.
+    is_proc_macro: bool,
     symbol_addresses: FxHashMap<Symbol<'input>, u64>,
     /// Symbols that we've already determined have no APIs. This is an optimisation that lets us
     /// skip these symbols when we see them again.
     symbol_has_no_apis: FxHashMap<Symbol<'input>, bool>,
 
+    /// Set whenever a name is found to match an API. Used to determine which sections of object
+    /// files don't reference any APIs.
+    apis_matched: bool,
+
     /// Information about each symbol obtained from the debug info.
     symbol_debug_info: FxHashMap<Symbol<'input>, SymbolDebugInfo<'input>>,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct ScanOutputs {
+    #[serde(with = "api_usages_list")]
     api_usages: FxHashMap<(PackageId, ApiName), ApiUsages>,
 
     /// Problems not related to api_usage. These can't be fixed by config changes via the UI, since
     /// once computed, they won't be recomputed.
+    #[serde(skip)]
     base_problems: ProblemList,
 
     possible_exported_apis: Vec<PossibleExportedApi>,
 
+    /// APIs referenced from code that the linker discarded from the final binary, together with
+    /// the permission selector for the package containing that code. These aren't API usages, but
+    /// let us report grants that are only exercised by dead code.
+    dead_code_api_usages: FxHashSet<(PermSel, ApiName)>,
+
     /// The API definitions used to produce these outputs. Used to determine if we need to recompute
     /// API usages.
     pub(crate) apis: BTreeMap<ApiName, ApiConfig>,
 }
 
+/// Serialises API usages as a list, since they're keyed by a tuple, which JSON doesn't support as a
+/// map key. The key can be recovered from the values.
+mod api_usages_list {
+    use super::ApiUsages;
+    use crate::config::ApiName;
+    use crate::crate_index::PackageId;
+    use fxhash::FxHashMap;
+    use serde::Deserialize;
+    use serde::Deserializer;
+    use serde::Serializer;
+
+    pub(super) fn serialize<S: Serializer>(
+        api_usages: &FxHashMap<(PackageId, ApiName), ApiUsages>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(api_usages.values())
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<FxHashMap<(PackageId, ApiName), ApiUsages>, D::Error> {
+        Ok(Vec::<ApiUsages>::deserialize(deserializer)?
+            .into_iter()
+            .map(|usages| ((usages.pkg_id.clone(), usages.api_name.clone()), usages))
+            .collect())
+    }
+}
+
 struct ObjectIndex<'obj, 'data> {
     obj: &'obj object::File<'data>,
 
@@ -116,15 +176,17 @@ struct ObjectIndex<'obj, 'data> {
 
 #[derive(Clone, Default)]
 struct SectionInfo<'data> {
-    first_symbol: Option<SymbolInfo<'data>>,
+    /// The symbols at the start of each atom in the section, ordered by offset. Other than for
+    /// Mach-O, there's only a single atom, which starts with the first symbol in the section.
+    atom_symbols: Vec<SymbolInfo<'data>>,
 }
 
 #[derive(Clone)]
 struct SymbolInfo<'data> {
-    /// The first symbol in the section.
+    /// The first symbol in the section or atom.
     symbol: Symbol<'data>,
 
-    /// The offset of the symbol.
+    /// The offset of the symbol within its section.
     offset: u64,
 }
 
@@ -160,10 +222,14 @@ fn scan_object_with_bin_bytes(
     paths: &[PathBuf],
 ) -> Result<ScanOutputs> {
     let start = Instant::now();
+    checker
+        .object_memo
+        .set_apis(&crate::checker::api_definitions(&checker.config));
     let obj = object::File::parse(bin_file_bytes.as_slice())
         .with_context(|| format!("Failed to parse {}", link_info.output_file.display()))?;
     let owned_dwarf = Dwarf::load(|id| load_section(&obj, id))?;
-    let dwarf = owned_dwarf.borrow(|section| gimli::EndianSlice::new(section, gimli::LittleEndian));
+    let endian = endian(&obj);
+    let dwarf = owned_dwarf.borrow(|section| gimli::EndianSlice::new(section, endian));
     let start = checker.timings.add_timing(start, "Parse bin");
     let debug_artifacts =
         dwarf::DebugArtifacts::from_dwarf(&dwarf, checker).with_context(|| {
@@ -190,20 +256,42 @@ fn scan_object_with_bin_bytes(
         backtracer,
         bin: BinInfo {
             filename: link_info.output_file.clone(),
+            artifact: link_info.artifact(&checker.target_dir),
             crate_sel: link_info.crate_sel.clone(),
+            is_proc_macro: link_info.is_proc_macro,
             symbol_addresses: Default::default(),
             symbol_debug_info: debug_artifacts.symbol_debug_info,
             symbol_has_no_apis: no_api_symbol_hashes,
+            apis_matched: false,
         },
         debug_enabled: checker.args.debug,
         new_api_usages: FxHashMap::default(),
+        new_api_free_sections: Vec::new(),
     };
     collector.bin.load_symbols(&obj)?;
+    if checker.collect_size_report {
+        let crate_index = checker.crate_index.clone();
+        checker.size_report.record_bin(
+            &link_info.output_file,
+            &link_info.crate_sel,
+            &obj,
+            &crate_index,
+        )?;
+    }
     let start = checker.timings.add_timing(start, "Load symbols from bin");
     for f in debug_artifacts.inlined_functions {
+        // If the function that was inlined into was itself inlined, then the outer location is
+        // where the outermost function was called. This is important for attributing references
+        // that originate from macro-generated code.
+        let non_inlined_from = f.outer_call_location.as_ref().map(|outer| Node {
+            names: f.from.clone(),
+            location_fetcher: LocationFetcher::InlinedFunction(outer),
+            link_section: None,
+        });
         let from = Node {
             names: f.from,
             location_fetcher: LocationFetcher::InlinedFunction(&f.call_location),
+            link_section: None,
         };
         let debug_data = if checker.args.debug {
             Some(UsageDebugData::Inlined(InlinedDebugData::from_offset(
@@ -215,7 +303,7 @@ fn scan_object_with_bin_bytes(
         };
         collector.process_reference(
             f.bin_location,
-            None,
+            non_inlined_from.as_ref(),
             &from,
             &f.to,
             checker,
@@ -233,17 +321,41 @@ fn scan_object_with_bin_bytes(
             .with_context(|| format!("Failed to process `{}`", path.display()))?;
     }
     collector.emit_shortest_api_usages();
+    for (key, sections) in collector.new_api_free_sections {
+        checker.object_memo.add_api_free_sections(key, sections);
+    }
     checker.timings.add_timing(start, "Process object files");
     Ok(collector.outputs)
 }
 
 impl ScanOutputs {
+    /// Updates the binary in which API usages were found to be the output of `link_info`. Used
+    /// when the binary was scanned at a different path, such as on a remote worker.
+    pub(crate) fn set_bin(&mut self, link_info: &LinkInfo, target_dir: &Path) {
+        let artifact = link_info.artifact(target_dir);
+        for api_usages in self.api_usages.values_mut() {
+            for usage in &mut api_usages.usages {
+                usage.bin_path = link_info.output_file.clone();
+                usage.artifact = artifact.clone();
+            }
+        }
+    }
+
+    pub(crate) fn api_usages(&self) -> impl Iterator<Item = &ApiUsages> {
+        self.api_usages.values()
+    }
+
     pub(crate) fn problems(&self, checker: &mut Checker) -> Result<ProblemList> {
         let mut problems: ProblemList = self.base_problems.clone();
         for api_usages in self.api_usages.values() {
+            checker.record_api_usages(api_usages);
             checker.api_used(api_usages, &mut problems)?;
         }
+        for (perm_sel, api) in &self.dead_code_api_usages {
+            checker.dead_code_api_used(api, perm_sel);
+        }
         checker.possible_exported_api_problems(&self.possible_exported_apis, &mut problems);
+        checker.run_plugins(self, &mut problems)?;
 
         Ok(problems)
     }
@@ -254,21 +366,26 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
         &mut self,
         filename: &Path,
         checker: &Checker,
-        ctx: &addr2line::Context<EndianSlice<'input, LittleEndian>>,
+        ctx: &addr2line::Context<EndianSlice<'input, RunTimeEndian>>,
     ) -> Result<()> {
-        let mut buffer = Vec::new();
         match Filetype::from_filename(filename) {
             Filetype::Archive => {
-                let mut archive = Archive::new(File::open(filename)?);
-                while let Some(entry_result) = archive.next_entry() {
-                    let Ok(mut entry) = entry_result else {
-                        continue;
-                    };
-                    buffer.clear();
-                    entry.read_to_end(&mut buffer)?;
-                    let object_file_path = ObjectFilePath::in_archive(filename, &entry)?;
-                    self.process_object_file_bytes(&object_file_path, &buffer, checker, ctx)
+                // Archives, in particular rlibs, can be very large, so rather than reading the
+                // whole archive, we read and process one entry at a time, discarding each before
+                // moving on to the next.
+                let file = File::open(filename)
+                    .with_context(|| format!("Failed to open `{}`", filename.display()))?;
+                let mut cache = ReadCache::new(file);
+                let entries = archive_entries(filename, &cache)?;
+                cache.clear();
+                for entry in entries {
+                    let object_file_path = ObjectFilePath::in_archive(filename, &entry.name)?;
+                    let bytes = (&cache)
+                        .read_bytes_at(entry.offset, entry.size)
+                        .map_err(|()| anyhow!("Failed to read {object_file_path}"))?;
+                    self.process_object_file_bytes(&object_file_path, bytes, checker, ctx)
                         .with_context(|| format!("Failed to process {object_file_path}"))?;
+                    cache.clear();
                 }
             }
             Filetype::Other => {
@@ -289,101 +406,198 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
         filename: &ObjectFilePath,
         file_bytes: &[u8],
         checker: &Checker,
-        ctx: &addr2line::Context<EndianSlice<'input, LittleEndian>>,
+        ctx: &addr2line::Context<EndianSlice<'input, RunTimeEndian>>,
     ) -> Result<()> {
         debug!("Processing object file {}", filename);
 
         let obj = object::File::parse(file_bytes).context("Failed to parse object file")?;
         let object_index = ObjectIndex::new(&obj);
+        // A backtracer needs to see all references, not just those to APIs, so we can only skip
+        // sections when we don't have one.
+        let memo_key = self
+            .backtracer
+            .is_none()
+            .then(|| ObjectMemo::key(file_bytes));
+        let mut api_free_sections = FxHashSet::default();
         for section in obj.sections() {
             let section_name = section.name().unwrap_or("");
-            let Some(first_sym_info) = object_index.first_symbol(&section) else {
-                debug!("Skipping section `{section_name}` due to lack of debug info");
+            let section_index = section.index().0;
+            if memo_key.is_some_and(|key| checker.object_memo.is_api_free(key, section_index)) {
                 continue;
-            };
-            let Some(symbol_address_in_bin) = self
-                .bin
-                .symbol_addresses
-                .get(&first_sym_info.symbol)
-                .cloned()
-            else {
-                debug!(
-                    "Skipping section `{}` because symbol `{}` doesn't appear in exe/so",
-                    section_name, first_sym_info.symbol
-                );
-                continue;
-            };
-            let Some(debug_info) = self.bin.symbol_debug_info.get(&first_sym_info.symbol) else {
+            }
+            let mut atoms = object_index.atoms(section.index()).peekable();
+            if atoms.peek().is_none() {
+                debug!("Skipping section `{section_name}` due to lack of debug info");
                 continue;
-            };
-            let fallback_source_location = debug_info.source_location();
-            let debug_data = self.debug_enabled.then(|| {
-                UsageDebugData::Relocation(RelocationDebugData {
-                    bin_path: self.bin.filename.clone(),
-                    object_file_path: filename.clone(),
-                    section_name: section_name.to_owned(),
-                })
-            });
-
-            for (offset, rel) in section.relocations() {
-                let mut target_symbols = Vec::new();
-                let rel = &rel;
-                object_index.add_target_symbols(
-                    rel,
-                    &mut target_symbols,
-                    &mut FxHashSet::default(),
-                    &self.bin.symbol_addresses,
-                )?;
-
-                // Use debug info to determine the function that the reference originated from.
-                let offset_in_bin = symbol_address_in_bin + offset - first_sym_info.offset;
-                let mut frames = ctx.find_frames(offset_in_bin).skip_all_loads()?;
-                let (frame_fn_name, frame_location) = frames
-                    .next()?
-                    .map(|frame| (frame.function, frame.location))
-                    .unwrap_or((None, None));
-                let location_fetcher = LocationFetcher::FrameWithFallback {
-                    frame_location,
-                    fallback: &fallback_source_location,
+            }
+            let relocations: Vec<(u64, Relocation)> = section.relocations().collect();
+            let link_section_apis = checker.apis_for_link_section(section_name);
+            // Sections such as `.init_array` use APIs just by being linked in, so are never
+            // recorded as API-free.
+            let mut section_is_api_free = link_section_apis.is_none();
+            for (first_sym_info, range) in atoms {
+                let relocations: Vec<&(u64, Relocation)> = relocations
+                    .iter()
+                    .filter(|(offset, _)| range.contains(offset))
+                    .collect();
+                self.bin.apis_matched = false;
+                let Some(symbol_address_in_bin) = self
+                    .bin
+                    .symbol_addresses
+                    .get(&first_sym_info.symbol)
+                    .cloned()
+                else {
+                    debug!(
+                        "Skipping section `{}` because symbol `{}` doesn't appear in exe/so",
+                        section_name, first_sym_info.symbol
+                    );
+                    self.record_dead_code_references(
+                        &object_index,
+                        &relocations,
+                        first_sym_info,
+                        link_section_apis,
+                        checker,
+                    )?;
+                    // Dead code isn't fully checked, so we can't tell whether the section would
+                    // use any APIs in a binary where it's live.
+                    section_is_api_free = false;
+                    continue;
                 };
-                let frame_symbol = frame_fn_name
-                    .as_ref()
-                    .map(|fn_name| Symbol::borrowed(&fn_name.name));
-                let bin_location = BinLocation {
-                    address: offset_in_bin,
-                    symbol_start: symbol_address_in_bin,
+                let Some(debug_info) = self.bin.symbol_debug_info.get(&first_sym_info.symbol)
+                else {
+                    section_is_api_free = false;
+                    continue;
                 };
+                let fallback_source_location = debug_info.source_location();
+                let debug_data = self.debug_enabled.then(|| {
+                    UsageDebugData::Relocation(RelocationDebugData {
+                        bin_path: self.bin.filename.clone(),
+                        object_file_path: filename.clone(),
+                        section_name: section_name.to_owned(),
+                    })
+                });
 
-                let from_symbol = frame_symbol.as_ref().unwrap_or(&first_sym_info.symbol);
-                let from = Node {
-                    names: self.bin.get_symbol_and_name(from_symbol),
-                    location_fetcher,
-                };
-                let mut non_inlined_from = None;
-                if frame_symbol.as_ref() != Some(&first_sym_info.symbol) {
-                    non_inlined_from = Some(Node {
-                        names: self.bin.get_symbol_and_name(&first_sym_info.symbol),
-                        location_fetcher: LocationFetcher::AlreadyResolved(
-                            &fallback_source_location,
-                        ),
-                    });
-                }
-                for target_symbol in target_symbols {
-                    if let Some(target_address) = self.bin.symbol_addresses.get(&target_symbol) {
-                        if let Some(b) = self.backtracer.as_mut() {
-                            b.add_reference(bin_location, *target_address);
+                for (offset, rel) in relocations {
+                    let mut target_symbols = Vec::new();
+                    object_index.add_target_symbols(
+                        rel,
+                        &mut target_symbols,
+                        &mut FxHashSet::default(),
+                        &self.bin.symbol_addresses,
+                    )?;
+
+                    // Use debug info to determine the function that the reference originated from.
+                    let offset_in_bin = symbol_address_in_bin + offset - first_sym_info.offset;
+                    let mut frames = ctx.find_frames(offset_in_bin).skip_all_loads()?;
+                    let (frame_fn_name, frame_location) = frames
+                        .next()?
+                        .map(|frame| (frame.function, frame.location))
+                        .unwrap_or((None, None));
+                    let location_fetcher = LocationFetcher::FrameWithFallback {
+                        frame_location,
+                        fallback: &fallback_source_location,
+                    };
+                    let frame_symbol = frame_fn_name
+                        .as_ref()
+                        .map(|fn_name| Symbol::borrowed(&fn_name.name));
+                    let bin_location = BinLocation {
+                        address: offset_in_bin,
+                        symbol_start: symbol_address_in_bin,
+                    };
+
+                    let from_symbol = frame_symbol.as_ref().unwrap_or(&first_sym_info.symbol);
+                    let from = Node {
+                        names: self.bin.get_symbol_and_name(from_symbol),
+                        location_fetcher,
+                        link_section: link_section_apis.map(|apis| (section_name, apis)),
+                    };
+                    let mut non_inlined_from = None;
+                    if frame_symbol.as_ref() != Some(&first_sym_info.symbol) {
+                        non_inlined_from = Some(Node {
+                            names: self.bin.get_symbol_and_name(&first_sym_info.symbol),
+                            location_fetcher: LocationFetcher::AlreadyResolved(
+                                &fallback_source_location,
+                            ),
+                            link_section: None,
+                        });
+                    }
+                    for target_symbol in target_symbols {
+                        if let Some(target_address) = self.bin.symbol_addresses.get(&target_symbol)
+                        {
+                            if let Some(b) = self.backtracer.as_mut() {
+                                b.add_reference(bin_location, *target_address);
+                            }
                         }
+                        let target = self.bin.get_symbol_and_name(&target_symbol);
+                        self.process_reference(
+                            bin_location,
+                            non_inlined_from.as_ref(),
+                            &from,
+                            &target,
+                            checker,
+                            debug_data.as_ref(),
+                        )?;
                     }
-                    let target = self.bin.get_symbol_and_name(&target_symbol);
-                    self.process_reference(
-                        bin_location,
-                        non_inlined_from.as_ref(),
-                        &from,
-                        &target,
-                        checker,
-                        debug_data.as_ref(),
-                    )?;
                 }
+                section_is_api_free &= !self.bin.apis_matched;
+            }
+            if section_is_api_free {
+                api_free_sections.insert(section_index);
+            }
+        }
+        if let Some(key) = memo_key {
+            self.new_api_free_sections.push((key, api_free_sections));
+        }
+        Ok(())
+    }
+
+    /// Records which APIs are referenced by `relocations`, which are from a section or atom that the
+    /// linker discarded. `link_section_apis` are the APIs that the section would have used by being
+    /// linked in, if any.
+    fn record_dead_code_references(
+        &mut self,
+        object_index: &ObjectIndex,
+        relocations: &[&(u64, Relocation)],
+        first_sym_info: &SymbolInfo,
+        link_section_apis: Option<&FxHashSet<ApiName>>,
+        checker: &Checker,
+    ) -> Result<()> {
+        // Sections from packages that we don't know about, such as the standard library, are
+        // common and can't have grants, so we check this before looking at relocations.
+        let mut names = first_sym_info.symbol.names()?;
+        let Some(crate_name) = names.next_name()?.and_then(|(mut parts, _)| parts.next()) else {
+            return Ok(());
+        };
+        let Some(pkg_id) = checker.crate_index.name_prefix_to_pkg_id().get(crate_name) else {
+            return Ok(());
+        };
+        let perm_sel = PermSel::with_scope(
+            pkg_id,
+            PermissionScope::determine(pkg_id, &self.bin.crate_sel, self.bin.is_proc_macro),
+        );
+        let dead_code_api_usages = &mut self.outputs.dead_code_api_usages;
+        for api in link_section_apis.into_iter().flatten() {
+            dead_code_api_usages.insert((perm_sel.clone(), api.clone()));
+        }
+        for (_, rel) in relocations {
+            let mut target_symbols = Vec::new();
+            object_index.add_target_symbols(
+                rel,
+                &mut target_symbols,
+                &mut FxHashSet::default(),
+                &self.bin.symbol_addresses,
+            )?;
+            for target_symbol in target_symbols {
+                let target = self.bin.get_symbol_and_name(&target_symbol);
+                self.bin
+                    .names_and_apis_do(&target, checker, |name, _, apis| {
+                        if !is_in_package(&name, pkg_id) {
+                            for api in apis {
+                                dead_code_api_usages.insert((perm_sel.clone(), api.clone()));
+                            }
+                        }
+                        Ok(())
+                    })?;
             }
         }
         Ok(())
@@ -409,60 +623,76 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
         let mut lazy_location = None;
         let mut lazy_crate_names = None;
         let bin_path = self.bin.filename.clone();
+        let artifact = self.bin.artifact.clone();
         let bin_sel = self.bin.crate_sel.clone();
-        self.bin
-            .names_and_apis_do(target, checker, |name, name_source, apis| {
-                // For the majority of references we expect no APIs to match. We defer computation
-                // of a source location and crate names until we know that an API matched.
-                if lazy_location.is_none() {
-                    lazy_location = Some(from.location_fetcher.location()?);
-                }
-                let location = lazy_location.as_ref().unwrap();
-                if lazy_crate_names.is_none() {
-                    lazy_crate_names = Some(checker.pkg_ids_from_source_path(location.filename())?);
+        let is_proc_macro = self.bin.is_proc_macro;
+        let mut record_usages = |name: Name, name_source: NameSource, apis: &FxHashSet<ApiName>| {
+            // For the majority of references we expect no APIs to match. We defer computation
+            // of a source location and crate names until we know that an API matched.
+            if lazy_location.is_none() {
+                lazy_location = Some(from.location_fetcher.location()?);
+            }
+            let location = lazy_location.as_ref().unwrap();
+            if lazy_crate_names.is_none() {
+                lazy_crate_names = Some(checker.attributed_pkg_ids(
+                    &from.names,
+                    checker.pkg_ids_from_source_path(location.filename())?,
+                )?);
+            }
+            let crate_names = lazy_crate_names.as_ref().unwrap();
+
+            for pkg_id in crate_names.as_ref() {
+                // If a package references another symbol within the same package,
+                // ignore it.
+                if is_in_package(&name, pkg_id) {
+                    continue;
                 }
-                let crate_names = lazy_crate_names.as_ref().unwrap();
-
-                for pkg_id in crate_names.as_ref() {
-                    // If a package references another symbol within the same package,
-                    // ignore it.
-                    // TODO: This should be use the crate name form (i.e. with underscores, not
-                    // hyphens).
-                    if name.starts_with(pkg_id.name_str()) {
+                for api in apis {
+                    if from_apis.contains(&api) {
                         continue;
                     }
-                    for api in apis {
-                        if from_apis.contains(&api) {
-                            continue;
-                        }
-                        let outer_location = non_inlined_from
-                            .map(|n| n.location_fetcher.location())
-                            .transpose()?;
-                        let api_usage = SingleApiUsage {
-                            pkg_id: pkg_id.clone(),
-                            scope: PermissionScope::determine(pkg_id, &bin_sel),
-                            api: api.clone(),
-                            usage: ApiUsage {
-                                bin_location,
-                                bin_path: bin_path.clone(),
-                                permission_scope: PermissionScope::determine(pkg_id, &bin_sel),
-                                source_location: location.clone(),
-                                outer_location,
-                                from: from.names.symbol_or_debug_name()?,
-                                to: target.symbol_or_debug_name()?,
-                                to_name: name.clone(),
-                                to_source: name_source.to_owned(),
-                                debug_data: debug_data.cloned(),
-                            },
-                        };
-                        self.new_api_usages
-                            .entry(api_usage.group_key())
-                            .or_default()
-                            .push(api_usage);
-                    }
+                    let outer_location = non_inlined_from
+                        .map(|n| n.location_fetcher.location())
+                        .transpose()?;
+                    let scope = PermissionScope::determine(pkg_id, &bin_sel, is_proc_macro);
+                    let api_usage = SingleApiUsage {
+                        pkg_id: pkg_id.clone(),
+                        scope,
+                        api: api.clone(),
+                        usage: ApiUsage {
+                            bin_location,
+                            bin_path: bin_path.clone(),
+                            artifact: artifact.clone(),
+                            permission_scope: scope,
+                            source_location: location.clone(),
+                            outer_location,
+                            from: from.names.symbol_or_debug_name()?,
+                            to: target.symbol_or_debug_name()?,
+                            to_name: name.clone(),
+                            to_source: name_source.to_owned(),
+                            debug_data: debug_data.cloned(),
+                        },
+                    };
+                    self.new_api_usages
+                        .entry(api_usage.group_key())
+                        .or_default()
+                        .push(api_usage);
                 }
-                Ok(())
-            })?;
+            }
+            Ok(())
+        };
+        self.bin
+            .names_and_apis_do(target, checker, &mut record_usages)?;
+        // Putting a function into a link section such as `.init_array` registers it to be run
+        // outside of `main`, which counts as using whatever APIs cover that section.
+        if let (Some((section_name, apis)), Some(symbol)) = (from.link_section, &target.symbol) {
+            self.bin.apis_matched = true;
+            record_usages(
+                crate::names::split_simple(section_name),
+                NameSource::Symbol(symbol.clone()),
+                apis,
+            )?;
+        }
         Ok(())
     }
 
@@ -541,6 +771,9 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
 struct Node<'a> {
     names: SymbolAndName<'a>,
     location_fetcher: LocationFetcher<'a>,
+    /// If the reference is from a link section such as `.init_array`, then the section name and the
+    /// APIs that registering a function there counts as using.
+    link_section: Option<(&'a str, &'a FxHashSet<ApiName>)>,
 }
 
 enum LocationFetcher<'a> {
@@ -570,68 +803,108 @@ impl<'a> LocationFetcher<'a> {
 
 impl<'obj, 'data> ObjectIndex<'obj, 'data> {
     fn new(obj: &'obj object::File<'data>) -> Self {
+        let splits_sections = obj.format() == BinaryFormat::MachO;
         let max_section_index = obj.sections().map(|s| s.index().0).max().unwrap_or(0);
         let mut section_infos = vec![SectionInfo::default(); max_section_index + 1];
+        let mut section_addresses = vec![0; max_section_index + 1];
+        for section in obj.sections() {
+            section_addresses[section.index().0] = section.address();
+        }
         for obj_symbol in obj.symbols() {
             let name = obj_symbol.name_bytes().unwrap_or_default();
             if name.is_empty() || !obj_symbol.is_definition() {
                 continue;
             }
+            // Mach-O symbols starting with `l`, such as `ltmp0`, are assembler-local and don't start
+            // an atom.
+            if splits_sections && matches!(name.first(), Some(b'l' | b'L')) {
+                continue;
+            }
             let Some(section_index) = obj_symbol.section_index() else {
                 continue;
             };
-            let section_info = &mut section_infos[section_index.0];
-            let symbol_is_first_in_section = section_info
-                .first_symbol
-                .as_ref()
-                .map(|existing| obj_symbol.address() < existing.offset)
-                .unwrap_or(true);
-            if symbol_is_first_in_section {
-                section_info.first_symbol = Some(SymbolInfo {
+            // Symbol addresses in Mach-O object files include the address of the section, whereas
+            // for other formats, sections in object files all have address 0.
+            section_infos[section_index.0]
+                .atom_symbols
+                .push(SymbolInfo {
                     symbol: Symbol::borrowed(name),
-                    offset: obj_symbol.address(),
+                    offset: obj_symbol
+                        .address()
+                        .wrapping_sub(section_addresses[section_index.0]),
                 });
+        }
+        for section_info in &mut section_infos {
+            // Sorting is stable, so where multiple symbols have the same offset, we keep whichever
+            // came first in the symbol table.
+            section_info.atom_symbols.sort_by_key(|info| info.offset);
+            section_info.atom_symbols.dedup_by_key(|info| info.offset);
+            if !splits_sections {
+                section_info.atom_symbols.truncate(1);
             }
         }
         Self { obj, section_infos }
     }
 
+    /// Returns the atoms of the specified section, each as its first symbol and the range of
+    /// offsets that it covers. Any part of the section before the first symbol is included in the
+    /// first atom.
+    fn atoms(
+        &self,
+        section_index: SectionIndex,
+    ) -> impl Iterator<Item = (&SymbolInfo<'data>, Range<u64>)> {
+        let symbols = self
+            .section_infos
+            .get(section_index.0)
+            .map(|section_info| section_info.atom_symbols.as_slice())
+            .unwrap_or_default();
+        symbols.iter().enumerate().map(|(i, symbol_info)| {
+            let start = if i == 0 { 0 } else { symbol_info.offset };
+            let end = symbols.get(i + 1).map_or(u64::MAX, |next| next.offset);
+            (symbol_info, start..end)
+        })
+    }
+
     /// Adds the symbol or symbols that `rel` refers to into `symbols_out`. If `rel` refers to a
-    /// section that doesn't define a non-local symbol at address 0, then all outgoing references
-    /// from that section will be included and so on recursively.
+    /// section (or atom) that doesn't define a non-local symbol at address 0, then all outgoing
+    /// references from that section will be included and so on recursively.
     fn add_target_symbols(
         &self,
-        rel: &object::Relocation,
+        rel: &Relocation,
         symbols_out: &mut Vec<Symbol<'data>>,
-        visited: &mut FxHashSet<SectionIndex>,
+        visited: &mut FxHashSet<(SectionIndex, Range<u64>)>,
         bin_symbols: &FxHashMap<Symbol, u64>,
     ) -> Result<()> {
         match self.get_symbol_or_section(rel.target(), bin_symbols)? {
-            SymbolOrSection::Symbol(symbol) => {
+            None => {}
+            Some(SymbolOrSection::Symbol(symbol)) => {
                 symbols_out.push(symbol);
             }
-            SymbolOrSection::Section(section_index) => {
-                if !visited.insert(section_index) {
+            Some(SymbolOrSection::Section(section_index, range)) => {
+                if !visited.insert((section_index, range.clone())) {
                     // We've already visited this section.
                     return Ok(());
                 }
                 let section = self.obj.section_by_index(section_index)?;
-                for (_, rel) in section.relocations() {
-                    self.add_target_symbols(&rel, symbols_out, visited, bin_symbols)?;
+                for (offset, rel) in section.relocations() {
+                    if range.contains(&offset) {
+                        self.add_target_symbols(&rel, symbols_out, visited, bin_symbols)?;
+                    }
                 }
             }
         }
         Ok(())
     }
 
-    /// Returns either symbol or the section index for a relocation target, giving preference to the
-    /// symbol.
+    /// Returns either symbol or the section index and range of offsets for a relocation target,
+    /// giving preference to the symbol. Returns `None` if the relocation doesn't refer to anything,
+    /// e.g. because it's for an absolute value.
     fn get_symbol_or_section(
         &self,
         target_in: RelocationTarget,
         bin_symbols: &FxHashMap<Symbol, u64>,
-    ) -> Result<SymbolOrSection<'data>> {
-        let section_index = match target_in {
+    ) -> Result<Option<SymbolOrSection<'data>>> {
+        let (section_index, offset) = match target_in {
             RelocationTarget::Symbol(symbol_index) => {
                 let Ok(symbol) = self.obj.symbol_by_index(symbol_index) else {
                     bail!("Invalid symbol index in object file");
@@ -640,38 +913,49 @@ impl<'obj, 'data> ObjectIndex<'obj, 'data> {
                 if !name.is_empty() {
                     let sym = Symbol::borrowed(name);
                     if bin_symbols.contains_key(&sym) || symbol.section_index().is_none() {
-                        return Ok(SymbolOrSection::Symbol(sym));
+                        return Ok(Some(SymbolOrSection::Symbol(sym)));
                     }
                 }
-                symbol.section_index().ok_or_else(|| {
+                let section_index = symbol.section_index().ok_or_else(|| {
                     anyhow!("Relocation target has empty name and no section index")
-                })?
+                })?;
+                let section = self.obj.section_by_index(section_index)?;
+                (
+                    section_index,
+                    Some(symbol.address().wrapping_sub(section.address())),
+                )
             }
+            // Mach-O relocations that aren't against a symbol refer to a section. The offset within
+            // the section is encoded in the instruction, which we don't decode.
+            RelocationTarget::Section(section_index) => (section_index, None),
+            RelocationTarget::Absolute => return Ok(None),
             _ => bail!("Unsupported relocation kind {target_in:?}"),
         };
-        let section_info = &self
-            .section_infos
-            .get(section_index.0)
-            .ok_or_else(|| anyhow!("Unnamed symbol has invalid section index"))?;
-        if let Some(first_symbol_info) = section_info.first_symbol.as_ref() {
-            if bin_symbols.contains_key(&first_symbol_info.symbol) {
-                return Ok(SymbolOrSection::Symbol(first_symbol_info.symbol.clone()));
-            }
+        if section_index.0 >= self.section_infos.len() {
+            bail!("Unnamed symbol has invalid section index");
         }
-        Ok(SymbolOrSection::Section(section_index))
-    }
-
-    /// Returns information about the first symbol in the section.
-    fn first_symbol(&self, section: &object::Section) -> Option<&SymbolInfo<'data>> {
-        self.section_infos
-            .get(section.index().0)
-            .and_then(|section_info| section_info.first_symbol.as_ref())
+        let mut atoms = self.atoms(section_index);
+        let atom = match offset {
+            Some(offset) => atoms.find(|(_, range)| range.contains(&offset)),
+            // Without an offset, we can only pick an atom if there's just the one.
+            None => atoms.next().filter(|_| atoms.next().is_none()),
+        };
+        let Some((first_symbol_info, range)) = atom else {
+            return Ok(Some(SymbolOrSection::Section(section_index, 0..u64::MAX)));
+        };
+        if bin_symbols.contains_key(&first_symbol_info.symbol) {
+            return Ok(Some(SymbolOrSection::Symbol(
+                first_symbol_info.symbol.clone(),
+            )));
+        }
+        Ok(Some(SymbolOrSection::Section(section_index, range)))
     }
 }
 
 enum SymbolOrSection<'data> {
     Symbol(Symbol<'data>),
-    Section(SectionIndex),
+    /// A section, or part of one, identified by its index and a range of offsets.
+    Section(SectionIndex, Range<u64>),
 }
 
 impl<'symbol, 'input: 'symbol> BinInfo<'input> {
@@ -765,6 +1049,20 @@ impl<'input> BinInfo<'input> {
                 }
             }
         }
+        // Non-Rust symbols, such as C library functions, don't have paths, so are matched by their
+        // whole name. We check these regardless of whether there's a debug name, since there may be
+        // a debug name for the declaration.
+        if let Some(symbol) = symbol_and_name.symbol.as_ref() {
+            if let Some(apis) = checker.apis_for_native_symbol(symbol) {
+                got_apis = true;
+                (callback)(
+                    crate::names::split_simple(symbol.to_str()?),
+                    NameSource::Symbol(symbol.clone()),
+                    apis,
+                )?;
+            }
+        }
+        self.apis_matched |= got_apis;
         if let Some(symbol) = symbol_and_name.symbol.as_ref() {
             if !got_apis {
                 // The need to call `to_heap` here is just to get past an annoying variance issue.
@@ -779,7 +1077,8 @@ impl<'input> BinInfo<'input> {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(bound(deserialize = "Symbol<'symbol>: Deserialize<'de>"))]
 pub(crate) enum NameSource<'symbol> {
     Symbol(Symbol<'symbol>),
     DebugName(DebugName<'static>),
@@ -817,6 +1116,41 @@ fn load_section<'data>(
     Ok(data)
 }
 
+/// Returns the byte order of `obj`. When cross-compiling, this might differ from the host.
+fn endian(obj: &object::File) -> RunTimeEndian {
+    if obj.is_little_endian() {
+        RunTimeEndian::Little
+    } else {
+        RunTimeEndian::Big
+    }
+}
+
+/// The location of an entry within an archive.
+struct ArchiveEntry {
+    name: Vec<u8>,
+    offset: u64,
+    size: u64,
+}
+
+/// Returns the locations of the entries in the archive `filename`, which is read via `cache`.
+/// Entries that can't be parsed are skipped.
+fn archive_entries(filename: &Path, cache: &ReadCache<File>) -> Result<Vec<ArchiveEntry>> {
+    let archive = ArchiveFile::parse(cache)
+        .with_context(|| format!("Failed to parse archive `{}`", filename.display()))?;
+    Ok(archive
+        .members()
+        .filter_map(|member| member.ok())
+        .map(|member| {
+            let (offset, size) = member.file_range();
+            ArchiveEntry {
+                name: member.name().to_owned(),
+                offset,
+                size,
+            }
+        })
+        .collect())
+}
+
 impl Filetype {
     fn from_filename(filename: &Path) -> Self {
         let Some(extension) = filename.extension() else {
@@ -881,7 +1215,7 @@ pub(crate) struct InlinedDebugData {
 impl InlinedDebugData {
     fn from_offset(
         low_pc: Option<u64>,
-        ctx: &addr2line::Context<EndianSlice<LittleEndian>>,
+        ctx: &addr2line::Context<EndianSlice<RunTimeEndian>>,
     ) -> Result<InlinedDebugData> {
         let mut frames = Vec::new();
         if let Some(offset) = low_pc {
@@ -895,3 +1229,199 @@ impl InlinedDebugData {
         Ok(InlinedDebugData { frames, low_pc })
     }
 }
+
+/// Returns whether `name` is a path within the crate for `pkg_id`. Paths use the crate name, which
+/// has underscores where the package name has hyphens.
+fn is_in_package(name: &Name, pkg_id: &PackageId) -> bool {
+    name.starts_with(&pkg_id.crate_name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_objects::RelocationTarget as TestTarget;
+    use super::test_objects::TestObject;
+    use super::test_objects::TestRelocation;
+    use super::test_objects::TestSection;
+    use super::test_objects::TestSymbol;
+    use super::*;
+
+    /// Returns the header of an archive entry with the specified name and size.
+    fn ar_header(name: &str, size: usize) -> String {
+        format!("{name:<16}{:<12}{:<6}{:<6}{:<8}{size:<10}`\n", 0, 0, 0, 644)
+    }
+
+    #[test]
+    fn archive_entries_are_located() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("libfoo.rlib");
+        let mut contents = "!<arch>\n".to_owned();
+        contents += &ar_header("a.o/", 3);
+        contents += "abc\n";
+        contents += &ar_header("bb.o/", 4);
+        contents += "defg";
+        std::fs::write(&path, &contents).unwrap();
+
+        let cache = ReadCache::new(File::open(&path).unwrap());
+        let entries = archive_entries(&path, &cache).unwrap();
+        let entries: Vec<(&[u8], &[u8])> = entries
+            .iter()
+            .map(|entry| {
+                let bytes = (&cache).read_bytes_at(entry.offset, entry.size).unwrap();
+                (entry.name.as_slice(), bytes)
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![(b"a.o".as_slice(), b"abc".as_slice()), (b"bb.o", b"defg")]
+        );
+    }
+
+    fn symbol(name: &'static str, definition: Option<(usize, u32)>) -> TestSymbol {
+        TestSymbol { name, definition }
+    }
+
+    fn relocation(offset: u32, target: TestTarget) -> TestRelocation {
+        TestRelocation { offset, target }
+    }
+
+    /// Returns the names of the atoms in the section named `section_name` with their ranges.
+    fn atoms(index: &ObjectIndex, section_name: &str) -> Vec<(String, Range<u64>)> {
+        let section = index.obj.section_by_name(section_name).unwrap();
+        index
+            .atoms(section.index())
+            .map(|(info, range)| (info.symbol.to_string(), range))
+            .collect()
+    }
+
+    /// Returns the names of the symbols referenced by the relocation at `offset` in the section
+    /// named `section_name`, when the bin contains `bin_symbols`.
+    fn targets(
+        index: &ObjectIndex,
+        section_name: &str,
+        offset: u64,
+        bin_symbols: &[&str],
+    ) -> Vec<String> {
+        let bin_symbols = bin_symbols
+            .iter()
+            .map(|name| (Symbol::borrowed(name.as_bytes()), 0))
+            .collect();
+        let section = index.obj.section_by_name(section_name).unwrap();
+        let (_, rel) = section
+            .relocations()
+            .find(|(rel_offset, _)| *rel_offset == offset)
+            .unwrap();
+        let mut symbols = Vec::new();
+        index
+            .add_target_symbols(&rel, &mut symbols, &mut FxHashSet::default(), &bin_symbols)
+            .unwrap();
+        symbols.iter().map(|symbol| symbol.to_string()).collect()
+    }
+
+    #[test]
+    fn names_in_hyphenated_package() {
+        let pkg_id = crate::crate_index::testing::pkg_id("foo-bar");
+        assert!(is_in_package(
+            &crate::names::split_simple("foo_bar::fs::read"),
+            &pkg_id
+        ));
+        assert!(!is_in_package(
+            &crate::names::split_simple("std::fs::read"),
+            &pkg_id
+        ));
+        assert!(!is_in_package(
+            &crate::names::split_simple("foo::fs::read"),
+            &pkg_id
+        ));
+    }
+
+    #[test]
+    fn macho_atoms() {
+        let object = TestObject {
+            sections: vec![
+                TestSection {
+                    name: "__text",
+                    size: 32,
+                    relocations: vec![
+                        relocation(4, TestTarget::Symbol(4)),
+                        relocation(20, TestTarget::Symbol(3)),
+                    ],
+                },
+                TestSection {
+                    name: "__const",
+                    size: 16,
+                    relocations: vec![
+                        relocation(0, TestTarget::Symbol(2)),
+                        relocation(8, TestTarget::Symbol(5)),
+                        relocation(12, TestTarget::Section(0)),
+                    ],
+                },
+            ],
+            symbols: vec![
+                symbol("ltmp0", Some((0, 0))),
+                symbol("_f", Some((0, 0))),
+                symbol("_g", Some((0, 16))),
+                symbol("_h", Some((1, 0))),
+                symbol("_read", None),
+                symbol("_write", None),
+                symbol("_k", Some((1, 12))),
+            ],
+        }
+        .macho();
+        let obj = object::File::parse(object.as_slice()).unwrap();
+        let index = ObjectIndex::new(&obj);
+        assert_eq!(
+            atoms(&index, "__text"),
+            vec![("_f".to_owned(), 0..16), ("_g".to_owned(), 16..u64::MAX)]
+        );
+        // `_h`'s address is 32, since it comes after `__text`, but its offset is 0.
+        assert_eq!(
+            atoms(&index, "__const"),
+            vec![("_h".to_owned(), 0..12), ("_k".to_owned(), 12..u64::MAX)]
+        );
+
+        assert_eq!(targets(&index, "__text", 20, &["_h"]), vec!["_h"]);
+        // `_g` isn't in the bin, so we follow its references, which lead to `_h`'s. We don't follow
+        // those of `_f` or `_k`, which are in the same sections.
+        assert_eq!(targets(&index, "__const", 0, &[]), vec!["_write"]);
+        // A reference to a section, rather than a symbol, could be to any of its atoms.
+        assert_eq!(targets(&index, "__const", 12, &[]), vec!["_read", "_write"]);
+    }
+
+    #[test]
+    fn coff_section_symbols() {
+        let object = TestObject {
+            sections: vec![
+                TestSection {
+                    name: ".text$f",
+                    size: 16,
+                    relocations: vec![relocation(4, TestTarget::Symbol(1))],
+                },
+                TestSection {
+                    name: ".text$g",
+                    size: 16,
+                    relocations: vec![relocation(4, TestTarget::Symbol(2))],
+                },
+            ],
+            symbols: vec![
+                symbol("f", Some((0, 0))),
+                symbol("g", Some((1, 0))),
+                symbol("read", None),
+            ],
+        }
+        .coff();
+        let obj = object::File::parse(object.as_slice()).unwrap();
+        let index = ObjectIndex::new(&obj);
+        // Section symbols, such as `.text$f`, aren't used to identify sections.
+        assert_eq!(
+            atoms(&index, ".text$f"),
+            vec![("f".to_owned(), 0..u64::MAX)]
+        );
+        assert_eq!(
+            atoms(&index, ".text$g"),
+            vec![("g".to_owned(), 0..u64::MAX)]
+        );
+
+        assert_eq!(targets(&index, ".text$f", 4, &["g"]), vec!["g"]);
+        assert_eq!(targets(&index, ".text$f", 4, &[]), vec!["read"]);
+    }
+}
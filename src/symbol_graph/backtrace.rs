@@ -62,8 +62,8 @@ impl Backtracer {
             )
         })?;
         let owned_dwarf = Dwarf::load(|id| super::load_section(&obj, id))?;
-        let dwarf =
-            owned_dwarf.borrow(|section| gimli::EndianSlice::new(section, gimli::LittleEndian));
+        let endian = super::endian(&obj);
+        let dwarf = owned_dwarf.borrow(|section| gimli::EndianSlice::new(section, endian));
         let ctx = addr2line::Context::from_dwarf(dwarf)
             .context("Failed in addr2line during backtrace")?;
 
@@ -14,7 +14,7 @@ use gimli::Attribute;
 use gimli::AttributeValue;
 use gimli::Dwarf;
 use gimli::EndianSlice;
-use gimli::LittleEndian;
+use gimli::RunTimeEndian;
 use gimli::Unit;
 use gimli::UnitOffset;
 use std::ffi::OsStr;
@@ -45,6 +45,10 @@ pub(crate) struct InlinedFunction<'input> {
     pub(crate) from: SymbolAndName<'input>,
     pub(crate) to: SymbolAndName<'input>,
     pub(crate) call_location: CallLocation<'input>,
+    /// Where the outermost inlined function was called from. This is a location within the
+    /// non-inlined function into which everything was inlined. None if `from` is itself the
+    /// non-inlined function.
+    pub(crate) outer_call_location: Option<CallLocation<'input>>,
 }
 
 impl<'input> CallLocation<'input> {
@@ -65,7 +69,7 @@ impl<'input> CallLocation<'input> {
 
 impl<'input> DebugArtifacts<'input> {
     pub(crate) fn from_dwarf(
-        dwarf: &Dwarf<EndianSlice<'input, LittleEndian>>,
+        dwarf: &Dwarf<EndianSlice<'input, RunTimeEndian>>,
         checker: &Checker,
     ) -> Result<Self> {
         let mut scanner = DwarfScanner::default();
@@ -76,7 +80,7 @@ impl<'input> DebugArtifacts<'input> {
 }
 
 impl<'input> DwarfScanner<'input> {
-    fn index_units(&mut self, dwarf: &Dwarf<EndianSlice<'input, LittleEndian>>) -> Result<()> {
+    fn index_units(&mut self, dwarf: &Dwarf<EndianSlice<'input, RunTimeEndian>>) -> Result<()> {
         let mut unit_headers = dwarf.units();
         while let Some(header) = unit_headers.next()? {
             let Some(debug_offset) = header.offset().as_debug_info_offset() else {
@@ -91,7 +95,7 @@ impl<'input> DwarfScanner<'input> {
 
     fn scan(
         &mut self,
-        dwarf: &Dwarf<EndianSlice<'input, LittleEndian>>,
+        dwarf: &Dwarf<EndianSlice<'input, RunTimeEndian>>,
         checker: &Checker,
     ) -> Result<()> {
         for unit in &self.units {
@@ -175,7 +179,7 @@ impl<'input> DwarfScanner<'input> {
     fn unit_containing(
         &self,
         offset: gimli::DebugInfoOffset,
-    ) -> Result<&Unit<EndianSlice<'input, LittleEndian>>> {
+    ) -> Result<&Unit<EndianSlice<'input, RunTimeEndian>>> {
         match self.unit_offsets.binary_search(&offset) {
             Ok(0) | Err(0) => {}
             Ok(index) | Err(index) => {
@@ -194,8 +198,8 @@ impl<'input> DwarfScanner<'input> {
 /// from the offset, we can determine information about the subprogram's attributes, but not about
 /// the namespace in which it's contained.
 fn get_subprogram_namespaces(
-    unit: &Unit<EndianSlice<LittleEndian>, usize>,
-    dwarf: &Dwarf<EndianSlice<LittleEndian>>,
+    unit: &Unit<EndianSlice<RunTimeEndian>, usize>,
+    dwarf: &Dwarf<EndianSlice<RunTimeEndian>>,
 ) -> Result<FxHashMap<UnitOffset, Namespace>> {
     let mut subprogram_namespaces: FxHashMap<UnitOffset, Namespace> = Default::default();
     let mut stack: Vec<Option<Namespace>> = Vec::new();
@@ -247,9 +251,9 @@ fn get_subprogram_namespaces(
 }
 
 struct UnitState<'input, 'dwarf> {
-    dwarf: &'dwarf Dwarf<EndianSlice<'input, LittleEndian>>,
+    dwarf: &'dwarf Dwarf<EndianSlice<'input, RunTimeEndian>>,
     frames: Vec<FrameState<'input>>,
-    unit: &'dwarf Unit<EndianSlice<'input, LittleEndian>, usize>,
+    unit: &'dwarf Unit<EndianSlice<'input, RunTimeEndian>, usize>,
     compdir: &'input Path,
     subprogram_namespaces: FxHashMap<UnitOffset, Namespace>,
 }
@@ -257,14 +261,14 @@ struct UnitState<'input, 'dwarf> {
 impl<'input, 'dwarf> UnitState<'input, 'dwarf> {
     fn attr_string(
         &self,
-        attr: AttributeValue<EndianSlice<'input, LittleEndian>, usize>,
-    ) -> Result<gimli::EndianSlice<'input, LittleEndian>> {
+        attr: AttributeValue<EndianSlice<'input, RunTimeEndian>, usize>,
+    ) -> Result<gimli::EndianSlice<'input, RunTimeEndian>> {
         Ok(self.dwarf.attr_string(self.unit, attr)?)
     }
 
     fn get_directory_and_filename(
         &self,
-        file_index: AttributeValue<EndianSlice<'input, LittleEndian>, usize>,
+        file_index: AttributeValue<EndianSlice<'input, RunTimeEndian>, usize>,
     ) -> Result<(Option<&'input OsStr>, &'input OsStr), anyhow::Error> {
         let header = self.line_program_header()?;
         let gimli::AttributeValue::FileIndex(file_index) = file_index else {
@@ -285,7 +289,7 @@ impl<'input, 'dwarf> UnitState<'input, 'dwarf> {
 
     fn line_program_header(
         &self,
-    ) -> Result<&gimli::LineProgramHeader<gimli::EndianSlice<'input, LittleEndian>>> {
+    ) -> Result<&gimli::LineProgramHeader<gimli::EndianSlice<'input, RunTimeEndian>>> {
         let line_program = self
             .unit
             .line_program
@@ -296,21 +300,21 @@ impl<'input, 'dwarf> UnitState<'input, 'dwarf> {
 
     fn get_symbol_and_name(
         &self,
-        attr: gimli::Attribute<EndianSlice<'input, LittleEndian>>,
+        attr: gimli::Attribute<EndianSlice<'input, RunTimeEndian>>,
         scanner: &DwarfScanner<'input>,
         max_depth: u32,
     ) -> Result<SymbolAndName<'input>> {
         match attr.value() {
             AttributeValue::UnitRef(unit_offset) => {
                 let unit = self.unit;
-                return self.get_symbol_and_name_in_unit(unit, unit_offset, max_depth, scanner);
+                self.get_symbol_and_name_in_unit(unit, unit_offset, max_depth, scanner)
             }
             AttributeValue::DebugInfoRef(offset) => {
                 let unit = scanner.unit_containing(offset)?;
                 let unit_offset = offset
                     .to_unit_offset(&unit.header)
                     .ok_or_else(|| anyhow!("Invalid unit offset"))?;
-                return self.get_symbol_and_name_in_unit(unit, unit_offset, max_depth, scanner);
+                self.get_symbol_and_name_in_unit(unit, unit_offset, max_depth, scanner)
             }
             _ => {
                 bail!("Unsupported abstract_origin type: {:?}", attr.value());
@@ -320,7 +324,7 @@ impl<'input, 'dwarf> UnitState<'input, 'dwarf> {
 
     fn get_symbol_and_name_in_unit(
         &self,
-        unit: &Unit<EndianSlice<'input, LittleEndian>, usize>,
+        unit: &Unit<EndianSlice<'input, RunTimeEndian>, usize>,
         unit_offset: UnitOffset,
         max_depth: u32,
         scanner: &DwarfScanner<'input>,
@@ -360,7 +364,7 @@ impl<'input, 'dwarf> UnitState<'input, 'dwarf> {
 
     fn scan_namespace(
         &self,
-        entries: &mut gimli::EntriesRaw<EndianSlice<'input, LittleEndian>>,
+        entries: &mut gimli::EntriesRaw<EndianSlice<'input, RunTimeEndian>>,
         attributes: &[gimli::AttributeSpecification],
     ) -> Result<Option<Namespace>> {
         // TODO: See if we can reduce duplication between this function and
@@ -400,7 +404,7 @@ impl<'input, 'dwarf> UnitState<'input, 'dwarf> {
 struct DwarfScanner<'input> {
     out: DebugArtifacts<'input>,
     unit_offsets: Vec<gimli::DebugInfoOffset>,
-    units: Vec<gimli::Unit<EndianSlice<'input, LittleEndian>>>,
+    units: Vec<gimli::Unit<EndianSlice<'input, RunTimeEndian>>>,
 }
 
 impl<'input> SymbolDebugInfo<'input> {
@@ -416,17 +420,17 @@ impl<'input> SymbolDebugInfo<'input> {
 
 #[derive(Default)]
 struct SymbolDebugInfoScanner<'input> {
-    name: Option<AttributeValue<EndianSlice<'input, LittleEndian>>>,
-    linkage_name: Option<AttributeValue<EndianSlice<'input, LittleEndian>>>,
+    name: Option<AttributeValue<EndianSlice<'input, RunTimeEndian>>>,
+    linkage_name: Option<AttributeValue<EndianSlice<'input, RunTimeEndian>>>,
     line: Option<u32>,
     column: Option<u32>,
-    file_index: Option<AttributeValue<EndianSlice<'input, LittleEndian>>>,
+    file_index: Option<AttributeValue<EndianSlice<'input, RunTimeEndian>>>,
 }
 
 impl<'input> SymbolDebugInfoScanner<'input> {
     fn handle_attribute(
         &mut self,
-        attr: Attribute<EndianSlice<'input, LittleEndian>>,
+        attr: Attribute<EndianSlice<'input, RunTimeEndian>>,
     ) -> Result<()> {
         match attr.name() {
             gimli::DW_AT_name => {
@@ -494,7 +498,7 @@ impl<'input> SymbolDebugInfoScanner<'input> {
     }
 }
 
-fn path_from_opt_slice(slice: Option<gimli::EndianSlice<gimli::LittleEndian>>) -> &Path {
+fn path_from_opt_slice(slice: Option<gimli::EndianSlice<'_, gimli::RunTimeEndian>>) -> &Path {
     slice
         .map(|dir| Path::new(OsStr::from_bytes(dir.slice())))
         .unwrap_or_else(|| Path::new(""))
@@ -527,7 +531,7 @@ pub(crate) struct CallLocation<'input> {
 impl<'input> InlinedFunctionScanner<'input> {
     fn handle_attribute<'dwarf>(
         &mut self,
-        attr: Attribute<EndianSlice<'input, LittleEndian>>,
+        attr: Attribute<EndianSlice<'input, RunTimeEndian>>,
         unit_state: &UnitState<'input, 'dwarf>,
         scanner: &DwarfScanner<'input>,
     ) -> Result<()> {
@@ -616,6 +620,7 @@ impl<'input> InlinedFunctionScanner<'input> {
                     from: frame.names.clone(),
                     to: self.names.clone(),
                     call_location: call_location.clone(),
+                    outer_call_location: outer_call_location(frames),
                 });
             }
         }
@@ -626,3 +631,15 @@ impl<'input> InlinedFunctionScanner<'input> {
         self.symbol_start = None;
     }
 }
+
+/// Returns the call location of the outermost inlined function in `frames`. The first frame with a
+/// name is the non-inlined function, so the next frame after it that has a call location is the
+/// outermost call.
+fn outer_call_location<'input>(frames: &[FrameState<'input>]) -> Option<CallLocation<'input>> {
+    frames
+        .iter()
+        .skip_while(|frame| frame.names.symbol.is_none() && frame.names.debug_name.is_none())
+        .skip(1)
+        .find(|frame| frame.call_location.filename.is_some())
+        .map(|frame| frame.call_location.clone())
+}
@@ -0,0 +1,258 @@
+//! Looks up symbols in the binaries left by a previous build, reporting where each is defined, which
+//! package that location is attributed to and which APIs the symbol's name matches. This is useful
+//! when diagnosing why an API usage was attributed to an unexpected package.
+
+use super::dwarf::DebugArtifacts;
+use super::endian;
+use super::load_section;
+use crate::checker::Checker;
+use crate::config::ApiName;
+use crate::location::SourceLocation;
+use crate::names::DebugName;
+use crate::symbol::Symbol;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use gimli::Dwarf;
+use object::Object;
+use object::ObjectKind;
+use object::ObjectSymbol;
+use std::collections::BTreeSet;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct LookupSymbolOptions {
+    /// The symbol to look up, either mangled, e.g. `_ZN3std2fs5write17h0123456789abcdefE`, or
+    /// demangled without the hash, e.g. `std::fs::write`.
+    symbol: String,
+
+    /// A binary to search. May be repeated. Defaults to the executables and shared objects left in
+    /// the target directory by the last build.
+    #[clap(long)]
+    bin: Vec<PathBuf>,
+}
+
+/// A definition of a symbol that was found in a binary.
+struct Definition {
+    symbol: String,
+    demangled: String,
+    location: Option<SourceLocation>,
+    /// Whether the symbol is only referenced by the binary, e.g. because it's defined in a shared
+    /// library.
+    is_undefined: bool,
+    apis: BTreeSet<ApiName>,
+}
+
+/// Prints the definitions of the symbol requested by `options` in binaries in `profile_dir`, or in
+/// those passed on the command line.
+pub(crate) fn print(
+    checker: &Checker,
+    profile_dir: &Path,
+    options: &LookupSymbolOptions,
+) -> Result<()> {
+    let bins = if options.bin.is_empty() {
+        find_binaries(profile_dir)?
+    } else {
+        options.bin.clone()
+    };
+    let mut num_found = 0;
+    for bin in &bins {
+        for definition in find_definitions(bin, &options.symbol, checker)? {
+            num_found += 1;
+            println!("{} ({})", definition.demangled, definition.symbol);
+            println!("  binary: {}", bin.display());
+            match &definition.location {
+                Some(location) => {
+                    println!("  defined at: {location}");
+                    let package = match checker.opt_pkg_ids_from_source_path(location.filename()) {
+                        Some(pkg_ids) if pkg_ids.is_empty() => "Rust standard library".to_owned(),
+                        Some(pkg_ids) => pkg_ids
+                            .iter()
+                            .map(|pkg_id| pkg_id.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        None => "unknown".to_owned(),
+                    };
+                    println!("  package: {package}");
+                }
+                None if definition.is_undefined => {
+                    println!("  defined at: not defined in this binary")
+                }
+                None => println!("  defined at: unknown (no debug info)"),
+            }
+            println!("  matches APIs: {}", api_list(&definition.apis));
+        }
+    }
+    if num_found == 0 {
+        println!(
+            "`{}` wasn't found in any of {} binaries",
+            options.symbol,
+            bins.len()
+        );
+        // The user may just want to know what a name would be matched as.
+        let name = crate::names::split_simple(&options.symbol);
+        let apis = checker.apis_for_name_iterator(name.parts());
+        println!(
+            "  matches APIs: {}",
+            api_list(&apis.iter().cloned().collect())
+        );
+    }
+    Ok(())
+}
+
+fn api_list(apis: &BTreeSet<ApiName>) -> String {
+    if apis.is_empty() {
+        return "(none)".to_owned();
+    }
+    apis.iter()
+        .map(|api| api.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns the executables and shared objects under `dir`. Object files from `-C save-temps` and
+/// the scripts that we put in place of binaries that we wrap are skipped.
+fn find_binaries(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut bins = Vec::new();
+    let mut pending = vec![dir.to_owned()];
+    while let Some(dir) = pending.pop() {
+        let entries = dir
+            .read_dir()
+            .with_context(|| format!("Failed to read directory `{}`", dir.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                if !matches!(
+                    path.file_name().and_then(|name| name.to_str()),
+                    Some("incremental" | ".fingerprint")
+                ) {
+                    pending.push(path);
+                }
+            } else if file_type.is_file()
+                && !matches!(
+                    path.extension().and_then(|ext| ext.to_str()),
+                    Some("o" | "rlib" | "rmeta" | "a" | "d")
+                )
+                && has_elf_magic(&path)
+            {
+                bins.push(path);
+            }
+        }
+    }
+    bins.sort();
+    Ok(bins)
+}
+
+fn has_elf_magic(path: &Path) -> bool {
+    let mut magic = [0; 4];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && magic == *b"\x7fELF"
+}
+
+/// Returns definitions in `bin` of symbols that match `query`, which is either a mangled symbol or
+/// a demangled symbol without its hash.
+fn find_definitions(bin: &Path, query: &str, checker: &Checker) -> Result<Vec<Definition>> {
+    let file_bytes =
+        std::fs::read(bin).with_context(|| format!("Failed to read `{}`", bin.display()))?;
+    let obj = object::File::parse(file_bytes.as_slice())
+        .with_context(|| format!("Failed to parse `{}`", bin.display()))?;
+    if !matches!(obj.kind(), ObjectKind::Executable | ObjectKind::Dynamic) {
+        return Ok(Vec::new());
+    }
+    let mut matched = Vec::new();
+    for sym in obj.symbols() {
+        let Ok(name) = sym.name() else {
+            continue;
+        };
+        if symbol_matches(name, query) && !matched.iter().any(|(n, _, _)| *n == name) {
+            matched.push((name, sym.address(), sym.is_undefined()));
+        }
+    }
+    if matched.is_empty() {
+        return Ok(Vec::new());
+    }
+    let owned_dwarf = Dwarf::load(|id| load_section(&obj, id))?;
+    let endian = endian(&obj);
+    let dwarf = owned_dwarf.borrow(|section| gimli::EndianSlice::new(section, endian));
+    let debug_artifacts = DebugArtifacts::from_dwarf(&dwarf, checker)
+        .with_context(|| format!("Failed while processing debug info for `{}`", bin.display()))?;
+    let ctx = addr2line::Context::from_dwarf(
+        owned_dwarf.borrow(|section| gimli::EndianSlice::new(section, endian)),
+    )
+    .with_context(|| format!("Failed in addr2line for `{}`", bin.display()))?;
+    let mut definitions = Vec::new();
+    for (name, address, is_undefined) in matched {
+        let symbol = Symbol::borrowed(name.as_bytes());
+        let debug_info = debug_artifacts.symbol_debug_info.get(&symbol);
+        let location = match debug_info {
+            Some(debug_info) => Some(debug_info.source_location()),
+            None if is_undefined => None,
+            // Symbols from the standard library have no debug info of their own, since we skip
+            // its compilation units, but we may still be able to find the location from their
+            // address.
+            None => ctx
+                .find_location(address)?
+                .and_then(|location| SourceLocation::try_from(&location).ok()),
+        };
+        definitions.push(Definition {
+            symbol: name.to_owned(),
+            demangled: format!("{:#}", rustc_demangle::demangle(name)),
+            location,
+            is_undefined,
+            apis: apis_for_symbol(
+                &symbol,
+                debug_info.and_then(|info| info.name.as_ref()),
+                checker,
+            )?,
+        });
+    }
+    Ok(definitions)
+}
+
+/// Returns whether the symbol `name` matches `query`.
+fn symbol_matches(name: &str, query: &str) -> bool {
+    name == query || format!("{:#}", rustc_demangle::demangle(name)) == query
+}
+
+/// Returns the APIs matched by any of the names in `symbol`, or in `debug_name` if we have it, since
+/// that's what's used when checking.
+fn apis_for_symbol(
+    symbol: &Symbol,
+    debug_name: Option<&DebugName>,
+    checker: &Checker,
+) -> Result<BTreeSet<ApiName>> {
+    let mut apis = BTreeSet::new();
+    if let Some(debug_name) = debug_name {
+        let mut it = debug_name.names_iterator();
+        while let Some((parts, _)) = it.next_name()? {
+            apis.extend(checker.apis_for_name_iterator(parts).iter().cloned());
+        }
+    } else {
+        let mut it = symbol.names()?;
+        while let Some((parts, _)) = it.next_name()? {
+            apis.extend(checker.apis_for_name_iterator(parts).iter().cloned());
+        }
+    }
+    Ok(apis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_mangled_and_demangled() {
+        let mangled = "_ZN3std2fs5write17h0123456789abcdefE";
+        assert!(symbol_matches(mangled, mangled));
+        assert!(symbol_matches(mangled, "std::fs::write"));
+        assert!(!symbol_matches(mangled, "std::fs::read"));
+        assert!(!symbol_matches(mangled, "std::fs"));
+        assert!(symbol_matches("atexit", "atexit"));
+    }
+}
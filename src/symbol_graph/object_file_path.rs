@@ -1,7 +1,6 @@
 use anyhow::Context;
 use anyhow::Result;
 use std::fmt::Display;
-use std::fs::File;
 use std::path::Path;
 use std::path::PathBuf;
 
@@ -21,15 +20,13 @@ impl ObjectFilePath {
         }
     }
 
-    pub(crate) fn in_archive(archive: &Path, entry: &ar::Entry<File>) -> Result<Self> {
-        let inner = PathBuf::from(
-            std::str::from_utf8(entry.header().identifier()).with_context(|| {
-                format!(
-                    "An archive entry in `{}` is not valid UTF-8",
-                    archive.display()
-                )
-            })?,
-        );
+    pub(crate) fn in_archive(archive: &Path, entry_name: &[u8]) -> Result<Self> {
+        let inner = PathBuf::from(std::str::from_utf8(entry_name).with_context(|| {
+            format!(
+                "An archive entry in `{}` is not valid UTF-8",
+                archive.display()
+            )
+        })?);
         Ok(Self {
             outer: archive.to_owned(),
             inner: Some(inner),
@@ -0,0 +1,82 @@
+//! Records which sections of object files have been found not to reference any APIs. The same
+//! object files, in particular those in the rlibs of dependencies, get linked into many binaries
+//! (bins, tests and build scripts), so this lets us avoid repeating work for each link step.
+
+use crate::config::ApiConfig;
+use crate::config::ApiName;
+use fxhash::FxHashMap;
+use fxhash::FxHashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+#[derive(Default)]
+pub(crate) struct ObjectMemo {
+    /// The API definitions with which the sections were checked. Sections that reference no APIs
+    /// may well reference newly added APIs, so everything is discarded if these change.
+    apis: BTreeMap<ApiName, ApiConfig>,
+
+    /// For each object file, keyed by the hash of its contents, the indexes of sections that don't
+    /// reference any APIs. Only sections that were linked into a binary and fully checked are
+    /// recorded, since a section that the linker discarded from one binary may be live in another.
+    api_free_sections: FxHashMap<u64, FxHashSet<usize>>,
+}
+
+impl ObjectMemo {
+    /// Returns the key for an object file with the supplied contents. We use SipHash rather than
+    /// FxHash, since a collision would mean that we'd miss API usages.
+    pub(crate) fn key(object_bytes: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        object_bytes.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Discards what we've recorded if `apis` differs from the APIs that it was recorded with.
+    pub(crate) fn set_apis(&mut self, apis: &BTreeMap<ApiName, ApiConfig>) {
+        if self.apis != *apis {
+            self.apis = apis.clone();
+            self.api_free_sections.clear();
+        }
+    }
+
+    /// Returns whether the section with index `section_index` in the object file with key `key` is
+    /// known not to reference any APIs.
+    pub(crate) fn is_api_free(&self, key: u64, section_index: usize) -> bool {
+        self.api_free_sections
+            .get(&key)
+            .is_some_and(|sections| sections.contains(&section_index))
+    }
+
+    /// Records that the sections in `sections` of the object file with key `key` don't reference
+    /// any APIs.
+    pub(crate) fn add_api_free_sections(&mut self, key: u64, sections: FxHashSet<usize>) {
+        self.api_free_sections
+            .entry(key)
+            .or_default()
+            .extend(sections);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discarded_when_apis_change() {
+        let mut memo = ObjectMemo::default();
+        let key = ObjectMemo::key(b"object");
+        assert_ne!(key, ObjectMemo::key(b"other object"));
+        memo.add_api_free_sections(key, [1, 3].into_iter().collect());
+        assert!(memo.is_api_free(key, 3));
+        assert!(!memo.is_api_free(key, 2));
+
+        memo.set_apis(&BTreeMap::new());
+        assert!(memo.is_api_free(key, 1));
+
+        let mut apis = BTreeMap::new();
+        apis.insert(ApiName::new("fs"), ApiConfig::default());
+        memo.set_apis(&apis);
+        assert!(!memo.is_api_free(key, 1));
+    }
+}
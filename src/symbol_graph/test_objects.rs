@@ -0,0 +1,274 @@
+//! Builds minimal object files in formats other than ELF, so that we can test our handling of
+//! objects from cross-compiled targets without needing the toolchains for those targets.
+
+pub(super) struct TestObject {
+    pub(super) sections: Vec<TestSection>,
+    pub(super) symbols: Vec<TestSymbol>,
+}
+
+pub(super) struct TestSection {
+    pub(super) name: &'static str,
+    pub(super) size: u32,
+    pub(super) relocations: Vec<TestRelocation>,
+}
+
+pub(super) struct TestSymbol {
+    pub(super) name: &'static str,
+    /// The index into `TestObject::sections` and offset within that section of the symbol's
+    /// definition. `None` if the symbol is undefined.
+    pub(super) definition: Option<(usize, u32)>,
+}
+
+pub(super) struct TestRelocation {
+    pub(super) offset: u32,
+    pub(super) target: RelocationTarget,
+}
+
+pub(super) enum RelocationTarget {
+    /// An index into `TestObject::symbols`.
+    Symbol(usize),
+    /// An index into `TestObject::sections`. Only supported for Mach-O.
+    Section(usize),
+}
+
+impl TestObject {
+    /// Returns the object as an x86_64 Mach-O object file. Sections are given consecutive addresses
+    /// and are all placed in a single segment, as is usual for object files.
+    pub(super) fn macho(&self) -> Vec<u8> {
+        const HEADER_SIZE: u32 = 32;
+        const SEGMENT_SIZE: u32 = 72;
+        const SECTION_SIZE: u32 = 80;
+        const SYMTAB_SIZE: u32 = 24;
+        let num_sections = self.sections.len() as u32;
+        let commands_size = SEGMENT_SIZE + SECTION_SIZE * num_sections + SYMTAB_SIZE;
+        let data_offset = HEADER_SIZE + commands_size;
+        let data_size: u32 = self.sections.iter().map(|section| section.size).sum();
+        let mut addresses = Vec::new();
+        let mut address = 0;
+        for section in &self.sections {
+            addresses.push(address);
+            address += section.size;
+        }
+        let mut reloc_offset = data_offset + data_size;
+        let mut reloc_offsets = Vec::new();
+        for section in &self.sections {
+            reloc_offsets.push(reloc_offset);
+            reloc_offset += 8 * section.relocations.len() as u32;
+        }
+        let symbols_offset = reloc_offset;
+        // Mach-O string tables conventionally start with a null, so that offset 0 isn't a name.
+        let (strings, string_offsets) = string_table(&self.symbols, 1);
+        let strings_offset = symbols_offset + 16 * self.symbols.len() as u32;
+
+        let mut out = Vec::new();
+        // mach_header_64: magic, cputype (x86_64), cpusubtype, filetype (MH_OBJECT), ncmds,
+        // sizeofcmds, flags (MH_SUBSECTIONS_VIA_SYMBOLS), reserved.
+        for value in [0xfeedfacf, 0x0100_0007, 3, 1, 2, commands_size, 0x2000, 0] {
+            push_u32(&mut out, value);
+        }
+        // LC_SEGMENT_64
+        push_u32(&mut out, 0x19);
+        push_u32(&mut out, SEGMENT_SIZE + SECTION_SIZE * num_sections);
+        push_name(&mut out, "", 16);
+        for value in [0, data_size as u64, data_offset as u64, data_size as u64] {
+            push_u64(&mut out, value);
+        }
+        for value in [7, 7, num_sections, 0] {
+            push_u32(&mut out, value);
+        }
+        for (index, section) in self.sections.iter().enumerate() {
+            push_name(&mut out, section.name, 16);
+            push_name(&mut out, "__TEXT", 16);
+            push_u64(&mut out, addresses[index] as u64);
+            push_u64(&mut out, section.size as u64);
+            for value in [
+                data_offset + addresses[index],
+                0,
+                reloc_offsets[index],
+                section.relocations.len() as u32,
+                0,
+                0,
+                0,
+                0,
+            ] {
+                push_u32(&mut out, value);
+            }
+        }
+        // LC_SYMTAB
+        for value in [
+            0x2,
+            SYMTAB_SIZE,
+            symbols_offset,
+            self.symbols.len() as u32,
+            strings_offset,
+            strings.len() as u32,
+        ] {
+            push_u32(&mut out, value);
+        }
+        out.resize((data_offset + data_size) as usize, 0);
+        for section in &self.sections {
+            for relocation in &section.relocations {
+                push_u32(&mut out, relocation.offset);
+                // r_symbolnum, then r_pcrel, r_length, r_extern and r_type.
+                let info = match relocation.target {
+                    // X86_64_RELOC_BRANCH, 4 bytes, PC-relative.
+                    RelocationTarget::Symbol(index) => {
+                        index as u32 | 1 << 24 | 2 << 25 | 1 << 27 | 2 << 28
+                    }
+                    // X86_64_RELOC_UNSIGNED, 8 bytes. Section numbers start at 1.
+                    RelocationTarget::Section(index) => (index as u32 + 1) | 3 << 25,
+                };
+                push_u32(&mut out, info);
+            }
+        }
+        for (symbol, string_offset) in self.symbols.iter().zip(string_offsets) {
+            push_u32(&mut out, string_offset);
+            match symbol.definition {
+                Some((section, offset)) => {
+                    // N_SECT | N_EXT
+                    out.push(0xf);
+                    out.push(section as u8 + 1);
+                    push_u16(&mut out, 0);
+                    push_u64(&mut out, (addresses[section] + offset) as u64);
+                }
+                None => {
+                    // N_UNDF | N_EXT
+                    out.push(0x1);
+                    out.push(0);
+                    push_u16(&mut out, 0);
+                    push_u64(&mut out, 0);
+                }
+            }
+        }
+        out.extend_from_slice(&strings);
+        out
+    }
+
+    /// Returns the object as an x86_64 COFF object file. Each section gets a section symbol, as
+    /// would be emitted by a compiler, so symbol indexes in relocations are adjusted to account for
+    /// these.
+    pub(super) fn coff(&self) -> Vec<u8> {
+        const HEADER_SIZE: u32 = 20;
+        const SECTION_SIZE: u32 = 40;
+        const SYMBOL_SIZE: u32 = 18;
+        let num_sections = self.sections.len() as u32;
+        let data_offset = HEADER_SIZE + SECTION_SIZE * num_sections;
+        let mut offset = data_offset;
+        let mut data_offsets = Vec::new();
+        for section in &self.sections {
+            data_offsets.push(offset);
+            offset += section.size;
+        }
+        let mut reloc_offsets = Vec::new();
+        for section in &self.sections {
+            reloc_offsets.push(offset);
+            offset += 10 * section.relocations.len() as u32;
+        }
+        let symbols_offset = offset;
+        // Each section symbol is followed by an auxiliary record.
+        let first_symbol_index = 2 * num_sections;
+        let num_symbols = first_symbol_index + self.symbols.len() as u32;
+        // COFF string tables start with their size.
+        let (mut strings, string_offsets) = string_table(&self.symbols, 4);
+        let strings_size = strings.len() as u32;
+        strings[..4].copy_from_slice(&strings_size.to_le_bytes());
+
+        let mut out = Vec::new();
+        // IMAGE_FILE_HEADER with machine IMAGE_FILE_MACHINE_AMD64.
+        push_u16(&mut out, 0x8664);
+        push_u16(&mut out, num_sections as u16);
+        for value in [0, symbols_offset, num_symbols] {
+            push_u32(&mut out, value);
+        }
+        push_u16(&mut out, 0);
+        push_u16(&mut out, 0);
+        for (index, section) in self.sections.iter().enumerate() {
+            push_name(&mut out, section.name, 8);
+            for value in [
+                0,
+                0,
+                section.size,
+                data_offsets[index],
+                reloc_offsets[index],
+                0,
+            ] {
+                push_u32(&mut out, value);
+            }
+            push_u16(&mut out, section.relocations.len() as u16);
+            push_u16(&mut out, 0);
+            // IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ
+            push_u32(&mut out, 0x6000_0020);
+        }
+        out.resize(reloc_offsets.first().copied().unwrap_or(offset) as usize, 0);
+        for section in &self.sections {
+            for relocation in &section.relocations {
+                let RelocationTarget::Symbol(index) = relocation.target else {
+                    panic!("COFF relocations must be against symbols");
+                };
+                push_u32(&mut out, relocation.offset);
+                push_u32(&mut out, first_symbol_index + index as u32);
+                // IMAGE_REL_AMD64_REL32
+                push_u16(&mut out, 4);
+            }
+        }
+        for (index, section) in self.sections.iter().enumerate() {
+            push_name(&mut out, section.name, 8);
+            push_u32(&mut out, 0);
+            push_u16(&mut out, index as u16 + 1);
+            push_u16(&mut out, 0);
+            // IMAGE_SYM_CLASS_STATIC with one auxiliary record.
+            out.push(3);
+            out.push(1);
+            push_u32(&mut out, section.size);
+            push_u16(&mut out, section.relocations.len() as u16);
+            out.resize(out.len() + SYMBOL_SIZE as usize - 6, 0);
+        }
+        for (symbol, string_offset) in self.symbols.iter().zip(string_offsets) {
+            push_u32(&mut out, 0);
+            push_u32(&mut out, string_offset);
+            let (section_number, value) = symbol
+                .definition
+                .map_or((0, 0), |(section, offset)| (section as u16 + 1, offset));
+            push_u32(&mut out, value);
+            push_u16(&mut out, section_number);
+            // A function.
+            push_u16(&mut out, 0x20);
+            // IMAGE_SYM_CLASS_EXTERNAL with no auxiliary records.
+            out.push(2);
+            out.push(0);
+        }
+        out.extend_from_slice(&strings);
+        out
+    }
+}
+
+/// Returns a string table containing the names of `symbols` after `prefix_len` zero bytes and the
+/// offset of each name within it.
+fn string_table(symbols: &[TestSymbol], prefix_len: usize) -> (Vec<u8>, Vec<u32>) {
+    let mut strings = vec![0; prefix_len];
+    let mut offsets = Vec::new();
+    for symbol in symbols {
+        offsets.push(strings.len() as u32);
+        strings.extend_from_slice(symbol.name.as_bytes());
+        strings.push(0);
+    }
+    (strings, offsets)
+}
+
+fn push_name(out: &mut Vec<u8>, name: &str, len: usize) {
+    assert!(name.len() <= len);
+    out.extend_from_slice(name.as_bytes());
+    out.resize(out.len() + len - name.len(), 0);
+}
+
+fn push_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
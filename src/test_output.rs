@@ -0,0 +1,156 @@
+//! Parsing of the output of test binaries that use the standard test harness (libtest). This lets us
+//! attribute failures to individual tests rather than to the test binary as a whole.
+
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt::Display;
+
+/// A test that failed, together with the output that libtest captured while it ran.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Hash)]
+pub(crate) struct FailedTest {
+    pub(crate) name: String,
+    pub(crate) output: String,
+}
+
+/// A kind of access that a failed test's output suggests was denied by the sandbox.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SandboxDenial {
+    Network,
+    Filesystem,
+}
+
+/// Messages that indicate that network access was blocked. With networking unshared, there are no
+/// interfaces other than loopback, so connections and DNS lookups fail with these.
+const NETWORK_DENIALS: &[&str] = &[
+    "Network is unreachable",
+    "failed to lookup address information",
+    "Temporary failure in name resolution",
+    "dns error",
+];
+
+/// Messages that indicate that filesystem access was blocked.
+const FILESYSTEM_DENIALS: &[&str] = &["Read-only file system", "Permission denied"];
+
+/// Returns the tests that failed according to `stdout`, which should be the output of a test binary.
+/// Returns an empty list if `stdout` doesn't look like libtest output.
+pub(crate) fn parse_failures(stdout: &str) -> Vec<FailedTest> {
+    let mut failures: Vec<FailedTest> = stdout
+        .lines()
+        .filter_map(|line| {
+            let name = line.strip_prefix("test ")?.strip_suffix(" ... FAILED")?;
+            Some(FailedTest {
+                name: name.to_owned(),
+                output: String::new(),
+            })
+        })
+        .collect();
+
+    // After all tests have run, libtest prints the captured output of each failed test under a
+    // header of the form "---- {name} stdout ----". These sections are followed by a list of the
+    // failed tests, which is also headed by "failures:".
+    let mut current: Option<&mut FailedTest> = None;
+    for line in stdout.lines() {
+        if let Some(name) = line
+            .strip_prefix("---- ")
+            .and_then(|rest| rest.strip_suffix(" stdout ----"))
+        {
+            current = failures.iter_mut().find(|f| f.name == name);
+            continue;
+        }
+        if line == "failures:" {
+            current = None;
+            continue;
+        }
+        if let Some(failure) = current.as_mut() {
+            failure.output.push_str(line);
+            failure.output.push('\n');
+        }
+    }
+    for failure in &mut failures {
+        let trimmed_len = failure.output.trim_end().len();
+        failure.output.truncate(trimmed_len);
+    }
+    failures
+}
+
+impl FailedTest {
+    /// Returns what kind of access, if any, the output of this test suggests was denied by the
+    /// sandbox. This is a heuristic, since the test might have failed for an unrelated reason that
+    /// happens to produce a similar message.
+    pub(crate) fn sandbox_denial(&self) -> Option<SandboxDenial> {
+        if NETWORK_DENIALS.iter().any(|m| self.output.contains(m)) {
+            Some(SandboxDenial::Network)
+        } else if FILESYSTEM_DENIALS.iter().any(|m| self.output.contains(m)) {
+            Some(SandboxDenial::Filesystem)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for SandboxDenial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SandboxDenial::Network => write!(f, "network access"),
+            SandboxDenial::Filesystem => write!(f, "filesystem access"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn parse_libtest_output() {
+        let stdout = indoc! {r#"
+
+            running 3 tests
+            test tests::passes ... ok
+            test tests::downloads ... FAILED
+            test tests::writes ... FAILED
+
+            failures:
+
+            ---- tests::downloads stdout ----
+            thread 'tests::downloads' panicked at src/lib.rs:10:5:
+            called `Result::unwrap()` on an `Err` value: Os { code: 101, kind: NetworkUnreachable, message: "Network is unreachable" }
+            note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+            ---- tests::writes stdout ----
+            thread 'tests::writes' panicked at src/lib.rs:15:5:
+            Read-only file system (os error 30)
+
+
+            failures:
+                tests::downloads
+                tests::writes
+
+            test result: FAILED. 1 passed; 2 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+        "#};
+        let failures = parse_failures(stdout);
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].name, "tests::downloads");
+        assert!(failures[0]
+            .output
+            .starts_with("thread 'tests::downloads' panicked"));
+        assert!(failures[0].output.ends_with("to display a backtrace"));
+        assert_eq!(failures[0].sandbox_denial(), Some(SandboxDenial::Network));
+        assert_eq!(failures[1].name, "tests::writes");
+        assert_eq!(
+            failures[1].output,
+            "thread 'tests::writes' panicked at src/lib.rs:15:5:\nRead-only file system (os error 30)"
+        );
+        assert_eq!(
+            failures[1].sandbox_denial(),
+            Some(SandboxDenial::Filesystem)
+        );
+    }
+
+    #[test]
+    fn not_libtest_output() {
+        assert!(parse_failures("Segmentation fault\n").is_empty());
+    }
+}
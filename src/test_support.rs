@@ -0,0 +1,179 @@
+//! Support for checking, from a crate's own tests, how cackle sees it when it's used as a
+//! dependency. This is mostly intended for authors of crates that publish `cackle/export.toml`,
+//! who want CI to check that a crate using theirs passes with their exported API definitions.
+//!
+//! Cargo is run with the test binary as the rustc wrapper and linker, so tests that use this must
+//! be in a test target with `harness = false`, whose `main` calls
+//! [`handle_proxy_invocation`](crate::handle_proxy_invocation) before doing anything else. e.g. in
+//! `Cargo.toml`:
+//!
+//! ```toml
+//! [[test]]
+//! name = "cackle"
+//! harness = false
+//! ```
+//!
+//! Then in `tests/cackle.rs`:
+//!
+//! ```no_run
+//! fn main() -> anyhow::Result<()> {
+//!     cackle::handle_proxy_invocation()?;
+//!     cackle::test_support::TestWorkspace::new("consumer")
+//!         .dependency("my-crate", env!("CARGO_MANIFEST_DIR"))
+//!         .main_source("fn main() { my_crate::read_config(); }")
+//!         .config(
+//!             r#"
+//!             [common]
+//!             version = 2
+//!
+//!             [pkg.my-crate]
+//!             import = ["fs"]
+//!             allow_apis = ["fs"]
+//!             "#,
+//!         )
+//!         .assert_passes();
+//!     Ok(())
+//! }
+//! ```
+
+use crate::AnalysisReport;
+use anyhow::Context;
+use anyhow::Result;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A binary crate, created in a temporary directory, that depends on the crates being tested and
+/// is analysed with a supplied config.
+#[derive(Debug, Clone)]
+pub struct TestWorkspace {
+    name: String,
+    dependencies: Vec<(String, PathBuf)>,
+    main_source: String,
+    config: String,
+}
+
+impl TestWorkspace {
+    /// Creates a workspace containing a single binary package called `name`. This is the name by
+    /// which the config refers to the package, e.g. `[pkg.name]`.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            dependencies: Vec::new(),
+            main_source: "fn main() {}".to_owned(),
+            config: "[common]\nversion = 2\n".to_owned(),
+        }
+    }
+
+    /// Adds a dependency on the package called `name` in the directory `path`.
+    pub fn dependency(mut self, name: &str, path: impl Into<PathBuf>) -> Self {
+        self.dependencies.push((name.to_owned(), path.into()));
+        self
+    }
+
+    /// Sets the contents of the package's `src/main.rs`.
+    pub fn main_source(mut self, source: &str) -> Self {
+        self.main_source = source.to_owned();
+        self
+    }
+
+    /// Sets the full contents of `cackle.toml`.
+    pub fn config(mut self, config: &str) -> Self {
+        self.config = config.to_owned();
+        self
+    }
+
+    /// Creates the workspace in a temporary directory and analyses it.
+    pub fn analyze(&self) -> Result<AnalysisReport> {
+        let dir = tempfile::tempdir()?;
+        self.write_to(dir.path())?;
+        crate::analyze(dir.path(), &dir.path().join("cackle.toml"))
+    }
+
+    /// Analyses the workspace, then panics unless it passed. The panic message lists the problems
+    /// that were found.
+    pub fn assert_passes(&self) {
+        let report = match self.analyze() {
+            Ok(report) => report,
+            Err(error) => panic!("Analysis of `{}` failed: {error:#}", self.name),
+        };
+        if !report.success {
+            let problems: Vec<String> = report
+                .problems
+                .iter()
+                .map(|problem| format!("{}\n{}", problem.message, problem.details))
+                .collect();
+            panic!(
+                "`{}` didn't pass cackle. Problems:\n{}",
+                self.name,
+                problems.join("\n")
+            );
+        }
+    }
+
+    fn write_to(&self, dir: &Path) -> Result<()> {
+        let mut package = toml::Table::new();
+        package.insert("name".to_owned(), self.name.clone().into());
+        package.insert("version".to_owned(), "0.1.0".into());
+        package.insert("edition".to_owned(), "2021".into());
+        let mut dependencies = toml::Table::new();
+        for (name, path) in &self.dependencies {
+            let path = std::fs::canonicalize(path)
+                .with_context(|| format!("Failed to find dependency `{}`", path.display()))?;
+            let mut dependency = toml::Table::new();
+            dependency.insert(
+                "path".to_owned(),
+                path.to_str()
+                    .with_context(|| format!("Non-UTF-8 path `{}`", path.display()))?
+                    .into(),
+            );
+            dependencies.insert(name.clone(), dependency.into());
+        }
+        let mut manifest = toml::Table::new();
+        manifest.insert("package".to_owned(), package.into());
+        manifest.insert("dependencies".to_owned(), dependencies.into());
+        // An empty workspace table stops cargo from looking for a workspace in parent directories.
+        manifest.insert("workspace".to_owned(), toml::Table::new().into());
+
+        std::fs::create_dir_all(dir.join("src"))?;
+        crate::fs::write(dir.join("Cargo.toml"), toml::to_string(&manifest)?)?;
+        crate::fs::write(dir.join("src").join("main.rs"), &self.main_source)?;
+        crate::fs::write(dir.join("cackle.toml"), &self.config)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let dep_dir = dir.path().join("dep");
+        std::fs::create_dir(&dep_dir).unwrap();
+        let workspace_dir = dir.path().join("workspace");
+        TestWorkspace::new("consumer")
+            .dependency("my-crate", &dep_dir)
+            .main_source("fn main() { my_crate::foo(); }")
+            .write_to(&workspace_dir)
+            .unwrap();
+
+        let manifest: toml::Table =
+            toml::from_str(&std::fs::read_to_string(workspace_dir.join("Cargo.toml")).unwrap())
+                .unwrap();
+        assert_eq!(manifest["package"]["name"].as_str(), Some("consumer"));
+        assert_eq!(
+            manifest["dependencies"]["my-crate"]["path"].as_str(),
+            dep_dir.canonicalize().unwrap().to_str()
+        );
+        assert!(manifest["workspace"].is_table());
+        assert_eq!(
+            std::fs::read_to_string(workspace_dir.join("src/main.rs")).unwrap(),
+            "fn main() { my_crate::foo(); }"
+        );
+        assert_eq!(
+            std::fs::read_to_string(workspace_dir.join("cackle.toml")).unwrap(),
+            "[common]\nversion = 2\n"
+        );
+    }
+}
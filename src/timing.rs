@@ -1,10 +1,20 @@
 use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::time::Duration;
 use std::time::Instant;
+use std::time::SystemTime;
 
 use fxhash::FxHashMap;
 
+/// Returns the number of seconds since the Unix epoch. Used for timestamps that we write to disk.
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
 /// Records how long different parts of execution take.
 #[derive(Default)]
 pub(crate) struct TimingCollector {
@@ -45,6 +55,14 @@ impl TimingCollector {
         }
         now
     }
+
+    /// Returns the total seconds recorded for each timing category.
+    pub(crate) fn seconds(&self) -> BTreeMap<&'static str, f64> {
+        self.timings
+            .iter()
+            .map(|(key, duration)| (*key, duration.as_secs_f64()))
+            .collect()
+    }
 }
 
 impl Display for TimingCollector {
@@ -0,0 +1,290 @@
+//! Renders the dependency tree, like `cargo tree`, annotating each package with the APIs that it's
+//! allowed to use and those that it was observed to use on the last complete check. This makes it
+//! possible to see at a glance where a capability such as `process` enters the graph.
+
+use crate::config::Config;
+use crate::config::PackageName;
+use crate::crate_index::CrateIndex;
+use crate::crate_index::DepKind;
+use crate::crate_index::PackageId;
+use anyhow::Result;
+use clap::Parser;
+use fxhash::FxHashMap;
+use fxhash::FxHashSet;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::path::Path;
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct TreeOptions {
+    /// Only show packages that are, or that depend on, packages that are allowed or were observed
+    /// to use this API. May be repeated, in which case any of the APIs will do.
+    #[clap(long)]
+    api: Vec<String>,
+}
+
+/// The APIs that we know a package to be associated with.
+#[derive(Default, Debug)]
+struct PackageApis {
+    /// APIs that the config allows the package to use in any scope, after inheritance.
+    allowed: BTreeSet<String>,
+    /// APIs that the package was observed to use on the last complete check.
+    observed: BTreeSet<String>,
+}
+
+struct Tree<'a> {
+    dependencies: &'a FxHashMap<PackageId, Vec<(PackageId, DepKind)>>,
+    apis: FxHashMap<PackageId, PackageApis>,
+    /// If non-empty, only subtrees containing packages associated with one of these APIs are shown.
+    filter: &'a [String],
+}
+
+/// Prints the dependency tree of the workspace members, or of the root package if there is one.
+pub(crate) fn print(
+    crate_index: &CrateIndex,
+    config: &Config,
+    target_dir: &Path,
+    options: &TreeOptions,
+) -> Result<()> {
+    let observed = crate::provenance::observed_apis(target_dir)?;
+    let mut allowed: FxHashMap<&PackageName, BTreeSet<String>> = FxHashMap::default();
+    for (perm_sel, pkg_config) in &config.permissions.packages {
+        allowed
+            .entry(&perm_sel.package_name)
+            .or_default()
+            .extend(pkg_config.allow_apis.iter().map(|api| api.to_string()));
+    }
+    let apis = crate_index
+        .package_ids()
+        .map(|pkg_id| {
+            let name = PackageName(pkg_id.pkg_name());
+            let package_apis = PackageApis {
+                allowed: allowed.get(&name).cloned().unwrap_or_default(),
+                observed: observed.get(&name).cloned().unwrap_or_default(),
+            };
+            (pkg_id.clone(), package_apis)
+        })
+        .collect();
+    let mut roots: Vec<PackageId> = match &crate_index.root_pkg_id {
+        Some(root) => vec![root.clone()],
+        None => crate_index
+            .package_infos
+            .iter()
+            .filter(|(_, info)| info.is_workspace_member)
+            .map(|(pkg_id, _)| pkg_id.clone())
+            .collect(),
+    };
+    roots.sort_by(|a, b| (a.name_str(), a.version()).cmp(&(b.name_str(), b.version())));
+    let tree = Tree {
+        dependencies: &crate_index.dependencies,
+        apis,
+        filter: &options.api,
+    };
+    let output = tree.render(&roots);
+    if output.is_empty() && !options.api.is_empty() {
+        println!(
+            "No packages are allowed, or were observed, to use {}",
+            options.api.join(" or ")
+        );
+    }
+    print!("{output}");
+    Ok(())
+}
+
+impl Tree<'_> {
+    fn render(&self, roots: &[PackageId]) -> String {
+        let included = self.included_packages();
+        let mut out = String::new();
+        let mut expanded = FxHashSet::default();
+        for root in roots {
+            if !is_included(&included, root) {
+                continue;
+            }
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            writeln!(out, "{}", self.label(root, DepKind::Normal)).unwrap();
+            expanded.insert(root.clone());
+            self.render_children(root, "", &included, &mut expanded, &mut out);
+        }
+        out
+    }
+
+    fn render_children(
+        &self,
+        pkg_id: &PackageId,
+        prefix: &str,
+        included: &Option<FxHashSet<PackageId>>,
+        expanded: &mut FxHashSet<PackageId>,
+        out: &mut String,
+    ) {
+        let children = self.visible_children(pkg_id, included);
+        for (index, (child, kind)) in children.iter().enumerate() {
+            let is_last = index + 1 == children.len();
+            let connector = if is_last { "└── " } else { "├── " };
+            write!(out, "{prefix}{connector}{}", self.label(child, *kind)).unwrap();
+            // Like `cargo tree`, we only expand each package once and mark later occurrences.
+            if !expanded.insert(child.clone()) {
+                if !self.visible_children(child, included).is_empty() {
+                    out.push_str(" (*)");
+                }
+                out.push('\n');
+                continue;
+            }
+            out.push('\n');
+            let child_prefix = format!("{prefix}{}", if is_last { "    " } else { "│   " });
+            self.render_children(child, &child_prefix, included, expanded, out);
+        }
+    }
+
+    fn visible_children<'b>(
+        &'b self,
+        pkg_id: &PackageId,
+        included: &Option<FxHashSet<PackageId>>,
+    ) -> Vec<&'b (PackageId, DepKind)> {
+        self.dependencies
+            .get(pkg_id)
+            .into_iter()
+            .flatten()
+            .filter(|(dep, _)| is_included(included, dep))
+            .collect()
+    }
+
+    fn label(&self, pkg_id: &PackageId, kind: DepKind) -> String {
+        let mut label = format!("{} v{}", pkg_id.name_str(), pkg_id.version());
+        if kind != DepKind::Normal {
+            write!(label, " ({kind})").unwrap();
+        }
+        if let Some(apis) = self.apis.get(pkg_id) {
+            for (heading, apis) in [("allowed", &apis.allowed), ("observed", &apis.observed)] {
+                if !apis.is_empty() {
+                    let apis: Vec<&str> = apis.iter().map(String::as_str).collect();
+                    write!(label, " [{heading}: {}]", apis.join(", ")).unwrap();
+                }
+            }
+        }
+        label
+    }
+
+    /// Returns the packages that are associated with one of the APIs in our filter, together with
+    /// everything that depends on them. Returns None if there's no filter.
+    fn included_packages(&self) -> Option<FxHashSet<PackageId>> {
+        if self.filter.is_empty() {
+            return None;
+        }
+        let mut dependents: FxHashMap<&PackageId, Vec<&PackageId>> = FxHashMap::default();
+        for (pkg_id, deps) in self.dependencies {
+            for (dep, _) in deps {
+                dependents.entry(dep).or_default().push(pkg_id);
+            }
+        }
+        let mut pending: Vec<&PackageId> = self
+            .apis
+            .iter()
+            .filter(|(_, apis)| {
+                self.filter
+                    .iter()
+                    .any(|api| apis.allowed.contains(api) || apis.observed.contains(api))
+            })
+            .map(|(pkg_id, _)| pkg_id)
+            .collect();
+        let mut included = FxHashSet::default();
+        while let Some(pkg_id) = pending.pop() {
+            if included.insert(pkg_id.clone()) {
+                pending.extend(dependents.get(pkg_id).into_iter().flatten());
+            }
+        }
+        Some(included)
+    }
+}
+
+/// Returns whether `pkg_id` should be shown, given the result of `Tree::included_packages`.
+fn is_included(included: &Option<FxHashSet<PackageId>>, pkg_id: &PackageId) -> bool {
+    match included {
+        Some(included) => included.contains(pkg_id),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crate_index::testing::pkg_id;
+    use indoc::indoc;
+
+    fn tree<'a>(
+        dependencies: &'a FxHashMap<PackageId, Vec<(PackageId, DepKind)>>,
+        filter: &'a [String],
+    ) -> Tree<'a> {
+        let mut apis: FxHashMap<PackageId, PackageApis> = FxHashMap::default();
+        apis.entry(pkg_id("d")).or_default().allowed = ["process".to_owned()].into();
+        apis.entry(pkg_id("b")).or_default().allowed = ["fs".to_owned(), "net".to_owned()].into();
+        apis.entry(pkg_id("b")).or_default().observed = ["net".to_owned()].into();
+        Tree {
+            dependencies,
+            apis,
+            filter,
+        }
+    }
+
+    fn dependencies() -> FxHashMap<PackageId, Vec<(PackageId, DepKind)>> {
+        [
+            (
+                pkg_id("a"),
+                vec![
+                    (pkg_id("b"), DepKind::Normal),
+                    (pkg_id("c"), DepKind::Build),
+                    (pkg_id("e"), DepKind::Dev),
+                ],
+            ),
+            (pkg_id("b"), vec![(pkg_id("d"), DepKind::Normal)]),
+            (pkg_id("c"), vec![(pkg_id("d"), DepKind::Normal)]),
+            (pkg_id("d"), vec![(pkg_id("f"), DepKind::Normal)]),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn render_all() {
+        let dependencies = dependencies();
+        assert_eq!(
+            tree(&dependencies, &[]).render(&[pkg_id("a")]),
+            indoc! {"
+                a v0.0.0
+                ├── b v0.0.0 [allowed: fs, net] [observed: net]
+                │   └── d v0.0.0 [allowed: process]
+                │       └── f v0.0.0
+                ├── c v0.0.0 (build)
+                │   └── d v0.0.0 [allowed: process] (*)
+                └── e v0.0.0 (dev)
+            "}
+        );
+    }
+
+    #[test]
+    fn render_filtered() {
+        let dependencies = dependencies();
+        assert_eq!(
+            tree(&dependencies, &["process".to_owned()]).render(&[pkg_id("a")]),
+            indoc! {"
+                a v0.0.0
+                ├── b v0.0.0 [allowed: fs, net] [observed: net]
+                │   └── d v0.0.0 [allowed: process]
+                └── c v0.0.0 (build)
+                    └── d v0.0.0 [allowed: process]
+            "}
+        );
+        assert_eq!(
+            tree(&dependencies, &["net".to_owned()]).render(&[pkg_id("a")]),
+            indoc! {"
+                a v0.0.0
+                └── b v0.0.0 [allowed: fs, net] [observed: net]
+            "}
+        );
+        assert_eq!(
+            tree(&dependencies, &["env".to_owned()]).render(&[pkg_id("a")]),
+            ""
+        );
+    }
+}
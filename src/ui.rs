@@ -1,15 +1,19 @@
 //! User interface for showing problems to the user and asking them what they'd like to do about
 //! them.
 
+use crate::analysis::ReportSink;
 use crate::checker::Checker;
+use crate::config_file::ConfigFile;
 use crate::crate_index::CrateIndex;
+use crate::crate_index::DepKind;
+use crate::crate_index::PackageId;
 use crate::events::AppEvent;
+use crate::problem::Problem;
 use crate::problem_store::ProblemStoreRef;
 use crate::Args;
 use anyhow::Result;
 use clap::ValueEnum;
 use log::info;
-use std::path::Path;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
@@ -19,6 +23,8 @@ use std::thread::JoinHandle;
 #[cfg(feature = "ui")]
 mod basic_term;
 #[cfg(feature = "ui")]
+mod config_diff;
+#[cfg(feature = "ui")]
 mod full_term;
 mod null_ui;
 
@@ -42,34 +48,38 @@ trait UserInterface: Send {
 
 pub(crate) fn start_ui(
     args: &Arc<Args>,
-    config_path: &Path,
+    config_file: &ConfigFile,
     checker: &Arc<Mutex<Checker>>,
     problem_store: ProblemStoreRef,
-    crate_index: Arc<CrateIndex>,
     event_receiver: Receiver<AppEvent>,
     abort_sender: Sender<()>,
+    report: Option<ReportSink>,
 ) -> Result<JoinHandle<Result<()>>> {
     let mut ui: Box<dyn UserInterface> = match args.ui_kind() {
         Kind::None => {
             info!("Starting null UI");
-            Box::new(null_ui::NullUi::new(args, abort_sender))
+            Box::new(null_ui::NullUi::new(args, checker, abort_sender, report))
         }
         #[cfg(feature = "ui")]
         Kind::Basic => {
             info!("Starting basic terminal UI");
             Box::new(basic_term::BasicTermUi::new(
-                config_path.to_owned(),
+                config_file.clone(),
                 checker,
+                abort_sender,
+                args.dry_run,
             ))
         }
         #[cfg(feature = "ui")]
         Kind::Full => {
             info!("Starting full terminal UI");
+            let crate_index = checker.lock().unwrap().crate_index.clone();
             Box::new(full_term::FullTermUi::new(
-                config_path.to_owned(),
+                config_file.clone(),
                 checker,
                 crate_index,
                 abort_sender,
+                args.theme,
             )?)
         }
     };
@@ -79,7 +89,10 @@ pub(crate) fn start_ui(
 }
 
 impl Args {
-    pub(crate) fn should_capture_cargo_output(&self) -> bool {
+    /// Returns whether we'll run a user interface, through which the user can select fixes for
+    /// problems. If we will, then cargo's output needs to be captured so that it doesn't mess up
+    /// the UI.
+    pub(crate) fn is_interactive(&self) -> bool {
         !matches!(self.ui_kind(), Kind::None)
     }
 
@@ -91,9 +104,80 @@ impl Args {
             return kind;
         }
         #[cfg(feature = "ui")]
+        if self.dry_run {
+            return Kind::Basic;
+        }
+        #[cfg(feature = "ui")]
         if std::io::IsTerminal::is_terminal(&std::io::stdout()) {
             return Kind::Full;
         }
         Kind::None
     }
 }
+
+/// Returns the notes that we add to `problem` when reporting it without a user interface.
+pub(crate) fn problem_notes(crate_index: &CrateIndex, problem: &Problem) -> Vec<String> {
+    [
+        dependency_path_line(crate_index, problem),
+        workspaces_line(crate_index, problem),
+        dep_kind_line(crate_index, problem),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Returns a line describing how the package that `problem` relates to ended up in the dependency
+/// tree. Returns None if the package is a workspace member or isn't reachable from one.
+fn dependency_path_line(crate_index: &CrateIndex, problem: &Problem) -> Option<String> {
+    let path = crate_index.dependency_path(problem.pkg_id()?)?;
+    if path.len() < 2 {
+        return None;
+    }
+    let path: Vec<String> = path.iter().map(|pkg_id| pkg_id.to_string()).collect();
+    Some(format!("Dependency path: {}", path.join(" -> ")))
+}
+
+/// Returns a line listing the workspaces whose dependency trees contain the package that `problem`
+/// relates to. Returns None unless we're analysing multiple workspaces.
+fn workspaces_line(crate_index: &CrateIndex, problem: &Problem) -> Option<String> {
+    package_workspaces_line(crate_index, problem.pkg_id()?)
+}
+
+/// Like `workspaces_line`, but for a package rather than a problem.
+fn package_workspaces_line(crate_index: &CrateIndex, pkg_id: &PackageId) -> Option<String> {
+    if crate_index.workspace_roots.len() < 2 {
+        return None;
+    }
+    let info = crate_index.package_info(pkg_id)?;
+    let roots: Vec<String> = info
+        .workspaces
+        .iter()
+        .map(|root| root.display().to_string())
+        .collect();
+    Some(format!("Workspaces: {}", roots.join(", ")))
+}
+
+/// Prints problems that the config downgraded to warnings, in the same format as when running
+/// without a user interface.
+#[cfg(feature = "ui")]
+fn print_downgraded(problems: &[Problem]) {
+    for problem in problems {
+        println!(
+            "{}",
+            crate::diagnostic::Diagnostic::new(problem, crate::problem::Severity::Warning)
+        );
+    }
+}
+
+/// Returns a line noting that the package that `problem` relates to is only used at build time or
+/// only by tests. Returns None for normal dependencies.
+fn dep_kind_line(crate_index: &CrateIndex, problem: &Problem) -> Option<String> {
+    match crate_index.dep_kind(problem.pkg_id()?) {
+        DepKind::Normal => None,
+        DepKind::Build => Some("Dependency kind: build (only used at build time)".to_owned()),
+        DepKind::Dev => {
+            Some("Dependency kind: dev (only used via dev-dependencies, e.g. by tests)".to_owned())
+        }
+    }
+}
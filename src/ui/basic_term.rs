@@ -3,39 +3,47 @@
 
 use crate::checker::Checker;
 use crate::config;
+use crate::config::approvals;
+use crate::config::approvals::Approval;
 use crate::config::ApiName;
 use crate::config::SandboxKind;
 use crate::config::MAX_VERSION;
 use crate::config_editor;
 use crate::config_editor::ConfigEditor;
 use crate::config_editor::Edit;
+use crate::config_editor::EditOpts;
+use crate::config_file;
+use crate::config_file::ConfigFile;
+use crate::config_file::ConflictPolicy;
 use crate::events::AppEvent;
 use crate::outcome::Outcome;
 use crate::problem::Problem;
 use crate::problem_store::ProblemStoreRef;
 use crate::sandbox;
+use crate::ui::config_diff;
+use crate::ui::config_diff::DiffLine;
 use anyhow::bail;
-use anyhow::Context;
 use anyhow::Result;
 use colored::Colorize;
 use indoc::indoc;
-use std::collections::VecDeque;
-use std::io::BufRead;
 use std::io::Write;
 use std::path::Path;
-use std::path::PathBuf;
 use std::sync::mpsc;
 use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
 use std::time::SystemTime;
 
 pub(crate) struct BasicTermUi {
-    config_path: PathBuf,
-    stdin_recv: Receiver<String>,
+    config_file: ConfigFile,
+    stdin: StdinReader,
     config_last_modified: Option<SystemTime>,
     checker: Arc<Mutex<Checker>>,
+    abort_sender: Sender<()>,
+    /// Whether to just print the fixes for each problem, then exit.
+    dry_run: bool,
 }
 
 impl super::UserInterface for BasicTermUi {
@@ -48,9 +56,24 @@ impl super::UserInterface for BasicTermUi {
             match event {
                 AppEvent::Shutdown => return Ok(()),
                 AppEvent::ProblemsAdded => {}
+                AppEvent::ReleaseTerminal => {
+                    // We only read from stdin while prompting, so there's nothing to release.
+                    problem_store.lock().terminal_released();
+                    continue;
+                }
+                AppEvent::TerminalAvailable => continue,
+            }
+            if self.dry_run {
+                self.print_all_fixes(&problem_store)?;
+                continue;
             }
             loop {
-                let pstore_lock = problem_store.lock();
+                let (config, crate_index) = {
+                    let checker = self.checker.lock().unwrap();
+                    (checker.config.clone(), checker.crate_index.clone())
+                };
+                let mut pstore_lock = problem_store.lock();
+                super::print_downgraded(&pstore_lock.resolve_downgraded(&config.permissions));
                 let Some((problem_index, problem)) = pstore_lock.deduplicated_into_iter().next()
                 else {
                     break;
@@ -67,8 +90,17 @@ impl super::UserInterface for BasicTermUi {
                     continue;
                 }
                 println!("{problem}");
-                let config = self.checker.lock().unwrap().config.clone();
+                if let Some(line) = super::dependency_path_line(&crate_index, problem) {
+                    println!("{line}");
+                }
+                if let Some(line) = super::workspaces_line(&crate_index, problem) {
+                    println!("{line}");
+                }
+                if let Some(line) = super::dep_kind_line(&crate_index, problem) {
+                    println!("{line}");
+                }
                 let fixes = config_editor::fixes_for_problem(problem, &config);
+                let problem_description = problem.to_string();
                 // We don't want to hold the mutex for any significant time, so we drop it now
                 // that we're done with `problem`, which was the only thing borrowed from the
                 // store. We certainly don't want to hold the lock while we prompt for user
@@ -82,7 +114,7 @@ impl super::UserInterface for BasicTermUi {
                 } else {
                     println!("dN) Diff for fix N. e.g 'd1'");
                 }
-                match self.prompt_for_fix(&fixes)? {
+                match self.prompt_for_fix(&problem_description, &fixes)? {
                     Outcome::Continue => {
                         problem_store.lock().resolve(problem_index);
                     }
@@ -95,13 +127,60 @@ impl super::UserInterface for BasicTermUi {
 }
 
 impl BasicTermUi {
-    pub(crate) fn new(config_path: PathBuf, checker: &Arc<Mutex<Checker>>) -> Self {
+    pub(crate) fn new(
+        config_file: ConfigFile,
+        checker: &Arc<Mutex<Checker>>,
+        abort_sender: Sender<()>,
+        dry_run: bool,
+    ) -> Self {
         Self {
-            config_last_modified: config_modification_time(&config_path),
-            config_path,
-            stdin_recv: start_stdin_channel(),
+            config_last_modified: config_modification_time(config_file.path()),
+            config_file,
+            stdin: StdinReader::start(),
             checker: checker.clone(),
+            abort_sender,
+            dry_run,
+        }
+    }
+
+    /// Prints each problem together with the diff for each of its fixes, then aborts, since nothing
+    /// is going to resolve the problems.
+    fn print_all_fixes(&self, problem_store: &ProblemStoreRef) -> Result<()> {
+        let (config, crate_index) = {
+            let checker = self.checker.lock().unwrap();
+            (checker.config.clone(), checker.crate_index.clone())
+        };
+        let mut pstore = problem_store.lock();
+        super::print_downgraded(&pstore.resolve_downgraded(&config.permissions));
+        // Once we've aborted, we ignore problems from any processes that were still running.
+        if pstore.has_aborted || pstore.is_empty() {
+            return Ok(());
         }
+        let _ = self.abort_sender.send(());
+        for (_, problem) in pstore.deduplicated_into_iter() {
+            println!("{problem}");
+            if let Some(line) = super::dependency_path_line(&crate_index, problem) {
+                println!("{line}");
+            }
+            if let Some(line) = super::workspaces_line(&crate_index, problem) {
+                println!("{line}");
+            }
+            if let Some(line) = super::dep_kind_line(&crate_index, problem) {
+                println!("{line}");
+            }
+            let fixes = config_editor::fixes_for_problem(problem, &config);
+            if fixes.is_empty() {
+                println!("No automatic fixes available");
+            }
+            for (index, fix) in fixes.iter().enumerate() {
+                println!("{})  {}", index + 1, fix.title());
+                print_edit_diff(&self.config_file, fix.as_ref())?;
+            }
+            println!();
+        }
+        println!("{} wasn't modified", self.config_file.path().display());
+        pstore.abort();
+        Ok(())
     }
 
     fn create_initial_config(&mut self) -> Result<Outcome> {
@@ -128,7 +207,7 @@ impl BasicTermUi {
         while !done {
             done = true;
             print_prompt()?;
-            for part in self.stdin_recv.recv()?.trim().split(',') {
+            for part in self.stdin.recv()?.trim().split(',') {
                 let part = part.trim();
                 if part.is_empty() {
                     continue;
@@ -147,33 +226,56 @@ impl BasicTermUi {
         println!("=========================================");
         println!("Press enter to write config, or control-c to abort");
         print_prompt()?;
-        self.stdin_recv.recv()?;
-        crate::fs::write_atomic(&self.config_path, &initial_toml)?;
-        self.config_last_modified = config_modification_time(&self.config_path);
+        self.stdin.recv()?;
+        self.config_file.write(&initial_toml)?;
+        self.config_last_modified = config_modification_time(self.config_file.path());
         Ok(Outcome::Continue)
     }
 
-    fn prompt_for_fix(&mut self, fixes: &[Box<dyn Edit>]) -> Result<Outcome> {
+    fn prompt_for_fix(&mut self, problem: &str, fixes: &[Box<dyn Edit>]) -> Result<Outcome> {
         loop {
             match self.get_action(fixes.len()) {
                 Ok(Action::ApplyFix(n)) => {
-                    let mut editor = ConfigEditor::from_file(&self.config_path)?;
-                    fixes[n].apply(&mut editor, &Default::default())?;
-                    editor.write(&self.config_path)?;
-                    self.config_last_modified = config_modification_time(&self.config_path);
+                    print_edit_diff(&self.config_file, fixes[n].as_ref())?;
+                    if !self.confirm("Apply this fix? [Y/n]")? {
+                        continue;
+                    }
+                    let mut editor = self.config_file.editor()?;
+                    let approval = self.prompt_for_approval(&editor, fixes[n].as_ref())?;
+                    let opts = EditOpts {
+                        is_final: true,
+                        ..Default::default()
+                    };
+                    let written = loop {
+                        approvals::apply_edit(
+                            &mut editor,
+                            problem,
+                            fixes[n].as_ref(),
+                            &opts,
+                            approval.as_ref(),
+                        )?;
+                        match editor.write(&self.config_file) {
+                            Ok(()) => break true,
+                            Err(error) if config_file::is_conflict(&error) => {
+                                println!("{error}");
+                                if !self.reapply_after_conflict()? {
+                                    break false;
+                                }
+                                editor = self.config_file.editor()?;
+                            }
+                            Err(error) => return Err(error),
+                        }
+                    };
+                    if !written {
+                        println!("Fix not applied");
+                        continue;
+                    }
+                    self.config_last_modified = config_modification_time(self.config_file.path());
                     return Ok(Outcome::Continue);
                 }
                 Ok(Action::ShowDiff(n)) => {
-                    let mut editor = ConfigEditor::from_file(&self.config_path)?;
-                    let fix = &fixes[n];
-                    fix.apply(&mut editor, &Default::default())?;
-                    println!("Diff for {}:", fix.title());
-                    show_diff(
-                        &std::fs::read_to_string(&self.config_path).with_context(|| {
-                            format!("Failed to read `{}`", self.config_path.display())
-                        })?,
-                        &editor.to_toml(),
-                    );
+                    println!("Diff for {}:", fixes[n].title());
+                    print_edit_diff(&self.config_file, fixes[n].as_ref())?;
                 }
                 Ok(Action::GiveUp) => return Ok(Outcome::GiveUp),
                 Ok(Action::Retry) => return Ok(Outcome::Continue),
@@ -184,6 +286,79 @@ impl BasicTermUi {
         }
     }
 
+    /// Prints `question` and returns whether the user answered yes. An empty response counts as
+    /// yes.
+    fn confirm(&mut self, question: &str) -> Result<bool> {
+        loop {
+            println!("{question}");
+            print_prompt()?;
+            match self.stdin.recv()?.trim().to_lowercase().as_str() {
+                "" | "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns whether to reload the config and reapply a fix after the config was changed by
+    /// something else while we were applying it.
+    fn reapply_after_conflict(&mut self) -> Result<bool> {
+        match self.config_file.conflict_policy() {
+            ConflictPolicy::Prompt => self.confirm("Reload it and reapply the fix? [Y/n]"),
+            ConflictPolicy::Reapply => Ok(true),
+            ConflictPolicy::Abort => Ok(false),
+        }
+    }
+
+    /// If approvals are required and `fix` would grant permissions, asks who approved it.
+    fn prompt_for_approval(
+        &mut self,
+        editor: &ConfigEditor,
+        fix: &dyn Edit,
+    ) -> Result<Option<Approval>> {
+        let approvals_required = self
+            .checker
+            .lock()
+            .unwrap()
+            .config
+            .raw
+            .common
+            .required_approvals
+            .is_some();
+        if !approvals_required || !approvals::edit_adds_grants(editor, fix)? {
+            return Ok(None);
+        }
+        println!("This fix grants permissions, which need to be approved");
+        let mut approval = Approval::default();
+        loop {
+            println!("Approved by (comma-separated):");
+            print_prompt()?;
+            match approvals::parse_approvers(&self.stdin.recv()?) {
+                Ok(approved_by) => {
+                    approval.approved_by = approved_by;
+                    break;
+                }
+                Err(error) => println!("{error}"),
+            }
+        }
+        println!("Ticket (optional):");
+        print_prompt()?;
+        let ticket = self.stdin.recv()?;
+        approval.ticket = Some(ticket.trim().to_owned()).filter(|ticket| !ticket.is_empty());
+        loop {
+            println!("Expires (optional, e.g. 2025-06-30):");
+            print_prompt()?;
+            match approvals::parse_expiry(&self.stdin.recv()?) {
+                Ok(expires) => {
+                    approval.expires = expires;
+                    break;
+                }
+                Err(error) => println!("{error}"),
+            }
+        }
+        Ok(Some(approval))
+    }
+
     fn get_action(&mut self, num_fixes: usize) -> Result<Action> {
         print_prompt()?;
 
@@ -192,13 +367,13 @@ impl BasicTermUi {
         // don't need an instant response to a file change.
         let response;
         loop {
-            match self.stdin_recv.recv_timeout(Duration::from_millis(250)) {
+            match self.stdin.recv_timeout(Duration::from_millis(250)) {
                 Ok(line) => {
                     response = line.to_lowercase();
                     break;
                 }
                 Err(mpsc::RecvTimeoutError::Timeout) => {
-                    let modified = config_modification_time(&self.config_path);
+                    let modified = config_modification_time(self.config_file.path());
                     if self.config_last_modified != modified {
                         self.config_last_modified = modified;
                         println!("\nConfig file modified, retrying...");
@@ -216,20 +391,57 @@ impl BasicTermUi {
     }
 }
 
-fn start_stdin_channel() -> Receiver<String> {
-    let (tx, rx) = std::sync::mpsc::channel();
-    std::thread::spawn(move || {
-        let stdin = std::io::stdin().lock();
-        for line in stdin.lines() {
-            let Ok(line) = line else {
-                break;
-            };
-            if tx.send(line).is_err() {
-                break;
+/// Reads lines from stdin on a separate thread. Lines are only read when requested, so that when
+/// we're not prompting, stdin is free to be used by a binary that's attached to the terminal.
+struct StdinReader {
+    request_sender: mpsc::Sender<()>,
+    line_receiver: Receiver<String>,
+    /// Whether we've requested a line that we haven't yet received.
+    pending: bool,
+}
+
+impl StdinReader {
+    fn start() -> Self {
+        let (request_sender, request_receiver) = mpsc::channel();
+        let (line_sender, line_receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut lines = std::io::stdin().lines();
+            for () in request_receiver {
+                let Some(Ok(line)) = lines.next() else {
+                    break;
+                };
+                if line_sender.send(line).is_err() {
+                    break;
+                }
             }
+        });
+        Self {
+            request_sender,
+            line_receiver,
+            pending: false,
         }
-    });
-    rx
+    }
+
+    fn request(&mut self) {
+        if !self.pending {
+            let _ = self.request_sender.send(());
+            self.pending = true;
+        }
+    }
+
+    fn recv(&mut self) -> Result<String, mpsc::RecvError> {
+        self.request();
+        let line = self.line_receiver.recv()?;
+        self.pending = false;
+        Ok(line)
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<String, mpsc::RecvTimeoutError> {
+        self.request();
+        let line = self.line_receiver.recv_timeout(timeout)?;
+        self.pending = false;
+        Ok(line)
+    }
 }
 
 fn print_prompt() -> Result<(), anyhow::Error> {
@@ -257,39 +469,26 @@ fn config_modification_time(config_path: &Path) -> Option<SystemTime> {
     std::fs::metadata(config_path).ok()?.modified().ok()
 }
 
-fn show_diff(original: &str, updated: &str) {
-    fn print_common(common: &mut VecDeque<&str>) {
-        for line in common.drain(..) {
-            println!(" {line}");
-        }
+/// Prints the help for `edit` and the changes that it would make to `config_file`. This is the same
+/// information as the full terminal UI shows when an edit is selected.
+fn print_edit_diff(config_file: &ConfigFile, edit: &dyn Edit) -> Result<()> {
+    println!("{}", edit.help());
+    let original = config_file.read()?;
+    let mut editor = ConfigEditor::from_toml_string(&original)?;
+    if let Err(error) = edit.apply(&mut editor, &Default::default()) {
+        println!("{error}");
     }
-
-    const CONTEXT: usize = 2;
-    let mut common = VecDeque::new();
-    let mut after_context = 0;
-    for diff in diff::lines(original, updated) {
-        match diff {
-            diff::Result::Both(s, _) => {
-                if after_context > 0 {
-                    after_context -= 1;
-                    println!(" {s}");
-                } else {
-                    common.push_back(s);
-                    if common.len() > CONTEXT {
-                        common.pop_front();
-                    }
-                }
-            }
-            diff::Result::Left(s) => {
-                print_common(&mut common);
-                println!("{}{}", "-".red(), s.red());
-                after_context = CONTEXT;
-            }
-            diff::Result::Right(s) => {
-                print_common(&mut common);
-                println!("{}{}", "+".green(), s.green());
-                after_context = CONTEXT;
-            }
+    let updated = editor.to_toml();
+    let diff = config_diff::diff(&original, &updated);
+    if !diff.is_empty() {
+        println!("=== Diff of {} ===", config_file.path().display());
+    }
+    for line in diff {
+        match line {
+            DiffLine::Context(s) => println!(" {s}"),
+            DiffLine::Removed(s) => println!("{}{}", "-".red(), s.red()),
+            DiffLine::Added(s) => println!("{}{}", "+".green(), s.green()),
         }
     }
+    Ok(())
 }
@@ -0,0 +1,51 @@
+//! Diffing of config files, shared by the user interfaces.
+
+use std::collections::VecDeque;
+
+pub(super) enum DiffLine<'a> {
+    Context(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Returns the lines of a diff from `original` to `updated`. Shows common context from the start to
+/// the end of the current section.
+pub(super) fn diff<'a>(original: &'a str, updated: &'a str) -> Vec<DiffLine<'a>> {
+    fn is_section_start(line: &str) -> bool {
+        line.starts_with('[')
+    }
+
+    let mut lines = Vec::new();
+
+    let mut common = VecDeque::new();
+    let mut after_context = false;
+    for diff in diff::lines(original, updated) {
+        match diff {
+            diff::Result::Both(s, _) => {
+                if after_context {
+                    if is_section_start(s) {
+                        after_context = false;
+                    } else {
+                        lines.push(DiffLine::Context(s));
+                    }
+                } else {
+                    if is_section_start(s) {
+                        common.clear();
+                    }
+                    common.push_back(s);
+                }
+            }
+            diff::Result::Left(s) => {
+                lines.extend(common.drain(..).map(DiffLine::Context));
+                lines.push(DiffLine::Removed(s));
+                after_context = true;
+            }
+            diff::Result::Right(s) => {
+                lines.extend(common.drain(..).map(DiffLine::Context));
+                lines.push(DiffLine::Added(s));
+                after_context = true;
+            }
+        }
+    }
+    lines
+}
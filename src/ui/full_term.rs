@@ -1,20 +1,20 @@
 //! A fullscreen terminal user interface.
 
 use crate::checker::Checker;
+use crate::config::UiTheme;
+use crate::config_file::ConfigFile;
 use crate::crate_index::CrateIndex;
 use crate::events::AppEvent;
 use crate::problem_store::ProblemStoreRef;
 use anyhow::Result;
 use crossterm::event::Event;
 use crossterm::event::KeyCode;
+use crossterm::event::MouseEventKind;
 use ratatui::backend::CrosstermBackend;
 use ratatui::layout::Constraint;
 use ratatui::layout::Direction;
 use ratatui::layout::Layout;
 use ratatui::layout::Rect;
-use ratatui::style::Color;
-use ratatui::style::Modifier;
-use ratatui::style::Style;
 use ratatui::widgets::Block;
 use ratatui::widgets::BorderType;
 use ratatui::widgets::Borders;
@@ -26,35 +26,40 @@ use ratatui::widgets::Paragraph;
 use ratatui::widgets::Wrap;
 use ratatui::Frame;
 use std::io::Stdout;
-use std::path::PathBuf;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
 use std::sync::mpsc::TryRecvError;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
+use theme::Theme;
 
 mod problems_ui;
+mod theme;
 
 pub(crate) struct FullTermUi {
-    config_path: PathBuf,
+    config_file: ConfigFile,
     abort_sender: Sender<()>,
     crate_index: Arc<CrateIndex>,
     checker: Arc<Mutex<Checker>>,
+    /// The theme to use regardless of what the config says.
+    theme_override: Option<UiTheme>,
 }
 
 impl FullTermUi {
     pub(crate) fn new(
-        config_path: PathBuf,
+        config_file: ConfigFile,
         checker: &Arc<Mutex<Checker>>,
         crate_index: Arc<CrateIndex>,
         abort_sender: Sender<()>,
+        theme_override: Option<UiTheme>,
     ) -> Result<Self> {
         Ok(Self {
-            config_path,
+            config_file,
             abort_sender,
             crate_index,
             checker: checker.clone(),
+            theme_override,
         })
     }
 }
@@ -70,7 +75,11 @@ impl Terminal {
     fn new() -> Result<Terminal> {
         crossterm::terminal::enable_raw_mode()?;
         let mut stdout = std::io::stdout();
-        crossterm::execute!(stdout, crossterm::terminal::EnterAlternateScreen)?;
+        crossterm::execute!(
+            stdout,
+            crossterm::terminal::EnterAlternateScreen,
+            crossterm::event::EnableMouseCapture
+        )?;
         let backend = ratatui::backend::CrosstermBackend::new(stdout);
         let term = ratatui::Terminal::new(backend)?;
         let output_lock = std::io::stderr().lock();
@@ -91,13 +100,18 @@ impl super::UserInterface for FullTermUi {
             problem_store.clone(),
             self.crate_index.clone(),
             self.checker.clone(),
-            self.config_path.clone(),
+            self.config_file.clone(),
+            self.theme_override,
         );
         let mut needs_redraw = true;
         let mut error = None;
-        match event_receiver.recv() {
-            Ok(AppEvent::ProblemsAdded) => {}
-            Err(..) | Ok(AppEvent::Shutdown) => return Ok(()),
+        loop {
+            match event_receiver.recv() {
+                Ok(AppEvent::ProblemsAdded) => break,
+                Ok(AppEvent::ReleaseTerminal) => problem_store.lock().terminal_released(),
+                Ok(AppEvent::TerminalAvailable) => {}
+                Err(..) | Ok(AppEvent::Shutdown) => return Ok(()),
+            }
         }
         let mut terminal = Terminal::new()?;
         loop {
@@ -120,7 +134,7 @@ impl super::UserInterface for FullTermUi {
                 terminal.term.draw(|f| {
                     screen.render(f);
                     if let Some(e) = error.as_ref() {
-                        render_error(f, e);
+                        render_error(f, e, &screen.theme());
                     }
                 })?;
                 needs_redraw = false;
@@ -133,17 +147,58 @@ impl super::UserInterface for FullTermUi {
                     }
                 }
                 Ok(AppEvent::Shutdown) => {
+                    drop(terminal);
+                    screen.print_warnings();
                     return Ok(());
                 }
+                Ok(AppEvent::ReleaseTerminal) => {
+                    drop(terminal);
+                    problem_store.lock().terminal_released();
+                    // Wait until the binary is done with the terminal before we take it back.
+                    loop {
+                        match event_receiver.recv() {
+                            Ok(AppEvent::TerminalAvailable) => break,
+                            Ok(AppEvent::ProblemsAdded) => {
+                                if let Err(e) = screen.problems_added() {
+                                    error = Some(e);
+                                }
+                            }
+                            Ok(AppEvent::ReleaseTerminal) => {
+                                problem_store.lock().terminal_released()
+                            }
+                            Err(..) | Ok(AppEvent::Shutdown) => return Ok(()),
+                        }
+                    }
+                    terminal = Terminal::new()?;
+                    needs_redraw = true;
+                }
+                Ok(AppEvent::TerminalAvailable) => {}
                 Err(TryRecvError::Disconnected) => return Ok(()),
                 Err(TryRecvError::Empty) => {
                     // TODO: Consider spawning a separate thread to read crossterm events, then feed
                     // them into the main event channel. That way we can avoid polling.
                     if crossterm::event::poll(Duration::from_millis(100))? {
-                        needs_redraw = true;
-                        let Ok(Event::Key(key)) = crossterm::event::read() else {
-                            continue;
+                        let key = match crossterm::event::read() {
+                            Ok(Event::Key(key)) => key,
+                            Ok(Event::Mouse(mouse)) if mouse.kind != MouseEventKind::Moved => {
+                                needs_redraw = true;
+                                // A click dismisses the error popup, but is otherwise ignored.
+                                if error.is_some() {
+                                    if matches!(mouse.kind, MouseEventKind::Down(_)) {
+                                        error = None;
+                                    }
+                                } else if let Err(e) = screen.handle_mouse(mouse) {
+                                    error = Some(e);
+                                }
+                                continue;
+                            }
+                            Ok(Event::Mouse(_)) => continue,
+                            _ => {
+                                needs_redraw = true;
+                                continue;
+                            }
                         };
+                        needs_redraw = true;
                         // When we're displaying an error, any key will dismiss the error popup. The key
                         // should then be ignored.
                         if error.take().is_some() {
@@ -169,16 +224,17 @@ impl Drop for Terminal {
         let _ = crossterm::terminal::disable_raw_mode();
         let _ = crossterm::execute!(
             self.term.backend_mut(),
+            crossterm::event::DisableMouseCapture,
             crossterm::terminal::LeaveAlternateScreen
         );
     }
 }
 
-fn render_build_progress(f: &mut Frame, area: Rect) {
+fn render_build_progress(f: &mut Frame, area: Rect, theme: &Theme) {
     let block = Block::default()
         .title("Building")
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Yellow));
+        .border_style(theme.progress_border);
     let paragraph = Paragraph::new("Build in progress...")
         .block(block)
         .wrap(Wrap { trim: false });
@@ -186,12 +242,12 @@ fn render_build_progress(f: &mut Frame, area: Rect) {
     f.render_widget(paragraph, area);
 }
 
-fn render_error(f: &mut Frame, error: &anyhow::Error) {
+fn render_error(f: &mut Frame, error: &anyhow::Error, theme: &Theme) {
     let area = message_area(f.size());
     let block = Block::default()
         .title("Error")
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Red));
+        .border_style(theme.error);
     let paragraph = Paragraph::new(format!("{error:#}"))
         .block(block)
         .wrap(Wrap { trim: false });
@@ -233,22 +289,25 @@ fn render_list(
     active: bool,
     area: Rect,
     index: usize,
-) {
+    theme: &Theme,
+) -> usize {
     let items: Vec<_> = items.collect();
     let mut block = Block::default().title(title).borders(Borders::ALL);
     if active {
         block = block
             .border_type(BorderType::Thick)
-            .border_style(Style::default().fg(Color::Yellow));
-    }
-    let mut style = Style::default().add_modifier(Modifier::REVERSED);
-    if active {
-        style = style.fg(Color::Yellow);
+            .border_style(theme.active_border);
     }
+    let style = if active {
+        theme.active_selection
+    } else {
+        theme.inactive_selection
+    };
     let list = List::new(items).block(block).highlight_style(style);
     let mut list_state = ListState::default();
     list_state.select(Some(index));
     f.render_stateful_widget(list, area, &mut list_state);
+    list_state.offset()
 }
 
 /// Increment or decrement `counter`, wrapping at `len`. `keycode` must be Down or Up.
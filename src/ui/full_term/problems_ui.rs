@@ -2,21 +2,31 @@
 
 use super::centre_area;
 use super::render_list;
+use super::theme::SyntaxStyles;
+use super::theme::Theme;
 use super::update_counter;
 use crate::checker::ApiUsage;
 use crate::checker::BinLocation;
 use crate::checker::Checker;
+use crate::config::approvals;
+use crate::config::approvals::Approval;
 use crate::config::Config;
+use crate::config::UiTheme;
 use crate::config_editor;
 use crate::config_editor::ConfigEditor;
 use crate::config_editor::Edit;
 use crate::config_editor::EditOpts;
+use crate::config_file;
+use crate::config_file::ConfigFile;
+use crate::config_file::ConflictPolicy;
 use crate::crate_index::CrateIndex;
+use crate::crate_index::DepKind;
 use crate::crate_index::PackageId;
 use crate::location::SourceLocation;
+use crate::muted_problems;
+use crate::problem::BinExecutionFailed;
 use crate::problem::OffTreeApiUsage;
 use crate::problem::Problem;
-use crate::problem_store::ProblemId;
 use crate::problem_store::ProblemStore;
 use crate::problem_store::ProblemStoreRef;
 use crate::symbol_graph::backtrace;
@@ -26,11 +36,13 @@ use anyhow::Context;
 use anyhow::Result;
 use crossterm::event::KeyCode;
 use crossterm::event::KeyEvent;
+use crossterm::event::MouseButton;
+use crossterm::event::MouseEvent;
+use crossterm::event::MouseEventKind;
 use ratatui::layout::Constraint;
 use ratatui::layout::Direction;
 use ratatui::layout::Layout;
 use ratatui::layout::Rect;
-use ratatui::style::Color;
 use ratatui::style::Modifier;
 use ratatui::style::Style;
 use ratatui::text::Line;
@@ -44,6 +56,8 @@ use ratatui::widgets::Row;
 use ratatui::widgets::Table;
 use ratatui::widgets::Wrap;
 use ratatui::Frame;
+use std::cell::Cell;
+use std::cell::RefCell;
 use std::path::Path;
 use std::path::PathBuf;
 use std::rc::Rc;
@@ -55,6 +69,9 @@ use tui_input::backend::crossterm::EventHandler;
 mod diff;
 mod syntax_styling;
 
+/// How many lines a pane is scrolled by for each step of the mouse wheel.
+const MOUSE_SCROLL_LINES: i32 = 3;
+
 pub(super) struct ProblemsUi {
     problem_store: ProblemStoreRef,
     crate_index: Arc<CrateIndex>,
@@ -63,12 +80,55 @@ pub(super) struct ProblemsUi {
     edit_index: usize,
     usage_index: usize,
     backtrace_index: usize,
-    config_path: PathBuf,
+    config_file: ConfigFile,
     accept_single_enabled: bool,
     show_package_details: bool,
     checker: Arc<Mutex<Checker>>,
     comment: Option<String>,
     previous_comments: Vec<String>,
+    /// The number of lines of output that were visible the last time we rendered the output
+    /// viewer. Used for paging.
+    output_page_lines: Cell<usize>,
+    /// The percentage of the screen height used by the list, if the user has resized it by
+    /// dragging the border below it.
+    list_percent: Option<u16>,
+    /// Whether the border below the list is currently being dragged.
+    dragging_split: bool,
+    /// How many lines the middle pane (details, edit diff or source) is scrolled by. For source,
+    /// this is relative to the default view centred on the usage, so may be negative.
+    middle_scroll: i32,
+    /// How many lines the bottom pane (package or usage details) is scrolled by.
+    bottom_scroll: u16,
+    /// Where things were drawn the last time we rendered. Used to work out what mouse events refer
+    /// to.
+    layout: RefCell<RenderedLayout>,
+    /// The theme selected on the command line, if any.
+    theme_override: Option<UiTheme>,
+    /// Problems that the config downgraded to warnings. These are listed after the problems, but
+    /// can't be selected, since there's nothing that needs to be done about them.
+    warnings: Vec<Problem>,
+}
+
+/// The areas of the screen that we drew things in during the last render.
+#[derive(Default)]
+struct RenderedLayout {
+    screen: Rect,
+    list: Rect,
+    /// The index of the first list row that was visible.
+    list_offset: usize,
+    /// What each row of the list represents.
+    list_rows: Vec<ListRow>,
+    middle: Rect,
+    bottom: Option<Rect>,
+}
+
+#[derive(Clone, Copy)]
+enum ListRow {
+    Problem(usize),
+    Edit(usize),
+    Usage(usize),
+    Frame(usize),
+    Warning,
 }
 
 #[derive(Debug)]
@@ -77,27 +137,55 @@ enum Mode {
     SelectEdit,
     SelectUsage,
     SetComment(tui_input::Input),
+    /// Collects who approved the selected edit before applying it.
+    SetApproval(ApprovalPrompt),
+    /// Collects how many days the selected problem should be muted for.
+    SetMuteDays(tui_input::Input),
     Backtrace(Vec<backtrace::Frame>),
     PromptAutoAccept,
+    /// Asks what to do after the config was changed by something else while we were writing edits
+    /// to it.
+    ConfigConflict(PendingEdit),
     ShowPackageTree,
     ShowInternalDiagnostics,
+    /// Shows an explanation of the selected kind of problem.
+    ShowExplanation,
+    /// Shows the output of a failed binary (e.g. a build script). The value is the number of lines
+    /// that we're scrolled down by.
+    ShowOutput(usize),
     Help,
 }
 
+/// Edits that weren't written because the config changed, so that they can be reapplied.
+#[derive(Debug, Clone)]
+enum PendingEdit {
+    SelectedEdit(Option<Approval>),
+    AcceptAllSingleEdits,
+}
+
 impl ProblemsUi {
     pub(super) fn quit_requested(&self) -> bool {
         self.modes.is_empty()
     }
 
     pub(super) fn render(&self, f: &mut Frame) {
-        let chunks = if self.show_package_details {
-            split_vertical(f.size(), &[30, 50, 20])
-        } else {
-            split_vertical(f.size(), &[35, 65])
+        let chunks = match (self.show_package_details, self.list_percent) {
+            (true, Some(list)) => split_vertical(f.size(), &[list, 80 - list, 20]),
+            (true, None) => split_vertical(f.size(), &[30, 50, 20]),
+            (false, Some(list)) => split_vertical(f.size(), &[list, 100 - list]),
+            (false, None) => split_vertical(f.size(), &[35, 65]),
         };
         let (top, middle) = (chunks[0], chunks[1]);
+        {
+            let mut layout = self.layout.borrow_mut();
+            layout.screen = f.size();
+            layout.list = top;
+            layout.middle = middle;
+            layout.bottom = chunks.get(2).copied();
+        }
 
-        self.render_problems(f, top);
+        let theme = self.theme();
+        self.render_problems(f, top, &theme);
 
         let mut previous_mode = None;
         for mode in self.modes.iter() {
@@ -117,7 +205,7 @@ impl ProblemsUi {
                     }
                 }
                 Mode::SelectEdit => {
-                    self.render_edit_help_and_diff(f, middle);
+                    self.render_edit_help_and_diff(f, middle, &theme);
                 }
                 Mode::SelectUsage => {
                     if !self
@@ -125,26 +213,135 @@ impl ProblemsUi {
                         .iter()
                         .any(|mode| matches!(mode, Mode::Backtrace(..)))
                     {
-                        self.render_usage_source(f, middle);
+                        self.render_usage_source(f, middle, &theme);
                         if let Some(bottom) = chunks.get(2) {
                             self.render_usage_details(f, *bottom);
                         }
                     }
                 }
                 Mode::Backtrace(frames) => {
-                    self.render_backtrace_source(frames, f, middle);
+                    self.render_backtrace_source(frames, f, middle, &theme);
                 }
-                Mode::PromptAutoAccept => render_auto_accept(f),
-                Mode::ShowPackageTree => self.render_package_tree(f),
-                Mode::ShowInternalDiagnostics => self.render_internal_diagnostics(f),
-                Mode::SetComment(input) => self.render_comment_input(input, f),
-                Mode::Help => render_help(f, previous_mode),
+                Mode::PromptAutoAccept => render_auto_accept(f, &theme),
+                Mode::ConfigConflict(_) => render_config_conflict(f, &theme),
+                Mode::ShowPackageTree => self.render_package_tree(f, &theme),
+                Mode::ShowInternalDiagnostics => self.render_internal_diagnostics(f, &theme),
+                Mode::ShowExplanation => self.render_explanation(f, &theme),
+                Mode::ShowOutput(scroll) => self.render_output(f, *scroll, &theme),
+                Mode::SetComment(input) => self.render_comment_input(input, f, &theme),
+                Mode::SetApproval(prompt) => render_approval_input(prompt, f, &theme),
+                Mode::SetMuteDays(input) => render_mute_input(input, f, &theme),
+                Mode::Help => render_help(f, previous_mode, &theme),
             }
             previous_mode = Some(mode);
         }
     }
 
+    pub(super) fn handle_mouse(&mut self, mouse: MouseEvent) -> Result<()> {
+        let (column, row) = (mouse.column, mouse.row);
+        let layout = self.layout.borrow();
+        let in_list = contains(layout.list, column, row);
+        let in_middle = contains(layout.middle, column, row);
+        let in_bottom = layout
+            .bottom
+            .is_some_and(|area| contains(area, column, row));
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                // The border between the list and the middle pane is made up of the bottom of the
+                // list's block and the top of the middle pane's block.
+                if (layout.list.bottom().saturating_sub(1)..=layout.list.bottom()).contains(&row) {
+                    drop(layout);
+                    self.dragging_split = true;
+                } else if in_list && self.list_is_active() {
+                    let index =
+                        (row.saturating_sub(layout.list.y + 1)) as usize + layout.list_offset;
+                    if let Some(list_row) = layout.list_rows.get(index).copied() {
+                        drop(layout);
+                        self.select_list_row(list_row);
+                    }
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.dragging_split => {
+                let height = layout.screen.height.max(1);
+                drop(layout);
+                let max = if self.show_package_details { 70 } else { 90 };
+                self.list_percent = Some(((row + 1) * 100 / height).clamp(10, max));
+            }
+            MouseEventKind::Up(MouseButton::Left) => {
+                drop(layout);
+                self.dragging_split = false;
+            }
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                drop(layout);
+                let up = mouse.kind == MouseEventKind::ScrollUp;
+                let key_code = if up { KeyCode::Up } else { KeyCode::Down };
+                if matches!(self.modes.last(), Some(Mode::ShowOutput(_)))
+                    || (in_list && self.list_is_active())
+                {
+                    return self.handle_key(KeyEvent::from(key_code));
+                }
+                let delta = if up {
+                    -MOUSE_SCROLL_LINES
+                } else {
+                    MOUSE_SCROLL_LINES
+                };
+                if in_middle {
+                    self.middle_scroll += delta;
+                    // Only source can be scrolled above where it starts by default.
+                    if !matches!(
+                        self.modes.last(),
+                        Some(Mode::SelectUsage | Mode::Backtrace(..))
+                    ) {
+                        self.middle_scroll = self.middle_scroll.max(0);
+                    }
+                } else if in_bottom {
+                    self.bottom_scroll = (i32::from(self.bottom_scroll) + delta).max(0) as u16;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Returns whether the list is what's currently accepting input.
+    fn list_is_active(&self) -> bool {
+        matches!(
+            self.modes.last(),
+            Some(Mode::SelectProblem | Mode::SelectEdit | Mode::SelectUsage | Mode::Backtrace(..))
+        )
+    }
+
+    fn select_list_row(&mut self, list_row: ListRow) {
+        match list_row {
+            ListRow::Problem(index) => {
+                while self.modes.len() > 1
+                    && !matches!(self.modes.last(), Some(Mode::SelectProblem))
+                {
+                    self.modes.pop();
+                }
+                self.problem_index = index;
+            }
+            ListRow::Edit(index) => self.edit_index = index,
+            ListRow::Usage(index) => {
+                if matches!(self.modes.last(), Some(Mode::Backtrace(..))) {
+                    self.modes.pop();
+                }
+                self.usage_index = index;
+            }
+            ListRow::Frame(index) => self.backtrace_index = index,
+            ListRow::Warning => {}
+        }
+        self.reset_scroll();
+    }
+
+    fn reset_scroll(&mut self) {
+        self.middle_scroll = 0;
+        self.bottom_scroll = 0;
+    }
+
     pub(super) fn handle_key(&mut self, key: KeyEvent) -> Result<()> {
+        self.reset_scroll();
+        let num_modes = self.modes.len();
         let Some(mode) = self.modes.last_mut() else {
             return Ok(());
         };
@@ -186,6 +383,41 @@ impl ProblemsUi {
             (Mode::SetComment(input), _) => {
                 input.handle_event(&crossterm::event::Event::Key(key));
             }
+            (Mode::SetApproval(_), KeyCode::Esc) => {
+                self.modes.pop();
+            }
+            (Mode::SetApproval(prompt), KeyCode::Enter) => {
+                if let Some(approval) = prompt.submit()? {
+                    self.modes.pop();
+                    self.finish_selected_edit(Some(&approval))?;
+                }
+            }
+            (Mode::SetApproval(prompt), _) => {
+                prompt
+                    .input
+                    .handle_event(&crossterm::event::Event::Key(key));
+            }
+            (Mode::SetMuteDays(_), KeyCode::Esc) => {
+                self.modes.pop();
+            }
+            (Mode::SetMuteDays(input), KeyCode::Enter) => {
+                let days = parse_mute_days(input.value())?;
+                self.modes.pop();
+                self.mute_selected_problem(days)?;
+            }
+            (Mode::SetMuteDays(input), _) => {
+                input.handle_event(&crossterm::event::Event::Key(key));
+            }
+            (Mode::ConfigConflict(pending), KeyCode::Char('r')) => {
+                let pending = pending.clone();
+                self.modes.pop();
+                match pending {
+                    PendingEdit::SelectedEdit(approval) => {
+                        self.finish_selected_edit(approval.as_ref())?
+                    }
+                    PendingEdit::AcceptAllSingleEdits => self.accept_all_single_edits()?,
+                }
+            }
             (_, KeyCode::Char('q')) => self.modes.clear(),
             (Mode::SelectProblem, KeyCode::Up | KeyCode::Down) => {
                 update_counter(
@@ -217,12 +449,83 @@ impl ProblemsUi {
                 }
                 self.enter_usage_mode();
             }
+            (Mode::SelectProblem, KeyCode::Char('m')) => {
+                if self.problem_store.lock().is_empty() {
+                    bail!("Sorry. There's no problem to mute");
+                }
+                self.modes.push(Mode::SetMuteDays(
+                    muted_problems::DEFAULT_MUTE_DAYS.to_string().into(),
+                ));
+            }
             (Mode::SelectProblem, KeyCode::Char('t')) => {
                 self.modes.push(Mode::ShowPackageTree);
             }
+            (Mode::SelectProblem, KeyCode::Char('o')) => {
+                if self.execution_failure().is_none() {
+                    bail!("Sorry. Only failed build scripts and binaries have output to show");
+                }
+                self.modes.push(Mode::ShowOutput(0));
+            }
+            (Mode::SelectProblem | Mode::ShowOutput(_), KeyCode::Char('r')) => {
+                self.retry_execution()?;
+                if matches!(self.modes.last(), Some(Mode::ShowOutput(_))) {
+                    self.modes.pop();
+                }
+            }
+            (Mode::ShowOutput(_), KeyCode::Char('o')) => {
+                self.modes.pop();
+            }
+            (Mode::ShowOutput(_), KeyCode::Char('f')) => {
+                // Jump to edits. Applying an edit resolves the problem, which causes the binary to
+                // be rerun with the updated config.
+                self.modes.pop();
+                if self.edits().is_empty() {
+                    bail!("Sorry. No automatic edits exist for this problem");
+                }
+                self.enter_edit_mode();
+            }
+            (
+                Mode::ShowOutput(scroll),
+                KeyCode::Up
+                | KeyCode::Down
+                | KeyCode::PageUp
+                | KeyCode::PageDown
+                | KeyCode::Home
+                | KeyCode::End,
+            ) => {
+                let scroll = *scroll;
+                let page = self.output_page_lines.get().max(1);
+                let max_scroll = self
+                    .execution_failure()
+                    .map(|failure| {
+                        output_lines(&failure, &self.theme())
+                            .len()
+                            .saturating_sub(page)
+                    })
+                    .unwrap_or(0);
+                let scroll = match key.code {
+                    KeyCode::Up => scroll.saturating_sub(1),
+                    KeyCode::Down => scroll + 1,
+                    KeyCode::PageUp => scroll.saturating_sub(page),
+                    KeyCode::PageDown => scroll + page,
+                    KeyCode::Home => 0,
+                    KeyCode::End => max_scroll,
+                    _ => unreachable!(),
+                }
+                .min(max_scroll);
+                if let Some(mode) = self.modes.last_mut() {
+                    *mode = Mode::ShowOutput(scroll);
+                }
+            }
             (Mode::ShowPackageTree, _) => {
                 self.modes.pop();
             }
+            (Mode::SelectProblem | Mode::SelectEdit | Mode::SelectUsage, KeyCode::Char('e')) => {
+                self.modes.push(Mode::ShowExplanation);
+            }
+            (Mode::ShowExplanation, KeyCode::Char('e')) => {
+                self.modes.pop();
+            }
             (Mode::SelectUsage, KeyCode::Char('d')) => {
                 // We're already in details mode, drop back out to the problems list.
                 self.modes.pop();
@@ -246,12 +549,12 @@ impl ProblemsUi {
                 self.modes.pop();
             }
             (Mode::SelectEdit, KeyCode::Char(' ' | 'f') | KeyCode::Enter) => {
-                self.apply_selected_edit()?;
-                self.comment = None;
-                if self.problem_index >= self.problem_store.lock().len() {
-                    self.problem_index = 0;
+                if self.selected_edit_needs_approval()? {
+                    self.modes
+                        .push(Mode::SetApproval(ApprovalPrompt::default()));
+                } else {
+                    self.finish_selected_edit(None)?;
                 }
-                self.modes.pop();
             }
             (Mode::SelectEdit, KeyCode::Char('c')) => {
                 if !self.current_edit_supports_comments() {
@@ -261,15 +564,19 @@ impl ProblemsUi {
                     self.comment.as_deref().unwrap_or_default().into(),
                 ));
             }
-            (Mode::SelectProblem, KeyCode::Char('a')) => {
-                if !self.accept_single_enabled {
-                    self.modes.push(Mode::PromptAutoAccept);
-                }
+            (Mode::SelectProblem, KeyCode::Char('a')) if !self.accept_single_enabled => {
+                self.modes.push(Mode::PromptAutoAccept);
             }
             (Mode::PromptAutoAccept, KeyCode::Enter) => {
+                if self.approvals_required() {
+                    self.modes.pop();
+                    bail!(
+                        "Sorry, fixes can't be accepted automatically when approvals are required"
+                    );
+                }
                 self.accept_single_enabled = true;
-                self.accept_all_single_edits()?;
                 self.modes.pop();
+                self.accept_all_single_edits()?;
             }
             (_, KeyCode::Char('p')) => {
                 self.show_package_details = !self.show_package_details;
@@ -278,10 +585,8 @@ impl ProblemsUi {
                 self.modes.pop();
             }
             (_, KeyCode::Char('h' | '?')) => self.modes.push(Mode::Help),
-            (_, KeyCode::Esc) => {
-                if self.modes.len() >= 2 {
-                    self.modes.pop();
-                }
+            (_, KeyCode::Esc) if num_modes >= 2 => {
+                self.modes.pop();
             }
             _ => {}
         }
@@ -305,7 +610,8 @@ impl ProblemsUi {
         problem_store: ProblemStoreRef,
         crate_index: Arc<CrateIndex>,
         checker: Arc<Mutex<Checker>>,
-        config_path: PathBuf,
+        config_file: ConfigFile,
+        theme_override: Option<UiTheme>,
     ) -> Self {
         Self {
             problem_store,
@@ -315,16 +621,48 @@ impl ProblemsUi {
             edit_index: 0,
             usage_index: 0,
             backtrace_index: 0,
-            config_path,
+            config_file,
             accept_single_enabled: false,
             show_package_details: true,
             checker,
             comment: None,
             previous_comments: Default::default(),
+            output_page_lines: Cell::new(0),
+            list_percent: None,
+            dragging_split: false,
+            middle_scroll: 0,
+            bottom_scroll: 0,
+            layout: Default::default(),
+            theme_override,
+            warnings: Vec::new(),
         }
     }
 
+    /// Returns the theme to render with. This is looked up each time, since the config may have
+    /// been reloaded.
+    pub(super) fn theme(&self) -> Theme {
+        let checker = self.checker.lock().unwrap();
+        Theme::new(&checker.config.raw.ui, self.theme_override)
+    }
+
+    /// Prints the problems that were downgraded to warnings. Called once the terminal has been
+    /// released, so that warnings are still visible after the UI exits.
+    pub(super) fn print_warnings(&self) {
+        super::super::print_downgraded(&self.warnings);
+    }
+
     pub(super) fn problems_added(&mut self) -> Result<()> {
+        let config = self.checker.lock().unwrap().config.clone();
+        let mut pstore = self.problem_store.lock();
+        for problem in pstore.resolve_downgraded(&config.permissions) {
+            if !self.warnings.contains(&problem) {
+                self.warnings.push(problem);
+            }
+        }
+        if self.problem_index >= pstore.len() {
+            self.problem_index = 0;
+        }
+        drop(pstore);
         if self.accept_single_enabled {
             self.accept_all_single_edits()?;
         }
@@ -332,44 +670,65 @@ impl ProblemsUi {
     }
 
     fn accept_all_single_edits(&mut self) -> Result<()> {
-        fn first_single_edit(
-            pstore: &MutexGuard<ProblemStore>,
-            config: &Config,
-        ) -> Option<(ProblemId, Box<dyn Edit>)> {
-            pstore
-                .deduplicated_into_iter()
-                .find_map(|(index, problem)| {
-                    let mut edits = config_editor::fixes_for_problem(problem, config);
-                    if edits.len() == 1 {
-                        Some((index, edits.pop().unwrap()))
-                    } else {
-                        None
-                    }
-                })
-        }
-
         let config = self.checker.lock().unwrap().config.clone();
         let mut pstore = self.problem_store.lock();
-        let mut editor = ConfigEditor::from_file(&self.config_path)?;
-        while let Some((index, edit)) = first_single_edit(&pstore, &config) {
-            edit.apply(&mut editor, &Default::default())?;
-            pstore.resolve(index);
+        let mut resolved = Vec::new();
+        let result = self.config_file.edit(|editor| {
+            resolved.clear();
+            for (index, problem) in pstore.deduplicated_into_iter() {
+                let edits = config_editor::fixes_for_problem(problem, &config);
+                let [edit] = edits.as_slice() else {
+                    continue;
+                };
+                approvals::apply_edit(
+                    editor,
+                    &problem.to_string(),
+                    edit.as_ref(),
+                    &EditOpts {
+                        is_final: true,
+                        ..Default::default()
+                    },
+                    None,
+                )?;
+                resolved.push(index);
+            }
+            Ok(())
+        });
+        if result.is_ok() {
+            for index in resolved {
+                pstore.resolve(index);
+            }
         }
-        self.write_config(&editor)?;
-        Ok(())
+        drop(pstore);
+        self.handle_write_result(result.map(drop), PendingEdit::AcceptAllSingleEdits)
     }
 
-    fn write_config(&self, editor: &ConfigEditor) -> Result<(), anyhow::Error> {
-        crate::fs::write_atomic(&self.config_path, &editor.to_toml())
+    /// Passes through the result of writing edits to the config, unless the write failed because
+    /// the config was changed by something else and we should ask the user what to do, in which
+    /// case we switch to a mode that asks them and from which `pending` can be reapplied.
+    fn handle_write_result(&mut self, result: Result<()>, pending: PendingEdit) -> Result<()> {
+        match result {
+            Err(error)
+                if config_file::is_conflict(&error)
+                    && self.config_file.conflict_policy() == ConflictPolicy::Prompt =>
+            {
+                if !matches!(self.modes.last(), Some(Mode::ConfigConflict(_))) {
+                    self.modes.push(Mode::ConfigConflict(pending));
+                }
+                Ok(())
+            }
+            result => result,
+        }
     }
 
-    fn render_problems(&self, f: &mut Frame, area: Rect) {
+    fn render_problems(&self, f: &mut Frame, area: Rect, theme: &Theme) {
         let pstore_lock = &self.problem_store.lock();
         if pstore_lock.is_empty() {
-            super::render_build_progress(f, area);
+            super::render_build_progress(f, area, theme);
             return;
         }
         let mut items = Vec::new();
+        let mut rows = Vec::new();
         let is_edit_mode = self.modes.iter().any(|m| matches!(m, &Mode::SelectEdit));
         let is_usage_mode = self.modes.iter().any(|m| matches!(m, &Mode::SelectUsage));
         let backtrace_frames = match self.modes.last() {
@@ -379,23 +738,25 @@ impl ProblemsUi {
         let config = self.checker.lock().unwrap().config.clone();
         for (index, (_, problem)) in pstore_lock.deduplicated_into_iter().enumerate() {
             items.push(ListItem::new(format!("{problem}")));
+            rows.push(ListRow::Problem(index));
             if index == self.problem_index {
                 if is_edit_mode {
                     let edits = edits_for_problem(pstore_lock, self.problem_index, &config);
-                    items.extend(
-                        edits
-                            .iter()
-                            .map(|fix| ListItem::new(format!("  {}", fix.title()))),
-                    );
+                    for (edit_index, fix) in edits.iter().enumerate() {
+                        items.push(ListItem::new(format!("  {}", fix.title())));
+                        rows.push(ListRow::Edit(edit_index));
+                    }
                 } else if is_usage_mode {
                     let usages =
                         usages_for_problem(pstore_lock, self.problem_index, &self.crate_index);
                     for (usage_index, usage) in usages.iter().enumerate() {
                         items.push(ListItem::new(format!("  {}", usage.list_display())));
+                        rows.push(ListRow::Usage(usage_index));
                         if let Some(frames) = backtrace_frames {
                             if usage_index == self.usage_index {
-                                for bt_frame in frames {
+                                for (frame_index, bt_frame) in frames.iter().enumerate() {
                                     items.push(ListItem::new(format!("    {bt_frame}")));
+                                    rows.push(ListRow::Frame(frame_index));
                                 }
                             }
                         }
@@ -403,6 +764,10 @@ impl ProblemsUi {
                 }
             }
         }
+        for problem in &self.warnings {
+            items.push(ListItem::new(format!("warning: {problem}")).style(theme.warning));
+            rows.push(ListRow::Warning);
+        }
         let mut index = self.problem_index;
         let title;
         if is_edit_mode {
@@ -420,7 +785,7 @@ impl ProblemsUi {
             title = "Problems";
         }
 
-        render_list(
+        let offset = render_list(
             f,
             title,
             items.into_iter(),
@@ -435,7 +800,11 @@ impl ProblemsUi {
             ),
             area,
             index,
+            theme,
         );
+        let mut layout = self.layout.borrow_mut();
+        layout.list_offset = offset;
+        layout.list_rows = rows;
     }
 
     fn render_details(&self, f: &mut Frame, area: Rect) {
@@ -456,7 +825,8 @@ impl ProblemsUi {
         }
         let paragraph = Paragraph::new(details)
             .block(block)
-            .wrap(Wrap { trim: false });
+            .wrap(Wrap { trim: false })
+            .scroll((self.middle_scroll.max(0) as u16, 0));
         f.render_widget(paragraph, area);
     }
 
@@ -473,43 +843,51 @@ impl ProblemsUi {
         )
     }
 
-    fn render_edit_help_and_diff(&self, f: &mut Frame, area: Rect) {
+    fn render_edit_help_and_diff(&self, f: &mut Frame, area: Rect, theme: &Theme) {
         let edits = self.edits();
         let Some(edit) = edits.get(self.edit_index) else {
             return;
         };
 
         let lines = config_diff_lines(
-            &self.config_path,
+            &self.config_file,
             &**edit,
             &self.edit_opts(),
             (area.height as usize).saturating_sub(4),
+            theme,
         )
-        .unwrap_or_else(error_lines);
+        .unwrap_or_else(|error| error_lines(error, theme));
 
         let block = Block::default().title("Edit details").borders(Borders::ALL);
         let paragraph = Paragraph::new(lines)
             .block(block)
-            .wrap(Wrap { trim: false });
+            .wrap(Wrap { trim: false })
+            .scroll((self.middle_scroll.max(0) as u16, 0));
         f.render_widget(paragraph, area);
     }
 
-    fn render_usage_source(&self, f: &mut Frame, area: Rect) {
+    fn render_usage_source(&self, f: &mut Frame, area: Rect, theme: &Theme) {
         let usages = self.usages();
         let Some(usage) = usages.get(self.usage_index) else {
             return;
         };
 
-        render_source_location(usage.source_location(), area, f);
+        render_source_location(usage.source_location(), area, f, self.middle_scroll, theme);
     }
 
-    fn render_backtrace_source(&self, frames: &[backtrace::Frame], f: &mut Frame, area: Rect) {
+    fn render_backtrace_source(
+        &self,
+        frames: &[backtrace::Frame],
+        f: &mut Frame,
+        area: Rect,
+        theme: &Theme,
+    ) {
         let Some(frame) = frames.get(self.backtrace_index) else {
             return;
         };
 
         if let Some(location) = frame.source_location.as_ref() {
-            render_source_location(location, area, f);
+            render_source_location(location, area, f, self.middle_scroll, theme);
         } else {
             let block = Block::default()
                 .title("Missing source location")
@@ -545,11 +923,12 @@ impl ProblemsUi {
             .borders(Borders::ALL);
         let paragraph = Paragraph::new(lines)
             .block(block)
-            .wrap(Wrap { trim: false });
+            .wrap(Wrap { trim: false })
+            .scroll((self.bottom_scroll, 0));
         f.render_widget(paragraph, area);
     }
 
-    fn render_internal_diagnostics(&self, f: &mut Frame) {
+    fn render_internal_diagnostics(&self, f: &mut Frame, theme: &Theme) {
         let usages = self.usages();
         let Some(usage) = usages.get(self.usage_index) else {
             return;
@@ -560,20 +939,137 @@ impl ProblemsUi {
             .unwrap_or_else(|| "No debug data for this usage".to_owned());
 
         let lines: Vec<_> = debug_data.lines().collect();
-        render_message(f, Some("Internal diagnostics"), &lines);
+        render_message(f, Some("Internal diagnostics"), &lines, theme);
+    }
+
+    fn render_output(&self, f: &mut Frame, scroll: usize, theme: &Theme) {
+        let Some(failure) = self.execution_failure() else {
+            return;
+        };
+        let screen = f.size();
+        let area = centre_area(
+            screen,
+            screen.width.saturating_sub(4),
+            screen.height.saturating_sub(2),
+        );
+        self.output_page_lines
+            .set((area.height as usize).saturating_sub(2));
+        let block = active_block(theme).title(format!(
+            "{failure} (up/down/pgup/pgdn to scroll, f for fixes, r to rerun, o/esc to close)"
+        ));
+        let paragraph = Paragraph::new(output_lines(&failure, theme))
+            .block(block)
+            .scroll((scroll.min(u16::MAX as usize) as u16, 0));
+        f.render_widget(Clear, area);
+        f.render_widget(paragraph, area);
+    }
+
+    /// Returns the currently selected problem if it's a failed binary execution.
+    fn execution_failure(&self) -> Option<BinExecutionFailed> {
+        let pstore = &self.problem_store.lock();
+        let (_, problem) = pstore.deduplicated_into_iter().nth(self.problem_index)?;
+        match problem {
+            Problem::ExecutionFailed(failure) | Problem::WaitingForInput(failure) => {
+                Some(failure.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolves the currently selected problem, which must be a failed binary execution or
+    /// compilation, without making any edits. This causes the subprocess to reload the config and
+    /// run the binary or rustc again. If it fails again, a new problem will be reported.
+    fn retry_execution(&mut self) -> Result<()> {
+        let mut pstore = self.problem_store.lock();
+        let Some((
+            index,
+            Problem::ExecutionFailed(..)
+            | Problem::WaitingForInput(..)
+            | Problem::TestFailed(..)
+            | Problem::CompilationFailed(..),
+        )) = pstore.deduplicated_into_iter().nth(self.problem_index)
+        else {
+            bail!(
+                "Sorry. Only failed compilations, build scripts, binaries and tests can be rerun"
+            );
+        };
+        pstore.resolve(index);
+        if self.problem_index >= pstore.len() {
+            self.problem_index = 0;
+        }
+        Ok(())
+    }
+
+    /// Mutes the currently selected problem for `days` days. It won't be reported again until
+    /// then, even by later runs.
+    fn mute_selected_problem(&mut self, days: u32) -> Result<()> {
+        let mut pstore = self.problem_store.lock();
+        let Some((index, _)) = pstore.deduplicated_into_iter().nth(self.problem_index) else {
+            return Ok(());
+        };
+        pstore.mute(index, days)?;
+        if self.problem_index >= pstore.len() {
+            self.problem_index = 0;
+        }
+        Ok(())
+    }
+
+    /// Applies the currently selected edit, then returns to the list of problems.
+    fn finish_selected_edit(&mut self, approval: Option<&Approval>) -> Result<()> {
+        let result = self.apply_selected_edit(approval);
+        if result.is_err() {
+            return self.handle_write_result(result, PendingEdit::SelectedEdit(approval.cloned()));
+        }
+        self.comment = None;
+        if self.problem_index >= self.problem_store.lock().len() {
+            self.problem_index = 0;
+        }
+        self.modes.pop();
+        Ok(())
+    }
+
+    fn approvals_required(&self) -> bool {
+        let checker = self.checker.lock().unwrap();
+        checker.config.raw.common.required_approvals.is_some()
+    }
+
+    /// Returns whether approvals are required and the currently selected edit would grant
+    /// permissions.
+    fn selected_edit_needs_approval(&self) -> Result<bool> {
+        if !self.approvals_required() {
+            return Ok(false);
+        }
+        let pstore_lock = self.problem_store.lock();
+        let config = self.checker.lock().unwrap().config.clone();
+        let edits = edits_for_problem(&pstore_lock, self.problem_index, &config);
+        let Some(edit) = edits.get(self.edit_index) else {
+            return Ok(false);
+        };
+        let editor = self.config_file.editor()?;
+        approvals::edit_adds_grants(&editor, edit.as_ref())
     }
 
     /// Applies the currently selected edit and resolves the problem that produced that edit.
-    fn apply_selected_edit(&self) -> Result<()> {
+    /// `approval` is recorded against any permissions that the edit grants.
+    fn apply_selected_edit(&self, approval: Option<&Approval>) -> Result<()> {
         let mut pstore_lock = self.problem_store.lock();
         let config = self.checker.lock().unwrap().config.clone();
         let edits = edits_for_problem(&pstore_lock, self.problem_index, &config);
         let Some(edit) = edits.get(self.edit_index) else {
             return Ok(());
         };
-        let mut editor = ConfigEditor::from_file(&self.config_path)?;
-        edit.apply(&mut editor, &self.edit_opts())?;
-        self.write_config(&editor)?;
+        let problem = pstore_lock
+            .deduplicated_into_iter()
+            .nth(self.problem_index)
+            .map(|(_, problem)| problem.to_string())
+            .unwrap_or_default();
+        let opts = EditOpts {
+            is_final: true,
+            ..self.edit_opts()
+        };
+        let editor = self.config_file.edit(|editor| {
+            approvals::apply_edit(editor, &problem, edit.as_ref(), &opts, approval)
+        })?;
 
         // Resolve the currently selected problem.
         let maybe_index = pstore_lock
@@ -597,7 +1093,7 @@ impl ProblemsUi {
         let Some(edit) = edits.get(self.edit_index) else {
             return false;
         };
-        let Ok(mut editor) = ConfigEditor::from_file(&self.config_path) else {
+        let Ok(mut editor) = self.config_file.editor() else {
             return false;
         };
         const PLACEHOLDER_COMMENT: &str = "CACKLE PLACEHOLDER COMMENT";
@@ -605,6 +1101,7 @@ impl ProblemsUi {
             &mut editor,
             &EditOpts {
                 comment: Some(PLACEHOLDER_COMMENT.to_owned()),
+                ..Default::default()
             },
         );
         editor.to_toml().contains(PLACEHOLDER_COMMENT)
@@ -626,21 +1123,63 @@ impl ProblemsUi {
                 writeln!(&mut text, "Documentation: {documentation}").unwrap();
             }
             writeln!(&mut text, "Local path: {}", crate_info.directory).unwrap();
+            if crate_info.is_vendored {
+                writeln!(&mut text, "Vendored: yes").unwrap();
+            }
+            if crate_info.dep_kind != DepKind::Normal {
+                writeln!(&mut text, "Dependency kind: {}", crate_info.dep_kind).unwrap();
+            }
+        }
+        if let Some(line) = crate::ui::package_workspaces_line(&self.crate_index, &pkg_id) {
+            writeln!(&mut text, "{line}").unwrap();
         }
 
         let block = Block::default()
             .title(format!("Details for package {pkg_id}"))
             .borders(Borders::ALL);
-        let paragraph = Paragraph::new(text).block(block).wrap(Wrap { trim: false });
+        let paragraph = Paragraph::new(text)
+            .block(block)
+            .wrap(Wrap { trim: false })
+            .scroll((self.bottom_scroll, 0));
         f.render_widget(paragraph, area);
     }
 
-    fn render_package_tree(&self, f: &mut Frame) {
+    fn render_package_tree(&self, f: &mut Frame, theme: &Theme) {
         let text = self
             .package_tree_text()
             .unwrap_or_else(|error| error.to_string());
         let lines: Vec<_> = text.lines().collect();
-        render_message(f, None, &lines);
+        render_message(f, None, &lines, theme);
+    }
+
+    fn render_explanation(&self, f: &mut Frame, theme: &Theme) {
+        let pstore = &self.problem_store.lock();
+        let Some((_, problem)) = pstore.deduplicated_into_iter().nth(self.problem_index) else {
+            return;
+        };
+        let text = problem.help().map_or_else(
+            || "No explanation is available for this problem".to_owned(),
+            |help| help.text(),
+        );
+        // The explanation is prose, so rather than sizing the area to fit the longest line like
+        // `render_message`, we pick a comfortable width and let the text wrap.
+        let screen = f.size();
+        let width = screen.width.saturating_sub(4).min(100);
+        let height = text
+            .lines()
+            .map(|line| {
+                (line.len() as u16)
+                    .div_ceil(width.saturating_sub(2).max(1))
+                    .max(1)
+            })
+            .sum::<u16>()
+            + 2;
+        let area = centre_area(screen, width, height);
+        let paragraph = Paragraph::new(text)
+            .block(active_block(theme).title(format!("Explanation of {}", problem.code())))
+            .wrap(Wrap { trim: false });
+        f.render_widget(Clear, area);
+        f.render_widget(paragraph, area);
     }
 
     fn package_tree_text(&self) -> Result<String> {
@@ -649,10 +1188,15 @@ impl ProblemsUi {
             .ok_or_else(|| anyhow!("No package selected"))?;
         let output = std::process::Command::new("cargo")
             .arg("tree")
+            .args(self.crate_index.network_mode.cargo_arg())
             .arg("--manifest-path")
             .arg(&self.crate_index.manifest_path)
             .arg("-i")
-            .arg(format!("{}@{}", pkg_id.name_str(), pkg_id.version()))
+            .arg(format!(
+                "{}@{}",
+                pkg_id.unqualified_name(),
+                pkg_id.version()
+            ))
             .output()
             .context("Failed to run `cargo tree`")?;
         let mut text =
@@ -685,28 +1229,120 @@ impl ProblemsUi {
         backtracer.backtrace(bin_location)
     }
 
-    fn render_comment_input(&self, input: &tui_input::Input, f: &mut Frame) {
+    fn render_comment_input(&self, input: &tui_input::Input, f: &mut Frame, theme: &Theme) {
         let area = centre_area(f.size(), 80, 3);
-        let paragraph = Paragraph::new(input.value()).block(active_block().title("Set comment"));
+        let paragraph =
+            Paragraph::new(input.value()).block(active_block(theme).title("Set comment"));
         f.render_widget(Clear, area);
         f.render_widget(paragraph, area);
         f.set_cursor(area.x + 1 + input.visual_cursor() as u16, area.y + 1);
     }
 
     pub(crate) fn needs_cursor(&self) -> bool {
-        matches!(self.modes.last(), Some(Mode::SetComment(..)))
+        matches!(
+            self.modes.last(),
+            Some(Mode::SetComment(..) | Mode::SetApproval(..) | Mode::SetMuteDays(..))
+        )
     }
 
     fn edit_opts(&self) -> EditOpts {
         EditOpts {
             comment: self.comment.clone(),
+            ..Default::default()
         }
     }
 }
 
-fn render_source_location(source_location: &SourceLocation, area: Rect, f: &mut Frame) {
-    let lines = usage_source_lines(source_location, (area.height as usize).saturating_sub(2))
-        .unwrap_or_else(error_lines);
+/// Prompts for each part of an approval in turn.
+#[derive(Debug, Default)]
+struct ApprovalPrompt {
+    approval: Approval,
+    field: ApprovalField,
+    input: tui_input::Input,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+enum ApprovalField {
+    #[default]
+    ApprovedBy,
+    Ticket,
+    Expires,
+}
+
+impl ApprovalPrompt {
+    /// Stores the current input and moves to the next field. Returns the approval once all fields
+    /// have been entered.
+    fn submit(&mut self) -> Result<Option<Approval>> {
+        let value = self.input.value();
+        self.field = match self.field {
+            ApprovalField::ApprovedBy => {
+                self.approval.approved_by = approvals::parse_approvers(value)?;
+                ApprovalField::Ticket
+            }
+            ApprovalField::Ticket => {
+                let ticket = value.trim();
+                self.approval.ticket = (!ticket.is_empty()).then(|| ticket.to_owned());
+                ApprovalField::Expires
+            }
+            ApprovalField::Expires => {
+                self.approval.expires = approvals::parse_expiry(value)?;
+                return Ok(Some(std::mem::take(&mut self.approval)));
+            }
+        };
+        self.input = tui_input::Input::default();
+        Ok(None)
+    }
+
+    fn title(&self) -> &'static str {
+        match self.field {
+            ApprovalField::ApprovedBy => "Approved by (comma-separated)",
+            ApprovalField::Ticket => "Ticket (optional)",
+            ApprovalField::Expires => "Expires (optional, e.g. 2025-06-30)",
+        }
+    }
+}
+
+fn render_approval_input(prompt: &ApprovalPrompt, f: &mut Frame, theme: &Theme) {
+    let area = centre_area(f.size(), 80, 3);
+    let paragraph =
+        Paragraph::new(prompt.input.value()).block(active_block(theme).title(prompt.title()));
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+    f.set_cursor(area.x + 1 + prompt.input.visual_cursor() as u16, area.y + 1);
+}
+
+fn render_mute_input(input: &tui_input::Input, f: &mut Frame, theme: &Theme) {
+    let area = centre_area(f.size(), 80, 3);
+    let paragraph =
+        Paragraph::new(input.value()).block(active_block(theme).title("Mute problem for days"));
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+    f.set_cursor(area.x + 1 + input.visual_cursor() as u16, area.y + 1);
+}
+
+fn parse_mute_days(value: &str) -> Result<u32> {
+    match value.trim().parse() {
+        Ok(days) if days > 0 => Ok(days),
+        _ => bail!("Number of days to mute for should be a positive whole number"),
+    }
+}
+
+/// Renders source around `source_location`. `scroll` is the number of lines to scroll down by from
+/// the default view, which is centred on the location.
+fn render_source_location(
+    source_location: &SourceLocation,
+    area: Rect,
+    f: &mut Frame,
+    scroll: i32,
+    theme: &Theme,
+) {
+    let lines = usage_source_lines(
+        source_location,
+        (area.height as usize).saturating_sub(2),
+        scroll,
+        theme.syntax.as_ref(),
+    )
+    .unwrap_or_else(|error| error_lines(error, theme));
 
     let block = Block::default()
         .title(source_location.filename().display().to_string())
@@ -717,29 +1353,27 @@ fn render_source_location(source_location: &SourceLocation, area: Rect, f: &mut
     f.render_widget(paragraph, area);
 }
 
-fn error_lines(error: anyhow::Error) -> Vec<Line<'static>> {
-    vec![Line::from(Span::styled(
-        format!("{error:#}"),
-        Style::default().fg(Color::Red),
-    ))]
+fn error_lines(error: anyhow::Error, theme: &Theme) -> Vec<Line<'static>> {
+    vec![Line::from(Span::styled(format!("{error:#}"), theme.error))]
 }
 
 fn config_diff_lines(
-    config_path: &Path,
+    config_file: &ConfigFile,
     edit: &dyn Edit,
     opts: &EditOpts,
     max_lines: usize,
+    theme: &Theme,
 ) -> Result<Vec<Line<'static>>> {
     let mut lines = Vec::new();
     lines.push(Line::from(edit.help().to_string()));
-    let original = std::fs::read_to_string(config_path).unwrap_or_default();
+    let original = config_file.read().unwrap_or_default();
     let mut editor = ConfigEditor::from_toml_string(&original)?;
     if let Err(error) = edit.apply(&mut editor, opts) {
         lines.push(Line::from(""));
         lines.push(Line::from(error.to_string()));
     }
     let updated = editor.to_toml();
-    let mut diff = diff::diff_lines(&original, &updated);
+    let mut diff = diff::diff_lines(&original, &updated, theme);
     if !diff.is_empty() {
         lines.push(Line::from(""));
         lines.push(Line::from("=== Diff of cackle.toml ==="));
@@ -752,8 +1386,10 @@ fn config_diff_lines(
 fn usage_source_lines(
     source_location: &SourceLocation,
     max_lines: usize,
+    scroll: i32,
+    syntax: Option<&SyntaxStyles>,
 ) -> Result<Vec<Line<'static>>> {
-    let before_context = (max_lines / 2) as i32;
+    let before_context = (max_lines / 2) as i32 - scroll;
 
     let mut lines = Vec::new();
     let source = crate::fs::read_to_string(source_location.filename())?;
@@ -777,22 +1413,59 @@ fn usage_source_lines(
         let column = (line_number == target_line)
             .then(|| source_location.column())
             .flatten();
-        format_line(&mut spans, column, line);
+        format_line(&mut spans, column, line, syntax);
         lines.push(Line::from(spans));
     }
     Ok(lines)
 }
 
-fn format_line(out: &mut Vec<Span>, column: Option<u32>, line: &str) {
+/// Returns the lines to show in the output viewer for a failed binary. The command used to run the
+/// sandbox, if any, is highlighted, since that's what users will want to copy if they'd like to
+/// reproduce the failure.
+fn output_lines(failure: &BinExecutionFailed, theme: &Theme) -> Vec<Line<'static>> {
+    let heading_style = Style::default().add_modifier(Modifier::BOLD);
+    let command_style = theme.command;
+    let mut lines = Vec::new();
+    let output = &failure.output;
+    if let Some(sandbox_display) = output.sandbox_config_display.as_ref() {
+        lines.push(Line::from(Span::styled(
+            "=== Sandbox command ===",
+            heading_style,
+        )));
+        for line in sandbox_display.lines() {
+            lines.push(Line::from(Span::styled(line.to_owned(), command_style)));
+        }
+        lines.push(Line::from(""));
+    }
+    for (heading, bytes) in [("stderr", &output.stderr), ("stdout", &output.stdout)] {
+        lines.push(Line::from(Span::styled(
+            format!("=== {heading} ==="),
+            heading_style,
+        )));
+        for line in String::from_utf8_lossy(bytes).lines() {
+            lines.push(Line::from(line.to_owned()));
+        }
+        lines.push(Line::from(""));
+    }
+    lines
+}
+
+/// Appends spans for `line` to `out`, highlighting `column` if supplied. Tokens are styled according
+/// to `syntax`, if supplied.
+fn format_line(
+    out: &mut Vec<Span>,
+    column: Option<u32>,
+    line: &str,
+    syntax: Option<&SyntaxStyles>,
+) {
     let mut offset = 0;
     let column_offset = column.map(|c| (c as usize).saturating_sub(1));
     for token in rustc_ap_rustc_lexer::tokenize(line) {
         let new_offset = offset + token.len;
         let token_text = &line[offset..new_offset];
-        let mut style = Style::default();
-        if let Some(colour) = syntax_styling::colour_for_token_kind(token.kind, token_text) {
-            style = style.fg(colour);
-        }
+        let mut style = syntax
+            .and_then(|styles| syntax_styling::style_for_token_kind(token.kind, token_text, styles))
+            .unwrap_or_default();
         if column_offset
             .map(|c| (offset..new_offset).contains(&c))
             .unwrap_or(false)
@@ -804,7 +1477,7 @@ fn format_line(out: &mut Vec<Span>, column: Option<u32>, line: &str) {
     }
 }
 
-fn render_help(f: &mut Frame, mode: Option<&Mode>) {
+fn render_help(f: &mut Frame, mode: Option<&Mode>, theme: &Theme) {
     let mut keys = vec![];
     let mut title = "Help";
     match mode {
@@ -816,7 +1489,11 @@ fn render_help(f: &mut Frame, mode: Option<&Mode>) {
                     "d",
                     "Select and show details of each usage (API/unsafe only)",
                 ),
+                ("e", "Explain this kind of problem and how to fix it"),
                 ("t", "Show tree of crate dependencies to this crate"),
+                ("o", "Show output of failed build script or binary"),
+                ("r", "Rerun failed compilation, build script or binary"),
+                ("m", "Mute this problem for a number of days"),
                 ("up", "Select previous problem"),
                 ("down", "Select next problem"),
                 ("a", "Enable auto-apply for problems with only one edit"),
@@ -828,6 +1505,7 @@ fn render_help(f: &mut Frame, mode: Option<&Mode>) {
                 ("space/enter/f", "Apply this edit"),
                 ("d", "Jump to usage details (API/unsafe only)"),
                 ("c", "Add comment to edit (supported edits only)"),
+                ("e", "Explain this kind of problem"),
                 ("up", "Select previous edit"),
                 ("down", "Select next edit"),
                 ("esc", "Return to problem list"),
@@ -840,14 +1518,32 @@ fn render_help(f: &mut Frame, mode: Option<&Mode>) {
                 ("down", "Select next usage"),
                 ("b", "Show backtrace for this usage (API only)"),
                 ("f", "Jump to edits for the current problem"),
+                ("e", "Explain this kind of problem"),
                 ("d/esc", "Return to problem list"),
                 ("i", "Show internal diagnostics (requires --debug)"),
             ]);
         }
+        Some(Mode::ShowOutput(..)) => {
+            title = "Help for show-output";
+            keys.extend([
+                ("up/down", "Scroll by one line"),
+                ("pgup/pgdn", "Scroll by one page"),
+                ("home/end", "Scroll to start or end"),
+                (
+                    "f",
+                    "Show edits, which when applied, rerun with the new config",
+                ),
+                ("r", "Rerun now without making any edits"),
+                ("o/esc", "Return to problem list"),
+            ]);
+        }
         _ => {}
     }
     keys.extend([
         ("p", "Toggle display of package details"),
+        ("click", "Select a problem, edit or usage"),
+        ("wheel", "Scroll the list, details or source"),
+        ("drag", "Resize the list by dragging its lower border"),
         ("q", "Quit"),
         ("h/?", "Show mode-specific help"),
     ]);
@@ -864,23 +1560,36 @@ fn render_help(f: &mut Frame, mode: Option<&Mode>) {
         Constraint::Max(area.width),
     ];
     let table = Table::new(rows)
-        .block(active_block().title(title))
+        .block(active_block(theme).title(title))
         .widths(&constraints);
     f.render_widget(Clear, area);
     f.render_widget(table, area);
 }
 
-fn render_auto_accept(f: &mut Frame) {
+fn render_config_conflict(f: &mut Frame, theme: &Theme) {
+    render_message(f, Some("Config changed"), &[
+        "The config was changed by something else while edits were being written to it, so they weren't written.",
+        "",
+        "Press r to reload the config and reapply the edits, or escape to abandon them.",
+    ], theme);
+}
+
+fn render_auto_accept(f: &mut Frame, theme: &Theme) {
     render_message(f, None, &[
         "Auto-accept edits for all problems that only have a single edit?",
         "",
         "It's recommended that you look over the resulting cackle.toml afterwards to see if there are any crates with permissions that you don't think they should have.",
         "",
         "Press enter to accept, or escape to cancel.",
-    ]);
+    ], theme);
 }
 
-fn render_message<S: AsRef<str>>(f: &mut Frame, title: Option<&str>, raw_lines: &[S]) {
+fn render_message<S: AsRef<str>>(
+    f: &mut Frame,
+    title: Option<&str>,
+    raw_lines: &[S],
+    theme: &Theme,
+) {
     let width = raw_lines
         .iter()
         .map(|line| line.as_ref().len())
@@ -889,7 +1598,7 @@ fn render_message<S: AsRef<str>>(f: &mut Frame, title: Option<&str>, raw_lines:
         + 2;
     let height = raw_lines.len() + 2;
     let area = centre_area(f.size(), (width as u16).max(20), (height as u16).max(5));
-    let mut block = active_block();
+    let mut block = active_block(theme);
     if let Some(title) = title {
         block = block.title(title);
     }
@@ -901,10 +1610,10 @@ fn render_message<S: AsRef<str>>(f: &mut Frame, title: Option<&str>, raw_lines:
     f.render_widget(paragraph, area);
 }
 
-fn active_block() -> Block<'static> {
+fn active_block(theme: &Theme) -> Block<'static> {
     Block::default()
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Yellow))
+        .border_style(theme.active_border)
 }
 
 fn edits_for_problem(
@@ -926,6 +1635,7 @@ fn usages_for_problem(
     let mut usages_out: Vec<Box<dyn DisplayUsage>> = Vec::new();
     match pstore_lock.deduplicated_into_iter().nth(problem_index) {
         Some((_, Problem::DisallowedApiUsage(usages)))
+        | Some((_, Problem::DeniedPathUsage(usages)))
         | Some((_, Problem::OffTreeApiUsage(OffTreeApiUsage { usages, .. }))) => {
             for usage in &usages.usages {
                 usages_out.push(Box::new(usage.clone()));
@@ -1026,6 +1736,11 @@ fn problem_details(problem: &Problem) -> String {
         Problem::DisallowedUnsafe(..) | Problem::DisallowedApiUsage(..) => {
             "Press 'd' to see details of each usage".to_owned()
         }
+        Problem::DeniedPathUsage(..) => {
+            "This path is listed in `[deny] paths`, so can't be used by packages outside the \
+             workspace, regardless of their permissions. Press 'd' to see details of each usage."
+                .to_owned()
+        }
         Problem::MissingConfiguration(..) => {
             "This user interface can guide you through creating an initial cackle.toml. \
              Press 'h' at any time to see what keys are available."
@@ -1043,6 +1758,14 @@ fn problem_details(problem: &Problem) -> String {
                 an inlined function references that symbol."
             )
         }
+        Problem::ExecutionFailed(..) | Problem::WaitingForInput(..) => {
+            format!(
+                "Press 'o' to view the output in a scrollable viewer or 'r' to rerun\n\n{problem:#}"
+            )
+        }
+        Problem::CompilationFailed(..) => {
+            format!("Press 'r' to retry compilation, e.g. after editing the config\n\n{problem:#}")
+        }
         Problem::NewConfigVersionAvailable(version) => {
             let notes = crate::config::versions::VERSIONS
                 .get(*version as usize)
@@ -1057,6 +1780,10 @@ fn problem_details(problem: &Problem) -> String {
     }
 }
 
+fn contains(area: Rect, column: u16, row: u16) -> bool {
+    (area.x..area.right()).contains(&column) && (area.y..area.bottom()).contains(&row)
+}
+
 fn split_vertical(area: Rect, percentages: &[u16]) -> Rc<[Rect]> {
     let constraints: Vec<_> = percentages
         .iter()
@@ -1,59 +1,19 @@
-use ratatui::style::Color;
-use ratatui::style::Style;
+use super::super::theme::Theme;
+use crate::ui::config_diff;
+use crate::ui::config_diff::DiffLine;
 use ratatui::text::Line;
 use ratatui::text::Span;
-use std::collections::VecDeque;
 
-/// Builds the styled lines of a diff from `original` to `updated`. Shows common context from the
-/// start to the end of the current section.
-pub(super) fn diff_lines(original: &str, updated: &str) -> Vec<Line<'static>> {
-    fn is_section_start(line: &str) -> bool {
-        line.starts_with('[')
-    }
-
-    let mut lines = Vec::new();
-
-    let mut common = VecDeque::new();
-    let mut after_context = false;
-    for diff in diff::lines(original, updated) {
-        match diff {
-            diff::Result::Both(s, _) => {
-                if after_context {
-                    if is_section_start(s) {
-                        after_context = false;
-                    } else {
-                        lines.push(Line::from(format!(" {s}")));
-                    }
-                } else {
-                    if is_section_start(s) {
-                        common.clear();
-                    }
-                    common.push_back(s);
-                }
-            }
-            diff::Result::Left(s) => {
-                for line in common.drain(..) {
-                    lines.push(Line::from(format!(" {line}")));
-                }
-                lines.push(Line::from(vec![Span::styled(
-                    format!("-{s}"),
-                    Style::default().fg(Color::Red),
-                )]));
-                after_context = true;
-            }
-            diff::Result::Right(s) => {
-                for line in common.drain(..) {
-                    lines.push(Line::from(format!(" {line}")));
-                }
-                lines.push(Line::from(vec![Span::styled(
-                    format!("+{s}"),
-                    Style::default().fg(Color::Green),
-                )]));
-                after_context = true;
-            }
-        }
-    }
-    lines
+/// Builds the styled lines of a diff from `original` to `updated`.
+pub(super) fn diff_lines(original: &str, updated: &str, theme: &Theme) -> Vec<Line<'static>> {
+    config_diff::diff(original, updated)
+        .into_iter()
+        .map(|line| match line {
+            DiffLine::Context(s) => Line::from(format!(" {s}")),
+            DiffLine::Removed(s) => Line::from(Span::styled(format!("-{s}"), theme.diff_removed)),
+            DiffLine::Added(s) => Line::from(Span::styled(format!("+{s}"), theme.diff_added)),
+        })
+        .collect()
 }
 
 /// Attempts, where possible to trim the supplied diff to less than `max_lines`.
@@ -169,6 +129,7 @@ fn test_diff_lines() {
             g2 = 7.5
             h = 8
         "# },
+        &Theme::new(&Default::default(), None),
     );
     let lines: Vec<_> = lines.iter().map(line_to_string).collect();
     let expected = vec![
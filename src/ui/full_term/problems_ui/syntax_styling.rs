@@ -1,15 +1,20 @@
-use ratatui::style::Color;
+use super::super::theme::SyntaxStyles;
+use ratatui::style::Style;
 use rustc_ap_rustc_lexer::LiteralKind;
 use rustc_ap_rustc_lexer::TokenKind;
 
-pub(super) fn colour_for_token_kind(kind: TokenKind, token_text: &str) -> Option<Color> {
+pub(super) fn style_for_token_kind(
+    kind: TokenKind,
+    token_text: &str,
+    styles: &SyntaxStyles,
+) -> Option<Style> {
     match kind {
-        TokenKind::LineComment { .. } | TokenKind::BlockComment { .. } => Some(Color::Green),
+        TokenKind::LineComment { .. } | TokenKind::BlockComment { .. } => Some(styles.comment),
         TokenKind::Ident | TokenKind::RawIdent => {
             if is_keyword(token_text) {
-                Some(Color::Blue)
+                Some(styles.keyword)
             } else {
-                Some(Color::LightGreen)
+                Some(styles.identifier)
             }
         }
         TokenKind::Literal {
@@ -19,12 +24,12 @@ pub(super) fn colour_for_token_kind(kind: TokenKind, token_text: &str) -> Option
                 | LiteralKind::RawByteStr { .. }
                 | LiteralKind::RawStr { .. },
             ..
-        } => Some(Color::Yellow),
-        TokenKind::Lifetime { .. } => Some(Color::Blue),
-        TokenKind::OpenParen | TokenKind::CloseParen => Some(Color::Blue),
-        TokenKind::OpenBrace | TokenKind::CloseBrace => Some(Color::Magenta),
-        TokenKind::OpenBracket | TokenKind::CloseBracket => Some(Color::Magenta),
-        TokenKind::Question => Some(Color::Yellow),
+        } => Some(styles.string),
+        TokenKind::Lifetime { .. } => Some(styles.keyword),
+        TokenKind::OpenParen | TokenKind::CloseParen => Some(styles.keyword),
+        TokenKind::OpenBrace | TokenKind::CloseBrace => Some(styles.brace),
+        TokenKind::OpenBracket | TokenKind::CloseBracket => Some(styles.brace),
+        TokenKind::Question => Some(styles.string),
         _ => None,
     }
 }
@@ -0,0 +1,125 @@
+//! Colours and other styling used by the full terminal UI.
+
+use crate::config::UiConfig;
+use crate::config::UiTheme;
+use ratatui::style::Color;
+use ratatui::style::Modifier;
+use ratatui::style::Style;
+
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Theme {
+    /// Borders of whatever is currently accepting input.
+    pub(super) active_border: Style,
+    /// The selected item in a list that's accepting input.
+    pub(super) active_selection: Style,
+    /// The selected item in a list that isn't accepting input.
+    pub(super) inactive_selection: Style,
+    /// Borders of the popup shown while a build is in progress.
+    pub(super) progress_border: Style,
+    /// Borders of error popups and the text of errors shown inline.
+    pub(super) error: Style,
+    /// Problems that were downgraded to warnings.
+    pub(super) warning: Style,
+    pub(super) diff_removed: Style,
+    pub(super) diff_added: Style,
+    /// The command used to run a sandbox, which is highlighted in the output viewer.
+    pub(super) command: Style,
+    /// Styles for source excerpts. None if source shouldn't be syntax highlighted.
+    pub(super) syntax: Option<SyntaxStyles>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(super) struct SyntaxStyles {
+    pub(super) comment: Style,
+    /// Keywords, lifetimes and parentheses.
+    pub(super) keyword: Style,
+    pub(super) identifier: Style,
+    /// String literals and `?`.
+    pub(super) string: Style,
+    /// Braces and brackets.
+    pub(super) brace: Style,
+}
+
+impl Theme {
+    /// Returns the theme selected by `config`, unless overridden by `theme_override`.
+    pub(super) fn new(config: &UiConfig, theme_override: Option<UiTheme>) -> Self {
+        let mut theme = match theme_override.or(config.theme).unwrap_or_default() {
+            UiTheme::Dark => Self::dark(),
+            UiTheme::Light => Self::light(),
+            UiTheme::HighContrast => Self::high_contrast(),
+        };
+        if config.syntax_highlighting == Some(false) {
+            theme.syntax = None;
+        }
+        theme
+    }
+
+    fn dark() -> Self {
+        let fg = |colour| Style::default().fg(colour);
+        Self {
+            active_border: fg(Color::Yellow),
+            active_selection: fg(Color::Yellow).add_modifier(Modifier::REVERSED),
+            inactive_selection: Style::default().add_modifier(Modifier::REVERSED),
+            progress_border: fg(Color::Yellow),
+            error: fg(Color::Red),
+            warning: fg(Color::Yellow),
+            diff_removed: fg(Color::Red),
+            diff_added: fg(Color::Green),
+            command: fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            syntax: Some(SyntaxStyles {
+                comment: fg(Color::Green),
+                keyword: fg(Color::Blue),
+                identifier: fg(Color::LightGreen),
+                string: fg(Color::Yellow),
+                brace: fg(Color::Magenta),
+            }),
+        }
+    }
+
+    /// Avoids yellow and light colours, which are hard to read on a light background.
+    fn light() -> Self {
+        let fg = |colour| Style::default().fg(colour);
+        Self {
+            active_border: fg(Color::Blue),
+            active_selection: fg(Color::Blue).add_modifier(Modifier::REVERSED),
+            inactive_selection: Style::default().add_modifier(Modifier::REVERSED),
+            progress_border: fg(Color::Blue),
+            error: fg(Color::Red),
+            warning: fg(Color::Magenta),
+            diff_removed: fg(Color::Red),
+            diff_added: fg(Color::Green),
+            command: fg(Color::Blue).add_modifier(Modifier::BOLD),
+            syntax: Some(SyntaxStyles {
+                comment: fg(Color::Green),
+                keyword: fg(Color::Blue),
+                identifier: Style::default(),
+                string: fg(Color::Red),
+                brace: fg(Color::Magenta),
+            }),
+        }
+    }
+
+    /// Uses the terminal's default colours, distinguishing things by weight and reversal, so that
+    /// it works on any background.
+    fn high_contrast() -> Self {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        Self {
+            active_border: bold,
+            active_selection: bold.add_modifier(Modifier::REVERSED),
+            inactive_selection: Style::default().add_modifier(Modifier::REVERSED),
+            progress_border: bold,
+            error: bold.fg(Color::Red),
+            warning: Style::default().add_modifier(Modifier::ITALIC),
+            diff_removed: bold.fg(Color::Red),
+            diff_added: bold.fg(Color::Green),
+            command: bold.add_modifier(Modifier::UNDERLINED),
+            syntax: Some(SyntaxStyles {
+                comment: Style::default().add_modifier(Modifier::ITALIC),
+                keyword: bold,
+                identifier: Style::default(),
+                string: Style::default().add_modifier(Modifier::UNDERLINED),
+                brace: Style::default(),
+            }),
+        }
+    }
+}
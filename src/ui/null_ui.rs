@@ -1,25 +1,42 @@
-//! A user-interface that never prompts. This is used when non-interactive mode is selected.
+//! A user-interface that never prompts. This is used when non-interactive mode is selected and when
+//! the analysis is run as a library.
 
+use crate::analysis::ReportSink;
+use crate::analysis::ReportedProblem;
+use crate::checker::Checker;
+use crate::diagnostic::Diagnostic;
 use crate::events::AppEvent;
 use crate::problem::Severity;
 use crate::problem_store::ProblemStoreRef;
 use crate::Args;
 use anyhow::Result;
-use colored::Colorize;
+use std::path::PathBuf;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
+use std::sync::Mutex;
 
 pub(crate) struct NullUi {
     args: Arc<Args>,
+    checker: Arc<Mutex<Checker>>,
     abort_sender: Sender<()>,
+
+    /// If set, problems are added to this rather than being printed.
+    report: Option<ReportSink>,
 }
 
 impl NullUi {
-    pub(crate) fn new(args: &Arc<Args>, abort_sender: Sender<()>) -> Self {
+    pub(crate) fn new(
+        args: &Arc<Args>,
+        checker: &Arc<Mutex<Checker>>,
+        abort_sender: Sender<()>,
+        report: Option<ReportSink>,
+    ) -> Self {
         Self {
             args: args.clone(),
+            checker: checker.clone(),
             abort_sender,
+            report,
         }
     }
 }
@@ -33,12 +50,19 @@ impl super::UserInterface for NullUi {
         while let Ok(event) = event_receiver.recv() {
             match event {
                 AppEvent::Shutdown => return Ok(()),
+                AppEvent::ReleaseTerminal => problem_store.lock().terminal_released(),
+                AppEvent::TerminalAvailable => {}
                 AppEvent::ProblemsAdded => {
+                    let (config, crate_index) = {
+                        let checker = self.checker.lock().unwrap();
+                        (checker.config.clone(), checker.crate_index.clone())
+                    };
+                    let github_workspace = std::env::var_os("GITHUB_WORKSPACE").map(PathBuf::from);
                     let mut pstore = problem_store.lock();
                     let mut has_errors = false;
                     for (_, problem) in pstore.deduplicated_into_iter() {
-                        let mut severity = problem.severity();
-                        if self.args.command.is_some() && severity == Severity::Warning {
+                        let mut severity = problem.severity(&config.permissions);
+                        if !self.args.is_full_analysis() && severity == Severity::Warning {
                             // When running for example `cackle test`, not everything will be
                             // analysed, so unused warnings are expected. As such, we suppress all
                             // warnings.
@@ -47,22 +71,32 @@ impl super::UserInterface for NullUi {
                         if self.args.fail_on_warnings {
                             severity = Severity::Error
                         };
-                        match severity {
-                            Severity::Warning => {
-                                println!("{} {problem:#}", "WARNING:".yellow())
-                            }
-                            Severity::Error => {
-                                if !has_errors {
-                                    has_errors = true;
-                                    // Kill cargo process then wait a bit for any terminal output to
-                                    // settle before we start reporting errors.
-                                    let _ = self.abort_sender.send(());
-                                    std::thread::sleep(std::time::Duration::from_millis(20));
-                                    println!();
-                                }
-                                println!("{} {problem:#}", "ERROR:".red())
+                        if severity == Severity::Error && !has_errors {
+                            has_errors = true;
+                            // Kill cargo process then wait a bit for any terminal output to settle
+                            // before we start reporting errors.
+                            let _ = self.abort_sender.send(());
+                            std::thread::sleep(std::time::Duration::from_millis(20));
+                            if self.report.is_none() {
+                                println!();
                             }
                         }
+                        if let Some(report) = &self.report {
+                            report.lock().unwrap().push(ReportedProblem::new(
+                                problem,
+                                severity,
+                                &crate_index,
+                            ));
+                            continue;
+                        }
+                        let mut diagnostic = Diagnostic::new(problem, severity);
+                        for note in super::problem_notes(&crate_index, problem) {
+                            diagnostic.add_note(note);
+                        }
+                        println!(
+                            "{}",
+                            diagnostic.render(self.args.format, github_workspace.as_deref())
+                        );
                     }
                     if has_errors {
                         pstore.abort();
@@ -92,7 +126,8 @@ fn test_null_ui_with_warning() {
     use crate::problem::Problem::UnusedPackageConfig;
 
     let (abort_sender, _abort_recv) = std::sync::mpsc::channel();
-    let mut ui = NullUi::new(&Arc::new(Args::default()), abort_sender);
+    let checker = Arc::new(Mutex::new(crate::checker::testing::checker_for_testing()));
+    let mut ui = NullUi::new(&Arc::new(Args::default()), &checker, abort_sender, None);
     let (event_send, event_recv) = std::sync::mpsc::channel();
     let mut problem_store = crate::problem_store::create(event_send.clone());
     let join_handle = std::thread::spawn({
@@ -1,14 +1,41 @@
-//! This module tokenises Rust code and looks for the unsafe keyword. This is done as an additional
-//! layer of defence in addition to use of the -Funsafe-code flag when compiling crates, since that
-//! flag unfortunately doesn't completely prevent use of unsafe.
+//! This module parses Rust code and looks for uses of unsafe. This is done as an additional layer
+//! of defence in addition to use of the -Funsafe-code flag when compiling crates, since that flag
+//! unfortunately doesn't completely prevent use of unsafe.
 
 use crate::location::SourceLocation;
 use anyhow::Context;
 use anyhow::Result;
+use proc_macro2::Span;
+use proc_macro2::TokenStream;
+use proc_macro2::TokenTree;
+use std::fmt::Display;
 use std::path::Path;
+use syn::visit::Visit;
 
-/// Returns the locations of all unsafe usages found in `path`.
-pub(crate) fn scan_path(path: &Path) -> Result<Vec<SourceLocation>> {
+/// A use of unsafe that was found in a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct UnsafeSite {
+    pub(crate) location: SourceLocation,
+    pub(crate) kind: UnsafeKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum UnsafeKind {
+    Block,
+    Function,
+    FunctionPointer,
+    Impl,
+    Trait,
+    ExternBlock,
+    /// An attribute like `#[unsafe(no_mangle)]`.
+    Attribute,
+    /// The unsafe keyword in tokens that we don't parse as Rust code, e.g. the input to a macro, or
+    /// anywhere in a file that we couldn't parse.
+    Keyword,
+}
+
+/// Returns all uses of unsafe found in `path`.
+pub(crate) fn scan_path(path: &Path) -> Result<Vec<UnsafeSite>> {
     let bytes =
         std::fs::read(path).with_context(|| format!("Failed to read `{}`", path.display()))?;
     let Ok(source) = std::str::from_utf8(&bytes) else {
@@ -19,9 +46,170 @@ pub(crate) fn scan_path(path: &Path) -> Result<Vec<SourceLocation>> {
     Ok(scan_string(source, path))
 }
 
-fn scan_string(source: &str, path: &Path) -> Vec<SourceLocation> {
+fn scan_string(source: &str, path: &Path) -> Vec<UnsafeSite> {
+    let sites = match syn::parse_file(source) {
+        Ok(file) => {
+            let mut visitor = UnsafeVisitor {
+                path,
+                sites: Vec::new(),
+            };
+            visitor.visit_file(&file);
+            visitor.sites
+        }
+        // The file might use syntax that's newer than what we can parse, or it might not be valid
+        // Rust at all, e.g. if it's only ever passed to a macro. Either way, we fall back to looking
+        // for the unsafe keyword in the file's tokens.
+        Err(_) => scan_tokens(source, path),
+    };
+    // Spans record the source text of every file we've parsed. We've finished with them, so free
+    // that memory rather than accumulating it for each file that we scan.
+    proc_macro2::extra::invalidate_current_thread_spans();
+    sites
+}
+
+struct UnsafeVisitor<'a> {
+    path: &'a Path,
+    sites: Vec<UnsafeSite>,
+}
+
+impl UnsafeVisitor<'_> {
+    fn add(&mut self, span: Span, kind: UnsafeKind) {
+        let start = span.start();
+        self.sites.push(UnsafeSite {
+            location: SourceLocation::new(
+                self.path,
+                start.line as u32,
+                Some(start.column as u32 + 1),
+            ),
+            kind,
+        });
+    }
+
+    fn add_if_unsafe(&mut self, unsafety: &Option<syn::Token![unsafe]>, kind: UnsafeKind) {
+        if let Some(unsafe_token) = unsafety {
+            self.add(unsafe_token.span, kind);
+        }
+    }
+
+    /// Reports any unsafe keyword in `tokens`, which we don't parse as Rust code.
+    fn visit_unparsed_tokens(&mut self, tokens: &TokenStream) {
+        for token in tokens.clone() {
+            match token {
+                TokenTree::Ident(ident) if ident == "unsafe" => {
+                    self.add(ident.span(), UnsafeKind::Keyword)
+                }
+                TokenTree::Group(group) => self.visit_unparsed_tokens(&group.stream()),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for UnsafeVisitor<'_> {
+    fn visit_expr_unsafe(&mut self, node: &'ast syn::ExprUnsafe) {
+        self.add(node.unsafe_token.span, UnsafeKind::Block);
+        syn::visit::visit_expr_unsafe(self, node);
+    }
+
+    fn visit_signature(&mut self, node: &'ast syn::Signature) {
+        self.add_if_unsafe(&node.unsafety, UnsafeKind::Function);
+        syn::visit::visit_signature(self, node);
+    }
+
+    fn visit_type_bare_fn(&mut self, node: &'ast syn::TypeBareFn) {
+        self.add_if_unsafe(&node.unsafety, UnsafeKind::FunctionPointer);
+        syn::visit::visit_type_bare_fn(self, node);
+    }
+
+    fn visit_item_impl(&mut self, node: &'ast syn::ItemImpl) {
+        self.add_if_unsafe(&node.unsafety, UnsafeKind::Impl);
+        syn::visit::visit_item_impl(self, node);
+    }
+
+    fn visit_item_trait(&mut self, node: &'ast syn::ItemTrait) {
+        self.add_if_unsafe(&node.unsafety, UnsafeKind::Trait);
+        syn::visit::visit_item_trait(self, node);
+    }
+
+    fn visit_item_foreign_mod(&mut self, node: &'ast syn::ItemForeignMod) {
+        self.add_if_unsafe(&node.unsafety, UnsafeKind::ExternBlock);
+        syn::visit::visit_item_foreign_mod(self, node);
+    }
+
+    fn visit_attribute(&mut self, node: &'ast syn::Attribute) {
+        if let Some(ident) = node.path().get_ident() {
+            if ident == "unsafe" {
+                self.add(ident.span(), UnsafeKind::Attribute);
+            }
+        }
+        syn::visit::visit_attribute(self, node);
+    }
+
+    fn visit_meta_list(&mut self, node: &'ast syn::MetaList) {
+        // Attribute arguments can contain further attributes, e.g. `cfg_attr`.
+        self.visit_unparsed_tokens(&node.tokens);
+        syn::visit::visit_meta_list(self, node);
+    }
+
+    fn visit_macro(&mut self, node: &'ast syn::Macro) {
+        self.visit_unparsed_tokens(&node.tokens);
+        syn::visit::visit_macro(self, node);
+    }
+
+    fn visit_item(&mut self, node: &'ast syn::Item) {
+        if let syn::Item::Verbatim(tokens) = node {
+            self.visit_unparsed_tokens(tokens);
+        }
+        syn::visit::visit_item(self, node);
+    }
+
+    fn visit_foreign_item(&mut self, node: &'ast syn::ForeignItem) {
+        if let syn::ForeignItem::Verbatim(tokens) = node {
+            self.visit_unparsed_tokens(tokens);
+        }
+        syn::visit::visit_foreign_item(self, node);
+    }
+
+    fn visit_impl_item(&mut self, node: &'ast syn::ImplItem) {
+        if let syn::ImplItem::Verbatim(tokens) = node {
+            self.visit_unparsed_tokens(tokens);
+        }
+        syn::visit::visit_impl_item(self, node);
+    }
+
+    fn visit_trait_item(&mut self, node: &'ast syn::TraitItem) {
+        if let syn::TraitItem::Verbatim(tokens) = node {
+            self.visit_unparsed_tokens(tokens);
+        }
+        syn::visit::visit_trait_item(self, node);
+    }
+
+    fn visit_expr(&mut self, node: &'ast syn::Expr) {
+        if let syn::Expr::Verbatim(tokens) = node {
+            self.visit_unparsed_tokens(tokens);
+        }
+        syn::visit::visit_expr(self, node);
+    }
+
+    fn visit_type(&mut self, node: &'ast syn::Type) {
+        if let syn::Type::Verbatim(tokens) = node {
+            self.visit_unparsed_tokens(tokens);
+        }
+        syn::visit::visit_type(self, node);
+    }
+
+    fn visit_pat(&mut self, node: &'ast syn::Pat) {
+        if let syn::Pat::Verbatim(tokens) = node {
+            self.visit_unparsed_tokens(tokens);
+        }
+        syn::visit::visit_pat(self, node);
+    }
+}
+
+/// Tokenises `source` and reports every occurrence of the unsafe keyword.
+fn scan_tokens(source: &str, path: &Path) -> Vec<UnsafeSite> {
     let mut offset = 0;
-    let mut locations = Vec::new();
+    let mut sites = Vec::new();
     for token in rustc_ap_rustc_lexer::tokenize(source) {
         let new_offset = offset + token.len;
         let token_text = &source[offset..new_offset];
@@ -29,27 +217,54 @@ fn scan_string(source: &str, path: &Path) -> Vec<SourceLocation> {
             let column = source[..new_offset]
                 .lines()
                 .last()
-                .map(|line| (line.len() - token_text.len() + 1) as u32)
+                .map(|line| (line.chars().count() - token_text.len() + 1) as u32)
                 .unwrap_or(1);
             let line = 1.max(source[..new_offset].lines().count() as u32);
-            locations.push(SourceLocation::new(path, line, Some(column)));
+            sites.push(UnsafeSite {
+                location: SourceLocation::new(path, line, Some(column)),
+                kind: UnsafeKind::Keyword,
+            });
         }
         offset = new_offset;
     }
-    locations
+    sites
+}
+
+impl Display for UnsafeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnsafeKind::Block => "unsafe block",
+            UnsafeKind::Function => "unsafe function",
+            UnsafeKind::FunctionPointer => "unsafe function pointer",
+            UnsafeKind::Impl => "unsafe impl",
+            UnsafeKind::Trait => "unsafe trait",
+            UnsafeKind::ExternBlock => "unsafe extern block",
+            UnsafeKind::Attribute => "unsafe attribute",
+            UnsafeKind::Keyword => "unsafe keyword",
+        }
+        .fmt(f)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::unsafe_checker::scan_path;
     use crate::unsafe_checker::scan_string;
+    use crate::unsafe_checker::UnsafeKind;
     use std::ops::Not;
     use std::path::Path;
 
     fn unsafe_line_col(source: &str) -> Option<(u32, u32)> {
         scan_string(source, Path::new("test.rs"))
             .first()
-            .map(|usage| (usage.line(), usage.column().unwrap()))
+            .map(|site| (site.location.line(), site.location.column().unwrap()))
+    }
+
+    fn unsafe_kinds(source: &str) -> Vec<(UnsafeKind, u32)> {
+        scan_string(source, Path::new("test.rs"))
+            .into_iter()
+            .map(|site| (site.kind, site.location.line()))
+            .collect()
     }
 
     #[test]
@@ -74,6 +289,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_unsafe_kinds() {
+        assert_eq!(
+            unsafe_kinds(indoc::indoc! {r#"
+                unsafe fn foo(f: unsafe fn()) {
+                    unsafe { f() }
+                }
+                unsafe trait T {}
+                unsafe impl T for u8 {}
+                unsafe extern "C" {
+                    safe fn bar();
+                }
+                #[unsafe(no_mangle)]
+                pub extern "C" fn baz() {}
+                #[cfg_attr(all(), unsafe(export_name = "x"))]
+                pub extern "C" fn x() {}
+            "#}),
+            vec![
+                (UnsafeKind::Function, 1),
+                (UnsafeKind::FunctionPointer, 1),
+                (UnsafeKind::Block, 2),
+                (UnsafeKind::Trait, 4),
+                (UnsafeKind::Impl, 5),
+                (UnsafeKind::ExternBlock, 6),
+                (UnsafeKind::Attribute, 9),
+                (UnsafeKind::Keyword, 11),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unsafe_in_macros() {
+        assert_eq!(
+            unsafe_kinds(indoc::indoc! {r#"
+                macro_rules! m {
+                    () => { unsafe { 1 } };
+                }
+                fn foo() {
+                    println!("unsafe {}", "unsafe");
+                    m!(unsafe);
+                }
+            "#}),
+            vec![(UnsafeKind::Keyword, 2), (UnsafeKind::Keyword, 6)]
+        );
+    }
+
+    #[test]
+    fn test_unparsable_source() {
+        // Source that syn can't parse still gets checked for the unsafe keyword.
+        assert_eq!(
+            unsafe_kinds("fn foo( { unsafe {} }"),
+            vec![(UnsafeKind::Keyword, 1)]
+        );
+    }
+
     #[track_caller]
     fn has_unsafe_in_file(path: &str) -> bool {
         let root = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR should be set");
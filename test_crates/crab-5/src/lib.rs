@@ -16,6 +16,13 @@ fn helper() -> bool {
 
 pub struct Metadata;
 
+impl Metadata {
+    #[inline(never)]
+    pub fn log(&self) {
+        std::hint::black_box(self);
+    }
+}
+
 pub struct MacroCallsite {
     _interest: AtomicU8,
     meta: &'static Metadata,
@@ -34,3 +41,23 @@ impl MacroCallsite {
         self.meta
     }
 }
+
+/// Calls `f` with the callsite's metadata. This mirrors `tracing_core::dispatcher::get_default`,
+/// which is passed a closure by macros in the `tracing` crate. When the closure gets inlined into
+/// this function, the closure (which belongs to the crate that invoked the macro) appears to be
+/// referenced from this crate.
+#[inline(always)]
+pub fn with_metadata<F: FnOnce(&Metadata)>(callsite: &MacroCallsite, f: F) {
+    f(callsite.metadata())
+}
+
+pub trait Callsite {
+    fn set_interest(&self, interest: u8);
+}
+
+/// Mirrors `tracing_core::callsite::register`, which gets passed a callsite that was defined by a
+/// macro in the crate that invoked the macro.
+#[inline(always)]
+pub fn register(callsite: &'static dyn Callsite) {
+    callsite.set_interest(1);
+}
@@ -2,6 +2,9 @@
 
 use std::ops::Deref;
 
+pub use crab_5::register;
+pub use crab_5::with_metadata;
+pub use crab_5::Callsite;
 pub use crab_5::MacroCallsite;
 pub use crab_5::Metadata;
 
@@ -34,6 +37,31 @@ macro_rules! impl_foo {
     };
 }
 
+/// Something that can be run via a trait object.
+pub trait Task {
+    fn run(&self) -> String;
+}
+
+struct FnTask<F>(F);
+
+impl<F: Fn(&'static str) -> T, T: std::fmt::Debug> Task for FnTask<F> {
+    fn run(&self) -> String {
+        format!("{:?}", (self.0)("HOME"))
+    }
+}
+
+/// Puts `f` into a trait object. The vtable, whose methods call `f`, is built here, but if `f` uses
+/// a restricted API, that usage should be attributed to whichever crate supplied `f`, not to us.
+pub fn boxed_task<F: Fn(&'static str) -> T + 'static, T: std::fmt::Debug + 'static>(
+    f: F,
+) -> Box<dyn Task> {
+    Box::new(FnTask(f))
+}
+
+pub fn run_tasks(tasks: &[Box<dyn Task>]) -> Vec<String> {
+    tasks.iter().map(|task| task.run()).collect()
+}
+
 /// This macro, together with some code in crab_5, reproduces a minimal subset of a structure present
 /// in the tracing/tracing-core crates. If the debug macro is invoked from another crate, say res1,
 /// then we observe a reference `crab_5::MacroCallsite::metadata -> res1::print_something::CALLSITE`.
@@ -45,6 +73,15 @@ macro_rules! debug {
         static META: $crate::Metadata = $crate::Metadata;
         static CALLSITE: $crate::MacroCallsite = $crate::MacroCallsite::new(&META);
         let meta = CALLSITE.metadata();
+        $crate::with_metadata(&CALLSITE, |meta| meta.log());
+        struct Interest(std::sync::atomic::AtomicU8);
+        impl $crate::Callsite for Interest {
+            fn set_interest(&self, interest: u8) {
+                self.0.store(interest, std::sync::atomic::Ordering::Relaxed);
+            }
+        }
+        static INTEREST: Interest = Interest(std::sync::atomic::AtomicU8::new(0));
+        $crate::register(&INTEREST);
     }};
 }
 
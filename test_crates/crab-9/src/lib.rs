@@ -1,6 +1,7 @@
 //! This crate's test checks various properties of the sandbox that it's running in.
 
 use std::path::Path;
+use std::path::PathBuf;
 
 pub fn access_files() {
     let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
@@ -15,9 +16,17 @@ pub fn access_files() {
         );
     }
 
-    // This write should succeed because the sandbox configuration for this package allows writing
-    // to the scratch directory.
-    let output_path = manifest_dir.join("scratch/writable.txt");
+    // This write should succeed because the sandbox configuration for this package provides a
+    // writable scratch directory. If we weren't built via cackle, then there's no scratch
+    // directory, so we fall back to the system's temporary directory.
+    let scratch_dir = match std::env::var_os("CACKLE_SCRATCH_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None if option_env!("CACKLE_SOCKET_PATH").is_some() => {
+            panic!("CACKLE_SCRATCH_DIR not set")
+        }
+        None => std::env::temp_dir(),
+    };
+    let output_path = scratch_dir.join("writable.txt");
     if let Err(error) = std::fs::write(&output_path, "This file is written by a test") {
         panic!("Failed to write {}: {error}", output_path.display());
     }
@@ -21,6 +21,12 @@ fn main() {
     println!("HOME: {:?}", crab_4::get_home());
     write_to_file("a.txt", "Hello");
     println!("pid={}", (crab_4::GET_PID[0])());
+    // Make sure that passing a function that uses an API into a trait object built by another
+    // crate is attributed to us.
+    println!(
+        "{:?}",
+        crab_6::run_tasks(&[crab_6::boxed_task(std::env::var::<&'static str>)])
+    );
     crab_4::access_file();
     crab_7::do_something();
     crab_8::print_defaults();